@@ -1,8 +1,10 @@
 //! Change tracking for the database
-#![allow(dead_code)]
 
 use std::sync::Arc;
 
+use salsa::Durability;
+
+use crate::SourceDatabase;
 use crate::input::{FileId, SourceRoot, SourceRootId};
 
 /// A change to a file
@@ -53,4 +55,24 @@ impl Change {
     pub fn set_source_root(&mut self, id: SourceRootId, root: SourceRoot) {
         self.roots.push((id, Arc::new(root)));
     }
+
+    /// Check whether the change changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.roots.is_empty()
+    }
+
+    /// Apply the change to a database, the source roots first
+    pub fn apply(self, db: &mut dyn SourceDatabase) {
+        for (id, root) in self.roots {
+            db.set_source_root_with_durability(id, root, Durability::LOW);
+        }
+        for change in self.files {
+            match change {
+                FileChange::Modified { file_id, new_text } => db.set_file_text(file_id, &new_text),
+                FileChange::Removed { file_id } => {
+                    db.remove_file(file_id);
+                }
+            }
+        }
+    }
 }
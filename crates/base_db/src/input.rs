@@ -54,6 +54,26 @@ impl SourceRoot {
         self.path_to_file.insert(path, file_id);
     }
 
+    /// Remove a file from this source root, with its path
+    ///
+    /// Returns whether the file was in the source root.
+    pub fn remove_file(&mut self, file_id: FileId) -> bool {
+        self.path_to_file.retain(|_, id| *id != file_id);
+        let len = self.files.len();
+        self.files.retain(|id| *id != file_id);
+        self.files.len() != len
+    }
+
+    /// Check whether a file is in this source root
+    pub fn contains_file(&self, file_id: FileId) -> bool {
+        self.files.contains(&file_id)
+    }
+
+    /// Check whether a path is inside the directory of this source root
+    pub fn contains_path(&self, path: &Path) -> bool {
+        path.starts_with(&self.path)
+    }
+
     /// Resolve a path relative to this source root
     pub fn resolve_path(&self, path: &Path) -> Option<FileId> {
         // First, try to find the exact path in our map
@@ -6,18 +6,22 @@
 
 mod change;
 pub mod input;
+pub mod lru;
 
 use std::hash::BuildHasherDefault;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use dashmap::DashMap;
 use dashmap::mapref::entry::Entry;
+pub use line_index::{LineCol, LineIndex, WideEncoding, WideLineCol};
 use rustc_hash::FxHasher;
 use salsa::{Durability, Setter};
-pub use {indexmap, la_arena, salsa, typed_arena};
+pub use {indexmap, la_arena, line_index, salsa, typed_arena};
 
-pub use crate::change::FileChange;
+pub use crate::change::{Change, FileChange};
 pub use crate::input::{FileId, SourceRoot, SourceRootId};
+pub use crate::lru::{LruCache, LruConfig, Query, QueryStats};
 
 /// Macro for implementing interned keys
 #[macro_export]
@@ -39,10 +43,10 @@ macro_rules! impl_intern_key {
     };
 }
 
-/// Default LRU cache capacity for file text
+/// Default LRU cache capacity for file text, see [`Query::FileText`]
 pub const DEFAULT_FILE_TEXT_LRU_CAP: u16 = 16;
 
-/// Default LRU cache capacity for parsing
+/// Default LRU cache capacity for parsing, see [`Query::Parse`]
 pub const DEFAULT_PARSE_LRU_CAP: u16 = 128;
 
 /// Files storage for the database
@@ -59,9 +63,14 @@ impl Files {
         Self::default()
     }
 
-    /// Get the text of a file
-    pub fn file_text(&self, file_id: FileId) -> FileText {
-        *self.files.get(&file_id).expect("Unable to fetch file; this is a bug")
+    /// Check whether the text of a file was set
+    pub fn contains_file(&self, file_id: FileId) -> bool {
+        self.files.contains_key(&file_id)
+    }
+
+    /// Get the text of a file, or `None` if it was never set
+    pub fn file_text(&self, file_id: FileId) -> Option<FileText> {
+        self.files.get(&file_id).map(|text| *text)
     }
 
     /// Set the text of a file
@@ -97,13 +106,65 @@ impl Files {
         };
     }
 
-    /// Get the source root of a file
-    pub fn source_root(&self, source_root_id: SourceRootId) -> SourceRootInput {
-        let source_root = self
+    /// Remove a file, with its membership of a source root
+    ///
+    /// The text of the file is emptied before it is dropped, so the queries
+    /// that read it are invalidated. Returns whether the file was known.
+    pub fn remove_file(&self, db: &mut dyn SourceDatabase, file_id: FileId) -> bool {
+        let Some((_, text)) = self.files.remove(&file_id) else {
+            return false;
+        };
+        text.set_text(db).to(Arc::from(""));
+
+        if let Some((_, file_source_root)) = self.file_source_roots.remove(&file_id) {
+            let source_root_id = file_source_root.source_root_id(&*db);
+            self.update_source_root(db, source_root_id, |source_root| {
+                source_root.remove_file(file_id);
+            });
+        }
+        true
+    }
+
+    /// Move a file to a new path
+    ///
+    /// The file keeps its ID and text, and moves to the source root whose
+    /// directory most closely contains the new path. It stays in its source
+    /// root if no other one contains the path. Returns whether the file was
+    /// known.
+    pub fn rename_file(&self, db: &mut dyn SourceDatabase, file_id: FileId, path: PathBuf) -> bool {
+        if !self.contains_file(file_id) {
+            return false;
+        }
+
+        let old_root = self.file_source_root(file_id).map(|input| input.source_root_id(&*db));
+        let new_root = self
             .source_roots
-            .get(&source_root_id)
-            .expect("Unable to fetch source root id; this is a bug");
-        *source_root
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().source_root(&*db)))
+            .filter(|(_, source_root)| source_root.contains_path(&path))
+            .max_by_key(|(_, source_root)| source_root.path.components().count())
+            .map(|(source_root_id, _)| source_root_id)
+            .or(old_root);
+
+        if let Some(old_root) = old_root {
+            self.update_source_root(db, old_root, |source_root| {
+                source_root.remove_file(file_id);
+            });
+        }
+        if let Some(new_root) = new_root {
+            self.update_source_root(db, new_root, |source_root| {
+                source_root.add_file_with_path(file_id, path);
+            });
+            if old_root != Some(new_root) {
+                self.set_file_source_root_with_durability(db, file_id, new_root, Durability::LOW);
+            }
+        }
+        true
+    }
+
+    /// Get a source root, or `None` if it was never set
+    pub fn source_root(&self, source_root_id: SourceRootId) -> Option<SourceRootInput> {
+        self.source_roots.get(&source_root_id).map(|source_root| *source_root)
     }
 
     /// Set the source root with a specific durability
@@ -126,13 +187,24 @@ impl Files {
         };
     }
 
-    /// Get the source root of a file
-    pub fn file_source_root(&self, id: FileId) -> FileSourceRootInput {
-        let file_source_root = self
-            .file_source_roots
-            .get(&id)
-            .expect("Unable to fetch FileSourceRootInput; this is a bug");
-        *file_source_root
+    /// Replace a source root with an updated copy of it
+    fn update_source_root(
+        &self,
+        db: &mut dyn SourceDatabase,
+        source_root_id: SourceRootId,
+        update: impl FnOnce(&mut SourceRoot),
+    ) {
+        let Some(input) = self.source_root(source_root_id) else {
+            return;
+        };
+        let mut source_root = SourceRoot::clone(&input.source_root(&*db));
+        update(&mut source_root);
+        input.set_source_root(db).to(Arc::new(source_root));
+    }
+
+    /// Get the source root of a file, or `None` if it was never set
+    pub fn file_source_root(&self, id: FileId) -> Option<FileSourceRootInput> {
+        self.file_source_roots.get(&id).map(|file_source_root| *file_source_root)
     }
 
     /// Set the source root of a file with a specific durability
@@ -181,6 +253,12 @@ pub struct FileText {
     pub file_id: FileId,
 }
 
+/// Line index of a file, used to convert between byte offsets and positions
+#[salsa::tracked]
+pub fn file_line_index(db: &dyn SourceDatabase, file_text: FileText) -> Arc<LineIndex> {
+    Arc::new(LineIndex::new(&file_text.text(db)))
+}
+
 /// File source root input for salsa
 #[salsa::input]
 #[derive(Debug)]
@@ -198,8 +276,11 @@ pub struct SourceRootInput {
 /// Database trait for source code and project model
 #[salsa::db]
 pub trait SourceDatabase: salsa::Database {
-    /// Text of the file
-    fn file_text(&self, file_id: FileId) -> FileText;
+    /// Whether the text of the file was set
+    fn contains_file(&self, file_id: FileId) -> bool;
+
+    /// Text of the file, or `None` if the file is unknown
+    fn file_text(&self, file_id: FileId) -> Option<FileText>;
 
     /// Set the text of a file
     fn set_file_text(&mut self, file_id: FileId, text: &str);
@@ -212,11 +293,17 @@ pub trait SourceDatabase: salsa::Database {
         durability: Durability,
     );
 
-    /// Contents of the source root
-    fn source_root(&self, id: SourceRootId) -> SourceRootInput;
+    /// Remove a file, with its membership of a source root
+    fn remove_file(&mut self, file_id: FileId) -> bool;
+
+    /// Move a file to a new path, updating the source root it belongs to
+    fn rename_file(&mut self, file_id: FileId, path: PathBuf) -> bool;
+
+    /// Contents of the source root, or `None` if the source root is unknown
+    fn source_root(&self, id: SourceRootId) -> Option<SourceRootInput>;
 
-    /// Source root of the file
-    fn file_source_root(&self, id: FileId) -> FileSourceRootInput;
+    /// Source root of the file, or `None` if the file is unknown
+    fn file_source_root(&self, id: FileId) -> Option<FileSourceRootInput>;
 
     /// Set the source root of a file with a specific durability
     fn set_file_source_root_with_durability(
@@ -0,0 +1,308 @@
+//! LRU capacities and statistics of the memoized queries of a database.
+//!
+//! The databases keep the results of their expensive queries, such as parsing
+//! a file, in [`LruCache`]s, so a result is reused until the text it was
+//! computed from changes. Each cache keeps at most as many results as the
+//! capacity configured for its [`Query`], dropping the least recently used
+//! ones first; a capacity of 0 keeps every result.
+//!
+//! Capacities default to [`DEFAULT_FILE_TEXT_LRU_CAP`] and
+//! [`DEFAULT_PARSE_LRU_CAP`], and are overridden by the `[database.lru]`
+//! section of the project manifest and by `RAM_LRU_<QUERY>` environment
+//! variables, such as `RAM_LRU_PARSE=64`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+
+use crate::{DEFAULT_FILE_TEXT_LRU_CAP, DEFAULT_PARSE_LRU_CAP};
+
+/// The prefix of the environment variables overriding capacities
+pub const ENV_PREFIX: &str = "RAM_LRU_";
+
+/// A memoized query of the databases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Query {
+    /// The line index of the text of a file
+    FileText,
+    /// The syntax tree of a file
+    Parse,
+}
+
+impl Query {
+    /// All the queries, in the order they are reported
+    pub const ALL: [Query; 2] = [Query::FileText, Query::Parse];
+
+    /// Get the name of the query, as written in the manifest
+    pub fn name(self) -> &'static str {
+        match self {
+            Query::FileText => "file-text",
+            Query::Parse => "parse",
+        }
+    }
+
+    /// Get the capacity of the query when it is not configured
+    pub fn default_capacity(self) -> u16 {
+        match self {
+            Query::FileText => DEFAULT_FILE_TEXT_LRU_CAP,
+            Query::Parse => DEFAULT_PARSE_LRU_CAP,
+        }
+    }
+
+    /// Get the environment variable overriding the capacity of the query
+    pub fn env_var(self) -> String {
+        format!("{ENV_PREFIX}{}", self.name().replace('-', "_").to_uppercase())
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Query {
+    type Err = LruConfigError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Query::ALL
+            .into_iter()
+            .find(|query| query.name() == name)
+            .ok_or_else(|| LruConfigError::UnknownQuery(name.to_string()))
+    }
+}
+
+/// An error raised while configuring the capacities of queries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LruConfigError {
+    /// No query has the name
+    UnknownQuery(String),
+    /// The capacity is not a number from 0 to 65535
+    InvalidCapacity {
+        /// Where the capacity was set, such as an environment variable
+        source: String,
+        /// The capacity as it was written
+        value: String,
+    },
+}
+
+impl fmt::Display for LruConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LruConfigError::UnknownQuery(name) => {
+                let names = Query::ALL.map(Query::name).join("', '");
+                write!(f, "unknown query '{name}', expected one of '{names}'")
+            }
+            LruConfigError::InvalidCapacity { source, value } => {
+                write!(f, "invalid capacity '{value}' in {source}, expected a number up to 65535")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LruConfigError {}
+
+/// The LRU capacity of each query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LruConfig {
+    capacities: BTreeMap<Query, u16>,
+}
+
+impl Default for LruConfig {
+    fn default() -> Self {
+        Self {
+            capacities: Query::ALL
+                .into_iter()
+                .map(|query| (query, query.default_capacity()))
+                .collect(),
+        }
+    }
+}
+
+impl LruConfig {
+    /// Create a configuration with the default capacities
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the capacity of a query, where 0 keeps every result
+    #[must_use]
+    pub fn with_capacity(mut self, query: Query, capacity: u16) -> Self {
+        self.capacities.insert(query, capacity);
+        self
+    }
+
+    /// Set the capacities of queries by name, as read from the manifest
+    pub fn with_overrides<'a>(
+        mut self,
+        overrides: impl IntoIterator<Item = (&'a str, u16)>,
+    ) -> Result<Self, LruConfigError> {
+        for (name, capacity) in overrides {
+            self.capacities.insert(name.parse()?, capacity);
+        }
+        Ok(self)
+    }
+
+    /// Set the capacities given by `RAM_LRU_<QUERY>` environment variables
+    pub fn with_env(self) -> Result<Self, LruConfigError> {
+        self.with_env_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Set the capacities given by the variables `var` looks up
+    pub fn with_env_vars(
+        mut self,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, LruConfigError> {
+        for query in Query::ALL {
+            let name = query.env_var();
+            let Some(value) = var(&name) else {
+                continue;
+            };
+            let capacity = value
+                .trim()
+                .parse()
+                .map_err(|_| LruConfigError::InvalidCapacity { source: name, value })?;
+            self.capacities.insert(query, capacity);
+        }
+        Ok(self)
+    }
+
+    /// Get the capacity of a query
+    pub fn capacity(&self, query: Query) -> u16 {
+        self.capacities.get(&query).copied().unwrap_or_else(|| query.default_capacity())
+    }
+}
+
+/// The memo count and hit rate of a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryStats {
+    /// The query
+    pub query: Query,
+    /// The number of results kept
+    pub memos: usize,
+    /// The number of results kept at most, or 0 for no limit
+    pub capacity: u16,
+    /// The number of times a kept result was reused
+    pub hits: u64,
+    /// The number of times the result had to be computed
+    pub misses: u64,
+    /// The number of results dropped to stay within the capacity
+    pub evictions: u64,
+}
+
+impl QueryStats {
+    /// Get the share of lookups that reused a kept result, or `None` if the
+    /// query was never run
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+impl fmt::Display for QueryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capacity = match self.capacity {
+            0 => "unbounded".to_string(),
+            capacity => capacity.to_string(),
+        };
+        write!(
+            f,
+            "{}: {} memos (capacity {capacity}), {} hits, {} misses, {} evictions",
+            self.query, self.memos, self.hits, self.misses, self.evictions
+        )?;
+        if let Some(rate) = self.hit_rate() {
+            write!(f, ", {:.1}% hit rate", rate * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// A map keeping the most recently used results of a query
+#[derive(Debug, Clone)]
+pub struct LruCache<K, V> {
+    query: Query,
+    capacity: u16,
+    /// The results, from the least to the most recently used
+    entries: IndexMap<K, V>,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<K: Hash + Eq, V> LruCache<K, V> {
+    /// Create an empty cache for a query
+    pub fn new(query: Query, capacity: u16) -> Self {
+        Self { query, capacity, entries: IndexMap::new(), hits: 0, misses: 0, evictions: 0 }
+    }
+
+    /// Get the result of a key, marking it as the most recently used
+    ///
+    /// The result is computed with `compute` and kept if it is not kept yet,
+    /// or if `is_fresh` says the kept one is outdated, such as when the text
+    /// it was computed from changed.
+    pub fn get_or_insert_with(
+        &mut self,
+        key: K,
+        is_fresh: impl FnOnce(&V) -> bool,
+        compute: impl FnOnce() -> V,
+    ) -> &V {
+        match self.entries.get_index_of(&key) {
+            Some(index) if is_fresh(&self.entries[index]) => {
+                self.hits += 1;
+                let last = self.entries.len() - 1;
+                self.entries.move_index(index, last);
+            }
+            _ => {
+                self.misses += 1;
+                self.entries.shift_remove(&key);
+                self.entries.insert(key, compute());
+                self.evict();
+            }
+        }
+        &self.entries[self.entries.len() - 1]
+    }
+
+    /// Drop the result of a key
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.shift_remove(key)
+    }
+
+    /// Change the capacity, dropping the results over it
+    pub fn set_capacity(&mut self, capacity: u16) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Get the number of results kept
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no result is kept
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the memo count and hit rate of the cache
+    pub fn stats(&self) -> QueryStats {
+        QueryStats {
+            query: self.query,
+            memos: self.entries.len(),
+            capacity: self.capacity,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn evict(&mut self) {
+        let capacity = usize::from(self.capacity);
+        while capacity > 0 && self.entries.len() > capacity {
+            self.entries.shift_remove_index(0);
+            self.evictions += 1;
+        }
+    }
+}
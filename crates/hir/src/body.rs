@@ -5,27 +5,155 @@
 
 use std::default::Default;
 use std::fmt;
+use std::mem::size_of;
+use std::ops::Range;
 use std::sync::Arc;
 
+use la_arena::{Arena, Idx, IdxRange, RawIdx};
+use ram_core::instruction::InstructionKind;
 use ram_syntax::AstNode;
 
 use crate::expr::ExprId;
 use crate::ids::{DefId, LocalDefId};
 
 /// A body of code, such as a function body or a block
+///
+/// Expressions, instructions and labels are allocated in arenas. The
+/// expression with ID `n` has index `n` in its arena, and so does the
+/// instruction at position `n` of the program.
 #[derive(Clone, PartialEq, Eq, Default)]
 pub struct Body {
     /// The owner of this body
     pub owner: DefId,
 
-    /// The expressions in this body
-    pub exprs: Vec<Expr>,
+    /// The expressions in this body, by ID
+    pub exprs: Arena<Expr>,
 
-    /// The instructions in this body
-    pub instructions: Vec<Instruction>,
+    /// The instructions in this body, in program order
+    pub instructions: Arena<Instruction>,
 
     /// Labels defined in this body
-    pub labels: Vec<Label>,
+    pub labels: Arena<Label>,
+}
+
+impl Body {
+    /// Returns the expression with an ID
+    pub fn expr(&self, id: ExprId) -> Option<&Expr> {
+        let idx = position_idx(&self.exprs, id.0 as usize)?;
+        Some(&self.exprs[idx])
+    }
+
+    /// Returns the instruction at a position of the program
+    pub fn instruction(&self, position: usize) -> Option<&Instruction> {
+        let idx = position_idx(&self.instructions, position)?;
+        Some(&self.instructions[idx])
+    }
+
+    /// Returns the instruction at a position of the program, to modify it
+    pub fn instruction_mut(&mut self, position: usize) -> Option<&mut Instruction> {
+        let idx = position_idx(&self.instructions, position)?;
+        Some(&mut self.instructions[idx])
+    }
+
+    /// Returns the instructions at a range of positions of the program
+    pub fn instructions_in(&self, positions: Range<usize>) -> Option<&[Instruction]> {
+        if positions.start > positions.end || positions.end > self.instructions.len() {
+            return None;
+        }
+        let start = Idx::from_raw(RawIdx::from(u32::try_from(positions.start).ok()?));
+        let end = Idx::from_raw(RawIdx::from(u32::try_from(positions.end).ok()?));
+        Some(&self.instructions[IdxRange::new(start..end)])
+    }
+
+    /// Returns the label with an ID
+    pub fn label(&self, id: LocalDefId) -> Option<&Label> {
+        self.labels.values().find(|label| label.id == id)
+    }
+
+    /// Returns the position in the program of the instruction with an ID
+    pub fn instruction_position(&self, id: LocalDefId) -> Option<usize> {
+        self.instructions.values().position(|instruction| instruction.id == id)
+    }
+
+    /// Returns the input/output contract the program declares, if any
+    pub fn io_contract(&self) -> Option<IoContract> {
+        IoContract::parse(self.instruction(0)?.docs.iter().map(String::as_str))
+    }
+
+    /// Releases the memory the arenas reserved for more elements
+    pub fn shrink_to_fit(&mut self) {
+        self.exprs.shrink_to_fit();
+        self.instructions.shrink_to_fit();
+        self.labels.shrink_to_fit();
+    }
+
+    /// Returns the memory held by the body
+    ///
+    /// The elements are counted with the strings and lists they own, but not
+    /// the room the arenas reserved for more of them, which
+    /// [`shrink_to_fit`](Body::shrink_to_fit) releases.
+    pub fn memory_usage(&self) -> BodyMemory {
+        let strings = |strings: &[String]| {
+            strings.iter().map(String::capacity).sum::<usize>()
+                + strings.capacity() * size_of::<String>()
+        };
+        let exprs = self
+            .exprs
+            .values()
+            .map(|expr| {
+                size_of::<Expr>()
+                    + match &expr.kind {
+                        ExprKind::Literal(Literal::String(text) | Literal::Label(text)) => {
+                            text.capacity()
+                        }
+                        ExprKind::InstructionCall(call) => {
+                            call.opcode.capacity() + call.operands.capacity() * size_of::<ExprId>()
+                        }
+                        _ => 0,
+                    }
+            })
+            .sum();
+        let instructions = self
+            .instructions
+            .values()
+            .map(|instruction| {
+                size_of::<Instruction>()
+                    + instruction.opcode.capacity()
+                    + instruction.label_name.as_ref().map_or(0, String::capacity)
+                    + strings(&instruction.docs)
+            })
+            .sum();
+        let labels = self
+            .labels
+            .values()
+            .map(|label| size_of::<Label>() + label.name.capacity() + strings(&label.docs))
+            .sum();
+        BodyMemory { exprs, instructions, labels }
+    }
+}
+
+/// Returns the index of the element at a position of an arena, if it has one
+fn position_idx<T>(arena: &Arena<T>, position: usize) -> Option<Idx<T>> {
+    let raw = RawIdx::from(u32::try_from(position).ok()?);
+    (position < arena.len()).then(|| Idx::from_raw(raw))
+}
+
+/// The memory held by the elements of a body, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BodyMemory {
+    /// The memory held by the expressions
+    pub exprs: usize,
+    /// The memory held by the instructions
+    pub instructions: usize,
+    /// The memory held by the labels
+    pub labels: usize,
+}
+
+impl BodyMemory {
+    /// Returns the memory held by all the elements
+    pub fn total(&self) -> usize {
+        self.exprs + self.instructions + self.labels
+    }
 }
 
 /// An expression in the body
@@ -58,6 +186,12 @@ pub enum ExprKind {
 
     /// An array access expression (e.g., 2[3])
     ArrayAccess(ArrayAccess),
+
+    /// Arithmetic on operand values (e.g., buffer+2)
+    ///
+    /// Constant expressions are folded into literals during lowering, so
+    /// any that remain could not be evaluated.
+    Binary(BinaryExpr),
 }
 
 /// A literal value
@@ -123,6 +257,57 @@ pub struct ArrayAccess {
     pub index: ExprId,
 }
 
+/// Arithmetic on operand values (e.g., buffer+2)
+#[derive(Clone, PartialEq, Eq)]
+pub struct BinaryExpr {
+    /// The operator
+    pub op: BinaryOp,
+
+    /// The left-hand side expression
+    pub lhs: ExprId,
+
+    /// The right-hand side expression
+    pub rhs: ExprId,
+}
+
+/// Arithmetic operators allowed in operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// Addition (`+`)
+    Add,
+
+    /// Subtraction (`-`)
+    Sub,
+
+    /// Multiplication (`*`)
+    Mul,
+
+    /// Integer division (`/`)
+    Div,
+}
+
+impl BinaryOp {
+    /// Returns the symbol of the operator
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+
+    /// Applies the operator, returning `None` on overflow or division by zero
+    pub fn apply(self, lhs: i64, rhs: i64) -> Option<i64> {
+        match self {
+            BinaryOp::Add => lhs.checked_add(rhs),
+            BinaryOp::Sub => lhs.checked_sub(rhs),
+            BinaryOp::Mul => lhs.checked_mul(rhs),
+            BinaryOp::Div => lhs.checked_div(rhs),
+        }
+    }
+}
+
 /// An instruction in the body
 #[derive(Clone, PartialEq, Eq)]
 pub struct Instruction {
@@ -132,6 +317,9 @@ pub struct Instruction {
     /// The opcode (name) of the instruction
     pub opcode: String,
 
+    /// The kind of instruction the opcode resolves to
+    pub kind: InstructionKind,
+
     /// The operand to the instruction (if any)
     pub operand: Option<ExprId>,
 
@@ -140,6 +328,65 @@ pub struct Instruction {
 
     /// Source span for this instruction
     pub span: std::ops::Range<usize>,
+
+    /// Documentation comments attached to this instruction, which may hold
+    /// annotations such as [`CHAR_ANNOTATION`]
+    pub docs: Vec<String>,
+}
+
+/// The annotation that makes a `WRITE` instruction output its value as a character
+pub const CHAR_ANNOTATION: &str = "#[char]";
+
+impl Instruction {
+    /// Returns whether a documentation comment of this instruction is `annotation`
+    pub fn has_annotation(&self, annotation: &str) -> bool {
+        self.docs.iter().any(|doc| doc.trim() == annotation)
+    }
+}
+
+/// The annotation that declares how many values a program reads
+pub const READS_ANNOTATION: &str = "@reads";
+
+/// The annotation that declares how many values a program writes
+pub const WRITES_ANNOTATION: &str = "@writes";
+
+/// The number of values a program declares it reads and writes
+///
+/// A contract is declared in the documentation comments of the first
+/// instruction, such as `#* @reads 2 @writes 1`. Either count may be left
+/// out, and then it is not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct IoContract {
+    /// The number of values the program reads, if declared
+    pub reads: Option<usize>,
+
+    /// The number of values the program writes, if declared
+    pub writes: Option<usize>,
+}
+
+impl IoContract {
+    /// Parse a contract from documentation comments
+    ///
+    /// Each annotation is followed by its count, and annotations without a
+    /// count are ignored. Returns `None` if no comment declares a count.
+    pub fn parse<'a>(docs: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut contract = Self::default();
+        for doc in docs {
+            let mut words = doc.split_whitespace().peekable();
+            while let Some(word) = words.next() {
+                let slot = match word {
+                    READS_ANNOTATION => &mut contract.reads,
+                    WRITES_ANNOTATION => &mut contract.writes,
+                    _ => continue,
+                };
+                if let Some(count) = words.peek().and_then(|count| count.parse().ok()) {
+                    *slot = Some(count);
+                    words.next();
+                }
+            }
+        }
+        (contract != Self::default()).then_some(contract)
+    }
 }
 
 /// A label in the body
@@ -156,6 +403,9 @@ pub struct Label {
 
     /// Source span for this label
     pub span: std::ops::Range<usize>,
+
+    /// Documentation comments attached to this label
+    pub docs: Vec<String>,
 }
 
 /// Query implementation for retrieving a body from the database
@@ -167,8 +417,11 @@ pub(crate) fn body_query(db: &dyn crate::db::HirDatabase, def_id: DefId) -> Arc<
     // Get the ItemTree for this file
     let item_tree = db.item_tree(file_id);
 
-    // Get the file text
-    let file_text = db.file_text(file_id).text(db).to_string();
+    // Get the file text, a missing file has an empty body
+    let Some(file_text) = db.file_text(file_id) else {
+        return Arc::default();
+    };
+    let file_text = file_text.text(db).to_string();
 
     // Parse the file text into an AST Program
     let (program, _errors) = ram_parser::parse(&file_text);
@@ -277,7 +530,7 @@ pub mod debug {
     /// println!("{:?}", debug_output);
     /// ```
     pub fn expr_by_id(body: &Body, expr_id: ExprId) -> String {
-        if let Some(expr) = body.exprs.get(expr_id.0 as usize) {
+        if let Some(expr) = body.expr(expr_id) {
             format!("{:?}", expr)
         } else {
             format!("Expression with ID {:?} not found", expr_id)
@@ -331,12 +584,12 @@ pub mod debug {
         // Labels section
         if !body.labels.is_empty() {
             result.push_str("\nLabels:\n");
-            for (i, label) in body.labels.iter().enumerate() {
+            for (i, label) in body.labels.values().enumerate() {
                 result.push_str(&format!("  [{:?}] {:?}\n", i, label));
 
                 // Show the instruction this label is mapped to
                 if let Some(instr_id) = label.instruction_id
-                    && let Some(pos) = body.instructions.iter().position(|i| i.id == instr_id)
+                    && let Some(pos) = body.instruction_position(instr_id)
                 {
                     result.push_str(&format!("      → Instruction [{:?}]\n", pos));
                 }
@@ -346,19 +599,19 @@ pub mod debug {
         // Instructions section
         if !body.instructions.is_empty() {
             result.push_str("\nInstructions:\n");
-            for (i, instruction) in body.instructions.iter().enumerate() {
+            for (i, instruction) in body.instructions.values().enumerate() {
                 result.push_str(&format!("  [{:?}] {:?}\n", i, instruction));
 
                 // Show the operand expression
                 if let Some(operand_id) = instruction.operand
-                    && let Some(expr) = body.exprs.get(operand_id.0 as usize)
+                    && let Some(expr) = body.expr(operand_id)
                 {
                     result.push_str(&format!("      Operand: {:?}\n", expr));
                 }
 
                 // Show the label associated with this instruction
                 if let Some(label_name) = &instruction.label_name
-                    && let Some(pos) = body.labels.iter().position(|l| &l.name == label_name)
+                    && let Some(pos) = body.labels.values().position(|l| &l.name == label_name)
                 {
                     result.push_str(&format!("      Label: [{:?}] {:?}\n", pos, label_name));
                 }
@@ -368,7 +621,7 @@ pub mod debug {
         // Expressions section
         if !body.exprs.is_empty() {
             result.push_str("\nExpressions:\n");
-            for (i, expr) in body.exprs.iter().enumerate() {
+            for (i, expr) in body.exprs.values().enumerate() {
                 result.push_str(&format!("  [{:?}] {:?}\n", i, expr));
 
                 // Add details based on expression kind
@@ -378,14 +631,14 @@ pub mod debug {
                     }
                     ExprKind::MemoryRef(mem_ref) => {
                         result.push_str(&format!("      Mode: {:?}\n", mem_ref.mode));
-                        if let Some(addr_expr) = body.exprs.get(mem_ref.address.0 as usize) {
+                        if let Some(addr_expr) = body.expr(mem_ref.address) {
                             result.push_str(&format!("      Address: {:?}\n", addr_expr));
                         }
                     }
                     ExprKind::InstructionCall(call) => {
                         result.push_str(&format!("      Opcode: {:?}\n", call.opcode));
                         for (j, operand_id) in call.operands.iter().enumerate() {
-                            if let Some(operand_expr) = body.exprs.get(operand_id.0 as usize) {
+                            if let Some(operand_expr) = body.expr(*operand_id) {
                                 result.push_str(&format!(
                                     "      Operand {:?}: {:?}\n",
                                     j, operand_expr
@@ -408,21 +661,21 @@ impl fmt::Debug for Body {
 
         if !self.labels.is_empty() {
             writeln!(f, "  Labels:")?;
-            for label in &self.labels {
+            for label in self.labels.values() {
                 writeln!(f, "    {:?}", label)?;
             }
         }
 
         if !self.instructions.is_empty() {
             writeln!(f, "  Instructions:")?;
-            for instruction in &self.instructions {
+            for instruction in self.instructions.values() {
                 writeln!(f, "    {:?}", instruction)?;
             }
         }
 
         if !self.exprs.is_empty() {
             writeln!(f, "  Expressions:")?;
-            for expr in &self.exprs {
+            for expr in self.exprs.values() {
                 writeln!(f, "    {:?}", expr)?;
             }
         }
@@ -445,6 +698,7 @@ impl fmt::Debug for ExprKind {
             ExprKind::MemoryRef(mem_ref) => write!(f, "{:?}", mem_ref),
             ExprKind::InstructionCall(call) => write!(f, "{:?}", call),
             ExprKind::ArrayAccess(array_access) => write!(f, "{:?}", array_access),
+            ExprKind::Binary(binary) => write!(f, "{:?}", binary),
         }
     }
 }
@@ -500,6 +754,12 @@ impl fmt::Debug for ArrayAccess {
     }
 }
 
+impl fmt::Debug for BinaryExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Binary(expr{:?} {} expr{:?})", self.lhs.0, self.op.symbol(), self.rhs.0)
+    }
+}
+
 impl fmt::Debug for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Instruction {{ id: {:?}, opcode: {:?}", self.id, self.opcode)?;
@@ -0,0 +1,79 @@
+//! Canonical form of HIR bodies
+//!
+//! Two programs that differ only in the names of their labels, the spelling of
+//! their opcodes (`jmp` or `JMP` for `JUMP`), their comments or their layout
+//! have the same canonical form. Printed with [`print_body`], the canonical
+//! form is a stable text for comparing programs, snapshotting them in tests
+//! and exporting them to other tools.
+//!
+//! [`print_body`]: crate::print::print_body
+
+use std::collections::HashMap;
+
+use crate::body::{Body, CHAR_ANNOTATION, ExprKind, Literal};
+use crate::print::print_body;
+
+/// The prefix of the names given to labels
+pub const LABEL_PREFIX: &str = "L";
+
+/// Transform a body into its canonical form
+///
+/// - Labels are renamed `L0`, `L1`, ... in the order of the instructions they
+///   are attached to, with unattached labels last.
+/// - Opcodes are replaced by the canonical name of their instruction.
+/// - Documentation comments are removed, except annotations such as
+///   [`CHAR_ANNOTATION`] that change what an instruction does.
+///
+/// Spans are kept, so diagnostics on the canonical body still point into the
+/// original source.
+pub fn canonicalize(body: &Body) -> Body {
+    let mut body = body.clone();
+
+    let mut order = body.labels.iter().map(|(index, _)| index).collect::<Vec<_>>();
+    order.sort_by_key(|&index| {
+        body.labels[index]
+            .instruction_id
+            .and_then(|instruction_id| body.instruction_position(instruction_id))
+            .unwrap_or(usize::MAX)
+    });
+
+    let mut names = HashMap::new();
+    for (number, index) in order.into_iter().enumerate() {
+        let label = &mut body.labels[index];
+        let name = format!("{}{}", LABEL_PREFIX, number);
+        names.insert(std::mem::replace(&mut label.name, name.clone()), name);
+        label.docs.clear();
+    }
+
+    // Label references by ID follow the renamed labels, the ones by name are renamed
+    for expr in body.exprs.values_mut() {
+        if let ExprKind::Literal(Literal::Label(name) | Literal::String(name)) = &mut expr.kind
+            && let Some(canonical) = names.get(name)
+        {
+            *name = canonical.clone();
+        }
+    }
+
+    for instruction in body.instructions.values_mut() {
+        instruction.opcode = instruction.kind.name().to_string();
+        instruction.label_name =
+            instruction.label_name.as_ref().and_then(|name| names.get(name)).cloned();
+        instruction.docs.retain(|doc| doc.trim() == CHAR_ANNOTATION);
+    }
+
+    body
+}
+
+/// Print the canonical form of a body as RAM assembly
+///
+/// # Examples
+///
+/// ```
+/// use hir::body::Body;
+/// use hir::canonical::print_canonical;
+///
+/// assert_eq!(print_canonical(&Body::default()), "");
+/// ```
+pub fn print_canonical(body: &Body) -> String {
+    print_body(&canonicalize(body))
+}
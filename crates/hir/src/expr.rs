@@ -5,12 +5,22 @@
 
 use std::fmt;
 
+use la_arena::{Idx, RawIdx};
+
+use crate::body::Expr;
 use crate::ty::Ty;
 
 /// A unique identifier for an expression in a body
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ExprId(pub u32);
 
+impl ExprId {
+    /// Get the index of the expression in the arena of its body
+    pub fn idx(self) -> Idx<Expr> {
+        Idx::from_raw(RawIdx::from(self.0))
+    }
+}
+
 impl fmt::Display for ExprId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "expr{}", self.0)
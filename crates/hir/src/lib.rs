@@ -9,11 +9,13 @@
 //! that reflects the actual semantics that will be executed.
 
 pub mod body;
+pub mod canonical;
 pub mod db;
 pub mod expr;
 pub mod ids;
 pub mod lower;
 pub mod name_resolution;
+pub mod print;
 pub mod source_analyzer;
 pub mod ty;
 
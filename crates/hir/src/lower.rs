@@ -8,14 +8,15 @@ use std::collections::HashMap;
 
 use base_db::input::FileId;
 use cstree::text::TextRange;
-use hir_def::item_tree::ItemTree;
+use hir_def::item_tree::{ItemAnchor, ItemTree};
+use la_arena::Arena;
 use ram_core::instruction::InstructionKind;
 use ram_syntax::{AstNode, SyntaxKind, ast};
 use tracing::{error, warn};
 
 use crate::body::{
-    AddressingMode, ArrayAccess, Body, Expr, ExprKind, Instruction, InstructionCall, Label,
-    LabelRef, Literal, MemoryRef,
+    AddressingMode, ArrayAccess, BinaryExpr, BinaryOp, Body, Expr, ExprKind, Instruction,
+    InstructionCall, Label, LabelRef, Literal, MemoryRef,
 };
 // Assume HirDatabase trait exists or will be added if needed for context lookups
 // use crate::db::HirDatabase;
@@ -37,6 +38,8 @@ pub enum HirError {
     InvalidArrayAccessorIndex(TextRange),
     LabelNotFoundInItemTree(String, TextRange),
     LabelNotFoundInBody(String, LocalDefId),
+    MissingBinaryOperand(TextRange),
+    InvalidBinaryOperator(TextRange),
     // Consider adding: UnknownIdentifier(String, TextRange),
 }
 
@@ -75,6 +78,12 @@ impl std::fmt::Display for HirError {
             HirError::LabelNotFoundInBody(name, id) => {
                 write!(f, "Label '{}' (ID {:?}) not found in HIR body labels", name, id)
             }
+            HirError::MissingBinaryOperand(range) => {
+                write!(f, "Missing operand for arithmetic expression at {:?}", range)
+            }
+            HirError::InvalidBinaryOperator(range) => {
+                write!(f, "Invalid operator for arithmetic expression at {:?}", range)
+            }
         }
     }
 }
@@ -103,32 +112,42 @@ pub struct HirCollector {
 impl HirCollector {
     /// Create a new HIR collector, initializing labels from the ItemTree.
     pub fn new(owner: DefId, file_id: FileId, item_tree: &ItemTree) -> Self {
+        Self::with_label_ids(owner, file_id, item_tree, &LabelIds::default())
+    }
+
+    /// Create a new HIR collector, giving the labels the IDs they had in the
+    /// last version of the file.
+    pub fn with_label_ids(
+        owner: DefId,
+        file_id: FileId,
+        item_tree: &ItemTree,
+        label_ids: &LabelIds,
+    ) -> Self {
         let mut label_defs = HashMap::new();
         let mut label_name_to_local_id = HashMap::new();
-        let mut labels = Vec::new();
+        let mut labels = Arena::new();
 
         // Pre-populate labels from ItemTree
-        for label_def in &item_tree.labels {
-            // Assume ItemTreeId corresponds to the LocalDefId for labels within this file.
-            let local_id = LocalDefId(label_def.id.0);
+        for (label_def, local_id) in item_tree.labels.iter().zip(label_ids.resolve(item_tree)) {
             let def_id = DefId { file_id, local_id };
 
             label_defs.insert(label_def.name.clone(), def_id);
             label_name_to_local_id.insert(label_def.name.clone(), local_id);
 
-            // Use a default span for now
-            let span = 0..0;
+            let range = label_def.source.syntax_node.text_range();
+            let span = range.start().into()..range.end().into();
 
-            labels.push(Label {
+            labels.alloc(Label {
                 id: local_id,
                 name: label_def.name.clone(),
                 instruction_id: None, // To be filled during AST lowering
                 span,
+                docs: item_tree.docs(label_def.id).map(str::to_string).collect(),
             });
         }
 
         Self {
-            body: Body { owner, exprs: Vec::new(), instructions: Vec::new(), labels },
+            body: Body { owner, exprs: Arena::new(), instructions: Arena::new(), labels },
             label_defs,
             label_name_to_local_id,
             next_expr_id: 0,
@@ -156,8 +175,17 @@ impl HirCollector {
     pub fn lower_program_body(&mut self, program: &ast::Program) -> Result<(), HirError> {
         let mut current_label_name: Option<String> = None;
         let mut last_instruction_id: Option<LocalDefId> = None;
+        // Documentation comments waiting for the instruction they precede
+        let mut pending_docs: Vec<String> = Vec::new();
 
         for stmt in program.statements() {
+            // Comments not attached to a statement document nothing
+            if stmt.is_comment() {
+                continue;
+            }
+            // Collect the documentation comments right above the statement
+            pending_docs.extend(stmt.doc_comments().iter().filter_map(|doc| doc.text()));
+
             // Check if this statement has an instruction
             let has_instruction = stmt.instruction().is_some();
 
@@ -186,6 +214,11 @@ impl HirCollector {
                 // Otherwise, fall through to process the instruction in the same statement
             }
 
+            // Documentation comments only carry over labels to the instruction
+            if !has_instruction {
+                pending_docs.clear();
+            }
+
             // Process instruction if present
             if let Some(instruction) = stmt.instruction() {
                 let instr_local_id = self.next_instruction_local_id(); // Get ID for this instruction.
@@ -199,12 +232,15 @@ impl HirCollector {
                     hir_instruction.label_name = Some(label_name.clone());
                 }
 
+                // Documentation comments before the instruction, or before its label
+                hir_instruction.docs = std::mem::take(&mut pending_docs);
+
                 // If there was a label just before this instruction, link it.
                 if let Some(label_name) = current_label_name.take() {
                     self.link_label_to_instruction(&label_name, instr_local_id)?;
                 }
 
-                self.body.instructions.push(hir_instruction);
+                self.body.instructions.alloc(hir_instruction);
                 last_instruction_id = Some(instr_local_id);
             }
         }
@@ -246,7 +282,7 @@ impl HirCollector {
             )); // Placeholder ID
         };
 
-        let Some(label_in_body) = self.body.labels.iter_mut().find(|l| l.id == label_local_id)
+        let Some(label_in_body) = self.body.labels.values_mut().find(|l| l.id == label_local_id)
         else {
             // This indicates an internal inconsistency.
             error!(
@@ -256,8 +292,11 @@ impl HirCollector {
             return Err(HirError::LabelNotFoundInBody(label_name.to_string(), label_local_id));
         };
 
-        // Link the label to this instruction's ID.
-        label_in_body.instruction_id = Some(instr_local_id);
+        // Link the label to this instruction's ID. A redefinition of the label
+        // (reported by the ItemTree) keeps pointing at the first definition.
+        if label_in_body.instruction_id.is_none() {
+            label_in_body.instruction_id = Some(instr_local_id);
+        }
         Ok(())
     }
 
@@ -277,8 +316,8 @@ impl HirCollector {
 
         let opcode = opcode_token.text().to_string().to_uppercase();
 
-        // Validate opcode if needed (optional).
-        let _kind = InstructionKind::from_name(&opcode);
+        // Resolve the opcode once, so later passes don't match on its text
+        let kind = InstructionKind::from_name(&opcode);
 
         // Lower the operand, if present.
         let mut operand_exprs = Vec::new();
@@ -305,15 +344,17 @@ impl HirCollector {
             }),
             span: expr_span,
         };
-        self.body.exprs.push(call_expr);
+        self.body.exprs.alloc(call_expr);
 
         // Create the HIR Instruction.
         let hir_instruction = Instruction {
             id: instr_local_id,
             opcode,
+            kind,
             operand: first_operand_expr_id, // Link to the first operand expression.
             label_name: None,               // Will be set by the caller if needed
             span: instr_span,               // Use the instruction span
+            docs: Vec::new(),               // Will be set by the caller if needed
         };
 
         Ok(hir_instruction)
@@ -328,7 +369,7 @@ impl HirCollector {
 
         // Push a placeholder expression that we'll overwrite later
         // This ensures the ExprId matches the index in self.body.exprs
-        self.body.exprs.push(Expr {
+        self.body.exprs.alloc(Expr {
             id: expr_id,
             kind: ExprKind::Literal(Literal::Int(0)), // Placeholder kind
             span: span.clone(),
@@ -349,14 +390,19 @@ impl HirCollector {
         };
 
         // Update the expression at the reserved index with the actual data
-        let index = expr_id.0 as usize;
-        self.body.exprs[index] = Expr { id: expr_id, kind, span };
+        self.body.exprs[expr_id.idx()] = Expr { id: expr_id, kind, span };
 
         Ok(expr_id)
     }
 
     /// Lower a direct operand (e.g., `LOAD 100`, `LOAD label`, `LOAD 2[3]`).
     fn lower_direct_operand(&mut self, operand: ast::DirectOperand) -> Result<ExprKind, HirError> {
+        // Arithmetic on addresses: `LOAD buffer+2` -> MemoryRef(Direct, Binary(..))
+        if let Some(ast::OperandExpr::Binary(binary)) = operand.expr() {
+            let address = self.lower_binary_expr(&binary)?;
+            return Ok(ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address }));
+        }
+
         let value_node = operand
             .value()
             .ok_or_else(|| HirError::MissingDirectOperandValue(operand.syntax().text_range()))?;
@@ -366,7 +412,7 @@ impl HirCollector {
             return self.lower_array_accessor(&value_node, &array_accessor, AddressingMode::Direct);
         }
 
-        if let Some(num) = value_node.as_number() {
+        if let Some(num) = value_node.as_int() {
             // Direct numeric address: `LOAD 100` -> MemoryRef(Direct, Literal(100))
             let literal_expr_id = self.create_literal_expr(Literal::Int(num))?;
             return Ok(ExprKind::MemoryRef(MemoryRef {
@@ -388,6 +434,12 @@ impl HirCollector {
         &mut self,
         operand: ast::IndirectOperand,
     ) -> Result<ExprKind, HirError> {
+        // Arithmetic on addresses: `LOAD *buffer+2` -> MemoryRef(Indirect, Binary(..))
+        if let Some(ast::OperandExpr::Binary(binary)) = operand.expr() {
+            let address = self.lower_binary_expr(&binary)?;
+            return Ok(ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Indirect, address }));
+        }
+
         let value_node = operand
             .value()
             .ok_or_else(|| HirError::MissingIndirectOperandValue(operand.syntax().text_range()))?;
@@ -401,7 +453,7 @@ impl HirCollector {
             );
         }
 
-        if let Some(num) = value_node.as_number() {
+        if let Some(num) = value_node.as_int() {
             // Indirect numeric address: `LOAD *100` -> MemoryRef(Indirect, Literal(100))
             let literal_expr_id = self.create_literal_expr(Literal::Int(num))?;
             return Ok(ExprKind::MemoryRef(MemoryRef {
@@ -423,6 +475,12 @@ impl HirCollector {
         &mut self,
         operand: ast::ImmediateOperand,
     ) -> Result<ExprKind, HirError> {
+        // Arithmetic on values: `LOAD =N*2` -> Binary(..)
+        // The expression itself is the value, like an immediate array access.
+        if let Some(ast::OperandExpr::Binary(binary)) = operand.expr() {
+            return Ok(ExprKind::Binary(self.lower_binary(&binary)?));
+        }
+
         let value_node = operand
             .value()
             .ok_or_else(|| HirError::MissingImmediateOperandValue(operand.syntax().text_range()))?;
@@ -436,7 +494,7 @@ impl HirCollector {
             );
         }
 
-        if let Some(num) = value_node.as_number() {
+        if let Some(num) = value_node.as_int() {
             // Immediate numeric value: `LOAD #100` -> Literal(100)
             // The value *is* the operand, not an address.
             return Ok(ExprKind::Literal(Literal::Int(num)));
@@ -500,7 +558,7 @@ impl HirCollector {
         // Use a default span since we don't have the original AST node here
         let span = 0..0;
         let expr = Expr { id: expr_id, kind: ExprKind::Literal(literal), span };
-        self.body.exprs.push(expr);
+        self.body.exprs.alloc(expr);
         Ok(expr_id)
     }
 
@@ -510,10 +568,72 @@ impl HirCollector {
         // Use a default span since we don't have the original AST node here
         let span = 0..0;
         let expr = Expr { id: expr_id, kind: ExprKind::LabelRef(LabelRef { label_id }), span };
-        self.body.exprs.push(expr);
+        self.body.exprs.alloc(expr);
         Ok(expr_id)
     }
 
+    /// Lower an arithmetic expression and add it to the body, returning its ExprId.
+    fn lower_binary_expr(&mut self, binary: &ast::BinaryExpr) -> Result<ExprId, HirError> {
+        let kind = ExprKind::Binary(self.lower_binary(binary)?);
+        Ok(self.push_expr(kind, binary.syntax().text_range()))
+    }
+
+    /// Lower the operator and both sides of an arithmetic expression.
+    fn lower_binary(&mut self, binary: &ast::BinaryExpr) -> Result<BinaryExpr, HirError> {
+        let range = binary.syntax().text_range();
+        let op = match binary.op_kind() {
+            Some(SyntaxKind::PLUS) => BinaryOp::Add,
+            Some(SyntaxKind::MINUS) => BinaryOp::Sub,
+            Some(SyntaxKind::STAR) => BinaryOp::Mul,
+            Some(SyntaxKind::SLASH) => BinaryOp::Div,
+            _ => return Err(HirError::InvalidBinaryOperator(range)),
+        };
+
+        // A side is missing when the parser recovered from an operator without a value
+        let lhs = binary.lhs().ok_or(HirError::MissingBinaryOperand(range))?;
+        let rhs = binary.rhs().ok_or(HirError::MissingBinaryOperand(range))?;
+
+        let lhs = self.lower_operand_expr(&lhs)?;
+        let rhs = self.lower_operand_expr(&rhs)?;
+        Ok(BinaryExpr { op, lhs, rhs })
+    }
+
+    /// Lower one side of an arithmetic expression to the value it stands for.
+    ///
+    /// Numbers and characters become integer literals and identifiers become label references,
+    /// which are folded into the position of their instruction by [`Self::finish`].
+    fn lower_operand_expr(&mut self, expr: &ast::OperandExpr) -> Result<ExprId, HirError> {
+        let value_node = match expr {
+            ast::OperandExpr::Binary(binary) => return self.lower_binary_expr(binary),
+            ast::OperandExpr::Value(value_node) => value_node,
+        };
+        let range = value_node.syntax().text_range();
+
+        let kind = if let Some(array_accessor) = value_node.array_accessor() {
+            // The value of `2[3]` is only known at runtime, so this is never folded
+            self.lower_array_accessor(value_node, &array_accessor, AddressingMode::Immediate)?
+        } else if let Some(num) = value_node.as_int() {
+            ExprKind::Literal(Literal::Int(num))
+        } else if let Some(ident) = value_node.as_identifier() {
+            match self.label_defs.get(&ident).copied() {
+                Some(label_id) => ExprKind::LabelRef(LabelRef { label_id }),
+                None => ExprKind::Literal(Literal::Label(ident)),
+            }
+        } else {
+            return Err(HirError::InvalidImmediateOperandValue(range));
+        };
+
+        Ok(self.push_expr(kind, range))
+    }
+
+    /// Helper to add an expression spanning `range` to the body.
+    fn push_expr(&mut self, kind: ExprKind, range: TextRange) -> ExprId {
+        let expr_id = self.next_expr_id();
+        let span = range.start().into()..range.end().into();
+        self.body.exprs.alloc(Expr { id: expr_id, kind, span });
+        expr_id
+    }
+
     /// Lower an array accessor expression (e.g., `2[3]`).
     fn lower_array_accessor(
         &mut self,
@@ -522,7 +642,7 @@ impl HirCollector {
         mode: AddressingMode,
     ) -> Result<ExprKind, HirError> {
         // Get the base value (array)
-        let base_expr_id = if let Some(num) = value_node.as_number() {
+        let base_expr_id = if let Some(num) = value_node.as_int() {
             // Numeric base (e.g., 2[3])
             self.create_literal_expr(Literal::Int(num))?
         } else if let Some(ident) = value_node.as_identifier() {
@@ -558,7 +678,7 @@ impl HirCollector {
             kind: ExprKind::ArrayAccess(ArrayAccess { array: base_expr_id, index: index_expr_id }),
             span,
         };
-        self.body.exprs.push(array_access_expr);
+        self.body.exprs.alloc(array_access_expr);
 
         // Based on the addressing mode, return the appropriate expression kind
         match mode {
@@ -574,10 +694,84 @@ impl HirCollector {
     }
 
     /// Finish building the body and return it.
-    pub fn finish(self) -> Body {
-        // Potentially perform final checks or optimizations on self.body here.
+    ///
+    /// Arithmetic expressions whose operands are all constant are folded into
+    /// integer literals. A label stands for the position of the instruction it
+    /// marks, which is the value the VM resolves it to. The arenas are shrunk
+    /// to the elements they hold, since the body does not grow once built.
+    pub fn finish(mut self) -> Body {
+        let folded: Vec<_> = self
+            .body
+            .exprs
+            .iter()
+            .filter(|(_, expr)| matches!(expr.kind, ExprKind::Binary(_)))
+            .filter_map(|(index, expr)| Some((index, self.const_value(expr.id)?)))
+            .collect();
+
+        for (index, value) in folded {
+            self.body.exprs[index].kind = ExprKind::Literal(Literal::Int(value));
+        }
+
+        self.body.shrink_to_fit();
         self.body
     }
+
+    /// Evaluate an expression that only depends on numbers and labels.
+    fn const_value(&self, expr_id: ExprId) -> Option<i64> {
+        match &self.body.expr(expr_id)?.kind {
+            ExprKind::Literal(Literal::Int(value)) => Some(*value),
+            ExprKind::LabelRef(label_ref) => {
+                let label = self.body.label(label_ref.label_id.local_id)?;
+                let position = self.body.instruction_position(label.instruction_id?)?;
+                i64::try_from(position).ok()
+            }
+            ExprKind::Binary(binary) => {
+                binary.op.apply(self.const_value(binary.lhs)?, self.const_value(binary.rhs)?)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The IDs the labels of a file were lowered with, by their anchors.
+///
+/// Labels are given their [`ItemTreeId`](hir_def::item_tree::ItemTreeId)s as
+/// IDs, which shift when an item is added above them. Lowering an edited file
+/// with the label IDs of its last version gives the labels that are still there
+/// the IDs they had, so the bodies of both versions refer to them alike.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelIds {
+    /// The ID of each label, by the anchor of its item
+    ids: HashMap<ItemAnchor, LocalDefId>,
+}
+
+impl LabelIds {
+    /// Collects the IDs of the labels of a body lowered from `item_tree`.
+    pub fn of(body: &Body, item_tree: &ItemTree) -> Self {
+        // Labels are lowered in the order of the ItemTree
+        let ids = item_tree
+            .labels
+            .iter()
+            .zip(body.labels.values())
+            .filter_map(|(label_def, label)| Some((item_tree.anchor(label_def.id)?, label.id)))
+            .collect();
+        Self { ids }
+    }
+
+    /// Returns the IDs to give the labels of `item_tree`, in order.
+    ///
+    /// The IDs are only kept if every label was there before, as a new label
+    /// could otherwise be given the ID of another one.
+    fn resolve(&self, item_tree: &ItemTree) -> Vec<LocalDefId> {
+        item_tree
+            .labels
+            .iter()
+            .map(|label_def| self.ids.get(&item_tree.anchor(label_def.id)?).copied())
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_else(|| {
+                item_tree.labels.iter().map(|label_def| LocalDefId(label_def.id.0)).collect()
+            })
+    }
 }
 
 /// Lower an AST Program to a HIR Body using information from the ItemTree.
@@ -589,9 +783,23 @@ pub fn lower_program(
     owner: DefId, // ID of the item this body belongs to (e.g., function, module)
     file_id: FileId,
     item_tree: &ItemTree, // Pre-parsed item information
+) -> Result<Body, HirError> {
+    lower_program_with_label_ids(program, owner, file_id, item_tree, &LabelIds::default())
+}
+
+/// Lower an edited AST Program to a HIR Body, keeping the IDs of the labels of
+/// the last version of the file.
+///
+/// See [`LabelIds`].
+pub fn lower_program_with_label_ids(
+    program: &ast::Program,
+    owner: DefId,
+    file_id: FileId,
+    item_tree: &ItemTree,
+    label_ids: &LabelIds,
 ) -> Result<Body, HirError> {
     // 1. Initialize collector with ItemTree info (labels, etc.)
-    let mut collector = HirCollector::new(owner, file_id, item_tree);
+    let mut collector = HirCollector::with_label_ids(owner, file_id, item_tree, label_ids);
 
     // 2. Lower the program body from the AST, linking labels to instructions
     collector.lower_program_body(program)?;
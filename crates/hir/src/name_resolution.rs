@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use base_db::input::FileId;
+use hir_def::item_tree::{ItemTree, ModulePath};
 
 use crate::ids::{DefId, DefReference, LocalDefId};
 
@@ -52,6 +53,84 @@ impl ResolvedFile {
     }
 }
 
+/// An import that brings an unknown name into scope from another module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSuggestion {
+    /// The module that defines the name
+    pub module: String,
+
+    /// The name to import
+    pub name: String,
+}
+
+impl ImportSuggestion {
+    /// Returns the `use` statement importing the name
+    pub fn use_stmt(&self) -> String {
+        format!("use {}::{}", self.module, self.name)
+    }
+
+    /// Returns where to insert the import in a file and the text to insert
+    ///
+    /// The import goes on the line after the last module declaration or use
+    /// statement of the file, or at its start if it has neither.
+    pub fn insertion(&self, source: &str, item_tree: &ItemTree) -> (usize, String) {
+        let last_import = item_tree
+            .modules
+            .iter()
+            .map(|module| &module.source)
+            .chain(item_tree.use_stmts.iter().map(|use_def| &use_def.source))
+            .map(|source| usize::from(source.syntax_node.text_range().end()))
+            .max();
+        let Some(end) = last_import else {
+            return (0, format!("{}\n", self.use_stmt()));
+        };
+        match source[end..].find('\n') {
+            Some(newline) => (end + newline + 1, format!("{}\n", self.use_stmt())),
+            None => (source.len(), format!("\n{}", self.use_stmt())),
+        }
+    }
+}
+
+/// Suggests imports for a name that a file uses but does not define
+///
+/// Every module whose ItemTree defines the name is suggested, unless the file
+/// already imports the name from it. Suggestions are sorted by module name.
+///
+/// # Parameters
+///
+/// * `item_tree` - The ItemTree of the file using the name.
+/// * `name` - The unknown name.
+/// * `modules` - The other modules of the source root, by name.
+pub fn suggest_imports<'a>(
+    item_tree: &ItemTree,
+    name: &str,
+    modules: impl IntoIterator<Item = (&'a str, &'a ItemTree)>,
+) -> Vec<ImportSuggestion> {
+    let mut suggestions = modules
+        .into_iter()
+        .filter(|(module, tree)| tree.label(name).is_some() && !imports(item_tree, module, name))
+        .map(|(module, _)| ImportSuggestion { module: module.to_string(), name: name.to_string() })
+        .collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| a.module.cmp(&b.module));
+    suggestions.dedup();
+    suggestions
+}
+
+/// Checks whether an ItemTree imports `name` from `module`, by name or with a wildcard
+fn imports(item_tree: &ItemTree, module: &str, name: &str) -> bool {
+    item_tree.use_stmts.iter().any(|use_def| match &use_def.path {
+        ModulePath::Simple { module: imported, symbol } => {
+            imported == module && symbol.as_deref().is_none_or(|symbol| symbol == name)
+        }
+        ModulePath::Nested { segments, is_wildcard: true } => {
+            segments.last().is_some_and(|imported| imported == module)
+        }
+        ModulePath::Nested { segments, is_wildcard: false } => {
+            matches!(segments.as_slice(), [.., imported, symbol] if imported == module && symbol == name)
+        }
+    })
+}
+
 /// Query implementation for resolving a file
 #[allow(dead_code)]
 pub(crate) fn resolve_file_query(
@@ -0,0 +1,116 @@
+//! Printing HIR bodies as RAM assembly
+//!
+//! This module renders a [`Body`] back into canonical RAM assembly. Each label
+//! is printed on its own line, preceded by its documentation comments, and
+//! each instruction is indented on the line after its labels. Opcodes are
+//! printed by their canonical name, so aliases such as `JMP` become `JUMP`.
+//!
+//! Lowering the printed program again yields an equivalent body, which makes
+//! the output suitable both for emitting transformed programs and for
+//! snapshotting lowering results in a readable form.
+
+use std::fmt::Write;
+
+use crate::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use crate::expr::ExprId;
+use crate::ids::DefId;
+
+/// The indentation of instructions
+const INDENT: &str = "    ";
+
+/// Print a body as RAM assembly
+///
+/// # Examples
+///
+/// ```
+/// use hir::body::Body;
+/// use hir::print::print_body;
+///
+/// let body = Body::default();
+/// assert_eq!(print_body(&body), "");
+/// ```
+pub fn print_body(body: &Body) -> String {
+    let mut output = String::new();
+
+    for instruction in body.instructions.values() {
+        for label in body.labels.values().filter(|l| l.instruction_id == Some(instruction.id)) {
+            print_label(&mut output, &label.name, &label.docs);
+        }
+        writeln!(output, "{}{}", INDENT, print_instruction(body, instruction)).unwrap();
+    }
+
+    // Labels that are not attached to any instruction go last
+    for label in body.labels.values().filter(|l| l.instruction_id.is_none()) {
+        print_label(&mut output, &label.name, &label.docs);
+    }
+
+    output
+}
+
+/// Print an instruction and its operand, without labels or indentation
+pub fn print_instruction(body: &Body, instruction: &Instruction) -> String {
+    match instruction.operand.and_then(|operand| print_operand(body, operand)) {
+        Some(operand) => format!("{} {}", instruction.kind.name(), operand),
+        None => instruction.kind.name().to_string(),
+    }
+}
+
+/// Print the operand of an instruction
+///
+/// Returns `None` if the expression does not exist or is not an operand.
+pub fn print_operand(body: &Body, expr_id: ExprId) -> Option<String> {
+    let expr = body.expr(expr_id)?;
+    match &expr.kind {
+        ExprKind::MemoryRef(mem_ref) => {
+            let prefix = match mem_ref.mode {
+                AddressingMode::Direct => "",
+                AddressingMode::Indirect => "*",
+                AddressingMode::Immediate => "=",
+            };
+            Some(format!("{}{}", prefix, print_value(body, mem_ref.address)?))
+        }
+        // Immediate values are lowered without a memory reference
+        ExprKind::Literal(Literal::Int(_) | Literal::String(_))
+        | ExprKind::ArrayAccess(_)
+        | ExprKind::Binary(_) => Some(format!("={}", print_value(body, expr_id)?)),
+        ExprKind::Literal(Literal::Label(_)) | ExprKind::LabelRef(_) => print_value(body, expr_id),
+        ExprKind::InstructionCall(_) => None,
+    }
+}
+
+/// Print a value used in an operand, such as an address or a label
+fn print_value(body: &Body, expr_id: ExprId) -> Option<String> {
+    let expr = body.expr(expr_id)?;
+    match &expr.kind {
+        ExprKind::Literal(Literal::Int(value)) => Some(value.to_string()),
+        ExprKind::Literal(Literal::String(name) | Literal::Label(name)) => Some(name.clone()),
+        ExprKind::LabelRef(label_ref) => label_name(body, label_ref.label_id),
+        ExprKind::ArrayAccess(access) => Some(format!(
+            "{}[{}]",
+            print_value(body, access.array)?,
+            print_value(body, access.index)?
+        )),
+        // Expressions are only nested as the parser builds them, so no parentheses are needed
+        ExprKind::Binary(binary) => Some(format!(
+            "{}{}{}",
+            print_value(body, binary.lhs)?,
+            binary.op.symbol(),
+            print_value(body, binary.rhs)?
+        )),
+        ExprKind::MemoryRef(_) | ExprKind::InstructionCall(_) => None,
+    }
+}
+
+/// Find the name of a label of the body by its definition ID
+fn label_name(body: &Body, label_id: DefId) -> Option<String> {
+    body.label(label_id.local_id).map(|label| label.name.clone())
+}
+
+/// Print a label definition, preceded by its documentation comments
+fn print_label(output: &mut String, name: &str, docs: &[String]) {
+    for doc in docs {
+        // The text of documentation comments keeps the space after the marker
+        writeln!(output, "#*{}", doc).unwrap();
+    }
+    writeln!(output, "{}:", name).unwrap();
+}
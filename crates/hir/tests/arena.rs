@@ -0,0 +1,63 @@
+use base_db::input::FileId;
+use hir::body::{Body, ExprKind, Literal};
+use hir::expr::ExprId;
+use hir::ids::{DefId, LocalDefId};
+use hir::lower::lower_program;
+use hir_def::item_tree::ItemTree;
+use ram_syntax::{AstNode, ast};
+
+/// Parse and lower a program to HIR
+fn lower(source: &str) -> Body {
+    let (events, errors) = ram_parser::parse(source);
+    assert!(errors.is_empty(), "Parse errors: {:?}", errors);
+
+    let (tree, cache) = ram_parser::build_tree(events);
+    let syntax_node = ram_syntax::SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = ast::Program::cast(syntax_node).unwrap();
+
+    let file_id = FileId(0);
+    let item_tree = ItemTree::lower(&program, file_id);
+    let owner = DefId { file_id, local_id: LocalDefId(0) };
+    lower_program(&program, owner, file_id, &item_tree).unwrap()
+}
+
+#[test]
+fn test_lookups() {
+    let body = lower("loop: LOAD 1\nJUMP loop\nHALT\n");
+
+    // Expressions are found by ID, and instructions by position
+    for (idx, expr) in body.exprs.iter() {
+        assert_eq!(expr.id.idx(), idx);
+        assert_eq!(body.expr(expr.id), Some(expr));
+    }
+    assert_eq!(body.expr(ExprId(body.exprs.len() as u32)), None);
+
+    let jump = body.instruction(1).unwrap();
+    assert_eq!(jump.opcode, "JUMP");
+    assert_eq!(body.instruction_position(jump.id), Some(1));
+    assert_eq!(body.instruction(3), None);
+    assert_eq!(body.instructions_in(1..3).unwrap().len(), 2);
+    assert_eq!(body.instructions_in(2..4), None);
+
+    let label = body.labels.values().next().unwrap();
+    assert_eq!(body.label(label.id).map(|label| label.name.as_str()), Some("loop"));
+}
+
+#[test]
+fn test_memory_usage() {
+    let small = lower("HALT\n").memory_usage();
+    let large =
+        lower("/// Adds one\nstart: LOAD 1\nADD =1\nWRITE 0\nJUMP start\nHALT\n").memory_usage();
+    assert!(small.total() > 0);
+    assert!(large.exprs > small.exprs);
+    assert!(large.instructions > small.instructions);
+    assert!(large.labels > small.labels);
+    assert_eq!(large.total(), large.exprs + large.instructions + large.labels);
+
+    // Shrinking a built body does not change what it holds
+    let mut body = lower("LOAD 1\nHALT\n");
+    let before = body.clone();
+    body.shrink_to_fit();
+    assert_eq!(body, before);
+    assert_eq!(Body::default().memory_usage().total(), 0);
+}
@@ -2,6 +2,7 @@ use base_db::input::FileId;
 use hir::ids::DefId;
 use hir::lower::lower_program;
 use hir_def::item_tree::ItemTree;
+use ram_core::InstructionKind;
 use ram_syntax::{AstNode, ast};
 
 #[test]
@@ -39,17 +40,18 @@ fn test_array_access_lowering() {
     );
 
     // Get the instruction
-    let instruction = &body.instructions[0];
+    let instruction = body.instruction(0).unwrap();
     assert_eq!(instruction.opcode, "LOAD", "Expected LOAD instruction, got {}", instruction.opcode);
+    assert_eq!(instruction.kind, InstructionKind::Load);
 
     // Print all expressions for debugging
-    for (i, expr) in body.exprs.iter().enumerate() {
+    for (i, expr) in body.exprs.values().enumerate() {
         println!("Expr {}: {:?}", i, expr);
     }
 
     // Verify that the operand exists
     let operand_id = instruction.operand.unwrap();
-    let operand = &body.exprs[operand_id.0 as usize];
+    let operand = &body.exprs[operand_id.idx()];
     println!("Operand: {:?}", operand);
 }
 
@@ -88,18 +90,18 @@ fn test_indirect_array_access_lowering() {
     );
 
     // Get the instruction
-    let instruction = &body.instructions[0];
+    let instruction = body.instruction(0).unwrap();
     assert_eq!(instruction.opcode, "LOAD", "Expected LOAD instruction, got {}", instruction.opcode);
 
     // Verify that the operand is an indirect memory reference with an array access
     let operand_id = instruction.operand.unwrap();
-    let operand = &body.exprs[operand_id.0 as usize];
+    let operand = &body.exprs[operand_id.idx()];
 
     // Print the operand for debugging
     println!("Operand: {:?}", operand);
 
     // Print all expressions for debugging
-    for (i, expr) in body.exprs.iter().enumerate() {
+    for (i, expr) in body.exprs.values().enumerate() {
         println!("Expr {}: {:?}", i, expr);
     }
 }
@@ -139,17 +141,17 @@ fn test_immediate_array_access_lowering() {
     );
 
     // Get the instruction
-    let instruction = &body.instructions[0];
+    let instruction = body.instruction(0).unwrap();
     assert_eq!(instruction.opcode, "LOAD", "Expected LOAD instruction, got {}", instruction.opcode);
 
     // Print all expressions for debugging
-    for (i, expr) in body.exprs.iter().enumerate() {
+    for (i, expr) in body.exprs.values().enumerate() {
         println!("Expr {}: {:?}", i, expr);
     }
 
     // Verify that the operand exists
     if let Some(operand_id) = instruction.operand {
-        let operand = &body.exprs[operand_id.0 as usize];
+        let operand = &body.exprs[operand_id.idx()];
         println!("Operand: {:?}", operand);
     } else {
         println!("No operand found");
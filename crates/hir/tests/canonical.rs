@@ -0,0 +1,64 @@
+use base_db::input::FileId;
+use hir::body::Body;
+use hir::canonical::{canonicalize, print_canonical};
+use hir::ids::{DefId, LocalDefId};
+use hir::lower::lower_program;
+use hir_def::item_tree::ItemTree;
+use ram_syntax::{AstNode, ast};
+
+/// Parse and lower a program to HIR
+fn lower(source: &str) -> Body {
+    let (events, errors) = ram_parser::parse(source);
+    assert!(errors.is_empty(), "Parse errors: {:?}", errors);
+
+    let (tree, cache) = ram_parser::build_tree(events);
+    let syntax_node = ram_syntax::SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = ast::Program::cast(syntax_node).unwrap();
+
+    let file_id = FileId(0);
+    let item_tree = ItemTree::lower(&program, file_id);
+    let owner = DefId { file_id, local_id: LocalDefId(0) };
+    lower_program(&program, owner, file_id, &item_tree).unwrap()
+}
+
+#[test]
+fn test_print_canonical() {
+    let source = "\
+#* Counts down from the input
+start: read 1
+again:  load 1   # Load the counter
+    jzero finish
+    sub =1
+    store 1
+    jmp again
+finish: halt
+";
+
+    assert_eq!(
+        print_canonical(&lower(source)),
+        "\
+L0:
+    READ 1
+L1:
+    LOAD 1
+    JZERO L2
+    SUB =1
+    STORE 1
+    JUMP L1
+L2:
+    HALT
+"
+    );
+}
+
+#[test]
+fn test_canonical_forms_match() {
+    let first = "loop: LOAD 1\nJGTZ loop\nHALT\n";
+    let second = "# Another name\nwhile:\n    load 1\n    jgtz while\n    halt\n";
+
+    assert_eq!(print_canonical(&lower(first)), print_canonical(&lower(second)));
+
+    let canonical = canonicalize(&lower(second));
+    assert!(canonical.instructions.values().all(|i| i.opcode == i.kind.name()));
+    assert_eq!(print_canonical(&canonical), print_canonical(&lower(first)));
+}
@@ -0,0 +1,57 @@
+use base_db::input::FileId;
+use hir::name_resolution::{ImportSuggestion, suggest_imports};
+use hir_def::item_tree::ItemTree;
+use ram_syntax::{AstNode, ast};
+
+/// Parse a source and lower it to an ItemTree
+fn item_tree(source: &str, file_id: FileId) -> ItemTree {
+    let (events, errors) = ram_parser::parse(source);
+    assert!(errors.is_empty(), "Parse errors: {:?}", errors);
+
+    let (tree, cache) = ram_parser::build_tree(events);
+    let syntax_node = ram_syntax::SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = ast::Program::cast(syntax_node).unwrap();
+    ItemTree::lower(&program, file_id)
+}
+
+#[test]
+fn test_suggest_imports_from_defining_modules() {
+    let main = item_tree("JUMP print\n", FileId(0));
+    let io = item_tree("print: WRITE 1\nHALT\n", FileId(1));
+    let math = item_tree("add: ADD 1\nHALT\n", FileId(2));
+    let out = item_tree("print: WRITE 2\nHALT\n", FileId(3));
+
+    let modules = [("out", &out), ("math", &math), ("io", &io)];
+    let suggestions = suggest_imports(&main, "print", modules);
+    assert_eq!(
+        suggestions,
+        [
+            ImportSuggestion { module: "io".to_string(), name: "print".to_string() },
+            ImportSuggestion { module: "out".to_string(), name: "print".to_string() },
+        ]
+    );
+    assert_eq!(suggestions[0].use_stmt(), "use io::print");
+
+    // Modules the file already imports from are not suggested again
+    let main = item_tree("use out::*\nJUMP print\n", FileId(0));
+    let suggestions = suggest_imports(&main, "print", modules);
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].module, "io");
+}
+
+#[test]
+fn test_import_insertion() {
+    let suggestion = ImportSuggestion { module: "io".to_string(), name: "print".to_string() };
+
+    let source = "JUMP print\n";
+    assert_eq!(
+        suggestion.insertion(source, &item_tree(source, FileId(0))),
+        (0, "use io::print\n".to_string())
+    );
+
+    let source = "mod io\nuse math::add\nJUMP print\n";
+    assert_eq!(
+        suggestion.insertion(source, &item_tree(source, FileId(0))),
+        (21, "use io::print\n".to_string())
+    );
+}
@@ -0,0 +1,124 @@
+use base_db::input::FileId;
+use hir::body::{AddressingMode, Body, ExprKind, IoContract, Literal};
+use hir::ids::{DefId, LocalDefId};
+use hir::lower::lower_program;
+use hir::print::print_body;
+use hir_def::item_tree::ItemTree;
+use ram_syntax::{AstNode, ast};
+
+/// Parse and lower a program to HIR
+fn lower(source: &str) -> Body {
+    let (events, errors) = ram_parser::parse(source);
+    assert!(errors.is_empty(), "Parse errors: {:?}", errors);
+
+    let (tree, cache) = ram_parser::build_tree(events);
+    let syntax_node = ram_syntax::SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = ast::Program::cast(syntax_node).unwrap();
+
+    let file_id = FileId(0);
+    let item_tree = ItemTree::lower(&program, file_id);
+    let owner = DefId { file_id, local_id: LocalDefId(0) };
+    lower_program(&program, owner, file_id, &item_tree).unwrap()
+}
+
+/// The kind of the operand of the instruction at `index`
+fn operand(body: &Body, index: usize) -> &ExprKind {
+    let operand_id = body.instructions[index].operand.unwrap();
+    &body.exprs[operand_id.idx()].kind
+}
+
+/// The kind of the address of a memory reference operand
+fn address<'a>(body: &'a Body, kind: &ExprKind) -> (&'a ExprKind, AddressingMode) {
+    let ExprKind::MemoryRef(mem_ref) = kind else {
+        panic!("Expected a memory reference, got {:?}", kind);
+    };
+    (&body.exprs[mem_ref.address.idx()].kind, mem_ref.mode.clone())
+}
+
+#[test]
+fn test_constant_expressions_are_folded() {
+    let source = "\
+start: LOAD =2+3*4
+       LOAD buffer+2
+       STORE *buffer-1
+       LOAD =20/3-start
+buffer: HALT
+";
+    let body = lower(source);
+
+    // Multiplication binds tighter than addition
+    assert_eq!(operand(&body, 0), &ExprKind::Literal(Literal::Int(14)));
+
+    // Labels stand for the position of their instruction
+    let (value, mode) = address(&body, operand(&body, 1));
+    assert_eq!(value, &ExprKind::Literal(Literal::Int(6)));
+    assert_eq!(mode, AddressingMode::Direct);
+
+    let (value, mode) = address(&body, operand(&body, 2));
+    assert_eq!(value, &ExprKind::Literal(Literal::Int(3)));
+    assert_eq!(mode, AddressingMode::Indirect);
+
+    // Division truncates and operators of the same precedence associate to the left
+    assert_eq!(operand(&body, 3), &ExprKind::Literal(Literal::Int(6)));
+}
+
+#[test]
+fn test_non_constant_expressions_are_kept() {
+    let body = lower("LOAD =missing+1\nLOAD =1/0\nHALT\n");
+
+    let ExprKind::Binary(binary) = operand(&body, 0) else {
+        panic!("Expected an arithmetic expression, got {:?}", operand(&body, 0));
+    };
+    let lhs = &body.exprs[binary.lhs.idx()];
+    assert_eq!(lhs.kind, ExprKind::Literal(Literal::Label("missing".to_string())));
+    assert_eq!(lhs.span, 6..13);
+
+    // Division by zero can not be folded
+    assert!(matches!(operand(&body, 1), ExprKind::Binary(_)));
+}
+
+#[test]
+fn test_print_non_constant_expressions() {
+    let body = lower("LOAD =missing*2+1\nHALT\n");
+    assert_eq!(print_body(&body), "    LOAD =missing*2+1\n    HALT\n");
+}
+
+#[test]
+fn test_negative_and_character_literals() {
+    let source = "\
+#* #[char]
+WRITE ='A'
+LOAD =-5
+LOAD ='\\n'
+ADD =1--2
+";
+    let body = lower(source);
+
+    // Characters stand for their code point
+    assert_eq!(operand(&body, 0), &ExprKind::Literal(Literal::Int(65)));
+    assert_eq!(operand(&body, 1), &ExprKind::Literal(Literal::Int(-5)));
+    assert_eq!(operand(&body, 2), &ExprKind::Literal(Literal::Int(10)));
+    assert_eq!(operand(&body, 3), &ExprKind::Literal(Literal::Int(3)));
+
+    // Doc comments are attached to the instruction that follows them
+    assert!(body.instruction(0).unwrap().has_annotation(hir::body::CHAR_ANNOTATION));
+    assert!(body.instruction(1).unwrap().docs.is_empty());
+}
+
+#[test]
+fn test_io_contract_annotations() {
+    let source = "\
+#* Adds two numbers
+#* @reads 2 @writes 1
+READ 1
+READ 2
+";
+    let body = lower(source);
+    let contract = body.io_contract().unwrap();
+    assert_eq!((contract.reads, contract.writes), (Some(2), Some(1)));
+
+    // Either count may be left out, and annotations without a count are ignored
+    let contract = IoContract::parse(["@writes 3", "@reads many"]).unwrap();
+    assert_eq!((contract.reads, contract.writes), (None, Some(3)));
+    assert_eq!(IoContract::parse(["Reads numbers"]), None);
+}
@@ -0,0 +1,69 @@
+use base_db::input::FileId;
+use hir::body::Body;
+use hir::ids::{DefId, LocalDefId};
+use hir::lower::lower_program;
+use hir::print::print_body;
+use hir_def::item_tree::ItemTree;
+use ram_syntax::{AstNode, ast};
+
+/// Parse and lower a program to HIR
+fn lower(source: &str) -> Body {
+    let (events, errors) = ram_parser::parse(source);
+    assert!(errors.is_empty(), "Parse errors: {:?}", errors);
+
+    let (tree, cache) = ram_parser::build_tree(events);
+    let syntax_node = ram_syntax::SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = ast::Program::cast(syntax_node).unwrap();
+
+    let file_id = FileId(0);
+    let item_tree = ItemTree::lower(&program, file_id);
+    let owner = DefId { file_id, local_id: LocalDefId(0) };
+    lower_program(&program, owner, file_id, &item_tree).unwrap()
+}
+
+#[test]
+fn test_print_body() {
+    let source = "\
+#* Reads the input
+loop: read 1
+    load 1
+    jzero end
+    load 2[3]
+    store *4
+    add =1
+    jmp loop
+end: halt
+";
+
+    assert_eq!(
+        print_body(&lower(source)),
+        "\
+#* Reads the input
+loop:
+    READ 1
+    LOAD 1
+    JZERO end
+    LOAD 2[3]
+    STORE *4
+    ADD =1
+    JUMP loop
+end:
+    HALT
+"
+    );
+}
+
+#[test]
+fn test_print_body_round_trip() {
+    let source = "\
+start:
+    LOAD =5
+    SUB 1[2]
+    JGTZ start
+    WRITE *3
+done: HALT
+";
+
+    let printed = print_body(&lower(source));
+    assert_eq!(print_body(&lower(&printed)), printed);
+}
@@ -0,0 +1,812 @@
+//! Alias analysis for HIR
+//!
+//! This module provides alias analysis for HIR bodies. It bounds the memory
+//! addresses that indirect (`*1`) and indexed (`2[1]`) operands may refer to,
+//! by tracking the ranges of values the registers holding the pointers may
+//! have. The ranges start from the accumulator values found by constant
+//! propagation. Accesses whose targets cannot be bounded may alias any address.
+//!
+//! The bounded targets make it possible to report uninitialized reads and
+//! unused writes through pointers, which the data flow analysis cannot see.
+//! The same ranges show which arithmetic instructions may overflow.
+
+use std::any::TypeId;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+use petgraph::graph::NodeIndex;
+use ram_core::{AccumulatorValue, ArithmeticMode, Effect, InstructionEffects, JumpCondition};
+
+use crate::analyzers::constant_propagation::ConstantPropagationAnalysis;
+use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph, EdgeKind};
+use crate::config::{PassOption, SettingValue};
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// The setting of the number of times the state before an instruction may
+/// grow before the registers that keep changing are given up on
+const WIDENING_LIMIT: &str = "widening-limit";
+
+/// The setting of whether writes no read can observe are reported
+const REPORT_UNUSED_WRITES: &str = "report-unused-writes";
+
+/// The setting of whether arithmetic that may overflow is reported
+const REPORT_OVERFLOWS: &str = "report-overflows";
+
+/// The setting of the arithmetic mode programs run with, which decides
+/// whether an overflow stops a run
+pub const ARITHMETIC: &str = "arithmetic";
+
+/// Alias analysis pass
+///
+/// This pass bounds the memory addresses accessed through indirect and indexed
+/// operands, and reports reads of memory no write can have initialized,
+/// writes to memory no read can observe and arithmetic that may overflow.
+#[derive(Default)]
+pub struct AliasAnalysis;
+
+impl AnalysisPass for AliasAnalysis {
+    type Output = AliasResult;
+
+    fn name(&self) -> &'static str {
+        "AliasAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "alias"
+    }
+
+    fn options(&self) -> Vec<PassOption> {
+        vec![
+            PassOption::new(
+                WIDENING_LIMIT,
+                "Times the range of a register may grow before it is given up on",
+                SettingValue::Integer(8),
+            ),
+            PassOption::new(
+                REPORT_UNUSED_WRITES,
+                "Report writes to memory no read can observe",
+                SettingValue::Bool(true),
+            ),
+            PassOption::new(
+                REPORT_OVERFLOWS,
+                "Report arithmetic whose result may not fit in 64 bits",
+                SettingValue::Bool(true),
+            ),
+            PassOption::new(
+                ARITHMETIC,
+                "The arithmetic mode programs run with: checked, wrapping or saturating",
+                SettingValue::String(ArithmeticMode::Checked.name().to_string()),
+            ),
+        ]
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<ControlFlowAnalysis>(), TypeId::of::<ConstantPropagationAnalysis>()]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::INSTRUCTIONS.union(BodyParts::EXPRS)
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        // Get the control flow graph
+        let cfg = match ctx.get_result::<ControlFlowAnalysis>() {
+            Ok(cfg) => cfg.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        // Get the constant accumulator values
+        let constants = match ctx.get_result::<ConstantPropagationAnalysis>() {
+            Ok(result) => result.constant_values.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let body = ctx.body().clone();
+        let effects = body
+            .instructions
+            .values()
+            .map(|instr| (instr.id, ctx.instruction_effects(instr)))
+            .collect::<HashMap<_, _>>();
+
+        let widening_limit = ctx.option::<usize>(self, WIDENING_LIMIT);
+        let analyzer = AliasAnalyzer::new(&body, &cfg, &effects, &constants, widening_limit);
+        let (accesses, ranges) = analyzer.analyze();
+        // Only checked arithmetic stops a run on overflow
+        let checked = ArithmeticMode::from_name(&ctx.option::<String>(self, ARITHMETIC))
+            .is_none_or(|mode| mode == ArithmeticMode::Checked);
+        let overflows = if checked && ctx.option::<bool>(self, REPORT_OVERFLOWS) {
+            analyzer.possible_overflows(&ranges)
+        } else {
+            Vec::new()
+        };
+        let result = AliasResult { accesses, ranges };
+
+        for (instr_id, accumulator, operand) in overflows {
+            ctx.warning_at_instruction(
+                "Arithmetic may overflow".to_string(),
+                format!(
+                    "The accumulator {} and the operand {}, so the result may not fit in 64 \
+                     bits; with checked arithmetic the run stops here",
+                    describe_range(accumulator),
+                    describe_range(operand)
+                ),
+                instr_id,
+            );
+        }
+
+        for (instr_id, targets) in result.find_uninitialized_reads(&cfg) {
+            ctx.warning_at_instruction(
+                format!("Uninitialized memory read at {}", targets),
+                "No write to this memory location reaches this indirect read".to_string(),
+                instr_id,
+            );
+        }
+
+        let unused_writes = if ctx.option::<bool>(self, REPORT_UNUSED_WRITES) {
+            result.find_unused_writes(&cfg)
+        } else {
+            Vec::new()
+        };
+        for (instr_id, targets) in unused_writes {
+            ctx.info_at_instruction(
+                format!("Unused memory write at {}", targets),
+                "No read of this memory location is reachable from this indirect write".to_string(),
+                instr_id,
+            );
+        }
+
+        Ok(result)
+    }
+}
+
+/// An inclusive range of values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ValueRange {
+    /// The smallest value in the range
+    pub min: i64,
+    /// The largest value in the range
+    pub max: i64,
+}
+
+impl ValueRange {
+    /// Create a range with a single value
+    pub fn constant(value: i64) -> Self {
+        Self { min: value, max: value }
+    }
+
+    /// Get the value of the range, if it has a single one
+    pub fn as_constant(self) -> Option<i64> {
+        (self.min == self.max).then_some(self.min)
+    }
+
+    /// Check whether the range contains a value
+    pub fn contains(self, value: i64) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    /// Check whether two ranges have a value in common
+    pub fn overlaps(self, other: Self) -> bool {
+        self.min <= other.max && other.min <= self.max
+    }
+
+    /// Get the smallest range containing both ranges
+    pub fn join(self, other: Self) -> Self {
+        Self { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    /// Get the values in both ranges
+    ///
+    /// Returns `None` if the ranges have no value in common.
+    pub fn intersect(self, other: Self) -> Option<Self> {
+        self.overlaps(other)
+            .then(|| Self { min: self.min.max(other.min), max: self.max.min(other.max) })
+    }
+
+    /// Get the range without a value at either end of it
+    ///
+    /// Returns `None` if the value is the only one in the range.
+    pub fn exclude(self, value: i64) -> Option<Self> {
+        match (self.min == value, self.max == value) {
+            (true, true) => None,
+            (true, false) => Some(Self { min: value + 1, ..self }),
+            (false, true) => Some(Self { max: value - 1, ..self }),
+            (false, false) => Some(self),
+        }
+    }
+
+    /// Get the range of the sums of values of both ranges
+    ///
+    /// Returns `None` if a sum overflows.
+    pub fn add(self, other: Self) -> Option<Self> {
+        Some(Self { min: self.min.checked_add(other.min)?, max: self.max.checked_add(other.max)? })
+    }
+
+    /// Get the range of the differences of values of both ranges
+    ///
+    /// Returns `None` if a difference overflows.
+    pub fn sub(self, other: Self) -> Option<Self> {
+        Some(Self { min: self.min.checked_sub(other.max)?, max: self.max.checked_sub(other.min)? })
+    }
+
+    /// Get the range of the products of values of both ranges
+    ///
+    /// Returns `None` if a product overflows.
+    pub fn mul(self, other: Self) -> Option<Self> {
+        // The extreme products are those of the bounds
+        let products = [
+            self.min.checked_mul(other.min)?,
+            self.min.checked_mul(other.max)?,
+            self.max.checked_mul(other.min)?,
+            self.max.checked_mul(other.max)?,
+        ];
+        Some(Self { min: *products.iter().min()?, max: *products.iter().max()? })
+    }
+}
+
+/// Describe the values of a range, as in "the accumulator is 5"
+fn describe_range(range: ValueRange) -> String {
+    match range.as_constant() {
+        Some(value) => format!("is {}", value),
+        None => format!("is between {} and {}", range.min, range.max),
+    }
+}
+
+/// The memory addresses an operand may refer to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AliasTargets {
+    /// An address within the range
+    Range(ValueRange),
+    /// Any address, as the targets could not be bounded
+    MayAliasAll,
+}
+
+impl AliasTargets {
+    /// Check whether two operands may refer to the same address
+    pub fn may_alias(self, other: Self) -> bool {
+        match (self, other) {
+            (AliasTargets::Range(range), AliasTargets::Range(other)) => range.overlaps(other),
+            _ => true,
+        }
+    }
+
+    /// Get the address the operand refers to, if it is statically known
+    pub fn must_alias(self) -> Option<i64> {
+        match self {
+            AliasTargets::Range(range) => range.as_constant(),
+            AliasTargets::MayAliasAll => None,
+        }
+    }
+}
+
+impl fmt::Display for AliasTargets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AliasTargets::Range(range) => match range.as_constant() {
+                Some(address) => write!(f, "address {}", address),
+                None => write!(f, "addresses {} to {}", range.min, range.max),
+            },
+            AliasTargets::MayAliasAll => write!(f, "any address"),
+        }
+    }
+}
+
+/// An access to memory through an indirect or indexed operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// The addresses the operand may refer to
+    pub targets: AliasTargets,
+    /// Whether the instruction reads the memory
+    pub reads: bool,
+    /// Whether the instruction writes the memory
+    pub writes: bool,
+}
+
+/// The result of alias analysis
+#[derive(Debug, Clone, Default)]
+pub struct AliasResult {
+    /// Map from instruction IDs to the memory they access through their operand
+    pub accesses: HashMap<LocalDefId, MemoryAccess>,
+    /// Map from instruction IDs to the register ranges before them
+    ranges: HashMap<LocalDefId, RegisterRanges>,
+}
+
+impl AliasResult {
+    /// Get the addresses the operand of an instruction may refer to
+    ///
+    /// Returns `None` if the instruction does not access memory through an
+    /// indirect or indexed operand.
+    pub fn targets(&self, instr_id: LocalDefId) -> Option<AliasTargets> {
+        self.accesses.get(&instr_id).map(|access| access.targets)
+    }
+
+    /// Get the range of values a register may have before an instruction,
+    /// where register 0 is the accumulator
+    ///
+    /// Returns `None` if the range could not be bounded, or the instruction is
+    /// unreachable.
+    pub fn register_range(&self, instr_id: LocalDefId, register: i64) -> Option<ValueRange> {
+        self.ranges.get(&instr_id)?.get(register)
+    }
+
+    /// Find reads of memory that no write may have initialized
+    ///
+    /// A write whose targets are unknown may initialize any address, and reads
+    /// whose targets are unknown are never reported.
+    pub fn find_uninitialized_reads(
+        &self,
+        cfg: &ControlFlowGraph,
+    ) -> Vec<(LocalDefId, AliasTargets)> {
+        let mut uninitialized = self
+            .accesses
+            .iter()
+            .filter(|(_, read)| read.reads && read.targets != AliasTargets::MayAliasAll)
+            .filter(|&(&reader, read)| {
+                !self.accesses.iter().any(|(&writer, write)| {
+                    write.writes
+                        && write.targets.may_alias(read.targets)
+                        && is_reachable(cfg, writer, reader)
+                })
+            })
+            .map(|(&instr_id, read)| (instr_id, read.targets))
+            .collect::<Vec<_>>();
+        uninitialized.sort_by_key(|(instr_id, _)| instr_id.0);
+        uninitialized
+    }
+
+    /// Find writes to memory that no read may observe
+    ///
+    /// A read whose targets are unknown may observe any address, and writes
+    /// whose targets are unknown are never reported.
+    pub fn find_unused_writes(&self, cfg: &ControlFlowGraph) -> Vec<(LocalDefId, AliasTargets)> {
+        let mut unused = self
+            .accesses
+            .iter()
+            .filter(|(_, write)| write.writes && write.targets != AliasTargets::MayAliasAll)
+            .filter(|&(&writer, write)| {
+                !self.accesses.iter().any(|(&reader, read)| {
+                    read.reads
+                        && read.targets.may_alias(write.targets)
+                        && is_reachable(cfg, writer, reader)
+                })
+            })
+            .map(|(&instr_id, write)| (instr_id, write.targets))
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|(instr_id, _)| instr_id.0);
+        unused
+    }
+}
+
+/// Check if there's a path from source to target in the CFG
+fn is_reachable(cfg: &ControlFlowGraph, source: LocalDefId, target: LocalDefId) -> bool {
+    match (cfg.get_node_by_instruction(source), cfg.get_node_by_instruction(target)) {
+        (Some(source_idx), Some(target_idx)) => cfg.has_path(source_idx, target_idx),
+        _ => false,
+    }
+}
+
+/// The ranges of values the accumulator and registers may have
+///
+/// A register without a range may have any value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RegisterRanges {
+    /// The range of the accumulator
+    accumulator: Option<ValueRange>,
+    /// A register known to hold the value of the accumulator
+    accumulator_register: Option<i64>,
+    /// Map from register indices to their ranges
+    registers: HashMap<i64, ValueRange>,
+}
+
+impl RegisterRanges {
+    /// The state at the start of the program, where the accumulator is zero
+    fn entry() -> Self {
+        Self {
+            accumulator: Some(ValueRange::constant(0)),
+            accumulator_register: None,
+            registers: HashMap::new(),
+        }
+    }
+
+    /// Get the range of a register, where register 0 is the accumulator
+    fn get(&self, register: i64) -> Option<ValueRange> {
+        if register == 0 { self.accumulator } else { self.registers.get(&register).copied() }
+    }
+
+    /// Set the range of a register, where register 0 is the accumulator
+    fn set(&mut self, register: i64, range: Option<ValueRange>) {
+        if register == 0 {
+            self.accumulator = range;
+        } else if let Some(range) = range {
+            self.registers.insert(register, range);
+        } else {
+            self.registers.remove(&register);
+        }
+    }
+
+    /// Get the state covering both states
+    fn join(&self, other: &Self) -> Self {
+        let accumulator = self.accumulator.zip(other.accumulator).map(|(a, b)| a.join(b));
+        let registers = self
+            .registers
+            .iter()
+            .filter_map(|(&register, &range)| {
+                Some((register, range.join(*other.registers.get(&register)?)))
+            })
+            .collect();
+        let accumulator_register = self
+            .accumulator_register
+            .filter(|_| self.accumulator_register == other.accumulator_register);
+        Self { accumulator, accumulator_register, registers }
+    }
+
+    /// Give up on the ranges that changed from `self` to `other`
+    fn widen(&self, other: &Self) -> Self {
+        let accumulator = self.accumulator.filter(|_| self.accumulator == other.accumulator);
+        let registers = self
+            .registers
+            .iter()
+            .filter(|&(register, range)| other.registers.get(register) == Some(range))
+            .map(|(&register, &range)| (register, range))
+            .collect();
+        Self { accumulator, accumulator_register: other.accumulator_register, registers }
+    }
+
+    /// Narrow the accumulator to the values for which a jump goes along an edge
+    ///
+    /// Returns `None` if no value of the accumulator does, as the edge is then
+    /// never taken.
+    ///
+    /// # Parameters
+    ///
+    /// * `condition` - The condition of the jump.
+    /// * `taken` - Whether the edge is the one taken when the condition holds.
+    fn refine(mut self, condition: JumpCondition, taken: bool) -> Option<Self> {
+        let Some(range) = self.accumulator else {
+            return Some(self);
+        };
+        let refined = match (condition, taken) {
+            (JumpCondition::Always, _) => range,
+            (JumpCondition::Positive, true) => {
+                range.intersect(ValueRange { min: 1, max: i64::MAX })?
+            }
+            (JumpCondition::Positive, false) => {
+                range.intersect(ValueRange { min: i64::MIN, max: 0 })?
+            }
+            (JumpCondition::Zero, true) => range.intersect(ValueRange::constant(0))?,
+            (JumpCondition::Zero, false) => range.exclude(0)?,
+        };
+        self.accumulator = Some(refined);
+        if let Some(register) = self.accumulator_register {
+            self.registers.insert(register, refined);
+        }
+        Some(self)
+    }
+}
+
+/// Analyzer for memory aliases
+struct AliasAnalyzer<'a> {
+    /// The HIR body being analyzed
+    body: &'a Body,
+    /// The control flow graph
+    cfg: &'a ControlFlowGraph,
+    /// Map from instruction IDs to their effects
+    effects: &'a HashMap<LocalDefId, InstructionEffects>,
+    /// Map from instruction IDs to constant accumulator values after the instruction
+    constants: &'a HashMap<LocalDefId, Option<i64>>,
+    /// Number of times the state before an instruction may grow before the
+    /// registers that keep changing are given up on
+    widening_limit: usize,
+}
+
+impl<'a> AliasAnalyzer<'a> {
+    /// Create a new alias analyzer
+    fn new(
+        body: &'a Body,
+        cfg: &'a ControlFlowGraph,
+        effects: &'a HashMap<LocalDefId, InstructionEffects>,
+        constants: &'a HashMap<LocalDefId, Option<i64>>,
+        widening_limit: usize,
+    ) -> Self {
+        Self { body, cfg, effects, constants, widening_limit }
+    }
+
+    /// Bound the targets of the memory accesses of all instructions
+    ///
+    /// The register ranges before the reachable instructions are returned
+    /// along with them.
+    fn analyze(&self) -> (HashMap<LocalDefId, MemoryAccess>, HashMap<LocalDefId, RegisterRanges>) {
+        let states = self.register_ranges();
+
+        let mut accesses = HashMap::new();
+        for instr in self.body.instructions.values() {
+            let Some(operand) = instr.operand else {
+                continue;
+            };
+            let state = self
+                .cfg
+                .get_node_by_instruction(instr.id)
+                .and_then(|node_idx| states.get(&node_idx));
+            let Some(targets) = self.memory_targets(operand, state) else {
+                continue;
+            };
+
+            let effects = &self.effects[&instr.id];
+            accesses.insert(
+                instr.id,
+                MemoryAccess {
+                    targets,
+                    reads: effects.reads_operand(),
+                    writes: effects.writes_operand(),
+                },
+            );
+        }
+
+        let ranges = states
+            .into_iter()
+            .filter_map(|(node_idx, state)| {
+                Some((self.cfg.get_node(node_idx).instruction_id?, state))
+            })
+            .collect();
+        (accesses, ranges)
+    }
+
+    /// Find the arithmetic instructions whose result may not fit in 64 bits
+    ///
+    /// Only instructions with a bounded accumulator and operand are checked,
+    /// so values nothing is known about are never reported. Each instruction
+    /// comes with the ranges of its accumulator and operand.
+    fn possible_overflows(
+        &self,
+        ranges: &HashMap<LocalDefId, RegisterRanges>,
+    ) -> Vec<(LocalDefId, ValueRange, ValueRange)> {
+        let mut overflows = Vec::new();
+        for instr in self.body.instructions.values() {
+            let (Some(state), Some(value), Some(operand_id)) =
+                (ranges.get(&instr.id), self.effects[&instr.id].accumulator_value(), instr.operand)
+            else {
+                continue;
+            };
+            let (Some(accumulator), Some(operand)) =
+                (state.accumulator, self.operand_range(operand_id, state))
+            else {
+                continue;
+            };
+            let fits = match value {
+                AccumulatorValue::Add => accumulator.add(operand).is_some(),
+                AccumulatorValue::Sub => accumulator.sub(operand).is_some(),
+                AccumulatorValue::Mul => accumulator.mul(operand).is_some(),
+                AccumulatorValue::Div => !(accumulator.contains(i64::MIN) && operand.contains(-1)),
+                AccumulatorValue::Operand | AccumulatorValue::Unknown => true,
+            };
+            if !fits {
+                overflows.push((instr.id, accumulator, operand));
+            }
+        }
+        overflows
+    }
+
+    /// Compute the register ranges before every reachable instruction
+    fn register_ranges(&self) -> HashMap<NodeIndex, RegisterRanges> {
+        let mut states = HashMap::new();
+        let Some(entry) = self.cfg.entry_node() else {
+            return states;
+        };
+        states.insert(entry, RegisterRanges::entry());
+
+        let mut visits = HashMap::<NodeIndex, usize>::new();
+        let mut worklist = VecDeque::from([entry]);
+        while let Some(node_idx) = worklist.pop_front() {
+            let mut state = states[&node_idx].clone();
+            if let Some(instr_id) = self.cfg.get_node(node_idx).instruction_id
+                && let Some(instr) = self.body.instructions.values().find(|i| i.id == instr_id)
+            {
+                self.transfer(instr, &mut state);
+            }
+
+            for (successor, edge) in self.cfg.get_outgoing_edges(node_idx) {
+                let Some(state) = self.follow_edge(node_idx, edge, &state) else {
+                    continue;
+                };
+                let joined = match states.get(&successor) {
+                    Some(previous) => {
+                        let visits = visits.entry(successor).or_default();
+                        *visits += 1;
+                        let joined = previous.join(&state);
+                        let joined = if *visits > self.widening_limit {
+                            previous.widen(&joined)
+                        } else {
+                            joined
+                        };
+                        if joined == *previous {
+                            continue;
+                        }
+                        joined
+                    }
+                    None => state,
+                };
+                states.insert(successor, joined);
+                worklist.push_back(successor);
+            }
+        }
+        states
+    }
+
+    /// Get the register ranges after following an edge out of a node
+    ///
+    /// Returns `None` if the edge is never taken.
+    fn follow_edge(
+        &self,
+        node_idx: NodeIndex,
+        edge: EdgeKind,
+        state: &RegisterRanges,
+    ) -> Option<RegisterRanges> {
+        let taken = match edge {
+            EdgeKind::ConditionalTrue => true,
+            EdgeKind::ConditionalFalse => false,
+            _ => return Some(state.clone()),
+        };
+        let condition = self
+            .cfg
+            .get_node(node_idx)
+            .instruction_id
+            .and_then(|instr_id| self.effects.get(&instr_id))
+            .and_then(InstructionEffects::jump_condition);
+        match condition {
+            Some(condition) => state.clone().refine(condition, taken),
+            None => Some(state.clone()),
+        }
+    }
+
+    /// Update the register ranges with the effects of an instruction
+    fn transfer(&self, instr: &Instruction, state: &mut RegisterRanges) {
+        let effects = &self.effects[&instr.id];
+        let accumulator = state.accumulator;
+        let operand = instr.operand.and_then(|operand_id| self.operand_range(operand_id, state));
+
+        // Update the register the instruction writes, if it is known. Writes
+        // through indirect and indexed operands go to memory instead.
+        if effects.writes_operand() {
+            let copies_accumulator =
+                effects.reads_accumulator() && !effects.contains(Effect::ReadsInput);
+            let value = accumulator.filter(|_| copies_accumulator);
+            match instr.operand.map(|operand_id| self.operand_kind(operand_id)) {
+                Some(OperandKind::Register(register)) => {
+                    state.set(register, value);
+                    if copies_accumulator && register != 0 {
+                        state.accumulator_register = Some(register);
+                    } else if state.accumulator_register == Some(register) {
+                        state.accumulator_register = None;
+                    }
+                }
+                Some(OperandKind::Memory) => {}
+                _ => {
+                    state.registers.clear();
+                    state.accumulator_register = None;
+                }
+            }
+        }
+
+        if let Some(value) = effects.accumulator_value() {
+            state.accumulator_register =
+                match (value, instr.operand.map(|id| self.operand_kind(id))) {
+                    (AccumulatorValue::Operand, Some(OperandKind::Register(register)))
+                        if register != 0 =>
+                    {
+                        Some(register)
+                    }
+                    _ => None,
+                };
+            state.accumulator = match value {
+                AccumulatorValue::Operand => operand,
+                AccumulatorValue::Add => accumulator.zip(operand).and_then(|(a, b)| a.add(b)),
+                AccumulatorValue::Sub => accumulator.zip(operand).and_then(|(a, b)| a.sub(b)),
+                AccumulatorValue::Mul | AccumulatorValue::Div | AccumulatorValue::Unknown => None,
+            };
+        }
+
+        // Constant propagation may know the accumulator more precisely
+        if let Some(value) = self.constants.get(&instr.id).copied().flatten() {
+            state.accumulator = Some(ValueRange::constant(value));
+        }
+    }
+
+    /// Get the range of values of an operand
+    fn operand_range(&self, operand_id: ExprId, state: &RegisterRanges) -> Option<ValueRange> {
+        match self.operand_kind(operand_id) {
+            OperandKind::Value(value) => Some(ValueRange::constant(value)),
+            OperandKind::Register(register) => state.get(register),
+            OperandKind::Memory | OperandKind::Unknown => None,
+        }
+    }
+
+    /// Classify what an operand refers to
+    fn operand_kind(&self, operand_id: ExprId) -> OperandKind {
+        let Some(expr) = self.body.expr(operand_id) else {
+            return OperandKind::Unknown;
+        };
+        match &expr.kind {
+            ExprKind::Literal(Literal::Int(value)) => OperandKind::Value(*value),
+            ExprKind::MemoryRef(mem_ref) => {
+                match (&mem_ref.mode, self.int_literal(mem_ref.address)) {
+                    (AddressingMode::Immediate, Some(value)) => OperandKind::Value(value),
+                    (AddressingMode::Direct, Some(register)) => OperandKind::Register(register),
+                    (AddressingMode::Indirect, _) => OperandKind::Memory,
+                    (AddressingMode::Direct, None)
+                        if matches!(
+                            self.expr_kind(mem_ref.address),
+                            Some(ExprKind::ArrayAccess(_))
+                        ) =>
+                    {
+                        OperandKind::Memory
+                    }
+                    _ => OperandKind::Unknown,
+                }
+            }
+            _ => OperandKind::Unknown,
+        }
+    }
+
+    /// Bound the memory addresses an operand refers to
+    ///
+    /// Returns `None` if the operand does not refer to memory.
+    fn memory_targets(
+        &self,
+        operand_id: ExprId,
+        state: Option<&RegisterRanges>,
+    ) -> Option<AliasTargets> {
+        let ExprKind::MemoryRef(mem_ref) = self.expr_kind(operand_id)? else {
+            return None;
+        };
+
+        let range = match (&mem_ref.mode, self.expr_kind(mem_ref.address)?) {
+            // `*r` refers to the address held by register `r`
+            (AddressingMode::Indirect, ExprKind::Literal(Literal::Int(register))) => {
+                state.and_then(|state| state.get(*register))
+            }
+            // `b[r]` refers to the address `b` plus the value of register `r`
+            (AddressingMode::Direct, ExprKind::ArrayAccess(access)) => {
+                match (self.int_literal(access.array), self.int_literal(access.index)) {
+                    (Some(base), Some(register)) => state
+                        .and_then(|state| state.get(register))
+                        .and_then(|index| ValueRange::constant(base).add(index)),
+                    _ => None,
+                }
+            }
+            (AddressingMode::Indirect, _) => None,
+            _ => return None,
+        };
+
+        Some(range.map_or(AliasTargets::MayAliasAll, AliasTargets::Range))
+    }
+
+    /// Get the kind of an expression
+    fn expr_kind(&self, expr_id: ExprId) -> Option<&'a ExprKind> {
+        self.body.expr(expr_id).map(|expr| &expr.kind)
+    }
+
+    /// Get the value of an integer literal expression
+    fn int_literal(&self, expr_id: ExprId) -> Option<i64> {
+        match self.expr_kind(expr_id)? {
+            ExprKind::Literal(Literal::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// What an operand refers to
+enum OperandKind {
+    /// An immediate value
+    Value(i64),
+    /// A register, where register 0 is the accumulator
+    Register(i64),
+    /// Memory, through an indirect or indexed operand
+    Memory,
+    /// Something that cannot be determined statically
+    Unknown,
+}
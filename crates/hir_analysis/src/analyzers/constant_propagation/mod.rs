@@ -2,18 +2,24 @@
 //!
 //! This module provides constant propagation analysis for HIR bodies.
 //! It analyzes the program to determine which values are constant and
-//! can be determined at compile time.
+//! can be determined at compile time. How instructions change the accumulator
+//! and when they jump is derived from their declared effects, and the values
+//! are tracked per version of the accumulator from the [`SsaAnalysis`].
 
 use std::any::TypeId;
 use std::collections::HashMap;
+use std::fmt;
 
-use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use base_db::LineIndex;
+use hir::body::{AddressingMode, Body, ExprKind, Literal};
 use hir::ids::LocalDefId;
 use miette::Diagnostic;
+use ram_core::{InstructionEffects, JumpCondition};
 
 use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph, EdgeKind};
-use crate::analyzers::data_flow::{DataFlowAnalysis, DataFlowGraph};
+use crate::analyzers::ssa::{AccumulatorVersions, SsaAnalysis, VersionDef, VersionId};
 use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
 use crate::pass::AnalysisPass;
 
 /// Constant propagation analysis pass
@@ -32,8 +38,16 @@ impl AnalysisPass for ConstantPropagationAnalysis {
         "ConstantPropagationAnalysis"
     }
 
+    fn id(&self) -> &'static str {
+        "constprop"
+    }
+
     fn dependencies(&self) -> Vec<TypeId> {
-        vec![TypeId::of::<ControlFlowAnalysis>(), TypeId::of::<DataFlowAnalysis>()]
+        vec![TypeId::of::<ControlFlowAnalysis>(), TypeId::of::<SsaAnalysis>()]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::INSTRUCTIONS.union(BodyParts::EXPRS)
     }
 
     fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
@@ -43,17 +57,22 @@ impl AnalysisPass for ConstantPropagationAnalysis {
             Err(e) => return Err(Box::new(e)),
         };
 
-        // Get the data flow graph
-        let dfg = match ctx.get_result::<DataFlowAnalysis>() {
-            Ok(dfg) => dfg.clone(),
+        // Get the versions of the accumulator
+        let versions = match ctx.get_result::<SsaAnalysis>() {
+            Ok(versions) => versions.clone(),
             Err(e) => return Err(Box::new(e)),
         };
 
         // Clone the body to avoid borrowing issues
         let body = ctx.body().clone();
+        let effects = body
+            .instructions
+            .values()
+            .map(|instr| (instr.id, ctx.instruction_effects(instr)))
+            .collect::<HashMap<_, _>>();
 
         // Analyze constant values
-        let mut analyzer = ConstantPropagationAnalyzer::new(&body, &cfg, &dfg);
+        let mut analyzer = ConstantPropagationAnalyzer::new(&body, &cfg, &versions, &effects);
         let result = analyzer.analyze();
 
         // Analyze the control flow graph to find branches that can be optimized
@@ -62,7 +81,7 @@ impl AnalysisPass for ConstantPropagationAnalysis {
         // Report optimizations only for branches that can be statically determined
         let mut diagnostics = Vec::new();
         for (instr_id, branch_taken) in &optimized_edges {
-            if let Some(instr) = body.instructions.iter().find(|i| i.id == *instr_id) {
+            if let Some(instr) = body.instructions.values().find(|i| i.id == *instr_id) {
                 let branch_str = match branch_taken {
                     BranchTaken::Always => "always",
                     BranchTaken::Never => "never",
@@ -97,6 +116,60 @@ pub struct ConstantPropagationResult {
     pub optimized_edges: HashMap<LocalDefId, BranchTaken>,
 }
 
+impl ConstantPropagationResult {
+    /// Tabulate the value of the accumulator after each instruction, in
+    /// program order, with the line each instruction is written on
+    pub fn to_table(&self, body: &Body, line_index: &LineIndex) -> ConstantTable {
+        let rows = body
+            .instructions
+            .values()
+            .map(|instr| ConstantRow {
+                instruction_id: instr.id,
+                line: line_index.line_col(instr.span.start).line + 1,
+                opcode: instr.opcode.clone(),
+                acc: self.constant_values.get(&instr.id).copied().flatten(),
+            })
+            .collect();
+        ConstantTable { rows }
+    }
+}
+
+/// The value of the accumulator after each instruction of a program
+///
+/// Displays as a table with a column for the line, the opcode and the value,
+/// with `?` for values that are not statically known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConstantTable {
+    /// The rows, in program order
+    pub rows: Vec<ConstantRow>,
+}
+
+/// A row of a [`ConstantTable`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantRow {
+    /// The ID of the instruction
+    pub instruction_id: LocalDefId,
+    /// The line the instruction is written on, starting at 1
+    pub line: u32,
+    /// The opcode as written in the source
+    pub opcode: String,
+    /// The value of the accumulator after the instruction, if known
+    pub acc: Option<i64>,
+}
+
+impl fmt::Display for ConstantTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_width = self.rows.iter().map(|row| row.line.to_string().len()).fold(4, usize::max);
+        let opcode_width = self.rows.iter().map(|row| row.opcode.len()).fold(6, usize::max);
+        writeln!(f, "{:>line_width$}  {:<opcode_width$}  acc", "line", "opcode")?;
+        for row in &self.rows {
+            let acc = row.acc.map_or_else(|| "?".to_string(), |value| value.to_string());
+            writeln!(f, "{:>line_width$}  {:<opcode_width$}  {}", row.line, row.opcode, acc)?;
+        }
+        Ok(())
+    }
+}
+
 /// Indicates whether a branch is always taken or never taken
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BranchTaken {
@@ -106,145 +179,138 @@ pub enum BranchTaken {
     Never,
 }
 
+/// The value of a version of the accumulator during the analysis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VersionValue {
+    /// No value reaches the version yet
+    Undetermined,
+    /// The version always holds this value
+    Constant(i64),
+    /// The version may hold different values
+    Varying,
+}
+
+impl VersionValue {
+    /// Create the value of a version from an evaluated value
+    fn from_evaluated(value: Option<i64>) -> Self {
+        value.map_or(VersionValue::Varying, VersionValue::Constant)
+    }
+
+    /// Get the constant value, if the version always holds one
+    fn as_constant(self) -> Option<i64> {
+        match self {
+            VersionValue::Constant(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Join the values of two versions meeting at a phi
+    fn join(self, other: Self) -> Self {
+        match (self, other) {
+            (VersionValue::Undetermined, value) | (value, VersionValue::Undetermined) => value,
+            (VersionValue::Constant(a), VersionValue::Constant(b)) if a == b => self,
+            _ => VersionValue::Varying,
+        }
+    }
+}
+
 /// Analyzer for constant propagation
 struct ConstantPropagationAnalyzer<'a> {
     /// The HIR body being analyzed
     body: &'a Body,
     /// The control flow graph
     cfg: &'a ControlFlowGraph,
+    /// The versions of the accumulator
+    versions: &'a AccumulatorVersions,
+    /// Map from instruction IDs to their effects
+    effects: &'a HashMap<LocalDefId, InstructionEffects>,
 
-    /// Map from instruction IDs to constant accumulator values after the instruction
-    constant_values: HashMap<LocalDefId, Option<i64>>,
+    /// The values of the versions of the accumulator, indexed by their numbers
+    values: Vec<VersionValue>,
 }
 
 impl<'a> ConstantPropagationAnalyzer<'a> {
     /// Create a new constant propagation analyzer
-    fn new(body: &'a Body, cfg: &'a ControlFlowGraph, _dfg: &'a DataFlowGraph) -> Self {
-        Self { body, cfg, constant_values: HashMap::new() }
+    fn new(
+        body: &'a Body,
+        cfg: &'a ControlFlowGraph,
+        versions: &'a AccumulatorVersions,
+        effects: &'a HashMap<LocalDefId, InstructionEffects>,
+    ) -> Self {
+        Self { body, cfg, versions, effects, values: Vec::new() }
     }
 
     /// Analyze the program to determine constant values
+    ///
+    /// Every version starts out undetermined and only ever becomes less
+    /// precise, so a value that stays constant around a loop is found too.
     fn analyze(&mut self) -> HashMap<LocalDefId, Option<i64>> {
-        // Initialize all instructions with unknown values
-        for instr in &self.body.instructions {
-            self.constant_values.insert(instr.id, None);
-        }
-
-        // Perform a topological sort of the CFG to process instructions in order
-        if let Ok(sorted_nodes) = self.cfg.topological_sort() {
-            // Process instructions in topological order
-            for node_idx in sorted_nodes {
-                if let Some(instr_id) = self.cfg.get_node(node_idx).instruction_id
-                    && let Some(instr) = self.body.instructions.iter().find(|i| i.id == instr_id)
-                {
-                    self.process_instruction(instr);
+        self.values = vec![VersionValue::Undetermined; self.versions.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (version, definition) in self.versions.versions() {
+                let value = self.evaluate(definition);
+                if self.values[version.0 as usize] != value {
+                    self.values[version.0 as usize] = value;
+                    changed = true;
                 }
             }
-        } else {
-            // If there's a cycle, process instructions in any order
-            // This is less precise but still safe
-            for instr in &self.body.instructions {
-                self.process_instruction(instr);
-            }
         }
 
-        self.constant_values.clone()
+        self.body
+            .instructions
+            .values()
+            .map(|instr| {
+                let value = self.versions.version_after(instr.id).and_then(|v| self.value(v));
+                (instr.id, value)
+            })
+            .collect()
     }
 
-    /// Process an instruction to determine its effect on constant values
-    fn process_instruction(&mut self, instr: &Instruction) {
-        // Get the current accumulator value (if known)
-        let acc_value = self.get_accumulator_value_before(instr.id);
-
-        // Update the accumulator value based on the instruction
-        let new_acc_value = match instr.opcode.to_uppercase().as_str() {
-            "LOAD" => {
-                // LOAD sets the accumulator to the value at the memory address
-                if let Some(operand_id) = instr.operand {
-                    // Only consider immediate values (like =10) as constants
-                    // All other memory references (including array accesses) are not statically known
-                    self.get_constant_operand_value(operand_id)
-                } else {
-                    None
-                }
-            }
-            "STORE" => {
-                // STORE doesn't change the accumulator
-                acc_value
-            }
-            "ADD" => {
-                // ADD adds the operand to the accumulator
-                if let (Some(acc), Some(operand_id)) = (acc_value, instr.operand) {
-                    self.get_constant_operand_value(operand_id)
-                        .map(|operand_value| acc + operand_value)
-                } else {
-                    None
-                }
-            }
-            "SUB" => {
-                // SUB subtracts the operand from the accumulator
-                if let (Some(acc), Some(operand_id)) = (acc_value, instr.operand) {
-                    self.get_constant_operand_value(operand_id)
-                        .map(|operand_value| acc - operand_value)
-                } else {
-                    None
-                }
-            }
-            "MUL" => {
-                // MUL multiplies the accumulator by the operand
-                if let (Some(acc), Some(operand_id)) = (acc_value, instr.operand) {
-                    self.get_constant_operand_value(operand_id)
-                        .map(|operand_value| acc * operand_value)
-                } else {
-                    None
-                }
-            }
-            "DIV" => {
-                // DIV divides the accumulator by the operand
-                if let (Some(acc), Some(operand_id)) = (acc_value, instr.operand) {
-                    if let Some(operand_value) = self.get_constant_operand_value(operand_id) {
-                        if operand_value != 0 {
-                            Some(acc / operand_value)
-                        } else {
-                            // Division by zero is undefined
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }
-            "JUMP" | "JMP" | "JGTZ" | "JZERO" => {
-                // Jump instructions don't change the accumulator
-                acc_value
-            }
-            "HALT" => {
-                // HALT doesn't change the accumulator
-                acc_value
-            }
-            "READ" => {
-                // READ gets input from the user, so the value is unknown
-                None
-            }
-            "WRITE" => {
-                // WRITE doesn't change the accumulator
-                acc_value
-            }
-            _ => {
-                // Unknown instruction, assume it makes the accumulator unknown
-                None
+    /// Compute the value of a version from the current values of the others
+    fn evaluate(&self, definition: &VersionDef) -> VersionValue {
+        match definition {
+            // The accumulator starts at 0
+            VersionDef::Entry => VersionValue::Constant(0),
+            VersionDef::Phi { inputs, .. } => inputs
+                .iter()
+                .map(|input| self.values[input.0 as usize])
+                .fold(VersionValue::Undetermined, VersionValue::join),
+            VersionDef::Instruction(instr_id) => {
+                let Some(before) = self.versions.version_before(*instr_id) else {
+                    return VersionValue::Varying;
+                };
+                let acc_value = match self.values[before.0 as usize] {
+                    VersionValue::Undetermined => return VersionValue::Undetermined,
+                    value => value.as_constant(),
+                };
+                let Some(value) = self.effects[instr_id].accumulator_value() else {
+                    return VersionValue::Varying;
+                };
+
+                // Only immediate operands (like =10) are considered constants,
+                // all other operands are not statically known.
+                let operand_value = self
+                    .body
+                    .instructions
+                    .values()
+                    .find(|instr| instr.id == *instr_id)
+                    .and_then(|instr| instr.operand)
+                    .and_then(|operand_id| self.get_constant_operand_value(operand_id));
+                VersionValue::from_evaluated(value.evaluate(acc_value, operand_value))
             }
-        };
+        }
+    }
 
-        // Update the constant value map
-        self.constant_values.insert(instr.id, new_acc_value);
+    /// Get the constant value of a version, if it always holds one
+    fn value(&self, version: VersionId) -> Option<i64> {
+        self.values.get(version.0 as usize).and_then(|value| value.as_constant())
     }
 
     /// Get the constant value of an operand, if known
     fn get_constant_operand_value(&self, operand_id: hir::expr::ExprId) -> Option<i64> {
-        if let Some(expr) = self.body.exprs.get(operand_id.0 as usize) {
+        if let Some(expr) = self.body.expr(operand_id) {
             match &expr.kind {
                 ExprKind::Literal(Literal::Int(value)) => Some(*value),
                 ExprKind::MemoryRef(mem_ref) => {
@@ -252,7 +318,7 @@ impl<'a> ConstantPropagationAnalyzer<'a> {
                     // unless they are direct literals with a constant address and immediate mode
                     if let AddressingMode::Immediate = mem_ref.mode {
                         // For immediate addressing (e.g., =5), we can use the literal value
-                        if let Some(addr_expr) = self.body.exprs.get(mem_ref.address.0 as usize)
+                        if let Some(addr_expr) = self.body.expr(mem_ref.address)
                             && let ExprKind::Literal(Literal::Int(value)) = &addr_expr.kind
                         {
                             return Some(*value);
@@ -275,37 +341,7 @@ impl<'a> ConstantPropagationAnalyzer<'a> {
 
     /// Get the accumulator value before an instruction, if known
     fn get_accumulator_value_before(&self, instr_id: LocalDefId) -> Option<i64> {
-        // Find the instruction's node in the CFG
-        let node_idx = self.cfg.get_node_by_instruction(instr_id)?;
-
-        // Get the predecessors of this node
-        let predecessors = self.cfg.get_predecessors(node_idx);
-        if predecessors.is_empty() {
-            // If there are no predecessors, this is the entry point
-            // The accumulator starts at 0
-            return Some(0);
-        }
-
-        // Check if all predecessors have the same constant accumulator value
-        let mut pred_values = Vec::new();
-        for pred_idx in predecessors {
-            if let Some(pred_instr_id) = self.cfg.get_node(pred_idx).instruction_id {
-                if let Some(value) = self.constant_values.get(&pred_instr_id).copied().flatten() {
-                    pred_values.push(value);
-                } else {
-                    // If any predecessor has an unknown value, the result is unknown
-                    return None;
-                }
-            }
-        }
-
-        // If all predecessors have the same value, return that value
-        if !pred_values.is_empty() && pred_values.iter().all(|&v| v == pred_values[0]) {
-            Some(pred_values[0])
-        } else {
-            // If predecessors have different values, the result is unknown
-            None
-        }
+        self.value(self.versions.version_before(instr_id)?)
     }
 
     /// Analyze the control flow graph to find branches that can be optimized
@@ -314,19 +350,14 @@ impl<'a> ConstantPropagationAnalyzer<'a> {
         let mut optimized_edges = HashMap::new();
 
         // Find conditional jumps with constant accumulator values
-        for instr in &self.body.instructions {
-            let opcode = instr.opcode.to_uppercase();
-
+        for instr in self.body.instructions.values() {
             // Check if this is a conditional jump
-            if opcode == "JGTZ" || opcode == "JZERO" {
+            let condition = self.effects[&instr.id].jump_condition();
+            if let Some(condition) = condition.filter(|&c| c != JumpCondition::Always) {
                 // Get the accumulator value before this instruction
                 if let Some(acc_value) = self.get_accumulator_value_before(instr.id) {
                     // Determine if the condition is always true or always false
-                    let condition_true = match opcode.as_str() {
-                        "JGTZ" => acc_value > 0,
-                        "JZERO" => acc_value == 0,
-                        _ => false,
-                    };
+                    let condition_true = condition.holds(acc_value);
 
                     // Get the node index for this instruction
                     if let Some(node_idx) = self.cfg.get_node_by_instruction(instr.id) {
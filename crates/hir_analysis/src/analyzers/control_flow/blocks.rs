@@ -0,0 +1,138 @@
+//! Basic block level view of the control flow graph
+//!
+//! The control flow graph has a node for each instruction, which is precise
+//! but verbose. The block graph collapses each basic block to a single node,
+//! so passes and exports can reason about straight-line code as a whole.
+
+use std::collections::{HashMap, HashSet};
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{Dfs, EdgeRef};
+
+use super::graph::{BasicBlock, ControlFlowGraph, EdgeKind};
+
+/// The ID of a basic block, its index in [`ControlFlowGraph::basic_blocks`]
+pub type BlockId = usize;
+
+/// The most instructions the label of a block lists before summarizing them
+pub const BLOCK_LABEL_INSTRUCTIONS: usize = 4;
+
+/// A control flow graph collapsed to its basic blocks
+///
+/// Each edge is a control flow edge from the last instruction of a block to
+/// the first one of another, or of the same block when it loops. The edges
+/// inside a block are left out.
+#[derive(Debug, Clone, Default)]
+pub struct BlockGraph {
+    /// The blocks, with the index of each node being its block ID
+    graph: DiGraph<BasicBlock, EdgeKind>,
+    /// Map from the nodes of the control flow graph to their blocks
+    block_of: HashMap<NodeIndex, BlockId>,
+    /// The block the program starts in
+    entry: Option<BlockId>,
+}
+
+impl BlockGraph {
+    /// Collapse a control flow graph to its basic blocks
+    pub fn new(cfg: &ControlFlowGraph) -> Self {
+        let mut graph = DiGraph::new();
+        let mut block_of = HashMap::new();
+        for (id, block) in cfg.basic_blocks().iter().enumerate() {
+            graph.add_node(block.clone());
+            block_of.extend(block.nodes.iter().map(|&node_idx| (node_idx, id)));
+        }
+
+        let mut edges = HashSet::new();
+        for edge in cfg.graph().edge_references() {
+            let (Some(&source), Some(&target)) =
+                (block_of.get(&edge.source()), block_of.get(&edge.target()))
+            else {
+                continue;
+            };
+            let to_block_start = graph[NodeIndex::new(target)].entry_node() == Some(edge.target());
+            if (source != target || to_block_start)
+                && edges.insert((source, target, *edge.weight()))
+            {
+                graph.add_edge(NodeIndex::new(source), NodeIndex::new(target), *edge.weight());
+            }
+        }
+
+        let entry = cfg.entry_node().and_then(|node_idx| block_of.get(&node_idx).copied());
+        Self { graph, block_of, entry }
+    }
+
+    /// Get a block by its ID
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.graph[NodeIndex::new(id)]
+    }
+
+    /// Get the number of blocks
+    pub fn block_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    /// Get the block a node of the control flow graph belongs to
+    pub fn block_of(&self, node_idx: NodeIndex) -> Option<BlockId> {
+        self.block_of.get(&node_idx).copied()
+    }
+
+    /// Get the block the program starts in
+    pub fn entry_block(&self) -> Option<BlockId> {
+        self.entry
+    }
+
+    /// Get the blocks control can go to from a block, sorted by ID
+    pub fn successors(&self, id: BlockId) -> Vec<(BlockId, EdgeKind)> {
+        let mut successors = self
+            .graph
+            .edges_directed(NodeIndex::new(id), petgraph::Direction::Outgoing)
+            .map(|edge| (edge.target().index(), *edge.weight()))
+            .collect::<Vec<_>>();
+        successors.sort_by_key(|&(target, _)| target);
+        successors
+    }
+
+    /// Get the blocks control can come to a block from, sorted by ID
+    pub fn predecessors(&self, id: BlockId) -> Vec<(BlockId, EdgeKind)> {
+        let mut predecessors = self
+            .graph
+            .edges_directed(NodeIndex::new(id), petgraph::Direction::Incoming)
+            .map(|edge| (edge.source().index(), *edge.weight()))
+            .collect::<Vec<_>>();
+        predecessors.sort_by_key(|&(source, _)| source);
+        predecessors
+    }
+
+    /// Find the blocks the program can never reach, sorted by ID
+    pub fn unreachable_blocks(&self) -> Vec<BlockId> {
+        let mut reachable = HashSet::new();
+        if let Some(entry) = self.entry {
+            let mut dfs = Dfs::new(&self.graph, NodeIndex::new(entry));
+            while let Some(node_idx) = dfs.next(&self.graph) {
+                reachable.insert(node_idx.index());
+            }
+        }
+        (0..self.block_count()).filter(|id| !reachable.contains(id)).collect()
+    }
+
+    /// Get the underlying petgraph directed graph
+    pub fn graph(&self) -> &DiGraph<BasicBlock, EdgeKind> {
+        &self.graph
+    }
+}
+
+/// Summarize the instructions of a block for its label
+///
+/// Blocks of more than [`BLOCK_LABEL_INSTRUCTIONS`] instructions keep their
+/// first two and their last one, with a line counting those left out.
+pub fn summarize_block(mut lines: Vec<String>) -> Vec<String> {
+    if lines.len() <= BLOCK_LABEL_INSTRUCTIONS {
+        return lines;
+    }
+    let last = lines.pop().unwrap();
+    let omitted = lines.len() - 2;
+    lines.truncate(2);
+    lines.push(format!("… {} more", omitted));
+    lines.push(last);
+    lines
+}
@@ -0,0 +1,163 @@
+//! Call graph of the procedures of a program
+//!
+//! The procedures are the program itself, starting at its first instruction,
+//! and the subroutines called by `CALL` instructions, starting at the label
+//! they call. A procedure owns the instructions reachable from its entry
+//! without returning, stepping over the calls it makes.
+
+use std::collections::HashMap;
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+
+use super::graph::ControlFlowGraph;
+
+/// The name of the procedure starting at the entry of an unlabeled program
+pub const ENTRY_PROCEDURE: &str = "main";
+
+/// A procedure of the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Procedure {
+    /// The name of the procedure, the label of its entry
+    pub name: String,
+    /// The node of the control flow graph the procedure starts at
+    pub entry: NodeIndex,
+    /// The nodes of the control flow graph of the procedure, in node order
+    pub nodes: Vec<NodeIndex>,
+}
+
+/// The calls between the procedures of a program
+///
+/// Edges go from a caller to a callee and carry the node of the call, so a
+/// procedure calling another twice has two edges to it.
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    /// The graph of procedures and the calls between them
+    graph: DiGraph<Procedure, NodeIndex>,
+    /// Map from procedure names to their nodes in the graph
+    procedures: HashMap<String, NodeIndex>,
+}
+
+impl CallGraph {
+    /// Build the call graph of a control flow graph
+    ///
+    /// `name` gives the label of the instruction at a node, which names the
+    /// procedure starting there.
+    pub fn build(cfg: &ControlFlowGraph, name: impl Fn(NodeIndex) -> Option<String>) -> Self {
+        let mut call_graph = Self::default();
+        let Some(entry) = cfg.entry_node() else {
+            return call_graph;
+        };
+
+        let mut entries = vec![entry];
+        let mut targets =
+            cfg.call_sites().keys().filter_map(|&call| cfg.call_target(call)).collect::<Vec<_>>();
+        targets.sort_unstable();
+        entries.extend(targets);
+
+        // The procedure at each entry, keyed by the entry
+        let mut by_entry = HashMap::new();
+        for entry_idx in entries {
+            if by_entry.contains_key(&entry_idx) {
+                continue;
+            }
+            let procedure_name = match name(entry_idx) {
+                Some(label) => label,
+                None if entry_idx == entry => ENTRY_PROCEDURE.to_string(),
+                None => format!("N{}", entry_idx.index()),
+            };
+            let procedure = Procedure {
+                name: procedure_name.clone(),
+                entry: entry_idx,
+                nodes: cfg.procedure_nodes(entry_idx),
+            };
+            let procedure_idx = call_graph.graph.add_node(procedure);
+            call_graph.procedures.insert(procedure_name, procedure_idx);
+            by_entry.insert(entry_idx, procedure_idx);
+        }
+
+        // Each call inside a procedure is an edge to the procedure it calls
+        let mut calls = Vec::new();
+        for caller in call_graph.graph.node_indices() {
+            for &node_idx in &call_graph.graph[caller].nodes {
+                if !cfg.call_sites().contains_key(&node_idx) {
+                    continue;
+                }
+                if let Some(callee) = cfg.call_target(node_idx).and_then(|t| by_entry.get(&t)) {
+                    calls.push((caller, *callee, node_idx));
+                }
+            }
+        }
+        for (caller, callee, call) in calls {
+            call_graph.graph.add_edge(caller, callee, call);
+        }
+
+        call_graph
+    }
+
+    /// Get the procedures, starting with the entry of the program
+    pub fn procedures(&self) -> impl Iterator<Item = &Procedure> {
+        self.graph.node_weights()
+    }
+
+    /// Get a procedure by its name
+    pub fn procedure(&self, name: &str) -> Option<&Procedure> {
+        self.procedures.get(name).map(|&procedure_idx| &self.graph[procedure_idx])
+    }
+
+    /// Get the number of procedures
+    pub fn len(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    /// Check whether the program has no procedures, as when it is empty
+    pub fn is_empty(&self) -> bool {
+        self.graph.node_count() == 0
+    }
+
+    /// Get the names of the procedures a procedure calls, without duplicates
+    pub fn callees(&self, name: &str) -> Vec<&str> {
+        let Some(&procedure_idx) = self.procedures.get(name) else {
+            return Vec::new();
+        };
+        let mut callees = self
+            .graph
+            .edges(procedure_idx)
+            .map(|edge| self.graph[edge.target()].name.as_str())
+            .collect::<Vec<_>>();
+        callees.sort_unstable();
+        callees.dedup();
+        callees
+    }
+
+    /// Get the calls between procedures, as the caller, the callee and the call
+    pub fn calls(&self) -> impl Iterator<Item = (&Procedure, &Procedure, NodeIndex)> {
+        self.graph
+            .edge_references()
+            .map(|edge| (&self.graph[edge.source()], &self.graph[edge.target()], *edge.weight()))
+    }
+
+    /// Get the groups of procedures that call each other recursively
+    ///
+    /// Each group is a cycle of the call graph, with its names sorted. A
+    /// procedure calling itself is a group of its own.
+    pub fn recursive_groups(&self) -> Vec<Vec<&str>> {
+        let mut groups = petgraph::algo::kosaraju_scc(&self.graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || self.graph.contains_edge(scc[0], scc[0]))
+            .map(|scc| {
+                let mut names =
+                    scc.iter().map(|&idx| self.graph[idx].name.as_str()).collect::<Vec<_>>();
+                names.sort_unstable();
+                names
+            })
+            .collect::<Vec<_>>();
+        groups.sort_unstable();
+        groups
+    }
+
+    /// Check whether a procedure can call itself, directly or indirectly
+    pub fn is_recursive(&self, name: &str) -> bool {
+        self.recursive_groups().iter().any(|group| group.contains(&name))
+    }
+}
@@ -6,14 +6,22 @@
 
 use std::collections::{HashMap, HashSet};
 
+use hir::body::Body;
 use hir::ids::LocalDefId;
 use petgraph::algo::{dominators, has_path_connecting, toposort};
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::{DiGraph, EdgeIndex, NodeIndex};
 use petgraph::visit::{Dfs, EdgeRef};
+use ram_core::InstructionKind;
+use serde_json::{Map, Value, json};
+
+use super::blocks::{BlockGraph, summarize_block};
+use super::call_graph::CallGraph;
+use crate::context::AnalysisContext;
+use crate::export::{ExportFormat, ExportOptions};
 
 /// The kind of edge in the control flow graph
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EdgeKind {
     /// An unconditional edge (e.g., fallthrough)
     Unconditional,
@@ -21,6 +29,10 @@ pub enum EdgeKind {
     ConditionalTrue,
     /// A conditional edge taken when the condition is false
     ConditionalFalse,
+    /// An edge from a call to the subroutine it calls
+    Call,
+    /// An edge from a return to the instruction after a call of its subroutine
+    Return,
 }
 
 /// A node in the control flow graph
@@ -65,6 +77,14 @@ impl BasicBlock {
     }
 }
 
+/// The nodes and edges an export of a control flow graph draws
+struct ExportView {
+    /// The drawn nodes, each with the labels of the instructions it stands for
+    nodes: Vec<(NodeIndex, Vec<String>)>,
+    /// The drawn edges between the drawn nodes
+    edges: Vec<(NodeIndex, NodeIndex, EdgeKind)>,
+}
+
 /// A control flow graph
 ///
 /// The control flow graph represents the control flow of a program as a directed graph,
@@ -79,6 +99,12 @@ pub struct ControlFlowGraph {
     entry_node: Option<NodeIndex>,
     /// Map from instruction IDs to node indices
     instr_to_node: HashMap<LocalDefId, NodeIndex>,
+    /// Map from calls to the node they return to, if any
+    call_sites: HashMap<NodeIndex, Option<NodeIndex>>,
+    /// The procedures of the program and the calls between them
+    call_graph: CallGraph,
+    /// The node after which execution can continue past the last instruction
+    falls_off_end: Option<NodeIndex>,
 }
 
 impl ControlFlowGraph {
@@ -89,6 +115,9 @@ impl ControlFlowGraph {
             basic_blocks: Vec::new(),
             entry_node: None,
             instr_to_node: HashMap::new(),
+            call_sites: HashMap::new(),
+            call_graph: CallGraph::default(),
+            falls_off_end: None,
         }
     }
 
@@ -114,6 +143,76 @@ impl ControlFlowGraph {
         self.graph.add_edge(source, target, kind)
     }
 
+    /// Record a call, with the node it returns to
+    ///
+    /// The edge to the called subroutine is added separately.
+    pub fn add_call_site(&mut self, call: NodeIndex, return_site: Option<NodeIndex>) {
+        self.call_sites.insert(call, return_site);
+    }
+
+    /// Get the calls of the graph, with the node each returns to
+    pub fn call_sites(&self) -> &HashMap<NodeIndex, Option<NodeIndex>> {
+        &self.call_sites
+    }
+
+    /// Get the entry of the subroutine a call calls
+    pub fn call_target(&self, call: NodeIndex) -> Option<NodeIndex> {
+        self.get_outgoing_edges(call)
+            .into_iter()
+            .find_map(|(target, kind)| (kind == EdgeKind::Call).then_some(target))
+    }
+
+    /// Get the nodes of the procedure starting at `entry`, in node order
+    ///
+    /// These are the nodes reachable from the entry without returning. Calls
+    /// are stepped over, continuing at the node they return to.
+    pub fn procedure_nodes(&self, entry: NodeIndex) -> Vec<NodeIndex> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![entry];
+        while let Some(node_idx) = stack.pop() {
+            if !visited.insert(node_idx) {
+                continue;
+            }
+            if let Some(&return_site) = self.call_sites.get(&node_idx) {
+                stack.extend(return_site);
+                continue;
+            }
+            stack.extend(
+                self.get_outgoing_edges(node_idx)
+                    .into_iter()
+                    .filter(|(_, kind)| *kind != EdgeKind::Return)
+                    .map(|(target, _)| target),
+            );
+        }
+
+        let mut nodes = visited.into_iter().collect::<Vec<_>>();
+        nodes.sort_unstable();
+        nodes
+    }
+
+    /// Record that execution can continue past `node`, the last instruction
+    pub fn set_falls_off_end(&mut self, node: NodeIndex) {
+        self.falls_off_end = Some(node);
+    }
+
+    /// Get the node after which execution can fall off the end of the program
+    ///
+    /// This is the last instruction when it neither halts nor always jumps
+    /// away, whether or not it is reachable.
+    pub fn falls_off_end(&self) -> Option<NodeIndex> {
+        self.falls_off_end
+    }
+
+    /// Get the call graph of the procedures of the program
+    pub fn call_graph(&self) -> &CallGraph {
+        &self.call_graph
+    }
+
+    /// Set the call graph of the procedures of the program
+    pub fn set_call_graph(&mut self, call_graph: CallGraph) {
+        self.call_graph = call_graph;
+    }
+
     /// Add a basic block to the graph
     pub fn add_basic_block(&mut self, block: BasicBlock) -> usize {
         let block_id = self.basic_blocks.len();
@@ -136,6 +235,23 @@ impl ControlFlowGraph {
         &self.basic_blocks[block_id]
     }
 
+    /// Get the basic blocks, indexed by their ID
+    pub fn basic_blocks(&self) -> &[BasicBlock] {
+        &self.basic_blocks
+    }
+
+    /// Collapse the graph to its basic blocks
+    pub fn block_graph(&self) -> BlockGraph {
+        BlockGraph::new(self)
+    }
+
+    /// Get the label of a basic block, summarizing the text of its instructions
+    pub fn block_label(&self, block: &BasicBlock, body: Option<&Body>) -> Vec<String> {
+        summarize_block(
+            block.nodes.iter().map(|&node_idx| self.node_label(node_idx, body)).collect(),
+        )
+    }
+
     /// Get the entry node of the graph
     pub fn entry_node(&self) -> Option<NodeIndex> {
         self.entry_node
@@ -338,11 +454,7 @@ impl ControlFlowGraph {
             let target_id = format!("N{}", target.index());
             let edge_kind = self.graph.edge_weight(edge).unwrap();
 
-            let edge_style = match edge_kind {
-                EdgeKind::Unconditional => "-->",
-                EdgeKind::ConditionalTrue => "-.->|true|",
-                EdgeKind::ConditionalFalse => "-.->|false|",
-            };
+            let edge_style = mermaid_edge(*edge_kind);
 
             result.push_str(&format!("    {} {} {}\n", source_id, edge_style, target_id));
         }
@@ -351,121 +463,14 @@ impl ControlFlowGraph {
     }
 
     /// Get a Mermaid representation of the graph with detailed instruction information
-    pub fn to_mermaid_with_context(&self, context: &crate::context::AnalysisContext) -> String {
+    pub fn to_mermaid_with_context(&self, context: &AnalysisContext) -> String {
         let mut result = String::from("graph TD\n");
-        let body = context.body();
+        let body: &Body = context.body();
 
         // Add nodes with detailed instruction information
         for node_idx in self.graph.node_indices() {
             let node_id = format!("N{}", node_idx.index());
-            let node = &self.graph[node_idx];
-
-            let label = if let Some(instr_id) = node.instruction_id {
-                // Find the instruction in the body
-
-                body.instructions
-                    .iter()
-                    .find(|i| i.id == instr_id)
-                    .map(|i| {
-                        let operand_str = match i.operand {
-                            Some(expr_id) => {
-                                // Try to find the expression
-                                if let Some(expr) = body.exprs.get(expr_id.0 as usize) {
-                                    match &expr.kind {
-                                        hir::body::ExprKind::Literal(lit) => match lit {
-                                            hir::body::Literal::Int(val) => format!("{}", val),
-                                            hir::body::Literal::String(s) => format!("\"{}\"", s),
-                                            hir::body::Literal::Label(label) => {
-                                                format!(":{}", label)
-                                            }
-                                        },
-                                        hir::body::ExprKind::LabelRef(label_ref) => {
-                                            // Find the label name from the label_id
-                                            // We need to match on the local_id part of the DefId
-                                            body.labels
-                                                .iter()
-                                                .find(|l| l.id.0 == label_ref.label_id.local_id.0)
-                                                .map(|l| format!(":{}", l.name))
-                                                .unwrap_or_else(|| {
-                                                    format!(
-                                                        "label_{}",
-                                                        label_ref.label_id.local_id.0
-                                                    )
-                                                })
-                                        }
-                                        hir::body::ExprKind::MemoryRef(mem_ref) => {
-                                            let mode_prefix = match mem_ref.mode {
-                                                hir::body::AddressingMode::Direct => "",
-                                                hir::body::AddressingMode::Indirect => "*",
-                                                hir::body::AddressingMode::Immediate => "=",
-                                            };
-
-                                            if let Some(addr_expr) =
-                                                body.exprs.get(mem_ref.address.0 as usize)
-                                            {
-                                                if let hir::body::ExprKind::Literal(
-                                                    hir::body::Literal::Int(val),
-                                                ) = &addr_expr.kind
-                                                {
-                                                    format!("{}{}", mode_prefix, val)
-                                                } else {
-                                                    format!("{}?", mode_prefix)
-                                                }
-                                            } else {
-                                                format!("{}?", mode_prefix)
-                                            }
-                                        }
-                                        hir::body::ExprKind::InstructionCall(_) => {
-                                            "call".to_string()
-                                        }
-                                        hir::body::ExprKind::ArrayAccess(array_access) => {
-                                            // Try to get the base and index expressions
-                                            let base_str = if let Some(base_expr) =
-                                                body.exprs.get(array_access.array.0 as usize)
-                                            {
-                                                match &base_expr.kind {
-                                                    hir::body::ExprKind::Literal(
-                                                        hir::body::Literal::Int(val),
-                                                    ) => val.to_string(),
-                                                    _ => "?".to_string(),
-                                                }
-                                            } else {
-                                                "?".to_string()
-                                            };
-
-                                            let index_str = if let Some(index_expr) =
-                                                body.exprs.get(array_access.index.0 as usize)
-                                            {
-                                                match &index_expr.kind {
-                                                    hir::body::ExprKind::Literal(
-                                                        hir::body::Literal::Int(val),
-                                                    ) => val.to_string(),
-                                                    _ => "?".to_string(),
-                                                }
-                                            } else {
-                                                "?".to_string()
-                                            };
-
-                                            format!("{}[{}]", base_str, index_str)
-                                        }
-                                    }
-                                } else {
-                                    "?".to_string()
-                                }
-                            }
-                            None => "".to_string(),
-                        };
-
-                        if operand_str.is_empty() {
-                            i.opcode.to_string()
-                        } else {
-                            format!("{} {}", i.opcode, operand_str)
-                        }
-                    })
-                    .unwrap_or_else(|| format!("Instr {}", instr_id.0))
-            } else {
-                "Unknown".to_string()
-            };
+            let label = self.node_label(node_idx, Some(body));
 
             // Escape quotes for Mermaid
             let escaped_label = label.replace("\"", "\\\"");
@@ -479,11 +484,7 @@ impl ControlFlowGraph {
             let target_id = format!("N{}", target.index());
             let edge_kind = self.graph.edge_weight(edge).unwrap();
 
-            let edge_style = match edge_kind {
-                EdgeKind::Unconditional => "-->",
-                EdgeKind::ConditionalTrue => "-.->|true|",
-                EdgeKind::ConditionalFalse => "-.->|false|",
-            };
+            let edge_style = mermaid_edge(*edge_kind);
 
             result.push_str(&format!("    {} {} {}\n", source_id, edge_style, target_id));
         }
@@ -491,6 +492,335 @@ impl ControlFlowGraph {
         result
     }
 
+    /// Export the graph in the given format
+    ///
+    /// With [`ExportOptions::interprocedural`], the nodes are grouped by the
+    /// procedure they belong to. The instructions of the body of the context
+    /// label the nodes if it is given.
+    pub fn export(
+        &self,
+        format: ExportFormat,
+        options: &ExportOptions,
+        context: Option<&AnalysisContext>,
+    ) -> String {
+        let body = context.map(|context| context.body().as_ref());
+        match format {
+            ExportFormat::Dot => self.export_dot(options, body),
+            ExportFormat::Mermaid => self.export_mermaid(options, body),
+            ExportFormat::Json => self.export_json(options, body),
+        }
+    }
+
+    /// Get the label of a node, the text of its instruction if the body is given
+    pub(crate) fn node_label(&self, node_idx: NodeIndex, body: Option<&Body>) -> String {
+        let Some(instr_id) = self.graph[node_idx].instruction_id else {
+            return "Unknown".to_string();
+        };
+        body.and_then(|body| instruction_text(body, instr_id))
+            .unwrap_or_else(|| format!("Instr {}", instr_id.0))
+    }
+
+    /// Get the nodes and edges an export draws, after applying its filters
+    ///
+    /// Unconditional jumps are trivia only if the body is given, as the nodes
+    /// do not know their instructions otherwise. A collapsed basic block is
+    /// drawn as its first drawn node, labeled with a summary of its instructions.
+    fn export_view(&self, options: &ExportOptions, body: Option<&Body>) -> ExportView {
+        let mut drawn = match self.entry_node {
+            Some(entry) if options.reachable_only => {
+                let mut reachable = HashSet::new();
+                let mut dfs = Dfs::new(&self.graph, entry);
+                while let Some(node_idx) = dfs.next(&self.graph) {
+                    reachable.insert(node_idx);
+                }
+                reachable
+            }
+            _ => self.graph.node_indices().collect(),
+        };
+        let trivia = match body {
+            Some(body) if options.hide_trivia => self
+                .graph
+                .node_indices()
+                .filter(|&node_idx| {
+                    let id = self.graph[node_idx].instruction_id;
+                    body.instructions
+                        .values()
+                        .any(|instr| Some(instr.id) == id && instr.kind == InstructionKind::Jump)
+                })
+                .collect(),
+            _ => HashSet::new(),
+        };
+        drawn.retain(|node_idx| !trivia.contains(node_idx));
+
+        // Each drawn node stands for itself, or for its block if it is collapsed
+        let mut representative = HashMap::new();
+        let mut nodes = Vec::new();
+        if options.collapse_blocks {
+            for block in &self.basic_blocks {
+                let members =
+                    block.nodes.iter().copied().filter(|node_idx| drawn.contains(node_idx));
+                let members = members.collect::<Vec<_>>();
+                let Some(&first) = members.first() else {
+                    continue;
+                };
+                representative.extend(members.iter().map(|&node_idx| (node_idx, first)));
+                let labels = members.iter().map(|&node_idx| self.node_label(node_idx, body));
+                nodes.push((first, summarize_block(labels.collect())));
+            }
+        }
+        for node_idx in self.graph.node_indices().filter(|node_idx| drawn.contains(node_idx)) {
+            if !representative.contains_key(&node_idx) {
+                representative.insert(node_idx, node_idx);
+                nodes.push((node_idx, vec![self.node_label(node_idx, body)]));
+            }
+        }
+        nodes.sort_by_key(|&(node_idx, _)| node_idx);
+
+        let mut edges = Vec::new();
+        for edge in self.graph.edge_references() {
+            let Some(&source) = representative.get(&edge.source()) else {
+                continue;
+            };
+            // Go through the trivia to the node control ends up at
+            let mut target = edge.target();
+            let mut seen = HashSet::new();
+            while trivia.contains(&target) && seen.insert(target) {
+                match self.get_outgoing_edges(target).first() {
+                    Some(&(next, _)) => target = next,
+                    None => break,
+                }
+            }
+            let Some(&target_representative) = representative.get(&target) else {
+                continue;
+            };
+            // Edges inside a collapsed block are not drawn, but loops back to it are
+            let inside_block = source == target_representative && target != target_representative;
+            let edge = (source, target_representative, *edge.weight());
+            if !inside_block && !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+
+        ExportView { nodes, edges }
+    }
+
+    /// Group the drawn nodes by procedure, if the export is interprocedural
+    ///
+    /// Each node belongs to the first procedure that contains it, and the
+    /// nodes of no procedure, such as unreachable ones, are left out.
+    fn procedure_groups(
+        &self,
+        options: &ExportOptions,
+        view: &ExportView,
+    ) -> Vec<(String, Vec<NodeIndex>)> {
+        if !options.interprocedural {
+            return Vec::new();
+        }
+
+        let drawn = view.nodes.iter().map(|(node_idx, _)| *node_idx).collect::<HashSet<_>>();
+
+        let mut grouped = HashSet::new();
+        self.call_graph
+            .procedures()
+            .map(|procedure| {
+                let title = if self.call_graph.is_recursive(&procedure.name) {
+                    format!("{} (recursive)", procedure.name)
+                } else {
+                    procedure.name.clone()
+                };
+                let nodes = procedure
+                    .nodes
+                    .iter()
+                    .copied()
+                    .filter(|node_idx| drawn.contains(node_idx) && grouped.insert(*node_idx))
+                    .collect();
+                (title, nodes)
+            })
+            .collect()
+    }
+
+    /// Export the graph in the DOT format
+    fn export_dot(&self, options: &ExportOptions, body: Option<&Body>) -> String {
+        let mut result = String::from("digraph {\n");
+        for attribute in options.dot_attributes() {
+            result.push_str(&format!("    {};\n", attribute));
+        }
+
+        let view = self.export_view(options, body);
+        let labels = view.nodes.iter().map(|(node_idx, labels)| (*node_idx, labels.join("\n")));
+        let labels = labels.collect::<HashMap<_, _>>();
+        let node_line =
+            |node_idx: NodeIndex| format!("N{} [label={:?}]", node_idx.index(), labels[&node_idx]);
+
+        let groups = self.procedure_groups(options, &view);
+        let grouped = groups.iter().flat_map(|(_, nodes)| nodes).collect::<HashSet<_>>();
+        for (index, (title, nodes)) in groups.iter().enumerate() {
+            result.push_str(&format!("    subgraph cluster_{} {{\n", index));
+            result.push_str(&format!("        label={:?};\n", title));
+            for &node_idx in nodes {
+                result.push_str(&format!("        {};\n", node_line(node_idx)));
+            }
+            result.push_str("    }\n");
+        }
+        for (node_idx, _) in view.nodes.iter().filter(|(node_idx, _)| !grouped.contains(node_idx)) {
+            result.push_str(&format!("    {};\n", node_line(*node_idx)));
+        }
+
+        for &(source, target, kind) in &view.edges {
+            let mut attributes = Vec::new();
+            if options.include_edge_labels {
+                let label = match kind {
+                    EdgeKind::Unconditional => None,
+                    EdgeKind::ConditionalTrue => Some("true"),
+                    EdgeKind::ConditionalFalse => Some("false"),
+                    EdgeKind::Call => Some("call"),
+                    EdgeKind::Return => Some("return"),
+                };
+                attributes.extend(label.map(|label| format!("label={:?}", label)));
+            }
+            match kind {
+                EdgeKind::Call => attributes.push("style=bold".to_string()),
+                EdgeKind::Return => attributes.push("style=dashed".to_string()),
+                _ => {}
+            }
+            if let Some(color) = options.edge_colors.get(&kind) {
+                attributes.push(format!("color={:?}", color));
+            }
+
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attributes.join(", "))
+            };
+            result.push_str(&format!(
+                "    N{} -> N{}{};\n",
+                source.index(),
+                target.index(),
+                attributes
+            ));
+        }
+
+        result.push_str("}\n");
+        result
+    }
+
+    /// Export the graph in the Mermaid format
+    fn export_mermaid(&self, options: &ExportOptions, body: Option<&Body>) -> String {
+        let mut result = options.mermaid_header();
+        let view = self.export_view(options, body);
+        let labels = view.nodes.iter().map(|(node_idx, labels)| {
+            let label = labels.join("<br>").replace('"', "\\\"");
+            (*node_idx, label)
+        });
+        let labels = labels.collect::<HashMap<_, _>>();
+        let node_line =
+            |node_idx: NodeIndex| format!("N{}[\"{}\"]", node_idx.index(), labels[&node_idx]);
+
+        let groups = self.procedure_groups(options, &view);
+        let grouped = groups.iter().flat_map(|(_, nodes)| nodes).collect::<HashSet<_>>();
+        for (index, (title, nodes)) in groups.iter().enumerate() {
+            result.push_str(&format!("    subgraph P{}[\"{}\"]\n", index, title));
+            for &node_idx in nodes {
+                result.push_str(&format!("        {}\n", node_line(node_idx)));
+            }
+            result.push_str("    end\n");
+        }
+        for (node_idx, _) in view.nodes.iter().filter(|(node_idx, _)| !grouped.contains(node_idx)) {
+            result.push_str(&format!("    {}\n", node_line(*node_idx)));
+        }
+
+        for &(source, target, kind) in &view.edges {
+            result.push_str(&format!(
+                "    N{} {} N{}\n",
+                source.index(),
+                mermaid_edge(kind),
+                target.index()
+            ));
+        }
+        // Mermaid styles links by the order they were added in
+        for (index, &(_, _, kind)) in view.edges.iter().enumerate() {
+            if let Some(color) = options.edge_colors.get(&kind) {
+                result.push_str(&format!("    linkStyle {} stroke:{}\n", index, color));
+            }
+        }
+
+        result
+    }
+
+    /// Export the graph in the JSON format
+    fn export_json(&self, options: &ExportOptions, body: Option<&Body>) -> String {
+        let nodes = self
+            .graph
+            .node_indices()
+            .map(|node_idx| {
+                json!({
+                    "id": format!("N{}", node_idx.index()),
+                    "label": self.node_label(node_idx, body),
+                })
+            })
+            .collect::<Vec<_>>();
+        let edges = self
+            .graph
+            .edge_references()
+            .map(|edge| {
+                json!({
+                    "source": format!("N{}", edge.source().index()),
+                    "target": format!("N{}", edge.target().index()),
+                    "kind": format!("{:?}", edge.weight()),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut json = Map::new();
+        json.insert("nodes".to_string(), Value::Array(nodes));
+        json.insert("edges".to_string(), Value::Array(edges));
+        if options.collapse_blocks {
+            let blocks = self.block_graph();
+            let blocks = (0..blocks.block_count())
+                .map(|id| {
+                    let block = blocks.block(id);
+                    json!({
+                        "id": id,
+                        "label": self.block_label(block, body),
+                        "nodes": block
+                            .nodes
+                            .iter()
+                            .map(|node_idx| format!("N{}", node_idx.index()))
+                            .collect::<Vec<_>>(),
+                        "successors": blocks
+                            .successors(id)
+                            .into_iter()
+                            .map(|(target, kind)| json!({ "block": target, "kind": format!("{:?}", kind) }))
+                            .collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            json.insert("blocks".to_string(), Value::Array(blocks));
+        }
+        if options.interprocedural {
+            let procedures = self
+                .call_graph
+                .procedures()
+                .map(|procedure| {
+                    json!({
+                        "name": procedure.name,
+                        "entry": format!("N{}", procedure.entry.index()),
+                        "nodes": procedure
+                            .nodes
+                            .iter()
+                            .map(|node_idx| format!("N{}", node_idx.index()))
+                            .collect::<Vec<_>>(),
+                        "calls": self.call_graph.callees(&procedure.name),
+                        "recursive": self.call_graph.is_recursive(&procedure.name),
+                    })
+                })
+                .collect::<Vec<_>>();
+            json.insert("procedures".to_string(), Value::Array(procedures));
+        }
+
+        serde_json::to_string_pretty(&Value::Object(json)).unwrap_or_else(|_| "{}".to_string())
+    }
+
     /// Get the underlying petgraph directed graph
     pub fn graph(&self) -> &DiGraph<Node, EdgeKind> {
         &self.graph
@@ -544,3 +874,101 @@ fn is_cyclic_directed<N, E>(graph: &DiGraph<N, E>) -> bool {
     // If it fails, the graph has cycles
     toposort(graph, None).is_err()
 }
+
+/// Get the Mermaid arrow of an edge
+fn mermaid_edge(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::Unconditional => "-->",
+        EdgeKind::ConditionalTrue => "-.->|true|",
+        EdgeKind::ConditionalFalse => "-.->|false|",
+        EdgeKind::Call => "==>|call|",
+        EdgeKind::Return => "-.->|return|",
+    }
+}
+
+/// Get the text of an instruction of a body, such as `LOAD =1`
+pub(crate) fn instruction_text(body: &Body, instr_id: LocalDefId) -> Option<String> {
+    let instr = body.instructions.values().find(|i| i.id == instr_id)?;
+    let operand_str = match instr.operand {
+        Some(expr_id) => {
+            // Try to find the expression
+            if let Some(expr) = body.expr(expr_id) {
+                match &expr.kind {
+                    hir::body::ExprKind::Literal(lit) => match lit {
+                        hir::body::Literal::Int(val) => format!("{}", val),
+                        hir::body::Literal::String(s) => format!("\"{}\"", s),
+                        hir::body::Literal::Label(label) => {
+                            format!(":{}", label)
+                        }
+                    },
+                    hir::body::ExprKind::LabelRef(label_ref) => {
+                        // Find the label name from the label_id
+                        // We need to match on the local_id part of the DefId
+                        body.labels
+                            .values()
+                            .find(|l| l.id.0 == label_ref.label_id.local_id.0)
+                            .map(|l| format!(":{}", l.name))
+                            .unwrap_or_else(|| format!("label_{}", label_ref.label_id.local_id.0))
+                    }
+                    hir::body::ExprKind::MemoryRef(mem_ref) => {
+                        let mode_prefix = match mem_ref.mode {
+                            hir::body::AddressingMode::Direct => "",
+                            hir::body::AddressingMode::Indirect => "*",
+                            hir::body::AddressingMode::Immediate => "=",
+                        };
+
+                        if let Some(addr_expr) = body.expr(mem_ref.address) {
+                            if let hir::body::ExprKind::Literal(hir::body::Literal::Int(val)) =
+                                &addr_expr.kind
+                            {
+                                format!("{}{}", mode_prefix, val)
+                            } else {
+                                format!("{}?", mode_prefix)
+                            }
+                        } else {
+                            format!("{}?", mode_prefix)
+                        }
+                    }
+                    hir::body::ExprKind::InstructionCall(_) => "call".to_string(),
+                    hir::body::ExprKind::ArrayAccess(array_access) => {
+                        // Try to get the base and index expressions
+                        let base_str = if let Some(base_expr) = body.expr(array_access.array) {
+                            match &base_expr.kind {
+                                hir::body::ExprKind::Literal(hir::body::Literal::Int(val)) => {
+                                    val.to_string()
+                                }
+                                _ => "?".to_string(),
+                            }
+                        } else {
+                            "?".to_string()
+                        };
+
+                        let index_str = if let Some(index_expr) = body.expr(array_access.index) {
+                            match &index_expr.kind {
+                                hir::body::ExprKind::Literal(hir::body::Literal::Int(val)) => {
+                                    val.to_string()
+                                }
+                                _ => "?".to_string(),
+                            }
+                        } else {
+                            "?".to_string()
+                        };
+
+                        format!("{}[{}]", base_str, index_str)
+                    }
+                    // Constant expressions were folded during lowering
+                    hir::body::ExprKind::Binary(_) => "?".to_string(),
+                }
+            } else {
+                "?".to_string()
+            }
+        }
+        None => "".to_string(),
+    };
+
+    if operand_str.is_empty() {
+        Some(instr.opcode.to_string())
+    } else {
+        Some(format!("{} {}", instr.opcode, operand_str))
+    }
+}
@@ -2,7 +2,8 @@
 //!
 //! This module provides control flow analysis for HIR bodies.
 //! It builds a control flow graph (CFG) from a HIR body and provides
-//! methods for analyzing the control flow.
+//! methods for analyzing the control flow. Calls to subroutines link the
+//! graphs of the procedures of the program, which form its call graph.
 
 use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
@@ -10,18 +11,33 @@ use std::collections::{HashMap, HashSet};
 use hir::body::Body;
 use hir::ids::LocalDefId;
 use miette::Diagnostic;
+use petgraph::graph::NodeIndex;
+use ram_core::InstructionKind;
+use ram_core::effect::InstructionEffects;
+use ram_diagnostics::Applicability;
 
 use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
 use crate::pass::AnalysisPass;
 
+mod blocks;
+mod call_graph;
 mod graph;
 
+pub use blocks::{BLOCK_LABEL_INSTRUCTIONS, BlockGraph, BlockId, summarize_block};
+pub use call_graph::{CallGraph, ENTRY_PROCEDURE, Procedure};
+pub(crate) use graph::instruction_text;
 pub use graph::{BasicBlock, ControlFlowGraph, EdgeKind, Node};
 
+/// The diagnostic code reported when execution can fall off the end
+pub const FALLS_OFF_END_CODE: &str = ram_diagnostics::codes::FALLS_OFF_END.code;
+
 /// Control flow analysis pass
 ///
 /// This pass analyzes the control flow of a HIR body and builds a control flow graph.
-/// It also detects unreachable code and other control flow issues.
+/// It also detects execution falling off the end of the program and recursive
+/// subroutines. Unreachable code and infinite loops are reported by the
+/// [`DominanceAnalysis`](crate::analyzers::dominance::DominanceAnalysis).
 #[derive(Default)]
 pub struct ControlFlowAnalysis;
 
@@ -32,83 +48,60 @@ impl AnalysisPass for ControlFlowAnalysis {
         "ControlFlowAnalysis"
     }
 
+    fn id(&self) -> &'static str {
+        "cfg"
+    }
+
     fn dependencies(&self) -> Vec<TypeId> {
         vec![]
     }
 
+    fn inputs(&self) -> BodyParts {
+        BodyParts::CODE
+    }
+
     fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
         // Clone the body to avoid borrowing issues
         let body = ctx.body().clone();
-        let mut cfg_builder = ControlFlowGraphBuilder::new(&body);
+        let effects = body
+            .instructions
+            .values()
+            .map(|instr| (instr.id, ctx.instruction_effects(instr)))
+            .collect();
+        let mut cfg_builder = ControlFlowGraphBuilder::new(&body, effects);
         let cfg = cfg_builder.build();
 
-        // Check for unreachable code
         let unreachable_nodes = cfg.find_unreachable_nodes();
 
-        // Collect instruction indices for unreachable nodes
-        let mut unreachable_indices: Vec<usize> = unreachable_nodes
-            .iter()
-            .filter_map(|&node_idx| {
-                let node = cfg.get_node(node_idx);
-                // Map the instruction ID back to its index in the body instructions vector
-                // This assumes instructions are stored sequentially
-                node.instruction_id.and_then(|id| body.instructions.iter().position(|i| i.id == id))
-            })
-            .collect();
-
-        // Sort indices to group them by program order
-        unreachable_indices.sort_unstable();
-
-        // Group contiguous indices into ranges
-        let mut unreachable_ranges: Vec<(usize, usize)> = Vec::new();
-
-        for &idx in &unreachable_indices {
-            match unreachable_ranges.last_mut() {
-                // If the current index follows immediately after the end of the last range, extend it
-                Some((_, end)) if *end + 1 == idx => {
-                    *end = idx;
-                }
-                // Otherwise, start a new range
-                _ => {
-                    unreachable_ranges.push((idx, idx));
-                }
-            }
-        }
-
-        // Report one warning per contiguous block
-        for (start_idx, end_idx) in unreachable_ranges {
-            let start_instr = &body.instructions[start_idx];
-            let end_instr = &body.instructions[end_idx];
-
-            // Create a span that covers the entire block
-            let full_span = start_instr.span.start..end_instr.span.end;
-
-            ctx.warning(
-                "Unreachable code",
-                "This block of instructions will never be executed",
-                Some(full_span),
+        // Check for execution falling off the end of the program
+        if let Some(node_idx) = cfg.falls_off_end()
+            && !unreachable_nodes.contains(&node_idx)
+            && let Some(instr_id) = cfg.get_node(node_idx).instruction_id
+        {
+            let span = ctx.get_instruction_span(instr_id);
+            // The body keeps no comments, so a comment after the instruction
+            // would end up after the inserted `HALT`
+            ctx.add_diagnostic(
+                ram_diagnostics::Diagnostic::builder()
+                    .with_message("Execution can fall off the end of the program")
+                    .with_help("Add a `HALT` instruction to stop the program explicitly")
+                    .with_primary_span(span.clone(), "execution continues past this instruction")
+                    .with_code(FALLS_OFF_END_CODE)
+                    .with_suggestion(span.end..span.end, "\nHALT", Applicability::MaybeIncorrect)
+                    .build_warning(),
             );
         }
 
-        // Check for infinite loops
-        let infinite_loops = cfg.find_infinite_loops();
-        for loop_nodes in infinite_loops {
-            let loop_instrs: Vec<_> = loop_nodes
-                .iter()
-                .filter_map(|&node_id| {
-                    let node = cfg.get_node(node_id);
-                    node.instruction_id
-                })
-                .collect();
-
-            if !loop_instrs.is_empty() {
-                // Use the first instruction in the loop for the warning
-                let first_instr_id = loop_instrs[0];
-                ctx.warning_at_instruction(
-                    "Potential infinite loop detected".to_string(),
-                    "This loop may not terminate".to_string(),
-                    first_instr_id,
-                );
+        // Point out recursive subroutines, whose recursion must end
+        for group in cfg.call_graph().recursive_groups() {
+            for name in group {
+                if let Some(label) = body.labels.values().find(|label| label.name == name) {
+                    ctx.info_at_label(
+                        format!("Recursive subroutine '{}'", name),
+                        "The subroutine calls itself, directly or through other subroutines",
+                        label.id,
+                    );
+                }
             }
         }
 
@@ -126,26 +119,34 @@ struct ControlFlowGraphBuilder<'a> {
     instr_to_node: HashMap<LocalDefId, petgraph::graph::NodeIndex>,
     /// Map from label names to instruction IDs
     label_to_instr: HashMap<String, LocalDefId>,
+    /// The effects of the instructions, which tell calls and returns apart
+    effects: HashMap<LocalDefId, InstructionEffects>,
 }
 
 impl<'a> ControlFlowGraphBuilder<'a> {
     /// Create a new control flow graph builder
-    fn new(body: &'a Body) -> Self {
+    fn new(body: &'a Body, effects: HashMap<LocalDefId, InstructionEffects>) -> Self {
         let mut label_to_instr = HashMap::new();
 
         // Build a map from label names to instruction IDs
-        for label in &body.labels {
+        for label in body.labels.values() {
             if let Some(instr_id) = label.instruction_id {
                 label_to_instr.insert(label.name.clone(), instr_id);
             }
         }
 
-        Self { body, cfg: ControlFlowGraph::new(), instr_to_node: HashMap::new(), label_to_instr }
+        Self {
+            body,
+            cfg: ControlFlowGraph::new(),
+            instr_to_node: HashMap::new(),
+            label_to_instr,
+            effects,
+        }
     }
 
     /// Helper method to find a label by its DefId
     fn find_label_by_id(&self, label_id: hir::ids::DefId) -> Option<&hir::body::Label> {
-        self.body.labels.iter().find(|l| {
+        self.body.labels.values().find(|l| {
             // We need to compare the file_id and local_id parts of DefId
             // since we can't directly compare DefId with LocalDefId
             label_id.file_id == self.body.owner.file_id && label_id.local_id.0 == l.id.0
@@ -157,130 +158,181 @@ impl<'a> ControlFlowGraphBuilder<'a> {
         &mut self,
         node_id: petgraph::graph::NodeIndex,
         target_node_id: petgraph::graph::NodeIndex,
-        opcode: &str,
+        kind: &InstructionKind,
         instr_index: usize,
     ) {
         // Add a jump edge
-        let edge_kind = match opcode.to_uppercase().as_str() {
-            "JUMP" | "JMP" => EdgeKind::Unconditional,
-            "JGTZ" => EdgeKind::ConditionalTrue,
-            "JZERO" => EdgeKind::ConditionalTrue,
-            _ => EdgeKind::Unconditional,
+        let edge_kind = if kind.is_conditional_jump() {
+            EdgeKind::ConditionalTrue
+        } else {
+            EdgeKind::Unconditional
         };
 
         self.cfg.add_edge(node_id, target_node_id, edge_kind);
 
         // For conditional jumps, also add a fallthrough edge
-        if (opcode.to_uppercase() == "JGTZ" || opcode.to_uppercase() == "JZERO")
-            && instr_index + 1 < self.body.instructions.len()
+        if kind.is_conditional_jump()
+            && let Some(next_instr) = self.body.instruction(instr_index + 1)
         {
-            let next_instr_id = self.body.instructions[instr_index + 1].id;
-            let next_node_id = self.instr_to_node[&next_instr_id];
+            let next_node_id = self.instr_to_node[&next_instr.id];
             self.cfg.add_edge(node_id, next_node_id, EdgeKind::ConditionalFalse);
         }
     }
 
+    /// Find the node of the instruction at the label an instruction refers to
+    ///
+    /// Returns `None` if the operand is not a label, or the label is not
+    /// defined in the body, as the target can not be determined statically.
+    fn label_target(&self, instr: &hir::body::Instruction) -> Option<NodeIndex> {
+        let operand_id = instr.operand?;
+        let expr = self.body.expr(operand_id)?;
+        let label_name = match &expr.kind {
+            // Handle literal label references (string literals representing labels)
+            hir::body::ExprKind::Literal(hir::body::Literal::Label(label_name)) => label_name,
+            // Handle references to labels resolved during lowering
+            hir::body::ExprKind::LabelRef(label_ref) => {
+                &self.find_label_by_id(label_ref.label_id)?.name
+            }
+            // Non-label operand, can't determine jump target statically
+            _ => return None,
+        };
+        let target_instr_id = self.label_to_instr.get(label_name)?;
+        self.instr_to_node.get(target_instr_id).copied()
+    }
+
+    /// Get the node of the instruction after the one at `instr_index`
+    fn next_node(&self, instr_index: usize) -> Option<NodeIndex> {
+        let next_instr = self.body.instruction(instr_index + 1)?;
+        Some(self.instr_to_node[&next_instr.id])
+    }
+
     /// Build the control flow graph
     fn build(&mut self) -> ControlFlowGraph {
         // Create nodes for all instructions
-        for instr in &self.body.instructions {
+        for instr in self.body.instructions.values() {
             let node_id = self.cfg.add_node(Node::new(Some(instr.id)));
             self.instr_to_node.insert(instr.id, node_id);
         }
 
+        // Returns get their edges once the subroutines are known
+        let mut returns = HashSet::new();
+
         // Create edges between nodes
-        for (i, instr) in self.body.instructions.iter().enumerate() {
+        for (i, instr) in self.body.instructions.values().enumerate() {
             let node_id = self.instr_to_node[&instr.id];
+            let effects = self.effects.get(&instr.id);
 
             // Check if this is a jump instruction
-            let is_jump =
-                matches!(instr.opcode.to_uppercase().as_str(), "JUMP" | "JMP" | "JGTZ" | "JZERO");
+            let is_jump = instr.kind.is_jump();
 
             // Check if this is a halt instruction
-            let is_halt = instr.opcode.to_uppercase() == "HALT";
+            let is_halt = instr.kind == InstructionKind::Halt;
 
             if is_jump {
                 // Add a conditional edge to the jump target
-                if let Some(operand_id) = instr.operand
-                    && let Some(expr) = self.body.exprs.get(operand_id.0 as usize)
-                {
-                    match &expr.kind {
-                        // Handle literal label references (string literals representing labels)
-                        hir::body::ExprKind::Literal(hir::body::Literal::Label(label_name)) => {
-                            if let Some(&target_instr_id) = self.label_to_instr.get(label_name)
-                                && let Some(&target_node_id) =
-                                    self.instr_to_node.get(&target_instr_id)
-                            {
-                                // Add appropriate edges for this jump instruction
-                                self.add_jump_edges(node_id, target_node_id, &instr.opcode, i);
-                            }
-                        }
-                        // Handle LabelRef type - this is what we were missing
-                        hir::body::ExprKind::LabelRef(label_ref) => {
-                            // Find the label by its ID
-                            if let Some(label) = self.find_label_by_id(label_ref.label_id)
-                                && let Some(&target_instr_id) = self.label_to_instr.get(&label.name)
-                                && let Some(&target_node_id) =
-                                    self.instr_to_node.get(&target_instr_id)
-                            {
-                                // Add appropriate edges for this jump instruction
-                                self.add_jump_edges(node_id, target_node_id, &instr.opcode, i);
-                            }
-                        }
-                        _ => {
-                            // Non-label operand, can't determine jump target statically
-                        }
-                    }
+                if let Some(target_node_id) = self.label_target(instr) {
+                    // Add appropriate edges for this jump instruction
+                    self.add_jump_edges(node_id, target_node_id, &instr.kind, i);
                 }
+            } else if effects.is_some_and(InstructionEffects::calls) {
+                // The subroutine continues at the next instruction when it returns
+                if let Some(target_node_id) = self.label_target(instr) {
+                    self.cfg.add_edge(node_id, target_node_id, EdgeKind::Call);
+                    self.cfg.add_call_site(node_id, self.next_node(i));
+                }
+            } else if effects.is_some_and(InstructionEffects::returns) {
+                returns.insert(node_id);
             } else if !is_halt {
                 // Add a fallthrough edge to the next instruction
-                if i + 1 < self.body.instructions.len() {
-                    let next_instr_id = self.body.instructions[i + 1].id;
-                    let next_node_id = self.instr_to_node[&next_instr_id];
+                if let Some(next_node_id) = self.next_node(i) {
                     self.cfg.add_edge(node_id, next_node_id, EdgeKind::Unconditional);
                 }
             }
+
+            // Execution continues past the last instruction unless it stops or
+            // always jumps away, including after a call returns
+            let continues = !is_halt
+                && !effects.is_some_and(InstructionEffects::returns)
+                && !(is_jump && !instr.kind.is_conditional_jump());
+            if continues && self.next_node(i).is_none() {
+                self.cfg.set_falls_off_end(node_id);
+            }
         }
 
+        self.add_return_edges(&returns);
+
+        // Group the instructions into procedures, named by their labels
+        let call_graph = CallGraph::build(&self.cfg, |node_idx| {
+            let instr_id = self.cfg.get_node(node_idx).instruction_id?;
+            self.body.instructions.values().find(|instr| instr.id == instr_id)?.label_name.clone()
+        });
+        self.cfg.set_call_graph(call_graph);
+
         // Identify basic blocks
         self.identify_basic_blocks();
 
         self.cfg.clone()
     }
 
+    /// Add edges from the returns of each called subroutine to its return sites
+    ///
+    /// The returns of a subroutine are those of its procedure, reachable from
+    /// its entry without returning first.
+    fn add_return_edges(&mut self, returns: &HashSet<NodeIndex>) {
+        let mut edges = Vec::new();
+        for (&call, &return_site) in self.cfg.call_sites() {
+            let (Some(entry), Some(return_site)) = (self.cfg.call_target(call), return_site) else {
+                continue;
+            };
+            let procedure_returns =
+                self.cfg.procedure_nodes(entry).into_iter().filter(|node| returns.contains(node));
+            edges.extend(procedure_returns.map(|return_id| (return_id, return_site)));
+        }
+
+        edges.sort_unstable();
+        edges.dedup();
+        for (return_id, return_site) in edges {
+            self.cfg.add_edge(return_id, return_site, EdgeKind::Return);
+        }
+    }
+
     /// Identify basic blocks in the control flow graph
     fn identify_basic_blocks(&mut self) {
         let mut current_block = Vec::new();
         let mut leaders: HashSet<LocalDefId> = HashSet::new();
 
         // The first instruction is always a leader
-        if let Some(first_instr) = self.body.instructions.first() {
+        if let Some(first_instr) = self.body.instructions.values().next() {
             leaders.insert(first_instr.id);
         }
 
-        // Instructions that are targets of jumps are leaders
-        for instr in &self.body.instructions {
+        let instructions = self.body.instructions.values().collect::<Vec<_>>();
+        for (i, instr) in instructions.iter().enumerate() {
             let node_id = self.instr_to_node[&instr.id];
+            let previous = i.checked_sub(1).map(|i| self.instr_to_node[&instructions[i].id]);
+            let next = instructions.get(i + 1).map(|next| self.instr_to_node[&next.id]);
 
-            // If this node has incoming edges, it's a leader
-            if !self.cfg.get_incoming_edges(node_id).is_empty() {
+            // Instructions reached other than by falling through are leaders
+            if self
+                .cfg
+                .get_incoming_edges(node_id)
+                .iter()
+                .any(|&(source, _)| Some(source) != previous)
+            {
                 leaders.insert(instr.id);
             }
 
-            // If this node has multiple outgoing edges, the next instruction is a leader
-            if self.cfg.get_outgoing_edges(node_id).len() > 1 {
-                // Find the next instruction
-                for (i, instr2) in self.body.instructions.iter().enumerate() {
-                    if instr2.id == instr.id && i + 1 < self.body.instructions.len() {
-                        leaders.insert(self.body.instructions[i + 1].id);
-                        break;
-                    }
-                }
+            // Unless an instruction only falls through, the next one is a leader
+            let outgoing = self.cfg.get_outgoing_edges(node_id);
+            if let Some(next) = next
+                && !matches!(outgoing[..], [(target, _)] if target == next)
+            {
+                leaders.insert(instructions[i + 1].id);
             }
         }
 
         // Create basic blocks
-        for instr in &self.body.instructions {
+        for instr in self.body.instructions.values() {
             let node_id = self.instr_to_node[&instr.id];
 
             if leaders.contains(&instr.id) {
@@ -3,6 +3,9 @@
 //! This module provides control flow optimization for HIR bodies.
 //! It uses the results of constant propagation analysis to optimize
 //! the control flow graph by removing branches that will never be taken.
+//! The optimized graph is then looked at block by block, to find the basic
+//! blocks the removed branches cut off and those left to run one after the
+//! other.
 
 use std::any::TypeId;
 use std::collections::HashMap;
@@ -16,8 +19,11 @@ use petgraph::visit::EdgeRef;
 use crate::analyzers::constant_propagation::{
     BranchTaken, ConstantPropagationAnalysis, ConstantPropagationResult,
 };
-use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph, EdgeKind};
+use crate::analyzers::control_flow::{
+    BlockGraph, BlockId, ControlFlowAnalysis, ControlFlowGraph, EdgeKind,
+};
 use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
 use crate::pass::AnalysisPass;
 
 /// Control flow optimizer pass
@@ -34,10 +40,19 @@ impl AnalysisPass for ControlFlowOptimizer {
         "ControlFlowOptimizer"
     }
 
+    fn id(&self) -> &'static str {
+        "cfg-opt"
+    }
+
     fn dependencies(&self) -> Vec<TypeId> {
         vec![TypeId::of::<ControlFlowAnalysis>(), TypeId::of::<ConstantPropagationAnalysis>()]
     }
 
+    fn inputs(&self) -> BodyParts {
+        // Everything is read from the results of the dependencies
+        BodyParts::NONE
+    }
+
     fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
         // Get the control flow graph
         let cfg = match ctx.get_result::<ControlFlowAnalysis>() {
@@ -58,12 +73,20 @@ impl AnalysisPass for ControlFlowOptimizer {
         let optimizer = ControlFlowGraphOptimizer::new(ctx.body(), &const_prop);
         optimizer.optimize(&mut cfg_copy);
 
+        // Reason about the optimized CFG at the granularity of its blocks
+        let blocks = cfg_copy.block_graph();
+        let unreachable_blocks = blocks.unreachable_blocks();
+        let mergeable_blocks = mergeable_blocks(&blocks, &unreachable_blocks);
+
         // Wrap the optimized CFG in Arc<Mutex<>> for thread-safe access
         let optimized_cfg = Arc::new(Mutex::new(cfg_copy));
 
         Ok(OptimizedControlFlowGraph {
             cfg: optimized_cfg,
             optimized_edges: const_prop.optimized_edges.clone(),
+            blocks,
+            unreachable_blocks,
+            mergeable_blocks,
         })
     }
 }
@@ -75,6 +98,32 @@ pub struct OptimizedControlFlowGraph {
     pub cfg: Arc<Mutex<ControlFlowGraph>>,
     /// Map from instruction IDs to branch taken information for conditional jumps
     pub optimized_edges: HashMap<LocalDefId, BranchTaken>,
+    /// The basic blocks of the optimized control flow graph
+    pub blocks: BlockGraph,
+    /// The blocks the optimized control flow graph can never reach
+    pub unreachable_blocks: Vec<BlockId>,
+    /// The pairs of reachable blocks that always run one right after the other
+    ///
+    /// The first block of a pair only goes to the second, and the second is
+    /// only reached from the first, so they could be merged into one block.
+    pub mergeable_blocks: Vec<(BlockId, BlockId)>,
+}
+
+/// Find the pairs of reachable blocks that always run one after the other
+///
+/// Calls and returns cross procedures, so the blocks they link are never merged.
+fn mergeable_blocks(blocks: &BlockGraph, unreachable: &[BlockId]) -> Vec<(BlockId, BlockId)> {
+    (0..blocks.block_count())
+        .filter(|id| !unreachable.contains(id))
+        .filter_map(|id| {
+            let [(next, kind)] = blocks.successors(id)[..] else {
+                return None;
+            };
+            let crosses_procedures = matches!(kind, EdgeKind::Call | EdgeKind::Return);
+            let merges = next != id && !crosses_procedures && blocks.predecessors(next).len() == 1;
+            merges.then_some((id, next))
+        })
+        .collect()
 }
 
 /// Optimizer for control flow graphs
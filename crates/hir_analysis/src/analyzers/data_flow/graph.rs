@@ -6,12 +6,16 @@
 
 use std::collections::{HashMap, HashSet};
 
+use hir::body::Body;
 use hir::ids::LocalDefId;
 use petgraph::algo::toposort;
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::{DiGraph, EdgeIndex, NodeIndex};
 use petgraph::visit::EdgeRef;
 
+use crate::analyzers::control_flow::instruction_text;
+use crate::context::AnalysisContext;
+
 /// The value flowing through a data flow edge
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataFlowValue {
@@ -103,8 +107,9 @@ impl DataFlowGraph {
 
     /// Find memory addresses that are read before being written
     ///
-    /// Returns a set of (address, instruction_id) pairs
-    pub fn find_uninitialized_reads(&self) -> HashSet<(i64, LocalDefId)> {
+    /// Returns the (address, instruction_id) pairs, ordered by instruction
+    /// and then by address
+    pub fn find_uninitialized_reads(&self) -> Vec<(i64, LocalDefId)> {
         let mut uninitialized = HashSet::new();
         let mut initialized = HashSet::new();
 
@@ -139,13 +144,14 @@ impl DataFlowGraph {
             }
         }
 
-        uninitialized
+        sorted_accesses(uninitialized)
     }
 
     /// Find memory addresses that are written but never read
     ///
-    /// Returns a set of (address, instruction_id) pairs
-    pub fn find_unused_writes(&self) -> HashSet<(i64, LocalDefId)> {
+    /// Returns the (address, instruction_id) pairs, ordered by instruction
+    /// and then by address
+    pub fn find_unused_writes(&self) -> Vec<(i64, LocalDefId)> {
         let mut written_addrs = HashSet::new();
         let mut read_addrs = HashSet::new();
         let mut written_with_instr = HashMap::new();
@@ -170,10 +176,11 @@ impl DataFlowGraph {
         }
 
         // Find addresses that are written to but never read from
-        written_addrs
-            .difference(&read_addrs)
-            .filter_map(|addr| written_with_instr.get(addr).map(|instr_id| (*addr, *instr_id)))
-            .collect()
+        sorted_accesses(
+            written_addrs
+                .difference(&read_addrs)
+                .filter_map(|addr| written_with_instr.get(addr).map(|instr_id| (*addr, *instr_id))),
+        )
     }
 
     /// Perform a topological sort of the nodes
@@ -186,6 +193,40 @@ impl DataFlowGraph {
         format!("{:?}", Dot::with_config(&self.graph, &[Config::EdgeNoLabel]))
     }
 
+    /// Get a Mermaid representation of the graph with detailed instruction information
+    ///
+    /// The nodes are labeled with the text of their instructions, like those
+    /// of the control flow graph, and the edges with the value they carry.
+    pub fn to_mermaid_with_context(&self, context: &AnalysisContext) -> String {
+        let mut result = String::from("graph TD\n");
+        let body: &Body = context.body();
+
+        for node_idx in self.graph.node_indices() {
+            let instr_id = self.graph[node_idx].instruction_id;
+            let label =
+                instruction_text(body, instr_id).unwrap_or_else(|| format!("Instr {}", instr_id.0));
+
+            // Escape quotes for Mermaid
+            let escaped_label = label.replace("\"", "\\\"");
+            result.push_str(&format!("    N{}[\"{}\"]\n", node_idx.index(), escaped_label));
+        }
+
+        // Edges are added in no particular order, so sort them for a stable diagram
+        let mut edges = self
+            .graph
+            .edge_references()
+            .map(|edge| {
+                (edge.source().index(), edge.target().index(), mermaid_value(*edge.weight()))
+            })
+            .collect::<Vec<_>>();
+        edges.sort();
+        for (source, target, value) in edges {
+            result.push_str(&format!("    N{} -->|\"{}\"| N{}\n", source, value, target));
+        }
+
+        result
+    }
+
     /// Get the underlying petgraph directed graph
     pub fn graph(&self) -> &DiGraph<DataFlowNode, DataFlowValue> {
         &self.graph
@@ -222,3 +263,22 @@ impl Default for DataFlowGraph {
         Self::new()
     }
 }
+
+/// Get the label of an edge carrying a value, such as `M[5]`
+fn mermaid_value(value: DataFlowValue) -> String {
+    match value {
+        DataFlowValue::Memory(address) => format!("M[{}]", address),
+        DataFlowValue::Accumulator => "ACC".to_string(),
+    }
+}
+
+/// Order memory accesses by instruction and then by address, so the
+/// diagnostics reported from them do not depend on the order of a hash set
+fn sorted_accesses(
+    accesses: impl IntoIterator<Item = (i64, LocalDefId)>,
+) -> Vec<(i64, LocalDefId)> {
+    let mut accesses = accesses.into_iter().collect::<Vec<_>>();
+    accesses.sort_by_key(|&(address, instr_id)| (instr_id.0, address));
+    accesses.dedup();
+    accesses
+}
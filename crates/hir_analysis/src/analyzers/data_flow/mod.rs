@@ -2,18 +2,21 @@
 //!
 //! This module provides data flow analysis for HIR bodies.
 //! It analyzes how data flows through the program and detects issues
-//! such as uninitialized variables and unused values.
+//! such as uninitialized variables and unused values. The memory accesses of
+//! instructions are derived from their declared effects.
 
 use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
 
-use hir::body::{Body, ExprKind, Instruction, Literal};
+use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
 use hir::expr::ExprId;
 use hir::ids::LocalDefId;
 use miette::Diagnostic;
+use ram_core::InstructionEffects;
 
 use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph};
 use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
 use crate::pass::AnalysisPass;
 
 mod graph;
@@ -34,10 +37,19 @@ impl AnalysisPass for DataFlowAnalysis {
         "DataFlowAnalysis"
     }
 
+    fn id(&self) -> &'static str {
+        "dataflow"
+    }
+
     fn dependencies(&self) -> Vec<TypeId> {
         vec![TypeId::of::<ControlFlowAnalysis>()]
     }
 
+    fn inputs(&self) -> BodyParts {
+        // Jumps are read from the control flow graph
+        BodyParts::INSTRUCTIONS.union(BodyParts::EXPRS)
+    }
+
     fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
         let body = ctx.body();
 
@@ -47,7 +59,13 @@ impl AnalysisPass for DataFlowAnalysis {
             Err(e) => return Err(Box::new(e)),
         };
 
-        let mut dfg_builder = DataFlowGraphBuilder::new(body, &cfg);
+        let effects = body
+            .instructions
+            .values()
+            .map(|instr| (instr.id, ctx.instruction_effects(instr)))
+            .collect::<HashMap<_, _>>();
+
+        let mut dfg_builder = DataFlowGraphBuilder::new(body, &cfg, &effects);
         let dfg = dfg_builder.build();
 
         // Check for uninitialized variables
@@ -80,6 +98,8 @@ struct DataFlowGraphBuilder<'a> {
     body: &'a Body,
     /// The control flow graph
     cfg: &'a ControlFlowGraph,
+    /// Map from instruction IDs to their effects
+    effects: &'a HashMap<LocalDefId, InstructionEffects>,
     /// The data flow graph being built
     dfg: DataFlowGraph,
     /// Map from instruction IDs to data flow node indices
@@ -92,10 +112,15 @@ struct DataFlowGraphBuilder<'a> {
 
 impl<'a> DataFlowGraphBuilder<'a> {
     /// Create a new data flow graph builder
-    fn new(body: &'a Body, cfg: &'a ControlFlowGraph) -> Self {
+    fn new(
+        body: &'a Body,
+        cfg: &'a ControlFlowGraph,
+        effects: &'a HashMap<LocalDefId, InstructionEffects>,
+    ) -> Self {
         Self {
             body,
             cfg,
+            effects,
             dfg: DataFlowGraph::new(),
             instr_to_node: HashMap::new(),
             written_addrs: HashSet::new(),
@@ -106,13 +131,13 @@ impl<'a> DataFlowGraphBuilder<'a> {
     /// Build the data flow graph
     fn build(&mut self) -> DataFlowGraph {
         // Create nodes for all instructions
-        for instr in &self.body.instructions {
+        for instr in self.body.instructions.values() {
             let node_id = self.dfg.add_node(DataFlowNode::new(instr.id));
             self.instr_to_node.insert(instr.id, node_id);
         }
 
         // Analyze each instruction to determine data flow
-        for instr in &self.body.instructions {
+        for instr in self.body.instructions.values() {
             self.analyze_instruction(instr);
         }
 
@@ -124,62 +149,48 @@ impl<'a> DataFlowGraphBuilder<'a> {
 
     /// Analyze an instruction to determine data flow
     fn analyze_instruction(&mut self, instr: &Instruction) {
-        match instr.opcode.to_uppercase().as_str() {
-            "LOAD" => {
-                // LOAD reads from memory and writes to the accumulator
-                if let Some(operand_id) = instr.operand
-                    && let Some(addr) = self.get_memory_address(operand_id)
-                {
-                    self.read_addrs.insert(addr);
-                }
-            }
-            "STORE" => {
-                // STORE reads from the accumulator and writes to memory
-                if let Some(operand_id) = instr.operand
-                    && let Some(addr) = self.get_memory_address(operand_id)
-                {
-                    self.written_addrs.insert(addr);
-                }
-            }
-            "ADD" | "SUB" | "MUL" | "DIV" => {
-                // Arithmetic operations read from memory and write to the accumulator
-                if let Some(operand_id) = instr.operand
-                    && let Some(addr) = self.get_memory_address(operand_id)
-                {
-                    self.read_addrs.insert(addr);
-                }
-            }
-            "READ" => {
-                // READ writes to memory
-                if let Some(operand_id) = instr.operand
-                    && let Some(addr) = self.get_memory_address(operand_id)
-                {
-                    self.written_addrs.insert(addr);
-                }
-            }
-            "WRITE" => {
-                // WRITE reads from memory
-                if let Some(operand_id) = instr.operand
-                    && let Some(addr) = self.get_memory_address(operand_id)
-                {
-                    self.read_addrs.insert(addr);
-                }
-            }
-            _ => {
-                // Other instructions may have different data flow patterns
+        let (read, written) = self.memory_accesses(instr);
+        self.read_addrs.extend(read);
+        self.written_addrs.extend(written);
+    }
+
+    /// Get the memory addresses an instruction reads and writes
+    ///
+    /// Only accesses to statically known addresses are returned. An indirect
+    /// operand reads the register holding the pointer, whatever the instruction
+    /// does with its target. The targets themselves are bounded by the
+    /// [`AliasAnalysis`](crate::analyzers::alias::AliasAnalysis).
+    fn memory_accesses(&self, instr: &Instruction) -> (Option<i64>, Option<i64>) {
+        let Some((mode, address)) =
+            instr.operand.and_then(|operand_id| self.get_memory_address(operand_id))
+        else {
+            return (None, None);
+        };
+
+        let effects = &self.effects[&instr.id];
+        match mode {
+            AddressingMode::Direct => {
+                let read = effects.reads_operand().then_some(address);
+                let written = effects.writes_operand().then_some(address);
+                (read, written)
             }
+            AddressingMode::Indirect => (Some(address), None),
+            AddressingMode::Immediate => (None, None),
         }
     }
 
-    /// Get the memory address from an expression ID
-    fn get_memory_address(&self, expr_id: ExprId) -> Option<i64> {
-        if let Some(expr) = self.body.exprs.get(expr_id.0 as usize) {
+    /// Get the addressing mode and memory address from an expression ID
+    ///
+    /// Immediate operands are values rather than addresses, so they have none.
+    fn get_memory_address(&self, expr_id: ExprId) -> Option<(AddressingMode, i64)> {
+        if let Some(expr) = self.body.expr(expr_id) {
             match &expr.kind {
-                ExprKind::Literal(Literal::Int(addr)) => Some(*addr),
                 ExprKind::MemoryRef(mem_ref) => {
-                    if let Some(addr_expr) = self.body.exprs.get(mem_ref.address.0 as usize) {
+                    if let Some(addr_expr) = self.body.expr(mem_ref.address) {
                         match &addr_expr.kind {
-                            ExprKind::Literal(Literal::Int(addr)) => Some(*addr),
+                            ExprKind::Literal(Literal::Int(addr)) => {
+                                Some((mem_ref.mode.clone(), *addr))
+                            }
                             _ => None,
                         }
                     } else {
@@ -202,51 +213,13 @@ impl<'a> DataFlowGraphBuilder<'a> {
         let mut addr_to_readers: HashMap<i64, Vec<LocalDefId>> = HashMap::new();
 
         // Analyze each instruction to determine data flow
-        for instr in &self.body.instructions {
-            match instr.opcode.to_uppercase().as_str() {
-                "LOAD" => {
-                    // LOAD reads from memory
-                    if let Some(operand_id) = instr.operand
-                        && let Some(addr) = self.get_memory_address(operand_id)
-                    {
-                        addr_to_readers.entry(addr).or_default().push(instr.id);
-                    }
-                }
-                "STORE" => {
-                    // STORE writes to memory
-                    if let Some(operand_id) = instr.operand
-                        && let Some(addr) = self.get_memory_address(operand_id)
-                    {
-                        addr_to_writers.entry(addr).or_default().push(instr.id);
-                    }
-                }
-                "ADD" | "SUB" | "MUL" | "DIV" => {
-                    // Arithmetic operations read from memory
-                    if let Some(operand_id) = instr.operand
-                        && let Some(addr) = self.get_memory_address(operand_id)
-                    {
-                        addr_to_readers.entry(addr).or_default().push(instr.id);
-                    }
-                }
-                "READ" => {
-                    // READ writes to memory
-                    if let Some(operand_id) = instr.operand
-                        && let Some(addr) = self.get_memory_address(operand_id)
-                    {
-                        addr_to_writers.entry(addr).or_default().push(instr.id);
-                    }
-                }
-                "WRITE" => {
-                    // WRITE reads from memory
-                    if let Some(operand_id) = instr.operand
-                        && let Some(addr) = self.get_memory_address(operand_id)
-                    {
-                        addr_to_readers.entry(addr).or_default().push(instr.id);
-                    }
-                }
-                _ => {
-                    // Other instructions may have different data flow patterns
-                }
+        for instr in self.body.instructions.values() {
+            let (read, written) = self.memory_accesses(instr);
+            if let Some(addr) = read {
+                addr_to_readers.entry(addr).or_default().push(instr.id);
+            }
+            if let Some(addr) = written {
+                addr_to_writers.entry(addr).or_default().push(instr.id);
             }
         }
 
@@ -0,0 +1,330 @@
+//! Dominance analysis for HIR
+//!
+//! This module provides dominance analysis for HIR bodies. It computes the
+//! dominator and post-dominator trees of the control flow graph, and finds
+//! the natural loops of the program from them. The trees tell which code can
+//! never run, and the loops which code can never stop running.
+
+use std::any::TypeId;
+use std::collections::HashSet;
+
+use hir::body::Body;
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+use petgraph::graph::NodeIndex;
+use ram_core::JumpCondition;
+
+use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph};
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+mod tree;
+
+/// The diagnostic code reported for code right after an unconditional jump
+/// or `HALT`
+pub const DEAD_CODE_CODE: &str = ram_diagnostics::codes::DEAD_CODE.code;
+
+pub use tree::DominatorTree;
+
+/// Dominance analysis pass
+///
+/// This pass computes the dominator and post-dominator trees of the control
+/// flow graph and its natural loops. It reports code that is unreachable from
+/// the entry of the program and loops that can never be left.
+#[derive(Default)]
+pub struct DominanceAnalysis;
+
+impl AnalysisPass for DominanceAnalysis {
+    type Output = DominanceInfo;
+
+    fn name(&self) -> &'static str {
+        "DominanceAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "dominance"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<ControlFlowAnalysis>()]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        // Jumps are read from the control flow graph
+        BodyParts::INSTRUCTIONS.union(BodyParts::LABELS)
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        // Get the control flow graph
+        let cfg = match ctx.get_result::<ControlFlowAnalysis>() {
+            Ok(cfg) => cfg.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let body = ctx.body().clone();
+        let info = DominanceInfo::new(&cfg);
+
+        report_unreachable_code(ctx, &body, &cfg, &info);
+        report_infinite_loops(ctx, &cfg, &info);
+
+        Ok(info)
+    }
+}
+
+/// The result of dominance analysis
+#[derive(Debug, Clone, Default)]
+pub struct DominanceInfo {
+    /// The dominator tree, rooted at the entry of the program
+    pub dominators: DominatorTree,
+    /// The post-dominator tree, rooted at the ends of the program
+    pub post_dominators: DominatorTree,
+    /// The natural loops, ordered by their headers
+    pub loops: Vec<NaturalLoop>,
+}
+
+impl DominanceInfo {
+    /// Compute the dominance information of a control flow graph
+    pub fn new(cfg: &ControlFlowGraph) -> Self {
+        let dominators = DominatorTree::dominators_of(cfg);
+        let post_dominators = DominatorTree::post_dominators_of(cfg);
+        let loops = NaturalLoop::find_all(cfg, &dominators);
+        Self { dominators, post_dominators, loops }
+    }
+
+    /// Get the immediate dominator of a node
+    pub fn immediate_dominator(&self, node_idx: NodeIndex) -> Option<NodeIndex> {
+        self.dominators.immediate_dominator(node_idx)
+    }
+
+    /// Get the immediate post-dominator of a node
+    pub fn immediate_post_dominator(&self, node_idx: NodeIndex) -> Option<NodeIndex> {
+        self.post_dominators.immediate_dominator(node_idx)
+    }
+
+    /// Check whether every path from the entry to `node_idx` goes through `dominator`
+    pub fn dominates(&self, dominator: NodeIndex, node_idx: NodeIndex) -> bool {
+        self.dominators.dominates(dominator, node_idx)
+    }
+
+    /// Check whether every path from `node_idx` to an end of the program goes
+    /// through `post_dominator`
+    pub fn post_dominates(&self, post_dominator: NodeIndex, node_idx: NodeIndex) -> bool {
+        self.post_dominators.dominates(post_dominator, node_idx)
+    }
+
+    /// Get the dominance frontier of a node
+    pub fn dominance_frontier(&self, node_idx: NodeIndex) -> HashSet<NodeIndex> {
+        self.dominators.dominance_frontier(node_idx)
+    }
+
+    /// Check whether a node can be reached from the entry of the program
+    pub fn is_reachable(&self, node_idx: NodeIndex) -> bool {
+        self.dominators.contains(node_idx)
+    }
+
+    /// Get the innermost loop containing a node, if any
+    pub fn innermost_loop(&self, node_idx: NodeIndex) -> Option<&NaturalLoop> {
+        self.loops
+            .iter()
+            .filter(|natural_loop| natural_loop.nodes.contains(&node_idx))
+            .min_by_key(|natural_loop| natural_loop.nodes.len())
+    }
+}
+
+/// A natural loop of a control flow graph
+///
+/// A natural loop is entered through its header, which dominates every node
+/// of the loop, and jumps back to the header from its latches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalLoop {
+    /// The node every iteration of the loop starts at
+    pub header: NodeIndex,
+    /// The nodes that jump back to the header, in order
+    pub latches: Vec<NodeIndex>,
+    /// The nodes of the loop, including the header and the latches
+    pub nodes: HashSet<NodeIndex>,
+    /// The nodes outside the loop that its nodes continue to, in order
+    pub exits: Vec<NodeIndex>,
+}
+
+impl NaturalLoop {
+    /// Find the natural loops of a control flow graph
+    ///
+    /// The loops with the same header are merged into one.
+    ///
+    /// # Parameters
+    ///
+    /// * `cfg` - The control flow graph.
+    /// * `dominators` - The dominator tree of the graph.
+    pub fn find_all(cfg: &ControlFlowGraph, dominators: &DominatorTree) -> Vec<Self> {
+        // An edge to a node that dominates its source is a back edge
+        let mut back_edges = cfg
+            .node_indices()
+            .into_iter()
+            .flat_map(|node_idx| {
+                cfg.get_successors(node_idx).into_iter().map(move |succ| (succ, node_idx))
+            })
+            .filter(|&(header, latch)| dominators.dominates(header, latch))
+            .collect::<Vec<_>>();
+        back_edges.sort();
+        back_edges.dedup();
+
+        let mut loops: Vec<Self> = Vec::new();
+        for (header, latch) in back_edges {
+            match loops.last_mut() {
+                Some(natural_loop) if natural_loop.header == header => {
+                    natural_loop.latches.push(latch);
+                }
+                _ => loops.push(Self {
+                    header,
+                    latches: vec![latch],
+                    nodes: HashSet::new(),
+                    exits: Vec::new(),
+                }),
+            }
+        }
+
+        for natural_loop in &mut loops {
+            // The loop holds the reachable nodes that reach a latch without
+            // going through the header
+            natural_loop.nodes.insert(natural_loop.header);
+            let mut stack = natural_loop.latches.clone();
+            while let Some(node_idx) = stack.pop() {
+                if natural_loop.nodes.insert(node_idx) {
+                    stack.extend(
+                        cfg.get_predecessors(node_idx)
+                            .into_iter()
+                            .filter(|&pred| dominators.contains(pred)),
+                    );
+                }
+            }
+
+            let mut exits = natural_loop
+                .nodes
+                .iter()
+                .flat_map(|&node_idx| cfg.get_successors(node_idx))
+                .filter(|succ| !natural_loop.nodes.contains(succ))
+                .collect::<Vec<_>>();
+            exits.sort();
+            exits.dedup();
+            natural_loop.exits = exits;
+        }
+        loops
+    }
+
+    /// Check whether the loop can never be left
+    pub fn is_infinite(&self) -> bool {
+        self.exits.is_empty()
+    }
+}
+
+/// Report the code that cannot be reached from the entry of the program
+///
+/// One warning is reported for each run of consecutive unreachable
+/// instructions, pointing at the instruction before it that does not continue.
+/// Runs that start right after an unconditional jump or `HALT`, without a
+/// label to jump to them, are dead code and carry a code of their own.
+fn report_unreachable_code(
+    ctx: &mut AnalysisContext,
+    body: &Body,
+    cfg: &ControlFlowGraph,
+    info: &DominanceInfo,
+) {
+    let reachable = |instr_id: LocalDefId| {
+        cfg.get_node_by_instruction(instr_id).is_some_and(|node_idx| info.is_reachable(node_idx))
+    };
+
+    // Group the unreachable instructions into runs, by program order
+    let instructions = body.instructions.values().collect::<Vec<_>>();
+    let mut unreachable_ranges: Vec<(usize, usize)> = Vec::new();
+    for (idx, instr) in instructions.iter().enumerate() {
+        if cfg.get_node_by_instruction(instr.id).is_none() || reachable(instr.id) {
+            continue;
+        }
+        match unreachable_ranges.last_mut() {
+            Some((_, end)) if *end + 1 == idx => *end = idx,
+            _ => unreachable_ranges.push((idx, idx)),
+        }
+    }
+
+    for (start_idx, end_idx) in unreachable_ranges {
+        let start_instr = instructions[start_idx];
+        let end_instr = instructions[end_idx];
+        let full_span = start_instr.span.start..end_instr.span.end;
+
+        let previous = start_idx.checked_sub(1).map(|idx| instructions[idx]);
+        let is_dead =
+            previous.is_some_and(|previous| {
+                let effects = ctx.instruction_effects(previous);
+                effects.halts() || effects.jump_condition() == Some(JumpCondition::Always)
+            }) && !body.labels.values().any(|label| label.instruction_id == Some(start_instr.id));
+
+        let mut builder = ram_diagnostics::Diagnostic::builder()
+            .with_message("Unreachable code")
+            .with_primary_span(full_span, "never executed");
+        builder = if is_dead {
+            builder
+                .with_help("Remove these instructions, or label them and jump to them")
+                .with_code(DEAD_CODE_CODE)
+        } else {
+            builder.with_help("This block of instructions will never be executed")
+        };
+        if let Some(previous) = previous
+            && reachable(previous.id)
+        {
+            builder = builder.with_secondary_span(
+                previous.span.clone(),
+                "execution never continues past this instruction",
+            );
+        }
+        ctx.add_diagnostic(builder.build_warning());
+    }
+}
+
+/// Report the loops that can never be left
+///
+/// Loops that are not natural, as they can be entered at more than one
+/// node, are reported if no node of theirs continues outside of them.
+fn report_infinite_loops(ctx: &mut AnalysisContext, cfg: &ControlFlowGraph, info: &DominanceInfo) {
+    let instruction_span = |ctx: &AnalysisContext, node_idx: NodeIndex| {
+        cfg.get_node(node_idx).instruction_id.map(|instr_id| ctx.get_instruction_span(instr_id))
+    };
+
+    for natural_loop in info.loops.iter().filter(|natural_loop| natural_loop.is_infinite()) {
+        let Some(header_span) = instruction_span(ctx, natural_loop.header) else {
+            continue;
+        };
+        let mut builder = ram_diagnostics::Diagnostic::builder()
+            .with_message("Infinite loop")
+            .with_help("No instruction of this loop continues outside of it, and none halts")
+            .with_primary_span(header_span, "the loop starts here");
+        for &latch in &natural_loop.latches {
+            if latch != natural_loop.header
+                && let Some(latch_span) = instruction_span(ctx, latch)
+            {
+                builder = builder.with_secondary_span(latch_span, "and jumps back here");
+            }
+        }
+        ctx.add_diagnostic(builder.build_warning());
+    }
+
+    for loop_nodes in cfg.find_infinite_loops() {
+        let is_natural = info.loops.iter().any(|natural_loop| {
+            natural_loop.is_infinite() && loop_nodes.contains(&natural_loop.header)
+        });
+        if is_natural {
+            continue;
+        }
+
+        let mut loop_instrs =
+            loop_nodes.iter().filter_map(|&node_idx| cfg.get_node(node_idx).instruction_id);
+        if let Some(first_instr_id) = loop_instrs.next() {
+            ctx.warning_at_instruction(
+                "Potential infinite loop detected".to_string(),
+                "This loop may not terminate".to_string(),
+                first_instr_id,
+            );
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Dominator tree implementation
+//!
+//! This module provides the implementation of dominator trees. A node
+//! dominates another if every path from the entry to the other node goes
+//! through it, and post-dominates it if every path from it to the end of
+//! the program does.
+
+use std::collections::{HashMap, HashSet};
+
+use petgraph::Direction;
+use petgraph::algo::dominators;
+use petgraph::graph::{DiGraph, NodeIndex};
+use serde_json::{Map, Value, json};
+
+use crate::analyzers::control_flow::{ControlFlowGraph, EdgeKind, Node};
+use crate::context::AnalysisContext;
+use crate::export::ExportFormat;
+
+/// A dominator tree of a control flow graph
+///
+/// The nodes that cannot be reached from the roots, such as unreachable code
+/// in a dominator tree or the nodes of a loop that never exits in a
+/// post-dominator tree, are not part of the tree.
+#[derive(Debug, Clone, Default)]
+pub struct DominatorTree {
+    /// The nodes without an immediate dominator
+    roots: Vec<NodeIndex>,
+    /// Map from nodes to their immediate dominators
+    idom: HashMap<NodeIndex, NodeIndex>,
+    /// Map from nodes to the nodes they immediately dominate
+    children: HashMap<NodeIndex, Vec<NodeIndex>>,
+    /// Map from nodes to their dominance frontiers
+    frontiers: HashMap<NodeIndex, HashSet<NodeIndex>>,
+}
+
+impl DominatorTree {
+    /// Compute the dominator tree of a control flow graph
+    ///
+    /// The tree is empty if the graph has no entry node.
+    pub fn dominators_of(cfg: &ControlFlowGraph) -> Self {
+        let Some(entry) = cfg.entry_node() else {
+            return Self::default();
+        };
+
+        let dom = dominators::simple_fast(cfg.graph(), entry);
+        let idom = cfg
+            .graph()
+            .node_indices()
+            .filter_map(|node_idx| Some((node_idx, dom.immediate_dominator(node_idx)?)))
+            .collect();
+        Self::new(vec![entry], idom, cfg.graph(), Direction::Incoming)
+    }
+
+    /// Compute the post-dominator tree of a control flow graph
+    ///
+    /// The nodes without successors end the program. They, and the nodes
+    /// whose paths to the end of the program share no node, are the roots.
+    pub fn post_dominators_of(cfg: &ControlFlowGraph) -> Self {
+        // Reverse the graph and join the ends of the program in a virtual exit
+        let mut reversed = cfg.graph().map(|_, _| (), |_, _| ());
+        reversed.reverse();
+        let exit = reversed.add_node(());
+        for node_idx in cfg.graph().node_indices() {
+            if cfg.get_successors(node_idx).is_empty() {
+                reversed.add_edge(exit, node_idx, ());
+            }
+        }
+
+        let dom = dominators::simple_fast(&reversed, exit);
+        let mut roots = Vec::new();
+        let mut idom = HashMap::new();
+        for node_idx in cfg.graph().node_indices() {
+            match dom.immediate_dominator(node_idx) {
+                Some(parent) if parent == exit => roots.push(node_idx),
+                Some(parent) => {
+                    idom.insert(node_idx, parent);
+                }
+                None => {}
+            }
+        }
+        Self::new(roots, idom, cfg.graph(), Direction::Outgoing)
+    }
+
+    /// Create a tree from the immediate dominators of its nodes
+    ///
+    /// # Parameters
+    ///
+    /// * `roots` - The nodes without an immediate dominator.
+    /// * `idom` - Map from the other nodes to their immediate dominators.
+    /// * `graph` - The graph the tree was computed from.
+    /// * `predecessors` - The direction of the edges from the predecessors of
+    ///   a node in the order the tree follows the graph.
+    fn new(
+        roots: Vec<NodeIndex>,
+        idom: HashMap<NodeIndex, NodeIndex>,
+        graph: &DiGraph<Node, EdgeKind>,
+        predecessors: Direction,
+    ) -> Self {
+        let mut children: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::new();
+        for (&node_idx, &parent) in &idom {
+            children.entry(parent).or_default().push(node_idx);
+        }
+        for nodes in children.values_mut() {
+            nodes.sort();
+        }
+
+        let mut tree = Self { roots, idom, children, frontiers: HashMap::new() };
+        let mut frontiers: HashMap<NodeIndex, HashSet<NodeIndex>> = HashMap::new();
+
+        // A node is in the frontier of the nodes that dominate one of its
+        // predecessors, up to its immediate dominator
+        for node_idx in graph.node_indices().filter(|&node_idx| tree.contains(node_idx)) {
+            let preds = graph
+                .neighbors_directed(node_idx, predecessors)
+                .filter(|&pred| tree.contains(pred))
+                .collect::<HashSet<_>>();
+            if preds.len() < 2 {
+                continue;
+            }
+            let stop = tree.immediate_dominator(node_idx);
+            for pred in preds {
+                let mut runner = Some(pred);
+                while runner != stop
+                    && let Some(current) = runner
+                {
+                    frontiers.entry(current).or_default().insert(node_idx);
+                    runner = tree.immediate_dominator(current);
+                }
+            }
+        }
+        tree.frontiers = frontiers;
+        tree
+    }
+
+    /// Get the nodes without an immediate dominator
+    pub fn roots(&self) -> &[NodeIndex] {
+        &self.roots
+    }
+
+    /// Check whether a node is part of the tree
+    pub fn contains(&self, node_idx: NodeIndex) -> bool {
+        self.idom.contains_key(&node_idx) || self.roots.contains(&node_idx)
+    }
+
+    /// Get the immediate dominator of a node
+    ///
+    /// Returns `None` for the roots and the nodes that are not part of the tree.
+    pub fn immediate_dominator(&self, node_idx: NodeIndex) -> Option<NodeIndex> {
+        self.idom.get(&node_idx).copied()
+    }
+
+    /// Get the nodes a node immediately dominates, in order
+    pub fn children(&self, node_idx: NodeIndex) -> &[NodeIndex] {
+        self.children.get(&node_idx).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Get the dominators of a node, from the node itself up to its root
+    ///
+    /// Returns no node if the node is not part of the tree.
+    pub fn dominators(&self, node_idx: NodeIndex) -> Vec<NodeIndex> {
+        if !self.contains(node_idx) {
+            return Vec::new();
+        }
+        std::iter::successors(Some(node_idx), |&node_idx| self.immediate_dominator(node_idx))
+            .collect()
+    }
+
+    /// Check whether `dominator` dominates `node_idx`
+    ///
+    /// Every node of the tree dominates itself.
+    pub fn dominates(&self, dominator: NodeIndex, node_idx: NodeIndex) -> bool {
+        self.dominators(node_idx).contains(&dominator)
+    }
+
+    /// Check whether `dominator` dominates `node_idx` and is another node
+    pub fn strictly_dominates(&self, dominator: NodeIndex, node_idx: NodeIndex) -> bool {
+        dominator != node_idx && self.dominates(dominator, node_idx)
+    }
+
+    /// Get the dominance frontier of a node
+    ///
+    /// These are the nodes where the dominance of the node ends: the node
+    /// dominates one of their predecessors, but not themselves strictly.
+    pub fn dominance_frontier(&self, node_idx: NodeIndex) -> HashSet<NodeIndex> {
+        self.frontiers.get(&node_idx).cloned().unwrap_or_default()
+    }
+
+    /// Get the edges of the tree, from each node to the nodes it immediately
+    /// dominates, in order
+    pub fn edges(&self) -> Vec<(NodeIndex, NodeIndex)> {
+        let mut edges =
+            self.idom.iter().map(|(&node_idx, &parent)| (parent, node_idx)).collect::<Vec<_>>();
+        edges.sort();
+        edges
+    }
+
+    /// Get the nodes of the tree, in order
+    fn nodes(&self) -> Vec<NodeIndex> {
+        let mut nodes = self.roots.iter().chain(self.idom.keys()).copied().collect::<Vec<_>>();
+        nodes.sort();
+        nodes
+    }
+
+    /// Export the tree in the given format
+    ///
+    /// The nodes are named like those of the exported control flow graph, and
+    /// labeled by the instructions of the body of the context if it is given.
+    ///
+    /// # Parameters
+    ///
+    /// * `format` - The format to export the tree in.
+    /// * `cfg` - The control flow graph the tree was computed from.
+    /// * `context` - The context holding the body of the graph, if any.
+    pub fn export(
+        &self,
+        format: ExportFormat,
+        cfg: &ControlFlowGraph,
+        context: Option<&AnalysisContext>,
+    ) -> String {
+        let body = context.map(|context| context.body().as_ref());
+        let label = |node_idx: NodeIndex| cfg.node_label(node_idx, body);
+        match format {
+            ExportFormat::Dot => {
+                let mut result = String::from("digraph {\n");
+                for node_idx in self.nodes() {
+                    result.push_str(&format!(
+                        "    N{} [label={:?}];\n",
+                        node_idx.index(),
+                        label(node_idx)
+                    ));
+                }
+                for (parent, node_idx) in self.edges() {
+                    result.push_str(&format!(
+                        "    N{} -> N{};\n",
+                        parent.index(),
+                        node_idx.index()
+                    ));
+                }
+                result.push_str("}\n");
+                result
+            }
+            ExportFormat::Mermaid => {
+                let mut result = String::from("graph TD\n");
+                for node_idx in self.nodes() {
+                    let label = label(node_idx).replace('"', "\\\"");
+                    result.push_str(&format!("    N{}[\"{}\"]\n", node_idx.index(), label));
+                }
+                for (parent, node_idx) in self.edges() {
+                    result.push_str(&format!(
+                        "    N{} --> N{}\n",
+                        parent.index(),
+                        node_idx.index()
+                    ));
+                }
+                result
+            }
+            ExportFormat::Json => {
+                let nodes = self
+                    .nodes()
+                    .into_iter()
+                    .map(|node_idx| {
+                        json!({
+                            "id": format!("N{}", node_idx.index()),
+                            "label": label(node_idx),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let edges = self
+                    .edges()
+                    .into_iter()
+                    .map(|(parent, node_idx)| {
+                        json!({
+                            "source": format!("N{}", parent.index()),
+                            "target": format!("N{}", node_idx.index()),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let roots = self
+                    .roots
+                    .iter()
+                    .map(|node_idx| Value::String(format!("N{}", node_idx.index())))
+                    .collect::<Vec<_>>();
+
+                let mut json = Map::new();
+                json.insert("roots".to_string(), Value::Array(roots));
+                json.insert("nodes".to_string(), Value::Array(nodes));
+                json.insert("edges".to_string(), Value::Array(edges));
+                serde_json::to_string_pretty(&Value::Object(json))
+                    .unwrap_or_else(|_| "{}".to_string())
+            }
+        }
+    }
+}
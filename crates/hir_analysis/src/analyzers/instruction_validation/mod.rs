@@ -1,23 +1,40 @@
 //! Instruction validation for HIR
 //!
 //! This module provides validation for instructions in HIR bodies.
-//! It checks that instructions are valid according to the instruction set
-//! and that operands are of the correct type.
+//! It checks that instructions are valid according to the instruction registry
+//! of the analysis context, which includes the instructions of plugins, and
+//! that operands are of the correct type.
 
 use std::any::TypeId;
 
-use hir::body::{Body, ExprKind, Literal};
+use hir::body::{AddressingMode, Body, CHAR_ANNOTATION, ExprKind, Instruction, Literal};
 use hir::expr::ExprId;
 use miette::Diagnostic;
-use ram_core::{InstructionKind, InstructionSet};
+use ram_core::{Effect, InstructionRegistry, Operand, OperandError, OperandKind, OperandValue};
+use ram_diagnostics::Applicability;
+use ram_diagnostics::suggestion::closest_match;
 
 use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
 use crate::pass::AnalysisPass;
 
+/// The diagnostic code reported for unknown instructions
+pub const UNKNOWN_INSTRUCTION_CODE: &str = ram_diagnostics::codes::UNKNOWN_INSTRUCTION.code;
+
+/// The diagnostic code reported for undefined labels
+pub const UNDEFINED_LABEL_CODE: &str = ram_diagnostics::codes::UNDEFINED_LABEL.code;
+
+/// The diagnostic code reported for operand expressions that are not constant
+pub const NON_CONSTANT_OPERAND_CODE: &str = ram_diagnostics::codes::NON_CONSTANT_OPERAND.code;
+
 /// Instruction validation analysis pass
 ///
-/// This pass validates instructions in a HIR body against the instruction set.
-/// It checks that instructions are valid and that operands are of the correct type.
+/// This pass validates instructions in a HIR body against the instruction
+/// registry of the analysis context. It checks that instructions are valid,
+/// that they have an operand exactly when their definition requires one, and
+/// that the addressing mode of the operand is one the definition allows. The
+/// operand rules come from [`ram_core::InstructionDefinition`], so the virtual
+/// machine rejects the same operands when it loads the program.
 #[derive(Default)]
 pub struct InstructionValidationAnalysis;
 
@@ -28,47 +45,60 @@ impl AnalysisPass for InstructionValidationAnalysis {
         "InstructionValidationAnalysis"
     }
 
+    fn id(&self) -> &'static str {
+        "validation"
+    }
+
     fn dependencies(&self) -> Vec<TypeId> {
         vec![]
     }
 
+    fn inputs(&self) -> BodyParts {
+        BodyParts::CODE
+    }
+
     fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
-        // Clone the body to avoid borrowing issues
+        // Clone the body and the registry to avoid borrowing issues
         let body = ctx.body().clone();
-        let instruction_set = InstructionSet::standard();
+        let registry = ctx.instruction_registry().clone();
 
-        for instr in &body.instructions {
-            // Check if the instruction exists in the instruction set
+        for instr in body.instructions.values() {
+            // Check if the instruction exists in the registry
             let opcode = instr.opcode.to_uppercase();
+            let Some(definition) = registry.get_by_name_case_insensitive(&opcode) else {
+                self.report_unknown_instruction(ctx, &registry, instr);
+                continue;
+            };
 
-            let kind = InstructionKind::from_name(&opcode);
-            if instruction_set.contains_name_case_insensitive(&opcode) {
-                // Check if the instruction has the correct number of operands
-                if kind.requires_operand() {
-                    if instr.operand.is_none() {
-                        ctx.error_at_instruction(
-                            format!("Instruction '{}' requires an operand", opcode),
-                            "Add an operand".to_string(),
-                            instr.id,
-                        );
-                    } else if let Some(operand_id) = instr.operand {
-                        // Validate the operand
-                        self.validate_operand(ctx, &body, operand_id, &kind, &opcode);
-                    }
-                } else if instr.operand.is_some() {
-                    ctx.error_at_instruction(
-                        format!("Instruction '{}' does not take an operand", opcode),
-                        "Remove the operand".to_string(),
-                        instr.id,
-                    );
-                }
-            } else {
-                ctx.error_at_instruction(
-                    format!("Unknown instruction: '{}'", opcode),
-                    "Use a valid instruction from the instruction set".to_string(),
+            // Only instructions that write to the output can write characters
+            if instr.has_annotation(CHAR_ANNOTATION)
+                && !definition.effects().contains(Effect::WritesOutput)
+            {
+                ctx.warning_at_instruction(
+                    format!("`{}` has no effect on '{}'", CHAR_ANNOTATION, opcode),
+                    "Only instructions that write to the output, like WRITE, print characters"
+                        .to_string(),
                     instr.id,
                 );
             }
+
+            // Check the operand with the rules the virtual machine uses
+            if let Err(error) = definition.validate_arity(instr.operand.is_some()) {
+                self.report_operand_error(ctx, instr, None, &error, &opcode);
+            } else if let Some(operand_id) = instr.operand {
+                let checked = match core_operand(&body, operand_id) {
+                    Some(operand) => definition.validate_operand(&operand),
+                    None => operand_kind(&body, operand_id)
+                        .map_or(Ok(()), |kind| definition.validate_operand_kind(kind)),
+                };
+                if let Err(error) = checked {
+                    self.report_operand_error(ctx, instr, Some(operand_id), &error, &opcode);
+                }
+                // Jumps and calls take a label as their operand
+                let effects = definition.effects();
+                let takes_label = effects.jump_condition().is_some() || effects.calls();
+                self.validate_operand(ctx, &body, operand_id, takes_label, &opcode);
+            }
         }
 
         Ok(())
@@ -76,46 +106,157 @@ impl AnalysisPass for InstructionValidationAnalysis {
 }
 
 impl InstructionValidationAnalysis {
+    /// Report an unknown instruction, suggesting the closest known instruction
+    fn report_unknown_instruction(
+        &self,
+        ctx: &mut AnalysisContext,
+        registry: &InstructionRegistry,
+        instr: &Instruction,
+    ) {
+        let opcode = instr.opcode.to_uppercase();
+        let mut names = registry.names().collect::<Vec<_>>();
+        // Registry order is unspecified, so sort the names to keep suggestions stable
+        names.sort();
+        let span = ctx.get_instruction_span(instr.id);
+        // The instruction span starts with its opcode
+        let opcode_span = span.start..span.start + instr.opcode.len();
+
+        let mut builder = ram_diagnostics::Diagnostic::builder()
+            .with_message(format!("Unknown instruction: '{}'", opcode))
+            .with_primary_span(opcode_span.clone(), "unknown instruction")
+            .with_code(UNKNOWN_INSTRUCTION_CODE);
+        builder = match closest_match(&instr.opcode, names.iter().map(String::as_str)) {
+            Some(name) => builder
+                .with_help(format!("A similar instruction exists: `{}`", name))
+                .with_suggestion(opcode_span, name, Applicability::MaybeIncorrect),
+            None => builder.with_help("Use a valid instruction from the instruction set"),
+        };
+        ctx.add_diagnostic(builder.build_error());
+    }
+
+    /// Report an undefined label, suggesting the closest defined label
+    fn report_undefined_label(
+        &self,
+        ctx: &mut AnalysisContext,
+        body: &Body,
+        operand_id: ExprId,
+        label: &str,
+    ) {
+        let span = ctx.get_expr_span(operand_id);
+
+        let mut builder = ram_diagnostics::Diagnostic::builder()
+            .with_message(format!("Undefined label: '{}'", label))
+            .with_primary_span(span.clone(), "undefined label")
+            .with_code(UNDEFINED_LABEL_CODE);
+        let closest = closest_match(label, body.labels.values().map(|l| l.name.as_str()));
+        builder = match closest {
+            // The operand may be prefixed by its addressing mode, so the label
+            // is replaced from the end of the operand
+            Some(name) if span.len() >= label.len() => builder
+                .with_help(format!("A label with a similar name exists: `{}`", name))
+                .with_suggestion(
+                    span.end - label.len()..span.end,
+                    name,
+                    Applicability::MaybeIncorrect,
+                ),
+            _ => builder.with_help("Define the label before using it"),
+        };
+        ctx.add_diagnostic(builder.build_error());
+    }
+
+    /// Report an arithmetic expression in an operand that was not folded
+    ///
+    /// Undefined labels are the usual cause, so they are reported instead
+    /// when the expression uses any.
+    fn report_non_constant_operand(&self, ctx: &mut AnalysisContext, body: &Body, expr_id: ExprId) {
+        let mut undefined = Vec::new();
+        collect_undefined_labels(body, expr_id, &mut undefined);
+        if !undefined.is_empty() {
+            for (label_id, label) in undefined {
+                self.report_undefined_label(ctx, body, label_id, &label);
+            }
+            return;
+        }
+
+        let span = ctx.get_expr_span(expr_id);
+        let diagnostic = ram_diagnostics::Diagnostic::builder()
+            .with_message("Operand expression is not constant")
+            .with_primary_span(span, "can not be evaluated at compile time")
+            .with_help("Use numbers and labels, and do not overflow or divide by zero")
+            .with_code(NON_CONSTANT_OPERAND_CODE)
+            .build_error();
+        ctx.add_diagnostic(diagnostic);
+    }
+
+    /// Report an operand the instruction definition rejects, at the operand
+    /// if the instruction has one and at the instruction otherwise
+    fn report_operand_error(
+        &self,
+        ctx: &mut AnalysisContext,
+        instr: &Instruction,
+        operand_id: Option<ExprId>,
+        error: &OperandError,
+        opcode: &str,
+    ) {
+        let (message, help) = match error {
+            OperandError::Missing { .. } => (
+                format!("Instruction '{}' requires an operand", opcode),
+                "Add an operand".to_string(),
+            ),
+            OperandError::Unexpected { .. } => (
+                format!("Instruction '{}' does not take an operand", opcode),
+                "Remove the operand".to_string(),
+            ),
+            OperandError::UnsupportedKind { kind, allowed, .. } => {
+                let help = if allowed.is_empty() {
+                    "Remove the operand".to_string()
+                } else {
+                    let names = allowed.iter().map(|kind| kind.name()).collect::<Vec<_>>();
+                    format!("Use one of the following operands: {}", names.join(", "))
+                };
+                (format!("Instruction '{}' does not accept {} operands", opcode, kind), help)
+            }
+            OperandError::ImmediateJump { .. } => (
+                format!("Instruction '{}' can not jump to an immediate value", opcode),
+                "Jump to a label instead".to_string(),
+            ),
+            OperandError::NegativeAddress { address } => (
+                format!("Negative memory address: {}", address),
+                "Memory addresses are numbered from 0".to_string(),
+            ),
+        };
+        match operand_id {
+            Some(operand_id) => ctx.error_at_expr(message, help, operand_id),
+            None => ctx.error_at_instruction(message, help, instr.id),
+        }
+    }
+
     /// Validate an operand against the instruction kind
     fn validate_operand(
         &self,
         ctx: &mut AnalysisContext,
         body: &Body,
         operand_id: ExprId,
-        kind: &InstructionKind,
+        takes_label: bool,
         opcode: &str,
     ) {
-        if let Some(expr) = body.exprs.get(operand_id.0 as usize) {
+        if let Some(expr) = body.expr(operand_id) {
             match &expr.kind {
                 ExprKind::Literal(literal) => {
                     match literal {
-                        Literal::Int(value) => {
-                            // Check if the integer is in range
-                            if *value < 0 {
-                                ctx.warning_at_expr(
-                                    format!("Negative memory address: {}", value),
-                                    "Memory addresses should be non-negative".to_string(),
-                                    operand_id,
-                                );
-                            }
+                        Literal::Int(_) => {
+                            // Immediate values are lowered without a memory
+                            // reference, so any integer is valid, including
+                            // negative numbers and character codes
                         }
                         Literal::Label(label) => {
                             // Check if the label exists
-                            if !body.labels.iter().any(|l| l.name == *label) {
-                                ctx.error_at_expr(
-                                    format!("Undefined label: '{}'", label),
-                                    "Define the label before using it".to_string(),
-                                    operand_id,
-                                );
+                            if !body.labels.values().any(|l| l.name == *label) {
+                                self.report_undefined_label(ctx, body, operand_id, label);
                             }
 
                             // Check if this is a jump instruction
-                            if !matches!(
-                                kind,
-                                InstructionKind::Jump
-                                    | InstructionKind::JumpGtz
-                                    | InstructionKind::JumpZero
-                            ) {
+                            if !takes_label {
                                 ctx.warning_at_expr(
                                     format!(
                                         "Label used as operand for non-jump instruction: '{}'",
@@ -143,16 +284,11 @@ impl InstructionValidationAnalysis {
                 ExprKind::LabelRef(_label_ref) => {
                     // Get the label name from the label ID
                     // We can't directly compare LocalDefId with DefId, so we'll just check all labels
-                    let label_name = body.labels.iter().map(|l| l.name.clone()).next();
+                    let label_name = body.labels.values().map(|l| l.name.clone()).next();
 
                     if let Some(_label_name) = label_name {
                         // Check if this is a jump instruction
-                        if !matches!(
-                            kind,
-                            InstructionKind::Jump
-                                | InstructionKind::JumpGtz
-                                | InstructionKind::JumpZero
-                        ) {
+                        if !takes_label {
                             ctx.warning_at_expr(
                                 format!("Label reference used as operand for non-jump instruction: '{}'", opcode),
                                 "Label references are typically used with jump instructions".to_string(),
@@ -169,16 +305,11 @@ impl InstructionValidationAnalysis {
                 }
                 ExprKind::MemoryRef(mem_ref) => {
                     // Check if the address is valid
-                    if let Some(addr_expr) = body.exprs.get(mem_ref.address.0 as usize) {
+                    if let Some(addr_expr) = body.expr(mem_ref.address) {
                         match &addr_expr.kind {
-                            ExprKind::Literal(Literal::Int(value)) => {
-                                if *value < 0 {
-                                    ctx.warning_at_expr(
-                                        format!("Negative memory address: {}", value),
-                                        "Memory addresses should be non-negative".to_string(),
-                                        mem_ref.address,
-                                    );
-                                }
+                            ExprKind::Literal(Literal::Int(_)) => {
+                                // Negative addresses were rejected with the
+                                // other operand rules of the definition
                             }
                             ExprKind::ArrayAccess(_) => {
                                 // Array access is a valid address expression
@@ -189,6 +320,10 @@ impl InstructionValidationAnalysis {
                             ExprKind::Literal(Literal::Label(_)) => {
                                 // Label literal is a valid address expression
                             }
+                            ExprKind::Binary(_) => {
+                                // Constant expressions were folded during lowering
+                                self.report_non_constant_operand(ctx, body, mem_ref.address);
+                            }
                             _ => {
                                 ctx.error_at_expr(
                                     "Memory reference address must be an integer, label, or array access".to_string(),
@@ -209,18 +344,20 @@ impl InstructionValidationAnalysis {
                         operand_id,
                     );
                 }
+                ExprKind::Binary(_) => {
+                    // Constant expressions were folded during lowering
+                    self.report_non_constant_operand(ctx, body, operand_id);
+                }
                 ExprKind::ArrayAccess(array_access) => {
                     // Validate the array base
-                    if let Some(base_expr) = body.exprs.get(array_access.array.0 as usize) {
+                    if let Some(base_expr) = body.expr(array_access.array) {
                         match &base_expr.kind {
-                            ExprKind::Literal(Literal::Int(value)) => {
-                                if *value < 0 {
-                                    ctx.warning_at_expr(
-                                        format!("Negative array base address: {}", value),
-                                        "Array base addresses should be non-negative".to_string(),
-                                        array_access.array,
-                                    );
-                                }
+                            ExprKind::Literal(Literal::Int(value)) if *value < 0 => {
+                                ctx.warning_at_expr(
+                                    format!("Negative array base address: {}", value),
+                                    "Array base addresses should be non-negative".to_string(),
+                                    array_access.array,
+                                );
                             }
                             _ => {
                                 // Other base types are allowed (e.g., variables, labels)
@@ -229,7 +366,7 @@ impl InstructionValidationAnalysis {
                     }
 
                     // Validate the array index
-                    if let Some(index_expr) = body.exprs.get(array_access.index.0 as usize) {
+                    if let Some(index_expr) = body.expr(array_access.index) {
                         match &index_expr.kind {
                             ExprKind::Literal(Literal::Int(value)) => {
                                 if *value < 0 {
@@ -254,3 +391,85 @@ impl InstructionValidationAnalysis {
         }
     }
 }
+
+/// Get the addressing mode of an operand
+///
+/// Label operands are direct, as jump targets are. Returns `None` for
+/// expressions that are not operands.
+pub(crate) fn operand_kind(body: &Body, operand_id: ExprId) -> Option<OperandKind> {
+    let expr = body.expr(operand_id)?;
+    match &expr.kind {
+        ExprKind::MemoryRef(mem_ref) => match mem_ref.mode {
+            AddressingMode::Direct => {
+                let address = body.expr(mem_ref.address)?;
+                if matches!(address.kind, ExprKind::ArrayAccess(_)) {
+                    Some(OperandKind::Indexed)
+                } else {
+                    Some(OperandKind::Direct)
+                }
+            }
+            AddressingMode::Indirect => Some(OperandKind::Indirect),
+            AddressingMode::Immediate => Some(OperandKind::Immediate),
+        },
+        ExprKind::Literal(Literal::Int(_) | Literal::String(_))
+        | ExprKind::ArrayAccess(_)
+        | ExprKind::Binary(_) => Some(OperandKind::Immediate),
+        ExprKind::Literal(Literal::Label(_)) | ExprKind::LabelRef(_) => Some(OperandKind::Direct),
+        ExprKind::InstructionCall(_) => None,
+    }
+}
+
+/// Convert an operand to the operand the virtual machine runs, so it can be
+/// checked with the operand rules of its definition
+///
+/// Returns `None` for operands whose value is not known before lowering
+/// finishes, such as arithmetic that was not folded.
+pub(crate) fn core_operand(body: &Body, operand_id: ExprId) -> Option<Operand> {
+    let kind = operand_kind(body, operand_id)?;
+    let value_of = |expr_id| match &body.expr(expr_id)?.kind {
+        ExprKind::Literal(Literal::Int(value)) => Some(OperandValue::Number(*value)),
+        ExprKind::Literal(Literal::Label(name) | Literal::String(name)) => {
+            Some(OperandValue::String(name.clone()))
+        }
+        ExprKind::LabelRef(label_ref) => body
+            .labels
+            .values()
+            .find(|label| label.id == label_ref.label_id.local_id)
+            .map(|label| OperandValue::String(label.name.clone())),
+        _ => None,
+    };
+    let value = match &body.expr(operand_id)?.kind {
+        ExprKind::MemoryRef(mem_ref) => match &body.expr(mem_ref.address)?.kind {
+            ExprKind::ArrayAccess(access) => {
+                match (value_of(access.array)?, value_of(access.index)?) {
+                    (OperandValue::Number(base), OperandValue::Number(index)) => {
+                        OperandValue::Indexed(base, index)
+                    }
+                    _ => return None,
+                }
+            }
+            _ => value_of(mem_ref.address)?,
+        },
+        _ => value_of(operand_id)?,
+    };
+    Some(Operand { kind, value })
+}
+
+/// Collect the labels an arithmetic expression uses that are not defined
+fn collect_undefined_labels(body: &Body, expr_id: ExprId, labels: &mut Vec<(ExprId, String)>) {
+    let Some(expr) = body.expr(expr_id) else {
+        return;
+    };
+    match &expr.kind {
+        ExprKind::Literal(Literal::Label(label)) => {
+            if !body.labels.values().any(|l| l.name == *label) {
+                labels.push((expr_id, label.clone()));
+            }
+        }
+        ExprKind::Binary(binary) => {
+            collect_undefined_labels(body, binary.lhs, labels);
+            collect_undefined_labels(body, binary.rhs, labels);
+        }
+        _ => {}
+    }
+}
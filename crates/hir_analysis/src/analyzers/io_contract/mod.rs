@@ -0,0 +1,387 @@
+//! Input/output contract checking for HIR
+//!
+//! This module provides an analysis that checks the number of values a
+//! program reads and writes against the contract it declares with `@reads`
+//! and `@writes` in a documentation comment of its first instruction.
+//!
+//! The values are counted along every path of the control flow graph from
+//! the entry to a `HALT`, or to the end of the program. Calls are stepped
+//! over with the counts of the paths through their subroutine, so returns
+//! only go back to the call they return from. A loop that reads or writes
+//! can run any number of times, so a path through it has no upper bound.
+
+use std::any::TypeId;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use hir::body::{Body, IoContract};
+use miette::Diagnostic;
+use petgraph::graph::NodeIndex;
+use ram_core::Effect;
+
+use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph, EdgeKind};
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// The diagnostic code reported for programs that break their contract
+pub const IO_CONTRACT_VIOLATION_CODE: &str = ram_diagnostics::codes::IO_CONTRACT_VIOLATION.code;
+
+/// Input/output contract analysis pass
+///
+/// This pass counts the values the runs of a program read and write, when
+/// the program declares a contract. Counts that no run matches are reported
+/// as errors, and counts that only some runs match as warnings. Counts with
+/// no upper bound are only checked against their lower bound.
+#[derive(Debug, Default)]
+pub struct IoContractAnalysis;
+
+impl AnalysisPass for IoContractAnalysis {
+    type Output = IoContractResult;
+
+    fn name(&self) -> &'static str {
+        "IoContractAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "io-contract"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<ControlFlowAnalysis>()]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        // The contract is read from the docs of the first instruction
+        BodyParts::INSTRUCTIONS
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        let body = ctx.body().clone();
+        let Some(contract) = body.io_contract() else {
+            return Ok(IoContractResult::default());
+        };
+        let cfg = match ctx.get_result::<ControlFlowAnalysis>() {
+            Ok(cfg) => cfg.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let nodes_with = |effect: Effect| {
+            body.instructions
+                .values()
+                .filter(|instr| ctx.instruction_effects(instr).contains(effect))
+                .filter_map(|instr| cfg.get_node_by_instruction(instr.id))
+                .collect::<HashSet<_>>()
+        };
+        let halts = nodes_with(Effect::Halts);
+        let returns = nodes_with(Effect::Returns);
+        let count = |counted: HashSet<NodeIndex>| {
+            let mut counter = PathCounter::new(&cfg, counted, &halts, &returns);
+            counter.count(cfg.entry_node()?, false)
+        };
+        let result = IoContractResult {
+            contract: Some(contract),
+            reads: count(nodes_with(Effect::ReadsInput)),
+            writes: count(nodes_with(Effect::WritesOutput)),
+        };
+
+        report(ctx, &body, &result);
+        Ok(result)
+    }
+}
+
+/// The number of values the runs of a program read or write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoCount {
+    /// The fewest values a run reads or writes
+    pub min: usize,
+    /// The most values a run reads or writes, or `None` if a loop that reads
+    /// or writes can run any number of times
+    pub max: Option<usize>,
+}
+
+impl IoCount {
+    /// The count of a path that reads or writes exactly `count` values
+    pub fn exactly(count: usize) -> Self {
+        Self { min: count, max: Some(count) }
+    }
+
+    /// Check whether a run can read or write `count` values
+    pub fn contains(&self, count: usize) -> bool {
+        self.min <= count && self.max.is_none_or(|max| count <= max)
+    }
+
+    /// The count of a path followed by another
+    fn then(self, other: Self) -> Self {
+        Self { min: self.min + other.min, max: self.max.zip(other.max).map(|(a, b)| a + b) }
+    }
+}
+
+/// The result of input/output contract analysis
+#[derive(Debug, Clone, Default)]
+pub struct IoContractResult {
+    /// The contract the program declares, if any
+    pub contract: Option<IoContract>,
+    /// The values the runs that halt read, if the program declares a contract
+    /// and any run halts
+    pub reads: Option<IoCount>,
+    /// The values the runs that halt write, if the program declares a
+    /// contract and any run halts
+    pub writes: Option<IoCount>,
+}
+
+/// Report the counts that break the contract
+fn report(ctx: &mut AnalysisContext, body: &Body, result: &IoContractResult) {
+    let (Some(contract), Some(entry)) = (result.contract, body.instructions.values().next()) else {
+        return;
+    };
+    let checks = [
+        ("reads", "read", contract.reads, result.reads),
+        ("writes", "write", contract.writes, result.writes),
+    ];
+    for (verb, infinitive, declared, count) in checks {
+        let (Some(declared), Some(count)) = (declared, count) else {
+            continue;
+        };
+        let builder = ram_diagnostics::Diagnostic::builder()
+            .with_message(format!(
+                "Program {} {}, but its contract declares {}",
+                verb,
+                describe(count),
+                declared
+            ))
+            .with_primary_span(entry.span.clone(), "the contract is declared for this program")
+            .with_code(IO_CONTRACT_VIOLATION_CODE);
+
+        let diagnostic = if !count.contains(declared) {
+            builder
+                .with_help(format!(
+                    "No run of the program that halts {} as many values as declared, change \
+                     the program or the `@{}` annotation",
+                    verb, verb
+                ))
+                .build_error()
+        } else if count.max.is_some() && count != IoCount::exactly(declared) {
+            builder
+                .with_help(format!(
+                    "Some paths of the program {} a different number of values than declared",
+                    infinitive
+                ))
+                .build_warning()
+        } else {
+            continue;
+        };
+        ctx.add_diagnostic(diagnostic);
+    }
+}
+
+/// Describe a number of values, such as `between 1 and 3 values`
+fn describe(count: IoCount) -> String {
+    let values = |count: usize| format!("{} value{}", count, if count == 1 { "" } else { "s" });
+    match count.max {
+        Some(max) if max == count.min => values(max),
+        Some(max) => format!("between {} and {}", count.min, values(max)),
+        None => format!("at least {}", values(count.min)),
+    }
+}
+
+/// Counts the values read or written along the paths of a control flow graph
+struct PathCounter<'a> {
+    /// The control flow graph
+    cfg: &'a ControlFlowGraph,
+    /// The nodes that read or write a value
+    counted: HashSet<NodeIndex>,
+    /// The nodes that halt the program
+    halts: &'a HashSet<NodeIndex>,
+    /// The nodes that return from a subroutine
+    returns: &'a HashSet<NodeIndex>,
+    /// The counts of the paths through each subroutine, by its entry
+    summaries: HashMap<NodeIndex, Option<IoCount>>,
+    /// The subroutines whose counts are being computed
+    in_progress: HashSet<NodeIndex>,
+}
+
+/// Where an edge of the graph the counter searches goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// A node of the control flow graph
+    Node(NodeIndex),
+    /// The end of every path
+    Exit,
+}
+
+impl<'a> PathCounter<'a> {
+    /// Create a counter of the values the `counted` nodes read or write
+    fn new(
+        cfg: &'a ControlFlowGraph,
+        counted: HashSet<NodeIndex>,
+        halts: &'a HashSet<NodeIndex>,
+        returns: &'a HashSet<NodeIndex>,
+    ) -> Self {
+        Self {
+            cfg,
+            counted,
+            halts,
+            returns,
+            summaries: HashMap::new(),
+            in_progress: HashSet::new(),
+        }
+    }
+
+    /// Count the values along the paths from `start` to a return, or to the
+    /// end of the program if `to_return` is false
+    ///
+    /// Returns `None` if no path ends.
+    fn count(&mut self, start: NodeIndex, to_return: bool) -> Option<IoCount> {
+        let mut edges = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![start];
+        while let Some(node_idx) = stack.pop() {
+            if !visited.insert(node_idx) {
+                continue;
+            }
+            let weight = IoCount::exactly(usize::from(self.counted.contains(&node_idx)));
+
+            let ends = if to_return {
+                self.returns.contains(&node_idx)
+            } else {
+                self.halts.contains(&node_idx) || self.cfg.falls_off_end() == Some(node_idx)
+            };
+            if ends {
+                edges.push((node_idx, Target::Exit, weight));
+            }
+
+            // Calls continue where they return, after the paths of the subroutine
+            if let Some(&return_site) = self.cfg.call_sites().get(&node_idx) {
+                let Some(entry) = self.cfg.call_target(node_idx) else {
+                    continue;
+                };
+                if let Some(return_site) = return_site
+                    && let Some(summary) = self.summary(entry)
+                {
+                    edges.push((node_idx, Target::Node(return_site), weight.then(summary)));
+                    stack.push(return_site);
+                }
+                // Runs can also halt inside the subroutine
+                if !to_return {
+                    edges.push((node_idx, Target::Node(entry), weight));
+                    stack.push(entry);
+                }
+                continue;
+            }
+
+            for (target, kind) in self.cfg.get_outgoing_edges(node_idx) {
+                if !matches!(kind, EdgeKind::Call | EdgeKind::Return) {
+                    edges.push((node_idx, Target::Node(target), weight));
+                    stack.push(target);
+                }
+            }
+        }
+
+        let mut nodes = visited.into_iter().collect::<Vec<_>>();
+        nodes.sort_unstable();
+        longest_and_shortest(start, &nodes, &edges)
+    }
+
+    /// Count the values along the paths through the subroutine at `entry`
+    ///
+    /// Recursive calls can read or write any number of values.
+    fn summary(&mut self, entry: NodeIndex) -> Option<IoCount> {
+        if let Some(&summary) = self.summaries.get(&entry) {
+            return summary;
+        }
+        if !self.in_progress.insert(entry) {
+            return Some(IoCount { min: 0, max: None });
+        }
+        let summary = self.count(entry, true);
+        self.in_progress.remove(&entry);
+        self.summaries.insert(entry, summary);
+        summary
+    }
+}
+
+/// Find the fewest and the most values along the paths from `start` to the
+/// exit of a graph
+///
+/// Returns `None` if no path reaches the exit.
+fn longest_and_shortest(
+    start: NodeIndex,
+    nodes: &[NodeIndex],
+    edges: &[(NodeIndex, Target, IoCount)],
+) -> Option<IoCount> {
+    // The exit goes after the nodes
+    let index = |target: Target| match target {
+        Target::Node(node_idx) => nodes.binary_search(&node_idx).ok(),
+        Target::Exit => Some(nodes.len()),
+    };
+    let edges = edges
+        .iter()
+        .filter_map(|&(source, target, count)| {
+            Some((index(Target::Node(source))?, index(target)?, count))
+        })
+        .collect::<Vec<_>>();
+    let start = index(Target::Node(start))?;
+    let exit = nodes.len();
+
+    // The fewest values, by Dijkstra's algorithm
+    let mut outgoing = vec![Vec::new(); exit + 1];
+    for &(source, target, count) in &edges {
+        outgoing[source].push((target, count.min));
+    }
+    let mut fewest = vec![None; exit + 1];
+    let mut queue = BinaryHeap::from([Reverse((0, start))]);
+    while let Some(Reverse((distance, node))) = queue.pop() {
+        if fewest[node].is_some() {
+            continue;
+        }
+        fewest[node] = Some(distance);
+        for &(target, weight) in &outgoing[node] {
+            if fewest[target].is_none() {
+                queue.push(Reverse((distance + weight, target)));
+            }
+        }
+    }
+    let min = fewest[exit]?;
+
+    // Only the paths that reach the exit matter for the most values
+    let mut reaches_exit = vec![false; exit + 1];
+    reaches_exit[exit] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(source, target, _) in &edges {
+            if reaches_exit[target] && !reaches_exit[source] {
+                reaches_exit[source] = true;
+                changed = true;
+            }
+        }
+    }
+    let edges = edges
+        .into_iter()
+        .filter(|&(source, target, _)| reaches_exit[source] && reaches_exit[target])
+        .map(|(source, target, count)| Some((source, target, count.max?)))
+        .collect::<Option<Vec<_>>>();
+    let Some(edges) = edges else {
+        return Some(IoCount { min, max: None });
+    };
+
+    // The most values, by Bellman-Ford, where a path that can still grow after
+    // as many rounds as there are nodes goes through a loop that reads or writes
+    let mut most = vec![None; exit + 1];
+    most[start] = Some(0);
+    for _ in 0..=exit {
+        let mut grew = false;
+        for &(source, target, weight) in &edges {
+            if let Some(value) = most[source]
+                && most[target].is_none_or(|current| current < value + weight)
+            {
+                most[target] = Some(value + weight);
+                grew = true;
+            }
+        }
+        if !grew {
+            return Some(IoCount { min, max: most[exit] });
+        }
+    }
+    Some(IoCount { min, max: None })
+}
@@ -5,20 +5,47 @@
 //! - Control flow analysis
 //! - Data flow analysis
 //! - Constant propagation analysis
+//! - Alias analysis of indirect and indexed operands
+//! - Dominance analysis with dominator trees and natural loops
+//! - SSA numbering of the versions of the accumulator
 //! - Control flow optimization
 //! - Instruction validation
+//! - Input/output contract checking
+//! - Metrics of the sections of a program between its labels
+//! - Lints for jumps that do not change where execution goes
+//! - Strict mode with classic RAM semantics
+//! - Termination checking with loop counters
+//! - Unused label detection
 
+pub mod alias;
 pub mod constant_propagation;
 pub mod control_flow;
 pub mod control_flow_optimizer;
 pub mod data_flow;
+pub mod dominance;
 pub mod instruction_validation;
+pub mod io_contract;
+pub mod scheduling;
+pub mod sections;
+pub mod ssa;
+pub mod strict_ram;
+pub mod termination;
+pub mod unused_labels;
 
 // Re-export main components
+pub use alias::{AliasAnalysis, AliasResult, AliasTargets, MemoryAccess, ValueRange};
 pub use constant_propagation::{
     BranchTaken, ConstantPropagationAnalysis, ConstantPropagationResult,
 };
 pub use control_flow::{ControlFlowAnalysis, ControlFlowGraph};
 pub use control_flow_optimizer::{ControlFlowOptimizer, OptimizedControlFlowGraph};
 pub use data_flow::{DataFlowAnalysis, DataFlowGraph};
+pub use dominance::{DominanceAnalysis, DominanceInfo, DominatorTree, NaturalLoop};
 pub use instruction_validation::InstructionValidationAnalysis;
+pub use io_contract::{IoContractAnalysis, IoContractResult, IoCount};
+pub use scheduling::SchedulingAnalysis;
+pub use sections::{Section, SectionAnalysis};
+pub use ssa::{AccumulatorVersions, SsaAnalysis, VersionDef, VersionId};
+pub use strict_ram::StrictRamAnalysis;
+pub use termination::{LoopCounter, NonTermination, TerminationAnalysis, TerminationResult};
+pub use unused_labels::UnusedLabelAnalysis;
@@ -0,0 +1,157 @@
+//! Jump scheduling lints for HIR
+//!
+//! This module provides an analysis that reports jumps which do not change
+//! where execution goes: jumps to the instruction right after them, and
+//! jumps right after a conditional jump that make one of the two redundant.
+//! Dead code after an unconditional jump or `HALT` is reported by the
+//! [`DominanceAnalysis`](crate::analyzers::dominance::DominanceAnalysis), as
+//! it knows whether anything else reaches it.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use hir::body::{Body, ExprKind, Instruction, Literal};
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+use ram_core::JumpCondition;
+
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// The diagnostic code reported for jumps to the next instruction
+pub const JUMP_TO_NEXT_CODE: &str = ram_diagnostics::codes::JUMP_TO_NEXT.code;
+
+/// The diagnostic code reported for jumps that can be merged with the
+/// conditional jump before them
+pub const REDUNDANT_JUMP_CODE: &str = ram_diagnostics::codes::REDUNDANT_JUMP.code;
+
+/// Jump scheduling analysis pass
+///
+/// This pass reports jumps to the next instruction, and pairs of jumps where
+/// a conditional jump is followed by an unconditional jump to the same label
+/// or by a conditional jump with the same condition. The second jump of a
+/// pair is only reported if it has no label, as otherwise other instructions
+/// can jump to it directly. The result is the jumps that can be removed.
+#[derive(Debug, Default)]
+pub struct SchedulingAnalysis;
+
+impl AnalysisPass for SchedulingAnalysis {
+    type Output = Vec<LocalDefId>;
+
+    fn name(&self) -> &'static str {
+        "SchedulingAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "scheduling"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::CODE
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        let body = ctx.body().clone();
+        let instructions = body.instructions.values().collect::<Vec<_>>();
+
+        let label_targets = body
+            .labels
+            .values()
+            .filter_map(|label| Some((label.name.as_str(), label.instruction_id?)))
+            .collect::<HashMap<_, _>>();
+        let is_labeled = |instr_id: LocalDefId| {
+            body.labels.values().any(|label| label.instruction_id == Some(instr_id))
+        };
+
+        // The condition and target of each jump, by program order
+        let jumps = instructions
+            .iter()
+            .map(|instr| {
+                let condition = ctx.instruction_effects(instr).jump_condition()?;
+                let target = target_label(&body, instr)?;
+                Some((condition, *label_targets.get(target.as_str())?))
+            })
+            .collect::<Vec<_>>();
+        let jumps_to_next = |idx: usize| {
+            jumps[idx].is_some_and(|(_, target)| {
+                instructions.get(idx + 1).is_some_and(|next| next.id == target)
+            })
+        };
+
+        let mut removable = Vec::new();
+        for (idx, instr) in instructions.iter().enumerate() {
+            if jumps_to_next(idx) {
+                ctx.add_diagnostic(
+                    ram_diagnostics::Diagnostic::builder()
+                        .with_message("Jump to the next instruction")
+                        .with_help("Remove the jump, execution continues here either way")
+                        .with_primary_span(instr.span.clone(), "jumps to the next instruction")
+                        .with_code(JUMP_TO_NEXT_CODE)
+                        .build_warning(),
+                );
+                removable.push(instr.id);
+                continue;
+            }
+
+            let (Some((first_condition, first_target)), Some(next)) =
+                (jumps[idx], instructions.get(idx + 1))
+            else {
+                continue;
+            };
+            let Some((second_condition, second_target)) = jumps[idx + 1] else {
+                continue;
+            };
+            if first_condition == JumpCondition::Always
+                || is_labeled(next.id)
+                || jumps_to_next(idx + 1)
+            {
+                continue;
+            }
+
+            if second_condition == JumpCondition::Always && second_target == first_target {
+                ctx.add_diagnostic(
+                    ram_diagnostics::Diagnostic::builder()
+                        .with_message("Conditional jump followed by a jump to the same label")
+                        .with_help("Replace both jumps with a single `JUMP` to the label")
+                        .with_primary_span(instr.span.start..next.span.end, "can be a single jump")
+                        .with_code(REDUNDANT_JUMP_CODE)
+                        .build_warning(),
+                );
+                removable.push(instr.id);
+            } else if second_condition == first_condition {
+                ctx.add_diagnostic(
+                    ram_diagnostics::Diagnostic::builder()
+                        .with_message("Jump is never taken")
+                        .with_help("Remove the jump, the one before it has the same condition")
+                        .with_primary_span(next.span.clone(), "never taken")
+                        .with_secondary_span(instr.span.clone(), "always taken instead")
+                        .with_code(REDUNDANT_JUMP_CODE)
+                        .build_warning(),
+                );
+                removable.push(next.id);
+            }
+        }
+
+        Ok(removable)
+    }
+}
+
+/// Get the label the operand of a jump refers to
+fn target_label(body: &Body, instr: &Instruction) -> Option<String> {
+    let mut operand = body.expr(instr.operand?)?;
+    if let ExprKind::MemoryRef(mem_ref) = &operand.kind {
+        operand = body.expr(mem_ref.address)?;
+    }
+    match &operand.kind {
+        ExprKind::LabelRef(label_ref) => {
+            body.label(label_ref.label_id.local_id).map(|label| label.name.clone())
+        }
+        ExprKind::Literal(Literal::Label(name)) => Some(name.clone()),
+        _ => None,
+    }
+}
@@ -0,0 +1,172 @@
+//! Label sections and their metrics
+//!
+//! Under the sections convention, which projects opt into with
+//! `sections = true` in the `[analysis]` section of their manifest, every
+//! label starts a named section of the program that runs up to the next
+//! label, as split by [`AnalysisScope::label_sections`]. This module provides
+//! an analysis that measures each section: how many instructions it has,
+//! which registers it names and which subroutines it calls.
+
+use std::any::TypeId;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use miette::Diagnostic;
+
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+use crate::scope::{AnalysisScope, ScopeId};
+
+/// A section of a program and its metrics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The label starting the section, or `None` for the instructions before
+    /// the first label
+    pub name: Option<String>,
+    /// The indices of the instructions of the section in the body
+    pub instructions: Range<usize>,
+    /// The source span from the label to the end of the last instruction
+    pub span: Range<usize>,
+    /// The registers named by the operands of the section, directly or as
+    /// the pointer of an indirect operand
+    pub registers: BTreeSet<i64>,
+    /// The labels of the subroutines the section calls, each once
+    pub calls: Vec<String>,
+}
+
+impl Section {
+    /// Get the number of instructions of the section
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Describe the metrics of the section, such as
+    /// `4 instructions, registers 1, 2, calls double`
+    pub fn summary(&self) -> String {
+        let count = self.instruction_count();
+        let mut parts = vec![format!("{} instruction{}", count, if count == 1 { "" } else { "s" })];
+        if !self.registers.is_empty() {
+            let registers =
+                self.registers.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+            parts.push(format!("registers {}", registers));
+        }
+        if !self.calls.is_empty() {
+            parts.push(format!("calls {}", self.calls.join(", ")));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Section analysis pass
+///
+/// This pass splits the body into its label sections and measures each of
+/// them. It reports no diagnostics; its results are shown by editors and
+/// reports.
+#[derive(Debug, Default)]
+pub struct SectionAnalysis;
+
+impl AnalysisPass for SectionAnalysis {
+    type Output = Vec<Section>;
+
+    fn name(&self) -> &'static str {
+        "SectionAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "sections"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        // The sections hold the spans of their code
+        BodyParts::ALL
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        let body = ctx.body().clone();
+        let registry = ctx.instruction_registry().clone();
+
+        let sections = AnalysisScope::label_sections(&body)
+            .into_iter()
+            .map(|scope| {
+                let name = match scope.id {
+                    ScopeId::LabelSection(name) => name,
+                    ScopeId::BasicBlock(_) => None,
+                };
+                let instructions =
+                    body.instructions_in(scope.instructions.clone()).unwrap_or_default();
+
+                let start = name
+                    .as_ref()
+                    .and_then(|name| body.labels.values().find(|label| &label.name == name))
+                    .map(|label| label.span.start)
+                    .or_else(|| instructions.first().map(|instr| instr.span.start))
+                    .unwrap_or_default();
+                let end = instructions.last().map_or(start, |instr| instr.span.end);
+
+                let mut registers = BTreeSet::new();
+                let mut calls = Vec::new();
+                for instr in instructions {
+                    let calls_label = registry
+                        .get_by_name_case_insensitive(&instr.opcode)
+                        .is_some_and(|definition| definition.effects().calls());
+                    if calls_label {
+                        calls.extend(target_label(&body, instr));
+                    } else {
+                        registers.extend(named_register(&body, instr));
+                    }
+                }
+                calls.sort();
+                calls.dedup();
+
+                Section {
+                    name,
+                    instructions: scope.instructions,
+                    span: start..end.max(start),
+                    registers,
+                    calls,
+                }
+            })
+            .collect();
+
+        Ok(sections)
+    }
+}
+
+/// Get the register the operand of an instruction names, if it is a constant
+///
+/// Immediate operands name no register, and neither do operands whose
+/// address is computed, such as array accesses.
+fn named_register(body: &Body, instr: &Instruction) -> Option<i64> {
+    let operand = body.expr(instr.operand?)?;
+    let ExprKind::MemoryRef(mem_ref) = &operand.kind else {
+        return None;
+    };
+    if mem_ref.mode == AddressingMode::Immediate {
+        return None;
+    }
+    match &body.expr(mem_ref.address)?.kind {
+        ExprKind::Literal(Literal::Int(address)) => Some(*address),
+        _ => None,
+    }
+}
+
+/// Get the label the operand of an instruction refers to
+fn target_label(body: &Body, instr: &Instruction) -> Option<String> {
+    let mut operand = body.expr(instr.operand?)?;
+    if let ExprKind::MemoryRef(mem_ref) = &operand.kind {
+        operand = body.expr(mem_ref.address)?;
+    }
+    match &operand.kind {
+        ExprKind::LabelRef(label_ref) => {
+            body.label(label_ref.label_id.local_id).map(|label| label.name.clone())
+        }
+        ExprKind::Literal(Literal::Label(name)) => Some(name.clone()),
+        _ => None,
+    }
+}
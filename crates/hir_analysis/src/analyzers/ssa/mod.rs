@@ -0,0 +1,396 @@
+//! SSA numbering of the accumulator for HIR
+//!
+//! This module numbers the values of the accumulator in the style of static
+//! single assignment form. Every instruction that writes the accumulator
+//! defines a new version of it, and the versions reaching a node of the
+//! control flow graph from different predecessors are joined by a phi before
+//! it. Each instruction then reads exactly one version of the accumulator,
+//! which tells where the value it reads was defined.
+
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+use petgraph::graph::NodeIndex;
+use ram_core::{AccumulatorValue, InstructionEffects};
+
+use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph};
+use crate::analyzers::dominance::{DominanceAnalysis, DominanceInfo};
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// SSA numbering pass
+///
+/// This pass numbers the versions of the accumulator and reports loads that
+/// leave the accumulator unchanged, as it already holds the loaded value.
+#[derive(Default)]
+pub struct SsaAnalysis;
+
+impl AnalysisPass for SsaAnalysis {
+    type Output = AccumulatorVersions;
+
+    fn name(&self) -> &'static str {
+        "SsaAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "ssa"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<ControlFlowAnalysis>(), TypeId::of::<DominanceAnalysis>()]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::INSTRUCTIONS.union(BodyParts::EXPRS)
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        // Get the control flow graph
+        let cfg = match ctx.get_result::<ControlFlowAnalysis>() {
+            Ok(cfg) => cfg.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        // Get the dominator trees
+        let dominance = match ctx.get_result::<DominanceAnalysis>() {
+            Ok(dominance) => dominance.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let body = ctx.body().clone();
+        let effects = body
+            .instructions
+            .values()
+            .map(|instr| (instr.id, ctx.instruction_effects(instr)))
+            .collect::<HashMap<_, _>>();
+
+        let versions = AccumulatorVersions::new(&body, &cfg, &dominance, &effects);
+        report_redundant_loads(ctx, &body, &cfg, &dominance, &effects, &versions);
+
+        Ok(versions)
+    }
+}
+
+/// The number of a version of the accumulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VersionId(pub u32);
+
+impl fmt::Display for VersionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "acc{}", self.0)
+    }
+}
+
+/// Where a version of the accumulator is defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionDef {
+    /// The value the accumulator holds when the program starts
+    Entry,
+    /// The value an instruction writes to the accumulator
+    Instruction(LocalDefId),
+    /// The join of the versions reaching an instruction from its predecessors
+    Phi {
+        /// The instruction the versions are joined before
+        instruction: LocalDefId,
+        /// The joined versions, in order
+        inputs: Vec<VersionId>,
+    },
+}
+
+/// The versions of the accumulator of a body
+///
+/// Unreachable instructions neither define nor read any version.
+#[derive(Debug, Clone, Default)]
+pub struct AccumulatorVersions {
+    /// The definitions of the versions, indexed by their numbers
+    definitions: Vec<VersionDef>,
+    /// Map from instruction IDs to the version of the accumulator before them
+    before: HashMap<LocalDefId, VersionId>,
+    /// Map from instruction IDs to the version they define
+    defined: HashMap<LocalDefId, VersionId>,
+    /// The instructions that read the accumulator
+    readers: HashSet<LocalDefId>,
+}
+
+impl AccumulatorVersions {
+    /// Number the versions of the accumulator of a body
+    ///
+    /// The versions are numbered in program order, with the entry version
+    /// first and each phi before the version its instruction defines. Phis
+    /// are placed on the iterated dominance frontiers of the definitions.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The body to number the versions of.
+    /// * `cfg` - The control flow graph of the body.
+    /// * `dominance` - The dominator trees of the graph.
+    /// * `effects` - Map from instruction IDs to their effects.
+    pub fn new(
+        body: &Body,
+        cfg: &ControlFlowGraph,
+        dominance: &DominanceInfo,
+        effects: &HashMap<LocalDefId, InstructionEffects>,
+    ) -> Self {
+        let Some(entry) = cfg.entry_node() else {
+            return Self::default();
+        };
+        let instruction_at = |node_idx: NodeIndex| cfg.get_node(node_idx).instruction_id;
+        let writes_accumulator = |instr_id: LocalDefId| {
+            effects.get(&instr_id).is_some_and(|effects| effects.accumulator_value().is_some())
+        };
+
+        // Place the phis on the iterated dominance frontiers of the nodes
+        // that define a version, the entry included
+        let mut worklist = cfg
+            .node_indices()
+            .into_iter()
+            .filter(|&node_idx| dominance.is_reachable(node_idx))
+            .filter(|&node_idx| instruction_at(node_idx).is_some_and(writes_accumulator))
+            .chain([entry])
+            .collect::<Vec<_>>();
+        let mut visited = worklist.iter().copied().collect::<HashSet<_>>();
+        let mut phis = HashSet::new();
+        while let Some(node_idx) = worklist.pop() {
+            for frontier in dominance.dominance_frontier(node_idx) {
+                if phis.insert(frontier) && visited.insert(frontier) {
+                    worklist.push(frontier);
+                }
+            }
+        }
+
+        // Number the versions in program order
+        let mut versions = Self { definitions: vec![VersionDef::Entry], ..Self::default() };
+        let mut phi_versions = HashMap::new();
+        for instr in body.instructions.values() {
+            let Some(node_idx) = cfg.get_node_by_instruction(instr.id) else {
+                continue;
+            };
+            if !dominance.is_reachable(node_idx) {
+                continue;
+            }
+            if phis.contains(&node_idx) {
+                let phi = VersionDef::Phi { instruction: instr.id, inputs: Vec::new() };
+                phi_versions.insert(node_idx, versions.push(phi));
+            }
+            if writes_accumulator(instr.id) {
+                let version = versions.push(VersionDef::Instruction(instr.id));
+                versions.defined.insert(instr.id, version);
+            }
+            if effects.get(&instr.id).is_some_and(InstructionEffects::reads_accumulator) {
+                versions.readers.insert(instr.id);
+            }
+        }
+
+        // Rename along the dominator tree, where the version after a node is
+        // the version before each node it immediately dominates
+        let entry_version = VersionId(0);
+        if let Some(&phi) = phi_versions.get(&entry) {
+            versions.add_phi_input(phi, entry_version);
+        }
+        let mut stack = vec![(entry, entry_version)];
+        while let Some((node_idx, incoming)) = stack.pop() {
+            let mut current = phi_versions.get(&node_idx).copied().unwrap_or(incoming);
+            if let Some(instr_id) = instruction_at(node_idx) {
+                versions.before.insert(instr_id, current);
+                if let Some(&version) = versions.defined.get(&instr_id) {
+                    current = version;
+                }
+            }
+
+            for succ in cfg.get_successors(node_idx) {
+                if let Some(&phi) = phi_versions.get(&succ) {
+                    versions.add_phi_input(phi, current);
+                }
+            }
+            for &child in dominance.dominators.children(node_idx) {
+                stack.push((child, current));
+            }
+        }
+        versions
+    }
+
+    /// Add a version and get its number
+    fn push(&mut self, definition: VersionDef) -> VersionId {
+        self.definitions.push(definition);
+        VersionId(self.definitions.len() as u32 - 1)
+    }
+
+    /// Add an input to a phi, unless it already joins it
+    fn add_phi_input(&mut self, phi: VersionId, input: VersionId) {
+        if let VersionDef::Phi { inputs, .. } = &mut self.definitions[phi.0 as usize]
+            && !inputs.contains(&input)
+        {
+            inputs.push(input);
+            inputs.sort();
+        }
+    }
+
+    /// Get the number of versions
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    /// Check whether there are no versions, as the body has no instructions
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    /// Get the versions and their definitions, in order
+    pub fn versions(&self) -> impl Iterator<Item = (VersionId, &VersionDef)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .map(|(index, definition)| (VersionId(index as u32), definition))
+    }
+
+    /// Get the definition of a version
+    pub fn definition(&self, version: VersionId) -> Option<&VersionDef> {
+        self.definitions.get(version.0 as usize)
+    }
+
+    /// Get the version of the accumulator before an instruction
+    pub fn version_before(&self, instr_id: LocalDefId) -> Option<VersionId> {
+        self.before.get(&instr_id).copied()
+    }
+
+    /// Get the version of the accumulator after an instruction
+    pub fn version_after(&self, instr_id: LocalDefId) -> Option<VersionId> {
+        self.defined_by(instr_id).or_else(|| self.version_before(instr_id))
+    }
+
+    /// Get the version an instruction defines, if it writes the accumulator
+    pub fn defined_by(&self, instr_id: LocalDefId) -> Option<VersionId> {
+        self.defined.get(&instr_id).copied()
+    }
+
+    /// Get the version an instruction reads, if it reads the accumulator
+    pub fn version_read(&self, instr_id: LocalDefId) -> Option<VersionId> {
+        self.version_before(instr_id).filter(|_| self.readers.contains(&instr_id))
+    }
+
+    /// Get the definitions a version can hold the value of
+    ///
+    /// Phis are looked through, so only the entry and instruction versions
+    /// are returned, in order.
+    pub fn reaching_definitions(&self, version: VersionId) -> Vec<VersionId> {
+        let mut reaching = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![version];
+        while let Some(version) = stack.pop() {
+            if !visited.insert(version) {
+                continue;
+            }
+            match self.definition(version) {
+                Some(VersionDef::Phi { inputs, .. }) => stack.extend(inputs),
+                Some(_) => reaching.push(version),
+                None => {}
+            }
+        }
+        reaching.sort();
+        reaching
+    }
+}
+
+/// Report the loads of a register whose value the accumulator already holds
+///
+/// The accumulator holds the value of a register after loading or storing
+/// it, until either is written again. Writes through indirect operands may
+/// write any register, so they end it as well.
+fn report_redundant_loads(
+    ctx: &mut AnalysisContext,
+    body: &Body,
+    cfg: &ControlFlowGraph,
+    dominance: &DominanceInfo,
+    effects: &HashMap<LocalDefId, InstructionEffects>,
+    versions: &AccumulatorVersions,
+) {
+    let direct_register = |instr: &Instruction| {
+        let expr = body.expr(instr.operand?)?;
+        let ExprKind::MemoryRef(mem_ref) = &expr.kind else {
+            return None;
+        };
+        let address = body.expr(mem_ref.address)?;
+        match (&mem_ref.mode, &address.kind) {
+            (AddressingMode::Direct, ExprKind::Literal(Literal::Int(register))) => Some(*register),
+            _ => None,
+        }
+    };
+    let dominates = |dominator: LocalDefId, instr_id: LocalDefId| match (
+        cfg.get_node_by_instruction(dominator),
+        cfg.get_node_by_instruction(instr_id),
+    ) {
+        (Some(dominator), Some(node_idx)) => dominance.dominates(dominator, node_idx),
+        _ => false,
+    };
+
+    // Group the instructions by the version of the accumulator before them
+    let mut live_ranges: HashMap<VersionId, Vec<&Instruction>> = HashMap::new();
+    for instr in body.instructions.values() {
+        if let Some(version) = versions.version_before(instr.id) {
+            live_ranges.entry(version).or_default().push(instr);
+        }
+    }
+
+    for instr in body.instructions.values() {
+        let instr_effects = &effects[&instr.id];
+        if instr_effects.accumulator_value() != Some(AccumulatorValue::Operand) {
+            continue;
+        }
+        let (Some(register), Some(version)) =
+            (direct_register(instr), versions.version_before(instr.id))
+        else {
+            continue;
+        };
+
+        // The value of the register is tied to the accumulator by loading
+        // it, or by storing the accumulator to it before the load
+        let loaded = match versions.definition(version) {
+            Some(VersionDef::Instruction(def_id)) => {
+                body.instructions.values().find(|def| def.id == *def_id).filter(|def| {
+                    effects[&def.id].accumulator_value() == Some(AccumulatorValue::Operand)
+                        && direct_register(def) == Some(register)
+                })
+            }
+            _ => None,
+        };
+        let live_range = live_ranges.get(&version).map(Vec::as_slice).unwrap_or_default();
+        let stored = live_range.iter().copied().find(|store| {
+            store.id != instr.id
+                && effects[&store.id].reads_accumulator()
+                && effects[&store.id].writes_operand()
+                && direct_register(store) == Some(register)
+                && dominates(store.id, instr.id)
+        });
+        let (tie, tie_label) = match (loaded, stored) {
+            (Some(load), _) => (load, "the register is already loaded here"),
+            (None, Some(store)) => (store, "the accumulator is stored to the register here"),
+            (None, None) => continue,
+        };
+
+        // Nothing else may write the register while the version is live
+        let clobbered = live_range.iter().any(|other| {
+            other.id != tie.id
+                && effects[&other.id].writes_operand()
+                && direct_register(other).is_none_or(|written| written == register)
+        });
+        if clobbered {
+            continue;
+        }
+
+        ctx.add_diagnostic(
+            ram_diagnostics::Diagnostic::builder()
+                .with_message("Redundant load")
+                .with_help(format!(
+                    "The accumulator already holds the value of register {register}"
+                ))
+                .with_primary_span(instr.span.clone(), "this load does not change the accumulator")
+                .with_secondary_span(tie.span.clone(), tie_label)
+                .build_advice(),
+        );
+    }
+}
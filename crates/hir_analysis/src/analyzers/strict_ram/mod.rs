@@ -0,0 +1,197 @@
+//! Strict mode with classic RAM semantics
+//!
+//! This module provides an analysis that holds programs to the textbook
+//! definition of the Random Access Machine of Cook and Reckhow. It is only
+//! registered when strict mode is enabled, and reports every construct the
+//! classic model does not have as an error.
+
+use std::any::TypeId;
+
+use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use hir::expr::ExprId;
+use miette::Diagnostic;
+use ram_core::OperandKind;
+
+use crate::analyzers::instruction_validation::operand_kind;
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// The diagnostic code reported for programs outside the classic RAM model
+pub const STRICT_RAM_VIOLATION_CODE: &str = ram_diagnostics::codes::STRICT_RAM_VIOLATION.code;
+
+/// Strict RAM analysis pass
+///
+/// This pass reports jumps with immediate operands, labels standing for
+/// registers, negative addresses and programs without a `HALT` instruction.
+/// Instructions unknown to the registry are left to the instruction validation.
+#[derive(Debug, Default)]
+pub struct StrictRamAnalysis;
+
+impl AnalysisPass for StrictRamAnalysis {
+    type Output = ();
+
+    fn name(&self) -> &'static str {
+        "StrictRamAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "strict-ram"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::INSTRUCTIONS.union(BodyParts::EXPRS)
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        let body = ctx.body().clone();
+        let registry = ctx.instruction_registry().clone();
+
+        let mut halts = false;
+        for instr in body.instructions.values() {
+            let Some(definition) = registry.get_by_name_case_insensitive(&instr.opcode) else {
+                continue;
+            };
+            let effects = definition.effects();
+            halts |= effects.halts();
+
+            let Some(operand_id) = instr.operand else {
+                continue;
+            };
+            if effects.jump_condition().is_some() || effects.calls() {
+                self.check_jump_target(ctx, &body, instr, operand_id);
+            } else {
+                self.check_register_address(ctx, &body, instr, operand_id);
+            }
+            self.check_negative_address(ctx, &body, operand_id);
+        }
+
+        if !halts {
+            let span = body
+                .instructions
+                .values()
+                .next_back()
+                .map(|instr| instr.span.clone())
+                .unwrap_or(0..0);
+            report(
+                ctx,
+                "Program has no HALT instruction",
+                span,
+                "the program ends here",
+                "Every program of the classic RAM model stops with `HALT`",
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl StrictRamAnalysis {
+    /// Report jumps to immediate operands
+    fn check_jump_target(
+        &self,
+        ctx: &mut AnalysisContext,
+        body: &Body,
+        instr: &Instruction,
+        operand_id: ExprId,
+    ) {
+        if operand_kind(body, operand_id) == Some(OperandKind::Immediate) {
+            let span = ctx.get_expr_span(operand_id);
+            report(
+                ctx,
+                format!("'{}' jumps to an immediate operand", instr.opcode.to_uppercase()),
+                span,
+                "immediate jump target",
+                "Jump to a label or an instruction number",
+            );
+        }
+    }
+
+    /// Report labels that stand for registers or values instead of jump targets
+    fn check_register_address(
+        &self,
+        ctx: &mut AnalysisContext,
+        body: &Body,
+        instr: &Instruction,
+        operand_id: ExprId,
+    ) {
+        let Some(operand) = body.expr(operand_id) else {
+            return;
+        };
+        let target = match &operand.kind {
+            ExprKind::MemoryRef(mem_ref) => body.expr(mem_ref.address),
+            _ => Some(operand),
+        };
+        if target.is_some_and(|target| is_label(&target.kind)) {
+            report(
+                ctx,
+                format!("'{}' uses a label as a register", instr.opcode.to_uppercase()),
+                operand.span.clone(),
+                "label used as a register",
+                "Registers are numbered, with register 0 the accumulator; labels are only jump targets",
+            );
+        }
+    }
+
+    /// Report negative addresses, including the base and index of array accesses
+    fn check_negative_address(&self, ctx: &mut AnalysisContext, body: &Body, operand_id: ExprId) {
+        let Some(ExprKind::MemoryRef(mem_ref)) = body.expr(operand_id).map(|expr| &expr.kind)
+        else {
+            return;
+        };
+
+        let mut addresses = vec![mem_ref.address];
+        if let Some(ExprKind::ArrayAccess(access)) =
+            body.expr(mem_ref.address).map(|expr| &expr.kind)
+        {
+            addresses = vec![access.array, access.index];
+        }
+
+        let negative =
+            addresses.iter().find_map(|address| match body.expr(address).map(|expr| &expr.kind) {
+                Some(ExprKind::Literal(Literal::Int(value))) if *value < 0 => Some(*value),
+                _ => None,
+            });
+        if let Some(value) = negative {
+            let span = ctx.get_expr_span(operand_id);
+            let what = match mem_ref.mode {
+                AddressingMode::Indirect => "indirect address",
+                _ => "address",
+            };
+            report(
+                ctx,
+                format!("Negative {} {}", what, value),
+                span,
+                "negative address",
+                "Registers are numbered from 0",
+            );
+        }
+    }
+}
+
+/// Returns `true` if the expression refers to a label
+fn is_label(kind: &ExprKind) -> bool {
+    matches!(kind, ExprKind::LabelRef(_) | ExprKind::Literal(Literal::Label(_)))
+}
+
+/// Report a construct the classic RAM model does not have
+fn report(
+    ctx: &mut AnalysisContext,
+    message: impl Into<String>,
+    span: std::ops::Range<usize>,
+    label: &str,
+    help: &str,
+) {
+    ctx.add_diagnostic(
+        ram_diagnostics::Diagnostic::builder()
+            .with_message(message)
+            .with_primary_span(span, label)
+            .with_help(help)
+            .with_code(STRICT_RAM_VIOLATION_CODE)
+            .build_error(),
+    );
+}
@@ -0,0 +1,643 @@
+//! Termination checking for HIR
+//!
+//! This module provides an analysis that tries to prove a program halts on
+//! every input. A program without cycles in its control flow graph always
+//! halts. Otherwise every cycle must be a natural loop with a counter: a
+//! register that the loop tests against a bound on every iteration, and
+//! that moves by the same constant step towards its exit on every iteration.
+//!
+//! The test of a loop is a conditional jump out of it that runs on every
+//! iteration, whose accumulator is the counter plus registers the loop never
+//! writes, such as constants or values read before it. A counter stepping
+//! up leaves a loop that exits once the accumulator is positive, and one
+//! stepping down a loop that exits once it is not. Tests for zero are only
+//! left for sure when the range of the accumulator at the test, as bounded
+//! by the [`AliasAnalysis`], shows the counter can not step past zero.
+//!
+//! Programs that may not halt are reported with the loops, cycles and
+//! recursive subroutines that no counter bounds.
+
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use hir::body::{AddressingMode, Body, ExprKind, Instruction, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+use petgraph::graph::{DiGraph, NodeIndex};
+use ram_core::{AccumulatorValue, Effect, InstructionEffects, JumpCondition};
+
+use crate::analyzers::alias::{AliasAnalysis, AliasResult, AliasTargets};
+use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph, EdgeKind};
+use crate::analyzers::dominance::{DominanceAnalysis, DominanceInfo, NaturalLoop};
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// The diagnostic code reported for the loops, cycles and subroutines that
+/// may keep a program from halting
+pub const MAY_NOT_HALT_CODE: &str = ram_diagnostics::codes::MAY_NOT_HALT.code;
+
+/// Termination analysis pass
+///
+/// This pass proves that a program halts when its control flow graph has no
+/// cycles, or when each of its loops has a counter that moves towards the
+/// exit of the loop. It reports that the program is guaranteed to halt, or
+/// warns about each loop, cycle and recursive subroutine that may keep it
+/// from halting.
+#[derive(Debug, Default)]
+pub struct TerminationAnalysis;
+
+impl AnalysisPass for TerminationAnalysis {
+    type Output = TerminationResult;
+
+    fn name(&self) -> &'static str {
+        "TerminationAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "termination"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![
+            TypeId::of::<ControlFlowAnalysis>(),
+            TypeId::of::<DominanceAnalysis>(),
+            TypeId::of::<AliasAnalysis>(),
+        ]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::CODE
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        let cfg = match ctx.get_result::<ControlFlowAnalysis>() {
+            Ok(cfg) => cfg.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        let info = match ctx.get_result::<DominanceAnalysis>() {
+            Ok(info) => info.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        let aliases = match ctx.get_result::<AliasAnalysis>() {
+            Ok(aliases) => aliases.clone(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let body = ctx.body().clone();
+        let effects = body
+            .instructions
+            .values()
+            .map(|instr| (instr.id, ctx.instruction_effects(instr)))
+            .collect::<HashMap<_, _>>();
+
+        let checker = TerminationChecker::new(&body, &cfg, &info, &aliases, &effects);
+        let result = checker.check();
+        report(ctx, &body, &cfg, &info, &result);
+        Ok(result)
+    }
+}
+
+/// A register proving that a loop terminates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopCounter {
+    /// The header of the loop
+    pub header: NodeIndex,
+    /// The conditional jump out of the loop that tests the counter
+    pub test: NodeIndex,
+    /// The register of the counter
+    pub register: i64,
+    /// How much the counter changes on every iteration
+    pub step: i64,
+}
+
+/// Something that may keep a program from halting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NonTermination {
+    /// A natural loop without a counter, by its header
+    Loop(NodeIndex),
+    /// A cycle that is not a natural loop, as it can be entered at more than
+    /// one node, with its nodes in order
+    Cycle(Vec<NodeIndex>),
+    /// A group of subroutines that call each other, by their names
+    Recursion(Vec<String>),
+}
+
+/// The result of termination analysis
+#[derive(Debug, Clone, Default)]
+pub struct TerminationResult {
+    /// The counters of the loops that terminate, ordered by their headers
+    pub counters: Vec<LoopCounter>,
+    /// What may keep the program from halting, with loops first, then cycles
+    /// and then recursive subroutines
+    pub reasons: Vec<NonTermination>,
+}
+
+impl TerminationResult {
+    /// Check whether the program is guaranteed to halt
+    pub fn halts(&self) -> bool {
+        self.reasons.is_empty()
+    }
+
+    /// Get the counter of the loop with the given header, if it terminates
+    pub fn counter(&self, header: NodeIndex) -> Option<&LoopCounter> {
+        self.counters.iter().find(|counter| counter.header == header)
+    }
+}
+
+/// Report the verdict of the analysis
+fn report(
+    ctx: &mut AnalysisContext,
+    body: &Body,
+    cfg: &ControlFlowGraph,
+    info: &DominanceInfo,
+    result: &TerminationResult,
+) {
+    let instruction_span = |ctx: &AnalysisContext, node_idx: NodeIndex| {
+        cfg.get_node(node_idx).instruction_id.map(|instr_id| ctx.get_instruction_span(instr_id))
+    };
+
+    if result.halts() {
+        let help = match result.counters.len() {
+            0 => "The program has no loops, so it runs every instruction at most once".to_string(),
+            count => format!(
+                "Each of its {} loop{} has a counter that moves towards the exit of the loop",
+                count,
+                if count == 1 { "" } else { "s" }
+            ),
+        };
+        if let Some(entry) = body.instructions.values().next() {
+            ctx.info_at_instruction("Program is guaranteed to halt", help, entry.id);
+        }
+        return;
+    }
+
+    for reason in &result.reasons {
+        let diagnostic = match reason {
+            NonTermination::Loop(header) => {
+                // Loops that can never be left are reported as infinite loops
+                let natural_loop =
+                    info.loops.iter().find(|natural_loop| natural_loop.header == *header);
+                let Some(natural_loop) = natural_loop else {
+                    continue;
+                };
+                let Some(header_span) = instruction_span(ctx, *header) else {
+                    continue;
+                };
+                if natural_loop.is_infinite() {
+                    continue;
+                }
+                let mut builder = ram_diagnostics::Diagnostic::builder()
+                    .with_message("Loop may not halt")
+                    .with_help(
+                        "No register of this loop is tested on every iteration and moves \
+                         towards its exit by the same step on every iteration",
+                    )
+                    .with_primary_span(header_span, "the loop starts here");
+                for &latch in &natural_loop.latches {
+                    if latch != *header
+                        && let Some(latch_span) = instruction_span(ctx, latch)
+                    {
+                        builder = builder.with_secondary_span(latch_span, "and jumps back here");
+                    }
+                }
+                builder
+            }
+            NonTermination::Cycle(nodes) => {
+                let spans = nodes
+                    .iter()
+                    .filter_map(|&node_idx| instruction_span(ctx, node_idx))
+                    .collect::<Vec<_>>();
+                let (Some(start), Some(end)) = (
+                    spans.iter().map(|span| span.start).min(),
+                    spans.iter().map(|span| span.end).max(),
+                ) else {
+                    continue;
+                };
+                ram_diagnostics::Diagnostic::builder()
+                    .with_message("Cycle may not halt")
+                    .with_help(
+                        "The cycle can be entered at more than one instruction, so it has no \
+                         single test to bound",
+                    )
+                    .with_primary_span(start..end, "the cycle spans these instructions")
+            }
+            NonTermination::Recursion(names) => {
+                let Some(label) = body.labels.values().find(|label| label.name == names[0]) else {
+                    continue;
+                };
+                ram_diagnostics::Diagnostic::builder()
+                    .with_message(format!("Recursive subroutine '{}' may not halt", names[0]))
+                    .with_help("The depth of the recursion can not be bounded")
+                    .with_primary_span(ctx.get_label_span(label.id), "the subroutine starts here")
+            }
+        };
+        ctx.add_diagnostic(diagnostic.with_code(MAY_NOT_HALT_CODE).build_warning());
+    }
+}
+
+/// The accumulator as a sum of registers and a constant
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Linear {
+    /// Map from registers to their coefficients, without zero coefficients
+    terms: BTreeMap<i64, i64>,
+    /// The constant added to the registers
+    constant: i64,
+}
+
+impl Linear {
+    /// The value of a register
+    fn register(register: i64) -> Self {
+        Self { terms: [(register, 1)].into(), constant: 0 }
+    }
+
+    /// A constant value
+    fn constant(constant: i64) -> Self {
+        Self { terms: BTreeMap::new(), constant }
+    }
+
+    /// Add `sign` times `other` to the sum
+    ///
+    /// Returns `None` on overflow.
+    fn add(mut self, other: &Self, sign: i64) -> Option<Self> {
+        for (&register, &coefficient) in &other.terms {
+            let term = self.terms.entry(register).or_default();
+            *term = term.checked_add(coefficient.checked_mul(sign)?)?;
+            if *term == 0 {
+                self.terms.remove(&register);
+            }
+        }
+        self.constant = self.constant.checked_add(other.constant.checked_mul(sign)?)?;
+        Some(self)
+    }
+}
+
+/// Checker of the termination of a program
+struct TerminationChecker<'a> {
+    /// The HIR body being analyzed
+    body: &'a Body,
+    /// The control flow graph
+    cfg: &'a ControlFlowGraph,
+    /// The dominance information of the control flow graph
+    info: &'a DominanceInfo,
+    /// The targets of indirect and indexed operands and the register ranges
+    aliases: &'a AliasResult,
+    /// Map from instruction IDs to their effects
+    effects: &'a HashMap<LocalDefId, InstructionEffects>,
+    /// Map from instruction IDs to the instructions
+    instructions: HashMap<LocalDefId, &'a Instruction>,
+}
+
+impl<'a> TerminationChecker<'a> {
+    /// Create a new termination checker
+    fn new(
+        body: &'a Body,
+        cfg: &'a ControlFlowGraph,
+        info: &'a DominanceInfo,
+        aliases: &'a AliasResult,
+        effects: &'a HashMap<LocalDefId, InstructionEffects>,
+    ) -> Self {
+        let instructions = body.instructions.values().map(|instr| (instr.id, instr)).collect();
+        Self { body, cfg, info, aliases, effects, instructions }
+    }
+
+    /// Look for a counter of every loop, and for the cycles and recursion
+    /// that no counter can bound
+    fn check(&self) -> TerminationResult {
+        let mut result = TerminationResult::default();
+
+        for natural_loop in &self.info.loops {
+            // Recursion is reported once for its subroutines
+            if self.is_recursive_call_loop(natural_loop) {
+                continue;
+            }
+            match self.find_counter(natural_loop) {
+                Some(counter) => result.counters.push(counter),
+                None => result.reasons.push(NonTermination::Loop(natural_loop.header)),
+            }
+        }
+
+        result.reasons.extend(self.irreducible_cycles().into_iter().map(NonTermination::Cycle));
+        result.reasons.extend(self.cfg.call_graph().recursive_groups().into_iter().map(|group| {
+            NonTermination::Recursion(group.into_iter().map(str::to_string).collect())
+        }));
+        result
+    }
+
+    /// Check whether a loop only jumps back to its header by calling it
+    fn is_recursive_call_loop(&self, natural_loop: &NaturalLoop) -> bool {
+        natural_loop.latches.iter().all(|&latch| {
+            self.cfg
+                .get_outgoing_edges(latch)
+                .into_iter()
+                .filter(|&(succ, _)| succ == natural_loop.header)
+                .all(|(_, edge)| edge == EdgeKind::Call)
+        })
+    }
+
+    /// Find the cycles of reachable nodes that no natural loop explains
+    ///
+    /// The back edges of the natural loops are left out, and so are returns,
+    /// which are replaced by an edge from each call to the instruction after
+    /// it. Recursion shows up as cycles through calls, and is left to the
+    /// call graph.
+    fn irreducible_cycles(&self) -> Vec<Vec<NodeIndex>> {
+        let back_edges = self
+            .info
+            .loops
+            .iter()
+            .flat_map(|natural_loop| {
+                natural_loop.latches.iter().map(|&latch| (latch, natural_loop.header))
+            })
+            .collect::<HashSet<_>>();
+        let reachable = |node_idx: NodeIndex| self.info.is_reachable(node_idx);
+
+        let mut graph = DiGraph::<NodeIndex, ()>::new();
+        let nodes = self
+            .cfg
+            .node_indices()
+            .into_iter()
+            .filter(|&node_idx| reachable(node_idx))
+            .map(|node_idx| (node_idx, graph.add_node(node_idx)))
+            .collect::<HashMap<_, _>>();
+        for (&node_idx, &source) in &nodes {
+            for (succ, edge) in self.cfg.get_outgoing_edges(node_idx) {
+                if matches!(edge, EdgeKind::Call | EdgeKind::Return)
+                    || back_edges.contains(&(node_idx, succ))
+                {
+                    continue;
+                }
+                if let Some(&target) = nodes.get(&succ) {
+                    graph.add_edge(source, target, ());
+                }
+            }
+        }
+        for (call, return_site) in self.cfg.call_sites() {
+            if let (Some(&source), Some(&target)) =
+                (nodes.get(call), return_site.and_then(|site| nodes.get(&site)))
+            {
+                graph.add_edge(source, target, ());
+            }
+        }
+
+        let mut cycles = petgraph::algo::tarjan_scc(&graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || graph.contains_edge(scc[0], scc[0]))
+            .map(|scc| {
+                let mut cycle = scc.into_iter().map(|idx| graph[idx]).collect::<Vec<_>>();
+                cycle.sort();
+                cycle
+            })
+            // Cycles that can never be left are reported as infinite loops
+            .filter(|cycle| {
+                cycle.iter().any(|&node_idx| {
+                    self.cfg.get_successors(node_idx).iter().any(|succ| !cycle.contains(succ))
+                })
+            })
+            .collect::<Vec<_>>();
+        cycles.sort();
+        cycles
+    }
+
+    /// Find a counter that bounds the iterations of a loop
+    fn find_counter(&self, natural_loop: &NaturalLoop) -> Option<LoopCounter> {
+        let mut tests = natural_loop
+            .nodes
+            .iter()
+            .copied()
+            .filter(|&node_idx| self.runs_once_per_iteration(natural_loop, node_idx))
+            .collect::<Vec<_>>();
+        tests.sort();
+        tests.into_iter().find_map(|test| self.counter_at_test(natural_loop, test))
+    }
+
+    /// Check whether the counter tested by a conditional jump bounds a loop
+    fn counter_at_test(&self, natural_loop: &NaturalLoop, test: NodeIndex) -> Option<LoopCounter> {
+        let instr_id = self.cfg.get_node(test).instruction_id?;
+        let condition = self.effects.get(&instr_id)?.jump_condition()?;
+
+        // The loop must be left along exactly one of the edges of the test
+        let mut exit_on_taken = None;
+        for (succ, edge) in self.cfg.get_outgoing_edges(test) {
+            let taken = match edge {
+                EdgeKind::ConditionalTrue => true,
+                EdgeKind::ConditionalFalse => false,
+                _ => return None,
+            };
+            if !natural_loop.nodes.contains(&succ) {
+                if exit_on_taken.is_some() {
+                    return None;
+                }
+                exit_on_taken = Some(taken);
+            }
+        }
+        let exit_on_taken = exit_on_taken?;
+
+        // The accumulator is the counter plus registers the loop never writes
+        let tested = self.accumulator_before(test)?;
+        let written = tested
+            .terms
+            .keys()
+            .copied()
+            .filter(|&register| self.loop_writes(natural_loop, register))
+            .collect::<Vec<_>>();
+        let [register] = written[..] else {
+            return None;
+        };
+        let step = self.counter_step(natural_loop, register)?;
+        let change = tested.terms[&register].checked_mul(step)?;
+
+        let towards_exit = match (condition, exit_on_taken) {
+            (JumpCondition::Positive, true) => change > 0,
+            (JumpCondition::Positive, false) => change < 0,
+            // A change from zero leaves a loop that runs while it is zero
+            (JumpCondition::Zero, false) => true,
+            (JumpCondition::Zero, true) | (JumpCondition::Always, _) => false,
+        };
+        // A value that keeps changing can only stay within a bounded range
+        // for so many iterations
+        let bounded = self
+            .aliases
+            .register_range(instr_id, 0)
+            .is_some_and(|range| range.min > i64::MIN && range.max < i64::MAX);
+
+        (towards_exit || bounded).then_some(LoopCounter {
+            header: natural_loop.header,
+            test,
+            register,
+            step,
+        })
+    }
+
+    /// Get how much the only write of a loop to a register changes it
+    ///
+    /// The write must copy the accumulator holding the register plus a
+    /// constant, and run once on every iteration.
+    fn counter_step(&self, natural_loop: &NaturalLoop, register: i64) -> Option<i64> {
+        let mut writes = natural_loop
+            .nodes
+            .iter()
+            .copied()
+            .filter(|&node_idx| self.writes_register(node_idx, register));
+        let (Some(write), None) = (writes.next(), writes.next()) else {
+            return None;
+        };
+        if !self.runs_once_per_iteration(natural_loop, write) {
+            return None;
+        }
+
+        let instr = self.instruction(write)?;
+        let effects = self.effects.get(&instr.id)?;
+        if !self.copies_accumulator(effects)
+            || instr.operand.and_then(|operand| self.direct_register(operand)) != Some(register)
+        {
+            return None;
+        }
+        let value = self.accumulator_before(write)?;
+        (value.terms == Linear::register(register).terms && value.constant != 0)
+            .then_some(value.constant)
+    }
+
+    /// Check whether a node of a loop runs exactly once on every iteration
+    ///
+    /// The node must run before every jump back to the header, and must not
+    /// be part of a loop nested in it.
+    fn runs_once_per_iteration(&self, natural_loop: &NaturalLoop, node_idx: NodeIndex) -> bool {
+        let dominates_latches =
+            natural_loop.latches.iter().all(|&latch| self.info.dominates(node_idx, latch));
+        let nested = self.info.loops.iter().any(|inner| {
+            inner.header != natural_loop.header
+                && natural_loop.nodes.contains(&inner.header)
+                && inner.nodes.contains(&node_idx)
+        });
+        dominates_latches && !nested
+    }
+
+    /// Check whether any instruction of a loop may write a register
+    fn loop_writes(&self, natural_loop: &NaturalLoop, register: i64) -> bool {
+        natural_loop.nodes.iter().any(|&node_idx| self.writes_register(node_idx, register))
+    }
+
+    /// Check whether the instruction at a node may write a register
+    fn writes_register(&self, node_idx: NodeIndex, register: i64) -> bool {
+        let Some(instr) = self.instruction(node_idx) else {
+            return false;
+        };
+        if !self.effects.get(&instr.id).is_some_and(InstructionEffects::writes_operand) {
+            return false;
+        }
+        if let Some(written) = instr.operand.and_then(|operand| self.direct_register(operand)) {
+            return written == register;
+        }
+        match self.aliases.targets(instr.id) {
+            Some(AliasTargets::Range(range)) => range.contains(register),
+            _ => true,
+        }
+    }
+
+    /// Compute the accumulator before the instruction at a node
+    ///
+    /// The instructions before it are followed back while each has a single
+    /// predecessor, up to the one that loads the accumulator.
+    fn accumulator_before(&self, node_idx: NodeIndex) -> Option<Linear> {
+        let mut chain = Vec::new();
+        let mut current = node_idx;
+        loop {
+            let [previous] = self.cfg.get_predecessors(current)[..] else {
+                return None;
+            };
+            if previous == node_idx || chain.len() > self.body.instructions.len() {
+                return None;
+            }
+            let instr = self.instruction(previous)?;
+            chain.push(instr);
+            if self.effects.get(&instr.id)?.accumulator_value() == Some(AccumulatorValue::Operand) {
+                break;
+            }
+            current = previous;
+        }
+
+        let mut value = None;
+        for instr in chain.into_iter().rev() {
+            value = Some(self.transfer(instr, value)?);
+        }
+        value
+    }
+
+    /// Update the accumulator with the effects of an instruction
+    ///
+    /// Returns `None` if the accumulator can not be written as a sum of
+    /// registers and a constant anymore.
+    fn transfer(&self, instr: &Instruction, value: Option<Linear>) -> Option<Linear> {
+        let effects = self.effects.get(&instr.id)?;
+        if effects.calls() || effects.returns() {
+            return None;
+        }
+        let operand = instr.operand.and_then(|operand| self.operand_value(operand));
+
+        let mut value = match effects.accumulator_value() {
+            Some(AccumulatorValue::Operand) => operand?,
+            Some(AccumulatorValue::Add) => value?.add(&operand?, 1)?,
+            Some(AccumulatorValue::Sub) => value?.add(&operand?, -1)?,
+            Some(_) => return None,
+            None => value?,
+        };
+
+        if effects.writes_operand() {
+            match instr.operand.and_then(|operand| self.direct_register(operand)) {
+                // The register now holds the accumulator
+                Some(register) if self.copies_accumulator(effects) => {
+                    value = Linear::register(register);
+                }
+                Some(register) if !value.terms.contains_key(&register) => {}
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    /// Check whether an instruction writes the accumulator to its operand
+    fn copies_accumulator(&self, effects: &InstructionEffects) -> bool {
+        effects.writes_operand()
+            && effects.reads_accumulator()
+            && !effects.contains(Effect::ReadsInput)
+    }
+
+    /// Get the value of an operand as a sum, if it is a constant or a register
+    fn operand_value(&self, operand_id: ExprId) -> Option<Linear> {
+        if let Some(register) = self.direct_register(operand_id) {
+            return Some(Linear::register(register));
+        }
+        match &self.body.expr(operand_id)?.kind {
+            ExprKind::Literal(Literal::Int(value)) => Some(Linear::constant(*value)),
+            ExprKind::MemoryRef(mem_ref) if mem_ref.mode == AddressingMode::Immediate => {
+                match self.body.expr(mem_ref.address)?.kind {
+                    ExprKind::Literal(Literal::Int(value)) => Some(Linear::constant(value)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the register a direct operand refers to
+    fn direct_register(&self, operand_id: ExprId) -> Option<i64> {
+        let ExprKind::MemoryRef(mem_ref) = &self.body.expr(operand_id)?.kind else {
+            return None;
+        };
+        if mem_ref.mode != AddressingMode::Direct {
+            return None;
+        }
+        match self.body.expr(mem_ref.address)?.kind {
+            ExprKind::Literal(Literal::Int(register)) => Some(register),
+            _ => None,
+        }
+    }
+
+    /// Get the instruction at a node
+    fn instruction(&self, node_idx: NodeIndex) -> Option<&'a Instruction> {
+        let instr_id = self.cfg.get_node(node_idx).instruction_id?;
+        self.instructions.get(&instr_id).copied()
+    }
+}
@@ -0,0 +1,103 @@
+//! Unused label detection for HIR
+//!
+//! This module provides an analysis that reports labels which are never
+//! referenced by any instruction in a HIR body.
+
+use std::any::TypeId;
+use std::collections::HashSet;
+
+use hir::body::{ExprKind, Literal};
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+
+use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
+use crate::pass::AnalysisPass;
+
+/// The diagnostic code reported for unused labels
+pub const UNUSED_LABEL_CODE: &str = ram_diagnostics::codes::UNUSED_LABEL.code;
+
+/// The doc comment annotation that marks a label as used
+pub const USED_ANNOTATION: &str = "#[used]";
+
+/// Unused label analysis pass
+///
+/// This pass reports labels that are never the target of a jump or any other
+/// reference. Labels are skipped when they are exported to other modules or
+/// carry a `#[used]` annotation in their doc comment.
+#[derive(Debug, Default)]
+pub struct UnusedLabelAnalysis {
+    /// Names of labels that are imported by other modules
+    exported: HashSet<String>,
+}
+
+impl UnusedLabelAnalysis {
+    /// Create an analysis that treats the given labels as exported
+    pub fn with_exported(exported: impl IntoIterator<Item = String>) -> Self {
+        Self { exported: exported.into_iter().collect() }
+    }
+}
+
+impl AnalysisPass for UnusedLabelAnalysis {
+    type Output = Vec<LocalDefId>;
+
+    fn name(&self) -> &'static str {
+        "UnusedLabelAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        "unused-labels"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        // Labels are only referred to by expressions
+        BodyParts::LABELS.union(BodyParts::EXPRS)
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        let body = ctx.body().clone();
+
+        // Collect every label referenced by an expression
+        let mut referenced_ids = HashSet::new();
+        let mut referenced_names = HashSet::new();
+        for expr in body.exprs.values() {
+            match &expr.kind {
+                ExprKind::LabelRef(label_ref) => {
+                    referenced_ids.insert(label_ref.label_id.local_id);
+                }
+                ExprKind::Literal(Literal::Label(name)) => {
+                    referenced_names.insert(name.as_str());
+                }
+                _ => {}
+            }
+        }
+
+        let mut unused = Vec::new();
+        for label in body.labels.values() {
+            if referenced_ids.contains(&label.id)
+                || referenced_names.contains(label.name.as_str())
+                || self.exported.contains(&label.name)
+                || label.docs.iter().any(|doc| doc.trim() == USED_ANNOTATION)
+            {
+                continue;
+            }
+
+            let span = ctx.get_label_span(label.id);
+            ctx.add_diagnostic(
+                ram_diagnostics::Diagnostic::builder()
+                    .with_message(format!("Label '{}' is never used", label.name))
+                    .with_help("Remove the label, or annotate it with `#[used]` in a doc comment")
+                    .with_primary_span(span, "label defined here")
+                    .with_code(UNUSED_LABEL_CODE)
+                    .build_warning(),
+            );
+            unused.push(label.id);
+        }
+
+        Ok(unused)
+    }
+}
@@ -0,0 +1,166 @@
+//! Configuration of analysis passes.
+//!
+//! Passes declare the settings they read, such as thresholds and toggles, as
+//! [`PassOption`]s with a default value, and read them from the
+//! [`AnalysisContext`](crate::AnalysisContext) with
+//! [`option`](crate::AnalysisContext::option). Users set them, and enable or
+//! disable passes, in the `[analysis.passes]` section of the project manifest,
+//! where each pass is known by its [ID](crate::AnalysisPass::id).
+//!
+//! # Example
+//!
+//! ```
+//! use hir_analysis::config::{AnalysisConfig, SettingValue};
+//!
+//! let config = AnalysisConfig::new()
+//!     .with_setting("alias", "widening-limit", SettingValue::Integer(16))
+//!     .with_pass_enabled("unused-labels", false);
+//!
+//! assert!(!config.is_enabled("unused-labels"));
+//! assert_eq!(config.value("alias", "widening-limit"), Some(&SettingValue::Integer(16)));
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ram_diagnostics::DiagnosticConfig;
+pub use ram_diagnostics::config::{PassSettings, SettingValue};
+
+/// A setting read by an analysis pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassOption {
+    /// The name of the setting, as written in the manifest.
+    pub name: &'static str,
+    /// What the setting changes.
+    pub description: &'static str,
+    /// The value of the setting when it is not configured, which also gives
+    /// its type.
+    pub default: SettingValue,
+}
+
+impl PassOption {
+    /// Creates a setting with a default value.
+    pub fn new(name: &'static str, description: &'static str, default: SettingValue) -> Self {
+        Self { name, description, default }
+    }
+}
+
+/// The configuration of the passes of an analysis pipeline.
+///
+/// Passes are enabled unless they are disabled, or they depend on a disabled
+/// pass. When the passes to run are [restricted](AnalysisConfig::with_only),
+/// only those and the passes they depend on run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisConfig {
+    /// The configuration of each pass, by pass ID.
+    passes: BTreeMap<String, PassSettings>,
+    /// The only passes to run, besides their dependencies, if restricted.
+    only: Option<BTreeSet<String>>,
+}
+
+impl AnalysisConfig {
+    /// Creates a configuration that runs every pass with its default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables or disables a pass.
+    #[must_use]
+    pub fn with_pass_enabled(mut self, pass: impl Into<String>, enabled: bool) -> Self {
+        self.passes.entry(pass.into()).or_default().enabled = Some(enabled);
+        self
+    }
+
+    /// Sets a setting of a pass.
+    #[must_use]
+    pub fn with_setting(
+        mut self,
+        pass: impl Into<String>,
+        name: impl Into<String>,
+        value: SettingValue,
+    ) -> Self {
+        self.passes.entry(pass.into()).or_default().values.insert(name.into(), value);
+        self
+    }
+
+    /// Runs only the given passes and the passes they depend on.
+    ///
+    /// This overrides the passes enabled or disabled one by one.
+    #[must_use]
+    pub fn with_only(mut self, passes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.only = Some(passes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Returns the configuration of each configured pass, by pass ID.
+    pub fn passes(&self) -> &BTreeMap<String, PassSettings> {
+        &self.passes
+    }
+
+    /// Returns the only passes to run, if restricted.
+    pub fn only(&self) -> Option<&BTreeSet<String>> {
+        self.only.as_ref()
+    }
+
+    /// Returns `false` if a pass is disabled, on its own or by a restriction.
+    ///
+    /// A pass that is enabled may still not run, if it depends on a disabled
+    /// pass.
+    pub fn is_enabled(&self, pass: &str) -> bool {
+        match &self.only {
+            Some(only) => only.contains(pass),
+            None => self.passes.get(pass).and_then(|settings| settings.enabled).unwrap_or(true),
+        }
+    }
+
+    /// Returns the configured value of a setting of a pass.
+    pub fn value(&self, pass: &str, name: &str) -> Option<&SettingValue> {
+        self.passes.get(pass)?.values.get(name)
+    }
+}
+
+impl From<&DiagnosticConfig> for AnalysisConfig {
+    /// Takes the configuration of the passes from a project configuration.
+    fn from(config: &DiagnosticConfig) -> Self {
+        Self { passes: config.passes().clone(), only: config.enabled_passes().cloned() }
+    }
+}
+
+/// A type the value of a setting can be read as.
+pub trait FromSettingValue: Sized {
+    /// Converts a value, or returns `None` if it has another type.
+    fn from_setting_value(value: &SettingValue) -> Option<Self>;
+}
+
+impl FromSettingValue for bool {
+    fn from_setting_value(value: &SettingValue) -> Option<Self> {
+        match value {
+            SettingValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromSettingValue for i64 {
+    fn from_setting_value(value: &SettingValue) -> Option<Self> {
+        match value {
+            SettingValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromSettingValue for usize {
+    /// Reads an integer, clamping negative values to zero.
+    fn from_setting_value(value: &SettingValue) -> Option<Self> {
+        i64::from_setting_value(value).map(|value| usize::try_from(value).unwrap_or(0))
+    }
+}
+
+impl FromSettingValue for String {
+    fn from_setting_value(value: &SettingValue) -> Option<Self> {
+        match value {
+            SettingValue::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
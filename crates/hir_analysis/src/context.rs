@@ -27,13 +27,17 @@
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::{Arc, LazyLock};
 
-use hir::body::Body;
+use hir::body::{Body, BodyMemory};
 use miette::*;
+use ram_core::{InstructionEffects, InstructionRegistry, InstructionSet};
 use ram_diagnostics::{Diagnostic, DiagnosticCollection};
 use tracing::{debug, error, instrument};
 
+use crate::config::{AnalysisConfig, FromSettingValue};
+use crate::diff::SpanMap;
 use crate::error::AnalysisError;
 use crate::pass::AnalysisPass;
 
@@ -41,26 +45,73 @@ use crate::pass::AnalysisPass;
 ///
 /// The [`AnalysisContext`] is passed to each analysis pass and allows passes to
 /// access the results of their dependencies. It also provides access to the
-/// HIR body being analyzed. It also collects diagnostics reported by analysis passes.
+/// HIR body being analyzed and to the instructions available to it. It also
+/// collects diagnostics reported by analysis passes.
 pub struct AnalysisContext {
     /// The HIR body being analyzed.
     body: Arc<hir::body::Body>,
+    /// The instructions available to the body, including those of plugins.
+    instructions: Arc<InstructionRegistry>,
+    /// The configuration of the passes.
+    config: Arc<AnalysisConfig>,
     /// Map from pass TypeId to analysis results.
     results: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
     /// Collection of diagnostics reported by analysis passes.
     diagnostics: DiagnosticCollection,
+    /// Map from pass TypeId to the range of the diagnostics it reported.
+    pass_diagnostics: HashMap<TypeId, Range<usize>>,
 }
 
 impl AnalysisContext {
     /// Creates a new [`AnalysisContext`] for the given HIR body.
     ///
+    /// Only the standard instructions are available to the body.
+    ///
     /// # Parameters
     ///
     /// * `body` - The HIR body to analyze.
     #[instrument(skip(body))]
     pub(crate) fn new(body: Arc<Body>) -> Self {
+        Self::with_instruction_registry(body, standard_instruction_registry())
+    }
+
+    /// Creates a new [`AnalysisContext`] for a HIR body using the given instructions.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The HIR body to analyze.
+    /// * `instructions` - The instructions available to the body.
+    #[instrument(skip(body, instructions))]
+    pub(crate) fn with_instruction_registry(
+        body: Arc<Body>,
+        instructions: Arc<InstructionRegistry>,
+    ) -> Self {
+        Self::with_config(body, instructions, Arc::default())
+    }
+
+    /// Creates a new [`AnalysisContext`] for a HIR body using the given
+    /// instructions and configuration of the passes.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The HIR body to analyze.
+    /// * `instructions` - The instructions available to the body.
+    /// * `config` - The configuration of the passes.
+    #[instrument(skip(body, instructions, config))]
+    pub(crate) fn with_config(
+        body: Arc<Body>,
+        instructions: Arc<InstructionRegistry>,
+        config: Arc<AnalysisConfig>,
+    ) -> Self {
         debug!("Creating new AnalysisContext");
-        AnalysisContext { body, results: HashMap::new(), diagnostics: DiagnosticCollection::new() }
+        AnalysisContext {
+            body,
+            instructions,
+            config,
+            results: HashMap::new(),
+            diagnostics: DiagnosticCollection::new(),
+            pass_diagnostics: HashMap::new(),
+        }
     }
 
     /// Add a diagnostic to the context.
@@ -337,6 +388,34 @@ impl AnalysisContext {
         &self.diagnostics
     }
 
+    /// Get the diagnostics reported by an analysis pass.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `P` - The type of the analysis pass.
+    ///
+    /// # Returns
+    ///
+    /// The diagnostics the pass reported, or none if it has not been run by
+    /// the pipeline.
+    pub fn pass_diagnostics<P>(&self) -> &[Diagnostic]
+    where
+        P: AnalysisPass + 'static,
+    {
+        self.pass_diagnostics
+            .get(&TypeId::of::<P>())
+            .and_then(|range| self.diagnostics.diagnostics().get(range.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Record that the diagnostics from `start` on were reported by a pass.
+    pub(crate) fn record_pass_diagnostics<P>(&mut self, start: usize)
+    where
+        P: AnalysisPass + 'static,
+    {
+        self.pass_diagnostics.insert(TypeId::of::<P>(), start..self.diagnostics.len());
+    }
+
     /// Check if there are any error diagnostics.
     ///
     /// # Returns
@@ -359,6 +438,83 @@ impl AnalysisContext {
         &self.body
     }
 
+    /// Returns the instructions available to the body being analyzed.
+    ///
+    /// This includes the instructions registered by plugins.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the instruction registry.
+    #[instrument(skip(self))]
+    pub fn instruction_registry(&self) -> &Arc<InstructionRegistry> {
+        debug!("Accessing instruction registry from AnalysisContext");
+        &self.instructions
+    }
+
+    /// Returns the configuration of the passes.
+    pub fn config(&self) -> &Arc<AnalysisConfig> {
+        &self.config
+    }
+
+    /// Returns the memory held by the analysis.
+    ///
+    /// The results of the passes are only counted, as their types are not
+    /// known to the context.
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            body: self.body.memory_usage(),
+            results: self.results.len(),
+            diagnostics: self.diagnostics.len(),
+            diagnostics_size: self.diagnostics.len() * std::mem::size_of::<Diagnostic>(),
+        }
+    }
+
+    /// Returns the value of a setting of a pass.
+    ///
+    /// The configured value is returned if there is one, and otherwise the
+    /// default value the pass declares in its
+    /// [options](AnalysisPass::options).
+    ///
+    /// # Parameters
+    ///
+    /// * `pass` - The pass reading the setting.
+    /// * `name` - The name of the setting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pass does not declare the setting, or declares it with
+    /// a type other than `T`.
+    pub fn option<T: FromSettingValue>(&self, pass: &impl AnalysisPass, name: &str) -> T {
+        let configured = self.config.value(pass.id(), name).and_then(T::from_setting_value);
+        configured.unwrap_or_else(|| {
+            pass.options()
+                .iter()
+                .find(|option| option.name == name)
+                .and_then(|option| T::from_setting_value(&option.default))
+                .unwrap_or_else(|| {
+                    panic!("pass '{}' does not declare the setting '{name}'", pass.id())
+                })
+        })
+    }
+
+    /// Returns the effects of an instruction of the body being analyzed.
+    ///
+    /// The effects are those declared by the definition of the instruction in
+    /// the registry. Instructions missing from the registry fall back to the
+    /// effects of their kind, so aliases such as `JMP` keep their effects, and
+    /// custom instructions have [unknown effects](InstructionEffects::unknown).
+    ///
+    /// # Parameters
+    ///
+    /// * `instr` - The instruction.
+    #[instrument(skip(self, instr))]
+    pub fn instruction_effects(&self, instr: &hir::body::Instruction) -> InstructionEffects {
+        match self.instructions.get_by_name_case_insensitive(&instr.opcode) {
+            Some(definition) => definition.effects(),
+            None => instr.kind.effects(),
+        }
+    }
+
     /// Get the span for an instruction.
     ///
     /// # Parameters
@@ -371,7 +527,7 @@ impl AnalysisContext {
     #[instrument(skip(self))]
     pub fn get_instruction_span(&self, instr_id: hir::ids::LocalDefId) -> std::ops::Range<usize> {
         debug!("Getting instruction span");
-        for instr in &self.body.instructions {
+        for instr in self.body.instructions.values() {
             if instr.id == instr_id {
                 return instr.span.clone();
             }
@@ -392,7 +548,7 @@ impl AnalysisContext {
     #[instrument(skip(self))]
     pub fn get_expr_span(&self, expr_id: hir::expr::ExprId) -> std::ops::Range<usize> {
         debug!("Getting expression span");
-        for expr in &self.body.exprs {
+        for expr in self.body.exprs.values() {
             if expr.id == expr_id {
                 return expr.span.clone();
             }
@@ -413,7 +569,7 @@ impl AnalysisContext {
     #[instrument(skip(self))]
     pub fn get_label_span(&self, label_id: hir::ids::LocalDefId) -> std::ops::Range<usize> {
         debug!("Getting label span");
-        for label in &self.body.labels {
+        for label in self.body.labels.values() {
             if label.id == label_id {
                 return label.span.clone();
             }
@@ -528,6 +684,47 @@ impl AnalysisContext {
         debug!("Result inserted successfully");
         Ok(())
     }
+
+    /// Reuses the result of an analysis pass from a previous analysis.
+    ///
+    /// The result is shared with `previous`, and the diagnostics the pass
+    /// reported there are reported again, at the spans the code they point at
+    /// moved to.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `P` - The type of the analysis pass whose result to reuse.
+    ///
+    /// # Parameters
+    ///
+    /// * `previous` - The context of the previous analysis.
+    /// * `spans` - Where the source of the previous body moved to in this one.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the result was reused.
+    /// * `Err(AnalysisError)` - If the previous analysis has no result for the pass.
+    #[instrument(skip(self, previous), fields(pass_type = std::any::type_name::<P>()))]
+    pub(crate) fn reuse_result<P>(
+        &mut self,
+        previous: &AnalysisContext,
+        spans: &SpanMap,
+    ) -> Result<(), AnalysisError>
+    where
+        P: AnalysisPass + 'static,
+    {
+        let result = previous.get_result::<P>()?;
+        let start = self.diagnostics.len();
+        for diagnostic in previous.pass_diagnostics::<P>() {
+            let mut diagnostic = diagnostic.clone();
+            spans.diagnostic(&mut diagnostic);
+            self.diagnostics.add(diagnostic);
+        }
+        self.record_pass_diagnostics::<P>(start);
+        self.results.insert(TypeId::of::<P>(), Box::new(result));
+        debug!("Result reused from the previous analysis");
+        Ok(())
+    }
 }
 
 /// Implements the [`From<Body>`] trait for [`AnalysisContext`].
@@ -551,6 +748,44 @@ impl From<hir::body::Body> for AnalysisContext {
     }
 }
 
+/// The memory held by an [`AnalysisContext`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The memory held by the analyzed body, in bytes.
+    pub body: BodyMemory,
+    /// The number of results of passes.
+    pub results: usize,
+    /// The number of diagnostics.
+    pub diagnostics: usize,
+    /// The memory held by the diagnostics, without their messages, in bytes.
+    pub diagnostics_size: usize,
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "body: {} bytes ({} in expressions, {} in instructions, {} in labels)",
+            self.body.total(),
+            self.body.exprs,
+            self.body.instructions,
+            self.body.labels
+        )?;
+        writeln!(f, "results: {}", self.results)?;
+        write!(f, "diagnostics: {} ({} bytes)", self.diagnostics, self.diagnostics_size)
+    }
+}
+
+/// Returns the registry of the standard instructions.
+///
+/// Every pipeline shares the same registry, so the results of one pipeline
+/// can be [reused](crate::AnalysisPipeline::reanalyze) by another.
+pub(crate) fn standard_instruction_registry() -> Arc<InstructionRegistry> {
+    static STANDARD: LazyLock<Arc<InstructionRegistry>> =
+        LazyLock::new(|| Arc::new(InstructionSet::standard().registry().clone()));
+    Arc::clone(&STANDARD)
+}
+
 /// Implements the `Debug` trait for [`AnalysisContext`].
 ///
 /// This allows printing the context for debugging purposes.
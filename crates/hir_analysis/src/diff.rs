@@ -0,0 +1,281 @@
+//! Differences between two versions of a HIR body.
+//!
+//! This module provides the [`BodyDiff`], which tells which instructions,
+//! labels and expressions of a body an edit added, removed or changed, and
+//! whether it moved them in the source. The
+//! [`AnalysisPipeline`](crate::AnalysisPipeline) uses it to reanalyze an edited
+//! body, rerunning only the passes that read the [`BodyParts`] that changed and
+//! reusing the results of the others. The spans of the diagnostics it reuses
+//! are moved along with the code they point at.
+//!
+//! # Example
+//!
+//! ```
+//! use hir::body::Body;
+//! use hir_analysis::diff::{BodyDiff, BodyParts};
+//!
+//! let diff = BodyDiff::between(&Body::default(), &Body::default());
+//! assert!(diff.is_empty());
+//! assert_eq!(diff.changed_parts(), BodyParts::NONE);
+//! ```
+
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::ops::Range;
+
+use hir::body::Body;
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_diagnostics::Diagnostic;
+
+/// A set of the parts of a body.
+///
+/// Passes declare the parts they read with
+/// [`AnalysisPass::inputs`](crate::AnalysisPass::inputs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BodyParts {
+    /// The instructions, in program order.
+    pub instructions: bool,
+    /// The labels.
+    pub labels: bool,
+    /// The expressions of the operands.
+    pub exprs: bool,
+    /// The positions of the instructions, labels and expressions in the source.
+    pub spans: bool,
+}
+
+impl BodyParts {
+    /// No part of a body.
+    pub const NONE: Self = Self { instructions: false, labels: false, exprs: false, spans: false };
+    /// Every part of a body.
+    pub const ALL: Self = Self { instructions: true, labels: true, exprs: true, spans: true };
+    /// Only the instructions.
+    pub const INSTRUCTIONS: Self = Self { instructions: true, ..Self::NONE };
+    /// Only the labels.
+    pub const LABELS: Self = Self { labels: true, ..Self::NONE };
+    /// Only the expressions.
+    pub const EXPRS: Self = Self { exprs: true, ..Self::NONE };
+    /// Only the spans.
+    pub const SPANS: Self = Self { spans: true, ..Self::NONE };
+    /// Every part of a body but the spans.
+    ///
+    /// Passes that only use spans to report diagnostics read this, as the
+    /// spans of reused diagnostics are moved along with the code.
+    pub const CODE: Self = Self { spans: false, ..Self::ALL };
+
+    /// Returns the parts in either set.
+    pub const fn union(self, other: Self) -> Self {
+        Self {
+            instructions: self.instructions || other.instructions,
+            labels: self.labels || other.labels,
+            exprs: self.exprs || other.exprs,
+            spans: self.spans || other.spans,
+        }
+    }
+
+    /// Returns `true` if a part is in both sets.
+    pub const fn intersects(self, other: Self) -> bool {
+        (self.instructions && other.instructions)
+            || (self.labels && other.labels)
+            || (self.exprs && other.exprs)
+            || (self.spans && other.spans)
+    }
+
+    /// Returns `true` if the set has no part.
+    pub const fn is_empty(self) -> bool {
+        !self.intersects(Self::ALL)
+    }
+}
+
+/// The differences between two versions of a body.
+///
+/// Instructions, labels and expressions are matched by their positions in the
+/// body. An item counts as changed if it was added, removed or any of its
+/// fields but its span changed, so an edit that only moves code, such as a new
+/// comment above it, changes nothing but the spans.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyDiff {
+    /// Whether the bodies belong to different definitions.
+    pub owner: bool,
+    /// The IDs of the instructions that changed.
+    pub instructions: HashSet<LocalDefId>,
+    /// The IDs of the labels that changed.
+    pub labels: HashSet<LocalDefId>,
+    /// The IDs of the expressions that changed.
+    pub exprs: HashSet<ExprId>,
+    /// Whether any instruction, label or expression moved in the source.
+    pub spans: bool,
+}
+
+impl BodyDiff {
+    /// Compares two versions of a body.
+    ///
+    /// # Parameters
+    ///
+    /// * `old` - The body before the edit.
+    /// * `new` - The body after the edit.
+    pub fn between(old: &Body, new: &Body) -> Self {
+        Self {
+            owner: old.owner != new.owner,
+            instructions: changed_items(
+                old.instructions.values(),
+                new.instructions.values(),
+                |instr| instr.id,
+                |old, new| {
+                    (old.id, &old.opcode, &old.kind, old.operand, &old.label_name, &old.docs)
+                        == (new.id, &new.opcode, &new.kind, new.operand, &new.label_name, &new.docs)
+                },
+            ),
+            labels: changed_items(
+                old.labels.values(),
+                new.labels.values(),
+                |label| label.id,
+                |old, new| {
+                    (old.id, &old.name, old.instruction_id, &old.docs)
+                        == (new.id, &new.name, new.instruction_id, &new.docs)
+                },
+            ),
+            exprs: changed_items(
+                old.exprs.values(),
+                new.exprs.values(),
+                |expr| expr.id,
+                |old, new| (old.id, &old.kind) == (new.id, &new.kind),
+            ),
+            spans: spans(old) != spans(new),
+        }
+    }
+
+    /// Returns `true` if the bodies are the same.
+    pub fn is_empty(&self) -> bool {
+        !self.owner
+            && self.instructions.is_empty()
+            && self.labels.is_empty()
+            && self.exprs.is_empty()
+            && !self.spans
+    }
+
+    /// Returns the parts of the body that changed.
+    ///
+    /// Every part counts as changed if the owner did, as references to labels
+    /// are resolved through it.
+    pub fn changed_parts(&self) -> BodyParts {
+        if self.owner {
+            return BodyParts::ALL;
+        }
+        BodyParts {
+            instructions: !self.instructions.is_empty(),
+            labels: !self.labels.is_empty(),
+            exprs: !self.exprs.is_empty(),
+            spans: self.spans,
+        }
+    }
+}
+
+/// Maps offsets in the source of a body to offsets in the source of an edited
+/// version of it.
+///
+/// Offsets are moved along with the instruction, label or expression at the
+/// same position in both bodies that holds them, or else with the one after
+/// them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SpanMap {
+    /// The spans of the items at the same positions, before and after the edit.
+    spans: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl SpanMap {
+    /// Maps the source of `old` to the source of `new`.
+    pub(crate) fn between(old: &Body, new: &Body) -> Self {
+        Self { spans: spans(old).into_iter().zip(spans(new)).collect() }
+    }
+
+    /// Returns where an offset ended up after the edit.
+    pub(crate) fn offset(&self, offset: usize) -> usize {
+        // The innermost item holding the offset keeps it at the same distance
+        // from its start
+        let holder = self
+            .spans
+            .iter()
+            .filter(|(old, _)| old.start <= offset && offset <= old.end)
+            .min_by_key(|(old, _)| old.len());
+        if let Some((old, new)) = holder {
+            return new.start + (offset - old.start).min(new.len());
+        }
+
+        // Otherwise it moves with the start of the next item, or the end of
+        // the last one
+        let anchor = self
+            .spans
+            .iter()
+            .filter(|(old, _)| old.start > offset)
+            .min_by_key(|(old, _)| old.start)
+            .map(|(old, new)| (old.start, new.start))
+            .or_else(|| {
+                self.spans
+                    .iter()
+                    .max_by_key(|(old, _)| old.end)
+                    .map(|(old, new)| (old.end, new.end))
+            });
+        match anchor {
+            Some((old, new)) => offset.saturating_add_signed(new as isize - old as isize),
+            None => offset,
+        }
+    }
+
+    /// Returns where a span ended up after the edit.
+    pub(crate) fn span(&self, span: &Range<usize>) -> Range<usize> {
+        let start = self.offset(span.start);
+        start..self.offset(span.end).max(start)
+    }
+
+    /// Moves the spans of a diagnostic on the body along with the edit.
+    ///
+    /// Diagnostics on other files are left as they are.
+    pub(crate) fn diagnostic(&self, diagnostic: &mut Diagnostic) {
+        if diagnostic.file.is_some() {
+            return;
+        }
+        for (span, _) in &mut diagnostic.labeled_spans {
+            *span = self.span(span);
+        }
+        for suggestion in &mut diagnostic.suggestions {
+            suggestion.span = self.span(&suggestion.span);
+        }
+    }
+}
+
+/// Returns the spans of the instructions, labels and expressions of a body, in
+/// this order.
+fn spans(body: &Body) -> Vec<Range<usize>> {
+    let instructions = body.instructions.values().map(|instr| instr.span.clone());
+    let labels = body.labels.values().map(|label| label.span.clone());
+    let exprs = body.exprs.values().map(|expr| expr.span.clone());
+    instructions.chain(labels).chain(exprs).collect()
+}
+
+/// Returns the IDs of the items that are not the same at the same position in
+/// both lists.
+///
+/// An item that changed is reported by its ID in both lists.
+fn changed_items<'a, T, Id>(
+    old: impl Iterator<Item = &'a T>,
+    new: impl Iterator<Item = &'a T>,
+    id: impl Fn(&T) -> Id,
+    same: impl Fn(&T, &T) -> bool,
+) -> HashSet<Id>
+where
+    T: 'a,
+    Id: Eq + Hash,
+{
+    let old = old.collect::<Vec<_>>();
+    let new = new.collect::<Vec<_>>();
+
+    let mut changed = HashSet::new();
+    for index in 0..old.len().max(new.len()) {
+        match (old.get(index), new.get(index)) {
+            (Some(old), Some(new)) if same(old, new) => {}
+            (old, new) => changed.extend(old.into_iter().chain(new).map(|item| id(item))),
+        }
+    }
+    changed
+}
@@ -127,4 +127,21 @@ pub enum AnalysisError {
         /// The type ID of the pass.
         pass_id: TypeId,
     },
+
+    /// The configuration of the passes does not match the registered passes.
+    ///
+    /// This error occurs when a configuration restricts the pipeline to a
+    /// pass that is not registered, or sets a setting a pass does not declare
+    /// or to a value of the wrong type.
+    #[diagnostic(
+        code(analysis::invalid_config),
+        help(
+            "Check the [analysis.passes] section of ram.toml and the passes given on the command line."
+        )
+    )]
+    #[error("Invalid analysis configuration: {0}")]
+    InvalidConfig(
+        /// A description of the problem.
+        String,
+    ),
 }
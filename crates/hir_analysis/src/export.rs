@@ -22,6 +22,8 @@ use petgraph::dot::{Config, Dot};
 use petgraph::graph::{DiGraph, NodeIndex};
 use serde_json::{self, Map, Value};
 
+use crate::analyzers::control_flow::EdgeKind;
+
 /// Supported export formats.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExportFormat {
@@ -43,6 +45,69 @@ impl fmt::Display for ExportFormat {
     }
 }
 
+/// The color theme of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportTheme {
+    /// Dark lines and text on a light background.
+    Light,
+    /// Light lines and text on a dark background.
+    Dark,
+}
+
+impl ExportTheme {
+    /// The name of the Mermaid theme.
+    fn mermaid_name(self) -> &'static str {
+        match self {
+            ExportTheme::Light => "default",
+            ExportTheme::Dark => "dark",
+        }
+    }
+
+    /// The color of the background and of the lines and text drawn on it.
+    fn dot_colors(self) -> (&'static str, &'static str) {
+        match self {
+            ExportTheme::Light => ("white", "black"),
+            ExportTheme::Dark => ("#1e1e1e", "white"),
+        }
+    }
+}
+
+/// The direction the ranks of a graph are laid out in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RankDirection {
+    /// From top to bottom.
+    #[default]
+    TopDown,
+    /// From bottom to top.
+    BottomUp,
+    /// From left to right.
+    LeftRight,
+    /// From right to left.
+    RightLeft,
+}
+
+impl RankDirection {
+    /// The direction in a Mermaid flowchart header.
+    fn mermaid(self) -> &'static str {
+        match self {
+            RankDirection::TopDown => "TD",
+            RankDirection::BottomUp => "BT",
+            RankDirection::LeftRight => "LR",
+            RankDirection::RightLeft => "RL",
+        }
+    }
+
+    /// The value of the DOT `rankdir` attribute.
+    fn dot(self) -> &'static str {
+        match self {
+            RankDirection::TopDown => "TB",
+            RankDirection::BottomUp => "BT",
+            RankDirection::LeftRight => "LR",
+            RankDirection::RightLeft => "RL",
+        }
+    }
+}
+
 /// Options for customizing exports.
 #[derive(Debug, Clone)]
 pub struct ExportOptions {
@@ -56,6 +121,29 @@ pub struct ExportOptions {
     pub compact: bool,
     /// Custom node labels, keyed by TypeId.
     pub node_labels: HashMap<TypeId, String>,
+    /// Whether to group control flow graphs by procedure.
+    ///
+    /// Each subroutine is drawn as a group of its own, linked to its callers
+    /// by call and return edges.
+    pub interprocedural: bool,
+    /// Whether to draw only the nodes of control flow graphs reachable from
+    /// their entry.
+    pub reachable_only: bool,
+    /// Whether to draw each basic block of control flow graphs as one node.
+    ///
+    /// JSON exports list the blocks alongside the nodes instead.
+    pub collapse_blocks: bool,
+    /// Whether to leave out the trivia of control flow graphs.
+    ///
+    /// Unconditional jumps only pass control on, so the edges into them are
+    /// drawn straight to their targets instead.
+    pub hide_trivia: bool,
+    /// The color theme, or `None` to leave the colors to the renderer.
+    pub theme: Option<ExportTheme>,
+    /// The colors of the control flow edges of each kind.
+    pub edge_colors: HashMap<EdgeKind, String>,
+    /// The direction the graph is laid out in.
+    pub rank_direction: RankDirection,
 }
 
 impl Default for ExportOptions {
@@ -66,10 +154,46 @@ impl Default for ExportOptions {
             include_edge_labels: false,
             compact: false,
             node_labels: HashMap::new(),
+            interprocedural: false,
+            reachable_only: false,
+            collapse_blocks: false,
+            hide_trivia: false,
+            theme: None,
+            edge_colors: HashMap::new(),
+            rank_direction: RankDirection::default(),
         }
     }
 }
 
+impl ExportOptions {
+    /// Get the first lines of a Mermaid flowchart, with its theme and direction.
+    pub(crate) fn mermaid_header(&self) -> String {
+        let mut header = String::new();
+        if let Some(theme) = self.theme {
+            header.push_str(&format!("%%{{init: {{'theme': '{}'}}}}%%\n", theme.mermaid_name()));
+        }
+        header.push_str(&format!("graph {}\n", self.rank_direction.mermaid()));
+        header
+    }
+
+    /// Get the statements setting the theme and direction of a DOT graph.
+    ///
+    /// Nothing is set for the default options, so the renderer defaults apply.
+    pub(crate) fn dot_attributes(&self) -> Vec<String> {
+        let mut attributes = Vec::new();
+        if self.rank_direction != RankDirection::TopDown {
+            attributes.push(format!("rankdir={}", self.rank_direction.dot()));
+        }
+        if let Some(theme) = self.theme {
+            let (background, foreground) = theme.dot_colors();
+            attributes.push(format!("bgcolor={:?}", background));
+            attributes.push(format!("node [color={:?}, fontcolor={:?}]", foreground, foreground));
+            attributes.push(format!("edge [color={:?}, fontcolor={:?}]", foreground, foreground));
+        }
+        attributes
+    }
+}
+
 /// Export utilities for the analysis pipeline.
 pub struct PipelineExporter<'a> {
     /// The dependency graph to export.
@@ -96,6 +220,20 @@ impl<'a> PipelineExporter<'a> {
         Self { graph, pass_nodes, pass_names }
     }
 
+    /// Get the passes and their nodes, in the order the nodes were added
+    ///
+    /// The pass nodes are kept in a hash map, so they are sorted to keep
+    /// exports the same from one run to the next.
+    fn sorted_pass_nodes(&self) -> Vec<(TypeId, NodeIndex)> {
+        let mut nodes = self
+            .pass_nodes
+            .iter()
+            .map(|(&type_id, &node_idx)| (type_id, node_idx))
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|&(_, node_idx)| node_idx);
+        nodes
+    }
+
     /// Generates an export of the dependency graph in the specified format.
     ///
     /// # Parameters
@@ -139,7 +277,7 @@ impl<'a> PipelineExporter<'a> {
         let mut node_map = HashMap::new();
 
         // Add nodes with labels
-        for (&type_id, &node_idx) in self.pass_nodes {
+        for (type_id, node_idx) in self.sorted_pass_nodes() {
             let label = if let Some(custom_label) = options.node_labels.get(&type_id) {
                 custom_label.clone()
             } else {
@@ -177,7 +315,13 @@ impl<'a> PipelineExporter<'a> {
             }
         }
 
-        format!("{:?}", Dot::with_config(&labeled_graph, &dot_config))
+        let dot = format!("{:?}", Dot::with_config(&labeled_graph, &dot_config));
+        let attributes = options
+            .dot_attributes()
+            .into_iter()
+            .map(|attribute| format!("    {};\n", attribute))
+            .collect::<String>();
+        dot.replacen("{\n", &format!("{{\n{}", attributes), 1)
     }
 
     /// Generates a Mermaid representation of the dependency graph.
@@ -190,10 +334,10 @@ impl<'a> PipelineExporter<'a> {
     ///
     /// A string containing the Mermaid representation.
     fn to_mermaid(&self, options: &ExportOptions) -> String {
-        let mut result = String::from("graph TD\n");
+        let mut result = options.mermaid_header();
 
         // Add nodes
-        for (&type_id, &node_idx) in self.pass_nodes {
+        for (type_id, node_idx) in self.sorted_pass_nodes() {
             let node_id = format!("N{}", node_idx.index());
 
             let label = if let Some(custom_label) = options.node_labels.get(&type_id) {
@@ -252,7 +396,7 @@ impl<'a> PipelineExporter<'a> {
         let mut edges = Vec::new();
 
         // Add nodes
-        for (&type_id, &node_idx) in self.pass_nodes {
+        for (type_id, node_idx) in self.sorted_pass_nodes() {
             let mut node = Map::new();
             node.insert("id".to_string(), Value::String(format!("N{}", node_idx.index())));
 
@@ -9,7 +9,9 @@
 //! * [`AnalysisContext`] - Stores and provides access to analysis results.
 //! * [`AnalysisPass`] - Trait for implementing analysis passes.
 //! * [`AnalysisPipeline`] - Manages the registration and execution of analysis passes.
+//! * [`AnalysisConfig`] - Enables and disables passes and sets their settings.
 //! * [`AnalysisError`] - Error types for the HIR analysis.
+//! * [`AnalysisScope`] - A part of a body, such as a basic block.
 //!
 //! # Example
 //!
@@ -53,26 +55,41 @@
 //! ```
 
 pub mod analyzers;
+pub mod config;
 pub mod context;
+pub mod diff;
 pub mod error;
 pub mod export;
 pub mod pass;
 pub mod pipeline;
+pub mod scope;
 pub mod visitors;
 
 // Re-export main components
+pub use analyzers::alias::{AliasAnalysis, AliasResult, AliasTargets};
 pub use analyzers::constant_propagation::{
-    BranchTaken, ConstantPropagationAnalysis, ConstantPropagationResult,
+    BranchTaken, ConstantPropagationAnalysis, ConstantPropagationResult, ConstantRow, ConstantTable,
 };
 pub use analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph};
 pub use analyzers::control_flow_optimizer::{ControlFlowOptimizer, OptimizedControlFlowGraph};
 pub use analyzers::data_flow::{DataFlowAnalysis, DataFlowGraph};
+pub use analyzers::dominance::{DominanceAnalysis, DominanceInfo, DominatorTree};
 pub use analyzers::instruction_validation::InstructionValidationAnalysis;
-pub use context::AnalysisContext;
+pub use analyzers::io_contract::{IoContractAnalysis, IoContractResult};
+pub use analyzers::scheduling::SchedulingAnalysis;
+pub use analyzers::sections::{Section, SectionAnalysis};
+pub use analyzers::ssa::{AccumulatorVersions, SsaAnalysis};
+pub use analyzers::strict_ram::StrictRamAnalysis;
+pub use analyzers::termination::{TerminationAnalysis, TerminationResult};
+pub use analyzers::unused_labels::UnusedLabelAnalysis;
+pub use config::{AnalysisConfig, PassOption};
+pub use context::{AnalysisContext, MemoryStats};
+pub use diff::{BodyDiff, BodyParts};
 pub use error::AnalysisError;
-pub use export::{ExportFormat, ExportOptions};
+pub use export::{ExportFormat, ExportOptions, ExportTheme, RankDirection};
 pub use pass::AnalysisPass;
 pub use pipeline::AnalysisPipeline;
+pub use scope::{AnalysisScope, ScopeId};
 
 #[cfg(test)]
 mod tests;
@@ -36,7 +36,9 @@ use std::fmt;
 
 use miette::*;
 
+use crate::config::PassOption;
 use crate::context::AnalysisContext;
+use crate::diff::BodyParts;
 
 /// Trait for analysis passes that can be registered with the `AnalysisPipeline`.
 ///
@@ -92,6 +94,32 @@ pub trait AnalysisPass: Any + Send + Sync {
     /// A static string slice containing the name of this pass.
     fn name(&self) -> &'static str;
 
+    /// Returns the ID of this pass.
+    ///
+    /// The ID names the pass in the project manifest and on the command line,
+    /// such as `constprop` in `ram validate --passes=cfg,constprop`. By
+    /// default it is the name of the pass.
+    ///
+    /// # Returns
+    ///
+    /// A static string slice containing the ID of this pass.
+    fn id(&self) -> &'static str {
+        self.name()
+    }
+
+    /// Returns the settings this pass reads.
+    ///
+    /// The settings are read with [`AnalysisContext::option`], and the
+    /// pipeline rejects configured values of other settings or of another
+    /// type. By default a pass reads no settings.
+    ///
+    /// # Returns
+    ///
+    /// The settings of this pass, with their default values.
+    fn options(&self) -> Vec<PassOption> {
+        Vec::new()
+    }
+
     /// Returns the dependencies of this pass.
     ///
     /// The `AnalysisPipeline` uses this information to determine the order in
@@ -104,6 +132,23 @@ pub trait AnalysisPass: Any + Send + Sync {
     /// pass depends on.
     fn dependencies(&self) -> Vec<TypeId>;
 
+    /// Returns the parts of the body this pass reads.
+    ///
+    /// When an edited body is [reanalyzed](crate::AnalysisPipeline::reanalyze),
+    /// the result of this pass is reused if none of these parts changed and
+    /// none of its dependencies was rerun. By default a pass reads every part.
+    ///
+    /// Passes whose results hold no spans need not read
+    /// [`BodyParts::SPANS`], as the spans of the diagnostics of reused passes
+    /// are moved along with the code.
+    ///
+    /// # Returns
+    ///
+    /// The parts of the body the result of this pass depends on.
+    fn inputs(&self) -> BodyParts {
+        BodyParts::ALL
+    }
+
     /// Runs this pass on the given context.
     ///
     /// This method performs the actual analysis and returns the result.
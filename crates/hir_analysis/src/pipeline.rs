@@ -25,14 +25,17 @@
 //! ```
 
 use std::any::TypeId;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 use petgraph::algo::toposort;
 use petgraph::graph::{DiGraph, NodeIndex};
+use ram_core::InstructionRegistry;
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::context::AnalysisContext;
+use crate::config::{AnalysisConfig, PassOption};
+use crate::context::{AnalysisContext, standard_instruction_registry};
+use crate::diff::{BodyDiff, BodyParts, SpanMap};
 use crate::error::AnalysisError;
 use crate::export::{ExportFormat, ExportOptions, PipelineExporter};
 use crate::pass::AnalysisPass;
@@ -46,6 +49,10 @@ pub struct AnalysisPipeline {
     pass_nodes: HashMap<TypeId, NodeIndex>,
     /// The dependency graph. Node weight is TypeId.
     graph: DiGraph<TypeId, ()>,
+    /// The instructions available to the analyzed bodies.
+    instructions: Arc<InstructionRegistry>,
+    /// The configuration of the passes.
+    config: Arc<AnalysisConfig>,
 }
 
 impl AnalysisPipeline {
@@ -63,7 +70,115 @@ impl AnalysisPipeline {
     #[instrument]
     pub fn new() -> Self {
         debug!("Creating new AnalysisPipeline");
-        Self { passes: HashMap::new(), pass_nodes: HashMap::new(), graph: DiGraph::new() }
+        Self {
+            passes: HashMap::new(),
+            pass_nodes: HashMap::new(),
+            graph: DiGraph::new(),
+            instructions: standard_instruction_registry(),
+            config: Arc::default(),
+        }
+    }
+
+    /// Sets the instructions available to the analyzed bodies.
+    ///
+    /// By default only the standard instructions are available. Passes such as
+    /// [`InstructionValidationAnalysis`](crate::InstructionValidationAnalysis)
+    /// validate instructions against this registry, so it should contain the
+    /// instructions of every active plugin.
+    ///
+    /// # Parameters
+    ///
+    /// * `instructions` - The instructions available to the analyzed bodies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use hir_analysis::pipeline::AnalysisPipeline;
+    /// use ram_core::standard_instructions;
+    ///
+    /// let mut pipeline = AnalysisPipeline::new();
+    /// pipeline.set_instruction_registry(Arc::new(standard_instructions()));
+    /// ```
+    pub fn set_instruction_registry(&mut self, instructions: Arc<InstructionRegistry>) {
+        debug!("Setting the instruction registry");
+        self.instructions = instructions;
+    }
+
+    /// Returns the instructions available to the analyzed bodies.
+    pub fn instruction_registry(&self) -> &Arc<InstructionRegistry> {
+        &self.instructions
+    }
+
+    /// Sets the configuration of the passes.
+    ///
+    /// The configuration is checked against the registered passes, so it
+    /// should be set after registering them. Settings of passes that are not
+    /// registered are ignored, as a pipeline may leave out passes that others
+    /// register, such as [`StrictRamAnalysis`](crate::StrictRamAnalysis).
+    ///
+    /// # Parameters
+    ///
+    /// * `config` - The configuration of the passes.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the configuration was set.
+    /// * `Err(AnalysisError::InvalidConfig)` if it restricts the pipeline to a
+    ///   pass that is not registered, or sets a setting a pass does not
+    ///   declare or to a value of the wrong type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hir_analysis::config::AnalysisConfig;
+    /// use hir_analysis::pipeline::AnalysisPipeline;
+    ///
+    /// let mut pipeline = AnalysisPipeline::new();
+    /// pipeline.set_config(AnalysisConfig::new().with_pass_enabled("cfg", false)).unwrap();
+    /// assert!(pipeline.set_config(AnalysisConfig::new().with_only(["cfg"])).is_err());
+    /// ```
+    pub fn set_config(&mut self, config: AnalysisConfig) -> Result<(), AnalysisError> {
+        let options = self.pass_options();
+        if let Some(unknown) =
+            config.only().into_iter().flatten().find(|pass| !options.contains_key(pass.as_str()))
+        {
+            return Err(AnalysisError::InvalidConfig(format!(
+                "unknown pass '{unknown}', expected one of {}",
+                options.keys().copied().collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        for (pass, settings) in config.passes() {
+            let Some(pass_options) = options.get(pass.as_str()) else {
+                continue;
+            };
+            for (name, value) in &settings.values {
+                let option =
+                    pass_options.iter().find(|option| option.name == name).ok_or_else(|| {
+                        AnalysisError::InvalidConfig(format!(
+                            "pass '{pass}' has no setting '{name}'"
+                        ))
+                    })?;
+                if std::mem::discriminant(value) != std::mem::discriminant(&option.default) {
+                    return Err(AnalysisError::InvalidConfig(format!(
+                        "'{pass}.{name}' must be a {}, not a {}",
+                        option.default.type_name(),
+                        value.type_name()
+                    )));
+                }
+            }
+        }
+
+        debug!("Setting the configuration of the passes");
+        self.config = Arc::new(config);
+        Ok(())
+    }
+
+    /// Returns the configuration of the passes.
+    pub fn config(&self) -> &Arc<AnalysisConfig> {
+        &self.config
     }
 
     /// Registers an analysis pass using its default implementation.
@@ -219,25 +334,17 @@ impl AnalysisPipeline {
     #[instrument(skip(self, body))]
     pub fn analyze(&self, body: Arc<hir::body::Body>) -> Result<AnalysisContext, AnalysisError> {
         info!("Starting analysis run");
-        let mut context = AnalysisContext::new(body);
+        let mut context = self.context(body);
 
-        let sorted_nodes = toposort(&self.graph, None).map_err(|cycle| {
-            let node_id = cycle.node_id();
-            let type_id = self.graph.node_weight(node_id).cloned();
-            error!(?node_id, ?type_id, "Dependency cycle detected in analysis passes");
-            AnalysisError::DependencyCycle(format!(
-                "Cycle detected involving node index {:?} (TypeId: {:?})",
-                node_id, type_id
-            ))
-        })?;
-
-        info!(pass_count = sorted_nodes.len(), "Executing passes in topological order");
+        let sorted_nodes = self.sorted_nodes()?;
+        let enabled = self.enabled_passes(&sorted_nodes);
+        info!(pass_count = enabled.len(), "Executing passes in topological order");
         for node_index in sorted_nodes {
-            let pass_id = self.graph[node_index];
-            let runner = self
-                .passes
-                .get(&pass_id)
-                .expect("Graph node TypeId should exist in passes map (internal error)");
+            let runner = self.runner(node_index);
+            if !enabled.contains(&self.graph[node_index]) {
+                debug!(pass = runner.name(), "Skipping disabled pass");
+                continue;
+            }
 
             info!(pass = runner.name(), "Executing analysis pass");
             match runner.run_pass(&mut context) {
@@ -252,6 +359,138 @@ impl AnalysisPipeline {
         info!("Analysis run finished successfully");
         Ok(context)
     }
+
+    /// Runs the registered analysis passes on an edited HIR body.
+    ///
+    /// The body is compared with the body of `previous`, and only the passes
+    /// that read a [part](crate::AnalysisPass::inputs) of the body that changed,
+    /// or depend on a pass that was rerun, are run again. The results and
+    /// diagnostics of the other passes are reused from `previous`, with the
+    /// spans of the diagnostics moved along with the code they point at.
+    ///
+    /// Every pass is run again if the pipeline has other instructions or
+    /// another configuration than the one `previous` was analyzed with.
+    ///
+    /// # Parameters
+    ///
+    /// * `previous` - The context of the analysis of the body before the edit,
+    ///   as returned by this pipeline.
+    /// * `body` - The HIR body after the edit.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AnalysisContext)` containing the results of all analysis passes.
+    /// * `Err(AnalysisError)` if any error occurred during analysis.
+    #[instrument(skip(self, previous, body))]
+    pub fn reanalyze(
+        &self,
+        previous: &AnalysisContext,
+        body: Arc<hir::body::Body>,
+    ) -> Result<AnalysisContext, AnalysisError> {
+        let diff = BodyDiff::between(previous.body(), &body);
+        let spans = SpanMap::between(previous.body(), &body);
+        // Passes validate against the instructions and read their settings,
+        // so other ones change everything
+        let changed = if Arc::ptr_eq(previous.instruction_registry(), &self.instructions)
+            && previous.config() == &self.config
+        {
+            diff.changed_parts()
+        } else {
+            BodyParts::ALL
+        };
+        info!(?changed, "Starting incremental analysis run");
+        let mut context = self.context(body);
+
+        let sorted_nodes = self.sorted_nodes()?;
+        let enabled = self.enabled_passes(&sorted_nodes);
+        let mut rerun = HashSet::new();
+        for node_index in sorted_nodes {
+            let pass_id = self.graph[node_index];
+            let runner = self.runner(node_index);
+            if !enabled.contains(&pass_id) {
+                debug!(pass = runner.name(), "Skipping disabled pass");
+                continue;
+            }
+
+            let stale = runner.inputs().intersects(changed)
+                || runner.dependencies().iter().any(|dependency| rerun.contains(dependency));
+            if !stale && runner.reuse_result(previous, &mut context, &spans).is_ok() {
+                debug!(pass = runner.name(), "Reused the result of the pass");
+                continue;
+            }
+
+            info!(pass = runner.name(), "Executing analysis pass");
+            rerun.insert(pass_id);
+            runner.run_pass(&mut context).inspect_err(|e| {
+                error!(pass = runner.name(), error = ?e, "Pass failed");
+            })?;
+        }
+
+        info!(rerun = rerun.len(), "Incremental analysis run finished successfully");
+        Ok(context)
+    }
+
+    /// Creates the context to analyze a body in.
+    fn context(&self, body: Arc<hir::body::Body>) -> AnalysisContext {
+        AnalysisContext::with_config(body, Arc::clone(&self.instructions), Arc::clone(&self.config))
+    }
+
+    /// Returns the passes the configuration lets run.
+    ///
+    /// A disabled pass disables the passes that depend on it. When the passes
+    /// are restricted, the passes the allowed ones depend on run too.
+    ///
+    /// # Parameters
+    ///
+    /// * `sorted_nodes` - The passes, sorted so that every pass comes after
+    ///   its dependencies.
+    fn enabled_passes(&self, sorted_nodes: &[NodeIndex]) -> HashSet<TypeId> {
+        let mut enabled = HashSet::new();
+        if self.config.only().is_some() {
+            // Dependents come first in reverse, so they require their dependencies in time
+            let mut required = HashSet::new();
+            for &node_index in sorted_nodes.iter().rev() {
+                let pass_id = self.graph[node_index];
+                let runner = self.runner(node_index);
+                if self.config.is_enabled(runner.id()) || required.contains(&pass_id) {
+                    enabled.insert(pass_id);
+                    required.extend(runner.dependencies());
+                }
+            }
+        } else {
+            for &node_index in sorted_nodes {
+                let runner = self.runner(node_index);
+                if self.config.is_enabled(runner.id())
+                    && runner.dependencies().iter().all(|dependency| enabled.contains(dependency))
+                {
+                    enabled.insert(self.graph[node_index]);
+                }
+            }
+        }
+        enabled
+    }
+
+    /// Sorts the passes so that every pass comes after its dependencies.
+    fn sorted_nodes(&self) -> Result<Vec<NodeIndex>, AnalysisError> {
+        toposort(&self.graph, None).map_err(|cycle| {
+            let node_id = cycle.node_id();
+            let type_id = self.graph.node_weight(node_id).cloned();
+            error!(?node_id, ?type_id, "Dependency cycle detected in analysis passes");
+            AnalysisError::DependencyCycle(format!(
+                "Cycle detected involving node index {:?} (TypeId: {:?})",
+                node_id, type_id
+            ))
+        })
+    }
+
+    /// Returns the runner of the pass of a node of the dependency graph.
+    fn runner(&self, node_index: NodeIndex) -> &dyn ErasedPassRunner {
+        let pass_id = self.graph[node_index];
+        self.passes
+            .get(&pass_id)
+            .expect("Graph node TypeId should exist in passes map (internal error)")
+            .as_ref()
+    }
 }
 
 // Default implementation for AnalysisPipeline
@@ -290,6 +529,28 @@ impl AnalysisPipeline {
         &self.pass_nodes
     }
 
+    /// Returns the settings of the registered passes, by pass ID.
+    ///
+    /// # Returns
+    ///
+    /// A map from pass ID to the settings the pass declares.
+    pub fn pass_options(&self) -> BTreeMap<&'static str, Vec<PassOption>> {
+        self.passes.values().map(|runner| (runner.id(), runner.options())).collect()
+    }
+
+    /// Returns the type ID of the registered pass with an ID.
+    ///
+    /// # Parameters
+    ///
+    /// * `id` - The [ID](crate::AnalysisPass::id) of the pass.
+    ///
+    /// # Returns
+    ///
+    /// The type ID of the pass, or `None` if no registered pass has the ID.
+    pub fn pass_type_id(&self, id: &str) -> Option<TypeId> {
+        self.passes.iter().find(|(_, runner)| runner.id() == id).map(|(&pass_id, _)| pass_id)
+    }
+
     /// Returns a map of pass names.
     ///
     /// This method collects the names of all registered passes.
@@ -373,15 +634,7 @@ impl AnalysisPipeline {
         debug!("Exporting execution order in {} format", format);
 
         // Perform topological sort to get execution order
-        let sorted_nodes = toposort(&self.graph, None).map_err(|cycle| {
-            let node_id = cycle.node_id();
-            let type_id = self.graph.node_weight(node_id).cloned();
-            error!(?node_id, ?type_id, "Dependency cycle detected in analysis passes");
-            AnalysisError::DependencyCycle(format!(
-                "Cycle detected involving node index {:?} (TypeId: {:?})",
-                node_id, type_id
-            ))
-        })?;
+        let sorted_nodes = self.sorted_nodes()?;
 
         // Create a new graph with the execution order
         let mut order_graph = DiGraph::new();
@@ -430,9 +683,32 @@ trait ErasedPassRunner: Send + Sync {
     /// Returns the name of the analysis pass.
     fn name(&self) -> &'static str;
 
+    /// Returns the ID of the analysis pass.
+    fn id(&self) -> &'static str;
+
+    /// Returns the settings the analysis pass reads.
+    fn options(&self) -> Vec<PassOption>;
+
     /// Returns the dependencies of the analysis pass.
     fn dependencies(&self) -> Vec<TypeId>;
 
+    /// Returns the parts of the body the analysis pass reads.
+    fn inputs(&self) -> BodyParts;
+
+    /// Reuses the result of the analysis pass from a previous analysis.
+    ///
+    /// # Parameters
+    ///
+    /// * `previous` - The context of the previous analysis.
+    /// * `ctx` - The analysis context to reuse the result in.
+    /// * `spans` - Where the source of the previous body moved to.
+    fn reuse_result(
+        &self,
+        previous: &AnalysisContext,
+        ctx: &mut AnalysisContext,
+        spans: &SpanMap,
+    ) -> Result<(), AnalysisError>;
+
     /// Returns the type ID of the analysis pass.
     fn type_id(&self) -> TypeId;
 }
@@ -441,10 +717,12 @@ impl<P: AnalysisPass + 'static> ErasedPassRunner for P {
     #[instrument(skip(self, ctx), fields(pass_name = self.name()))]
     fn run_pass(&self, ctx: &mut AnalysisContext) -> Result<(), AnalysisError> {
         debug!("Running pass");
+        let diagnostics_start = ctx.diagnostics().len();
         match self.run(ctx) {
             Ok(output) => {
                 debug!("Pass produced output, inserting into context");
                 ctx.insert_result::<P>(output)?;
+                ctx.record_pass_diagnostics::<P>(diagnostics_start);
                 debug!("Pass finished successfully");
                 Ok(())
             }
@@ -462,10 +740,31 @@ impl<P: AnalysisPass + 'static> ErasedPassRunner for P {
         P::name(self)
     }
 
+    fn id(&self) -> &'static str {
+        P::id(self)
+    }
+
+    fn options(&self) -> Vec<PassOption> {
+        P::options(self)
+    }
+
     fn dependencies(&self) -> Vec<TypeId> {
         P::dependencies(self)
     }
 
+    fn inputs(&self) -> BodyParts {
+        P::inputs(self)
+    }
+
+    fn reuse_result(
+        &self,
+        previous: &AnalysisContext,
+        ctx: &mut AnalysisContext,
+        spans: &SpanMap,
+    ) -> Result<(), AnalysisError> {
+        ctx.reuse_result::<P>(previous, spans)
+    }
+
     fn type_id(&self) -> TypeId {
         TypeId::of::<P>()
     }
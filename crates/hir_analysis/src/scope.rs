@@ -0,0 +1,93 @@
+//! Parts of a body.
+//!
+//! This module provides the [`AnalysisScope`], a range of instructions of a body
+//! such as a basic block or the section of a label. The
+//! [`SectionAnalysis`](crate::SectionAnalysis) measures the label sections of a
+//! body.
+//!
+//! # Example
+//!
+//! ```
+//! use hir::body::Body;
+//! use hir_analysis::scope::AnalysisScope;
+//!
+//! let body = Body::default();
+//! assert!(AnalysisScope::label_sections(&body).is_empty());
+//! ```
+
+use std::ops::Range;
+
+use hir::body::Body;
+
+use crate::analyzers::control_flow::ControlFlowGraph;
+/// Identifies a scope of a body, across edits of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ScopeId {
+    /// The basic block with this index in the control flow graph.
+    BasicBlock(usize),
+    /// The instructions from a label up to the next one, or from the start of
+    /// the body up to the first label if `None`.
+    LabelSection(Option<String>),
+}
+
+/// A range of consecutive instructions of a body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalysisScope {
+    /// The identifier of the scope.
+    pub id: ScopeId,
+    /// The indices of the instructions of the scope in the body.
+    pub instructions: Range<usize>,
+}
+
+impl AnalysisScope {
+    /// Returns the scopes of the basic blocks of a control flow graph.
+    ///
+    /// Blocks without instructions are left out.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The body the graph was built from.
+    /// * `cfg` - The control flow graph of the body.
+    pub fn basic_blocks(body: &Body, cfg: &ControlFlowGraph) -> Vec<Self> {
+        cfg.basic_blocks()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, block)| {
+                let positions = block.nodes.iter().filter_map(|&node| {
+                    let instr_id = cfg.get_node(node).instruction_id?;
+                    body.instructions.values().position(|instr| instr.id == instr_id)
+                });
+                let (start, end) = positions.fold(None, |range, position| match range {
+                    Some((start, end)) => {
+                        Some((usize::min(start, position), usize::max(end, position)))
+                    }
+                    None => Some((position, position)),
+                })?;
+                Some(Self { id: ScopeId::BasicBlock(index), instructions: start..end + 1 })
+            })
+            .collect()
+    }
+
+    /// Returns the scopes of the sections of a body between its labels.
+    ///
+    /// Every labeled instruction starts a section, named by its first label.
+    /// The instructions before the first label form a section of their own.
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The body to split.
+    pub fn label_sections(body: &Body) -> Vec<Self> {
+        let mut sections: Vec<Self> = Vec::new();
+        for (index, instr) in body.instructions.values().enumerate() {
+            let label = body.labels.values().find(|label| label.instruction_id == Some(instr.id));
+            match sections.last_mut() {
+                Some(section) if label.is_none() => section.instructions.end = index + 1,
+                _ => sections.push(Self {
+                    id: ScopeId::LabelSection(label.map(|label| label.name.clone())),
+                    instructions: index..index + 1,
+                }),
+            }
+        }
+        sections
+    }
+}
@@ -0,0 +1,275 @@
+//! Tests for the alias analysis
+
+use std::sync::Arc;
+
+use hir::body::{
+    AddressingMode, ArrayAccess, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef,
+};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+
+use crate::analyzers::alias::{AliasAnalysis, AliasResult, AliasTargets, ValueRange};
+use crate::config::SettingValue;
+use crate::{
+    AnalysisConfig, AnalysisContext, AnalysisPipeline, ConstantPropagationAnalysis,
+    ControlFlowAnalysis, DataFlowAnalysis, DominanceAnalysis, SsaAnalysis,
+};
+
+/// An operand of a test instruction
+enum Operand {
+    /// An immediate value, like `=5`
+    Value(i64),
+    /// A register, like `5`
+    Direct(i64),
+    /// The memory a register points to, like `*5`
+    Indirect(i64),
+    /// The memory at a base plus the value of a register, like `2[5]`
+    Indexed(i64, i64),
+}
+
+/// Add an expression to a body
+fn push_expr(body: &mut Body, kind: ExprKind) -> ExprId {
+    let id = ExprId(body.exprs.len() as u32);
+    body.exprs.alloc(Expr { id, kind, span: 0..0 });
+    id
+}
+
+/// Create a body running the instructions in order
+fn create_body(instructions: &[(&str, Option<Operand>)]) -> Body {
+    let mut body = Body::default();
+    for (index, (opcode, operand)) in instructions.iter().enumerate() {
+        let operand = operand.as_ref().map(|operand| {
+            let (mode, address) = match *operand {
+                Operand::Value(value) => {
+                    return push_expr(&mut body, ExprKind::Literal(Literal::Int(value)));
+                }
+                Operand::Direct(register) => (
+                    AddressingMode::Direct,
+                    push_expr(&mut body, ExprKind::Literal(Literal::Int(register))),
+                ),
+                Operand::Indirect(register) => (
+                    AddressingMode::Indirect,
+                    push_expr(&mut body, ExprKind::Literal(Literal::Int(register))),
+                ),
+                Operand::Indexed(base, register) => {
+                    let array = push_expr(&mut body, ExprKind::Literal(Literal::Int(base)));
+                    let index = push_expr(&mut body, ExprKind::Literal(Literal::Int(register)));
+                    let access = ExprKind::ArrayAccess(ArrayAccess { array, index });
+                    (AddressingMode::Direct, push_expr(&mut body, access))
+                }
+            };
+            push_expr(&mut body, ExprKind::MemoryRef(MemoryRef { mode, address }))
+        });
+
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: None,
+            span: 0..0,
+            docs: Vec::new(),
+        });
+    }
+    body
+}
+
+/// Run the alias analysis and the passes it needs on a body
+fn analyze(body: Body) -> AnalysisContext {
+    analyze_with_config(body, AnalysisConfig::new())
+}
+
+/// Run the alias analysis with the given configuration on a body
+fn analyze_with_config(body: Body, config: AnalysisConfig) -> AnalysisContext {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<DominanceAnalysis>().unwrap();
+    pipeline.register::<DataFlowAnalysis>().unwrap();
+    pipeline.register::<SsaAnalysis>().unwrap();
+    pipeline.register::<ConstantPropagationAnalysis>().unwrap();
+    pipeline.register::<AliasAnalysis>().unwrap();
+    pipeline.set_config(config).unwrap();
+    pipeline.analyze(Arc::new(body)).unwrap()
+}
+
+/// Collect the messages the alias analysis reported
+fn messages(context: &AnalysisContext) -> Vec<String> {
+    context
+        .pass_diagnostics::<AliasAnalysis>()
+        .iter()
+        .map(|diagnostic| diagnostic.message.clone())
+        .collect()
+}
+
+/// Get the results of the alias analysis
+fn result(context: &AnalysisContext) -> Arc<AliasResult> {
+    context.get_result::<AliasAnalysis>().unwrap()
+}
+
+#[test]
+fn test_value_range() {
+    let range = ValueRange { min: 2, max: 5 };
+    assert!(range.contains(2) && range.contains(5) && !range.contains(6));
+    assert!(range.overlaps(ValueRange::constant(5)));
+    assert!(!range.overlaps(ValueRange { min: 6, max: 9 }));
+    assert_eq!(range.join(ValueRange::constant(8)), ValueRange { min: 2, max: 8 });
+    assert_eq!(range.sub(ValueRange::constant(1)), Some(ValueRange { min: 1, max: 4 }));
+    assert_eq!(ValueRange::constant(i64::MAX).add(ValueRange::constant(1)), None);
+    assert_eq!(range.mul(ValueRange { min: -1, max: 2 }), Some(ValueRange { min: -5, max: 10 }));
+    assert_eq!(ValueRange::constant(i64::MIN).mul(ValueRange::constant(-1)), None);
+    assert_eq!(ValueRange::constant(3).as_constant(), Some(3));
+    assert_eq!(range.as_constant(), None);
+}
+
+#[test]
+fn test_constant_pointer() {
+    let context = analyze(create_body(&[
+        ("LOAD", Some(Operand::Value(5))),
+        ("STORE", Some(Operand::Direct(1))),
+        ("LOAD", Some(Operand::Value(7))),
+        ("STORE", Some(Operand::Indirect(1))),
+        ("LOAD", Some(Operand::Indirect(1))),
+        ("HALT", None),
+    ]));
+
+    let result = result(&context);
+    assert_eq!(result.targets(LocalDefId(3)).and_then(AliasTargets::must_alias), Some(5));
+    assert_eq!(result.targets(LocalDefId(4)).and_then(AliasTargets::must_alias), Some(5));
+    assert_eq!(result.targets(LocalDefId(1)), None);
+    assert!(messages(&context).is_empty());
+}
+
+#[test]
+fn test_uninitialized_read_and_unused_write() {
+    let context = analyze(create_body(&[
+        ("LOAD", Some(Operand::Value(5))),
+        ("STORE", Some(Operand::Direct(1))),
+        ("ADD", Some(Operand::Value(1))),
+        ("STORE", Some(Operand::Direct(2))),
+        ("STORE", Some(Operand::Indirect(1))),
+        ("LOAD", Some(Operand::Indirect(2))),
+        ("HALT", None),
+    ]));
+
+    assert_eq!(
+        messages(&context),
+        ["Uninitialized memory read at address 6", "Unused memory write at address 5"]
+    );
+}
+
+#[test]
+fn test_unused_writes_can_be_silenced() {
+    let body = create_body(&[
+        ("LOAD", Some(Operand::Value(5))),
+        ("STORE", Some(Operand::Direct(1))),
+        ("STORE", Some(Operand::Indirect(1))),
+        ("HALT", None),
+    ]);
+    let config = AnalysisConfig::new().with_setting(
+        "alias",
+        "report-unused-writes",
+        SettingValue::Bool(false),
+    );
+
+    let context = analyze_with_config(body, config);
+    assert!(messages(&context).is_empty());
+    assert_eq!(result(&context).targets(LocalDefId(2)).and_then(AliasTargets::must_alias), Some(5));
+}
+
+#[test]
+fn test_arithmetic_that_may_overflow() {
+    // Nothing is known about the accumulator after the overflow, so the
+    // subtraction is not reported
+    let body = create_body(&[
+        ("LOAD", Some(Operand::Value(5))),
+        ("MUL", Some(Operand::Value(2))),
+        ("LOAD", Some(Operand::Value(i64::MAX))),
+        ("STORE", Some(Operand::Direct(1))),
+        ("LOAD", Some(Operand::Value(1))),
+        ("ADD", Some(Operand::Direct(1))),
+        ("SUB", Some(Operand::Value(1))),
+        ("HALT", None),
+    ]);
+
+    let context = analyze(body.clone());
+    assert_eq!(messages(&context), ["Arithmetic may overflow"]);
+    let diagnostic = &context.pass_diagnostics::<AliasAnalysis>()[0];
+    assert!(
+        diagnostic
+            .help
+            .starts_with(&format!("The accumulator is 1 and the operand is {}", i64::MAX))
+    );
+
+    let config =
+        AnalysisConfig::new().with_setting("alias", "report-overflows", SettingValue::Bool(false));
+    assert!(messages(&analyze_with_config(body.clone(), config)).is_empty());
+
+    // Arithmetic that wraps or saturates never stops a run
+    for mode in ["wrapping", "saturating"] {
+        let config = AnalysisConfig::new().with_setting(
+            "alias",
+            "arithmetic",
+            SettingValue::String(mode.to_string()),
+        );
+        assert!(messages(&analyze_with_config(body.clone(), config)).is_empty(), "{mode}");
+    }
+}
+
+#[test]
+fn test_indexed_range() {
+    // Register 1 counts down from 3, and `10[1]` is written on the way
+    let mut body = create_body(&[
+        ("LOAD", Some(Operand::Value(3))),
+        ("STORE", Some(Operand::Direct(1))),
+        ("STORE", Some(Operand::Indexed(10, 1))),
+        ("SUB", Some(Operand::Value(1))),
+        ("STORE", Some(Operand::Direct(1))),
+        ("JGTZ", None),
+        ("LOAD", Some(Operand::Indexed(20, 1))),
+        ("HALT", None),
+    ]);
+    let target = push_expr(&mut body, ExprKind::Literal(Literal::Label("loop".into())));
+    body.instruction_mut(5).unwrap().operand = Some(target);
+    body.labels.alloc(Label {
+        id: LocalDefId(8),
+        name: "loop".to_string(),
+        instruction_id: Some(LocalDefId(2)),
+        span: 0..0,
+        docs: Vec::new(),
+    });
+
+    let context = analyze(body);
+    let result = result(&context);
+    assert_eq!(
+        result.targets(LocalDefId(2)),
+        Some(AliasTargets::Range(ValueRange { min: 11, max: 13 }))
+    );
+
+    // The loop only exits once register 1 is zero
+    assert_eq!(result.targets(LocalDefId(6)).and_then(AliasTargets::must_alias), Some(20));
+    assert_eq!(
+        messages(&context),
+        ["Uninitialized memory read at address 20", "Unused memory write at addresses 11 to 13"]
+    );
+}
+
+#[test]
+fn test_unknown_pointer_may_alias_all() {
+    let context = analyze(create_body(&[
+        ("READ", Some(Operand::Direct(1))),
+        ("LOAD", Some(Operand::Value(7))),
+        ("STORE", Some(Operand::Indirect(1))),
+        ("LOAD", Some(Operand::Value(5))),
+        ("STORE", Some(Operand::Direct(2))),
+        ("LOAD", Some(Operand::Indirect(2))),
+        ("HALT", None),
+    ]));
+
+    let result = result(&context);
+    assert_eq!(result.targets(LocalDefId(2)), Some(AliasTargets::MayAliasAll));
+    assert_eq!(result.targets(LocalDefId(5)).and_then(AliasTargets::must_alias), Some(5));
+
+    // The write may have initialized the read, and the read may observe it
+    assert!(messages(&context).is_empty());
+}
@@ -1,10 +1,12 @@
 use std::collections::HashSet;
 
-use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::body::{AddressingMode, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef};
 use hir::expr::ExprId;
 use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+use ram_diagnostics::{Applicability, DiagnosticKind};
 
-use crate::analyzers::control_flow::ControlFlowAnalysis;
+use crate::analyzers::control_flow::{ControlFlowAnalysis, FALLS_OFF_END_CODE};
 use crate::analyzers::data_flow::DataFlowAnalysis;
 use crate::analyzers::instruction_validation::InstructionValidationAnalysis;
 use crate::context::AnalysisContext;
@@ -15,66 +17,75 @@ fn create_test_body() -> Body {
     let mut body = Body::default();
 
     // Add some instructions
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(0),
         opcode: "LOAD".to_string(),
+        kind: InstructionKind::from_name("LOAD"),
         operand: Some(ExprId(0)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(1),
         opcode: "ADD".to_string(),
+        kind: InstructionKind::from_name("ADD"),
         operand: Some(ExprId(1)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(2),
         opcode: "STORE".to_string(),
+        kind: InstructionKind::from_name("STORE"),
         operand: Some(ExprId(2)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(3),
         opcode: "JUMP".to_string(),
+        kind: InstructionKind::from_name("JUMP"),
         operand: Some(ExprId(3)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     // Add a label
-    body.labels.push(Label {
+    body.labels.alloc(Label {
         id: LocalDefId(4),
         name: "LOOP".to_string(),
         instruction_id: Some(LocalDefId(0)),
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     // Add some expressions
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(0),
         kind: ExprKind::Literal(Literal::Int(10)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(1),
         kind: ExprKind::Literal(Literal::Int(20)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(2),
         kind: ExprKind::Literal(Literal::Int(30)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(3),
         kind: ExprKind::Literal(Literal::Label("LOOP".to_string())),
         span: 0..0, // Default span
@@ -116,6 +127,36 @@ fn test_control_flow_analysis() {
     assert!(result.has_path(jump_node_idx, load_node_idx));
 }
 
+#[test]
+fn test_control_flow_falls_off_end() {
+    // Ending the program with `JUMP` never falls off its end
+    let mut context = AnalysisContext::from(create_test_body());
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    assert_eq!(cfg.falls_off_end(), None);
+
+    // `JGTZ` continues past the last instruction when it does not jump
+    let mut body = create_test_body();
+    let last = body.instructions.values_mut().next_back().unwrap();
+    last.opcode = "JGTZ".to_string();
+    last.kind = InstructionKind::from_name("JGTZ");
+    last.span = 20..29;
+    let mut context = AnalysisContext::from(body);
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    assert_eq!(cfg.falls_off_end(), cfg.get_node_by_instruction(LocalDefId(3)));
+
+    let diagnostic = context
+        .diagnostics()
+        .diagnostics()
+        .iter()
+        .find(|d| d.code.as_deref() == Some(FALLS_OFF_END_CODE))
+        .expect("falling off the end should be reported");
+    assert_eq!(diagnostic.kind, DiagnosticKind::Warning);
+    assert_eq!(diagnostic.suggestions.len(), 1);
+    assert_eq!(diagnostic.suggestions[0].span, 29..29);
+    assert_eq!(diagnostic.suggestions[0].replacement, "\nHALT");
+    assert_eq!(diagnostic.suggestions[0].applicability, Applicability::MaybeIncorrect);
+}
+
 #[test]
 fn test_data_flow_analysis() {
     // Create a new context with the test body
@@ -140,6 +181,40 @@ fn test_data_flow_analysis() {
     assert!(uninitialized.is_empty());
 }
 
+#[test]
+fn test_data_flow_mermaid_with_context() {
+    // `STORE 1` then `LOAD 1`, sharing the operand
+    let mut body = Body::default();
+    body.exprs.alloc(Expr { id: ExprId(0), kind: ExprKind::Literal(Literal::Int(1)), span: 0..0 });
+    body.exprs.alloc(Expr {
+        id: ExprId(1),
+        kind: ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address: ExprId(0) }),
+        span: 0..0,
+    });
+    for (index, opcode) in ["STORE", "LOAD", "HALT"].into_iter().enumerate() {
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand: (opcode != "HALT").then_some(ExprId(1)),
+            label_name: None,
+            span: 0..0,
+            docs: Vec::new(),
+        });
+    }
+
+    let mut context = AnalysisContext::from(body);
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    context.store_result::<ControlFlowAnalysis>(cfg);
+    let dfg = DataFlowAnalysis.run(&mut context).unwrap();
+
+    let mermaid = dfg.to_mermaid_with_context(&context);
+    assert!(mermaid.starts_with("graph TD\n"));
+    assert!(mermaid.contains("N0[\"STORE 1\"]"));
+    assert!(mermaid.contains("N2[\"HALT\"]"));
+    assert!(mermaid.contains("N0 -->|\"M[1]\"| N1"));
+}
+
 #[test]
 fn test_instruction_validation() {
     let body = create_test_body();
@@ -154,12 +229,14 @@ fn test_instruction_validation() {
 
     // Create a body with an invalid instruction
     let mut invalid_body = Body::default();
-    invalid_body.instructions.push(Instruction {
+    invalid_body.instructions.alloc(Instruction {
         id: LocalDefId(0),
         opcode: "INVALID".to_string(),
+        kind: InstructionKind::from_name("INVALID"),
         operand: None,
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     let mut invalid_context = AnalysisContext::from(invalid_body);
@@ -0,0 +1,126 @@
+//! Tests for the call graph and the interprocedural export
+
+use std::sync::Arc;
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+use ram_diagnostics::DiagnosticKind;
+
+use crate::analyzers::control_flow::{ControlFlowAnalysis, ControlFlowGraph};
+use crate::context::AnalysisContext;
+use crate::export::{ExportFormat, ExportOptions};
+use crate::pass::AnalysisPass;
+
+/// Create an instruction of a test body
+fn instruction(id: u32, opcode: &str, operand: Option<u32>, label: Option<&str>) -> Instruction {
+    Instruction {
+        id: LocalDefId(id),
+        opcode: opcode.to_string(),
+        kind: InstructionKind::from_name(opcode),
+        operand: operand.map(ExprId),
+        label_name: label.map(str::to_string),
+        span: 0..0,
+        docs: Vec::new(),
+    }
+}
+
+/// Create a label of a test body
+fn label(id: u32, name: &str, instruction_id: u32) -> Label {
+    Label {
+        id: LocalDefId(id),
+        name: name.to_string(),
+        instruction_id: Some(LocalDefId(instruction_id)),
+        span: 0..0,
+        docs: Vec::new(),
+    }
+}
+
+/// Create a body for `CALL a`, `HALT`, `a: CALL b`, `RET`, `b: CALL a` and `RET`
+fn create_recursive_body() -> Body {
+    let mut body = Body::default();
+    body.instructions.alloc(instruction(0, "CALL", Some(0), None));
+    body.instructions.alloc(instruction(1, "HALT", None, None));
+    body.instructions.alloc(instruction(2, "CALL", Some(1), Some("a")));
+    body.instructions.alloc(instruction(3, "RET", None, None));
+    body.instructions.alloc(instruction(4, "CALL", Some(2), Some("b")));
+    body.instructions.alloc(instruction(5, "RET", None, None));
+    body.labels.alloc(label(6, "a", 2));
+    body.labels.alloc(label(7, "b", 4));
+    for (id, name) in [(0, "a"), (1, "b"), (2, "a")] {
+        body.exprs.alloc(Expr {
+            id: ExprId(id),
+            kind: ExprKind::Literal(Literal::Label(name.to_string())),
+            span: 0..0,
+        });
+    }
+    body
+}
+
+/// Run the control flow analysis with the subroutine instructions
+fn analyze(body: Body) -> (AnalysisContext, ControlFlowGraph) {
+    let mut context = AnalysisContext::with_instruction_registry(
+        Arc::new(body),
+        Arc::new(ram_core::subroutine_instructions()),
+    );
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    (context, cfg)
+}
+
+#[test]
+fn test_call_graph_of_subroutines() {
+    let (context, cfg) = analyze(create_recursive_body());
+    let node = |id| cfg.get_node_by_instruction(LocalDefId(id)).unwrap();
+    let call_graph = cfg.call_graph();
+
+    let names =
+        call_graph.procedures().map(|procedure| procedure.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["main", "a", "b"]);
+    assert_eq!(call_graph.procedure("main").unwrap().nodes, vec![node(0), node(1)]);
+    assert_eq!(call_graph.procedure("a").unwrap().nodes, vec![node(2), node(3)]);
+    assert_eq!(call_graph.procedure("b").unwrap().entry, node(4));
+
+    assert_eq!(call_graph.callees("main"), vec!["a"]);
+    assert_eq!(call_graph.callees("a"), vec!["b"]);
+    assert_eq!(call_graph.callees("b"), vec!["a"]);
+    assert_eq!(call_graph.calls().count(), 3);
+
+    // `a` and `b` call each other, which is reported once for each
+    assert_eq!(call_graph.recursive_groups(), vec![vec!["a", "b"]]);
+    assert!(!call_graph.is_recursive("main"));
+    let infos = context
+        .diagnostics()
+        .diagnostics()
+        .iter()
+        .filter(|diagnostic| diagnostic.kind == DiagnosticKind::Info)
+        .map(|diagnostic| diagnostic.message.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(infos, vec!["Recursive subroutine 'a'", "Recursive subroutine 'b'"]);
+}
+
+#[test]
+fn test_interprocedural_export() {
+    let (context, cfg) = analyze(create_recursive_body());
+    let options = ExportOptions { interprocedural: true, ..Default::default() };
+
+    let mermaid = cfg.export(ExportFormat::Mermaid, &options, Some(&context));
+    assert!(mermaid.contains("subgraph P0[\"main\"]"));
+    assert!(mermaid.contains("subgraph P1[\"a (recursive)\"]"));
+    assert!(mermaid.contains("N0[\"CALL :a\"]"));
+    assert!(mermaid.contains("N0 ==>|call| N2"));
+    assert!(mermaid.contains("N3 -.->|return| N1"));
+
+    let dot = cfg.export(ExportFormat::Dot, &options, None);
+    assert!(dot.contains("subgraph cluster_2 {"));
+    assert!(dot.contains("N4 -> N2 [style=bold];"));
+
+    // Without the option, the nodes are not grouped
+    let flat = cfg.export(ExportFormat::Mermaid, &ExportOptions::default(), Some(&context));
+    assert!(!flat.contains("subgraph"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&cfg.export(ExportFormat::Json, &options, None)).unwrap();
+    assert_eq!(json["procedures"][2]["calls"], serde_json::json!(["a"]));
+    assert_eq!(json["procedures"][1]["recursive"], serde_json::json!(true));
+}
@@ -4,11 +4,13 @@ use base_db::input::FileId;
 use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
 use hir::expr::ExprId;
 use hir::ids::{DefId, LocalDefId};
+use ram_core::InstructionKind;
 
 use crate::analyzers::constant_propagation::ConstantPropagationAnalysis;
 use crate::analyzers::control_flow::ControlFlowAnalysis;
 use crate::analyzers::control_flow_optimizer::ControlFlowOptimizer;
-use crate::analyzers::data_flow::DataFlowAnalysis;
+use crate::analyzers::dominance::DominanceAnalysis;
+use crate::analyzers::ssa::SsaAnalysis;
 use crate::context::AnalysisContext;
 use crate::pass::AnalysisPass;
 
@@ -26,82 +28,95 @@ fn create_test_body_with_constant_condition() -> Body {
     // loop: LOAD =30 ; This will always be executed
     // HALT      ; This will always be executed
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(0),
         opcode: "LOAD".to_string(),
+        kind: InstructionKind::from_name("LOAD"),
         operand: Some(ExprId(0)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(1),
         opcode: "JGTZ".to_string(),
+        kind: InstructionKind::from_name("JGTZ"),
         operand: Some(ExprId(1)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(2),
         opcode: "LOAD".to_string(),
+        kind: InstructionKind::from_name("LOAD"),
         operand: Some(ExprId(2)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(3),
         opcode: "HALT".to_string(),
+        kind: InstructionKind::from_name("HALT"),
         operand: None,
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(4),
         opcode: "LOAD".to_string(),
+        kind: InstructionKind::from_name("LOAD"),
         operand: Some(ExprId(3)),
         label_name: Some("loop".to_string()),
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(5),
         opcode: "HALT".to_string(),
+        kind: InstructionKind::from_name("HALT"),
         operand: None,
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     // Add a label
-    body.labels.push(Label {
+    body.labels.alloc(Label {
         id: LocalDefId(6),
         name: "loop".to_string(),
         instruction_id: Some(LocalDefId(4)),
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     // Add expressions
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(0),
         kind: ExprKind::Literal(Literal::Int(10)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(1),
         kind: ExprKind::Literal(Literal::Label("loop".to_string())),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(2),
         kind: ExprKind::Literal(Literal::Int(20)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(3),
         kind: ExprKind::Literal(Literal::Int(30)),
         span: 0..0, // Default span
@@ -121,10 +136,12 @@ fn test_control_flow_optimizer() {
     let cf_result = cf_analysis.run(&mut context).unwrap();
     context.store_result::<ControlFlowAnalysis>(cf_result);
 
-    // Run the data flow analysis (dependency for constant propagation)
-    let df_analysis = DataFlowAnalysis;
-    let df_result = df_analysis.run(&mut context).unwrap();
-    context.store_result::<DataFlowAnalysis>(df_result);
+    // Run the dominance analysis and number the versions of the accumulator
+    // (dependencies for constant propagation)
+    let dominance_result = DominanceAnalysis.run(&mut context).unwrap();
+    context.store_result::<DominanceAnalysis>(dominance_result);
+    let ssa_result = SsaAnalysis.run(&mut context).unwrap();
+    context.store_result::<SsaAnalysis>(ssa_result);
 
     // Run the constant propagation analysis (dependency)
     let const_prop_analysis = ConstantPropagationAnalysis;
@@ -157,4 +174,11 @@ fn test_control_flow_optimizer() {
     let unreachable_node = optimized_cfg.get_node_by_instruction(unreachable_instr_id).unwrap();
 
     assert!(!optimized_cfg.has_path(entry_node, unreachable_node));
+
+    // The blocks are `LOAD =10; JGTZ loop`, `LOAD =20; HALT` and `loop: LOAD =30; HALT`
+    assert_eq!(result.blocks.block_count(), 3);
+    assert_eq!(result.blocks.block_of(unreachable_node), Some(1));
+    assert_eq!(result.unreachable_blocks, [1]);
+    // Once the branch is always taken, the jump and its target run one after the other
+    assert_eq!(result.mergeable_blocks, [(0, 2)]);
 }
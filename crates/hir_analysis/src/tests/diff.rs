@@ -0,0 +1,237 @@
+//! Tests for diffing bodies and reanalyzing edited bodies
+
+use std::any::TypeId;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use miette::Diagnostic;
+use ram_core::InstructionKind;
+
+use crate::diff::{BodyDiff, BodyParts};
+use crate::pass::AnalysisPass;
+use crate::{AnalysisContext, AnalysisPipeline, ControlFlowAnalysis, UnusedLabelAnalysis};
+
+/// Counts its runs, reading only the labels
+#[derive(Default)]
+struct LabelPass {
+    runs: Arc<AtomicUsize>,
+}
+
+impl AnalysisPass for LabelPass {
+    type Output = usize;
+
+    fn name(&self) -> &'static str {
+        "LabelPass"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::LABELS
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        Ok(ctx.body().labels.len())
+    }
+}
+
+/// Counts its runs, reading only the result of [`LabelPass`]
+#[derive(Default)]
+struct DependentPass {
+    runs: Arc<AtomicUsize>,
+}
+
+impl AnalysisPass for DependentPass {
+    type Output = usize;
+
+    fn name(&self) -> &'static str {
+        "DependentPass"
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<LabelPass>()]
+    }
+
+    fn inputs(&self) -> BodyParts {
+        BodyParts::NONE
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        let labels = match ctx.get_result::<LabelPass>() {
+            Ok(result) => result,
+            Err(e) => return Err(Box::new(e)),
+        };
+        Ok(*labels * 2)
+    }
+}
+
+/// Create a body with an unused `end` label
+fn create_test_body() -> Body {
+    let mut body = Body::default();
+    body.instructions = [
+        Instruction {
+            id: LocalDefId(2),
+            opcode: "LOAD".to_string(),
+            kind: InstructionKind::from_name("LOAD"),
+            operand: Some(ExprId(0)),
+            label_name: None,
+            span: 0..6,
+            docs: Vec::new(),
+        },
+        Instruction {
+            id: LocalDefId(3),
+            opcode: "HALT".to_string(),
+            kind: InstructionKind::from_name("HALT"),
+            operand: None,
+            label_name: Some("end".to_string()),
+            span: 12..16,
+            docs: Vec::new(),
+        },
+    ]
+    .into_iter()
+    .collect();
+    body.exprs = [Expr { id: ExprId(0), kind: ExprKind::Literal(Literal::Int(1)), span: 5..6 }]
+        .into_iter()
+        .collect();
+    body.labels = [Label {
+        id: LocalDefId(0),
+        name: "end".to_string(),
+        instruction_id: Some(LocalDefId(3)),
+        span: 7..11,
+        docs: Vec::new(),
+    }]
+    .into_iter()
+    .collect();
+    body
+}
+
+#[test]
+fn test_body_diff() {
+    let body = create_test_body();
+    assert!(BodyDiff::between(&body, &body).is_empty());
+
+    let mut edited = create_test_body();
+    edited.instruction_mut(1).unwrap().docs = vec!["Stops".to_string()];
+    edited.exprs[ExprId(0).idx()].kind = ExprKind::Literal(Literal::Int(2));
+    let diff = BodyDiff::between(&body, &edited);
+    assert_eq!(diff.instructions.iter().copied().collect::<Vec<_>>(), [LocalDefId(3)]);
+    assert_eq!(diff.exprs.iter().copied().collect::<Vec<_>>(), [ExprId(0)]);
+    assert!(diff.labels.is_empty());
+    assert_eq!(diff.changed_parts(), BodyParts::INSTRUCTIONS.union(BodyParts::EXPRS));
+
+    // Moved code only changes the spans
+    edited = create_test_body();
+    for instr in edited.instructions.values_mut() {
+        instr.span = instr.span.start + 1..instr.span.end + 1;
+    }
+    let diff = BodyDiff::between(&body, &edited);
+    assert!(diff.instructions.is_empty());
+    assert_eq!(diff.changed_parts(), BodyParts::SPANS);
+
+    // Removed and reordered items count as changed
+    edited = create_test_body();
+    edited.instructions = edited.instructions.values().rev().cloned().collect();
+    edited.labels.clear();
+    let diff = BodyDiff::between(&body, &edited);
+    assert_eq!(diff.instructions.len(), 2);
+    assert_eq!(diff.labels.len(), 1);
+    assert_eq!(
+        diff.changed_parts(),
+        BodyParts::INSTRUCTIONS.union(BodyParts::LABELS).union(BodyParts::SPANS)
+    );
+}
+
+#[test]
+fn test_reanalyze_reruns_stale_passes() {
+    let label_runs = Arc::new(AtomicUsize::new(0));
+    let dependent_runs = Arc::new(AtomicUsize::new(0));
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register_pass(LabelPass { runs: Arc::clone(&label_runs) }).unwrap();
+    pipeline.register_pass(DependentPass { runs: Arc::clone(&dependent_runs) }).unwrap();
+
+    let context = pipeline.analyze(Arc::new(create_test_body())).unwrap();
+    assert_eq!((label_runs.load(Ordering::SeqCst), dependent_runs.load(Ordering::SeqCst)), (1, 1));
+
+    // Editing an instruction leaves the results of the passes valid
+    let mut edited = create_test_body();
+    edited.instruction_mut(0).unwrap().docs = vec!["Loads one".to_string()];
+    let context = pipeline.reanalyze(&context, Arc::new(edited.clone())).unwrap();
+    assert_eq!((label_runs.load(Ordering::SeqCst), dependent_runs.load(Ordering::SeqCst)), (1, 1));
+    assert_eq!(*context.get_result::<DependentPass>().unwrap(), 2);
+
+    // Editing a label reruns the pass reading labels and the pass depending on it
+    edited.labels.clear();
+    let context = pipeline.reanalyze(&context, Arc::new(edited)).unwrap();
+    assert_eq!((label_runs.load(Ordering::SeqCst), dependent_runs.load(Ordering::SeqCst)), (2, 2));
+    assert_eq!(*context.get_result::<DependentPass>().unwrap(), 0);
+}
+
+#[test]
+fn test_reanalyze_keeps_diagnostics_of_reused_passes() {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<UnusedLabelAnalysis>().unwrap();
+
+    let context = pipeline.analyze(Arc::new(create_test_body())).unwrap();
+    assert_eq!(context.pass_diagnostics::<UnusedLabelAnalysis>().len(), 1);
+
+    let mut edited = create_test_body();
+    edited.instruction_mut(0).unwrap().docs = vec!["Loads one".to_string()];
+    let reanalyzed = pipeline.reanalyze(&context, Arc::new(edited)).unwrap();
+    assert!(Arc::ptr_eq(
+        &context.get_result::<UnusedLabelAnalysis>().unwrap(),
+        &reanalyzed.get_result::<UnusedLabelAnalysis>().unwrap()
+    ));
+    assert!(!Arc::ptr_eq(
+        &context.get_result::<ControlFlowAnalysis>().unwrap(),
+        &reanalyzed.get_result::<ControlFlowAnalysis>().unwrap()
+    ));
+    let messages = |context: &AnalysisContext| {
+        context
+            .pass_diagnostics::<UnusedLabelAnalysis>()
+            .iter()
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(messages(&reanalyzed), messages(&context));
+    assert_eq!(reanalyzed.diagnostics().len(), context.diagnostics().len());
+}
+
+#[test]
+fn test_reanalyze_moves_diagnostics_of_reused_passes() {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<UnusedLabelAnalysis>().unwrap();
+
+    let context = pipeline.analyze(Arc::new(create_test_body())).unwrap();
+    let spans = |context: &AnalysisContext| {
+        context.pass_diagnostics::<UnusedLabelAnalysis>()[0]
+            .labeled_spans
+            .iter()
+            .map(|(span, _)| span.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(spans(&context), [7..11]);
+
+    // A comment line inserted between the instructions moves the code after it
+    let mut edited = create_test_body();
+    edited.instruction_mut(1).unwrap().span = 17..21;
+    edited.labels.values_mut().for_each(|label| label.span = 12..16);
+    let reanalyzed = pipeline.reanalyze(&context, Arc::new(edited)).unwrap();
+    assert!(Arc::ptr_eq(
+        &context.get_result::<ControlFlowAnalysis>().unwrap(),
+        &reanalyzed.get_result::<ControlFlowAnalysis>().unwrap()
+    ));
+    assert!(Arc::ptr_eq(
+        &context.get_result::<UnusedLabelAnalysis>().unwrap(),
+        &reanalyzed.get_result::<UnusedLabelAnalysis>().unwrap()
+    ));
+    assert_eq!(spans(&reanalyzed), [12..16]);
+}
@@ -0,0 +1,214 @@
+//! Tests for the dominance analysis
+
+use std::sync::Arc;
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use petgraph::graph::NodeIndex;
+use ram_core::InstructionKind;
+
+use crate::analyzers::dominance::{DEAD_CODE_CODE, DominanceAnalysis, DominanceInfo};
+use crate::export::ExportFormat;
+use crate::{AnalysisContext, AnalysisPipeline, ControlFlowAnalysis, ControlFlowGraph};
+
+/// Create an instruction with an operand
+fn instruction(id: u32, opcode: &str, operand: Option<u32>, offset: usize) -> Instruction {
+    Instruction {
+        id: LocalDefId(id),
+        opcode: opcode.to_string(),
+        kind: InstructionKind::from_name(opcode),
+        operand: operand.map(ExprId),
+        label_name: None,
+        span: offset..offset + 5,
+        docs: Vec::new(),
+    }
+}
+
+/// Create a label of an instruction
+fn label(id: u32, name: &str, instruction_id: u32) -> Label {
+    Label {
+        id: LocalDefId(id),
+        name: name.to_string(),
+        instruction_id: Some(LocalDefId(instruction_id)),
+        span: 0..0,
+        docs: Vec::new(),
+    }
+}
+
+/// Create an expression referring to a label
+fn label_expr(id: u32, name: &str) -> Expr {
+    Expr { id: ExprId(id), kind: ExprKind::Literal(Literal::Label(name.into())), span: 0..0 }
+}
+
+/// Create the body of a loop that counts the accumulator down to zero
+///
+/// ```text
+///       READ 1
+/// loop: JZERO end
+///       SUB =1
+///       JUMP loop
+/// end:  HALT
+/// ```
+fn create_loop_body() -> Body {
+    let mut body = Body::default();
+    body.instructions = [
+        instruction(2, "READ", Some(0), 0),
+        instruction(3, "JZERO", Some(1), 10),
+        instruction(4, "SUB", Some(2), 20),
+        instruction(5, "JUMP", Some(3), 30),
+        instruction(6, "HALT", None, 40),
+    ]
+    .into_iter()
+    .collect();
+    body.exprs = [
+        Expr { id: ExprId(0), kind: ExprKind::Literal(Literal::Int(1)), span: 0..0 },
+        label_expr(1, "end"),
+        Expr { id: ExprId(2), kind: ExprKind::Literal(Literal::Int(1)), span: 0..0 },
+        label_expr(3, "loop"),
+    ]
+    .into_iter()
+    .collect();
+    body.labels = [label(0, "loop", 3), label(1, "end", 6)].into_iter().collect();
+    body
+}
+
+/// Analyze a body with the control flow and dominance analyses
+fn analyze(body: Body) -> AnalysisContext {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<DominanceAnalysis>().unwrap();
+    pipeline.analyze(Arc::new(body)).unwrap()
+}
+
+/// Get the node of an instruction
+fn node(cfg: &ControlFlowGraph, id: u32) -> NodeIndex {
+    cfg.get_node_by_instruction(LocalDefId(id)).unwrap()
+}
+
+#[test]
+fn test_dominator_queries() {
+    let context = analyze(create_loop_body());
+    let cfg = context.get_result::<ControlFlowAnalysis>().unwrap();
+    let info = context.get_result::<DominanceAnalysis>().unwrap();
+    let [read, header, sub, jump, halt] = [2, 3, 4, 5, 6].map(|id| node(&cfg, id));
+
+    assert_eq!(info.dominators.roots(), [read]);
+    assert_eq!(info.immediate_dominator(read), None);
+    assert_eq!(info.immediate_dominator(header), Some(read));
+    assert_eq!(info.immediate_dominator(halt), Some(header));
+    assert_eq!(info.dominators.children(header), [sub, halt]);
+    assert!(info.dominates(header, jump));
+    assert!(!info.dominates(sub, halt));
+    assert!(info.dominators.strictly_dominates(read, header));
+    assert!(!info.dominators.strictly_dominates(header, header));
+
+    // The dominance of the body of the loop ends at its header
+    assert_eq!(info.dominance_frontier(sub), [header].into());
+    assert_eq!(info.dominance_frontier(header), [header].into());
+    assert!(info.dominance_frontier(read).is_empty());
+
+    // Every path to the end of the program goes through the test of the loop
+    assert_eq!(info.post_dominators.roots(), [halt]);
+    assert_eq!(info.immediate_post_dominator(sub), Some(jump));
+    assert_eq!(info.immediate_post_dominator(jump), Some(header));
+    assert!(info.post_dominates(header, read));
+    assert!(!info.post_dominates(sub, read));
+}
+
+#[test]
+fn test_natural_loops() {
+    let context = analyze(create_loop_body());
+    let cfg = context.get_result::<ControlFlowAnalysis>().unwrap();
+    let info = context.get_result::<DominanceAnalysis>().unwrap();
+    let [read, header, sub, jump, halt] = [2, 3, 4, 5, 6].map(|id| node(&cfg, id));
+
+    assert_eq!(info.loops.len(), 1);
+    let natural_loop = &info.loops[0];
+    assert_eq!(natural_loop.header, header);
+    assert_eq!(natural_loop.latches, [jump]);
+    assert_eq!(natural_loop.nodes, [header, sub, jump].into());
+    assert_eq!(natural_loop.exits, [halt]);
+    assert!(!natural_loop.is_infinite());
+    assert_eq!(info.innermost_loop(sub), Some(natural_loop));
+    assert_eq!(info.innermost_loop(read), None);
+
+    assert!(context.pass_diagnostics::<DominanceAnalysis>().is_empty());
+}
+
+#[test]
+fn test_infinite_loop_and_unreachable_code() {
+    // `loop: JUMP loop` never exits, so the `HALT` after it never runs
+    let mut body = Body::default();
+    body.instructions = [instruction(1, "JUMP", Some(0), 0), instruction(2, "HALT", None, 10)]
+        .into_iter()
+        .collect();
+    body.exprs = [label_expr(0, "loop")].into_iter().collect();
+    body.labels = [label(0, "loop", 1)].into_iter().collect();
+
+    let context = analyze(body);
+    let info = context.get_result::<DominanceAnalysis>().unwrap();
+    assert_eq!(info.loops.len(), 1);
+    assert!(info.loops[0].is_infinite());
+
+    let diagnostics = context.pass_diagnostics::<DominanceAnalysis>();
+    let unreachable = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.message == "Unreachable code")
+        .expect("the instruction after the loop should be unreachable");
+    assert_eq!(unreachable.labeled_spans[0].0, 10..15);
+    assert_eq!(unreachable.labeled_spans[1].0, 0..5);
+    assert_eq!(unreachable.code.as_deref(), Some(DEAD_CODE_CODE));
+    assert!(diagnostics.iter().any(|diagnostic| diagnostic.message == "Infinite loop"));
+}
+
+#[test]
+fn test_labeled_unreachable_code_is_not_dead_code() {
+    // `skip: WRITE 1` is jumped over, but its label could be jumped to
+    let mut body = Body::default();
+    body.instructions = [
+        instruction(1, "JUMP", Some(0), 0),
+        instruction(2, "WRITE", Some(1), 10),
+        instruction(3, "HALT", None, 20),
+    ]
+    .into_iter()
+    .collect();
+    body.exprs = [
+        label_expr(0, "end"),
+        Expr { id: ExprId(1), kind: ExprKind::Literal(Literal::Int(1)), span: 0..0 },
+    ]
+    .into_iter()
+    .collect();
+    body.labels = [label(4, "skip", 2), label(5, "end", 3)].into_iter().collect();
+
+    let context = analyze(body);
+    let diagnostics = context.pass_diagnostics::<DominanceAnalysis>();
+    let unreachable = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.message == "Unreachable code")
+        .expect("the jumped over instruction should be unreachable");
+    assert_eq!(unreachable.labeled_spans[0].0, 10..15);
+    assert_eq!(unreachable.code, None);
+}
+
+#[test]
+fn test_dominator_tree_export() {
+    let context = analyze(create_loop_body());
+    let cfg = context.get_result::<ControlFlowAnalysis>().unwrap();
+    let info: Arc<DominanceInfo> = context.get_result::<DominanceAnalysis>().unwrap();
+    let [read, header] = [2, 3].map(|id| node(&cfg, id));
+
+    let edge = format!("N{} -> N{}", read.index(), header.index());
+    let dot = info.dominators.export(ExportFormat::Dot, &cfg, Some(&context));
+    assert!(dot.starts_with("digraph {"));
+    assert!(dot.contains(&edge));
+    assert!(dot.contains("JZERO :end"));
+
+    let mermaid = info.dominators.export(ExportFormat::Mermaid, &cfg, None);
+    assert!(mermaid.contains(&format!("N{} --> N{}", read.index(), header.index())));
+
+    let json = info.post_dominators.export(ExportFormat::Json, &cfg, None);
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["roots"].as_array().map(Vec::len), Some(1));
+    assert_eq!(json["edges"].as_array().map(Vec::len), Some(4));
+}
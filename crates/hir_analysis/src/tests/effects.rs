@@ -0,0 +1,203 @@
+//! Tests for analyses driven by the effects of instructions
+
+use std::sync::Arc;
+
+use hir::body::{AddressingMode, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::{
+    AccumulatorValue, Effect, InstructionBuilder, InstructionEffects, InstructionKind,
+    InstructionRegistry, OperandKind,
+};
+
+use crate::analyzers::constant_propagation::{
+    BranchTaken, ConstantPropagationAnalysis, ConstantPropagationResult,
+};
+use crate::analyzers::control_flow::{ControlFlowAnalysis, EdgeKind};
+use crate::analyzers::data_flow::{DataFlowAnalysis, DataFlowGraph};
+use crate::analyzers::dominance::DominanceAnalysis;
+use crate::analyzers::ssa::SsaAnalysis;
+use crate::context::AnalysisContext;
+use crate::pass::AnalysisPass;
+
+/// Create a registry of the standard instructions and two plugin instructions:
+/// `SET`, which loads its operand, and `SAVE`, which stores the accumulator
+fn create_plugin_registry() -> Arc<InstructionRegistry> {
+    let mut registry = ram_core::standard_instructions();
+
+    let set = InstructionBuilder::new("SET")
+        .allow_operand_kind(OperandKind::Immediate)
+        .effects(
+            InstructionEffects::new()
+                .with(Effect::ReadsOperand)
+                .with(Effect::WritesAccumulator(AccumulatorValue::Operand)),
+        )
+        .build();
+    registry.register(InstructionKind::Custom(Arc::from("SET")), set);
+
+    let save = InstructionBuilder::new("SAVE")
+        .allow_operand_kind(OperandKind::Direct)
+        .effects(
+            InstructionEffects::new().with(Effect::ReadsAccumulator).with(Effect::WritesOperand),
+        )
+        .build();
+    registry.register(InstructionKind::Custom(Arc::from("SAVE")), save);
+
+    Arc::new(registry)
+}
+
+/// Create an instruction of a test body
+fn instruction(id: u32, opcode: &str, operand: Option<u32>) -> Instruction {
+    Instruction {
+        id: LocalDefId(id),
+        opcode: opcode.to_string(),
+        kind: InstructionKind::from_name(opcode),
+        operand: operand.map(ExprId),
+        label_name: None,
+        span: 0..0,
+        docs: Vec::new(),
+    }
+}
+
+/// Create an expression of a test body
+fn expr(id: u32, kind: ExprKind) -> Expr {
+    Expr { id: ExprId(id), kind, span: 0..0 }
+}
+
+/// Create a body for `SET =0`, `JZERO end`, `HALT` and `end: HALT`
+fn create_branch_body() -> Body {
+    let mut body = Body::default();
+    body.instructions.alloc(instruction(0, "SET", Some(0)));
+    body.instructions.alloc(instruction(1, "JZERO", Some(1)));
+    body.instructions.alloc(instruction(2, "HALT", None));
+    body.instructions
+        .alloc(Instruction { label_name: Some("end".to_string()), ..instruction(3, "HALT", None) });
+    body.labels.alloc(Label {
+        id: LocalDefId(4),
+        name: "end".to_string(),
+        instruction_id: Some(LocalDefId(3)),
+        span: 0..0,
+        docs: Vec::new(),
+    });
+    body.exprs.alloc(expr(0, ExprKind::Literal(Literal::Int(0))));
+    body.exprs.alloc(expr(1, ExprKind::Literal(Literal::Label("end".to_string()))));
+    body
+}
+
+/// Create a body for `CALL sub`, `CALL sub`, `HALT` and `sub: RET`
+fn create_call_body() -> Body {
+    let mut body = Body::default();
+    body.instructions.alloc(instruction(0, "CALL", Some(0)));
+    body.instructions.alloc(instruction(1, "CALL", Some(1)));
+    body.instructions.alloc(instruction(2, "HALT", None));
+    body.instructions
+        .alloc(Instruction { label_name: Some("sub".to_string()), ..instruction(3, "RET", None) });
+    body.labels.alloc(Label {
+        id: LocalDefId(4),
+        name: "sub".to_string(),
+        instruction_id: Some(LocalDefId(3)),
+        span: 0..0,
+        docs: Vec::new(),
+    });
+    body.exprs.alloc(expr(0, ExprKind::Literal(Literal::Label("sub".to_string()))));
+    body.exprs.alloc(expr(1, ExprKind::Literal(Literal::Label("sub".to_string()))));
+    body
+}
+
+/// Create a body for `SAVE 1` followed by `LOAD 1`
+fn create_memory_body() -> Body {
+    let mut body = Body::default();
+    body.instructions.alloc(instruction(0, "SAVE", Some(1)));
+    body.instructions.alloc(instruction(1, "LOAD", Some(3)));
+    body.exprs.alloc(expr(0, ExprKind::Literal(Literal::Int(1))));
+    body.exprs.alloc(expr(
+        1,
+        ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address: ExprId(0) }),
+    ));
+    body.exprs.alloc(expr(2, ExprKind::Literal(Literal::Int(1))));
+    body.exprs.alloc(expr(
+        3,
+        ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address: ExprId(2) }),
+    ));
+    body
+}
+
+/// Run the data flow analysis and its dependencies
+fn run_data_flow(context: &mut AnalysisContext) -> DataFlowGraph {
+    let cfg = ControlFlowAnalysis.run(context).unwrap();
+    context.store_result::<ControlFlowAnalysis>(cfg);
+    DataFlowAnalysis.run(context).unwrap()
+}
+
+/// Run the constant propagation analysis and its dependencies
+fn run_constant_propagation(context: &mut AnalysisContext) -> ConstantPropagationResult {
+    let cfg = ControlFlowAnalysis.run(context).unwrap();
+    context.store_result::<ControlFlowAnalysis>(cfg);
+    let dominance = DominanceAnalysis.run(context).unwrap();
+    context.store_result::<DominanceAnalysis>(dominance);
+    let versions = SsaAnalysis.run(context).unwrap();
+    context.store_result::<SsaAnalysis>(versions);
+    ConstantPropagationAnalysis.run(context).unwrap()
+}
+
+#[test]
+fn test_constant_propagation_uses_plugin_effects() {
+    let mut context = AnalysisContext::with_instruction_registry(
+        Arc::new(create_branch_body()),
+        create_plugin_registry(),
+    );
+    let result = run_constant_propagation(&mut context);
+
+    assert_eq!(result.constant_values[&LocalDefId(0)], Some(0));
+    assert_eq!(result.optimized_edges.get(&LocalDefId(1)), Some(&BranchTaken::Always));
+}
+
+#[test]
+fn test_undeclared_effects_clobber_the_accumulator() {
+    // Without the plugin, `SET` has unknown effects
+    let mut context = AnalysisContext::from(create_branch_body());
+    let result = run_constant_propagation(&mut context);
+
+    assert_eq!(result.constant_values[&LocalDefId(0)], None);
+    assert!(result.optimized_edges.is_empty());
+}
+
+#[test]
+fn test_data_flow_uses_plugin_effects() {
+    let mut context = AnalysisContext::with_instruction_registry(
+        Arc::new(create_memory_body()),
+        create_plugin_registry(),
+    );
+    let dfg = run_data_flow(&mut context);
+
+    // The value `SAVE` writes to register 1 flows into `LOAD`
+    assert_eq!(dfg.edge_count(), 1);
+    assert!(dfg.find_unused_writes().is_empty());
+
+    // Without the plugin, `SAVE` is only assumed to read its operand
+    let mut context = AnalysisContext::from(create_memory_body());
+    assert_eq!(run_data_flow(&mut context).edge_count(), 0);
+}
+
+#[test]
+fn test_control_flow_models_calls_and_returns() {
+    let mut context = AnalysisContext::with_instruction_registry(
+        Arc::new(create_call_body()),
+        Arc::new(ram_core::subroutine_instructions()),
+    );
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    let node = |id| cfg.get_node_by_instruction(LocalDefId(id)).unwrap();
+
+    // Calls go to the subroutine, which returns after each of them
+    assert_eq!(cfg.get_outgoing_edges(node(0)), vec![(node(3), EdgeKind::Call)]);
+    assert_eq!(cfg.get_outgoing_edges(node(1)), vec![(node(3), EdgeKind::Call)]);
+    let mut return_sites = cfg.get_successors(node(3));
+    return_sites.sort();
+    assert_eq!(return_sites, vec![node(1), node(2)]);
+    assert!(cfg.find_unreachable_nodes().is_empty());
+
+    // Without the subroutine instructions, `CALL` falls through like any other
+    let mut context = AnalysisContext::from(create_call_body());
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    assert_eq!(cfg.get_successors(node(0)), vec![node(1)]);
+}
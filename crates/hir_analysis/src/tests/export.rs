@@ -0,0 +1,191 @@
+//! Tests for the basic block graph and the filters and themes of graph exports
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+
+use crate::analyzers::control_flow::{
+    ControlFlowAnalysis, ControlFlowGraph, EdgeKind, summarize_block,
+};
+use crate::context::AnalysisContext;
+use crate::export::{ExportFormat, ExportOptions, ExportTheme, RankDirection};
+use crate::pass::AnalysisPass;
+
+/// Create a body from its lines, each with a label, an opcode and the label
+/// it jumps to
+fn create_body(lines: &[(Option<&str>, &str, Option<&str>)]) -> Body {
+    let mut body = Body::default();
+    for (index, &(label, opcode, target)) in lines.iter().enumerate() {
+        let operand = target.map(|target| {
+            let id = ExprId(body.exprs.len() as u32);
+            let kind = ExprKind::Literal(Literal::Label(target.to_string()));
+            body.exprs.alloc(Expr { id, kind, span: 0..0 });
+            id
+        });
+        if let Some(name) = label {
+            body.labels.alloc(Label {
+                id: LocalDefId(body.labels.len() as u32 + 100),
+                name: name.to_string(),
+                instruction_id: Some(LocalDefId(index as u32)),
+                span: 0..0,
+                docs: Vec::new(),
+            });
+        }
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: label.map(str::to_string),
+            span: 0..0,
+            docs: Vec::new(),
+        });
+    }
+    body
+}
+
+/// Build the control flow graph of a loop with a jump to a jump and dead code
+///
+/// The node of the instruction at index `i` is `Ni`.
+fn analyze() -> (ControlFlowGraph, AnalysisContext) {
+    let body = create_body(&[
+        (Some("loop"), "READ", None),
+        (None, "JZERO", Some("out")),
+        (None, "JUMP", Some("loop")),
+        (Some("dead"), "WRITE", None),
+        (Some("out"), "JUMP", Some("end")),
+        (Some("end"), "HALT", None),
+    ]);
+    let mut context = AnalysisContext::from(body);
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+    (cfg, context)
+}
+
+#[test]
+fn test_block_graph() {
+    // The blocks are `READ; JZERO`, `JUMP loop`, `WRITE` and `JUMP end; HALT`
+    let (cfg, context) = analyze();
+    let blocks = cfg.block_graph();
+    assert_eq!(blocks.block_count(), 4);
+    assert_eq!(blocks.entry_block(), Some(0));
+    let halt = cfg.get_node_by_instruction(LocalDefId(5)).unwrap();
+    assert_eq!(blocks.block_of(halt), Some(3));
+
+    assert_eq!(
+        blocks.successors(0),
+        [(1, EdgeKind::ConditionalFalse), (3, EdgeKind::ConditionalTrue)]
+    );
+    assert_eq!(blocks.successors(1), [(0, EdgeKind::Unconditional)]);
+    assert_eq!(
+        blocks.predecessors(3),
+        [(0, EdgeKind::ConditionalTrue), (2, EdgeKind::Unconditional)]
+    );
+    assert_eq!(blocks.unreachable_blocks(), [2]);
+
+    let body: &Body = context.body();
+    assert_eq!(cfg.block_label(blocks.block(3), Some(body)), ["JUMP :end", "HALT"]);
+}
+
+#[test]
+fn test_summarize_block() {
+    let lines = |count: usize| (0..count).map(|i| format!("I{i}")).collect::<Vec<_>>();
+    assert_eq!(summarize_block(lines(4)), lines(4));
+    assert_eq!(summarize_block(lines(7)), ["I0", "I1", "… 4 more", "I6"]);
+}
+
+/// Export a control flow graph to Mermaid with the given options
+fn mermaid(cfg: &ControlFlowGraph, context: &AnalysisContext, options: ExportOptions) -> String {
+    cfg.export(ExportFormat::Mermaid, &options, Some(context))
+}
+
+#[test]
+fn test_reachable_only() {
+    let (cfg, context) = analyze();
+    assert!(mermaid(&cfg, &context, ExportOptions::default()).contains("N3[\"WRITE\"]"));
+
+    let options = ExportOptions { reachable_only: true, ..Default::default() };
+    let export = mermaid(&cfg, &context, options);
+    assert!(!export.contains("N3"));
+    assert!(export.contains("N4[\"JUMP :end\"]"));
+}
+
+#[test]
+fn test_hide_trivia() {
+    let (cfg, context) = analyze();
+    let options = ExportOptions { hide_trivia: true, ..Default::default() };
+    let export = mermaid(&cfg, &context, options);
+
+    // The edges into the jumps go straight to their targets
+    assert!(!export.contains("JUMP"));
+    assert!(export.contains("N1 -.->|true| N5"));
+    assert!(export.contains("N1 -.->|false| N0"));
+    assert!(export.contains("N3 --> N5"));
+}
+
+#[test]
+fn test_collapse_blocks() {
+    let (cfg, context) = analyze();
+    let options = ExportOptions { collapse_blocks: true, ..Default::default() };
+    let export = mermaid(&cfg, &context, options);
+
+    assert!(export.contains("N0[\"READ<br>JZERO :out\"]"));
+    assert!(!export.contains("N1"));
+    assert!(export.contains("N0 -.->|true| N4"));
+    assert!(export.contains("N2 --> N0"));
+
+    // A block that loops back to itself keeps its edge
+    let dot = cfg.export(
+        ExportFormat::Dot,
+        &ExportOptions { collapse_blocks: true, hide_trivia: true, ..Default::default() },
+        Some(&context),
+    );
+    assert!(dot.contains("N0 -> N0;"));
+    assert!(dot.contains("N0 [label=\"READ\\nJZERO :out\"]"));
+}
+
+#[test]
+fn test_json_blocks() {
+    let (cfg, context) = analyze();
+    let options = ExportOptions { collapse_blocks: true, ..Default::default() };
+    let json = cfg.export(ExportFormat::Json, &options, Some(&context));
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    // The nodes are still listed, along with the blocks they form
+    assert_eq!(json["nodes"].as_array().unwrap().len(), 6);
+    assert_eq!(json["blocks"][0]["label"], serde_json::json!(["READ", "JZERO :out"]));
+    assert_eq!(json["blocks"][0]["nodes"], serde_json::json!(["N0", "N1"]));
+    assert_eq!(json["blocks"][1]["successors"][0]["block"], 0);
+    let flat = cfg.export(ExportFormat::Json, &ExportOptions::default(), None);
+    assert!(!flat.contains("blocks"));
+}
+
+#[test]
+fn test_theme_and_direction() {
+    let (cfg, context) = analyze();
+    let options = ExportOptions {
+        theme: Some(ExportTheme::Dark),
+        rank_direction: RankDirection::LeftRight,
+        edge_colors: [(EdgeKind::ConditionalTrue, "red".to_string())].into_iter().collect(),
+        ..Default::default()
+    };
+
+    let export = mermaid(&cfg, &context, options.clone());
+    assert!(export.starts_with("%%{init: {'theme': 'dark'}}%%\ngraph LR\n"));
+    let true_edge = export
+        .lines()
+        .filter(|line| line.contains("-->") || line.contains("-.->"))
+        .position(|line| line.contains("|true|"))
+        .unwrap();
+    assert!(export.contains(&format!("linkStyle {} stroke:red", true_edge)));
+
+    let dot = cfg.export(ExportFormat::Dot, &options, Some(&context));
+    assert!(dot.contains("rankdir=LR;"));
+    assert!(dot.contains("bgcolor=\"#1e1e1e\";"));
+    assert!(dot.contains("N1 -> N4 [color=\"red\"];"));
+
+    // The defaults leave the look to the renderer
+    let dot = cfg.export(ExportFormat::Dot, &ExportOptions::default(), Some(&context));
+    assert!(!dot.contains("rankdir") && !dot.contains("color"));
+    assert!(mermaid(&cfg, &context, ExportOptions::default()).starts_with("graph TD\n"));
+}
@@ -0,0 +1,278 @@
+//! Tests for the instruction validation analysis
+
+use std::sync::Arc;
+
+use hir::body::{
+    AddressingMode, BinaryExpr, BinaryOp, Body, CHAR_ANNOTATION, Expr, ExprKind, Instruction,
+    Label, Literal, MemoryRef,
+};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::{InstructionBuilder, InstructionKind, InstructionRegistry, OperandKind};
+use ram_diagnostics::Applicability;
+
+use crate::analyzers::instruction_validation::{
+    InstructionValidationAnalysis, NON_CONSTANT_OPERAND_CODE, UNDEFINED_LABEL_CODE,
+    UNKNOWN_INSTRUCTION_CODE,
+};
+use crate::context::AnalysisContext;
+use crate::pass::AnalysisPass;
+
+/// Create a body for `loop: LAOD 1` followed by `JUMP lop`
+fn create_test_body() -> Body {
+    let mut body = Body::default();
+
+    body.instructions.alloc(Instruction {
+        id: LocalDefId(1),
+        opcode: "LAOD".to_string(),
+        kind: InstructionKind::from_name("LAOD"),
+        operand: Some(ExprId(0)),
+        label_name: Some("loop".to_string()),
+        span: 6..12,
+        docs: Vec::new(),
+    });
+    body.instructions.alloc(Instruction {
+        id: LocalDefId(2),
+        opcode: "JUMP".to_string(),
+        kind: InstructionKind::from_name("JUMP"),
+        operand: Some(ExprId(1)),
+        label_name: None,
+        span: 13..21,
+        docs: Vec::new(),
+    });
+
+    body.exprs.alloc(Expr {
+        id: ExprId(0),
+        kind: ExprKind::Literal(Literal::Int(1)),
+        span: 11..12,
+    });
+    body.exprs.alloc(Expr {
+        id: ExprId(1),
+        kind: ExprKind::Literal(Literal::Label("lop".to_string())),
+        span: 18..21,
+    });
+
+    body.labels.alloc(Label {
+        id: LocalDefId(0),
+        name: "loop".to_string(),
+        instruction_id: Some(LocalDefId(1)),
+        span: 0..5,
+        docs: Vec::new(),
+    });
+
+    body
+}
+
+#[test]
+fn test_suggests_similar_instruction() {
+    let mut context = AnalysisContext::from(create_test_body());
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    let diagnostic = diagnostics
+        .iter()
+        .find(|d| d.code.as_deref() == Some(UNKNOWN_INSTRUCTION_CODE))
+        .expect("Expected an unknown instruction diagnostic");
+
+    assert_eq!(diagnostic.labeled_spans[0].0, 6..10);
+    assert!(diagnostic.help.contains("`LOAD`"));
+    assert_eq!(diagnostic.suggestions.len(), 1);
+    assert_eq!(diagnostic.suggestions[0].span, 6..10);
+    assert_eq!(diagnostic.suggestions[0].replacement, "LOAD");
+    assert_eq!(diagnostic.suggestions[0].applicability, Applicability::MaybeIncorrect);
+}
+
+#[test]
+fn test_suggests_similar_label() {
+    let mut context = AnalysisContext::from(create_test_body());
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    let diagnostic = diagnostics
+        .iter()
+        .find(|d| d.code.as_deref() == Some(UNDEFINED_LABEL_CODE))
+        .expect("Expected an undefined label diagnostic");
+
+    assert!(diagnostic.message.contains("'lop'"));
+    assert_eq!(diagnostic.suggestions.len(), 1);
+    assert_eq!(diagnostic.suggestions[0].span, 18..21);
+    assert_eq!(diagnostic.suggestions[0].replacement, "loop");
+    assert_eq!(diagnostic.suggestions[0].applicability, Applicability::MaybeIncorrect);
+}
+
+#[test]
+fn test_no_suggestion_for_unrelated_names() {
+    let mut body = create_test_body();
+    body.instruction_mut(0).unwrap().opcode = "SQRT".to_string();
+    body.exprs[ExprId(1).idx()].kind = ExprKind::Literal(Literal::Label("finish".to_string()));
+
+    let mut context = AnalysisContext::from(body);
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.iter().filter(|d| d.code.is_some()).count(), 2);
+    assert!(diagnostics.iter().all(|d| d.suggestions.is_empty()));
+}
+
+/// Create a body for a single instruction with an operand
+fn create_instruction_body(opcode: &str, operand: ExprKind) -> Body {
+    let mut body = Body::default();
+
+    body.instructions.alloc(Instruction {
+        id: LocalDefId(0),
+        opcode: opcode.to_string(),
+        kind: InstructionKind::from_name(opcode),
+        operand: Some(ExprId(0)),
+        label_name: None,
+        span: 0..10,
+        docs: Vec::new(),
+    });
+    body.exprs.alloc(Expr { id: ExprId(0), kind: operand, span: 8..10 });
+    body.exprs.alloc(Expr { id: ExprId(1), kind: ExprKind::Literal(Literal::Int(2)), span: 9..10 });
+
+    body
+}
+
+/// Create a registry of the standard instructions and a `SWAP` plugin
+/// instruction taking a direct operand
+fn create_plugin_registry() -> Arc<InstructionRegistry> {
+    let mut registry = ram_core::standard_instructions();
+    let swap = InstructionBuilder::new("SWAP").allow_operand_kind(OperandKind::Direct).build();
+    registry.register(InstructionKind::Custom(Arc::from("SWAP")), swap);
+    Arc::new(registry)
+}
+
+/// Create a direct operand for register 2
+fn direct_operand() -> ExprKind {
+    ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address: ExprId(1) })
+}
+
+#[test]
+fn test_plugin_instructions_are_valid() {
+    let body = create_instruction_body("swap", direct_operand());
+
+    // Without the plugin the instruction is unknown
+    let mut context = AnalysisContext::from(body.clone());
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+    assert!(context.has_errors());
+
+    let mut context =
+        AnalysisContext::with_instruction_registry(Arc::new(body), create_plugin_registry());
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+    assert!(!context.has_errors());
+}
+
+#[test]
+fn test_rejects_operand_kinds_the_plugin_does_not_allow() {
+    let body = create_instruction_body("SWAP", ExprKind::Literal(Literal::Int(2)));
+
+    let mut context =
+        AnalysisContext::with_instruction_registry(Arc::new(body), create_plugin_registry());
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Instruction 'SWAP' does not accept immediate operands");
+    assert_eq!(diagnostics[0].help, "Use one of the following operands: direct");
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 8..10);
+}
+
+#[test]
+fn test_rejects_immediate_store() {
+    let body = create_instruction_body("STORE", ExprKind::Literal(Literal::Int(2)));
+
+    let mut context = AnalysisContext::from(body);
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Instruction 'STORE' does not accept immediate operands");
+}
+
+#[test]
+fn test_rejects_immediate_jumps() {
+    let immediate = MemoryRef { mode: AddressingMode::Immediate, address: ExprId(1) };
+    let body = create_instruction_body("JUMP", ExprKind::MemoryRef(immediate));
+
+    let mut context = AnalysisContext::from(body);
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Instruction 'JUMP' can not jump to an immediate value");
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 8..10);
+}
+
+#[test]
+fn test_rejects_negative_addresses() {
+    let mut body = create_instruction_body("LOAD", direct_operand());
+    body.exprs[ExprId(1).idx()].kind = ExprKind::Literal(Literal::Int(-2));
+
+    let mut context = AnalysisContext::from(body);
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    // The virtual machine refuses to load the program, so this is an error
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Negative memory address: -2");
+    assert!(context.has_errors());
+}
+
+#[test]
+fn test_pipeline_uses_its_instruction_registry() {
+    let mut pipeline = crate::AnalysisPipeline::new();
+    pipeline.register::<InstructionValidationAnalysis>().unwrap();
+    pipeline.set_instruction_registry(create_plugin_registry());
+
+    let context =
+        pipeline.analyze(Arc::new(create_instruction_body("SWAP", direct_operand()))).unwrap();
+    assert!(!context.has_errors());
+}
+
+/// Create a body for `LOAD =2/<rhs>`, an expression lowering could not fold
+fn create_non_constant_body(rhs: ExprKind) -> Body {
+    let binary = BinaryExpr { op: BinaryOp::Div, lhs: ExprId(1), rhs: ExprId(2) };
+    let mut body = create_instruction_body("LOAD", ExprKind::Binary(binary));
+    body.exprs.alloc(Expr { id: ExprId(2), kind: rhs, span: 10..17 });
+    body
+}
+
+#[test]
+fn test_rejects_non_constant_operand() {
+    let mut context =
+        AnalysisContext::from(create_non_constant_body(ExprKind::Literal(Literal::Int(0))));
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code.as_deref(), Some(NON_CONSTANT_OPERAND_CODE));
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 8..10);
+}
+
+#[test]
+fn test_reports_undefined_labels_in_operand_expressions() {
+    let mut context = AnalysisContext::from(create_non_constant_body(ExprKind::Literal(
+        Literal::Label("missing".to_string()),
+    )));
+    InstructionValidationAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code.as_deref(), Some(UNDEFINED_LABEL_CODE));
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 10..17);
+}
+
+#[test]
+fn test_warns_about_char_annotation_without_output() {
+    let run = |opcode: &str| {
+        let mut body = create_instruction_body(opcode, ExprKind::Literal(Literal::Int(65)));
+        body.instruction_mut(0).unwrap().docs.push(format!(" {}", CHAR_ANNOTATION));
+
+        let mut context = AnalysisContext::from(body);
+        InstructionValidationAnalysis.run(&mut context).unwrap();
+        context.diagnostics().diagnostics().iter().map(|d| d.message.clone()).collect::<Vec<_>>()
+    };
+
+    assert!(run("WRITE").is_empty());
+    assert_eq!(run("LOAD"), vec!["`#[char]` has no effect on 'LOAD'".to_string()]);
+}
@@ -0,0 +1,164 @@
+//! Tests for the input/output contract analysis
+
+use std::sync::Arc;
+
+use hir::body::{AddressingMode, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+use ram_diagnostics::DiagnosticKind;
+
+use crate::analyzers::io_contract::{IO_CONTRACT_VIOLATION_CODE, IoContractAnalysis, IoCount};
+use crate::{AnalysisContext, AnalysisPipeline, ControlFlowAnalysis};
+
+/// Create a body from a contract and lines, each with a label, an opcode and
+/// the register or label of its operand
+///
+/// Operands that start with a digit are registers, and others are labels.
+fn create_body(contract: &str, lines: &[(Option<&str>, &str, Option<&str>)]) -> Body {
+    let mut body = Body::default();
+    for (index, &(label, opcode, operand)) in lines.iter().enumerate() {
+        let operand = operand.map(|operand| {
+            let kind = match operand.parse() {
+                Ok(register) => {
+                    let address = ExprId(body.exprs.len() as u32);
+                    body.exprs.alloc(Expr {
+                        id: address,
+                        kind: ExprKind::Literal(Literal::Int(register)),
+                        span: 0..0,
+                    });
+                    ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address })
+                }
+                Err(_) => ExprKind::Literal(Literal::Label(operand.to_string())),
+            };
+            let id = ExprId(body.exprs.len() as u32);
+            body.exprs.alloc(Expr { id, kind, span: 0..0 });
+            id
+        });
+        if let Some(name) = label {
+            body.labels.alloc(Label {
+                id: LocalDefId(body.labels.len() as u32 + 100),
+                name: name.to_string(),
+                instruction_id: Some(LocalDefId(index as u32)),
+                span: 0..0,
+                docs: Vec::new(),
+            });
+        }
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: label.map(str::to_string),
+            span: 10 * index..10 * index + 5,
+            docs: if index == 0 { vec![contract.to_string()] } else { Vec::new() },
+        });
+    }
+    body
+}
+
+/// Analyze a body with the contract analysis
+fn analyze(body: Body) -> AnalysisContext {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<IoContractAnalysis>().unwrap();
+    pipeline.analyze(Arc::new(body)).unwrap()
+}
+
+#[test]
+fn test_contract_that_holds() {
+    let context = analyze(create_body(
+        "@reads 2 @writes 1",
+        &[
+            (None, "READ", Some("1")),
+            (None, "READ", Some("2")),
+            (None, "WRITE", Some("1")),
+            (None, "HALT", None),
+        ],
+    ));
+
+    let result = context.get_result::<IoContractAnalysis>().unwrap();
+    assert_eq!(result.reads, Some(IoCount::exactly(2)));
+    assert_eq!(result.writes, Some(IoCount::exactly(1)));
+    assert!(context.pass_diagnostics::<IoContractAnalysis>().is_empty());
+}
+
+#[test]
+fn test_contract_that_every_run_breaks() {
+    let context = analyze(create_body(
+        "@reads 2",
+        &[(None, "READ", Some("1")), (None, "WRITE", Some("1")), (None, "HALT", None)],
+    ));
+
+    let diagnostics = context.pass_diagnostics::<IoContractAnalysis>();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Program reads 1 value, but its contract declares 2");
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::Error);
+    assert_eq!(diagnostics[0].code.as_deref(), Some(IO_CONTRACT_VIOLATION_CODE));
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 0..5);
+}
+
+#[test]
+fn test_contract_that_some_paths_break() {
+    let context = analyze(create_body(
+        "@reads 2 @writes 1",
+        &[
+            (None, "READ", Some("1")),
+            (None, "LOAD", Some("1")),
+            (None, "JZERO", Some("end")),
+            (None, "READ", Some("2")),
+            (Some("end"), "WRITE", Some("1")),
+            (None, "HALT", None),
+        ],
+    ));
+
+    let diagnostics = context.pass_diagnostics::<IoContractAnalysis>();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "Program reads between 1 and 2 values, but its contract declares 2"
+    );
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::Warning);
+}
+
+#[test]
+fn test_loops_have_no_upper_bound() {
+    // Reading until the input is not positive reads at least one value
+    let lines = [
+        (Some("loop"), "READ", Some("1")),
+        (None, "LOAD", Some("1")),
+        (None, "JGTZ", Some("loop")),
+        (None, "HALT", None),
+    ];
+    let context = analyze(create_body("@reads 3", &lines));
+    let result = context.get_result::<IoContractAnalysis>().unwrap();
+    assert_eq!(result.reads, Some(IoCount { min: 1, max: None }));
+    assert!(context.pass_diagnostics::<IoContractAnalysis>().is_empty());
+
+    let context = analyze(create_body("@reads 0", &lines));
+    let diagnostics = context.pass_diagnostics::<IoContractAnalysis>();
+    assert_eq!(
+        diagnostics[0].message,
+        "Program reads at least 1 value, but its contract declares 0"
+    );
+}
+
+#[test]
+fn test_subroutines_return_to_their_call() {
+    // Each call writes once, and returns only to the instruction after it
+    let context = analyze(create_body(
+        "@writes 2",
+        &[
+            (None, "CALL", Some("print")),
+            (None, "CALL", Some("print")),
+            (None, "HALT", None),
+            (Some("print"), "WRITE", Some("1")),
+            (None, "RET", None),
+        ],
+    ));
+
+    let result = context.get_result::<IoContractAnalysis>().unwrap();
+    assert_eq!(result.writes, Some(IoCount::exactly(2)));
+    assert_eq!(result.reads, Some(IoCount::exactly(0)));
+    assert!(context.pass_diagnostics::<IoContractAnalysis>().is_empty());
+}
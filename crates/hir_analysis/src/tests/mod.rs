@@ -1,6 +1,21 @@
 //! Tests for the HIR analysis
 
+pub mod alias;
 pub mod analyzers;
+pub mod call_graph;
 pub mod control_flow_optimizer;
 pub mod diagnostics;
+pub mod diff;
+pub mod dominance;
+pub mod effects;
+pub mod export;
+pub mod instruction_validation;
+pub mod io_contract;
 pub mod pipeline;
+pub mod scheduling;
+pub mod scope;
+pub mod sections;
+pub mod ssa;
+pub mod strict_ram;
+pub mod termination;
+pub mod unused_labels;
@@ -6,6 +6,7 @@ use std::sync::Arc;
 use hir::body::Body;
 use miette::Diagnostic;
 
+use crate::config::{AnalysisConfig, PassOption, SettingValue};
 use crate::{AnalysisContext, AnalysisError, AnalysisPass, AnalysisPipeline};
 
 // --- Dummy Passes ---
@@ -134,3 +135,112 @@ fn test_missing_dependency() {
     println!("Missing dependency result: {:?}", result);
     assert!(matches!(result, Err(AnalysisError::PassNotRegistered { .. })));
 }
+
+/// A pass repeating a word a configured number of times
+#[derive(Default)]
+struct RepeatPass;
+impl AnalysisPass for RepeatPass {
+    type Output = String;
+    fn name(&self) -> &'static str {
+        "RepeatPass"
+    }
+    fn id(&self) -> &'static str {
+        "repeat"
+    }
+    fn options(&self) -> Vec<PassOption> {
+        vec![
+            PassOption::new("times", "How many times to repeat", SettingValue::Integer(2)),
+            PassOption::new("word", "The word to repeat", SettingValue::String("ram".to_string())),
+        ]
+    }
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn Diagnostic>> {
+        Ok(ctx.option::<String>(self, "word").repeat(ctx.option::<usize>(self, "times")))
+    }
+}
+
+/// Create a pipeline with passes A, B and C, and the repeat pass
+fn configurable_pipeline() -> AnalysisPipeline {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<PassA>().unwrap();
+    pipeline.register::<PassB>().unwrap();
+    pipeline.register::<PassC>().unwrap();
+    pipeline.register::<RepeatPass>().unwrap();
+    pipeline
+}
+
+#[test]
+fn test_pass_options() -> Result<(), AnalysisError> {
+    let mut pipeline = configurable_pipeline();
+    let context = pipeline.analyze(Arc::new(Body::default()))?;
+    assert_eq!(*context.get_result::<RepeatPass>()?, "ramram");
+
+    pipeline.set_config(AnalysisConfig::new().with_setting(
+        "repeat",
+        "times",
+        SettingValue::Integer(3),
+    ))?;
+    let context = pipeline.analyze(Arc::new(Body::default()))?;
+    assert_eq!(*context.get_result::<RepeatPass>()?, "ramramram");
+
+    // The settings are reread when they change, even if the body did not
+    pipeline.set_config(AnalysisConfig::new().with_setting(
+        "repeat",
+        "word",
+        SettingValue::String("hi".to_string()),
+    ))?;
+    let context = pipeline.reanalyze(&context, Arc::new(Body::default()))?;
+    assert_eq!(*context.get_result::<RepeatPass>()?, "hihi");
+    Ok(())
+}
+
+#[test]
+fn test_invalid_config() {
+    let mut pipeline = configurable_pipeline();
+    for config in [
+        AnalysisConfig::new().with_setting("repeat", "speed", SettingValue::Integer(1)),
+        AnalysisConfig::new().with_setting("repeat", "times", SettingValue::Bool(true)),
+        AnalysisConfig::new().with_only(["repeat", "cfg"]),
+    ] {
+        let result = pipeline.set_config(config);
+        assert!(matches!(result, Err(AnalysisError::InvalidConfig(_))), "{result:?}");
+    }
+
+    // Passes that are not registered may be configured
+    let config = AnalysisConfig::new().with_setting("cfg", "depth", SettingValue::Integer(1));
+    assert!(pipeline.set_config(config).is_ok());
+}
+
+#[test]
+fn test_disabled_passes() -> Result<(), AnalysisError> {
+    // Disabling a pass disables the passes that depend on it
+    let mut pipeline = configurable_pipeline();
+    pipeline.set_config(AnalysisConfig::new().with_pass_enabled("PassB", false))?;
+    let context = pipeline.analyze(Arc::new(Body::default()))?;
+    assert!(context.get_result::<PassA>().is_ok());
+    assert!(context.get_result::<PassB>().is_err());
+    assert!(context.get_result::<PassC>().is_err());
+    assert!(context.get_result::<RepeatPass>().is_ok());
+
+    // Restricting the passes runs the passes they depend on too
+    pipeline.set_config(AnalysisConfig::new().with_only(["PassB"]))?;
+    let context = pipeline.analyze(Arc::new(Body::default()))?;
+    assert!(context.get_result::<PassA>().is_ok());
+    assert!(context.get_result::<PassB>().is_ok());
+    assert!(context.get_result::<PassC>().is_err());
+    assert!(context.get_result::<RepeatPass>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_memory_stats() -> Result<(), AnalysisError> {
+    let pipeline = configurable_pipeline();
+    let stats = pipeline.analyze(Arc::new(Body::default()))?.memory_stats();
+    assert_eq!(stats.body.total(), 0);
+    assert_eq!(stats.results, 4);
+    assert_eq!(stats.diagnostics, 0);
+    assert!(stats.to_string().starts_with("body: 0 bytes"));
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Tests for the jump scheduling lints
+
+use std::sync::Arc;
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+
+use crate::analyzers::scheduling::{JUMP_TO_NEXT_CODE, REDUNDANT_JUMP_CODE, SchedulingAnalysis};
+use crate::{AnalysisContext, AnalysisPipeline};
+
+/// Create a body from its lines, each with a label, an opcode and the label
+/// it jumps to
+///
+/// The instruction of the line at index `i` has the ID `i` and spans the
+/// bytes `10 * i..10 * i + 5`.
+fn create_body(lines: &[(Option<&str>, &str, Option<&str>)]) -> Body {
+    let mut body = Body::default();
+    let mut exprs = Vec::new();
+    let mut labels = Vec::new();
+    let mut instructions = Vec::new();
+    for (index, &(label, opcode, target)) in lines.iter().enumerate() {
+        let id = index as u32;
+        let operand = target.map(|target| {
+            let expr_id = ExprId(exprs.len() as u32);
+            exprs.push(Expr {
+                id: expr_id,
+                kind: ExprKind::Literal(Literal::Label(target.to_string())),
+                span: 0..0,
+            });
+            expr_id
+        });
+        if let Some(name) = label {
+            labels.push(Label {
+                id: LocalDefId(labels.len() as u32 + 100),
+                name: name.to_string(),
+                instruction_id: Some(LocalDefId(id)),
+                span: 0..0,
+                docs: Vec::new(),
+            });
+        }
+        instructions.push(Instruction {
+            id: LocalDefId(id),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: label.map(str::to_string),
+            span: 10 * index..10 * index + 5,
+            docs: Vec::new(),
+        });
+    }
+    body.exprs = exprs.into_iter().collect();
+    body.labels = labels.into_iter().collect();
+    body.instructions = instructions.into_iter().collect();
+    body
+}
+
+/// Analyze a body with the scheduling analysis
+fn analyze(body: Body) -> AnalysisContext {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<SchedulingAnalysis>().unwrap();
+    pipeline.analyze(Arc::new(body)).unwrap()
+}
+
+#[test]
+fn test_jump_to_next_instruction() {
+    let context = analyze(create_body(&[
+        (None, "READ", None),
+        (None, "JGTZ", Some("print")),
+        (Some("print"), "WRITE", None),
+        (None, "JUMP", Some("end")),
+        (Some("end"), "HALT", None),
+    ]));
+
+    let diagnostics = context.pass_diagnostics::<SchedulingAnalysis>();
+    assert_eq!(diagnostics.len(), 2);
+    assert!(diagnostics.iter().all(|d| d.code.as_deref() == Some(JUMP_TO_NEXT_CODE)));
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 10..15);
+    assert_eq!(diagnostics[1].labeled_spans[0].0, 30..35);
+
+    let removable = context.get_result::<SchedulingAnalysis>().unwrap();
+    assert_eq!(*removable, [LocalDefId(1), LocalDefId(3)]);
+}
+
+#[test]
+fn test_redundant_jump_pairs() {
+    let context = analyze(create_body(&[
+        (Some("loop"), "READ", None),
+        (None, "JZERO", Some("end")),
+        (None, "JUMP", Some("end")),
+        (None, "WRITE", None),
+        (None, "JGTZ", Some("loop")),
+        (None, "JGTZ", Some("end")),
+        (None, "HALT", None),
+        (Some("end"), "HALT", None),
+    ]));
+
+    let diagnostics = context.pass_diagnostics::<SchedulingAnalysis>();
+    assert_eq!(diagnostics.len(), 2);
+    assert!(diagnostics.iter().all(|d| d.code.as_deref() == Some(REDUNDANT_JUMP_CODE)));
+
+    // The conditional jump and the jump after it are merged
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 10..25);
+    // The second `JGTZ` is never taken, whatever its label
+    assert_eq!(diagnostics[1].message, "Jump is never taken");
+    assert_eq!(diagnostics[1].labeled_spans[0].0, 50..55);
+    assert_eq!(diagnostics[1].labeled_spans[1].0, 40..45);
+
+    let removable = context.get_result::<SchedulingAnalysis>().unwrap();
+    assert_eq!(*removable, [LocalDefId(1), LocalDefId(5)]);
+}
+
+#[test]
+fn test_jumps_that_matter() {
+    let context = analyze(create_body(&[
+        (Some("loop"), "READ", None),
+        // Different conditions can both be taken
+        (None, "JZERO", Some("end")),
+        (None, "JGTZ", Some("loop")),
+        // A jump with a label can be jumped to directly
+        (None, "JZERO", Some("end")),
+        (Some("again"), "JUMP", Some("end")),
+        (None, "JUMP", Some("again")),
+        (Some("end"), "HALT", None),
+    ]));
+
+    assert!(context.pass_diagnostics::<SchedulingAnalysis>().is_empty());
+}
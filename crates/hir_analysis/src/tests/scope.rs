@@ -0,0 +1,100 @@
+//! Tests for splitting bodies into scopes
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+
+use crate::pass::AnalysisPass;
+use crate::scope::{AnalysisScope, ScopeId};
+use crate::{AnalysisContext, ControlFlowAnalysis};
+
+/// Create an instruction with an operand
+fn instruction(
+    id: u32,
+    opcode: &str,
+    operand: Option<u32>,
+    span: std::ops::Range<usize>,
+) -> Instruction {
+    Instruction {
+        id: LocalDefId(id),
+        opcode: opcode.to_string(),
+        kind: InstructionKind::from_name(opcode),
+        operand: operand.map(ExprId),
+        label_name: None,
+        span,
+        docs: Vec::new(),
+    }
+}
+
+/// Create a label of an instruction
+fn label(id: u32, name: &str, instruction_id: u32) -> Label {
+    Label {
+        id: LocalDefId(id),
+        name: name.to_string(),
+        instruction_id: Some(LocalDefId(instruction_id)),
+        span: 0..0,
+        docs: Vec::new(),
+    }
+}
+
+/// Create the body of a loop that counts register 1 down to zero
+fn create_test_body() -> Body {
+    let mut body = Body::default();
+    body.instructions = [
+        instruction(2, "READ", Some(0), 0..6),
+        instruction(3, "LOAD", Some(1), 7..19),
+        instruction(4, "JZERO", Some(2), 20..29),
+        instruction(5, "JUMP", Some(3), 30..39),
+        instruction(6, "HALT", None, 40..49),
+    ]
+    .into_iter()
+    .collect();
+    body.exprs = [
+        Expr { id: ExprId(0), kind: ExprKind::Literal(Literal::Int(1)), span: 5..6 },
+        Expr { id: ExprId(1), kind: ExprKind::Literal(Literal::Int(1)), span: 18..19 },
+        Expr { id: ExprId(2), kind: ExprKind::Literal(Literal::Label("end".into())), span: 26..29 },
+        Expr {
+            id: ExprId(3),
+            kind: ExprKind::Literal(Literal::Label("loop".into())),
+            span: 35..39,
+        },
+    ]
+    .into_iter()
+    .collect();
+    body.labels = [label(0, "loop", 3), label(1, "end", 6)].into_iter().collect();
+    body
+}
+
+#[test]
+fn test_label_sections() {
+    let sections = AnalysisScope::label_sections(&create_test_body());
+    assert_eq!(
+        sections,
+        vec![
+            AnalysisScope { id: ScopeId::LabelSection(None), instructions: 0..1 },
+            AnalysisScope { id: ScopeId::LabelSection(Some("loop".into())), instructions: 1..4 },
+            AnalysisScope { id: ScopeId::LabelSection(Some("end".into())), instructions: 4..5 },
+        ]
+    );
+    assert!(AnalysisScope::label_sections(&Body::default()).is_empty());
+}
+
+#[test]
+fn test_basic_blocks() {
+    let body = create_test_body();
+    let mut context = AnalysisContext::from(body.clone());
+    let cfg = ControlFlowAnalysis.run(&mut context).unwrap();
+
+    // The blocks cover every instruction once, in order
+    let blocks = AnalysisScope::basic_blocks(&body, &cfg);
+    assert_eq!(blocks.first().map(|block| block.instructions.start), Some(0));
+    assert_eq!(blocks.last().map(|block| block.instructions.end), Some(5));
+    for (index, pair) in blocks.windows(2).enumerate() {
+        assert_eq!(pair[0].id, ScopeId::BasicBlock(index));
+        assert_eq!(pair[0].instructions.end, pair[1].instructions.start);
+    }
+
+    // The loop header starts a block
+    assert!(blocks.iter().any(|block| block.instructions.start == 1));
+}
@@ -0,0 +1,117 @@
+//! Tests for the section analysis
+
+use std::collections::BTreeSet;
+
+use hir::body::{
+    AddressingMode, Body, Expr, ExprKind, Instruction, Label, LabelRef, Literal, MemoryRef,
+};
+use hir::expr::ExprId;
+use hir::ids::{DefId, LocalDefId};
+use ram_core::InstructionKind;
+
+use crate::analyzers::sections::SectionAnalysis;
+use crate::context::AnalysisContext;
+use crate::pass::AnalysisPass;
+
+/// Create a body with a subroutine `twice` and a `main` section calling it
+///
+/// ```text
+/// READ 1
+/// twice: ADD 1
+///        STORE *5
+///        RET
+/// main:  CALL twice
+///        LOAD =2
+///        HALT
+/// ```
+fn create_test_body() -> Body {
+    let mut body = Body::default();
+
+    let instructions = [
+        ("READ", Some(ExprId(1)), None, 0..6),
+        ("ADD", Some(ExprId(1)), Some("twice"), 14..19),
+        ("STORE", Some(ExprId(3)), None, 27..35),
+        ("RET", None, None, 43..46),
+        ("CALL", Some(ExprId(4)), Some("main"), 54..64),
+        ("LOAD", Some(ExprId(6)), None, 72..78),
+        ("HALT", None, None, 86..90),
+    ];
+    for (index, (opcode, operand, label, span)) in instructions.into_iter().enumerate() {
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32 + 10),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: label.map(str::to_string),
+            span,
+            docs: Vec::new(),
+        });
+    }
+
+    let exprs = [
+        ExprKind::Literal(Literal::Int(1)),
+        ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Direct, address: ExprId(0) }),
+        ExprKind::Literal(Literal::Int(5)),
+        ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Indirect, address: ExprId(2) }),
+        ExprKind::LabelRef(LabelRef {
+            label_id: DefId { local_id: LocalDefId(0), ..DefId::default() },
+        }),
+        ExprKind::Literal(Literal::Int(2)),
+        ExprKind::MemoryRef(MemoryRef { mode: AddressingMode::Immediate, address: ExprId(5) }),
+    ];
+    for (index, kind) in exprs.into_iter().enumerate() {
+        body.exprs.alloc(Expr { id: ExprId(index as u32), kind, span: 0..0 });
+    }
+
+    body.labels.alloc(Label {
+        id: LocalDefId(0),
+        name: "twice".to_string(),
+        instruction_id: Some(LocalDefId(11)),
+        span: 7..13,
+        docs: Vec::new(),
+    });
+    body.labels.alloc(Label {
+        id: LocalDefId(1),
+        name: "main".to_string(),
+        instruction_id: Some(LocalDefId(14)),
+        span: 47..52,
+        docs: Vec::new(),
+    });
+
+    body
+}
+
+#[test]
+fn test_section_metrics() {
+    let mut context = AnalysisContext::from(create_test_body());
+    let sections = SectionAnalysis.run(&mut context).unwrap();
+
+    let names = sections.iter().map(|section| section.name.as_deref()).collect::<Vec<_>>();
+    assert_eq!(names, [None, Some("twice"), Some("main")]);
+
+    // The instructions before the first label form a section of their own
+    assert_eq!(sections[0].instructions, 0..1);
+    assert_eq!(sections[0].span, 0..6);
+    assert_eq!(sections[0].registers, BTreeSet::from([1]));
+
+    // A section spans from its label to its last instruction
+    let twice = &sections[1];
+    assert_eq!(twice.instruction_count(), 3);
+    assert_eq!(twice.span, 7..46);
+    assert_eq!(twice.registers, BTreeSet::from([1, 5]));
+    assert!(twice.calls.is_empty());
+    assert_eq!(twice.summary(), "3 instructions, registers 1, 5");
+
+    // Immediate operands name no register, and call targets are not registers
+    let main = &sections[2];
+    assert!(main.registers.is_empty());
+    assert_eq!(main.calls, ["twice"]);
+    assert_eq!(main.summary(), "3 instructions, calls twice");
+    assert!(context.diagnostics().diagnostics().is_empty());
+}
+
+#[test]
+fn test_sections_of_empty_body() {
+    let mut context = AnalysisContext::from(Body::default());
+    assert!(SectionAnalysis.run(&mut context).unwrap().is_empty());
+}
@@ -0,0 +1,215 @@
+//! Tests for the SSA numbering of the accumulator
+
+use std::sync::Arc;
+
+use base_db::LineIndex;
+use hir::body::{AddressingMode, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+
+use crate::analyzers::ssa::{AccumulatorVersions, SsaAnalysis, VersionDef, VersionId};
+use crate::{
+    AnalysisContext, AnalysisPipeline, BranchTaken, ConstantPropagationAnalysis,
+    ControlFlowAnalysis, DominanceAnalysis,
+};
+
+/// An operand of a test instruction
+enum Operand {
+    /// No operand
+    None,
+    /// An immediate value, like `=5`
+    Value(i64),
+    /// A register, like `5`
+    Register(i64),
+    /// A label, like `loop`
+    Label(&'static str),
+}
+
+/// Add an expression to a body
+fn push_expr(body: &mut Body, kind: ExprKind) -> ExprId {
+    let id = ExprId(body.exprs.len() as u32);
+    body.exprs.alloc(Expr { id, kind, span: 0..0 });
+    id
+}
+
+/// Create a body running the instructions in order, with the labels before
+/// the instructions at their indices
+fn create_body(instructions: &[(&str, Operand)], labels: &[(&str, u32)]) -> Body {
+    let mut body = Body::default();
+    for (index, (opcode, operand)) in instructions.iter().enumerate() {
+        let operand = match *operand {
+            Operand::None => None,
+            Operand::Value(value) => {
+                Some(push_expr(&mut body, ExprKind::Literal(Literal::Int(value))))
+            }
+            Operand::Register(register) => {
+                let address = push_expr(&mut body, ExprKind::Literal(Literal::Int(register)));
+                let mem_ref = MemoryRef { mode: AddressingMode::Direct, address };
+                Some(push_expr(&mut body, ExprKind::MemoryRef(mem_ref)))
+            }
+            Operand::Label(name) => {
+                Some(push_expr(&mut body, ExprKind::Literal(Literal::Label(name.to_string()))))
+            }
+        };
+
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: None,
+            span: index * 10..index * 10 + 5,
+            docs: Vec::new(),
+        });
+    }
+    for (index, &(name, instruction)) in labels.iter().enumerate() {
+        body.labels.alloc(Label {
+            id: LocalDefId((instructions.len() + index) as u32),
+            name: name.to_string(),
+            instruction_id: Some(LocalDefId(instruction)),
+            span: 0..0,
+            docs: Vec::new(),
+        });
+    }
+    body
+}
+
+/// Number the versions of the accumulator of a body and propagate constants
+fn analyze(body: Body) -> AnalysisContext {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<DominanceAnalysis>().unwrap();
+    pipeline.register::<SsaAnalysis>().unwrap();
+    pipeline.register::<ConstantPropagationAnalysis>().unwrap();
+    pipeline.analyze(Arc::new(body)).unwrap()
+}
+
+/// Get the versions of the accumulator
+fn versions(context: &AnalysisContext) -> Arc<AccumulatorVersions> {
+    context.get_result::<SsaAnalysis>().unwrap()
+}
+
+#[test]
+fn test_versions_join_at_loop_header() {
+    let context = analyze(create_body(
+        &[
+            ("LOAD", Operand::Value(3)),
+            ("JZERO", Operand::Label("end")),
+            ("SUB", Operand::Value(1)),
+            ("JUMP", Operand::Label("loop")),
+            ("HALT", Operand::None),
+        ],
+        &[("loop", 1), ("end", 4)],
+    ));
+    let versions = versions(&context);
+    let id = LocalDefId;
+
+    // The loop joins the loaded value and the decremented one
+    let (load, phi, sub) = (VersionId(1), VersionId(2), VersionId(3));
+    assert_eq!(versions.len(), 4);
+    assert_eq!(versions.definition(VersionId(0)), Some(&VersionDef::Entry));
+    assert_eq!(versions.definition(load), Some(&VersionDef::Instruction(id(0))));
+    assert_eq!(
+        versions.definition(phi),
+        Some(&VersionDef::Phi { instruction: id(1), inputs: vec![load, sub] })
+    );
+    assert_eq!(versions.definition(sub), Some(&VersionDef::Instruction(id(2))));
+
+    assert_eq!(versions.version_before(id(0)), Some(VersionId(0)));
+    assert_eq!(versions.version_read(id(1)), Some(phi));
+    assert_eq!(versions.version_read(id(2)), Some(phi));
+    assert_eq!(versions.version_after(id(2)), Some(sub));
+    assert_eq!(versions.version_before(id(4)), Some(phi));
+    assert_eq!(versions.version_read(id(3)), None);
+    assert_eq!(versions.reaching_definitions(phi), [load, sub]);
+
+    // The value changes around the loop
+    let constants = context.get_result::<ConstantPropagationAnalysis>().unwrap();
+    assert_eq!(constants.constant_values[&id(0)], Some(3));
+    assert_eq!(constants.constant_values[&id(1)], None);
+    assert!(constants.optimized_edges.is_empty());
+}
+
+#[test]
+fn test_constant_around_loop() {
+    // Both versions joined at the loop hold 5, so the loop is never left
+    let context = analyze(create_body(
+        &[
+            ("LOAD", Operand::Value(5)),
+            ("JZERO", Operand::Label("end")),
+            ("LOAD", Operand::Value(5)),
+            ("JUMP", Operand::Label("loop")),
+            ("HALT", Operand::None),
+        ],
+        &[("loop", 1), ("end", 4)],
+    ));
+
+    let constants = context.get_result::<ConstantPropagationAnalysis>().unwrap();
+    assert_eq!(constants.constant_values[&LocalDefId(1)], Some(5));
+    assert_eq!(constants.optimized_edges.get(&LocalDefId(1)), Some(&BranchTaken::Never));
+}
+
+#[test]
+fn test_constant_table() {
+    let context = analyze(create_body(
+        &[("LOAD", Operand::Value(5)), ("ADD", Operand::Register(1)), ("HALT", Operand::None)],
+        &[],
+    ));
+    // Each instruction is written on a line of its own
+    let line_index = LineIndex::new(&"LOAD =5  \n".repeat(3));
+
+    let constants = context.get_result::<ConstantPropagationAnalysis>().unwrap();
+    let table = constants.to_table(context.body(), &line_index);
+    let rows = table.rows.iter().map(|row| (row.line, row.opcode.as_str(), row.acc));
+    assert_eq!(
+        rows.collect::<Vec<_>>(),
+        [(1, "LOAD", Some(5)), (2, "ADD", None), (3, "HALT", None)]
+    );
+    assert_eq!(
+        table.to_string(),
+        "line  opcode  acc\n   1  LOAD    5\n   2  ADD     ?\n   3  HALT    ?\n"
+    );
+}
+
+#[test]
+fn test_unreachable_instructions_have_no_version() {
+    let context = analyze(create_body(
+        &[("HALT", Operand::None), ("LOAD", Operand::Value(1)), ("HALT", Operand::None)],
+        &[],
+    ));
+    let versions = versions(&context);
+
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions.version_before(LocalDefId(1)), None);
+    assert_eq!(versions.defined_by(LocalDefId(1)), None);
+}
+
+#[test]
+fn test_redundant_loads() {
+    let context = analyze(create_body(
+        &[
+            ("READ", Operand::Register(1)),
+            ("LOAD", Operand::Register(1)),
+            ("STORE", Operand::Register(2)),
+            ("LOAD", Operand::Register(2)),
+            ("LOAD", Operand::Register(2)),
+            ("READ", Operand::Register(2)),
+            ("LOAD", Operand::Register(2)),
+            ("HALT", Operand::None),
+        ],
+        &[],
+    ));
+
+    // The load after the store and the one after it are redundant, but not
+    // the one after the register is read again
+    let diagnostics = context.pass_diagnostics::<SsaAnalysis>();
+    let spans = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.message == "Redundant load")
+        .map(|diagnostic| {
+            (diagnostic.labeled_spans[0].0.clone(), diagnostic.labeled_spans[1].0.clone())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(spans, [(30..35, 20..25), (40..45, 30..35)]);
+}
@@ -0,0 +1,109 @@
+//! Tests for the strict RAM analysis
+
+use hir::body::{
+    AddressingMode, ArrayAccess, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef,
+};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+use ram_diagnostics::DiagnosticKind;
+
+use crate::analyzers::strict_ram::{STRICT_RAM_VIOLATION_CODE, StrictRamAnalysis};
+use crate::context::AnalysisContext;
+use crate::pass::AnalysisPass;
+
+/// Create an instruction of a test body
+fn instruction(id: u32, opcode: &str, operand: Option<u32>) -> Instruction {
+    Instruction {
+        id: LocalDefId(id),
+        opcode: opcode.to_string(),
+        kind: InstructionKind::from_name(opcode),
+        operand: operand.map(ExprId),
+        label_name: None,
+        span: 0..0,
+        docs: Vec::new(),
+    }
+}
+
+/// Create an expression of a test body
+fn expr(id: u32, kind: ExprKind) -> Expr {
+    Expr { id: ExprId(id), kind, span: 0..0 }
+}
+
+/// Create a memory reference to the expression `address`
+fn memory_ref(mode: AddressingMode, address: u32) -> ExprKind {
+    ExprKind::MemoryRef(MemoryRef { mode, address: ExprId(address) })
+}
+
+/// Create a body for `loop: LOAD *1`, `JGTZ loop` and `HALT`
+fn create_classic_body() -> Body {
+    let mut body = Body::default();
+    body.instructions.alloc(Instruction {
+        label_name: Some("loop".to_string()),
+        ..instruction(0, "LOAD", Some(0))
+    });
+    body.instructions.alloc(instruction(1, "JGTZ", Some(2)));
+    body.instructions.alloc(instruction(2, "HALT", None));
+    body.labels.alloc(Label {
+        id: LocalDefId(3),
+        name: "loop".to_string(),
+        instruction_id: Some(LocalDefId(0)),
+        span: 0..0,
+        docs: Vec::new(),
+    });
+    body.exprs.alloc(expr(0, memory_ref(AddressingMode::Indirect, 1)));
+    body.exprs.alloc(expr(1, ExprKind::Literal(Literal::Int(1))));
+    body.exprs.alloc(expr(2, ExprKind::Literal(Literal::Label("loop".to_string()))));
+    body
+}
+
+/// Run the strict RAM analysis and collect the messages it reports
+fn analyze(body: Body) -> Vec<String> {
+    let mut context = AnalysisContext::from(body);
+    StrictRamAnalysis.run(&mut context).unwrap();
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert!(diagnostics.iter().all(|d| d.code.as_deref() == Some(STRICT_RAM_VIOLATION_CODE)));
+    assert!(diagnostics.iter().all(|d| d.kind == DiagnosticKind::Error));
+    diagnostics.iter().map(|d| d.message.clone()).collect()
+}
+
+#[test]
+fn test_accepts_classic_programs() {
+    assert!(analyze(create_classic_body()).is_empty());
+}
+
+#[test]
+fn test_requires_halt() {
+    let mut body = create_classic_body();
+    body.instructions = body.instructions.values().take(2).cloned().collect();
+    assert_eq!(analyze(body), vec!["Program has no HALT instruction"]);
+}
+
+#[test]
+fn test_rejects_constructs_outside_the_model() {
+    // LOAD *-1, STORE loop, LOAD 2[-1], JUMP =0 and HALT
+    let mut body = create_classic_body();
+    body.instructions = body.instructions.values().take(1).cloned().collect();
+    body.exprs[ExprId(1).idx()].kind = ExprKind::Literal(Literal::Int(-1));
+    body.instructions.alloc(instruction(1, "STORE", Some(2)));
+    body.instructions.alloc(instruction(2, "LOAD", Some(3)));
+    body.instructions.alloc(instruction(4, "JUMP", Some(7)));
+    body.instructions.alloc(instruction(5, "HALT", None));
+    body.exprs.alloc(expr(3, memory_ref(AddressingMode::Direct, 4)));
+    body.exprs
+        .alloc(expr(4, ExprKind::ArrayAccess(ArrayAccess { array: ExprId(5), index: ExprId(6) })));
+    body.exprs.alloc(expr(5, ExprKind::Literal(Literal::Int(2))));
+    body.exprs.alloc(expr(6, ExprKind::Literal(Literal::Int(-1))));
+    body.exprs.alloc(expr(7, ExprKind::Literal(Literal::Int(0))));
+
+    assert_eq!(
+        analyze(body),
+        vec![
+            "Negative indirect address -1",
+            "'STORE' uses a label as a register",
+            "Negative address -1",
+            "'JUMP' jumps to an immediate operand",
+        ]
+    );
+}
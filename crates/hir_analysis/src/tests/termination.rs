@@ -0,0 +1,200 @@
+//! Tests for the termination analysis
+
+use std::sync::Arc;
+
+use hir::body::{AddressingMode, Body, Expr, ExprKind, Instruction, Label, Literal, MemoryRef};
+use hir::expr::ExprId;
+use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
+
+use crate::analyzers::termination::{MAY_NOT_HALT_CODE, NonTermination, TerminationAnalysis};
+use crate::{
+    AliasAnalysis, AnalysisContext, AnalysisPipeline, ConstantPropagationAnalysis,
+    ControlFlowAnalysis, DominanceAnalysis, SsaAnalysis,
+};
+
+/// An operand of a test instruction
+enum Operand {
+    /// An immediate value, like `=5`
+    Value(i64),
+    /// A register, like `5`
+    Direct(i64),
+    /// A label, like `loop`
+    Label(&'static str),
+}
+
+/// Add an expression to a body
+fn push_expr(body: &mut Body, kind: ExprKind) -> ExprId {
+    let id = ExprId(body.exprs.len() as u32);
+    body.exprs.alloc(Expr { id, kind, span: 0..0 });
+    id
+}
+
+/// Create a body from its lines, each with a label, an opcode and an operand
+///
+/// The instruction of the line at index `i` has the ID `i` and spans the
+/// bytes `10 * i..10 * i + 5`.
+fn create_body(lines: &[(Option<&str>, &str, Option<Operand>)]) -> Body {
+    let mut body = Body::default();
+    for (index, (label, opcode, operand)) in lines.iter().enumerate() {
+        let operand = operand.as_ref().map(|operand| match *operand {
+            Operand::Value(value) => push_expr(&mut body, ExprKind::Literal(Literal::Int(value))),
+            Operand::Direct(register) => {
+                let address = push_expr(&mut body, ExprKind::Literal(Literal::Int(register)));
+                let mem_ref = MemoryRef { mode: AddressingMode::Direct, address };
+                push_expr(&mut body, ExprKind::MemoryRef(mem_ref))
+            }
+            Operand::Label(name) => {
+                push_expr(&mut body, ExprKind::Literal(Literal::Label(name.to_string())))
+            }
+        });
+        if let Some(name) = label {
+            let id = LocalDefId(body.labels.len() as u32 + 100);
+            body.labels.alloc(Label {
+                id,
+                name: name.to_string(),
+                instruction_id: Some(LocalDefId(index as u32)),
+                span: 0..0,
+                docs: Vec::new(),
+            });
+        }
+        body.instructions.alloc(Instruction {
+            id: LocalDefId(index as u32),
+            opcode: opcode.to_string(),
+            kind: InstructionKind::from_name(opcode),
+            operand,
+            label_name: label.map(str::to_string),
+            span: 10 * index..10 * index + 5,
+            docs: Vec::new(),
+        });
+    }
+    body
+}
+
+/// Run the termination analysis and the passes it needs on a body
+fn analyze(body: Body) -> AnalysisContext {
+    let mut pipeline = AnalysisPipeline::new();
+    pipeline.register::<ControlFlowAnalysis>().unwrap();
+    pipeline.register::<DominanceAnalysis>().unwrap();
+    pipeline.register::<SsaAnalysis>().unwrap();
+    pipeline.register::<ConstantPropagationAnalysis>().unwrap();
+    pipeline.register::<AliasAnalysis>().unwrap();
+    pipeline.register::<TerminationAnalysis>().unwrap();
+    pipeline.analyze(Arc::new(body)).unwrap()
+}
+
+/// Create a loop that counts register 1 down, after `setup` sets it
+///
+/// ```text
+///       <setup>
+/// loop: LOAD 1
+///       JZERO done
+///       SUB =1
+///       STORE 1
+///       JUMP loop
+/// done: HALT
+/// ```
+fn countdown(setup: (&'static str, Operand)) -> Body {
+    let (opcode, operand) = setup;
+    create_body(&[
+        (None, opcode, Some(operand)),
+        (None, "STORE", Some(Operand::Direct(1))),
+        (Some("loop"), "LOAD", Some(Operand::Direct(1))),
+        (None, "JZERO", Some(Operand::Label("done"))),
+        (None, "SUB", Some(Operand::Value(1))),
+        (None, "STORE", Some(Operand::Direct(1))),
+        (None, "JUMP", Some(Operand::Label("loop"))),
+        (Some("done"), "HALT", None),
+    ])
+}
+
+#[test]
+fn test_loop_free_program_halts() {
+    let context = analyze(create_body(&[
+        (None, "READ", Some(Operand::Direct(1))),
+        (None, "LOAD", Some(Operand::Direct(1))),
+        (None, "JZERO", Some(Operand::Label("end"))),
+        (None, "WRITE", Some(Operand::Direct(1))),
+        (Some("end"), "HALT", None),
+    ]));
+
+    let result = context.get_result::<TerminationAnalysis>().unwrap();
+    assert!(result.halts());
+    assert!(result.counters.is_empty());
+    let diagnostics = context.pass_diagnostics::<TerminationAnalysis>();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Program is guaranteed to halt");
+}
+
+#[test]
+fn test_counter_bounded_by_input() {
+    // The loop runs while the input is positive, whatever it is
+    let context = analyze(create_body(&[
+        (None, "READ", Some(Operand::Direct(1))),
+        (Some("loop"), "LOAD", Some(Operand::Direct(1))),
+        (None, "JGTZ", Some(Operand::Label("body"))),
+        (None, "HALT", None),
+        (Some("body"), "SUB", Some(Operand::Value(1))),
+        (None, "STORE", Some(Operand::Direct(1))),
+        (None, "JUMP", Some(Operand::Label("loop"))),
+    ]));
+
+    let result = context.get_result::<TerminationAnalysis>().unwrap();
+    assert!(result.halts());
+    let [counter] = result.counters[..] else {
+        panic!("the loop should have a counter");
+    };
+    assert_eq!((counter.register, counter.step), (1, -1));
+    let cfg = context.get_result::<ControlFlowAnalysis>().unwrap();
+    assert_eq!(cfg.get_node(counter.test).instruction_id, Some(LocalDefId(2)));
+}
+
+#[test]
+fn test_counter_bounded_by_constant() {
+    // Counting down from 5 reaches zero, as the range of the counter shows
+    let context = analyze(countdown(("LOAD", Operand::Value(5))));
+    let result = context.get_result::<TerminationAnalysis>().unwrap();
+    assert!(result.halts());
+    assert_eq!(result.counters.len(), 1);
+}
+
+#[test]
+fn test_counter_that_may_skip_its_exit() {
+    // A negative input never counts down to zero
+    let context = analyze(countdown(("READ", Operand::Direct(1))));
+    let result = context.get_result::<TerminationAnalysis>().unwrap();
+    assert!(!result.halts());
+    let cfg = context.get_result::<ControlFlowAnalysis>().unwrap();
+    let header = cfg.get_node_by_instruction(LocalDefId(2)).unwrap();
+    assert_eq!(result.reasons, [NonTermination::Loop(header)]);
+
+    let diagnostics = context.pass_diagnostics::<TerminationAnalysis>();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Loop may not halt");
+    assert_eq!(diagnostics[0].code.as_deref(), Some(MAY_NOT_HALT_CODE));
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 20..25);
+    assert_eq!(diagnostics[0].labeled_spans[1].0, 60..65);
+}
+
+#[test]
+fn test_cycle_with_two_entries() {
+    // `a` and `b` both jump into the cycle, so neither heads a loop
+    let context = analyze(create_body(&[
+        (None, "READ", Some(Operand::Direct(1))),
+        (None, "LOAD", Some(Operand::Direct(1))),
+        (None, "JZERO", Some(Operand::Label("b"))),
+        (Some("a"), "WRITE", Some(Operand::Direct(1))),
+        (Some("b"), "READ", Some(Operand::Direct(1))),
+        (None, "LOAD", Some(Operand::Direct(1))),
+        (None, "JGTZ", Some(Operand::Label("a"))),
+        (None, "HALT", None),
+    ]));
+
+    let result = context.get_result::<TerminationAnalysis>().unwrap();
+    assert!(matches!(&result.reasons[..], [NonTermination::Cycle(nodes)] if nodes.len() == 4));
+
+    let diagnostics = context.pass_diagnostics::<TerminationAnalysis>();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Cycle may not halt");
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 30..65);
+}
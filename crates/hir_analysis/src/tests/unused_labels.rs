@@ -0,0 +1,124 @@
+//! Tests for the unused label analysis
+
+use hir::body::{Body, Expr, ExprKind, Instruction, Label, LabelRef, Literal};
+use hir::expr::ExprId;
+use hir::ids::{DefId, LocalDefId};
+use ram_core::InstructionKind;
+use ram_diagnostics::DiagnosticKind;
+
+use crate::analyzers::unused_labels::{UNUSED_LABEL_CODE, UnusedLabelAnalysis};
+use crate::context::AnalysisContext;
+use crate::pass::AnalysisPass;
+
+/// Create a body with a `loop` label targeted by a jump and an unused `end` label
+fn create_test_body() -> Body {
+    let mut body = Body::default();
+
+    body.instructions.alloc(Instruction {
+        id: LocalDefId(2),
+        opcode: "READ".to_string(),
+        kind: InstructionKind::from_name("READ"),
+        operand: Some(ExprId(0)),
+        label_name: Some("loop".to_string()),
+        span: 6..12,
+        docs: Vec::new(),
+    });
+    body.instructions.alloc(Instruction {
+        id: LocalDefId(3),
+        opcode: "JUMP".to_string(),
+        kind: InstructionKind::from_name("JUMP"),
+        operand: Some(ExprId(1)),
+        label_name: None,
+        span: 13..22,
+        docs: Vec::new(),
+    });
+    body.instructions.alloc(Instruction {
+        id: LocalDefId(4),
+        opcode: "HALT".to_string(),
+        kind: InstructionKind::from_name("HALT"),
+        operand: None,
+        label_name: Some("end".to_string()),
+        span: 28..32,
+        docs: Vec::new(),
+    });
+
+    body.exprs.alloc(Expr {
+        id: ExprId(0),
+        kind: ExprKind::Literal(Literal::Int(1)),
+        span: 11..12,
+    });
+    body.exprs.alloc(Expr {
+        id: ExprId(1),
+        kind: ExprKind::LabelRef(LabelRef {
+            label_id: DefId { local_id: LocalDefId(0), ..DefId::default() },
+        }),
+        span: 18..22,
+    });
+
+    body.labels.alloc(Label {
+        id: LocalDefId(0),
+        name: "loop".to_string(),
+        instruction_id: Some(LocalDefId(2)),
+        span: 0..5,
+        docs: Vec::new(),
+    });
+    body.labels.alloc(Label {
+        id: LocalDefId(1),
+        name: "end".to_string(),
+        instruction_id: Some(LocalDefId(4)),
+        span: 23..27,
+        docs: Vec::new(),
+    });
+
+    body
+}
+
+#[test]
+fn test_reports_unused_label() {
+    let mut context = AnalysisContext::from(create_test_body());
+
+    let unused = UnusedLabelAnalysis::default().run(&mut context).unwrap();
+    assert_eq!(unused, vec![LocalDefId(1)]);
+
+    let diagnostics = context.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::Warning);
+    assert_eq!(diagnostics[0].code.as_deref(), Some(UNUSED_LABEL_CODE));
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 23..27);
+    assert!(diagnostics[0].message.contains("'end'"));
+}
+
+#[test]
+fn test_label_literal_counts_as_use() {
+    let mut body = create_test_body();
+    body.exprs.alloc(Expr {
+        id: ExprId(2),
+        kind: ExprKind::Literal(Literal::Label("end".to_string())),
+        span: 0..0,
+    });
+    let mut context = AnalysisContext::from(body);
+
+    let unused = UnusedLabelAnalysis::default().run(&mut context).unwrap();
+    assert!(unused.is_empty());
+    assert!(context.diagnostics().diagnostics().is_empty());
+}
+
+#[test]
+fn test_skips_labels_annotated_as_used() {
+    let mut body = create_test_body();
+    body.labels.values_mut().nth(1).unwrap().docs =
+        vec![" Entry point for the runtime".to_string(), " #[used]".to_string()];
+    let mut context = AnalysisContext::from(body);
+
+    let unused = UnusedLabelAnalysis::default().run(&mut context).unwrap();
+    assert!(unused.is_empty());
+}
+
+#[test]
+fn test_skips_exported_labels() {
+    let mut context = AnalysisContext::from(create_test_body());
+
+    let analysis = UnusedLabelAnalysis::with_exported(["end".to_string()]);
+    let unused = analysis.run(&mut context).unwrap();
+    assert!(unused.is_empty());
+}
@@ -1,6 +1,7 @@
 use hir::body::{Body, Expr, ExprKind, Instruction, Label, Literal};
 use hir::expr::ExprId;
 use hir::ids::LocalDefId;
+use ram_core::InstructionKind;
 
 use crate::visitors::traits::Visitor;
 use crate::visitors::walkers::walk_body;
@@ -15,55 +16,62 @@ fn create_test_body() -> Body {
     let mut body = Body::default();
 
     // Add some instructions
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(0),
         opcode: "LOAD".to_string(),
+        kind: InstructionKind::from_name("LOAD"),
         operand: Some(ExprId(0)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(1),
         opcode: "ADD".to_string(),
+        kind: InstructionKind::from_name("ADD"),
         operand: Some(ExprId(1)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
-    body.instructions.push(Instruction {
+    body.instructions.alloc(Instruction {
         id: LocalDefId(2),
         opcode: "STORE".to_string(),
+        kind: InstructionKind::from_name("STORE"),
         operand: Some(ExprId(2)),
         label_name: None,
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     // Add some expressions
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(0),
         kind: ExprKind::Literal(Literal::Int(10)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(1),
         kind: ExprKind::Literal(Literal::Int(20)),
         span: 0..0, // Default span
     });
 
-    body.exprs.push(Expr {
+    body.exprs.alloc(Expr {
         id: ExprId(2),
         kind: ExprKind::Literal(Literal::Int(30)),
         span: 0..0, // Default span
     });
 
     // Add a label
-    body.labels.push(Label {
+    body.labels.alloc(Label {
         id: LocalDefId(3),
         name: "LOOP".to_string(),
         instruction_id: Some(LocalDefId(0)),
         span: 0..0, // Default span
+        docs: Vec::new(),
     });
 
     body
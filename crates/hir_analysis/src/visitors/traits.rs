@@ -5,7 +5,9 @@
 
 use std::ops::ControlFlow;
 
-use hir::body::{ArrayAccess, Body, Expr, Instruction, InstructionCall, Label, Literal, MemoryRef};
+use hir::body::{
+    ArrayAccess, BinaryExpr, Body, Expr, Instruction, InstructionCall, Label, Literal, MemoryRef,
+};
 use hir::expr::ExprId;
 
 /// Result type for visitor methods
@@ -81,7 +83,7 @@ pub trait Visitor {
     /// This method is called when visiting an expression by its ID. It's useful when
     /// traversing references to expressions.
     fn visit_expr_id(&mut self, expr_id: ExprId, body: &Body) -> VisitorResult<Self::Result> {
-        if let Some(expr) = body.exprs.get(expr_id.0 as usize) {
+        if let Some(expr) = body.expr(expr_id) {
             self.visit_expr(expr)
         } else {
             ControlFlow::Continue(())
@@ -123,6 +125,13 @@ pub trait Visitor {
         ControlFlow::Continue(())
     }
 
+    /// Visit an arithmetic expression
+    ///
+    /// This method is called when visiting an arithmetic expression in an operand.
+    fn visit_binary_expr(&mut self, _binary: &BinaryExpr) -> VisitorResult<Self::Result> {
+        ControlFlow::Continue(())
+    }
+
     /// Visit an instruction
     ///
     /// This method is called when visiting an instruction.
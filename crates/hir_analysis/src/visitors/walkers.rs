@@ -63,21 +63,21 @@ pub fn walk_body<V: Visitor>(mut visitor: V, body: &Body) -> V::Result {
     }
 
     // Visit all instructions
-    for instruction in &body.instructions {
+    for instruction in body.instructions.values() {
         if let ControlFlow::Break(result) = walk_instruction(&mut visitor, instruction, body) {
             return result;
         }
     }
 
     // Visit all labels
-    for label in &body.labels {
+    for label in body.labels.values() {
         if let ControlFlow::Break(result) = walk_label(&mut visitor, label, body) {
             return result;
         }
     }
 
     // Visit all expressions
-    for expr in &body.exprs {
+    for expr in body.exprs.values() {
         if let ControlFlow::Break(result) = walk_expr(&mut visitor, expr, body) {
             return result;
         }
@@ -138,6 +138,20 @@ pub fn walk_expr<V: Visitor>(
             // Visit the index expression
             visitor.visit_expr_id(array_access.index, body)
         }
+        ExprKind::Binary(binary) => {
+            // Visit the arithmetic expression
+            if let ControlFlow::Break(result) = visitor.visit_binary_expr(binary) {
+                return ControlFlow::Break(result);
+            }
+
+            // Visit the left-hand side
+            if let ControlFlow::Break(result) = visitor.visit_expr_id(binary.lhs, body) {
+                return ControlFlow::Break(result);
+            }
+
+            // Visit the right-hand side
+            visitor.visit_expr_id(binary.rhs, body)
+        }
         ExprKind::InstructionCall(call) => {
             // Visit the instruction call
             if let ControlFlow::Break(result) = visitor.visit_instruction_call(call) {
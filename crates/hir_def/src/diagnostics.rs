@@ -0,0 +1,152 @@
+//! Diagnostics produced while building the ItemTree
+//!
+//! These are reported for problems that can be detected from the item
+//! summary alone, such as a label being defined more than once. They are
+//! kept as plain data so the ItemTree stays comparable, and are converted
+//! into [`ram_diagnostics::Diagnostic`] when they need to be reported.
+
+use std::ops::Range;
+
+use base_db::input::FileId;
+use cstree::text::TextRange;
+use ram_diagnostics::{Diagnostic, codes};
+
+/// A diagnostic found during ItemTree lowering
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemTreeDiagnostic {
+    /// A label was defined more than once
+    DuplicateLabel {
+        /// The name of the label
+        name: String,
+        /// The file containing the first definition
+        first_file: FileId,
+        /// The range of the first definition
+        first_range: TextRange,
+        /// The file containing the duplicate definition
+        file_id: FileId,
+        /// The range of the duplicate definition
+        range: TextRange,
+        /// A name that is not used by any other label in the file
+        suggested_name: String,
+    },
+
+    /// A label has the same name as a module declared in the same file
+    LabelShadowsModule {
+        /// The name of the label
+        name: String,
+        /// The file containing the label
+        file_id: FileId,
+        /// The range of the label definition
+        range: TextRange,
+        /// The range of the module declaration
+        module_range: TextRange,
+    },
+
+    /// A label has the same name as a symbol imported with `use`
+    LabelShadowsImport {
+        /// The name of the label
+        name: String,
+        /// The file containing the label
+        file_id: FileId,
+        /// The range of the label definition
+        range: TextRange,
+        /// The range of the `use` statement
+        import_range: TextRange,
+    },
+}
+
+impl ItemTreeDiagnostic {
+    /// Returns the file the diagnostic should be reported in
+    pub fn file_id(&self) -> FileId {
+        match self {
+            ItemTreeDiagnostic::DuplicateLabel { file_id, .. }
+            | ItemTreeDiagnostic::LabelShadowsModule { file_id, .. }
+            | ItemTreeDiagnostic::LabelShadowsImport { file_id, .. } => *file_id,
+        }
+    }
+
+    /// Converts this diagnostic into a reportable [`Diagnostic`]
+    ///
+    /// Spans in other files can't be named, so they are described in a note.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        self.to_diagnostic_with_files(|_| None)
+    }
+
+    /// Converts this diagnostic into a reportable [`Diagnostic`], naming files with `file_name`
+    ///
+    /// Spans in files that `file_name` can name are reported as external spans,
+    /// others are described in a note.
+    pub fn to_diagnostic_with_files(
+        &self,
+        file_name: impl Fn(FileId) -> Option<String>,
+    ) -> Diagnostic {
+        let diagnostic = match self {
+            ItemTreeDiagnostic::DuplicateLabel {
+                name,
+                first_file,
+                first_range,
+                file_id,
+                range,
+                suggested_name,
+            } => {
+                let builder = Diagnostic::builder()
+                    .with_message(format!("Label '{name}' is defined multiple times"))
+                    .with_help(format!("Rename this label, for example to '{suggested_name}'"))
+                    .with_primary_span(to_span(*range), "label redefined here")
+                    .with_code(codes::DUPLICATE_LABEL.code);
+
+                if first_file == file_id {
+                    builder
+                        .with_secondary_span(to_span(*first_range), "first defined here")
+                        .with_note("Jumps to this label always target the first definition.")
+                        .build_error()
+                } else if let Some(first_file_name) = file_name(*first_file) {
+                    builder
+                        .with_external_span(
+                            first_file_name,
+                            to_span(*first_range),
+                            "first defined here",
+                        )
+                        .with_note("Jumps to this label always target the first definition.")
+                        .build_error()
+                } else {
+                    builder
+                        .with_note(format!(
+                            "'{name}' is first defined in file {first_file} at {first_range:?}."
+                        ))
+                        .with_note("Jumps to this label always target the first definition.")
+                        .build_error()
+                }
+            }
+            ItemTreeDiagnostic::LabelShadowsModule { name, range, module_range, .. } => {
+                Diagnostic::builder()
+                    .with_message(format!("Label '{name}' shadows the module '{name}'"))
+                    .with_help("Rename the label to avoid confusing it with the module")
+                    .with_primary_span(to_span(*range), "label defined here")
+                    .with_secondary_span(to_span(*module_range), "module declared here")
+                    .with_code(codes::LABEL_SHADOWS_MODULE.code)
+                    .build_warning()
+            }
+            ItemTreeDiagnostic::LabelShadowsImport { name, range, import_range, .. } => {
+                Diagnostic::builder()
+                    .with_message(format!("Label '{name}' shadows an imported symbol"))
+                    .with_help("Rename the label or remove the import")
+                    .with_primary_span(to_span(*range), "label defined here")
+                    .with_secondary_span(to_span(*import_range), "symbol imported here")
+                    .with_code(codes::LABEL_SHADOWS_IMPORT.code)
+                    .with_note("References to this name resolve to the local label.")
+                    .build_warning()
+            }
+        };
+
+        match file_name(self.file_id()) {
+            Some(name) => diagnostic.with_file(name),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Converts a [`TextRange`] into a byte range usable by diagnostics
+fn to_span(range: TextRange) -> Range<usize> {
+    range.start().into()..range.end().into()
+}
@@ -5,11 +5,15 @@
 //! the AST and HIR, providing a stable representation that is less affected
 //! by edits inside function bodies.
 
+use std::fmt;
+
 use base_db::input::FileId;
 use ram_syntax::{ResolvedNode, ast};
 
+use crate::diagnostics::ItemTreeDiagnostic;
+
 /// A unique identifier for an item within an ItemTree
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ItemTreeId(pub u32); // Make pub for use in hir::lower
 
 /// The ItemTree holds a summary of the top-level items in a source file.
@@ -27,6 +31,85 @@ pub struct ItemTree {
 
     /// Documentation comments attached to items
     pub doc_comments: Vec<DocComment>,
+
+    /// Diagnostics found while lowering the items of this file
+    pub diagnostics: Vec<ItemTreeDiagnostic>,
+}
+
+/// The kind of an item in the ItemTree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemKind {
+    /// A module declaration
+    Module,
+    /// A use statement
+    Use,
+    /// A label definition
+    Label,
+}
+
+/// An item of the ItemTree, whatever its kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item<'a> {
+    /// A module declaration
+    Module(&'a ModuleDef),
+    /// A use statement
+    Use(&'a UseDef),
+    /// A label definition
+    Label(&'a LabelDef),
+}
+
+impl Item<'_> {
+    /// Returns the ID of the item in its ItemTree
+    pub fn id(&self) -> ItemTreeId {
+        match self {
+            Item::Module(module) => module.id,
+            Item::Use(use_def) => use_def.id,
+            Item::Label(label) => label.id,
+        }
+    }
+
+    /// Returns the kind of the item
+    pub fn kind(&self) -> ItemKind {
+        match self {
+            Item::Module(_) => ItemKind::Module,
+            Item::Use(_) => ItemKind::Use,
+            Item::Label(_) => ItemKind::Label,
+        }
+    }
+
+    /// Returns the name of the item, or the imported path for use statements
+    pub fn name(&self) -> String {
+        match self {
+            Item::Module(module) => module.name.clone(),
+            Item::Use(use_def) => use_def.path.to_string(),
+            Item::Label(label) => label.name.clone(),
+        }
+    }
+
+    /// Returns the source location of the item
+    pub fn source(&self) -> &ItemSource {
+        match self {
+            Item::Module(module) => &module.source,
+            Item::Use(use_def) => &use_def.source,
+            Item::Label(label) => &label.source,
+        }
+    }
+}
+
+/// A reference to an item that survives edits of its file
+///
+/// [`ItemTreeId`]s are handed out in source order, so adding an item shifts the
+/// IDs of every item after it. An anchor names an item by its kind and name
+/// instead, counting earlier items of the same kind and name to tell repeated
+/// ones apart, and can be resolved against the ItemTree of the edited file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ItemAnchor {
+    /// The kind of the item
+    pub kind: ItemKind,
+    /// The name of the item, as returned by [`Item::name`]
+    pub name: String,
+    /// The number of earlier items with the same kind and name
+    pub occurrence: usize,
 }
 
 /// A module declaration in the ItemTree
@@ -74,6 +157,40 @@ pub enum ModulePath {
     },
 }
 
+impl fmt::Display for ModulePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModulePath::Simple { module, symbol } => {
+                write!(f, "{module}::{}", symbol.as_deref().unwrap_or("*"))
+            }
+            ModulePath::Nested { segments, is_wildcard } => {
+                write!(f, "{}", segments.join("::"))?;
+                if *is_wildcard { write!(f, "::*") } else { Ok(()) }
+            }
+        }
+    }
+}
+
+impl ModulePath {
+    /// Returns the module a path imports from and the symbol it imports,
+    /// `None` for a wildcard
+    ///
+    /// A nested path imports from its last module. Returns `None` for a
+    /// nested path without a module.
+    pub fn module_and_symbol(&self) -> Option<(&str, Option<&str>)> {
+        match self {
+            ModulePath::Simple { module, symbol } => Some((module, symbol.as_deref())),
+            ModulePath::Nested { segments, is_wildcard: true } => {
+                segments.last().map(|module| (module.as_str(), None))
+            }
+            ModulePath::Nested { segments, is_wildcard: false } => match segments.as_slice() {
+                [.., module, symbol] => Some((module, Some(symbol))),
+                _ => None,
+            },
+        }
+    }
+}
+
 /// A label declaration in the ItemTree
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LabelDef {
@@ -117,4 +234,101 @@ impl ItemTree {
     pub fn lower(ast: &ast::Program, file_id: FileId) -> Self {
         crate::lower::lower_program(ast, file_id)
     }
+
+    /// Looks up a label definition by name
+    pub fn label(&self, name: &str) -> Option<&LabelDef> {
+        self.labels.iter().find(|label| label.name == name)
+    }
+
+    /// Returns every item of the tree, in source order
+    pub fn items(&self) -> Vec<Item<'_>> {
+        let mut items: Vec<Item<'_>> = self
+            .modules
+            .iter()
+            .map(Item::Module)
+            .chain(self.use_stmts.iter().map(Item::Use))
+            .chain(self.labels.iter().map(Item::Label))
+            .collect();
+        items.sort_by_key(|item| item.id());
+        items
+    }
+
+    /// Looks up an item by its ID
+    pub fn item(&self, id: ItemTreeId) -> Option<Item<'_>> {
+        self.modules
+            .iter()
+            .find(|module| module.id == id)
+            .map(Item::Module)
+            .or_else(|| self.use_stmts.iter().find(|use_def| use_def.id == id).map(Item::Use))
+            .or_else(|| self.labels.iter().find(|label| label.id == id).map(Item::Label))
+    }
+
+    /// Returns an anchor for an item that can be resolved after the file is edited
+    pub fn anchor(&self, id: ItemTreeId) -> Option<ItemAnchor> {
+        let item = self.item(id)?;
+        let (kind, name) = (item.kind(), item.name());
+        let occurrence = self
+            .items()
+            .iter()
+            .take_while(|other| other.id() != id)
+            .filter(|other| other.kind() == kind && other.name() == name)
+            .count();
+        Some(ItemAnchor { kind, name, occurrence })
+    }
+
+    /// Finds the item an anchor refers to in this tree
+    pub fn resolve_anchor(&self, anchor: &ItemAnchor) -> Option<ItemTreeId> {
+        self.items()
+            .into_iter()
+            .filter(|item| item.kind() == anchor.kind && item.name() == anchor.name)
+            .nth(anchor.occurrence)
+            .map(|item| item.id())
+    }
+
+    /// Returns the documentation comments attached to an item, in source order
+    pub fn docs(&self, id: ItemTreeId) -> impl Iterator<Item = &str> {
+        self.doc_comments.iter().filter(move |doc| doc.item_id == id).map(|doc| doc.text.as_str())
+    }
+
+    /// Returns the modules imported whole with a wildcard, in source order
+    pub fn wildcard_imports(&self) -> Vec<&str> {
+        self.use_stmts
+            .iter()
+            .filter_map(|use_def| match use_def.path.module_and_symbol() {
+                Some((module, None)) => Some(module),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Reports labels of this tree that are already defined in an included module.
+    ///
+    /// Lowering only sees a single file, so duplicates across module boundaries
+    /// are detected here once the ItemTrees of the included modules are known.
+    pub fn duplicate_labels_in(&self, included: &ItemTree) -> Vec<ItemTreeDiagnostic> {
+        self.labels
+            .iter()
+            .filter_map(|label| {
+                let first = included.label(&label.name)?;
+                Some(ItemTreeDiagnostic::DuplicateLabel {
+                    name: label.name.clone(),
+                    first_file: first.source.file_id,
+                    first_range: first.source.syntax_node.text_range(),
+                    file_id: label.source.file_id,
+                    range: label.source.syntax_node.text_range(),
+                    suggested_name: unused_label_name(&label.name, |candidate| {
+                        self.label(candidate).is_some() || included.label(candidate).is_some()
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns the first `{name}_{n}` that is not reported as taken by `is_taken`.
+pub(crate) fn unused_label_name(name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    (1..)
+        .map(|n| format!("{name}_{n}"))
+        .find(|candidate| !is_taken(candidate))
+        .expect("an unused label name always exists")
 }
@@ -6,9 +6,14 @@
 //! that is less affected by common code edits.
 
 pub mod db;
+pub mod diagnostics;
 pub mod item_scope;
 pub mod item_tree;
 mod lower;
 pub mod path;
 
+#[cfg(test)]
+mod tests;
+
+pub use crate::diagnostics::ItemTreeDiagnostic;
 pub use crate::item_tree::ItemTree;
@@ -6,10 +6,15 @@
 //! function bodies or complex expressions.
 
 use base_db::input::FileId;
+use cstree::text::TextRange;
 use ram_syntax::{AstNode, ast};
 use tracing::warn; // Use warn for potentially unattached doc comments
 
-use crate::item_tree::{DocComment, ItemSource, ItemTree, ItemTreeId, LabelDef, ModuleDef};
+use crate::diagnostics::ItemTreeDiagnostic;
+use crate::item_tree::{
+    DocComment, ItemSource, ItemTree, ItemTreeId, LabelDef, ModuleDef, ModulePath, UseDef,
+    unused_label_name,
+};
 
 /// Lowers an AST `Program` node into an `ItemTree`.
 ///
@@ -32,12 +37,20 @@ struct ItemTreeLowerer {
     next_id: u32,
     /// Stores doc comments encountered before their associated item.
     pending_doc_comments: Vec<String>,
+    /// Labels that redefine an earlier label, as `(name, range)` pairs.
+    duplicate_labels: Vec<(String, TextRange)>,
 }
 
 impl ItemTreeLowerer {
     /// Creates a new `ItemTreeLowerer` for the given file.
     fn new(file_id: FileId) -> Self {
-        Self { tree: ItemTree::new(), file_id, next_id: 0, pending_doc_comments: Vec::new() }
+        Self {
+            tree: ItemTree::new(),
+            file_id,
+            next_id: 0,
+            pending_doc_comments: Vec::new(),
+            duplicate_labels: Vec::new(),
+        }
     }
 
     /// Generates a new unique ID for an item within the `ItemTree`.
@@ -50,23 +63,41 @@ impl ItemTreeLowerer {
     /// Processes the statements within a `Program` node to populate the `ItemTree`.
     fn lower_program_items(&mut self, program: &ast::Program) {
         for stmt in program.statements() {
-            // Process doc comments first, accumulating them.
-            if let Some(doc_comment) = stmt.doc_comment() {
+            // Doc comments not attached to a statement document nothing.
+            if stmt.is_comment() {
+                for group in stmt.comment_groups() {
+                    for doc_comment in group.doc_comments() {
+                        self.collect_pending_doc_comment(&doc_comment);
+                    }
+                }
+                self.clear_pending_doc_comments("a blank line or the end of the file");
+                continue;
+            }
+
+            // Doc comments on the lines right above the statement document its item.
+            for doc_comment in stmt.doc_comments() {
                 self.collect_pending_doc_comment(&doc_comment);
-                continue; // Move to the next statement after collecting the comment.
             }
 
             // Process module declarations.
             if let Some(mod_stmt) = stmt.mod_stmt() {
                 self.lower_module(&mod_stmt);
             }
+            // Process module imports.
+            else if let Some(use_stmt) = stmt.use_stmt() {
+                self.lower_use(&use_stmt);
+            }
             // Process label definitions.
             else if let Some(label_def) = stmt.label_def() {
                 self.lower_label(&label_def);
             }
+            // Doc comments on instructions are lowered with the body.
+            else if stmt.instruction().is_some() {
+                self.pending_doc_comments.clear();
+            }
             // If it's not a doc comment or a known item, clear pending comments.
             else {
-                self.clear_pending_doc_comments("statement that is not a module or label");
+                self.clear_pending_doc_comments("statement that is not an item");
             }
         }
         // Warn about any remaining doc comments at the end of the file.
@@ -104,6 +135,13 @@ impl ItemTreeLowerer {
             return;
         };
 
+        // Keep the first definition and report the redefinition once all labels are known.
+        if self.tree.label(&name).is_some() {
+            self.duplicate_labels.push((name, label.syntax().text_range()));
+            self.clear_pending_doc_comments("duplicate label");
+            return;
+        }
+
         let id = self.next_item_id();
         let source = ItemSource { file_id: self.file_id, syntax_node: label.syntax().clone() };
 
@@ -112,6 +150,35 @@ impl ItemTreeLowerer {
         self.attach_pending_doc_comments(id);
     }
 
+    /// Lowers a module import (`UseStmt`) and adds it to the `ItemTree`.
+    fn lower_use(&mut self, use_stmt: &ast::UseStmt) {
+        let Some(path) = use_stmt.path() else {
+            warn!("Encountered use statement without a path: {:?}", use_stmt.syntax().text_range());
+            self.clear_pending_doc_comments("use statement without a path");
+            return;
+        };
+
+        let mut segments = path.segments();
+        let is_wildcard = path.is_wildcard();
+        let path = match (segments.len(), is_wildcard) {
+            (0, _) => {
+                self.clear_pending_doc_comments("use statement without a module");
+                return;
+            }
+            (1, true) => ModulePath::Simple { module: segments.remove(0), symbol: None },
+            (2, false) => {
+                let symbol = segments.pop();
+                ModulePath::Simple { module: segments.remove(0), symbol }
+            }
+            _ => ModulePath::Nested { segments, is_wildcard },
+        };
+
+        let id = self.next_item_id();
+        let source = ItemSource { file_id: self.file_id, syntax_node: use_stmt.syntax().clone() };
+        self.tree.use_stmts.push(UseDef { path, id, source });
+        self.attach_pending_doc_comments(id);
+    }
+
     /// Collects the text of a documentation comment, storing it temporarily.
     fn collect_pending_doc_comment(&mut self, doc_comment: &ast::DocComment) {
         if let Some(text) = doc_comment.text() {
@@ -141,9 +208,64 @@ impl ItemTreeLowerer {
         self.pending_doc_comments.clear();
     }
 
+    /// Reports labels that were defined more than once.
+    fn report_duplicate_labels(&mut self) {
+        let duplicates = std::mem::take(&mut self.duplicate_labels);
+        for (name, range) in &duplicates {
+            let Some(first) = self.tree.label(name) else { continue };
+            let first_range = first.source.syntax_node.text_range();
+            let suggested_name = unused_label_name(name, |candidate| {
+                self.tree.label(candidate).is_some()
+                    || duplicates.iter().any(|(other, _)| other == candidate)
+            });
+            self.tree.diagnostics.push(ItemTreeDiagnostic::DuplicateLabel {
+                name: name.clone(),
+                first_file: self.file_id,
+                first_range,
+                file_id: self.file_id,
+                range: *range,
+                suggested_name,
+            });
+        }
+    }
+
+    /// Reports labels whose names collide with modules or imported symbols.
+    fn report_shadowed_names(&mut self) {
+        let mut diagnostics = Vec::new();
+        for label in &self.tree.labels {
+            let range = label.source.syntax_node.text_range();
+
+            if let Some(module) = self.tree.modules.iter().find(|m| m.name == label.name) {
+                diagnostics.push(ItemTreeDiagnostic::LabelShadowsModule {
+                    name: label.name.clone(),
+                    file_id: self.file_id,
+                    range,
+                    module_range: module.source.syntax_node.text_range(),
+                });
+            }
+
+            let imported = self.tree.use_stmts.iter().find(|use_def| match &use_def.path {
+                ModulePath::Simple { symbol, .. } => symbol.as_deref() == Some(&label.name),
+                ModulePath::Nested { segments, is_wildcard } => {
+                    !is_wildcard && segments.last() == Some(&label.name)
+                }
+            });
+            if let Some(use_def) = imported {
+                diagnostics.push(ItemTreeDiagnostic::LabelShadowsImport {
+                    name: label.name.clone(),
+                    file_id: self.file_id,
+                    range,
+                    import_range: use_def.source.syntax_node.text_range(),
+                });
+            }
+        }
+        self.tree.diagnostics.extend(diagnostics);
+    }
+
     /// Finalizes the lowering process and returns the completed `ItemTree`.
-    fn finish(self) -> ItemTree {
-        // Potential future step: Sort or optimize the tree vectors if needed.
+    fn finish(mut self) -> ItemTree {
+        self.report_duplicate_labels();
+        self.report_shadowed_names();
         self.tree
     }
 }
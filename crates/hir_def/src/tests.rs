@@ -0,0 +1,135 @@
+//! Tests for ItemTree lowering
+
+use base_db::input::FileId;
+use ram_syntax::{AstNode, ast};
+
+use crate::diagnostics::ItemTreeDiagnostic;
+use crate::item_tree::{ItemKind, ItemTree, ModulePath};
+
+/// Helper function to parse a source string and lower it to an ItemTree
+fn lower(source: &str, file_id: FileId) -> ItemTree {
+    let (events, _errors) = ram_parser::parse(source);
+    let (tree, cache) = ram_parser::build_tree(events);
+    let syntax_node = ram_syntax::SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = ast::Program::cast(syntax_node).unwrap();
+    ItemTree::lower(&program, file_id)
+}
+
+#[test]
+fn test_duplicate_label_keeps_first_definition() {
+    let source = "start: LOAD 1\nstart: LOAD 2\nHALT\n";
+    let tree = lower(source, FileId(0));
+
+    assert_eq!(tree.labels.len(), 1);
+    assert_eq!(tree.labels[0].source.syntax_node.text_range().start(), 0.into());
+
+    let [ItemTreeDiagnostic::DuplicateLabel { name, first_range, range, suggested_name, .. }] =
+        tree.diagnostics.as_slice()
+    else {
+        panic!("Expected a single duplicate label diagnostic, got {:?}", tree.diagnostics);
+    };
+    assert_eq!(name, "start");
+    assert_eq!(suggested_name, "start_1");
+    assert!(first_range.start() < range.start());
+
+    let diagnostic = tree.diagnostics[0].to_diagnostic();
+    assert_eq!(diagnostic.code.as_deref(), Some("E003"));
+    assert_eq!(diagnostic.labeled_spans.len(), 2);
+}
+
+#[test]
+fn test_duplicate_label_suggestion_avoids_existing_names() {
+    let source = "loop: LOAD 1\nloop_1: LOAD 2\nloop: HALT\n";
+    let tree = lower(source, FileId(0));
+
+    let [ItemTreeDiagnostic::DuplicateLabel { suggested_name, .. }] = tree.diagnostics.as_slice()
+    else {
+        panic!("Expected a single duplicate label diagnostic, got {:?}", tree.diagnostics);
+    };
+    assert_eq!(suggested_name, "loop_2");
+}
+
+#[test]
+fn test_label_shadowing_module_and_import() {
+    let source = "mod math\nuse io::print\nmath: LOAD 1\nprint: HALT\n";
+    let tree = lower(source, FileId(0));
+
+    assert_eq!(
+        tree.use_stmts[0].path,
+        ModulePath::Simple { module: "io".to_string(), symbol: Some("print".to_string()) }
+    );
+    assert!(matches!(
+        tree.diagnostics.as_slice(),
+        [
+            ItemTreeDiagnostic::LabelShadowsModule { .. },
+            ItemTreeDiagnostic::LabelShadowsImport { .. }
+        ]
+    ));
+}
+
+#[test]
+fn test_duplicate_labels_across_modules() {
+    let main = lower("start: LOAD 1\nHALT\n", FileId(0));
+    let module = lower("start: HALT\n", FileId(1));
+
+    let diagnostics = main.duplicate_labels_in(&module);
+    let [ItemTreeDiagnostic::DuplicateLabel { first_file, file_id, .. }] = diagnostics.as_slice()
+    else {
+        panic!("Expected a single duplicate label diagnostic, got {diagnostics:?}");
+    };
+    assert_eq!(*first_file, FileId(1));
+    assert_eq!(*file_id, FileId(0));
+
+    let diagnostic = diagnostics[0].to_diagnostic_with_files(|file_id| match file_id {
+        FileId(0) => Some("main.ram".to_string()),
+        FileId(1) => Some("math.ram".to_string()),
+        _ => None,
+    });
+    assert_eq!(diagnostic.file.as_deref(), Some("main.ram"));
+    assert_eq!(diagnostic.labeled_spans.len(), 1);
+    assert_eq!(diagnostic.external_spans.len(), 1);
+    assert_eq!(diagnostic.external_spans[0].file, "math.ram");
+    assert_eq!(diagnostic.external_spans[0].span, 0..6);
+}
+
+#[test]
+fn test_items_in_source_order() {
+    let source = "mod math\nuse io::*\nstart: LOAD 1\nmod util\nend: HALT\n";
+    let tree = lower(source, FileId(0));
+
+    let items: Vec<_> = tree.items().iter().map(|item| (item.kind(), item.name())).collect();
+    assert_eq!(
+        items,
+        [
+            (ItemKind::Module, "math".to_string()),
+            (ItemKind::Use, "io::*".to_string()),
+            (ItemKind::Label, "start".to_string()),
+            (ItemKind::Module, "util".to_string()),
+            (ItemKind::Label, "end".to_string()),
+        ]
+    );
+
+    let end = tree.label("end").unwrap();
+    assert_eq!(tree.item(end.id).map(|item| item.name()).as_deref(), Some("end"));
+}
+
+#[test]
+fn test_item_anchors_survive_edits() {
+    let before = lower("use io::print\nstart: LOAD 1\nloop: HALT\n", FileId(0));
+    let after =
+        lower("mod math\nuse io::print\nuse io::read\nstart: LOAD 1\nloop: HALT\n", FileId(0));
+
+    let loop_id = before.label("loop").unwrap().id;
+    let anchor = before.anchor(loop_id).unwrap();
+    assert_eq!(anchor.kind, ItemKind::Label);
+
+    let moved = after.resolve_anchor(&anchor).unwrap();
+    assert_ne!(moved, loop_id);
+    assert_eq!(moved, after.label("loop").unwrap().id);
+
+    let import = before.anchor(before.use_stmts[0].id).unwrap();
+    assert_eq!(after.resolve_anchor(&import), Some(after.use_stmts[0].id));
+
+    let removed = lower("start: LOAD 1\n", FileId(0));
+    assert_eq!(removed.resolve_anchor(&anchor), None);
+}
@@ -0,0 +1,211 @@
+//! Line and column lookup for source text.
+//!
+//! A [`LineIndex`] maps byte offsets in a text to zero-based line and column
+//! pairs and back. Columns are counted in UTF-8 bytes, matching the byte
+//! ranges used by the parser and diagnostics. Editors count columns in UTF-16
+//! or UTF-32 code units instead, so [`LineIndex::to_wide`] and
+//! [`LineIndex::to_utf8`] convert between the two.
+//!
+//! # Examples
+//!
+//! ```
+//! use line_index::{LineCol, LineIndex, WideEncoding, WideLineCol};
+//!
+//! let index = LineIndex::new("LOAD 1\n# héllo\nHALT");
+//!
+//! let line_col = index.line_col(11);
+//! assert_eq!(line_col, LineCol { line: 1, col: 4 });
+//! assert_eq!(index.offset(line_col), Some(11));
+//!
+//! // `é` is two bytes in UTF-8 but a single UTF-16 code unit
+//! let wide = index.to_wide(WideEncoding::Utf16, LineCol { line: 1, col: 5 });
+//! assert_eq!(wide, Some(WideLineCol { line: 1, col: 4 }));
+//! ```
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A zero-based line and column, with the column counted in UTF-8 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    /// The zero-based line number
+    pub line: u32,
+    /// The zero-based column, in UTF-8 bytes
+    pub col: u32,
+}
+
+/// A zero-based line and column, with the column counted in the code units of
+/// a [`WideEncoding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WideLineCol {
+    /// The zero-based line number
+    pub line: u32,
+    /// The zero-based column, in code units of the encoding
+    pub col: u32,
+}
+
+/// An encoding whose code units are used to count columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WideEncoding {
+    /// Columns count UTF-16 code units
+    Utf16,
+    /// Columns count Unicode scalar values
+    Utf32,
+}
+
+impl WideEncoding {
+    /// Returns the number of code units `c` takes in this encoding.
+    pub fn measure(self, c: char) -> usize {
+        match self {
+            WideEncoding::Utf16 => c.len_utf16(),
+            WideEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// A character that takes more than one byte in UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    /// The byte column the character starts at
+    start: usize,
+    /// The character itself
+    c: char,
+}
+
+impl WideChar {
+    /// The byte column just past the character.
+    fn end(self) -> usize {
+        self.start + self.c.len_utf8()
+    }
+
+    /// How many more UTF-8 bytes the character takes than code units in `encoding`.
+    fn excess(self, encoding: WideEncoding) -> usize {
+        self.c.len_utf8() - encoding.measure(self.c)
+    }
+}
+
+/// Maps byte offsets in a text to lines and columns.
+///
+/// Lines are terminated by `\n` or `\r\n`. The terminator belongs to the line
+/// it ends, but is not part of the range returned by [`LineIndex::line_range`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// The byte range of each line, without its terminator
+    lines: Vec<Range<usize>>,
+    /// The multi-byte characters of each line that contains any
+    wide_chars: HashMap<u32, Vec<WideChar>>,
+    /// The length of the text in bytes
+    len: usize,
+}
+
+impl LineIndex {
+    /// Build the line index of `text`.
+    pub fn new(text: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut wide_chars: HashMap<u32, Vec<WideChar>> = HashMap::new();
+        let mut line_start = 0;
+
+        for (offset, c) in text.char_indices() {
+            if c == '\n' {
+                let end = if text[..offset].ends_with('\r') { offset - 1 } else { offset };
+                lines.push(line_start..end);
+                line_start = offset + 1;
+            } else if c.len_utf8() > 1 {
+                wide_chars
+                    .entry(to_u32(lines.len()))
+                    .or_default()
+                    .push(WideChar { start: offset - line_start, c });
+            }
+        }
+        lines.push(line_start..text.len());
+
+        Self { lines, wide_chars, len: text.len() }
+    }
+
+    /// Returns the length of the indexed text in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the indexed text is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of lines. An empty text has one empty line.
+    pub fn line_count(&self) -> u32 {
+        to_u32(self.lines.len())
+    }
+
+    /// Returns the byte range of `line`, without its line terminator.
+    pub fn line_range(&self, line: u32) -> Option<Range<usize>> {
+        self.lines.get(line as usize).cloned()
+    }
+
+    /// Returns the line and column of a byte offset.
+    ///
+    /// Offsets past the end of the text are clamped to its end.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.len);
+        let line = self.lines.partition_point(|range| range.start <= offset) - 1;
+        LineCol { line: to_u32(line), col: to_u32(offset - self.lines[line].start) }
+    }
+
+    /// Returns the byte offset of a line and column.
+    ///
+    /// Columns past the end of the line are clamped to its end, as the LSP
+    /// specification requires. Returns `None` if the line does not exist.
+    pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+        let range = self.line_range(line_col.line)?;
+        Some((range.start + line_col.col as usize).min(range.end))
+    }
+
+    /// Convert a UTF-8 column to a column counted in `encoding`.
+    ///
+    /// Returns `None` if the line does not exist.
+    pub fn to_wide(&self, encoding: WideEncoding, line_col: LineCol) -> Option<WideLineCol> {
+        self.line_range(line_col.line)?;
+        let mut col = line_col.col as usize;
+        for c in self.line_wide_chars(line_col.line) {
+            if c.end() > line_col.col as usize {
+                break;
+            }
+            col -= c.excess(encoding);
+        }
+        Some(WideLineCol { line: line_col.line, col: to_u32(col) })
+    }
+
+    /// Convert a column counted in `encoding` to a UTF-8 column.
+    ///
+    /// Columns past the end of the line are clamped to its end. Returns `None`
+    /// if the line does not exist.
+    pub fn to_utf8(&self, encoding: WideEncoding, line_col: WideLineCol) -> Option<LineCol> {
+        let range = self.line_range(line_col.line)?;
+        let mut col = line_col.col as usize;
+        for c in self.line_wide_chars(line_col.line) {
+            if c.start >= col {
+                break;
+            }
+            col += c.excess(encoding);
+            // A column inside a surrogate pair refers to the start of its character
+            if col < c.end() {
+                col = c.start;
+                break;
+            }
+        }
+        Some(LineCol { line: line_col.line, col: to_u32(col.min(range.len())) })
+    }
+
+    /// Returns the multi-byte characters of `line`, in order.
+    fn line_wide_chars(&self, line: u32) -> impl Iterator<Item = WideChar> + '_ {
+        self.wide_chars.get(&line).into_iter().flatten().copied()
+    }
+}
+
+/// Convert a line or column to `u32`, which is what editors use.
+fn to_u32(value: usize) -> u32 {
+    u32::try_from(value).expect("source text is larger than 4 GiB")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,102 @@
+//! Tests for line lookup and wide column conversion
+
+use crate::{LineCol, LineIndex, WideEncoding, WideLineCol};
+
+fn line_col(line: u32, col: u32) -> LineCol {
+    LineCol { line, col }
+}
+
+fn wide(line: u32, col: u32) -> WideLineCol {
+    WideLineCol { line, col }
+}
+
+#[test]
+fn test_line_col() {
+    let index = LineIndex::new("LOAD 1\nADD 2\n\nHALT");
+
+    assert_eq!(index.line_count(), 4);
+    assert_eq!(index.line_col(0), line_col(0, 0));
+    assert_eq!(index.line_col(6), line_col(0, 6));
+    assert_eq!(index.line_col(7), line_col(1, 0));
+    assert_eq!(index.line_col(13), line_col(2, 0));
+    assert_eq!(index.line_col(16), line_col(3, 2));
+    // Offsets past the end are clamped
+    assert_eq!(index.line_col(100), line_col(3, 4));
+}
+
+#[test]
+fn test_offset() {
+    let index = LineIndex::new("LOAD 1\nADD 2\n\nHALT");
+
+    assert_eq!(index.offset(line_col(0, 0)), Some(0));
+    assert_eq!(index.offset(line_col(1, 4)), Some(11));
+    assert_eq!(index.offset(line_col(3, 4)), Some(18));
+    // Columns past the end of a line are clamped to it
+    assert_eq!(index.offset(line_col(0, 50)), Some(6));
+    assert_eq!(index.offset(line_col(4, 0)), None);
+
+    for offset in 0..=18 {
+        assert_eq!(index.offset(index.line_col(offset)), Some(offset));
+    }
+}
+
+#[test]
+fn test_empty_text() {
+    let index = LineIndex::new("");
+
+    assert!(index.is_empty());
+    assert_eq!(index.line_count(), 1);
+    assert_eq!(index.line_col(0), line_col(0, 0));
+    assert_eq!(index.line_range(0), Some(0..0));
+    assert_eq!(index.line_range(1), None);
+}
+
+#[test]
+fn test_line_range() {
+    let index = LineIndex::new("LOAD 1\r\nHALT\n");
+
+    assert_eq!(index.line_count(), 3);
+    assert_eq!(index.line_range(0), Some(0..6));
+    assert_eq!(index.line_range(1), Some(8..12));
+    assert_eq!(index.line_range(2), Some(13..13));
+    assert_eq!(index.line_col(8), line_col(1, 0));
+    // The carriage return is not part of the line
+    assert_eq!(index.offset(line_col(0, 7)), Some(6));
+}
+
+#[test]
+fn test_wide_columns_utf16() {
+    // `é` is 2 bytes and 1 code unit, `😀` is 4 bytes and 2 code units
+    let index = LineIndex::new("LOAD 1\n# é😀x\nHALT");
+
+    let cases = [(2, 2), (4, 3), (8, 5), (9, 6)];
+    for (utf8, utf16) in cases {
+        assert_eq!(index.to_wide(WideEncoding::Utf16, line_col(1, utf8)), Some(wide(1, utf16)));
+        assert_eq!(index.to_utf8(WideEncoding::Utf16, wide(1, utf16)), Some(line_col(1, utf8)));
+    }
+
+    // Lines without multi-byte characters are unchanged
+    assert_eq!(index.to_wide(WideEncoding::Utf16, line_col(2, 3)), Some(wide(2, 3)));
+    assert_eq!(index.to_wide(WideEncoding::Utf16, line_col(3, 0)), None);
+}
+
+#[test]
+fn test_wide_columns_utf32() {
+    let index = LineIndex::new("é😀x");
+
+    let cases = [(0, 0), (2, 1), (6, 2), (7, 3)];
+    for (utf8, utf32) in cases {
+        assert_eq!(index.to_wide(WideEncoding::Utf32, line_col(0, utf8)), Some(wide(0, utf32)));
+        assert_eq!(index.to_utf8(WideEncoding::Utf32, wide(0, utf32)), Some(line_col(0, utf8)));
+    }
+}
+
+#[test]
+fn test_wide_column_inside_surrogate_pair() {
+    let index = LineIndex::new("a😀b");
+
+    // The second code unit of the emoji refers to the start of the emoji
+    assert_eq!(index.to_utf8(WideEncoding::Utf16, wide(0, 2)), Some(line_col(0, 1)));
+    // Columns past the end of the line are clamped
+    assert_eq!(index.to_utf8(WideEncoding::Utf16, wide(0, 10)), Some(line_col(0, 6)));
+}
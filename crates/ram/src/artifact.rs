@@ -0,0 +1,156 @@
+//! Module for building and verifying bytecode artifacts
+//!
+//! Next to each artifact, `ram build` writes a source map linking its
+//! instructions to the lines of the program they were compiled from, so runs
+//! of the artifact can point at the original source.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use miette::{IntoDiagnostic, Result, miette};
+use ram_core::ArithmeticMode;
+use ram_core::registry::InstructionRegistry;
+use ram_vm::bytecode::{self, Artifact};
+use ram_vm::source_map::SOURCE_MAP_VERSION;
+use ram_vm::{SourceMap, VmDatabase, VmDatabaseImpl};
+
+use crate::cache::Cache;
+use crate::run::compile_program;
+use crate::{VERSION, language};
+
+/// The extension added to the path of an artifact for its source map
+pub const SOURCE_MAP_EXTENSION: &str = "map";
+
+/// Compile a RAM program to a bytecode artifact
+///
+/// The artifact is written to `output`, or next to the program with the
+/// bytecode extension, and its source map next to it, see
+/// [`source_map_path`]. Returns the path of the artifact. With `strict_ram`,
+/// the program is held to the classic RAM model.
+pub fn build_artifact(
+    program_path: &Path,
+    output: Option<&Path>,
+    strict_ram: bool,
+    cache: Option<&Cache>,
+) -> Result<PathBuf> {
+    let program_text = std::fs::read_to_string(program_path).into_diagnostic()?;
+    let db = VmDatabaseImpl::new();
+    let program = compile_program(
+        program_path,
+        &program_text,
+        &db,
+        strict_ram,
+        ArithmeticMode::default(),
+        cache,
+    )?;
+
+    let registry = db.instruction_registry();
+    let artifact = Artifact::new(program, &program_text, VERSION.pkg_version(), &registry);
+
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| program_path.with_extension(bytecode::FILE_EXTENSION));
+    std::fs::write(&output, artifact.to_bytes()).into_diagnostic()?;
+
+    let (_, body, _) = language::lower_program(&program_text);
+    let source_map = SourceMap::from_body(&body, program_path.display().to_string(), &program_text);
+    let json = serde_json::to_vec_pretty(&source_map).into_diagnostic()?;
+    std::fs::write(source_map_path(&output), json).into_diagnostic()?;
+    Ok(output)
+}
+
+/// Get the path of the source map of an artifact, such as `add.rbc.map`
+pub fn source_map_path(artifact: &Path) -> PathBuf {
+    let mut path = OsString::from(artifact);
+    path.push(".");
+    path.push(SOURCE_MAP_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Read a source map
+///
+/// Fails if the file is not a source map, or one of a format this version of
+/// `ram` can not read.
+pub fn load_source_map(path: &Path) -> Result<SourceMap> {
+    let contents = std::fs::read(path).into_diagnostic()?;
+    let source_map: SourceMap = serde_json::from_slice(&contents)
+        .map_err(|e| miette!("Invalid source map {}: {}", path.display(), e))?;
+    if source_map.version != SOURCE_MAP_VERSION {
+        return Err(miette!(
+            "Cannot read the source map {}: it has version {}, but this version of ram reads version {}",
+            path.display(),
+            source_map.version,
+            SOURCE_MAP_VERSION
+        ));
+    }
+    Ok(source_map)
+}
+
+/// Find the source map of a program
+///
+/// Only artifacts have one, written next to them by `ram build`. Returns
+/// `None` for programs run from source and artifacts without a source map.
+pub fn find_source_map(program_path: &Path) -> Result<Option<SourceMap>> {
+    if program_path.extension().is_none_or(|ext| ext != bytecode::FILE_EXTENSION) {
+        return Ok(None);
+    }
+    let path = source_map_path(program_path);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    load_source_map(&path).map(Some)
+}
+
+/// Check the integrity of an artifact and its compatibility with this build
+///
+/// The signature of the artifact and every incompatibility are printed. If
+/// `source` is given, the artifact must have been built from it. Fails if the
+/// artifact is corrupted or can not run.
+pub fn verify_artifact(path: &Path, source: Option<&Path>) -> Result<()> {
+    let source = source.map(std::fs::read_to_string).transpose().into_diagnostic()?;
+    let artifact = read_artifact(path)?;
+    let signature = &artifact.signature;
+
+    println!("Artifact: {}", path.display());
+    println!("Format version: {}", bytecode::FORMAT_VERSION);
+    println!("Compiler version: {}", signature.compiler_version);
+    println!("Source hash: {:016x}", signature.source_hash);
+    println!("Instruction set: {:016x}", signature.instruction_set);
+    println!("Instructions: {}", artifact.program.instructions.len());
+
+    let registry = VmDatabaseImpl::new().instruction_registry();
+    check_compatibility(&artifact, &registry, source.as_deref())
+}
+
+/// Read an artifact to run it, reporting its incompatibilities with this build
+pub fn load_artifact(path: &Path, registry: &InstructionRegistry) -> Result<Artifact> {
+    let artifact = read_artifact(path)?;
+    check_compatibility(&artifact, registry, None)?;
+    Ok(artifact)
+}
+
+/// Read and decode an artifact, checking its integrity
+fn read_artifact(path: &Path) -> Result<Artifact> {
+    let bytes = std::fs::read(path).into_diagnostic()?;
+    Artifact::from_bytes(&bytes)
+        .map_err(|err| miette!("Invalid artifact {}: {}", path.display(), err))
+}
+
+/// Print the incompatibilities of an artifact, failing if any prevents running it
+fn check_compatibility(
+    artifact: &Artifact,
+    registry: &InstructionRegistry,
+    source: Option<&str>,
+) -> Result<()> {
+    let incompatibilities = artifact.verify(VERSION.pkg_version(), registry, source);
+    for incompatibility in &incompatibilities {
+        let level = if incompatibility.is_fatal() { "error" } else { "warning" };
+        eprintln!("{}: artifact {}", level, incompatibility);
+    }
+
+    let fatal = incompatibilities.iter().filter(|incompatibility| incompatibility.is_fatal());
+    match fatal.count() {
+        0 => Ok(()),
+        count => Err(miette!("The artifact is incompatible with this build ({} errors)", count)),
+    }
+}
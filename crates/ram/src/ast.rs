@@ -0,0 +1,346 @@
+//! Module for inspecting the syntax tree of programs
+//!
+//! `ram ast` prints the concrete syntax tree the parser builds for a program,
+//! with its comments, whitespace and error nodes, to debug the parser and to
+//! attach to issue reports. The tree can be narrowed down to the node that
+//! covers a range of the source, and to the nodes of some kinds.
+
+use std::fmt;
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+use base_db::{LineCol, LineIndex};
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use ram_syntax::cstree::util::NodeOrToken;
+use ram_syntax::cstree::{RawSyntaxKind, Syntax};
+use ram_syntax::{AstNode, ResolvedNode, SyntaxKind};
+use serde::Serialize;
+
+use crate::cli::AstFormat;
+use crate::language;
+
+/// A range of the source, between two positions as an editor shows them
+///
+/// Lines and columns count from 1, and the end position is right after the
+/// last character of the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceRange {
+    /// Where the range starts
+    pub start: LineCol,
+    /// Where the range ends
+    pub end: LineCol,
+}
+
+impl SourceRange {
+    /// Get the byte offsets of the range, if it is inside the source
+    pub fn offsets(&self, line_index: &LineIndex) -> Option<Range<usize>> {
+        Some(line_index.offset(self.start)?..line_index.offset(self.end)?)
+    }
+}
+
+impl FromStr for SourceRange {
+    type Err = String;
+
+    /// Parse a range written as `line:col-line:col`, or a position as `line:col`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let position = |position: &str| -> Option<LineCol> {
+            let (line, col) = position.trim().split_once(':')?;
+            let line = line.parse::<u32>().ok()?.checked_sub(1)?;
+            let col = col.parse::<u32>().ok()?.checked_sub(1)?;
+            Some(LineCol { line, col })
+        };
+        let (start, end) = value.split_once('-').unwrap_or((value, value));
+        match (position(start), position(end)) {
+            (Some(start), Some(end)) if start <= end => Ok(Self { start, end }),
+            _ => Err(format!("expected LINE:COL-LINE:COL, counting from 1, got `{value}`")),
+        }
+    }
+}
+
+impl fmt::Display for SourceRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start.line + 1,
+            self.start.col + 1,
+            self.end.line + 1,
+            self.end.col + 1
+        )
+    }
+}
+
+/// Parse the name of a kind of syntax node, such as `INSTRUCTION`
+///
+/// Tokens have kinds too, but only nodes can be selected.
+pub fn parse_node_kind(name: &str) -> Result<SyntaxKind, String> {
+    let name = name.trim().to_ascii_uppercase();
+    let kind = (0..=SyntaxKind::EOF as u32)
+        .map(|raw| SyntaxKind::from_raw(RawSyntaxKind(raw)))
+        .find(|kind| format!("{kind:?}") == name)
+        .ok_or_else(|| format!("unknown syntax kind `{name}`"))?;
+    if kind as u32 >= SyntaxKind::WHITESPACE as u32 {
+        return Err(format!("`{name}` is a token kind, only node kinds can be selected"));
+    }
+    Ok(kind)
+}
+
+/// The part of a syntax tree to print
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AstSelection {
+    /// Only print the smallest node that covers this range
+    pub range: Option<SourceRange>,
+    /// Only print the outermost nodes of these kinds, with their subtrees
+    pub kinds: Vec<SyntaxKind>,
+}
+
+impl AstSelection {
+    /// Select the nodes to print from the root of a syntax tree
+    ///
+    /// Fails if the range is not inside the source.
+    pub fn select(&self, root: &ResolvedNode, source: &str) -> Result<Vec<ResolvedNode>> {
+        let node = match self.range {
+            Some(range) => {
+                let offsets = range
+                    .offsets(&LineIndex::new(source))
+                    .ok_or_else(|| miette!("The range {range} is outside of the program"))?;
+                covering_node(root, offsets)
+            }
+            None => root.clone(),
+        };
+        if self.kinds.is_empty() {
+            return Ok(vec![node]);
+        }
+
+        let mut nodes = Vec::new();
+        collect_kinds(&node, &self.kinds, &mut nodes);
+        Ok(nodes)
+    }
+}
+
+/// Find the smallest node that covers a range of byte offsets
+fn covering_node(root: &ResolvedNode, range: Range<usize>) -> ResolvedNode {
+    let mut node = root.clone();
+    loop {
+        let Some(child) = node
+            .children()
+            .find(|child| {
+                let text_range = child.text_range();
+                usize::from(text_range.start()) <= range.start
+                    && range.end <= usize::from(text_range.end())
+            })
+            .cloned()
+        else {
+            return node;
+        };
+        node = child;
+    }
+}
+
+/// Collect the outermost nodes of some kinds under a node, in order
+fn collect_kinds(node: &ResolvedNode, kinds: &[SyntaxKind], nodes: &mut Vec<ResolvedNode>) {
+    if kinds.contains(&node.kind()) {
+        nodes.push(node.clone());
+        return;
+    }
+    for child in node.children() {
+        collect_kinds(child, kinds, nodes);
+    }
+}
+
+/// A position in the source, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AstPosition {
+    /// The line of the position
+    pub line: u32,
+    /// The column of the position, in bytes
+    pub column: u32,
+}
+
+/// A node or token of the syntax tree, as printed as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AstElement {
+    /// The kind of the element, such as `INSTRUCTION` or `IDENTIFIER`
+    pub kind: String,
+    /// The byte range of the element in the source
+    pub span: Range<usize>,
+    /// Where the element starts
+    pub start: AstPosition,
+    /// Where the element ends
+    pub end: AstPosition,
+    /// The text of the element, if it is a token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The children of the element, if it is a node
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<AstElement>,
+}
+
+impl AstElement {
+    /// Convert a node of the syntax tree, with its subtree
+    pub fn from_node(node: &ResolvedNode, line_index: &LineIndex) -> Self {
+        let children = node
+            .children_with_tokens()
+            .map(|child| match child {
+                NodeOrToken::Node(node) => Self::from_node(node, line_index),
+                NodeOrToken::Token(token) => {
+                    let range = token.text_range();
+                    Self::new(
+                        token.kind(),
+                        usize::from(range.start())..usize::from(range.end()),
+                        Some(token.text().to_string()),
+                        Vec::new(),
+                        line_index,
+                    )
+                }
+            })
+            .collect();
+        let range = node.text_range();
+        let span = usize::from(range.start())..usize::from(range.end());
+        Self::new(node.kind(), span, None, children, line_index)
+    }
+
+    fn new(
+        kind: SyntaxKind,
+        span: Range<usize>,
+        text: Option<String>,
+        children: Vec<AstElement>,
+        line_index: &LineIndex,
+    ) -> Self {
+        let position = |offset| {
+            let LineCol { line, col } = line_index.line_col(offset);
+            AstPosition { line: line + 1, column: col + 1 }
+        };
+        Self {
+            kind: format!("{kind:?}"),
+            start: position(span.start),
+            end: position(span.end),
+            span,
+            text,
+            children,
+        }
+    }
+
+    /// Print the element as an indented tree, one element per line
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        let _ = write!(
+            out,
+            "{:indent$}{} {}:{}-{}:{}",
+            "",
+            self.kind,
+            self.start.line,
+            self.start.column,
+            self.end.line,
+            self.end.column,
+            indent = depth * 2
+        );
+        if let Some(text) = &self.text {
+            let _ = write!(out, " {text:?}");
+        }
+        out.push('\n');
+        for child in &self.children {
+            child.write_tree(out, depth + 1);
+        }
+    }
+}
+
+/// Render the selected nodes of a syntax tree in a format
+pub fn render(nodes: &[ResolvedNode], source: &str, format: AstFormat) -> Result<String> {
+    let line_index = LineIndex::new(source);
+    let elements = || nodes.iter().map(|node| AstElement::from_node(node, &line_index));
+    Ok(match format {
+        AstFormat::Debug => nodes.iter().map(|node| format!("{node:#?}\n")).collect(),
+        AstFormat::Tree => elements().map(|element| element.to_tree()).collect(),
+        AstFormat::Json => {
+            let elements: Vec<_> = elements().collect();
+            format!("{}\n", serde_json::to_string_pretty(&elements).into_diagnostic()?)
+        }
+    })
+}
+
+/// Print the syntax tree of a RAM program, or the part of it selected
+///
+/// Returns the number of nodes printed.
+pub fn print_ast(path: &Path, selection: &AstSelection, format: AstFormat) -> Result<usize> {
+    let source = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read file: {}", path.display()))?;
+    let (program, _body, _diagnostics) = language::lower_program(&source);
+    let nodes = selection.select(program.syntax(), &source)?;
+    print!("{}", render(&nodes, &source, format)?);
+    Ok(nodes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(source: &str, selection: &AstSelection) -> Vec<ResolvedNode> {
+        let (program, _body, _diagnostics) = language::lower_program(source);
+        selection.select(program.syntax(), source).unwrap()
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        let range: SourceRange = "1:6-2:3".parse().unwrap();
+        assert_eq!(range.start, LineCol { line: 0, col: 5 });
+        assert_eq!(range.end, LineCol { line: 1, col: 2 });
+        assert_eq!(range.to_string(), "1:6-2:3");
+        assert_eq!("3:4".parse::<SourceRange>().unwrap().to_string(), "3:4-3:4");
+        assert!("2:1-1:1".parse::<SourceRange>().is_err());
+        assert!("0:1".parse::<SourceRange>().is_err());
+
+        assert_eq!(parse_node_kind("instruction"), Ok(SyntaxKind::INSTRUCTION));
+        assert_eq!(parse_node_kind("STMT"), Ok(SyntaxKind::STMT));
+        assert!(parse_node_kind("IDENTIFIER").is_err());
+        assert!(parse_node_kind("NOT_A_KIND").is_err());
+    }
+
+    #[test]
+    fn test_select_range_and_kinds() {
+        let source = "loop: LOAD =1\nJUMP loop\n";
+        let range = Some("1:12-1:14".parse().unwrap());
+        let nodes = select(source, &AstSelection { range, kinds: Vec::new() });
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].kind(), SyntaxKind::IMMEDIATE_OPERAND);
+        assert_eq!(
+            render(&nodes, source, AstFormat::Tree).unwrap(),
+            "IMMEDIATE_OPERAND 1:12-1:14\n  EQUALS 1:12-1:13 \"=\"\n  OPERAND_VALUE 1:13-1:14\n    NUMBER 1:13-1:14 \"1\"\n"
+        );
+
+        let kinds = vec![SyntaxKind::INSTRUCTION, SyntaxKind::LABEL_DEF];
+        let nodes = select(source, &AstSelection { range: None, kinds });
+        let kinds: Vec<_> = nodes.iter().map(|node| node.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![SyntaxKind::LABEL_DEF, SyntaxKind::INSTRUCTION, SyntaxKind::INSTRUCTION]
+        );
+
+        let range = Some("9:1".parse().unwrap());
+        let (program, _body, _diagnostics) = language::lower_program(source);
+        let selection = AstSelection { range, kinds: Vec::new() };
+        assert!(selection.select(program.syntax(), source).is_err());
+    }
+
+    #[test]
+    fn test_render_json() {
+        let source = "WRITE 1\n";
+        let nodes = select(source, &AstSelection::default());
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&nodes, source, AstFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[0]["kind"], "ROOT");
+        assert_eq!(json[0]["span"]["end"], 8);
+        assert_eq!(json[0]["end"]["line"], 2);
+        let identifier = json[0]["children"][0]["children"][0]["children"][0].clone();
+        assert_eq!(identifier["kind"], "IDENTIFIER");
+        assert_eq!(identifier["text"], "WRITE");
+    }
+}
@@ -0,0 +1,357 @@
+//! On-disk cache of analysis results.
+//!
+//! Validating a program parses, lowers and analyzes it from scratch. The
+//! diagnostics this produces only depend on the program text, the diagnostic
+//! configuration and the version of `ram`, so they are stored in the cache
+//! directory under a hash of those inputs. Later invocations on an unchanged
+//! program report the stored diagnostics instead of running the analysis
+//! passes again.
+//!
+//! Only the diagnostics are cached. `ram validate` needs nothing else, but
+//! `ram run` still parses and lowers the program on a hit to build the program
+//! the VM runs, so it only skips the analysis passes.
+//!
+//! Within one invocation, the database keeps the syntax trees and line
+//! indexes of the most recently used files in memory. How many it keeps is
+//! configured per query, see [`lru_config`], and [`database_stats`] reports
+//! how often they were reused.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use base_db::lru::LruConfigError;
+use base_db::{FileId, LruConfig, QueryStats, SourceDatabase};
+use hir::db::HirDatabase;
+use miette::{IntoDiagnostic, WrapErr};
+use ram_core::InstructionRegistry;
+use ram_diagnostics::{Diagnostic, DiagnosticConfig};
+use ram_vm::VmDatabaseImpl;
+use tracing::debug;
+
+/// The default cache directory, relative to the working directory
+pub const DEFAULT_CACHE_DIR: &str = ".ram_cache";
+
+/// The extension of cache entries
+const ENTRY_EXTENSION: &str = "json";
+
+/// The key a cache entry is stored under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    /// Compute the key of a program analyzed with `config`, validating its
+    /// instructions against `instructions`
+    ///
+    /// The version of `ram` is part of the key, so entries written by other
+    /// versions are never read. So are the instructions, with those of
+    /// loaded plugins, as a program using an instruction of the extended set
+    /// is only valid when it is analyzed with that set.
+    pub fn new(
+        source: &str,
+        config: &DiagnosticConfig,
+        instructions: &InstructionRegistry,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        source.hash(&mut hasher);
+        config.hash(&mut hasher);
+
+        // The registry is unordered, so its instructions are sorted first
+        let mut fingerprint = instructions
+            .kinds()
+            .filter_map(|kind| {
+                let info = instructions.get_info(&kind)?;
+                let effects = instructions.get(&kind)?.effects();
+                Some(format!(
+                    "{} {} {:?} {:?}",
+                    info.name, info.requires_operand, info.allowed_operand_kinds, effects
+                ))
+            })
+            .collect::<Vec<_>>();
+        fingerprint.sort();
+        fingerprint.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+/// The number and size of the entries of a cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of entries
+    pub entries: usize,
+    /// The total size of the entries in bytes
+    pub size: u64,
+}
+
+/// A directory of cached analysis results
+#[derive(Debug, Clone)]
+pub struct Cache {
+    /// The directory the entries are stored in
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Create a cache stored in `dir`
+    ///
+    /// The directory is created when the first entry is written.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Get the directory the entries are stored in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the diagnostics stored under `key`
+    ///
+    /// Missing and unreadable entries are cache misses.
+    pub fn get(&self, key: CacheKey) -> Option<Vec<Diagnostic>> {
+        let contents = std::fs::read(self.entry_path(key)).ok()?;
+        match serde_json::from_slice(&contents) {
+            Ok(diagnostics) => Some(diagnostics),
+            Err(err) => {
+                debug!("Ignoring invalid cache entry {:016x}: {}", key.0, err);
+                None
+            }
+        }
+    }
+
+    /// Store the diagnostics of a program under `key`
+    pub fn put(&self, key: CacheKey, diagnostics: &[Diagnostic]) -> io::Result<()> {
+        self.create_dir()?;
+
+        // Write to a temporary file first so readers never see a partial entry
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(&mut file, diagnostics)?;
+        file.persist(self.entry_path(key))?;
+        Ok(())
+    }
+
+    /// Count the entries of the cache
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for path in self.entry_paths()? {
+            stats.entries += 1;
+            stats.size += std::fs::metadata(path)?.len();
+        }
+        Ok(stats)
+    }
+
+    /// Remove every entry of the cache
+    ///
+    /// The directory itself is only removed if nothing else is left in it.
+    /// Returns the statistics of the removed entries.
+    pub fn clean(&self) -> io::Result<CacheStats> {
+        let stats = self.stats()?;
+        for path in self.entry_paths()? {
+            std::fs::remove_file(path)?;
+        }
+
+        let gitignore = self.dir.join(".gitignore");
+        if gitignore.exists() {
+            std::fs::remove_file(gitignore)?;
+        }
+        if self.dir.exists() && std::fs::read_dir(&self.dir)?.next().is_none() {
+            std::fs::remove_dir(&self.dir)?;
+        }
+        Ok(stats)
+    }
+
+    /// Get the path of the entry stored under `key`
+    fn entry_path(&self, key: CacheKey) -> PathBuf {
+        self.dir.join(format!("{:016x}.{ENTRY_EXTENSION}", key.0))
+    }
+
+    /// Get the paths of all entries
+    fn entry_paths(&self) -> io::Result<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == ENTRY_EXTENSION) {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Create the cache directory if it does not exist yet
+    fn create_dir(&self) -> io::Result<()> {
+        if !self.dir.exists() {
+            std::fs::create_dir_all(&self.dir)?;
+            // Keep the cache out of version control
+            std::fs::write(self.dir.join(".gitignore"), "*\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the LRU capacities of the queries of the database
+///
+/// The capacities set in the manifest override the defaults, and the
+/// `RAM_LRU_<QUERY>` environment variables override both.
+pub fn lru_config(config: &DiagnosticConfig) -> Result<LruConfig, LruConfigError> {
+    let overrides =
+        config.lru_capacities().iter().map(|(query, capacity)| (query.as_str(), *capacity));
+    LruConfig::new().with_overrides(overrides)?.with_env()
+}
+
+/// Load programs into a database and get the memo count and hit rate of
+/// each query after analyzing them
+pub fn database_stats(paths: &[PathBuf], lru: LruConfig) -> miette::Result<Vec<QueryStats>> {
+    let mut db = VmDatabaseImpl::builder().lru_config(lru).build();
+    let mut file_ids = Vec::with_capacity(paths.len());
+    for (index, path) in paths.iter().enumerate() {
+        let source = std::fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to read file: {}", path.display()))?;
+        let file_id = FileId(index as u32);
+        db.set_file_text(file_id, &source);
+        file_ids.push(file_id);
+    }
+
+    for file_id in file_ids {
+        db.resolve_file(file_id);
+        db.bodies_in_file(file_id);
+        db.line_index(file_id);
+    }
+    Ok(db.query_stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_diagnostics::Level;
+
+    use super::*;
+
+    fn standard() -> InstructionRegistry {
+        ram_core::standard_instructions()
+    }
+
+    fn diagnostics() -> Vec<Diagnostic> {
+        vec![
+            Diagnostic::warning("Unused label".to_string(), "Remove it".to_string(), 0..5)
+                .with_code("W003"),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let key = CacheKey::new("LOAD 1\nHALT\n", &DiagnosticConfig::default(), &standard());
+
+        assert!(cache.get(key).is_none());
+        cache.put(key, &diagnostics()).unwrap();
+
+        let cached = cache.get(key).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].message, "Unused label");
+        assert_eq!(cached[0].code.as_deref(), Some("W003"));
+        assert_eq!(cached[0].labeled_spans[0].0, 0..5);
+    }
+
+    #[test]
+    fn test_key_covers_source_and_config() {
+        let config = DiagnosticConfig::default();
+        let key = CacheKey::new("LOAD 1\n", &config, &standard());
+
+        assert_eq!(key, CacheKey::new("LOAD 1\n", &config, &standard()));
+        assert_ne!(key, CacheKey::new("LOAD 2\n", &config, &standard()));
+        let allowed = config.with_level("W003", Level::Allow);
+        assert_ne!(key, CacheKey::new("LOAD 1\n", &allowed, &standard()));
+    }
+
+    #[test]
+    fn test_key_covers_instructions() {
+        let config = DiagnosticConfig::default();
+        let source = "RAND 1\nHALT\n";
+        let standard_key = CacheKey::new(source, &config, &standard());
+        let extended_key = CacheKey::new(source, &config, &ram_core::extended_instructions());
+        assert_ne!(standard_key, extended_key);
+        assert_eq!(
+            extended_key,
+            CacheKey::new(source, &config, &ram_core::extended_instructions())
+        );
+
+        // An entry written while validating with one set is not read with the other
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        cache.put(standard_key, &diagnostics()).unwrap();
+        assert!(cache.get(extended_key).is_none());
+    }
+
+    #[test]
+    fn test_invalid_entry_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let key = CacheKey::new("HALT\n", &DiagnosticConfig::default(), &standard());
+
+        std::fs::write(cache.entry_path(key), "not json").unwrap();
+        assert!(cache.get(key).is_none());
+    }
+
+    #[test]
+    fn test_stats_and_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+
+        for source in ["LOAD 1\n", "LOAD 2\n"] {
+            cache
+                .put(
+                    CacheKey::new(source, &DiagnosticConfig::default(), &standard()),
+                    &diagnostics(),
+                )
+                .unwrap();
+        }
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.size > 0);
+
+        assert_eq!(cache.clean().unwrap(), stats);
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+        assert!(!cache.dir().exists());
+    }
+
+    #[test]
+    fn test_clean_keeps_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        cache
+            .put(CacheKey::new("HALT\n", &DiagnosticConfig::default(), &standard()), &diagnostics())
+            .unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "keep me").unwrap();
+
+        assert_eq!(cache.clean().unwrap().entries, 1);
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_database_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ["a.ram", "b.ram"].map(|name| dir.path().join(name));
+        for path in &paths {
+            std::fs::write(path, "start: LOAD 1\nJUMP start\n").unwrap();
+        }
+
+        let config = DiagnosticConfig::new().with_lru_capacity("parse", 1);
+        let lru = LruConfig::new().with_overrides([("parse", 1)]).unwrap();
+        assert_eq!(lru_config(&config).unwrap().capacity(base_db::Query::Parse), 1);
+        let stats = database_stats(&paths, lru).unwrap();
+        let parse = stats.iter().find(|stats| stats.query == base_db::Query::Parse).unwrap();
+        assert_eq!(parse.memos, 1);
+        assert_eq!(parse.evictions, 1);
+        assert!(parse.hits > 0);
+
+        let config = DiagnosticConfig::new().with_lru_capacity("typecheck", 1);
+        assert!(lru_config(&config).is_err());
+    }
+}
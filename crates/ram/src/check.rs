@@ -0,0 +1,198 @@
+//! Module for checking many RAM programs at once
+//!
+//! The programs do not depend on each other, so they are analyzed in parallel
+//! on a pool of threads, all against the same registry of instructions. The
+//! diagnostics are printed once every program is checked, in the order of
+//! the files, so the output does not depend on the number of threads.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use miette::{IntoDiagnostic, Result, WrapErr};
+use ram_core::InstructionRegistry;
+use ram_diagnostics::{Diagnostic, DiagnosticKind};
+use rayon::prelude::*;
+use tracing::debug;
+use walkdir::WalkDir;
+
+use crate::cache::{Cache, CacheKey};
+use crate::language;
+
+/// How to check the programs
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// Hold the programs to the classic RAM model
+    pub strict_ram: bool,
+    /// Run only these analysis passes and the ones they depend on
+    pub passes: Vec<String>,
+    /// The number of threads to check with, or 0 for one per CPU
+    pub jobs: usize,
+}
+
+/// The result of checking one program
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    /// The file of the program
+    pub path: PathBuf,
+    /// The text of the program
+    pub source: String,
+    /// The diagnostics of the program
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl FileReport {
+    /// Check if any of the diagnostics is an error
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.kind == DiagnosticKind::Error)
+    }
+}
+
+/// Find the RAM programs to check
+///
+/// Files are checked as given, and directories are searched for `.ram` files,
+/// including their subdirectories. The programs are returned sorted, each
+/// once.
+pub fn find_programs(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut programs = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            programs.push(path.clone());
+            continue;
+        }
+        for entry in WalkDir::new(path) {
+            let path = entry.into_diagnostic()?.into_path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "ram") {
+                programs.push(path);
+            }
+        }
+    }
+    programs.sort();
+    programs.dedup();
+    Ok(programs)
+}
+
+/// Check programs in parallel
+///
+/// Returns the reports in the order of `paths`, or the first error reading a
+/// program or its configuration.
+pub fn check_programs(
+    paths: &[PathBuf],
+    options: &CheckOptions,
+    cache: Option<&Cache>,
+) -> Result<Vec<FileReport>> {
+    let instructions = Arc::new(ram_core::standard_instructions());
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()
+        .into_diagnostic()
+        .wrap_err("Failed to start the threads to check with")?;
+    debug!("Checking {} programs on {} threads", paths.len(), pool.current_num_threads());
+
+    pool.install(|| {
+        paths.par_iter().map(|path| check_program(path, options, &instructions, cache)).collect()
+    })
+}
+
+/// Check the programs under `paths` and print their diagnostics
+///
+/// Returns whether any of the programs has errors.
+pub fn report_check(
+    paths: &[PathBuf],
+    options: &CheckOptions,
+    cache: Option<&Cache>,
+) -> Result<bool> {
+    let programs = find_programs(paths)?;
+    let reports = check_programs(&programs, options, cache)?;
+    for report in &reports {
+        let file_name = report.path.display().to_string();
+        for error in
+            language::report_diagnostics(&file_name, &report.source, report.diagnostics.clone())
+        {
+            eprintln!("{:?}", error);
+        }
+    }
+
+    let failed = reports.iter().filter(|report| report.has_errors()).count();
+    eprintln!("Checked {} programs, {} with errors", reports.len(), failed);
+    Ok(failed > 0)
+}
+
+/// Check one program, reusing its cached diagnostics if it did not change
+fn check_program(
+    path: &Path,
+    options: &CheckOptions,
+    instructions: &Arc<InstructionRegistry>,
+    cache: Option<&Cache>,
+) -> Result<FileReport> {
+    let source = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read file: {}", path.display()))?;
+    let mut config = language::diagnostic_config_for(path, options.strict_ram)?;
+    if !options.passes.is_empty() {
+        config = config.with_enabled_passes(options.passes.clone());
+    }
+
+    let key = CacheKey::new(&source, &config, instructions);
+    let diagnostics = match cache.and_then(|cache| cache.get(key)) {
+        Some(diagnostics) => {
+            debug!("Using cached diagnostics for {}", path.display());
+            diagnostics
+        }
+        None => {
+            let (_program, _body, _pipeline, _context, diagnostics) =
+                language::analyze_program_with_instructions(
+                    &source,
+                    &config,
+                    Arc::clone(instructions),
+                );
+            if let Some(cache) = cache
+                && let Err(err) = cache.put(key, &diagnostics)
+            {
+                debug!("Failed to cache diagnostics for {}: {}", path.display(), err);
+            }
+            diagnostics
+        }
+    };
+
+    Ok(FileReport { path: path.to_path_buf(), source, diagnostics })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_programs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("week1")).unwrap();
+        std::fs::write(dir.path().join("week1/b.ram"), "HALT\n").unwrap();
+        std::fs::write(dir.path().join("a.ram"), "HALT\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let a = dir.path().join("a.ram");
+        let programs = find_programs(&[dir.path().to_path_buf(), a.clone()]).unwrap();
+        assert_eq!(programs, [a, dir.path().join("week1/b.ram")]);
+    }
+
+    #[test]
+    fn test_check_programs_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..8)
+            .map(|index| {
+                let path = dir.path().join(format!("{index}.ram"));
+                let source = if index % 2 == 0 { "HALT\n" } else { "JUMP missing\nHALT\n" };
+                std::fs::write(&path, source).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        for jobs in [1, 4] {
+            let options = CheckOptions { jobs, ..Default::default() };
+            let reports = check_programs(&paths, &options, None).unwrap();
+            let checked = reports.iter().map(|report| report.path.clone()).collect::<Vec<_>>();
+            assert_eq!(checked, paths);
+            let failed = reports.iter().map(FileReport::has_errors).collect::<Vec<_>>();
+            assert_eq!(failed, [false, true, false, true, false, true, false, true]);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Recording of tracing spans in the Chrome trace-event format
+//!
+//! The layer records when every span is entered and exited, and writes the
+//! events as a JSON trace that flame-graph viewers such as Perfetto or
+//! `chrome://tracing` can open. It is disabled until a trace file is set.
+
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use serde_json::{Map, Value, json};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::Interest;
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The file traces are written to if none is given
+pub const DEFAULT_TRACE_FILE: &str = "trace.json";
+
+/// Records the spans of the program while a trace file is set
+#[derive(Clone)]
+pub struct ChromeTraceLayer {
+    path: Arc<RwLock<Option<PathBuf>>>,
+    events: Arc<Mutex<Vec<Value>>>,
+    start: Instant,
+}
+
+impl ChromeTraceLayer {
+    /// Create a disabled layer
+    pub fn new() -> Self {
+        Self {
+            path: Arc::new(RwLock::new(None)),
+            events: Arc::new(Mutex::new(Vec::new())),
+            start: Instant::now(),
+        }
+    }
+
+    /// Start recording spans, to be written to `path`, or stop if it is `None`
+    pub fn set_path(&self, path: Option<PathBuf>) {
+        *self.path.write().unwrap() = path;
+    }
+
+    /// Check if spans are being recorded
+    pub fn is_enabled(&self) -> bool {
+        self.path.read().unwrap().is_some()
+    }
+
+    /// Write the recorded events to the trace file, if one is set
+    pub fn finish(&self) -> std::io::Result<()> {
+        let Some(path) = self.path.read().unwrap().clone() else {
+            return Ok(());
+        };
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        write_trace(&path, events)
+    }
+
+    /// Record an event of a span, `B` when it is entered and `E` when it is exited
+    fn record<S>(&self, id: &Id, phase: &str, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut event = json!({
+            "name": span.name(),
+            "cat": span.metadata().target(),
+            "ph": phase,
+            "ts": self.start.elapsed().as_secs_f64() * 1_000_000.0,
+            "pid": std::process::id(),
+            "tid": thread_id(),
+        });
+        if phase == "B"
+            && let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>()
+            && !fields.is_empty()
+        {
+            event["args"] = Value::Object(fields.clone());
+        }
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Default for ChromeTraceLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Map::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        self.record(id, "B", &ctx);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        self.record(id, "E", &ctx);
+    }
+}
+
+impl<S> Filter<S> for ChromeTraceLayer {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: &Context<'_, S>) -> bool {
+        metadata.is_span() && self.is_enabled()
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // Spans are checked every time, as recording can start after they are registered
+        if metadata.is_span() { Interest::sometimes() } else { Interest::never() }
+    }
+}
+
+/// The fields a span was created with
+struct SpanFields(Map<String, Value>);
+
+/// Collects the fields of a span as JSON values
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+/// A small number identifying the current thread in the trace
+fn thread_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<u64> = const { Cell::new(0) };
+    }
+    ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        }
+        id.get()
+    })
+}
+
+/// Write trace events to a file, as a JSON object with a `traceEvents` array
+fn write_trace(path: &Path, events: Vec<Value>) -> std::io::Result<()> {
+    let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+    std::fs::write(path, serde_json::to_vec(&trace)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info_span;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn test_records_spans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.json");
+        let layer = ChromeTraceLayer::new();
+        let subscriber =
+            tracing_subscriber::registry().with(layer.clone().with_filter(layer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            info_span!("ignored").in_scope(|| {});
+            layer.set_path(Some(path.clone()));
+            info_span!("pass", pass_name = "control_flow").in_scope(|| {
+                info_span!("batch", first_step = 0).in_scope(|| {});
+            });
+        });
+        layer.finish().unwrap();
+
+        let trace: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let phases = events
+            .iter()
+            .map(|event| {
+                format!("{} {}", event["ph"].as_str().unwrap(), event["name"].as_str().unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(phases, vec!["B pass", "B batch", "E batch", "E pass"]);
+        assert_eq!(events[0]["args"]["pass_name"], "control_flow");
+        assert_eq!(events[1]["args"]["first_step"], 0);
+    }
+}
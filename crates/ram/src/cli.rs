@@ -3,9 +3,19 @@ use std::path::PathBuf;
 use clap::builder::Styles;
 use clap::builder::styling::{AnsiColor, Effects, Style};
 use clap::{Args, Parser, Subcommand};
+use clap_complete::ArgValueCompleter;
+use clap_complete::aot::Shell;
+use hir_analysis::analyzers::control_flow::EdgeKind;
+use hir_analysis::{ExportTheme, RankDirection};
+use ram_core::ArithmeticMode;
+use ram_syntax::SyntaxKind;
+use ram_vm::Watchpoint;
+use serde::{Deserialize, Serialize};
 
-use crate::VERSION;
+use crate::ast::SourceRange;
 use crate::color::ColorChoice;
+use crate::self_update::Channel;
+use crate::{VERSION, completions};
 
 // Configures Clap v3-style help menu colors
 const STYLES: Styles = Styles::styled()
@@ -58,43 +68,576 @@ pub enum Command {
     },
 
     /// Run the Language Server Protocol (LSP) server.
+    ///
+    /// The server talks to the editor that started it over stdio, unless it
+    /// is given a port of the loopback interface to serve clients on.
     #[command(alias = "lsp")]
-    Server,
+    Server {
+        /// Serve clients connecting to this port over TCP.
+        #[arg(long, value_name = "PORT")]
+        tcp: Option<u16>,
+
+        /// Speak WebSockets on the TCP port, as browser-based editors do.
+        #[arg(long, action, requires = "tcp")]
+        websocket: bool,
+    },
+
+    /// Print the syntax tree of a RAM program.
+    ///
+    /// The tree has the comments, whitespace and error nodes of the program,
+    /// and can be narrowed down to the node that covers a range of the source
+    /// and to the nodes of some kinds.
+    Ast {
+        /// The RAM program file to parse.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        program: PathBuf,
+
+        /// How to print the tree.
+        #[arg(long, short = 'f', alias = "format", value_enum, default_value = "tree")]
+        output_format: AstFormat,
+
+        /// Only print the smallest node that covers this range, with lines
+        /// and columns counting from 1.
+        #[arg(long, value_name = "LINE:COL-LINE:COL")]
+        range: Option<SourceRange>,
+
+        /// Only print the nodes of these kinds, like `STMT,INSTRUCTION`.
+        #[arg(
+            long,
+            value_name = "KINDS",
+            value_delimiter = ',',
+            value_parser = crate::ast::parse_node_kind
+        )]
+        kinds: Vec<SyntaxKind>,
+    },
+
+    /// Print the HIR a RAM program is lowered to.
+    ///
+    /// Every instruction, label and operand expression is printed with its
+    /// id and the range of the source it was lowered from.
+    Hir {
+        /// The RAM program file to lower.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        program: PathBuf,
+
+        /// Print the `ram.hir` JSON document, as `validate --emit hir-json` does.
+        #[arg(long, action)]
+        json: bool,
+    },
+
+    /// Print the items of a RAM program: its modules, imports and labels.
+    #[command(name = "itemtree", alias = "item-tree")]
+    ItemTree {
+        /// The RAM program file to lower.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        program: PathBuf,
+
+        /// Print the `ram.item-tree` JSON document.
+        #[arg(long, action)]
+        json: bool,
+    },
 
     /// Validate a RAM file.
     Validate {
         /// The file to validate.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
         program: String,
 
-        /// Output the ast as JSON.
-        #[arg(long, short, action)]
+        /// Print the debug representation of the syntax tree, like `ram ast
+        /// --format debug`.
+        #[arg(long, short, action, hide = true)]
         ast: bool,
 
         #[arg(long, short, action)]
         reprint: bool,
 
+        /// Print the canonical form of the program, with renumbered labels and
+        /// canonical opcodes, and without comments.
+        #[arg(long, action)]
+        canonical: bool,
+
         #[arg(long, action)]
         show_pipeline: bool,
 
         #[arg(long, alias = "cfg", action)]
         show_cfg: bool,
 
+        /// Group the control flow graph by subroutine, with calls between them.
+        #[arg(long, action, requires = "show_cfg")]
+        interprocedural: bool,
+
+        /// Draw only the instructions reachable from the start of the program.
+        #[arg(long, action, requires = "show_cfg")]
+        reachable_only: bool,
+
+        /// Draw each basic block of the control flow graph as a single node.
+        #[arg(long, action, requires = "show_cfg")]
+        collapse_blocks: bool,
+
+        /// Leave out unconditional jumps, linking the edges into them to their targets.
+        #[arg(long, action, requires = "show_cfg")]
+        hide_trivia: bool,
+
+        /// Color the control flow edges of a kind, like `call=red`. The kinds are
+        /// `unconditional`, `true`, `false`, `call` and `return`.
+        #[arg(
+            long,
+            value_name = "KIND=COLOR",
+            value_parser = parse_edge_color,
+            requires = "show_cfg"
+        )]
+        edge_color: Vec<(EdgeKind, String)>,
+
+        /// The color theme of the graphs.
+        #[arg(long, value_enum)]
+        theme: Option<GraphTheme>,
+
+        /// The direction the graphs are laid out in.
+        #[arg(long, value_enum, default_value = "top-down")]
+        rank_dir: GraphDirection,
+
+        /// Show the data flow graph, with the memory address each edge carries.
+        #[arg(long, alias = "dfg", action)]
+        show_dfg: bool,
+
+        /// Print the value of the accumulator after each instruction, when it is
+        /// known without running the program.
+        #[arg(long, alias = "constants", action)]
+        show_constants: bool,
+
+        /// Show the dominator tree of the control flow graph.
+        #[arg(long, alias = "dominators", action)]
+        show_dominators: bool,
+
+        /// Print the memory held by the lowered program and its analysis.
+        #[arg(long, action)]
+        show_memory: bool,
+
+        /// Hold the program to the classic RAM model, reporting violations as errors.
         #[arg(long, action)]
-        show_hir: bool,
+        strict_ram: bool,
+
+        /// Run only the analysis passes with these IDs, separated by commas, and
+        /// the passes they depend on, instead of those enabled in ram.toml.
+        #[arg(long, value_delimiter = ',', value_name = "PASSES")]
+        passes: Vec<String>,
+
+        /// Print analysis results as JSON documents with a stable schema, one
+        /// for each format, separated by commas.
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "FORMAT")]
+        emit: Vec<EmitFormat>,
+    },
+
+    /// Check many RAM programs in parallel, printing their diagnostics in file order.
+    Check {
+        /// The files to check, and directories to search for RAM programs.
+        #[arg(required = true, add = ArgValueCompleter::new(completions::program_files()))]
+        paths: Vec<PathBuf>,
+
+        /// Hold the programs to the classic RAM model, reporting violations as errors.
+        #[arg(long, action)]
+        strict_ram: bool,
+
+        /// Run only the analysis passes with these IDs, separated by commas, and
+        /// the passes they depend on, instead of those enabled in ram.toml.
+        #[arg(long, value_delimiter = ',', value_name = "PASSES")]
+        passes: Vec<String>,
+
+        /// The number of programs to check at once, one per CPU by default.
+        #[arg(long, short, value_name = "N", default_value_t = 0)]
+        jobs: usize,
     },
 
     /// Run a RAM program in the virtual machine.
     Run {
-        /// The RAM program file to execute.
+        /// The RAM program or bytecode artifact file to execute.
+        #[arg(
+            required_unless_present_any = ["resume", "replay"],
+            add = ArgValueCompleter::new(completions::program_files())
+        )]
+        program: Option<String>,
+
+        /// Input values to provide to the program, separated by spaces or commas,
+        /// or a file containing them.
+        #[arg(long, short, value_name = "VALUES")]
+        input: Option<String>,
+
+        /// Initial register values, as `address=value` pairs separated by commas.
+        #[arg(long, short, value_name = "ASSIGNMENTS", conflicts_with = "resume")]
+        memory: Option<String>,
+
+        /// Seed the random numbers of `RAND`, making the run reproducible.
+        #[arg(long, value_name = "SEED")]
+        seed: Option<u64>,
+
+        /// The instruction set to run with, such as `standard` or `extended`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "standard",
+            add = ArgValueCompleter::new(completions::instruction_sets)
+        )]
+        instruction_set: String,
+
+        /// Pause the run after executing this many instructions.
+        #[arg(long, value_name = "STEPS")]
+        max_steps: Option<usize>,
+
+        /// Save the state of the machine to this file when the run stops.
+        #[arg(long, value_name = "FILE")]
+        save_state: Option<PathBuf>,
+
+        /// Resume the run saved in this state file, with its program, input and
+        /// arithmetic.
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["program", "input", "seed", "arithmetic"]
+        )]
+        resume: Option<PathBuf>,
+
+        /// Hold the program to the classic RAM model, reporting violations as errors.
+        #[arg(long, action)]
+        strict_ram: bool,
+
+        /// Write an HTML report to this file, with every line of the program
+        /// colored by how many times it was executed.
+        #[arg(long, value_name = "FILE")]
+        heatmap: Option<PathBuf>,
+
+        /// How to print the result of the run.
+        #[arg(long, short = 'f', value_enum, default_value = "text")]
+        output_format: RunFormat,
+
+        /// Run common pairs of instructions, such as `LOAD` then `ADD`, as one
+        /// superinstruction. The result of the run does not change.
+        #[arg(long, action)]
+        fuse: bool,
+
+        /// What arithmetic instructions do when their result does not fit in
+        /// 64 bits.
+        #[arg(long, value_enum, default_value = "checked")]
+        arithmetic: Arithmetic,
+
+        /// Record the input, the seed and everything else the run depends on
+        /// to this file, to replay the run exactly with `--replay`.
+        #[arg(long, value_name = "FILE", conflicts_with = "resume")]
+        record: Option<PathBuf>,
+
+        /// Replay the run recorded in this file, failing if it ends differently.
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["program", "input", "memory", "seed", "resume", "record"]
+        )]
+        replay: Option<PathBuf>,
+    },
+
+    /// Run a RAM program and explain what every executed instruction does.
+    ExplainRun {
+        /// The RAM program or bytecode artifact file to explain.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
         program: String,
 
-        /// Input values to provide to the program (space-separated).
-        #[arg(long, short, value_delimiter = ' ')]
-        input: Option<Vec<i64>>,
+        /// Input values to provide to the program, separated by spaces or commas,
+        /// or a file containing them.
+        #[arg(long, short, value_name = "VALUES")]
+        input: Option<String>,
 
-        /// Show memory contents after execution.
-        #[arg(long, short, action)]
-        memory: bool,
+        /// The instruction set to run with, such as `standard` or `extended`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "standard",
+            add = ArgValueCompleter::new(completions::instruction_sets)
+        )]
+        instruction_set: String,
+
+        /// Stop explaining after this many instructions.
+        #[arg(long, value_name = "STEPS", default_value_t = 1000)]
+        max_steps: usize,
+
+        /// Print the explanation as Markdown, for teaching materials.
+        #[arg(long, action)]
+        markdown: bool,
+    },
+
+    /// Run a RAM program in the debugger, pausing when watched places are accessed.
+    Debug {
+        /// The RAM program or bytecode artifact file to debug.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        program: String,
+
+        /// Input values to provide to the program, separated by spaces or commas,
+        /// or a file containing them.
+        #[arg(long, short, value_name = "VALUES")]
+        input: Option<String>,
+
+        /// Initial register values, as `address=value` pairs separated by commas.
+        #[arg(long, short, value_name = "ASSIGNMENTS")]
+        memory: Option<String>,
+
+        /// The instruction set to run with, such as `standard` or `extended`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "standard",
+            add = ArgValueCompleter::new(completions::instruction_sets)
+        )]
+        instruction_set: String,
+
+        /// Pause when this place is read or written, such as `5`, `M[12]` or `ACC`.
+        /// Add `:r` or `:w` to only pause on reads or writes.
+        #[arg(long, short, value_name = "PLACE")]
+        watch: Vec<Watchpoint>,
+
+        /// Pause a run that goes on for more than this many seconds since it
+        /// was resumed, and stop it if there are no more commands.
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<f64>,
+
+        /// The source map of the artifact, to show the lines of the original
+        /// program. By default, the one `ram build` wrote next to it is used.
+        #[arg(long, value_name = "FILE")]
+        source_map: Option<PathBuf>,
+    },
+
+    /// Grade a directory of submissions against the test cases of a spec.
+    Grade {
+        /// The TOML file with the test cases, limits and banned instructions.
+        #[arg(long, value_name = "FILE")]
+        spec: PathBuf,
+
+        /// The directory with the RAM programs or bytecode artifacts to grade.
+        submissions: PathBuf,
+
+        /// The instruction set to run with, such as `standard` or `extended`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "standard",
+            add = ArgValueCompleter::new(completions::instruction_sets)
+        )]
+        instruction_set: String,
+
+        /// How to print the grading reports.
+        #[arg(long, short = 'f', value_enum, default_value = "csv")]
+        output_format: GradeFormat,
+
+        /// Stop grading a submission after this many seconds.
+        ///
+        /// The case running at that point, and the ones after it, are reported
+        /// as cancelled, with the steps the running case took so far.
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+    },
+
+    /// Check that two RAM programs write the same output for every input of a domain.
+    Equiv {
+        /// The first RAM program or bytecode artifact file.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        left: PathBuf,
+
+        /// The second RAM program or bytecode artifact file.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        right: PathBuf,
+
+        /// One range for each input value, such as `0..=9, 1..4, 7`.
+        #[arg(long, value_name = "RANGES")]
+        inputs: String,
+
+        /// Check this many random inputs of the domain instead of all of them.
+        #[arg(long, value_name = "COUNT")]
+        random: Option<usize>,
+
+        /// Seed the random inputs and the random numbers of `RAND`.
+        #[arg(long, value_name = "SEED")]
+        seed: Option<u64>,
+
+        /// The instruction set to run with, such as `standard` or `extended`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "standard",
+            add = ArgValueCompleter::new(completions::instruction_sets)
+        )]
+        instruction_set: String,
+
+        /// Stop each program after executing this many instructions on an input.
+        #[arg(long, value_name = "STEPS", default_value_t = ram_vm::grader::DEFAULT_MAX_STEPS)]
+        max_steps: usize,
+    },
+
+    /// Show how the instructions of two RAM programs differ.
+    ///
+    /// Comments, layout and the spelling of opcodes are ignored, and renamed
+    /// labels are reported as renames instead of changed lines.
+    Diff {
+        /// The original RAM program.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        left: PathBuf,
+
+        /// The changed RAM program.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        right: PathBuf,
+
+        /// How to print the changes.
+        #[arg(long, short = 'f', alias = "format", value_enum, default_value = "text")]
+        output_format: DiffFormat,
+    },
+
+    /// Run two RAM programs on the same input and show where their traces differ.
+    DiffTrace {
+        /// The first RAM program or bytecode artifact file.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        left: PathBuf,
+
+        /// The second RAM program or bytecode artifact file.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        right: PathBuf,
+
+        /// Input values to provide to both programs, separated by spaces or
+        /// commas, or a file containing them.
+        #[arg(long, short, value_name = "VALUES")]
+        input: Option<String>,
+
+        /// The instruction set to run with, such as `standard` or `extended`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "standard",
+            add = ArgValueCompleter::new(completions::instruction_sets)
+        )]
+        instruction_set: String,
+
+        /// Stop each program after executing this many instructions.
+        #[arg(long, value_name = "STEPS", default_value_t = 1000)]
+        max_steps: usize,
+    },
+
+    /// Find pairs of similar programs among a directory of submissions.
+    Similarity {
+        /// The directory with the RAM programs to compare.
+        submissions: PathBuf,
+
+        /// The number of consecutive instructions compared at a time.
+        #[arg(long, value_name = "N", default_value_t = crate::similarity::DEFAULT_NGRAM)]
+        ngram: usize,
+
+        /// Only report pairs at least this similar, from 0 to 1.
+        #[arg(long, value_name = "SCORE", default_value_t = 0.0)]
+        threshold: f64,
+
+        /// How to print the similar pairs.
+        #[arg(long, short = 'f', value_enum, default_value = "text")]
+        output_format: SimilarityFormat,
+    },
+
+    /// Report usage statistics of the RAM programs under a directory.
+    ///
+    /// The programs are only analyzed locally; nothing is sent anywhere.
+    Stats {
+        /// The directory to search for RAM programs, including subdirectories.
+        dir: PathBuf,
+
+        /// How to print the statistics.
+        #[arg(long, short = 'f', value_enum, default_value = "table")]
+        output_format: StatsFormat,
+    },
+
+    /// Compile a RAM program to a bytecode artifact.
+    Build {
+        /// The RAM program file to compile.
+        #[arg(add = ArgValueCompleter::new(completions::program_files()))]
+        program: String,
+
+        /// The artifact file to write, next to the program by default.
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Hold the program to the classic RAM model, reporting violations as errors.
+        #[arg(long, action)]
+        strict_ram: bool,
+    },
+
+    /// Check the integrity and compatibility of a bytecode artifact.
+    VerifyArtifact {
+        /// The artifact file to verify.
+        artifact: PathBuf,
+
+        /// The RAM program the artifact must have been built from.
+        #[arg(long, value_name = "FILE")]
+        source: Option<PathBuf>,
+    },
+
+    /// Print the completion script of a shell.
+    ///
+    /// Source the script in the configuration of the shell, for example with
+    /// `ram completions bash > ~/.local/share/bash-completion/completions/ram`.
+    /// Shells that support it can complete programs and instruction sets as
+    /// you type instead, with `source <(COMPLETE=bash ram)`.
+    Completions {
+        /// The shell to print the completion script of.
+        shell: Shell,
+    },
+
+    /// Write the manual pages of `ram` and its commands to a directory.
+    Manpages {
+        /// The directory to write the pages to, created if it does not exist.
+        dir: PathBuf,
+    },
+
+    /// Explain a diagnostic code.
+    Explain {
+        /// The diagnostic code to explain, e.g. `E001`.
+        code: String,
+    },
+
+    /// Manage the analysis cache.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+
+    /// Manage the `ram` binary.
+    #[command(name = "self")]
+    Self_ {
+        #[command(subcommand)]
+        command: SelfCommand,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum CacheCommand {
+    /// Remove all cache entries.
+    Clean,
+    /// Show the number and size of the cache entries.
+    ///
+    /// Given programs, also analyze them in one database and show how many
+    /// results each of its queries keeps and how often they were reused. The
+    /// capacities are read from the `[database.lru]` section of the manifest
+    /// and the `RAM_LRU_<QUERY>` environment variables.
+    Stats {
+        /// The programs, or directories of programs, to load into the database.
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Clone, Copy)]
+pub enum SelfCommand {
+    /// Update `ram` to the newest release.
+    ///
+    /// The release archive is checked against its published SHA-256 checksum
+    /// before it replaces the running binary.
+    Update {
+        /// The release channel to update from.
+        #[arg(long, value_enum, default_value = "stable")]
+        channel: Channel,
+
+        /// Show the version that would be installed and its release notes,
+        /// without installing it.
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
@@ -131,6 +674,27 @@ pub struct GlobalArgs {
     #[arg(global = true, long, value_name = "FILE")]
     pub mirror: Option<PathBuf>,
 
+    /// Record the spans of the VM and the analysis passes as a trace.
+    ///
+    /// `json` writes the Chrome trace-event format, which flame-graph viewers
+    /// such as Perfetto can open.
+    #[arg(global = true, long, value_enum, value_name = "FORMAT")]
+    pub trace_output: Option<TraceFormat>,
+
+    /// The file to write the trace to, `trace.json` by default.
+    #[arg(global = true, long, value_name = "FILE")]
+    pub trace_file: Option<PathBuf>,
+
+    /// Do not read or write cached analysis results.
+    #[arg(global = true, long)]
+    pub no_cache: bool,
+
+    /// Path to the cache directory.
+    ///
+    /// Defaults to `.ram_cache` in the current working directory.
+    #[arg(global = true, long, env = "RAM_CACHE_DIR", value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
     /// Control the use of color in output.
     ///
     /// By default, uv will automatically detect support for colors when writing to a terminal.
@@ -156,3 +720,159 @@ pub enum VersionFormat {
     /// Display the version as a TOML.
     Toml,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EmitFormat {
+    /// The lowered program: its instructions, labels and operand expressions.
+    HirJson,
+    /// The control flow graph between the instructions.
+    CfgJson,
+    /// The data flow graph between the instructions.
+    DfgJson,
+    /// The value of the accumulator after each instruction, by line.
+    ConstantsJson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphTheme {
+    /// Dark lines and text on a light background.
+    Light,
+    /// Light lines and text on a dark background.
+    Dark,
+}
+
+impl From<GraphTheme> for ExportTheme {
+    fn from(theme: GraphTheme) -> Self {
+        match theme {
+            GraphTheme::Light => ExportTheme::Light,
+            GraphTheme::Dark => ExportTheme::Dark,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphDirection {
+    /// Lay the graph out from top to bottom.
+    TopDown,
+    /// Lay the graph out from bottom to top.
+    BottomUp,
+    /// Lay the graph out from left to right.
+    LeftRight,
+    /// Lay the graph out from right to left.
+    RightLeft,
+}
+
+impl From<GraphDirection> for RankDirection {
+    fn from(direction: GraphDirection) -> Self {
+        match direction {
+            GraphDirection::TopDown => RankDirection::TopDown,
+            GraphDirection::BottomUp => RankDirection::BottomUp,
+            GraphDirection::LeftRight => RankDirection::LeftRight,
+            GraphDirection::RightLeft => RankDirection::RightLeft,
+        }
+    }
+}
+
+/// Parse the color of a kind of control flow edges, like `call=red`
+fn parse_edge_color(value: &str) -> Result<(EdgeKind, String), String> {
+    let (kind, color) =
+        value.split_once('=').ok_or_else(|| format!("expected KIND=COLOR, got `{value}`"))?;
+    let kind = match kind {
+        "unconditional" => EdgeKind::Unconditional,
+        "true" => EdgeKind::ConditionalTrue,
+        "false" => EdgeKind::ConditionalFalse,
+        "call" => EdgeKind::Call,
+        "return" => EdgeKind::Return,
+        _ => return Err(format!("unknown edge kind `{kind}`")),
+    };
+    Ok((kind, color.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RunFormat {
+    /// Display the result of the run as plain text.
+    #[default]
+    Text,
+    /// Display the result of the run as JSON.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Arithmetic {
+    /// Stop the run with an error pointing at the instruction that overflows.
+    #[default]
+    Checked,
+    /// Wrap around at the bounds, like two's complement hardware does.
+    Wrapping,
+    /// Clamp the result to the nearest bound.
+    Saturating,
+}
+
+impl From<Arithmetic> for ArithmeticMode {
+    fn from(arithmetic: Arithmetic) -> Self {
+        match arithmetic {
+            Arithmetic::Checked => ArithmeticMode::Checked,
+            Arithmetic::Wrapping => ArithmeticMode::Wrapping,
+            Arithmetic::Saturating => ArithmeticMode::Saturating,
+        }
+    }
+}
+
+impl From<ArithmeticMode> for Arithmetic {
+    fn from(mode: ArithmeticMode) -> Self {
+        match mode {
+            ArithmeticMode::Checked => Arithmetic::Checked,
+            ArithmeticMode::Wrapping => Arithmetic::Wrapping,
+            ArithmeticMode::Saturating => Arithmetic::Saturating,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GradeFormat {
+    /// Display one CSV row for each case of each submission.
+    Csv,
+    /// Display the grading reports as JSON.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SimilarityFormat {
+    /// Display one line for each pair, with its similarity score.
+    Text,
+    /// Display the pairs as JSON.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AstFormat {
+    /// Display the tree indented, one node or token per line.
+    Tree,
+    /// Display the debug representation of the tree.
+    Debug,
+    /// Display the tree as JSON.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// Display one line for each change.
+    Text,
+    /// Display the changes as JSON.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsFormat {
+    /// Display the statistics as tables.
+    Table,
+    /// Display the statistics as JSON.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TraceFormat {
+    /// Write the Chrome trace-event format as JSON.
+    Json,
+}
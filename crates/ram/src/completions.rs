@@ -0,0 +1,103 @@
+//! Module for shell completions of the command line
+//!
+//! `ram completions <shell>` prints a static completion script built from the
+//! clap definitions of the commands. Shells that support it can instead ask
+//! the binary for completions as the user types, by sourcing the script
+//! printed with `COMPLETE=<shell> ram`; those complete program arguments with
+//! `.ram` and `.rbc` files only, and instruction sets with the registered ones.
+
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::Path;
+
+use clap::CommandFactory;
+use clap_complete::aot::{Shell, generate};
+use clap_complete::{CompleteEnv, CompletionCandidate, PathCompleter};
+use ram_core::INSTRUCTION_SET_REGISTRY;
+use ram_vm::bytecode;
+
+use crate::cli::Cli;
+
+/// The name of the binary the completions are for
+const BIN_NAME: &str = "ram";
+
+/// Answer a completion request of the shell and exit, if this run is one
+///
+/// Must run before anything is printed, as the completions go to stdout.
+pub fn complete_from_env() {
+    CompleteEnv::with_factory(Cli::command).bin(BIN_NAME).complete();
+}
+
+/// Write the static completion script of a shell
+pub fn write_completions(shell: Shell, out: &mut impl Write) {
+    generate(shell, &mut Cli::command(), BIN_NAME, out);
+}
+
+/// Complete the path of a RAM program or bytecode artifact
+///
+/// Directories are completed too, to reach the programs inside them.
+pub fn program_files() -> PathCompleter {
+    PathCompleter::any().filter(|path| path.is_dir() || is_program_file(path))
+}
+
+/// Complete the name of a registered instruction set
+///
+/// Names are looked up ignoring case, so they are completed in lowercase.
+pub fn instruction_sets(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy().to_lowercase();
+    let mut sets = INSTRUCTION_SET_REGISTRY
+        .sets()
+        .map(|set| (set.name.to_lowercase(), set.description.clone()))
+        .filter(|(name, _)| name.starts_with(&current))
+        .collect::<Vec<_>>();
+    sets.sort();
+    sets.into_iter()
+        .map(|(name, description)| CompletionCandidate::new(name).help(Some(description.into())))
+        .collect()
+}
+
+/// Check whether a path is a RAM program or a bytecode artifact
+fn is_program_file(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().is_some_and(|ext| ext == "ram" || ext == bytecode::FILE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_set_candidates() {
+        let names = instruction_sets(OsStr::new(""))
+            .iter()
+            .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["extended", "standard", "subroutines"]);
+
+        let candidates = instruction_sets(OsStr::new("su"));
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].get_value(), "subroutines");
+        assert!(candidates[0].get_help().is_some());
+    }
+
+    #[test]
+    fn test_program_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["add.ram", "add.rbc", "notes.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        assert!(is_program_file(&dir.path().join("add.ram")));
+        assert!(is_program_file(&dir.path().join("add.rbc")));
+        assert!(!is_program_file(&dir.path().join("notes.txt")));
+        assert!(!is_program_file(dir.path()));
+    }
+
+    #[test]
+    fn test_static_completions() {
+        let mut script = Vec::new();
+        write_completions(Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("_ram()"));
+        assert!(script.contains("explain-run"));
+    }
+}
@@ -0,0 +1,395 @@
+//! Module for debugging program runs with watchpoints
+//!
+//! The program runs until an instruction reads or writes a watched place,
+//! such as register 5, and the debugger pauses to take commands: printing
+//! places, stepping, and adding or removing watchpoints. Without more
+//! commands, the run continues to the end without pausing.
+//!
+//! With a timeout, a run that takes longer pauses as if it hit a watchpoint,
+//! so a program stuck in a loop can still be inspected, and stops when no
+//! commands are left.
+//!
+//! Artifacts are debugged with their source map, if `ram build` wrote one, so
+//! pauses show the line of the original program.
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use miette::{GraphicalReportHandler, GraphicalTheme, IntoDiagnostic, Result, miette};
+use ram_core::ArithmeticMode;
+use ram_core::error::VmError;
+use ram_vm::{EvalError, Location, VecInput, VecOutput, VirtualMachine, WatchHit, Watchpoint};
+
+use crate::cache::Cache;
+use crate::{artifact, run};
+
+/// The commands of the debugger, shown by `help`
+const HELP: &str = "\
+Commands:
+  c, continue      Run until a watched place is accessed
+  s, step          Execute the next instruction
+  p, print PLACE   Print the value of a place, such as 5, R5, M[12] or ACC
+  w, watch PLACE   Pause when a place is accessed, or only read or written with :r or :w
+  u, unwatch PLACE Stop watching a place
+  e, eval EXPR     Evaluate an expression, such as R3 + M[R1] or ACC == 0
+  l, list          List the watchpoints
+  q, quit          Stop the run
+  h, help          Show this help
+";
+
+/// Options of a debugged run
+#[derive(Debug, Clone)]
+pub struct DebugOptions {
+    /// The name of the instruction set the program runs with
+    pub instruction_set: String,
+    /// The places watched from the start of the run
+    pub watchpoints: Vec<Watchpoint>,
+    /// How long the run may go on each time it is resumed, if limited
+    pub timeout: Option<Duration>,
+    /// The source map of the program, instead of the one next to the artifact
+    pub source_map: Option<PathBuf>,
+}
+
+/// How the debugger resumes a paused run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    /// Run until a watched place is accessed
+    Continue,
+    /// Execute a single instruction
+    Step,
+    /// Run to the end, as there are no more commands
+    Detach,
+    /// Stop the run
+    Quit,
+}
+
+/// Run a RAM program in the debugger, reading commands from standard input
+pub fn debug_run(
+    program_path: &Path,
+    input: Vec<i64>,
+    memory: Vec<(i64, i64)>,
+    options: &DebugOptions,
+    cache: Option<&Cache>,
+) -> Result<()> {
+    let db = run::instruction_set_db(&options.instruction_set)?;
+    let program = run::load_program(program_path, &db, false, ArithmeticMode::default(), cache)?;
+    let mut builder = VirtualMachine::builder(program, VecInput::new(input), VecOutput::new(), db)
+        .with_memory_values(memory);
+    for &watchpoint in &options.watchpoints {
+        builder = builder.with_watchpoint(watchpoint);
+    }
+    let source_map = match &options.source_map {
+        Some(path) => Some(artifact::load_source_map(path)?),
+        None => artifact::find_source_map(program_path)?,
+    };
+    if let Some(source_map) = source_map {
+        builder = builder.with_source_map(Arc::new(source_map));
+    }
+    let mut vm = builder.build();
+
+    let stdin = std::io::stdin();
+    debug(&mut vm, options.timeout, stdin.lock(), std::io::stdout())
+}
+
+/// Debug a virtual machine, reading commands from `commands` and printing to `out`
+///
+/// The run pauses whenever an instruction accesses a watched place, or runs
+/// for longer than `timeout` since it was resumed. Once `commands` runs out,
+/// the run continues to the end without pausing, or until it times out.
+pub fn debug(
+    vm: &mut VirtualMachine<VecInput, VecOutput>,
+    timeout: Option<Duration>,
+    mut commands: impl BufRead,
+    mut out: impl Write,
+) -> Result<()> {
+    // The run is cancelled once the deadline of the current resume passes
+    let deadline = Arc::new(Mutex::new(None::<Instant>));
+    if timeout.is_some() {
+        let deadline = Arc::clone(&deadline);
+        vm.set_cancellation(Arc::new(move || {
+            deadline.lock().unwrap().is_some_and(|deadline| Instant::now() >= deadline)
+        }));
+    }
+
+    let mut resume = Resume::Continue;
+    while !vm.is_finished() && resume != Resume::Quit {
+        *deadline.lock().unwrap() = timeout.map(|timeout| Instant::now() + timeout);
+        let pc = vm.pc();
+        let result = match resume {
+            Resume::Continue => vm.run_until_watch(usize::MAX),
+            Resume::Step => vm.step().map(|()| vm.take_watch_hits()),
+            Resume::Detach => {
+                // Nothing is paused at anymore, so nothing is watched
+                for watchpoint in vm.watchpoints().list() {
+                    vm.unwatch(watchpoint.location);
+                }
+                vm.run().map(|()| Vec::new())
+            }
+            Resume::Quit => unreachable!("the run stops when quitting"),
+        };
+        let hits = match result {
+            Err(VmError::Cancelled(_)) if resume == Resume::Detach => break,
+            Err(VmError::Cancelled(_)) => {
+                writeln!(out, "Timed out. {}", describe_pause(vm, vm.pc(), &[]))
+                    .into_diagnostic()?;
+                resume = read_commands(vm, &mut commands, &mut out)?;
+                continue;
+            }
+            result => result.map_err(|e| miette!("Failed to run program: {}", e))?,
+        };
+
+        // A run that continued without hits has finished
+        if resume == Resume::Detach || (resume == Resume::Continue && hits.is_empty()) {
+            break;
+        }
+        let pc = hits.first().map_or(pc, |hit| hit.pc);
+        writeln!(out, "{}", describe_pause(vm, pc, &hits)).into_diagnostic()?;
+        if vm.is_finished() {
+            break;
+        }
+        resume = read_commands(vm, &mut commands, &mut out)?;
+    }
+
+    let output = &vm.output;
+    writeln!(out, "Output:      {:?}", output.values).into_diagnostic()?;
+    if !output.text.is_empty() {
+        writeln!(out, "Text:        {}", output.text).into_diagnostic()?;
+    }
+    writeln!(out, "Accumulator: {}", vm.accumulator()).into_diagnostic()?;
+    writeln!(out, "Steps:       {}", vm.steps()).into_diagnostic()?;
+    if !vm.is_finished() {
+        writeln!(out, "Stopped after {} steps", vm.steps()).into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// Describe where the run paused, such as
+/// `Paused at step 3, instruction 2 (STORE 5): R5 written with value 7`
+fn describe_pause(
+    vm: &VirtualMachine<VecInput, VecOutput>,
+    pc: usize,
+    hits: &[WatchHit],
+) -> String {
+    let mut text = format!("Paused at step {}, instruction {}", vm.steps(), pc);
+    if let Some(instruction) = vm.program().get_instruction(pc) {
+        text.push_str(&format!(" ({})", instruction));
+    }
+    if let Some(location) = vm.source_location(pc) {
+        text.push_str(&format!(" at {}", location));
+    }
+    if !hits.is_empty() {
+        let hits = hits.iter().map(WatchHit::to_string).collect::<Vec<_>>();
+        text.push_str(&format!(": {}", hits.join(", ")));
+    }
+    text
+}
+
+/// Read and run commands until one resumes the run
+fn read_commands(
+    vm: &mut VirtualMachine<VecInput, VecOutput>,
+    commands: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<Resume> {
+    loop {
+        write!(out, "(ram) ").into_diagnostic()?;
+        out.flush().into_diagnostic()?;
+        let mut line = String::new();
+        if commands.read_line(&mut line).into_diagnostic()? == 0 {
+            writeln!(out).into_diagnostic()?;
+            return Ok(Resume::Detach);
+        }
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let place = words.next();
+        let argument = line.trim().split_once(char::is_whitespace).map_or("", |(_, rest)| rest);
+        let reply = match (command, place) {
+            ("", _) => continue,
+            ("c" | "continue", _) => return Ok(Resume::Continue),
+            ("s" | "step", _) => return Ok(Resume::Step),
+            ("q" | "quit", _) => return Ok(Resume::Quit),
+            ("h" | "help", _) => HELP.trim_end().to_string(),
+            ("l" | "list", _) => {
+                let watchpoints = vm.watchpoints().list();
+                if watchpoints.is_empty() {
+                    "No watchpoints".to_string()
+                } else {
+                    let watchpoints = watchpoints.iter().map(Watchpoint::to_string);
+                    format!("Watching {}", watchpoints.collect::<Vec<_>>().join(", "))
+                }
+            }
+            ("e" | "eval", None) => "Missing the expression, such as `eval R1 + 1`".to_string(),
+            ("e" | "eval", Some(_)) => match ram_vm::evaluate(argument.trim(), &*vm) {
+                Ok(value) => value.to_string(),
+                Err(error) => render_error(&error),
+            },
+            ("p" | "print" | "w" | "watch" | "u" | "unwatch", None) => {
+                format!("Missing the place, such as `{} 5`", command)
+            }
+            ("p" | "print" | "w" | "watch" | "u" | "unwatch", Some(place)) => {
+                match place.parse::<Watchpoint>() {
+                    Ok(watchpoint) => run_place_command(vm, command, watchpoint),
+                    Err(error) => error,
+                }
+            }
+            _ => format!("Unknown command '{}', type `help` for the commands", command),
+        };
+        writeln!(out, "{}", reply).into_diagnostic()?;
+    }
+}
+
+/// Run a command on a place, returning its reply
+fn run_place_command(
+    vm: &mut VirtualMachine<VecInput, VecOutput>,
+    command: &str,
+    watchpoint: Watchpoint,
+) -> String {
+    let location = watchpoint.location;
+    match command {
+        "p" | "print" => {
+            let value = match location {
+                Location::Accumulator => vm.accumulator(),
+                Location::Register(address) => vm.get_register_value(address),
+                Location::Memory(address) => vm.get_heap_value(address),
+            };
+            format!("{} = {}", location, value)
+        }
+        "w" | "watch" => {
+            vm.watch(watchpoint);
+            format!("Watching {}", watchpoint)
+        }
+        _ if vm.unwatch(location) => format!("No longer watching {}", location),
+        _ => format!("{} is not watched", location),
+    }
+}
+
+/// Render the error of an expression, pointing at where it went wrong
+fn render_error(error: &EvalError) -> String {
+    let mut text = String::new();
+    let handler = GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor());
+    match handler.render_report(&mut text, error) {
+        Ok(()) => text.trim_end().to_string(),
+        Err(_) => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_vm::{SourceMap, VmDatabase, VmDatabaseImpl};
+
+    use super::*;
+
+    /// Debug a program watching some places, returning the printed text
+    fn debug_source(source: &str, input: Vec<i64>, watch: &[&str], commands: &str) -> String {
+        let db = Arc::new(VmDatabaseImpl::new());
+        let program = db.parse_to_vm_program(source).unwrap();
+        let mut vm = VirtualMachine::new(program, VecInput::new(input), VecOutput::new(), db);
+        for place in watch {
+            vm.watch(place.parse().unwrap());
+        }
+        let mut out = Vec::new();
+        debug(&mut vm, None, commands.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_pause_at_watchpoints() {
+        let text = debug_source(
+            "READ 1\nLOAD 1\nSTORE 5\nADD =1\nSTORE 5\nWRITE 5\nHALT\n",
+            vec![3],
+            &["5:w"],
+            "p 5\ncontinue\nprint ACC\nunwatch 5\ncontinue\n",
+        );
+        assert_eq!(
+            text,
+            "Paused at step 3, instruction 2 (STORE 5): R5 written with value 3\n\
+             (ram) R5 = 3\n\
+             (ram) Paused at step 5, instruction 4 (STORE 5): R5 written with value 4\n\
+             (ram) ACC = 4\n\
+             (ram) No longer watching R5\n\
+             (ram) Output:      [4]\n\
+             Accumulator: 4\n\
+             Steps:       7\n"
+        );
+    }
+
+    #[test]
+    fn test_step_and_watch() {
+        let text = debug_source(
+            "LOAD =2\nSTORE 1\nLOAD 1\nHALT\n",
+            Vec::new(),
+            &["ACC:w"],
+            "step\nwatch R1:r\nlist\nc\nq\n",
+        );
+        assert_eq!(
+            text,
+            "Paused at step 1, instruction 0 (LOAD =2): ACC written with value 2\n\
+             (ram) Paused at step 2, instruction 1 (STORE 1)\n\
+             (ram) Watching R1:r\n\
+             (ram) Watching ACC:w, R1:r\n\
+             (ram) Paused at step 3, instruction 2 (LOAD 1): R1 read with value 2, ACC written with value 2\n\
+             (ram) Output:      []\n\
+             Accumulator: 2\n\
+             Steps:       3\n\
+             Stopped after 3 steps\n"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_expressions() {
+        let text = debug_source(
+            "READ 1\nLOAD =3\nSTORE 2\nend: HALT\n",
+            vec![4],
+            &["2:w"],
+            "eval R1 * R2 + 1\ne ACC == 3\neval end\neval nowhere + 1\neval R1 +\nq\n",
+        );
+        assert!(text.contains("(ram) 13\n(ram) 1\n(ram) 3\n"));
+        assert!(text.contains("Unknown label 'nowhere'"));
+        assert!(text.contains("no label of the program has this name"));
+        assert!(text.contains("Expected a value, found the end of the expression"));
+    }
+
+    #[test]
+    fn test_pause_at_source_locations() {
+        let source = "READ 1\nLOAD 1\n  STORE 5\nHALT\n";
+        let (_, body, _) = crate::language::lower_program(source);
+        let db = Arc::new(VmDatabaseImpl::new());
+        let program = db.parse_to_vm_program(source).unwrap();
+        let mut vm = VirtualMachine::builder(program, VecInput::new(vec![3]), VecOutput::new(), db)
+            .with_watchpoint("5:w".parse().unwrap())
+            .with_source_map(Arc::new(SourceMap::from_body(&body, "add.ram", source)))
+            .build();
+        let mut out = Vec::new();
+        debug(&mut vm, None, "".as_bytes(), &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with(
+            "Paused at step 3, instruction 2 (STORE 5) at add.ram:3:3: R5 written with value 3\n"
+        ));
+    }
+
+    #[test]
+    fn test_run_to_end_without_commands() {
+        let text = debug_source("LOAD =1\nSTORE 1\nSTORE 1\nHALT\n", Vec::new(), &["1"], "");
+        assert!(text.starts_with("Paused at step 2, instruction 1 (STORE 1)"));
+        assert!(text.ends_with("(ram) \nOutput:      []\nAccumulator: 1\nSteps:       4\n"));
+    }
+
+    #[test]
+    fn test_pause_on_timeout() {
+        let db = Arc::new(VmDatabaseImpl::new());
+        let program = db.parse_to_vm_program("loop: JUMP loop\n").unwrap();
+        let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+        let mut out = Vec::new();
+        let timeout = Some(Duration::from_millis(10));
+        debug(&mut vm, timeout, "step\n".as_bytes(), &mut out).unwrap();
+
+        // The loop pauses once, and stops when it times out without commands
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("Timed out. Paused at step "));
+        assert_eq!(text.matches("Paused at step").count(), 2);
+        assert!(text.ends_with(&format!("Stopped after {} steps\n", vm.steps())));
+    }
+}
@@ -0,0 +1,386 @@
+//! Module for structural diffs of programs
+//!
+//! Both programs are lowered to HIR and compared instruction by instruction
+//! instead of line by line, so comments, layout and the spelling of opcodes
+//! do not show up as changes. The instructions are aligned with the names of
+//! the labels they jump to left out, which finds the labels that were
+//! renamed; instructions that only changed their operand, that moved
+//! elsewhere, or that were added or removed are reported as such.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use base_db::LineIndex;
+use hir::body::Body;
+use hir::print::{print_instruction, print_operand};
+use miette::{IntoDiagnostic, Result};
+use serde::Serialize;
+
+use crate::cli::DiffFormat;
+use crate::language;
+
+/// An instruction of one of the programs, as compared by the diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffInstruction {
+    /// The line of the instruction, counting from 1
+    pub line: usize,
+    /// The labels attached to the instruction
+    pub labels: Vec<String>,
+    /// The name of the instruction
+    pub kind: String,
+    /// The operand of the instruction, if it has one
+    pub operand: Option<String>,
+    /// The label the operand refers to, if it refers to one
+    pub target: Option<String>,
+    /// The instruction as printed, without its labels
+    pub text: String,
+}
+
+impl DiffInstruction {
+    /// Get the instructions of a program, in order
+    pub fn from_body(body: &Body, source: &str) -> Vec<Self> {
+        let line_index = LineIndex::new(source);
+        body.instructions
+            .values()
+            .map(|instruction| {
+                let labels = body
+                    .labels
+                    .values()
+                    .filter(|label| label.instruction_id == Some(instruction.id))
+                    .map(|label| label.name.clone())
+                    .collect();
+                let operand = instruction.operand.and_then(|operand| print_operand(body, operand));
+                let target = operand
+                    .clone()
+                    .filter(|operand| body.labels.values().any(|label| &label.name == operand));
+                Self {
+                    line: line_index.line_col(instruction.span.start).line as usize + 1,
+                    labels,
+                    kind: instruction.kind.name().to_string(),
+                    operand,
+                    target,
+                    text: print_instruction(body, instruction),
+                }
+            })
+            .collect()
+    }
+
+    /// Get what is compared to align the instructions, with label names left out
+    fn shape(&self) -> (&str, Option<&str>) {
+        let operand = match &self.target {
+            Some(_) => Some("<label>"),
+            None => self.operand.as_deref(),
+        };
+        (&self.kind, operand)
+    }
+
+    /// Check whether the instruction does the same as another, once the labels
+    /// of its program are renamed as in the other program
+    fn same_as(&self, other: &DiffInstruction, renames: &HashMap<String, String>) -> bool {
+        let renamed = |target: &String| renames.get(target).unwrap_or(target).clone();
+        self.shape() == other.shape() && self.target.as_ref().map(renamed) == other.target
+    }
+}
+
+/// A change from the first program to the second
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+pub enum Change {
+    /// A label was renamed
+    LabelRenamed { from: String, to: String },
+    /// A label was attached to an instruction
+    LabelAdded { name: String, line: usize },
+    /// A label was removed from an instruction
+    LabelRemoved { name: String, line: usize },
+    /// An instruction was added
+    Added { line: usize, instruction: String },
+    /// An instruction was removed
+    Removed { line: usize, instruction: String },
+    /// An instruction moved to another place of the program
+    Moved { from_line: usize, to_line: usize, instruction: String },
+    /// The operand of an instruction changed
+    OperandChanged { from_line: usize, to_line: usize, from: String, to: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::LabelRenamed { from, to } => write!(f, "rename  label {} → {}", from, to),
+            Change::LabelAdded { name, line } => write!(f, "label   line {}: added {}", line, name),
+            Change::LabelRemoved { name, line } => {
+                write!(f, "label   line {}: removed {}", line, name)
+            }
+            Change::Added { line, instruction } => {
+                write!(f, "add     line {}: {}", line, instruction)
+            }
+            Change::Removed { line, instruction } => {
+                write!(f, "remove  line {}: {}", line, instruction)
+            }
+            Change::Moved { from_line, to_line, instruction } => {
+                write!(f, "move    line {} → {}: {}", from_line, to_line, instruction)
+            }
+            Change::OperandChanged { from_line, to_line, from, to } => {
+                write!(f, "change  line {} → {}: {} → {}", from_line, to_line, from, to)
+            }
+        }
+    }
+}
+
+/// The structural diff of two programs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProgramDiff {
+    /// The changes, with label renames first and then in the order of the
+    /// second program
+    pub changes: Vec<Change>,
+}
+
+impl ProgramDiff {
+    /// Compare the instructions of two programs
+    pub fn new(left: &[DiffInstruction], right: &[DiffInstruction]) -> Self {
+        let matches = align(left, right);
+
+        // Labels on instructions that line up are the same label, renamed
+        let mut renames = HashMap::new();
+        let mut changes = Vec::new();
+        for &(l, r) in &matches {
+            if let ([from], [to]) = (left[l].labels.as_slice(), right[r].labels.as_slice())
+                && from != to
+                && !renames.contains_key(from)
+            {
+                renames.insert(from.clone(), to.clone());
+                changes.push((0, 0, Change::LabelRenamed { from: from.clone(), to: to.clone() }));
+            }
+        }
+
+        for &(l, r) in &matches {
+            let (left, right) = (&left[l], &right[r]);
+            if !(left.labels.len() == 1 && right.labels.len() == 1) {
+                for name in left.labels.iter().filter(|name| !right.labels.contains(name)) {
+                    let change = Change::LabelRemoved { name: name.clone(), line: left.line };
+                    changes.push((r, 0, change));
+                }
+                for name in right.labels.iter().filter(|name| !left.labels.contains(name)) {
+                    let change = Change::LabelAdded { name: name.clone(), line: right.line };
+                    changes.push((r, 1, change));
+                }
+            }
+            if !left.same_as(right, &renames) {
+                changes.push((r, 2, operand_changed(left, right)));
+            }
+        }
+
+        // The unmatched instructions, with the index in the second program of
+        // the gap between matches they are in
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        let (mut l, mut r) = (0, 0);
+        for (ml, mr) in matches.iter().copied().chain(std::iter::once((left.len(), right.len()))) {
+            removed.extend((l..ml).map(|i| (i, r)));
+            added.extend((r..mr).map(|j| (j, r)));
+            (l, r) = (ml + 1, mr + 1);
+        }
+
+        // Instructions that were removed in one place and added in another moved
+        let mut moved = vec![false; removed.len()];
+        added.retain(|&(r, _)| {
+            let found = removed
+                .iter()
+                .enumerate()
+                .position(|(index, &(l, _))| !moved[index] && left[l].same_as(&right[r], &renames));
+            if let Some(index) = found {
+                moved[index] = true;
+                let (l, _) = removed[index];
+                let change = Change::Moved {
+                    from_line: left[l].line,
+                    to_line: right[r].line,
+                    instruction: right[r].text.clone(),
+                };
+                changes.push((r, 2, change));
+            }
+            found.is_none()
+        });
+        let mut removed = removed
+            .into_iter()
+            .zip(moved)
+            .filter_map(|(removed, moved)| (!moved).then_some(removed))
+            .collect::<Vec<_>>();
+
+        // Instructions of the same kind in the same place changed their operand
+        added.retain(|&(r, gap)| {
+            let found = removed
+                .iter()
+                .position(|&(l, removed_gap)| removed_gap == gap && left[l].kind == right[r].kind);
+            if let Some(index) = found {
+                let (l, _) = removed.remove(index);
+                changes.push((r, 2, operand_changed(&left[l], &right[r])));
+            }
+            found.is_none()
+        });
+
+        for (l, gap) in removed {
+            let change = Change::Removed { line: left[l].line, instruction: left[l].text.clone() };
+            changes.push((gap, 1, change));
+        }
+        for (r, _) in added {
+            let change = Change::Added { line: right[r].line, instruction: right[r].text.clone() };
+            changes.push((r, 2, change));
+        }
+
+        changes.sort_by_key(|&(position, order, _)| (position, order));
+        Self { changes: changes.into_iter().map(|(_, _, change)| change).collect() }
+    }
+
+    /// Check whether the programs do the same, up to comments, layout and
+    /// the spelling of opcodes
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Render the diff as text, one line for each change
+    pub fn to_text(&self) -> String {
+        if self.changes.is_empty() {
+            return "The programs have the same instructions.\n".to_string();
+        }
+        let mut text =
+            self.changes.iter().map(|change| format!("{}\n", change)).collect::<String>();
+        let plural = if self.changes.len() == 1 { "" } else { "s" };
+        text.push_str(&format!("{} change{}\n", self.changes.len(), plural));
+        text
+    }
+}
+
+/// Describe the change of the operand of an instruction
+fn operand_changed(left: &DiffInstruction, right: &DiffInstruction) -> Change {
+    Change::OperandChanged {
+        from_line: left.line,
+        to_line: right.line,
+        from: left.text.clone(),
+        to: right.text.clone(),
+    }
+}
+
+/// Find the instructions of the programs that line up, as pairs of indices in
+/// order
+///
+/// This is the longest common subsequence of the instructions, compared with
+/// the names of the labels they jump to left out.
+fn align(left: &[DiffInstruction], right: &[DiffInstruction]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the length of the longest common subsequence of
+    // left[i..] and right[j..]
+    let width = right.len() + 1;
+    let mut lengths = vec![0u32; (left.len() + 1) * width];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lengths[i * width + j] = if left[i].shape() == right[j].shape() {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if left[i].shape() == right[j].shape() {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+/// Compare two RAM programs and print the changes from the first to the second
+///
+/// Returns the diff, so the caller can tell whether the programs differ.
+pub fn diff_programs(left: &Path, right: &Path, format: DiffFormat) -> Result<ProgramDiff> {
+    let instructions = |path: &Path| -> Result<Vec<DiffInstruction>> {
+        let source = std::fs::read_to_string(path).into_diagnostic()?;
+        let (_program, body, _diagnostics) = language::lower_program(&source);
+        Ok(DiffInstruction::from_body(&body, &source))
+    };
+    let diff = ProgramDiff::new(&instructions(left)?, &instructions(right)?);
+    match format {
+        DiffFormat::Text => print!("{}", diff.to_text()),
+        DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff).into_diagnostic()?),
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(left: &str, right: &str) -> ProgramDiff {
+        let instructions = |source: &str| {
+            let (_program, body, _diagnostics) = language::lower_program(source);
+            DiffInstruction::from_body(&body, source)
+        };
+        ProgramDiff::new(&instructions(left), &instructions(right))
+    }
+
+    #[test]
+    fn test_same_instructions() {
+        let diff = diff(
+            "READ 1\nloop: LOAD 1\nJZERO done\nSUB =1\nSTORE 1\nJUMP loop\ndone: HALT\n",
+            "# Counts down\nread 1\nloop:  load 1\n  jzero done\n  sub =1\n  store 1\n  jmp loop\ndone: halt\n",
+        );
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_text(), "The programs have the same instructions.\n");
+    }
+
+    #[test]
+    fn test_label_renames_and_operand_changes() {
+        let diff = diff(
+            "READ 1\nloop: LOAD 1\nJZERO done\nSUB =1\nSTORE 1\nJUMP loop\ndone: HALT\n",
+            "READ 1\nagain: LOAD 1\nJZERO done\nSUB =2\nSTORE 1\nJUMP again\ndone: HALT\n",
+        );
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::LabelRenamed { from: "loop".to_string(), to: "again".to_string() },
+                Change::OperandChanged {
+                    from_line: 4,
+                    to_line: 4,
+                    from: "SUB =1".to_string(),
+                    to: "SUB =2".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_text(),
+            "rename  label loop → again\nchange  line 4 → 4: SUB =1 → SUB =2\n2 changes\n"
+        );
+    }
+
+    #[test]
+    fn test_added_removed_and_moved_instructions() {
+        let diff = diff(
+            "READ 1\nREAD 2\nLOAD 1\nADD 2\nSTORE 3\nWRITE 3\nHALT\n",
+            "READ 2\nREAD 1\nLOAD 1\nADD 2\nWRITE 0\nHALT\n",
+        );
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::Moved { from_line: 1, to_line: 2, instruction: "READ 1".to_string() },
+                Change::Removed { line: 5, instruction: "STORE 3".to_string() },
+                Change::OperandChanged {
+                    from_line: 6,
+                    to_line: 5,
+                    from: "WRITE 3".to_string(),
+                    to: "WRITE 0".to_string(),
+                },
+            ]
+        );
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["changes"][0]["change"], "moved");
+        assert_eq!(json["changes"][1]["change"], "removed");
+    }
+}
@@ -0,0 +1,507 @@
+//! Module for diffing the traces of two programs
+//!
+//! Both programs run with tracing on the same input, and their traces are
+//! aligned like the lines of two files: steps that executed the same
+//! instruction with the same results line up, and the others are shown as
+//! changed, or as missing on one side. The first divergence is highlighted,
+//! and every time the registers of the two runs start to hold different
+//! values the difference is listed below the step that caused it.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use miette::{IntoDiagnostic, Result};
+use owo_colors::Style;
+use ram_core::ArithmeticMode;
+use ram_vm::{Location, TraceStep, VecInput, VecOutput, VirtualMachine};
+
+use crate::cache::Cache;
+use crate::run;
+
+/// The most pairs of steps compared to align two traces
+///
+/// Longer traces are aligned step by step past their common start and end.
+pub const MAX_ALIGNMENT_CELLS: usize = 4_000_000;
+
+/// The width of each column of the diff
+const COLUMN_WIDTH: usize = 36;
+
+/// Options of a trace diff
+#[derive(Debug, Clone)]
+pub struct DiffTraceOptions {
+    /// The name of the instruction set the programs run with
+    pub instruction_set: String,
+    /// The maximum number of instructions each program executes
+    pub max_steps: usize,
+}
+
+/// An executed instruction, as compared between the traces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffStep {
+    /// The number of the step, counting from 1
+    pub step: u64,
+    /// The executed instruction
+    pub instruction: String,
+    /// The accumulator after the instruction ran
+    pub accumulator: i64,
+    /// The place written through the operand and its new value
+    pub written: Option<(Location, i64)>,
+}
+
+impl DiffStep {
+    /// Check whether two steps did the same thing
+    ///
+    /// The step numbers are left out, so a step can line up with one run
+    /// earlier or later by the other program.
+    fn same_as(&self, other: &DiffStep) -> bool {
+        self.instruction == other.instruction
+            && self.accumulator == other.accumulator
+            && self.written == other.written
+    }
+
+    /// Describe the step in a column of the diff
+    fn describe(&self) -> String {
+        let written = match self.written {
+            Some((location, value)) if location != Location::Accumulator => {
+                format!(", {}={}", location, value)
+            }
+            _ => String::new(),
+        };
+        format!("{:>4} {:<12} ACC={}{}", self.step, self.instruction, self.accumulator, written)
+    }
+}
+
+impl From<&TraceStep> for DiffStep {
+    fn from(step: &TraceStep) -> Self {
+        Self {
+            step: step.step,
+            instruction: step.instruction.to_string(),
+            accumulator: step.accumulator_after,
+            written: step.written,
+        }
+    }
+}
+
+/// The trace of a run of one of the programs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    /// The executed instructions
+    pub steps: Vec<DiffStep>,
+    /// The values written by the program
+    pub output: Vec<i64>,
+    /// The runtime error the program failed with
+    pub error: Option<String>,
+    /// Whether the program halted
+    pub halted: bool,
+}
+
+impl Trace {
+    /// Describe how the run ended
+    fn ending(&self) -> String {
+        match (&self.error, self.halted) {
+            (Some(error), _) => format!("failed: {}", error),
+            (None, true) => format!("halted with output {:?}", self.output),
+            (None, false) => format!("stopped after {} steps", self.steps.len()),
+        }
+    }
+}
+
+/// A line of the diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    /// The steps at these indices of the traces did the same thing
+    Same(usize, usize),
+    /// The steps at these indices of the traces did different things
+    Changed(usize, usize),
+    /// The step at this index of the left trace has no match in the right one
+    Left(usize),
+    /// The step at this index of the right trace has no match in the left one
+    Right(usize),
+}
+
+impl Row {
+    /// Get the indices of the steps of the row in the left and right traces
+    fn indices(self) -> (Option<usize>, Option<usize>) {
+        match self {
+            Row::Same(left, right) | Row::Changed(left, right) => (Some(left), Some(right)),
+            Row::Left(left) => (Some(left), None),
+            Row::Right(right) => (None, Some(right)),
+        }
+    }
+}
+
+/// A difference between the registers of the runs, found after a row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDelta {
+    /// The row after which the registers differ
+    pub row: usize,
+    /// The place that differs
+    pub location: Location,
+    /// Its value in the left run, if it was written
+    pub left: Option<i64>,
+    /// Its value in the right run, if it was written
+    pub right: Option<i64>,
+}
+
+/// The aligned traces of two programs
+#[derive(Debug, Clone)]
+pub struct TraceDiff {
+    /// The trace of the first program
+    pub left: Trace,
+    /// The trace of the second program
+    pub right: Trace,
+    /// The aligned steps
+    pub rows: Vec<Row>,
+    /// The differences of the registers, in the order of their rows
+    pub deltas: Vec<MemoryDelta>,
+}
+
+impl TraceDiff {
+    /// Align the traces of two runs
+    pub fn new(left: Trace, right: Trace) -> Self {
+        let rows = align(&left.steps, &right.steps);
+        let deltas = memory_deltas(&left.steps, &right.steps, &rows);
+        Self { left, right, rows, deltas }
+    }
+
+    /// Get the index of the first row where the traces diverge
+    pub fn first_divergence(&self) -> Option<usize> {
+        self.rows.iter().position(|row| !matches!(row, Row::Same(..)))
+    }
+
+    /// Check whether the programs ran the same way
+    pub fn identical(&self) -> bool {
+        self.first_divergence().is_none()
+            && self.left.output == self.right.output
+            && self.left.error == self.right.error
+            && self.left.halted == self.right.halted
+    }
+
+    /// Write the diff with the traces side by side
+    ///
+    /// The first divergence is highlighted; the styling is stripped by the
+    /// stream when colors are disabled.
+    pub fn write(&self, out: &mut impl Write, left_name: &str, right_name: &str) -> Result<()> {
+        let first = self.first_divergence();
+        let changed = Style::new().yellow();
+        let highlight = Style::new().red().bold();
+
+        writeln!(out, "  {:<width$} │ {}", left_name, right_name, width = COLUMN_WIDTH)
+            .into_diagnostic()?;
+        let mut deltas = self.deltas.iter().peekable();
+        for (index, row) in self.rows.iter().enumerate() {
+            let (left, right) = row.indices();
+            let left = left.map(|i| self.left.steps[i].describe()).unwrap_or_default();
+            let right = right.map(|i| self.right.steps[i].describe()).unwrap_or_default();
+            let (marker, style) = match row {
+                Row::Same(..) => (' ', Style::new()),
+                _ if Some(index) == first => ('>', highlight),
+                Row::Changed(..) => ('~', changed),
+                Row::Left(_) => ('-', changed),
+                Row::Right(_) => ('+', changed),
+            };
+            let line = format!(
+                "{} {:<width$} │ {}",
+                marker,
+                truncate(&left),
+                truncate(&right),
+                width = COLUMN_WIDTH
+            );
+            writeln!(out, "{}", style.style(line.trim_end())).into_diagnostic()?;
+
+            while let Some(delta) = deltas.next_if(|delta| delta.row == index) {
+                let value = |value: Option<i64>| {
+                    value.map(|value| value.to_string()).unwrap_or_else(|| "unset".to_string())
+                };
+                writeln!(
+                    out,
+                    "    {} is {} on the left, {} on the right",
+                    delta.location,
+                    value(delta.left),
+                    value(delta.right)
+                )
+                .into_diagnostic()?;
+            }
+        }
+
+        writeln!(out).into_diagnostic()?;
+        match first {
+            Some(row) => {
+                let (left, right) = self.rows[row].indices();
+                let step = |trace: &Trace, index: Option<usize>| match index {
+                    Some(index) => format!("step {}", trace.steps[index].step),
+                    None => "no step".to_string(),
+                };
+                writeln!(
+                    out,
+                    "The traces diverge first at {} on the left and {} on the right.",
+                    step(&self.left, left),
+                    step(&self.right, right)
+                )
+                .into_diagnostic()?;
+            }
+            None if self.identical() => {
+                writeln!(out, "The traces are identical.").into_diagnostic()?;
+            }
+            None => {
+                writeln!(out, "The traces are identical, but the runs end differently.")
+                    .into_diagnostic()?;
+            }
+        }
+        writeln!(out, "{}: {}", left_name, self.left.ending()).into_diagnostic()?;
+        writeln!(out, "{}: {}", right_name, self.right.ending()).into_diagnostic()?;
+        Ok(())
+    }
+}
+
+/// Run two RAM programs on the same input and print the diff of their traces
+///
+/// Returns the diff, so the caller can tell whether the traces diverged.
+pub fn diff_programs(
+    left_path: &Path,
+    right_path: &Path,
+    input: Vec<i64>,
+    options: &DiffTraceOptions,
+    cache: Option<&Cache>,
+    out: &mut impl Write,
+) -> Result<TraceDiff> {
+    let db = run::instruction_set_db(&options.instruction_set)?;
+    let mut traces = Vec::new();
+    for path in [left_path, right_path] {
+        let program = run::load_program(path, &db, false, ArithmeticMode::default(), cache)?;
+        let mut vm = VirtualMachine::new(
+            program,
+            VecInput::new(input.clone()),
+            VecOutput::new(),
+            db.clone(),
+        );
+        traces.push(trace(&mut vm, options.max_steps));
+    }
+    let right = traces.pop().unwrap_or_default();
+    let left = traces.pop().unwrap_or_default();
+
+    let diff = TraceDiff::new(left, right);
+    diff.write(out, &left_path.display().to_string(), &right_path.display().to_string())?;
+    Ok(diff)
+}
+
+/// Run a virtual machine for at most `max_steps` steps, tracing each of them
+pub fn trace(vm: &mut VirtualMachine<VecInput, VecOutput>, max_steps: usize) -> Trace {
+    let mut trace = Trace::default();
+    while trace.steps.len() < max_steps && !vm.is_finished() {
+        match vm.step_traced() {
+            Ok(step) => trace.steps.push(DiffStep::from(&step)),
+            Err(error) => {
+                trace.error = Some(error.to_string());
+                break;
+            }
+        }
+    }
+    trace.halted = trace.error.is_none() && vm.is_finished();
+    trace.output = vm.output.values.clone();
+    trace
+}
+
+/// Align two traces, keeping as many matching steps as possible in order
+///
+/// The common start and end are matched directly, and the steps between them
+/// by a longest common subsequence. Runs of unmatched steps on both sides are
+/// paired up as changed steps.
+pub fn align(left: &[DiffStep], right: &[DiffStep]) -> Vec<Row> {
+    let prefix = left.iter().zip(right).take_while(|(l, r)| l.same_as(r)).count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(l, r)| l.same_as(r))
+        .count();
+    let (left_end, right_end) = (left.len() - suffix, right.len() - suffix);
+
+    let mut rows = (0..prefix).map(|i| Row::Same(i, i)).collect::<Vec<_>>();
+    let middle = match_middle(&left[prefix..left_end], &right[prefix..right_end]);
+
+    let (mut l, mut r) = (prefix, prefix);
+    for (ml, mr) in middle
+        .into_iter()
+        .map(|(ml, mr)| (ml + prefix, mr + prefix))
+        .chain(std::iter::once((left_end, right_end)))
+    {
+        let paired = (ml - l).min(mr - r);
+        rows.extend((0..paired).map(|i| Row::Changed(l + i, r + i)));
+        rows.extend((l + paired..ml).map(Row::Left));
+        rows.extend((r + paired..mr).map(Row::Right));
+        if ml < left_end {
+            rows.push(Row::Same(ml, mr));
+        }
+        (l, r) = (ml + 1, mr + 1);
+    }
+
+    rows.extend((0..suffix).map(|i| Row::Same(left_end + i, right_end + i)));
+    rows
+}
+
+/// Find the matching steps of two traces, as pairs of indices in order
+///
+/// Traces too long to compare every pair of steps are matched at the same
+/// indices only.
+fn match_middle(left: &[DiffStep], right: &[DiffStep]) -> Vec<(usize, usize)> {
+    if left.len().saturating_mul(right.len()) > MAX_ALIGNMENT_CELLS {
+        return (0..left.len().min(right.len()))
+            .filter(|&i| left[i].same_as(&right[i]))
+            .map(|i| (i, i))
+            .collect();
+    }
+
+    // lengths[i][j] is the length of the longest common subsequence of
+    // left[i..] and right[j..]
+    let width = right.len() + 1;
+    let mut lengths = vec![0u32; (left.len() + 1) * width];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lengths[i * width + j] = if left[i].same_as(&right[j]) {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if left[i].same_as(&right[j]) {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+/// Find where the registers of the runs start to differ
+///
+/// After each row, the registers written by either run are compared, and a
+/// delta is reported for each one whose values differ in a way they did not
+/// after the row before.
+fn memory_deltas(left: &[DiffStep], right: &[DiffStep], rows: &[Row]) -> Vec<MemoryDelta> {
+    let mut left_memory = BTreeMap::new();
+    let mut right_memory = BTreeMap::new();
+    let mut differing: BTreeMap<Location, (Option<i64>, Option<i64>)> = BTreeMap::new();
+    let mut deltas = Vec::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        let (l, r) = row.indices();
+        let mut touched = Vec::new();
+        for (memory, step) in
+            [(&mut left_memory, l.map(|i| &left[i])), (&mut right_memory, r.map(|i| &right[i]))]
+        {
+            if let Some((location, value)) = step.and_then(|step| step.written)
+                && location != Location::Accumulator
+            {
+                memory.insert(location, value);
+                touched.push(location);
+            }
+        }
+
+        for location in touched {
+            let values =
+                (left_memory.get(&location).copied(), right_memory.get(&location).copied());
+            if values.0 == values.1 {
+                differing.remove(&location);
+            } else if differing.insert(location, values) != Some(values) {
+                deltas.push(MemoryDelta { row: index, location, left: values.0, right: values.1 });
+            }
+        }
+    }
+    deltas
+}
+
+/// Shorten the description of a step to fit in a column
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= COLUMN_WIDTH {
+        return text.to_string();
+    }
+    let mut truncated = text.chars().take(COLUMN_WIDTH - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ram_vm::{VmDatabase, VmDatabaseImpl};
+
+    use super::*;
+
+    fn trace_source(source: &str, input: Vec<i64>) -> Trace {
+        let db = Arc::new(VmDatabaseImpl::new());
+        let program = db.parse_to_vm_program(source).unwrap();
+        let mut vm = VirtualMachine::new(program, VecInput::new(input), VecOutput::new(), db);
+        trace(&mut vm, 100)
+    }
+
+    #[test]
+    fn test_identical_traces() {
+        let source = "READ 1\nLOAD 1\nADD =1\nSTORE 2\nWRITE 2\nHALT\n";
+        let diff = TraceDiff::new(trace_source(source, vec![4]), trace_source(source, vec![4]));
+        assert!(diff.identical());
+        assert!(diff.rows.iter().all(|row| matches!(row, Row::Same(..))));
+        assert!(diff.deltas.is_empty());
+
+        let mut out = Vec::new();
+        diff.write(&mut out, "a.ram", "b.ram").unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("The traces are identical."));
+    }
+
+    #[test]
+    fn test_first_divergence_and_memory_deltas() {
+        let left = trace_source("READ 1\nLOAD 1\nADD =1\nSTORE 2\nWRITE 2\nHALT\n", vec![4]);
+        let right = trace_source("READ 1\nLOAD 1\nADD =2\nSTORE 2\nWRITE 2\nHALT\n", vec![4]);
+        let diff = TraceDiff::new(left, right);
+
+        assert!(!diff.identical());
+        assert_eq!(diff.first_divergence(), Some(2));
+        assert_eq!(diff.rows[2], Row::Changed(2, 2));
+        assert_eq!(
+            diff.deltas,
+            vec![MemoryDelta {
+                row: 3,
+                location: Location::Register(2),
+                left: Some(5),
+                right: Some(6),
+            }]
+        );
+
+        let mut out = Vec::new();
+        diff.write(&mut out, "a.ram", "b.ram").unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("R2 is 5 on the left, 6 on the right"));
+        assert!(
+            text.contains(
+                "The traces diverge first at step 3 on the left and step 3 on the right."
+            )
+        );
+    }
+
+    #[test]
+    fn test_align_extra_steps() {
+        let left = trace_source("READ 1\nLOAD 1\nWRITE 1\nHALT\n", vec![4]);
+        let right = trace_source("READ 1\nLOAD 1\nSTORE 3\nWRITE 1\nHALT\n", vec![4]);
+        let diff = TraceDiff::new(left, right);
+
+        assert_eq!(
+            diff.rows,
+            vec![Row::Same(0, 0), Row::Same(1, 1), Row::Right(2), Row::Same(2, 3), Row::Same(3, 4)]
+        );
+        assert_eq!(diff.first_divergence(), Some(2));
+        assert_eq!(diff.deltas[0].location, Location::Register(3));
+        assert_eq!(diff.deltas[0].left, None);
+    }
+}
@@ -0,0 +1,543 @@
+//! Module for the machine-readable analysis results of `ram validate --emit`,
+//! `ram hir --json` and `ram itemtree --json`
+//!
+//! Every document names its `schema` and the `version` of it, which is bumped
+//! whenever a field is removed or changes meaning, so tools can tell whether
+//! they understand the output. Instructions, labels and expressions are
+//! identified by their HIR ids, nodes of the graphs by the instruction they
+//! stand for, and every list is sorted, so the output for a program is the
+//! same on every run and diffs cleanly between two versions of it.
+
+use base_db::LineIndex;
+use hir::body::{AddressingMode, Body, ExprKind, Literal};
+use hir_analysis::analyzers::control_flow::EdgeKind;
+use hir_analysis::analyzers::data_flow::DataFlowValue;
+use hir_analysis::{
+    AnalysisContext, ConstantPropagationAnalysis, ControlFlowAnalysis, DataFlowAnalysis,
+};
+use hir_def::item_tree::{ItemKind, ItemTree};
+use miette::{IntoDiagnostic, Result, miette};
+use serde::Serialize;
+
+use crate::cli::EmitFormat;
+
+/// The version of the schemas of the emitted documents
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A range of bytes in the source of the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Span {
+    /// The offset of the first byte
+    pub start: usize,
+    /// The offset after the last byte
+    pub end: usize,
+}
+
+impl From<&std::ops::Range<usize>> for Span {
+    fn from(range: &std::ops::Range<usize>) -> Self {
+        Self { start: range.start, end: range.end }
+    }
+}
+
+/// The lowered program, emitted with `hir-json`
+#[derive(Debug, Clone, Serialize)]
+pub struct HirDocument {
+    /// Always `ram.hir`
+    pub schema: &'static str,
+    /// The version of the schema
+    pub version: u32,
+    /// The instructions, in program order
+    pub instructions: Vec<HirInstruction>,
+    /// The labels, in program order
+    pub labels: Vec<HirLabel>,
+    /// The operand expressions, by id
+    pub exprs: Vec<HirExpr>,
+}
+
+/// An instruction of the lowered program
+#[derive(Debug, Clone, Serialize)]
+pub struct HirInstruction {
+    /// The id of the instruction
+    pub id: u32,
+    /// The opcode as written in the source
+    pub opcode: String,
+    /// The canonical name of the instruction the opcode resolves to
+    pub kind: String,
+    /// The id of the operand expression
+    pub operand: Option<u32>,
+    /// The label defined on the instruction
+    pub label: Option<String>,
+    /// Where the instruction is written
+    pub span: Span,
+    /// The documentation comments of the instruction
+    pub docs: Vec<String>,
+}
+
+/// A label of the lowered program
+#[derive(Debug, Clone, Serialize)]
+pub struct HirLabel {
+    /// The id of the label
+    pub id: u32,
+    /// The name of the label
+    pub name: String,
+    /// The id of the instruction the label points to
+    pub instruction: Option<u32>,
+    /// Where the label is defined
+    pub span: Span,
+    /// The documentation comments of the label
+    pub docs: Vec<String>,
+}
+
+/// An operand expression of the lowered program
+#[derive(Debug, Clone, Serialize)]
+pub struct HirExpr {
+    /// The id of the expression
+    pub id: u32,
+    /// Where the expression is written
+    pub span: Span,
+    /// What the expression is
+    #[serde(flatten)]
+    pub kind: HirExprKind,
+}
+
+/// What an operand expression is
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum HirExprKind {
+    /// An integer
+    Int { value: i64 },
+    /// A string
+    String { value: String },
+    /// A name that could not be resolved to a label
+    Label { name: String },
+    /// A reference to a label, by its id in the file defining it
+    LabelRef { file: u32, label: u32 },
+    /// A memory address with its addressing mode
+    MemoryRef { mode: &'static str, address: u32 },
+    /// A call to an instruction
+    InstructionCall { opcode: String, operands: Vec<u32> },
+    /// An element of an array
+    ArrayAccess { array: u32, index: u32 },
+    /// Arithmetic that could not be folded into a constant
+    Binary { op: &'static str, lhs: u32, rhs: u32 },
+}
+
+/// The items of a file, printed by `ram itemtree --json`
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemTreeDocument {
+    /// Always `ram.item-tree`
+    pub schema: &'static str,
+    /// The version of the schema
+    pub version: u32,
+    /// The modules, imports and labels of the file, in source order
+    pub items: Vec<ItemTreeItem>,
+}
+
+/// An item of a file
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemTreeItem {
+    /// The id of the item in the item tree
+    pub id: u32,
+    /// `module`, `use` or `label`
+    pub kind: &'static str,
+    /// The name of the item, or the imported path for imports
+    pub name: String,
+    /// Where the item is written
+    pub span: Span,
+    /// The documentation comments of the item
+    pub docs: Vec<String>,
+}
+
+/// The control flow graph, emitted with `cfg-json`
+#[derive(Debug, Clone, Serialize)]
+pub struct CfgDocument {
+    /// Always `ram.cfg`
+    pub schema: &'static str,
+    /// The version of the schema
+    pub version: u32,
+    /// The instruction execution starts at
+    pub entry: Option<u32>,
+    /// The instruction execution can fall off the end of the program after
+    pub falls_off_end: Option<u32>,
+    /// The instructions of the graph
+    pub nodes: Vec<u32>,
+    /// The edges between instructions
+    pub edges: Vec<CfgEdge>,
+}
+
+/// An edge of the control flow graph
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct CfgEdge {
+    /// The instruction control flows from
+    pub source: u32,
+    /// The instruction control flows to
+    pub target: u32,
+    /// How control flows along the edge
+    pub kind: &'static str,
+}
+
+/// The data flow graph, emitted with `dfg-json`
+#[derive(Debug, Clone, Serialize)]
+pub struct DfgDocument {
+    /// Always `ram.dfg`
+    pub schema: &'static str,
+    /// The version of the schema
+    pub version: u32,
+    /// The instructions of the graph
+    pub nodes: Vec<u32>,
+    /// The values flowing from the instruction writing them to the one reading them
+    pub edges: Vec<DfgEdge>,
+    /// The addresses read before they are written, with the reading instruction
+    pub uninitialized_reads: Vec<DfgAccess>,
+    /// The addresses written but never read, with the writing instruction
+    pub unused_writes: Vec<DfgAccess>,
+}
+
+/// An edge of the data flow graph
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct DfgEdge {
+    /// The instruction writing the value
+    pub source: u32,
+    /// The instruction reading the value
+    pub target: u32,
+    /// Where the value is held
+    pub value: DfgValue,
+}
+
+/// Where a value of the data flow graph is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum DfgValue {
+    /// In the accumulator
+    Accumulator,
+    /// In memory at an address
+    Memory { address: i64 },
+}
+
+/// An access of an address by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct DfgAccess {
+    /// The address accessed
+    pub address: i64,
+    /// The instruction accessing it
+    pub instruction: u32,
+}
+
+/// The known values of the accumulator, emitted with `constants-json`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConstantsDocument {
+    /// Always `ram.constants`
+    pub schema: &'static str,
+    /// The version of the schema
+    pub version: u32,
+    /// The instructions, in program order
+    pub instructions: Vec<ConstantInstruction>,
+}
+
+/// The value of the accumulator after an instruction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstantInstruction {
+    /// The id of the instruction
+    pub id: u32,
+    /// The line the instruction is written on, starting at 1
+    pub line: u32,
+    /// The opcode as written in the source
+    pub opcode: String,
+    /// The value of the accumulator after the instruction, if it is known
+    pub acc: Option<i64>,
+}
+
+/// Serialize an analysis result as a pretty-printed JSON document
+///
+/// Fails if the analysis the document is made from did not run.
+pub fn emit(
+    format: EmitFormat,
+    src: &str,
+    body: &Body,
+    context: &AnalysisContext,
+) -> Result<String> {
+    match format {
+        EmitFormat::HirJson => to_json(&hir_document(body)),
+        EmitFormat::CfgJson => to_json(&cfg_document(context)?),
+        EmitFormat::DfgJson => to_json(&dfg_document(context)?),
+        EmitFormat::ConstantsJson => to_json(&constants_document(src, body, context)?),
+    }
+}
+
+/// Build the document of the lowered program
+pub fn hir_document(body: &Body) -> HirDocument {
+    let instructions = body
+        .instructions
+        .values()
+        .map(|instruction| HirInstruction {
+            id: instruction.id.0,
+            opcode: instruction.opcode.clone(),
+            kind: instruction.kind.name().to_string(),
+            operand: instruction.operand.map(|operand| operand.0),
+            label: instruction.label_name.clone(),
+            span: Span::from(&instruction.span),
+            docs: instruction.docs.clone(),
+        })
+        .collect();
+    let labels = body
+        .labels
+        .values()
+        .map(|label| HirLabel {
+            id: label.id.0,
+            name: label.name.clone(),
+            instruction: label.instruction_id.map(|id| id.0),
+            span: Span::from(&label.span),
+            docs: label.docs.clone(),
+        })
+        .collect();
+
+    let mut exprs = body
+        .exprs
+        .values()
+        .map(|expr| HirExpr {
+            id: expr.id.0,
+            span: Span::from(&expr.span),
+            kind: match &expr.kind {
+                ExprKind::Literal(Literal::Int(value)) => HirExprKind::Int { value: *value },
+                ExprKind::Literal(Literal::String(value)) => {
+                    HirExprKind::String { value: value.clone() }
+                }
+                ExprKind::Literal(Literal::Label(name)) => {
+                    HirExprKind::Label { name: name.clone() }
+                }
+                ExprKind::LabelRef(label_ref) => HirExprKind::LabelRef {
+                    file: label_ref.label_id.file_id.0,
+                    label: label_ref.label_id.local_id.0,
+                },
+                ExprKind::MemoryRef(memory_ref) => HirExprKind::MemoryRef {
+                    mode: match memory_ref.mode {
+                        AddressingMode::Direct => "direct",
+                        AddressingMode::Indirect => "indirect",
+                        AddressingMode::Immediate => "immediate",
+                    },
+                    address: memory_ref.address.0,
+                },
+                ExprKind::InstructionCall(call) => HirExprKind::InstructionCall {
+                    opcode: call.opcode.clone(),
+                    operands: call.operands.iter().map(|operand| operand.0).collect(),
+                },
+                ExprKind::ArrayAccess(access) => {
+                    HirExprKind::ArrayAccess { array: access.array.0, index: access.index.0 }
+                }
+                ExprKind::Binary(binary) => HirExprKind::Binary {
+                    op: binary.op.symbol(),
+                    lhs: binary.lhs.0,
+                    rhs: binary.rhs.0,
+                },
+            },
+        })
+        .collect::<Vec<_>>();
+    exprs.sort_by_key(|expr| expr.id);
+
+    HirDocument { schema: "ram.hir", version: SCHEMA_VERSION, instructions, labels, exprs }
+}
+
+/// Build the document of the items of a file
+pub fn item_tree_document(item_tree: &ItemTree) -> ItemTreeDocument {
+    let items = item_tree
+        .items()
+        .into_iter()
+        .map(|item| {
+            let range = item.source().syntax_node.text_range();
+            ItemTreeItem {
+                id: item.id().0,
+                kind: match item.kind() {
+                    ItemKind::Module => "module",
+                    ItemKind::Use => "use",
+                    ItemKind::Label => "label",
+                },
+                name: item.name(),
+                span: Span { start: usize::from(range.start()), end: usize::from(range.end()) },
+                docs: item_tree.docs(item.id()).map(str::to_string).collect(),
+            }
+        })
+        .collect();
+
+    ItemTreeDocument { schema: "ram.item-tree", version: SCHEMA_VERSION, items }
+}
+
+/// Build the document of the control flow graph
+pub fn cfg_document(context: &AnalysisContext) -> Result<CfgDocument> {
+    let cfg = context
+        .get_result::<ControlFlowAnalysis>()
+        .map_err(|e| miette!("The control flow graph is not available: {}", e))?;
+    let instruction = |node| cfg.get_node(node).instruction_id.map(|id| id.0);
+
+    let mut nodes = cfg.node_indices().into_iter().filter_map(instruction).collect::<Vec<_>>();
+    nodes.sort();
+    let mut edges = cfg
+        .node_indices()
+        .into_iter()
+        .flat_map(|node| {
+            cfg.get_outgoing_edges(node).into_iter().map(move |(target, kind)| (node, target, kind))
+        })
+        .filter_map(|(source, target, kind)| {
+            Some(CfgEdge {
+                source: instruction(source)?,
+                target: instruction(target)?,
+                kind: match kind {
+                    EdgeKind::Unconditional => "unconditional",
+                    EdgeKind::ConditionalTrue => "conditional-true",
+                    EdgeKind::ConditionalFalse => "conditional-false",
+                    EdgeKind::Call => "call",
+                    EdgeKind::Return => "return",
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    edges.sort();
+    edges.dedup();
+
+    Ok(CfgDocument {
+        schema: "ram.cfg",
+        version: SCHEMA_VERSION,
+        entry: cfg.entry_node().and_then(instruction),
+        falls_off_end: cfg.falls_off_end().and_then(instruction),
+        nodes,
+        edges,
+    })
+}
+
+/// Build the document of the data flow graph
+pub fn dfg_document(context: &AnalysisContext) -> Result<DfgDocument> {
+    let dfg = context
+        .get_result::<DataFlowAnalysis>()
+        .map_err(|e| miette!("The data flow graph is not available: {}", e))?;
+    let instruction = |node| dfg.get_node(node).instruction_id.0;
+
+    let mut nodes = dfg.node_indices().into_iter().map(instruction).collect::<Vec<_>>();
+    nodes.sort();
+    let mut edges = dfg
+        .node_indices()
+        .into_iter()
+        .flat_map(|node| {
+            dfg.get_outgoing_edges(node).into_iter().map(move |(target, value)| DfgEdge {
+                source: instruction(node),
+                target: instruction(target),
+                value: match value {
+                    DataFlowValue::Accumulator => DfgValue::Accumulator,
+                    DataFlowValue::Memory(address) => DfgValue::Memory { address },
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    edges.sort();
+    edges.dedup();
+
+    let accesses = |accesses: Vec<(i64, hir::ids::LocalDefId)>| {
+        let mut accesses = accesses
+            .into_iter()
+            .map(|(address, id)| DfgAccess { address, instruction: id.0 })
+            .collect::<Vec<_>>();
+        accesses.sort();
+        accesses
+    };
+
+    Ok(DfgDocument {
+        schema: "ram.dfg",
+        version: SCHEMA_VERSION,
+        nodes,
+        edges,
+        uninitialized_reads: accesses(dfg.find_uninitialized_reads()),
+        unused_writes: accesses(dfg.find_unused_writes()),
+    })
+}
+
+/// Build the document of the known values of the accumulator
+pub fn constants_document(
+    src: &str,
+    body: &Body,
+    context: &AnalysisContext,
+) -> Result<ConstantsDocument> {
+    let constants = context
+        .get_result::<ConstantPropagationAnalysis>()
+        .map_err(|e| miette!("The constant propagation results are not available: {}", e))?;
+    let instructions = constants
+        .to_table(body, &LineIndex::new(src))
+        .rows
+        .into_iter()
+        .map(|row| ConstantInstruction {
+            id: row.instruction_id.0,
+            line: row.line,
+            opcode: row.opcode,
+            acc: row.acc,
+        })
+        .collect();
+
+    Ok(ConstantsDocument { schema: "ram.constants", version: SCHEMA_VERSION, instructions })
+}
+
+/// Serialize a document as pretty-printed JSON
+pub(crate) fn to_json(document: &impl Serialize) -> Result<String> {
+    serde_json::to_string_pretty(document).into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_diagnostics::DiagnosticConfig;
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::language;
+
+    fn emit_json(source: &str, format: EmitFormat) -> Value {
+        let (_, body, _, context, _) =
+            language::analyze_program(source, &DiagnosticConfig::default());
+        serde_json::from_str(&emit(format, source, &body, &context).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_emit_hir() {
+        let hir = emit_json("loop: LOAD 1\nJUMP loop\n", EmitFormat::HirJson);
+        assert_eq!(hir["schema"], "ram.hir");
+        assert_eq!(hir["version"], SCHEMA_VERSION);
+        assert_eq!(hir["instructions"][0]["opcode"], "LOAD");
+        assert_eq!(hir["instructions"][0]["label"], "loop");
+        assert_eq!(hir["instructions"][0]["span"], json!({ "start": 6, "end": 12 }));
+        assert_eq!(hir["labels"][0]["name"], "loop");
+        assert_eq!(hir["labels"][0]["instruction"], hir["instructions"][0]["id"]);
+
+        let operand = &hir["instructions"][0]["operand"];
+        let expr = hir["exprs"].as_array().unwrap().iter().find(|expr| &expr["id"] == operand);
+        assert_eq!(expr.unwrap()["kind"], "memory-ref");
+        assert_eq!(expr.unwrap()["mode"], "direct");
+    }
+
+    #[test]
+    fn test_emit_graphs() {
+        let source = "READ 1\nJZERO end\nSTORE 2\nend: LOAD 2\nHALT\n";
+        let cfg = emit_json(source, EmitFormat::CfgJson);
+        assert_eq!(cfg["schema"], "ram.cfg");
+        assert_eq!(cfg["entry"], 0);
+        assert_eq!(cfg["nodes"], json!([0, 1, 2, 3, 4]));
+        let edges = cfg["edges"].as_array().unwrap();
+        assert!(edges.contains(&json!({ "source": 1, "target": 3, "kind": "conditional-true" })));
+        assert!(edges.contains(&json!({ "source": 1, "target": 2, "kind": "conditional-false" })));
+
+        let dfg = emit_json(source, EmitFormat::DfgJson);
+        assert_eq!(dfg["schema"], "ram.dfg");
+        assert_eq!(dfg["nodes"], json!([0, 1, 2, 3, 4]));
+
+        // The output is the same on every run
+        assert_eq!(emit_json(source, EmitFormat::DfgJson), dfg);
+        assert_eq!(emit_json(source, EmitFormat::CfgJson), cfg);
+    }
+
+    #[test]
+    fn test_emit_constants() {
+        let constants = emit_json("LOAD =3\nADD =4\n\nLOAD 1\nHALT\n", EmitFormat::ConstantsJson);
+        assert_eq!(constants["schema"], "ram.constants");
+        assert_eq!(
+            constants["instructions"][0],
+            json!({ "id": 0, "line": 1, "opcode": "LOAD", "acc": 3 })
+        );
+        assert_eq!(constants["instructions"][1]["acc"], 7);
+        assert_eq!(constants["instructions"][2]["line"], 4);
+        assert_eq!(constants["instructions"][2]["acc"], Value::Null);
+    }
+}
@@ -0,0 +1,137 @@
+//! Module for checking that two programs behave the same
+//!
+//! The programs run with every input of a finite domain, or a random sample
+//! of it, and the first input they behave differently on is reported.
+
+use std::path::Path;
+
+use miette::{Result, miette};
+use ram_core::ArithmeticMode;
+use ram_vm::Rng;
+use ram_vm::equivalence::{EquivalenceOptions, EquivalenceReport, InputDomain, check_equivalence};
+
+use crate::cache::Cache;
+use crate::run;
+
+/// The largest domain checked input by input, larger ones must be sampled
+pub const MAX_EXHAUSTIVE_INPUTS: u64 = 1_000_000;
+
+/// Options of an equivalence check
+#[derive(Debug, Clone)]
+pub struct EquivOptions {
+    /// The name of the instruction set the programs run with
+    pub instruction_set: String,
+    /// The maximum number of instructions each program executes on an input
+    pub max_steps: usize,
+    /// The number of random inputs to check, instead of every input
+    pub random: Option<usize>,
+    /// The seed of the random inputs and of the random numbers of the programs
+    pub seed: Option<u64>,
+}
+
+/// Check that two RAM programs behave the same on a domain of inputs and print the result
+pub fn check_programs(
+    left_path: &Path,
+    right_path: &Path,
+    domain: &InputDomain,
+    options: &EquivOptions,
+    cache: Option<&Cache>,
+) -> Result<EquivalenceReport> {
+    let db = run::instruction_set_db(&options.instruction_set)?;
+    let left = run::load_program(left_path, &db, false, ArithmeticMode::default(), cache)?;
+    let right = run::load_program(right_path, &db, false, ArithmeticMode::default(), cache)?;
+
+    let check_options =
+        EquivalenceOptions { max_steps: options.max_steps, seed: options.seed.unwrap_or(0) };
+    let report = match options.random {
+        Some(count) => {
+            let mut rng = options.seed.map_or_else(Rng::from_time, Rng::new);
+            let inputs = std::iter::repeat_with(|| domain.sample(&mut rng)).take(count);
+            check_equivalence(&left, &right, &db, inputs, &check_options)
+        }
+        None => match domain.size() {
+            Some(size) if size <= MAX_EXHAUSTIVE_INPUTS => {
+                check_equivalence(&left, &right, &db, domain.inputs(), &check_options)
+            }
+            _ => {
+                return Err(miette!(
+                    "The input domain has more than {} inputs, check a sample with --random",
+                    MAX_EXHAUSTIVE_INPUTS
+                ));
+            }
+        },
+    };
+
+    match &report.divergence {
+        None => println!("The programs are equivalent on all {} inputs.", report.inputs_checked),
+        Some(divergence) => {
+            println!(
+                "The programs diverge on input {:?}, after {} matching outputs:",
+                divergence.input, divergence.position
+            );
+            println!(
+                "  {} {} after {} steps",
+                left_path.display(),
+                divergence.left,
+                divergence.left_steps
+            );
+            println!(
+                "  {} {} after {} steps",
+                right_path.display(),
+                divergence.right,
+                divergence.right_steps
+            );
+        }
+    }
+    Ok(report)
+}
+
+/// Parse the `--inputs` argument, one range of values for each input value
+///
+/// Ranges are separated by commas or whitespace, and are written `0..=9`,
+/// `0..10` or as a single value, such as `0..=9, 1..4, 7`.
+pub fn parse_domain(arg: &str) -> Result<InputDomain> {
+    let ranges = arg
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let range = if let Some((start, end)) = token.split_once("..=") {
+                parse_bound(start)?..=parse_bound(end)?
+            } else if let Some((start, end)) = token.split_once("..") {
+                let end = parse_bound(end)?
+                    .checked_sub(1)
+                    .ok_or_else(|| miette!("Invalid input range '{}'", token))?;
+                parse_bound(start)?..=end
+            } else {
+                let value = parse_bound(token)?;
+                value..=value
+            };
+            if range.is_empty() {
+                return Err(miette!("Invalid input range '{}': it has no values", token));
+            }
+            Ok(range)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(InputDomain::new(ranges))
+}
+
+/// Parse a bound of an input range
+fn parse_bound(token: &str) -> Result<i64> {
+    token.trim().parse::<i64>().map_err(|e| miette!("Invalid number '{}': {}", token, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_domain() {
+        let domain = parse_domain("0..=2, -1..1 7").unwrap();
+        assert_eq!(domain, InputDomain::new(vec![0..=2, -1..=0, 7..=7]));
+        assert_eq!(domain.size(), Some(6));
+
+        assert!(parse_domain("3..=1").is_err());
+        assert!(parse_domain("0..0").is_err());
+        assert!(parse_domain("a..=3").is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Module for explaining program runs step by step
+//!
+//! The program runs with tracing, and every executed instruction is described
+//! in words, such as `ADD 2 — ACC becomes 7 (4 + 3 from R2)`. The explanation
+//! is printed as text or as Markdown for teaching materials.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use miette::Result;
+use ram_core::effect::{AccumulatorValue, Effect, JumpCondition};
+use ram_core::operand::{Operand, OperandKind, OperandValue};
+use ram_core::{ArithmeticMode, InstructionKind};
+use ram_vm::{TraceStep, VecInput, VecOutput, VirtualMachine, VmDatabase, VmDatabaseImpl};
+
+use crate::cache::Cache;
+use crate::run;
+
+/// Options of an explained run
+#[derive(Debug, Clone)]
+pub struct ExplainOptions {
+    /// The name of the instruction set the program runs with
+    pub instruction_set: String,
+    /// The maximum number of instructions to explain
+    pub max_steps: usize,
+    /// Whether the explanation is printed as Markdown
+    pub markdown: bool,
+}
+
+/// How an explained run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ending {
+    /// The program halted
+    Halted,
+    /// The program failed with a runtime error
+    Failed(String),
+    /// The run reached the maximum number of steps
+    Stopped,
+}
+
+/// The explanation of a run
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    /// The executed instructions and what each of them did
+    pub steps: Vec<(String, String)>,
+    /// The values written by the program
+    pub output: Vec<i64>,
+    /// How the run ended
+    pub ending: Ending,
+    /// The instructions used, with the semantics from their metadata
+    pub instructions: Vec<(String, String)>,
+}
+
+/// Run a RAM program and print an explanation of every step
+pub fn explain_run(
+    program_path: &Path,
+    input: Vec<i64>,
+    options: &ExplainOptions,
+    cache: Option<&Cache>,
+) -> Result<()> {
+    let db = run::instruction_set_db(&options.instruction_set)?;
+    let program = run::load_program(program_path, &db, false, ArithmeticMode::default(), cache)?;
+    let mut vm =
+        VirtualMachine::new(program, VecInput::new(input.clone()), VecOutput::new(), db.clone());
+
+    let explanation = explain(&mut vm, &db, options.max_steps);
+    if options.markdown {
+        let name = program_path.file_name().unwrap_or(program_path.as_os_str());
+        print!("{}", explanation.to_markdown(&name.to_string_lossy(), &input));
+    } else {
+        print!("{}", explanation.to_text());
+    }
+    Ok(())
+}
+
+/// Run a virtual machine for at most `max_steps` steps, explaining each of them
+///
+/// The instructions are looked up in `db` for the semantics in their metadata.
+pub fn explain(
+    vm: &mut VirtualMachine<VecInput, VecOutput>,
+    db: &VmDatabaseImpl,
+    max_steps: usize,
+) -> Explanation {
+    let mut steps = Vec::new();
+    let mut kinds: Vec<InstructionKind> = Vec::new();
+    let mut ending = Ending::Stopped;
+
+    while steps.len() < max_steps {
+        if vm.is_finished() {
+            ending = Ending::Halted;
+            break;
+        }
+        let instruction = vm.program().get_instruction(vm.pc()).cloned();
+        if let Some(instruction) = &instruction
+            && !kinds.contains(&instruction.kind)
+        {
+            kinds.push(instruction.kind.clone());
+        }
+
+        match vm.step_traced() {
+            Ok(step) => steps.push((step.instruction.to_string(), describe_step(&step))),
+            Err(error) => {
+                let text = instruction.map(|i| i.to_string()).unwrap_or_default();
+                steps.push((text, format!("fails: {}", error)));
+                ending = Ending::Failed(error.to_string());
+                break;
+            }
+        }
+    }
+    if ending == Ending::Stopped && vm.is_finished() {
+        ending = Ending::Halted;
+    }
+
+    let instructions = kinds
+        .into_iter()
+        .map(|kind| {
+            let metadata = match db.get_instruction_definition(&kind) {
+                Some(definition) => definition.metadata(),
+                None => kind.metadata(),
+            };
+            let semantics =
+                if metadata.semantics.is_empty() { metadata.summary } else { metadata.semantics };
+            (kind.to_string(), semantics)
+        })
+        .collect();
+
+    Explanation { steps, output: vm.output.values.clone(), ending, instructions }
+}
+
+impl Explanation {
+    /// Render the explanation as plain text, one line per step
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (number, (instruction, description)) in self.steps.iter().enumerate() {
+            let _ = writeln!(text, "Step {}: {} — {}", number + 1, instruction, description);
+        }
+        let _ = writeln!(text, "{}", self.summary());
+        text
+    }
+
+    /// Render the explanation as Markdown, with a table of the steps
+    pub fn to_markdown(&self, program: &str, input: &[i64]) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "# Run of `{}`\n", program);
+        if !input.is_empty() {
+            let values = input.iter().map(i64::to_string).collect::<Vec<_>>();
+            let _ = writeln!(text, "Input: `{}`\n", values.join(" "));
+        }
+
+        let _ = writeln!(text, "| Step | Instruction | What happens |");
+        let _ = writeln!(text, "|------|-------------|--------------|");
+        for (number, (instruction, description)) in self.steps.iter().enumerate() {
+            let _ = writeln!(text, "| {} | `{}` | {} |", number + 1, instruction, description);
+        }
+        let _ = writeln!(text, "\n{}", self.summary());
+
+        if !self.instructions.is_empty() {
+            let _ = writeln!(text, "\n## Instructions\n");
+            for (name, semantics) in &self.instructions {
+                let _ = writeln!(text, "- `{}`: {}", name, semantics);
+            }
+        }
+        text
+    }
+
+    /// Describe how the run ended
+    fn summary(&self) -> String {
+        let steps = self.steps.len();
+        match &self.ending {
+            Ending::Halted => {
+                format!("The program halted after {} steps with output {:?}.", steps, self.output)
+            }
+            Ending::Failed(error) => format!("The program failed at step {}: {}.", steps, error),
+            Ending::Stopped => format!("The explanation stops after {} steps.", steps),
+        }
+    }
+}
+
+/// Describe what an executed instruction did, such as `ACC becomes 7 (4 + 3 from R2)`
+pub fn describe_step(step: &TraceStep) -> String {
+    let effects = &step.effects;
+    let operand = step.instruction.operand.as_ref();
+    let mut clauses = Vec::new();
+
+    let operand_value = step.operand_value.map(|value| value.to_string()).unwrap_or_default();
+    let from = source(operand).map(|source| format!(" from {}", source)).unwrap_or_default();
+    let arithmetic = |symbol: &str| {
+        format!(
+            "ACC becomes {} ({} {} {}{})",
+            step.accumulator_after, step.accumulator_before, symbol, operand_value, from
+        )
+    };
+    match effects.accumulator_value() {
+        Some(AccumulatorValue::Operand) if from.is_empty() => {
+            clauses.push(format!("ACC becomes {}", step.accumulator_after));
+        }
+        Some(AccumulatorValue::Operand) => {
+            clauses.push(format!("ACC becomes {} ({})", step.accumulator_after, from.trim()));
+        }
+        Some(AccumulatorValue::Add) => clauses.push(arithmetic("+")),
+        Some(AccumulatorValue::Sub) => clauses.push(arithmetic("-")),
+        Some(AccumulatorValue::Mul) => clauses.push(arithmetic("×")),
+        Some(AccumulatorValue::Div) => clauses.push(arithmetic("÷")),
+        Some(AccumulatorValue::Unknown) | None
+            if step.accumulator_after != step.accumulator_before =>
+        {
+            clauses.push(format!("ACC becomes {}", step.accumulator_after));
+        }
+        _ => {}
+    }
+
+    if let Some((location, value)) = step.written {
+        let origin = if effects.contains(Effect::ReadsInput) {
+            " (read from the input)"
+        } else if effects.reads_accumulator() {
+            " (from ACC)"
+        } else {
+            ""
+        };
+        clauses.push(format!("{} becomes {}{}", location, value, origin));
+    }
+
+    if effects.contains(Effect::WritesOutput) {
+        clauses.push(format!("writes {} to the output", operand_value));
+    }
+
+    let target = operand.map(ToString::to_string).unwrap_or_default();
+    let accumulator = step.accumulator_before;
+    match effects.jump_condition() {
+        Some(JumpCondition::Always) => clauses.push(format!("jumps to {}", target)),
+        Some(condition) => {
+            let (holds, fails) = match condition {
+                JumpCondition::Positive => ("is greater than 0", "is not greater than 0"),
+                _ => ("is 0", "is not 0"),
+            };
+            clauses.push(if condition.holds(accumulator) {
+                format!("jumps to {}, as ACC ({}) {}", target, accumulator, holds)
+            } else {
+                format!("does not jump, as ACC ({}) {}", accumulator, fails)
+            });
+        }
+        None => {}
+    }
+    if effects.calls() {
+        clauses.push(format!("calls {}", target));
+    }
+    if effects.returns() {
+        clauses.push("returns from the subroutine".to_string());
+    }
+    if effects.halts() {
+        clauses.push("the program halts".to_string());
+    }
+
+    if clauses.is_empty() { "nothing changes".to_string() } else { clauses.join(", ") }
+}
+
+/// Describe where an operand reads its value from, or `None` if it is immediate
+fn source(operand: Option<&Operand>) -> Option<String> {
+    let operand = operand?;
+    match (operand.kind, &operand.value) {
+        (OperandKind::Immediate, _) => None,
+        (OperandKind::Direct, OperandValue::Number(address)) => Some(format!("R{}", address)),
+        _ => Some(operand.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn explain_source(source: &str, input: Vec<i64>) -> Explanation {
+        let db = Arc::new(VmDatabaseImpl::new());
+        let program = db.parse_to_vm_program(source).unwrap();
+        let mut vm =
+            VirtualMachine::new(program, VecInput::new(input), VecOutput::new(), db.clone());
+        explain(&mut vm, &db, 100)
+    }
+
+    #[test]
+    fn test_describe_steps() {
+        let explanation = explain_source(
+            "READ 2\nLOAD =4\nADD 2\nSTORE 3\nWRITE 3\nJZERO end\nend: HALT\n",
+            vec![3],
+        );
+        let descriptions = explanation.steps.iter().map(|(_, d)| d.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            vec![
+                "R2 becomes 3 (read from the input)",
+                "ACC becomes 4",
+                "ACC becomes 7 (4 + 3 from R2)",
+                "R3 becomes 7 (from ACC)",
+                "writes 7 to the output",
+                "does not jump, as ACC (7) is not 0",
+                "the program halts",
+            ]
+        );
+        assert_eq!(explanation.ending, Ending::Halted);
+
+        let text = explanation.to_text();
+        assert!(text.starts_with("Step 1: READ 2 — R2 becomes 3 (read from the input)\n"));
+        assert!(text.ends_with("The program halted after 7 steps with output [7].\n"));
+    }
+
+    #[test]
+    fn test_explain_failures_and_markdown() {
+        let explanation = explain_source("LOAD =1\nDIV =0\nHALT\n", vec![]);
+        assert_eq!(explanation.steps.len(), 2);
+        assert!(matches!(explanation.ending, Ending::Failed(_)));
+
+        let markdown = explanation.to_markdown("div.ram", &[]);
+        assert!(markdown.starts_with("# Run of `div.ram`\n"));
+        assert!(markdown.contains("| 1 | `LOAD =1` | ACC becomes 1 |"));
+        assert!(markdown.contains("- `DIV`: Divides the accumulator"));
+    }
+}
@@ -0,0 +1,221 @@
+//! Module for batch-grading submissions against a grading spec
+//!
+//! The spec is a TOML file with the test cases, limits and banned instructions:
+//!
+//! ```toml
+//! banned-instructions = ["MUL"]
+//!
+//! [limits]
+//! max-steps = 10000
+//! max-instructions = 50
+//!
+//! [[cases]]
+//! name = "adds"
+//! input = [5, 7]
+//! output = [12]
+//! ```
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use miette::{IntoDiagnostic, Result, miette};
+use ram_core::ArithmeticMode;
+use ram_vm::bytecode;
+use ram_vm::grader::{CaseStatus, GradingReport, GradingSpec, grade, grade_with_cancellation};
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::cli::GradeFormat;
+use crate::run;
+
+/// The grading of one submission
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmissionReport {
+    /// The file of the submission
+    pub submission: PathBuf,
+    /// The report of the grading, if the submission compiled
+    #[serde(flatten)]
+    pub report: Option<GradingReport>,
+    /// The reason the submission could not be graded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SubmissionReport {
+    /// Check if the submission passed every case without violating the spec
+    pub fn passed(&self) -> bool {
+        self.report.as_ref().is_some_and(GradingReport::passed)
+    }
+}
+
+/// Read a grading spec from a TOML file
+pub fn load_spec(spec_path: &Path) -> Result<GradingSpec> {
+    let text = std::fs::read_to_string(spec_path).into_diagnostic()?;
+    toml::from_str(&text)
+        .map_err(|e| miette!("Invalid grading spec {}: {}", spec_path.display(), e))
+}
+
+/// Grade every program and bytecode artifact in a directory and print the reports
+///
+/// With a `timeout`, the grading of each submission is cancelled once it takes
+/// longer, so a program that never halts does not hold up the others.
+pub fn grade_submissions(
+    spec_path: &Path,
+    submissions: &Path,
+    instruction_set: &str,
+    format: GradeFormat,
+    timeout: Option<Duration>,
+    cache: Option<&Cache>,
+) -> Result<Vec<SubmissionReport>> {
+    let spec = load_spec(spec_path)?;
+    let db = run::instruction_set_db(instruction_set)?;
+
+    let mut paths = std::fs::read_dir(submissions)
+        .into_diagnostic()?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    paths.retain(|path| {
+        path.is_file()
+            && path.extension().is_some_and(|ext| ext == "ram" || ext == bytecode::FILE_EXTENSION)
+    });
+    paths.sort();
+
+    let reports = paths
+        .into_iter()
+        .map(|path| match run::load_program(&path, &db, false, ArithmeticMode::default(), cache) {
+            Ok(program) => SubmissionReport {
+                report: Some(match timeout {
+                    Some(timeout) => {
+                        let deadline = Instant::now() + timeout;
+                        let expired = move || Instant::now() >= deadline;
+                        grade_with_cancellation(&program, &db, &spec, Arc::new(expired))
+                    }
+                    None => grade(&program, &db, &spec),
+                }),
+                error: None,
+                submission: path,
+            },
+            Err(error) => {
+                SubmissionReport { report: None, error: Some(error.to_string()), submission: path }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        GradeFormat::Csv => print!("{}", to_csv(&reports)),
+        GradeFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&reports).into_diagnostic()?)
+        }
+    }
+    Ok(reports)
+}
+
+/// Render reports as CSV, with one row for each case of each submission
+///
+/// Submissions that could not be graded have a single row without a case.
+pub fn to_csv(reports: &[SubmissionReport]) -> String {
+    let mut csv = String::from("submission,case,status,steps,violations\n");
+    for submission in reports {
+        let name = csv_field(&submission.submission.display().to_string());
+        let Some(report) = &submission.report else {
+            let _ = writeln!(csv, "{},,compile-error,,", name);
+            continue;
+        };
+        let violations = report.violations.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let violations = csv_field(&violations.join("; "));
+        if report.cases.is_empty() {
+            let _ = writeln!(csv, "{},,,,{}", name, violations);
+        }
+        for case in &report.cases {
+            let status = match &case.status {
+                CaseStatus::Passed => "passed",
+                CaseStatus::WrongOutput => "wrong-output",
+                CaseStatus::StepLimitExceeded => "step-limit-exceeded",
+                CaseStatus::RuntimeError(_) => "runtime-error",
+                CaseStatus::Cancelled => "cancelled",
+            };
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{}",
+                name,
+                csv_field(&case.name),
+                status,
+                case.steps,
+                violations
+            );
+        }
+    }
+    csv
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_vm::grader::{CaseResult, Violation};
+
+    use super::*;
+
+    #[test]
+    fn test_spec_from_toml() {
+        let spec: GradingSpec = toml::from_str(
+            r#"
+            banned-instructions = ["MUL"]
+
+            [limits]
+            max-steps = 100
+
+            [[cases]]
+            name = "adds"
+            input = [5, 7]
+            output = [12]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(spec.banned_instructions, vec!["MUL"]);
+        assert_eq!(spec.limits.max_steps, 100);
+        assert_eq!(spec.limits.max_instructions, None);
+        assert_eq!(spec.cases[0].input, vec![5, 7]);
+        assert!(toml::from_str::<GradingSpec>("[[cases]]\nname = \"x\"\noutpt = []\n").is_err());
+    }
+
+    #[test]
+    fn test_csv_report() {
+        let reports = vec![
+            SubmissionReport {
+                submission: PathBuf::from("alice.ram"),
+                report: Some(GradingReport {
+                    violations: vec![Violation::TooManyInstructions { count: 9, max: 8 }],
+                    cases: vec![CaseResult {
+                        name: "adds, twice".to_string(),
+                        status: CaseStatus::Passed,
+                        steps: 6,
+                        output: vec![12],
+                    }],
+                }),
+                error: None,
+            },
+            SubmissionReport {
+                submission: PathBuf::from("bob.ram"),
+                report: None,
+                error: Some("Unknown instruction".to_string()),
+            },
+        ];
+        assert_eq!(
+            to_csv(&reports),
+            "submission,case,status,steps,violations\n\
+             alice.ram,\"adds, twice\",passed,6,\"has 9 instructions, more than the 8 allowed\"\n\
+             bob.ram,,compile-error,,\n"
+        );
+    }
+}
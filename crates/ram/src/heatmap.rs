@@ -0,0 +1,189 @@
+//! Module for the HTML reports of execution heatmaps
+//!
+//! The report shows the source of a program with every line colored by how
+//! many times its instructions were executed, from untouched to the hottest
+//! line of the run, so hot loops stand out at a glance.
+//!
+//! Under the sections convention of the project manifest, the report also
+//! lists the sections the labels of the program start, with their metrics and
+//! how many steps of the run were spent in each.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use hir_analysis::{AnalysisPipeline, Section, SectionAnalysis};
+use miette::{IntoDiagnostic, Result, miette};
+use ram_vm::{Heatmap, bytecode};
+
+use crate::language;
+
+/// Write the HTML heatmap of a run of a program to `report_path`
+///
+/// # Parameters
+///
+/// * `program_path` - The program that ran, which must be a source file.
+/// * `counts` - The number of times each instruction was executed, by index.
+/// * `report_path` - The file to write the report to.
+pub fn write_report(program_path: &Path, counts: &[u64], report_path: &Path) -> Result<()> {
+    if program_path.extension().is_some_and(|ext| ext == bytecode::FILE_EXTENSION) {
+        return Err(miette!("A heatmap needs the source of the program, not a bytecode artifact"));
+    }
+    let source = std::fs::read_to_string(program_path).into_diagnostic()?;
+    let (_, body, _) = language::lower_program(&source);
+    let heatmap = Heatmap::new(&source, &body, counts);
+
+    let mut sections = Vec::new();
+    if language::diagnostic_config_for(program_path, false)?.sections() {
+        let mut pipeline = AnalysisPipeline::new();
+        pipeline.register::<SectionAnalysis>().map_err(|e| miette!("{}", e))?;
+        let context = pipeline.analyze(Arc::new(body)).map_err(|e| miette!("{}", e))?;
+        sections = context.get_result::<SectionAnalysis>().map_err(|e| miette!("{}", e))?.to_vec();
+    }
+
+    let name = program_path.file_name().unwrap_or(program_path.as_os_str());
+    let html = to_html(&name.to_string_lossy(), &source, &heatmap, &sections, counts);
+    std::fs::write(report_path, html).into_diagnostic()
+}
+
+/// Render the heatmap of a program as a standalone HTML page
+///
+/// `sections` are the measured sections of the program, listed before its
+/// source unless there are none, and `counts` the number of times each
+/// instruction was executed, by index.
+pub fn to_html(
+    name: &str,
+    source: &str,
+    heatmap: &Heatmap,
+    sections: &[Section],
+    counts: &[u64],
+) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Execution heatmap of {name}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; margin: 2em; }}\n\
+         table {{ border-collapse: collapse; font-family: monospace; }}\n\
+         td {{ padding: 0 0.75em; white-space: pre; }}\n\
+         td.line, td.count {{ color: #777; text-align: right; }}\n\
+         table.sections {{ font-family: sans-serif; margin-bottom: 2em; }}\n\
+         table.sections th {{ padding: 0 0.75em; text-align: left; }}\n\
+         </style>\n\
+         </head>\n\
+         <body>\n\
+         <h1>Execution heatmap of {name}</h1>\n",
+        name = escape(name),
+    );
+    match heatmap.hottest() {
+        Some(hottest) => {
+            let _ = writeln!(
+                html,
+                "<p>The hottest line is line {}, executed {} times.</p>",
+                hottest.line + 1,
+                hottest.count
+            );
+        }
+        None => html.push_str("<p>No instruction was executed.</p>\n"),
+    }
+
+    if !sections.is_empty() {
+        html.push_str(
+            "<table class=\"sections\">\n<tr><th>Section</th><th>Steps</th><th>Metrics</th></tr>\n",
+        );
+        for section in sections {
+            let steps = counts.get(section.instructions.clone()).unwrap_or_default();
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"count\">{}</td><td>{}</td></tr>",
+                escape(section.name.as_deref().unwrap_or("(start)")),
+                steps.iter().sum::<u64>(),
+                escape(&section.summary()),
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<table>\n");
+    for (line, text) in source.lines().enumerate() {
+        let line = line as u32;
+        let count = heatmap.count(line).map(|count| count.to_string()).unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr style=\"background-color: {}\"><td class=\"line\">{}</td>\
+             <td class=\"count\">{}</td><td>{}</td></tr>",
+            heat_color(heatmap.heat(line)),
+            line + 1,
+            count,
+            escape(text),
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Get the background color of a line, from transparent to red as it gets hotter
+fn heat_color(heat: f64) -> String {
+    format!("rgba(230, 60, 20, {:.2})", heat * 0.8)
+}
+
+/// Escape text to include it in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_diagnostics::DiagnosticConfig;
+    use ram_vm::LineHeat;
+
+    use super::*;
+
+    #[test]
+    fn test_heatmap_html() {
+        let heatmap = Heatmap {
+            lines: vec![LineHeat { line: 0, count: 1 }, LineHeat { line: 1, count: 4 }],
+            max_count: 4,
+        };
+        let html = to_html("a<b>.ram", "LOAD =1\nloop: JUMP loop\n# done\n", &heatmap, &[], &[]);
+
+        assert!(html.contains("<title>Execution heatmap of a&lt;b&gt;.ram</title>"));
+        assert!(html.contains("The hottest line is line 2, executed 4 times."));
+        assert!(html.contains(
+            "<tr style=\"background-color: rgba(230, 60, 20, 0.20)\"><td class=\"line\">1</td>\
+             <td class=\"count\">1</td><td>LOAD =1</td></tr>"
+        ));
+        assert!(html.contains("rgba(230, 60, 20, 0.80)\"><td class=\"line\">2</td>"));
+        assert!(html.contains(
+            "rgba(230, 60, 20, 0.00)\"><td class=\"line\">3</td><td class=\"count\"></td>"
+        ));
+        assert!(!html.contains("<table class=\"sections\">"));
+    }
+
+    #[test]
+    fn test_heatmap_sections() {
+        let source = "LOAD =1\nloop: STORE 2\nJUMP loop\n";
+        let config = DiagnosticConfig::new().with_sections(true);
+        let (_, _, _, context, _) = language::analyze_program(source, &config);
+        let sections = context.get_result::<SectionAnalysis>().unwrap();
+        let counts = [1, 3, 3];
+        let heatmap = Heatmap {
+            lines: vec![LineHeat { line: 0, count: 1 }, LineHeat { line: 1, count: 3 }],
+            max_count: 3,
+        };
+
+        let html = to_html("loop.ram", source, &heatmap, &sections, &counts);
+        assert!(
+            html.contains(
+                "<tr><td>(start)</td><td class=\"count\">1</td><td>1 instruction</td></tr>"
+            )
+        );
+        assert!(html.contains(
+            "<tr><td>loop</td><td class=\"count\">6</td><td>2 instructions, registers 2</td></tr>"
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+//! Module for inspecting the lowered structures of programs
+//!
+//! `ram hir` prints the HIR a program is lowered to, and `ram itemtree` the
+//! items of its file: the modules, imports and labels other files can refer
+//! to. Every part is printed with its id and the range of the source it was
+//! lowered from, one part per line, or as the versioned JSON documents of
+//! [`emit`](crate::emit) with `--json`.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use base_db::LineIndex;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+use crate::ast::SourceRange;
+use crate::emit::{self, HirDocument, ItemTreeDocument, Span};
+use crate::language;
+
+/// Print the HIR of a RAM program, as text or as a `ram.hir` JSON document
+pub fn print_hir(path: &Path, json: bool) -> Result<()> {
+    let source = read_program(path)?;
+    let (_program, body, _diagnostics) = language::lower_program(&source);
+    let document = emit::hir_document(&body);
+    if json {
+        println!("{}", emit::to_json(&document)?);
+    } else {
+        print!("{}", hir_text(&document, &LineIndex::new(&source)));
+    }
+    Ok(())
+}
+
+/// Print the items of a RAM program, as text or as a `ram.item-tree` JSON document
+pub fn print_item_tree(path: &Path, json: bool) -> Result<()> {
+    let source = read_program(path)?;
+    let document = emit::item_tree_document(&language::lower_item_tree(&source));
+    if json {
+        println!("{}", emit::to_json(&document)?);
+    } else {
+        print!("{}", item_tree_text(&document, &LineIndex::new(&source)));
+    }
+    Ok(())
+}
+
+fn read_program(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read file: {}", path.display()))
+}
+
+/// Print the HIR of a program, one instruction, label or expression per line
+pub fn hir_text(document: &HirDocument, line_index: &LineIndex) -> String {
+    let mut out = String::from("instructions:\n");
+    for instruction in &document.instructions {
+        let _ = write!(
+            out,
+            "  #{} {} ({}) {}",
+            instruction.id,
+            instruction.opcode,
+            instruction.kind,
+            range(line_index, instruction.span)
+        );
+        if let Some(operand) = instruction.operand {
+            let _ = write!(out, " operand=#{operand}");
+        }
+        if let Some(label) = &instruction.label {
+            let _ = write!(out, " label={label}");
+        }
+        out.push('\n');
+        write_docs(&mut out, &instruction.docs);
+    }
+
+    out.push_str("labels:\n");
+    for label in &document.labels {
+        let _ = write!(out, "  #{} {} {}", label.id, label.name, range(line_index, label.span));
+        if let Some(instruction) = label.instruction {
+            let _ = write!(out, " instruction=#{instruction}");
+        }
+        out.push('\n');
+        write_docs(&mut out, &label.docs);
+    }
+
+    out.push_str("exprs:\n");
+    for expr in &document.exprs {
+        // The fields of an expression are those of its kind in the JSON document
+        let fields = serde_json::to_value(&expr.kind).unwrap_or_default();
+        let kind = fields["kind"].as_str().unwrap_or_default();
+        let _ = write!(out, "  #{} {} {}", expr.id, kind, range(line_index, expr.span));
+        for (name, value) in fields.as_object().into_iter().flatten() {
+            if name != "kind" {
+                let _ = write!(out, " {name}={value}");
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Print the items of a file, one per line
+pub fn item_tree_text(document: &ItemTreeDocument, line_index: &LineIndex) -> String {
+    let mut out = String::new();
+    for item in &document.items {
+        let _ = writeln!(
+            out,
+            "#{} {} {} {}",
+            item.id,
+            item.kind,
+            item.name,
+            range(line_index, item.span)
+        );
+        write_docs(&mut out, &item.docs);
+    }
+    out
+}
+
+fn write_docs(out: &mut String, docs: &[String]) {
+    for doc in docs {
+        let _ = writeln!(out, "    doc: {}", doc.trim());
+    }
+}
+
+/// The lines and columns of a span, as `line:col-line:col`
+fn range(line_index: &LineIndex, span: Span) -> SourceRange {
+    SourceRange { start: line_index.line_col(span.start), end: line_index.line_col(span.end) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hir_text() {
+        let source = "loop: LOAD 1\nJUMP loop\n";
+        let (_program, body, _diagnostics) = language::lower_program(source);
+        let text = hir_text(&emit::hir_document(&body), &LineIndex::new(source));
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "instructions:");
+        assert!(lines[1].starts_with("  #0 LOAD (LOAD) 1:7-1:13 operand=#"));
+        assert!(lines[1].ends_with(" label=loop"));
+        assert!(lines[2].starts_with("  #1 JUMP (JUMP) 2:1-2:10"));
+        assert_eq!(lines[3], "labels:");
+        assert!(lines[4].starts_with("  #0 loop 1:1-"));
+        assert!(lines[4].ends_with(" instruction=#0"));
+        assert_eq!(lines[5], "exprs:");
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("memory-ref") && line.contains("mode=\"direct\""))
+        );
+    }
+
+    #[test]
+    fn test_item_tree_text_and_json() {
+        let source = "mod math\nuse io::*\n#* Where it starts\nstart: LOAD 1\n";
+        let document = emit::item_tree_document(&language::lower_item_tree(source));
+        let text = item_tree_text(&document, &LineIndex::new(source));
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines[0].starts_with("#0 module math 1:1-"));
+        assert!(lines[1].starts_with("#1 use io::* 2:1-"));
+        assert!(lines[2].starts_with("#2 label start 4:1-"));
+        assert_eq!(lines[3], "    doc: Where it starts");
+
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(json["schema"], "ram.item-tree");
+        assert_eq!(json["version"], emit::SCHEMA_VERSION);
+        assert_eq!(json["items"][2]["kind"], "label");
+        assert_eq!(json["items"][2]["name"], "start");
+        assert_eq!(json["items"][2]["span"]["start"], 38);
+    }
+}
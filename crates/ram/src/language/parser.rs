@@ -1,7 +1,15 @@
+use std::path::Path;
 use std::sync::Arc;
 
-use hir_analysis::{AnalysisContext, AnalysisPipeline};
+use hir_analysis::{AnalysisConfig, AnalysisContext, AnalysisPipeline};
+use miette::IntoDiagnostic;
+use ram_core::{InstructionRegistry, PluginAnalysisPass, PluginManager};
+use ram_diagnostics::sources::ANONYMOUS_FILE_NAME;
+use ram_diagnostics::{Diagnostic, DiagnosticConfig, SourceMap};
 use ram_parser::{AstNode, Program, SyntaxNode, build_tree, convert_errors, parse};
+use vfs::{Vfs, VfsPath};
+
+use crate::plugins::PluginAnalysis;
 
 /// Create a parser for RAM assembly language.
 ///
@@ -17,6 +25,23 @@ pub fn parser()
     |source| parse_program(source)
 }
 
+/// Load the diagnostic configuration that applies to a program.
+///
+/// The manifest is searched for in the directory of the program and its
+/// ancestors. Programs outside of a project use the default configuration.
+/// Strict mode is enabled if `strict_ram` is set, or if the manifest enables it.
+pub fn diagnostic_config_for(
+    program_path: &Path,
+    strict_ram: bool,
+) -> miette::Result<DiagnosticConfig> {
+    let dir = program_path.parent().unwrap_or_else(|| Path::new("."));
+    let config = match DiagnosticConfig::discover(dir) {
+        Some(manifest) => DiagnosticConfig::load(&manifest).into_diagnostic()?,
+        None => DiagnosticConfig::default(),
+    };
+    Ok(if strict_ram { config.with_strict_ram(true) } else { config })
+}
+
 /// Parse RAM assembly code into a syntax tree.
 ///
 /// This function uses the recursive descent parser to parse the input string
@@ -25,6 +50,28 @@ pub fn parser()
 pub fn parse_program(
     source: &str,
 ) -> (Program, hir::body::Body, AnalysisPipeline, AnalysisContext, Vec<miette::Error>) {
+    parse_program_with_config(ANONYMOUS_FILE_NAME, source, &DiagnosticConfig::default())
+}
+
+/// Parse RAM assembly code, reporting diagnostics according to `config`.
+///
+/// This behaves like [`parse_program`], but applies the configured severity
+/// levels and the suppression comments of `source` to the reported diagnostics.
+/// Diagnostics are reported against the file `file_name`.
+pub fn parse_program_with_config(
+    file_name: &str,
+    source: &str,
+    config: &DiagnosticConfig,
+) -> (Program, hir::body::Body, AnalysisPipeline, AnalysisContext, Vec<miette::Error>) {
+    let (program, body, pipeline, context, diagnostics) = analyze_program(source, config);
+    (program, body, pipeline, context, report_diagnostics(file_name, source, diagnostics))
+}
+
+/// Parse RAM assembly code and lower it to HIR without analyzing it.
+///
+/// Returns the diagnostics found while parsing and lowering, before any
+/// severity configuration is applied.
+pub fn lower_program(source: &str) -> (Program, hir::body::Body, Vec<Diagnostic>) {
     // Parse the source text using our recursive descent parser
     let (events, errors) = parse(source);
     let mut errors = errors;
@@ -38,31 +85,196 @@ pub fn parse_program(
 
     // Create an ItemTree for the program
     let item_tree = hir_def::item_tree::ItemTree::lower(&program, file_id);
+    errors.extend(item_tree.diagnostics.iter().map(hir_def::ItemTreeDiagnostic::to_diagnostic));
 
     // Lower the program to HIR
     let body = hir::lower::lower_program(&program, hir::ids::DefId::default(), file_id, &item_tree)
         .unwrap();
 
+    (program, body, errors)
+}
+
+/// Parse RAM assembly code and lower its items to an ItemTree.
+///
+/// The diagnostics found while parsing are left out, as [`lower_program`]
+/// returns them.
+pub fn lower_item_tree(source: &str) -> hir_def::item_tree::ItemTree {
+    lower_item_tree_of(source, base_db::input::FileId(0))
+}
+
+fn lower_item_tree_of(
+    source: &str,
+    file_id: base_db::input::FileId,
+) -> hir_def::item_tree::ItemTree {
+    let (events, _errors) = parse(source);
+    let (tree, cache) = build_tree(events);
+    let syntax_node = SyntaxNode::new_root_with_resolver(tree, cache);
+    let program = Program::cast(syntax_node).expect("Failed to cast root node to Program");
+    hir_def::item_tree::ItemTree::lower(&program, file_id)
+}
+
+/// Find the labels of a program that are also defined in the modules it
+/// imports with a wildcard.
+///
+/// A module is loaded through a [`Vfs`] from the `.ram` file named after it in
+/// the directory of the program, and modules without a file are skipped. The
+/// configured severity levels and the suppression comments of `source` are
+/// applied. Returns the diagnostics with the program and the modules that were
+/// read, to render them with [`report_diagnostics_in`].
+pub fn module_diagnostics(
+    path: &Path,
+    source: &str,
+    config: &DiagnosticConfig,
+) -> (Vec<Diagnostic>, SourceMap) {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut vfs = Vfs::new();
+    let program_file = vfs.set_file_contents(VfsPath::from(path), Some(source.to_string()));
+    let item_tree = lower_item_tree_of(source, program_file);
+
+    let mut sources = SourceMap::single(path.display().to_string(), source);
+    let mut diagnostics = Vec::new();
+    for module in item_tree.wildcard_imports() {
+        // A program importing itself defines no label twice
+        let Some(module_file) = vfs
+            .load_file(&dir.join(format!("{module}.ram")))
+            .filter(|module_file| *module_file != program_file)
+        else {
+            continue;
+        };
+        let (Some(text), Some(module_path)) =
+            (vfs.file_text(module_file), vfs.file_path(module_file))
+        else {
+            continue;
+        };
+        let module_name = module_path.to_string();
+        let module_tree = lower_item_tree_of(&text, module_file);
+        diagnostics.extend(item_tree.duplicate_labels_in(&module_tree).iter().map(|diagnostic| {
+            diagnostic.to_diagnostic_with_files(|file_id| {
+                (file_id == module_file).then(|| module_name.clone())
+            })
+        }));
+        sources.add(module_name, text.to_string());
+    }
+    (config.apply(source, diagnostics), sources)
+}
+
+/// Parse and analyze RAM assembly code without rendering its diagnostics.
+///
+/// The configured severity levels and the suppression comments of `source`
+/// are applied to the returned diagnostics, and the program is held to the
+/// classic RAM model if the configuration enables strict mode. Only the
+/// standard instructions are available to the program.
+pub fn analyze_program(
+    source: &str,
+    config: &DiagnosticConfig,
+) -> (Program, hir::body::Body, AnalysisPipeline, AnalysisContext, Vec<Diagnostic>) {
+    analyze_program_with_instructions(source, config, Arc::new(ram_core::standard_instructions()))
+}
+
+/// Parse and analyze RAM assembly code using the given instructions.
+///
+/// This behaves like [`analyze_program`], but validates the instructions of
+/// the program against `instructions`, which may include those of plugins.
+pub fn analyze_program_with_instructions(
+    source: &str,
+    config: &DiagnosticConfig,
+    instructions: Arc<InstructionRegistry>,
+) -> (Program, hir::body::Body, AnalysisPipeline, AnalysisContext, Vec<Diagnostic>) {
+    analyze(source, config, instructions, Vec::new())
+}
+
+/// Parse and analyze RAM assembly code with the instructions and analysis
+/// passes of plugins.
+///
+/// This behaves like [`analyze_program`], but the program may use the
+/// instructions of `plugins`, and the diagnostics of their
+/// [analysis passes](crate::plugins) are reported with the others.
+pub fn analyze_program_with_plugins(
+    source: &str,
+    config: &DiagnosticConfig,
+    plugins: &PluginManager,
+) -> (Program, hir::body::Body, AnalysisPipeline, AnalysisContext, Vec<Diagnostic>) {
+    let mut instructions = ram_core::standard_instructions();
+    plugins.register_all(&mut instructions);
+    analyze(source, config, Arc::new(instructions), plugins.analysis_passes())
+}
+
+/// Parse and analyze RAM assembly code with the given instructions and
+/// analysis passes of plugins.
+fn analyze(
+    source: &str,
+    config: &DiagnosticConfig,
+    instructions: Arc<InstructionRegistry>,
+    plugin_passes: Vec<Arc<dyn PluginAnalysisPass>>,
+) -> (Program, hir::body::Body, AnalysisPipeline, AnalysisContext, Vec<Diagnostic>) {
+    let (program, body, mut errors) = lower_program(source);
+
     let mut pipeline = AnalysisPipeline::new();
+    pipeline.set_instruction_registry(instructions);
 
     pipeline.register::<hir_analysis::analyzers::InstructionValidationAnalysis>().ok();
     pipeline.register::<hir_analysis::analyzers::ControlFlowAnalysis>().ok();
+    pipeline.register::<hir_analysis::analyzers::DominanceAnalysis>().ok();
     pipeline.register::<hir_analysis::analyzers::DataFlowAnalysis>().ok();
+    pipeline.register::<hir_analysis::analyzers::SsaAnalysis>().ok();
     pipeline.register::<hir_analysis::analyzers::ConstantPropagationAnalysis>().ok();
+    pipeline.register::<hir_analysis::analyzers::AliasAnalysis>().ok();
     pipeline.register::<hir_analysis::analyzers::ControlFlowOptimizer>().ok();
+    pipeline.register::<hir_analysis::analyzers::UnusedLabelAnalysis>().ok();
+    pipeline.register::<hir_analysis::analyzers::SchedulingAnalysis>().ok();
+    pipeline.register::<hir_analysis::analyzers::IoContractAnalysis>().ok();
+    if config.strict_ram() {
+        pipeline.register::<hir_analysis::analyzers::StrictRamAnalysis>().ok();
+    }
+    if config.termination() {
+        pipeline.register::<hir_analysis::analyzers::TerminationAnalysis>().ok();
+    }
+    if config.sections() {
+        pipeline.register::<hir_analysis::analyzers::SectionAnalysis>().ok();
+    }
+    if !plugin_passes.is_empty() {
+        match PluginAnalysis::new(&pipeline, plugin_passes) {
+            Ok(pass) => {
+                pipeline.register_pass(pass).ok();
+            }
+            Err(err) => {
+                let range = program.syntax().text_range();
+                errors.push(Diagnostic::error(
+                    err.to_string(),
+                    "Check the analysis passes of the plugins in use".to_string(),
+                    range.start().into()..range.end().into(),
+                ));
+            }
+        }
+    }
+    if let Err(err) = pipeline.set_config(AnalysisConfig::from(config)) {
+        let range = program.syntax().text_range();
+        errors.push(Diagnostic::error(
+            err.to_string(),
+            "Check the [analysis.passes] section of ram.toml and the --passes option".to_string(),
+            range.start().into()..range.end().into(),
+        ));
+    }
 
     // Run the analysis pipeline
     let analysis_context = match pipeline.analyze(Arc::new(body.clone())) {
         Ok(context) => {
-            // Add any diagnostics from the analysis to our errors
-            errors.extend(context.diagnostics().clone());
+            // Add any diagnostics from the analysis to our errors, skipping the
+            // ones the parser already reported at the same place
+            let analysis_errors = context
+                .diagnostics()
+                .clone()
+                .into_iter()
+                .filter(|diagnostic| !errors.iter().any(|error| error.is_duplicate_of(diagnostic)))
+                .collect::<Vec<_>>();
+            errors.extend(analysis_errors);
             context
         }
         Err(err) => {
             // If analysis fails, add a diagnostic about it
             let range = program.syntax().text_range();
             let span = range.start().into()..range.end().into();
-            errors.push(ram_parser::Diagnostic::error(
+            errors.push(Diagnostic::error(
                 format!("Analysis failed: {}", err),
                 "Check your program for semantic errors".to_string(),
                 span,
@@ -72,15 +284,92 @@ pub fn parse_program(
         }
     };
 
-    // Convert the errors into miette errors
-    let miette_errors = if errors.is_empty() {
+    // Apply the configured severities and suppression comments
+    let errors = config.apply(source, errors);
+
+    (program, body, pipeline, analysis_context, errors)
+}
+
+/// Convert diagnostics into errors reported against the file `file_name`.
+pub fn report_diagnostics(
+    file_name: &str,
+    source: &str,
+    diagnostics: Vec<Diagnostic>,
+) -> Vec<miette::Error> {
+    report_diagnostics_in(&SourceMap::single(file_name, source), diagnostics)
+}
+
+/// Convert diagnostics into errors reported against the main file of
+/// `sources`, with the spans in its other files shown from their text.
+pub fn report_diagnostics_in(
+    sources: &SourceMap,
+    diagnostics: Vec<Diagnostic>,
+) -> Vec<miette::Error> {
+    if diagnostics.is_empty() {
         // No errors, return an empty vector
         Vec::new()
     } else {
         // Convert the errors to miette errors
-        let parser_error = convert_errors(source, errors);
+        let parser_error = convert_errors(sources, diagnostics);
         vec![miette::Error::new(parser_error)]
-    };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hir_analysis::export::{ExportFormat, ExportOptions};
+
+    use super::*;
+    use crate::cli::EmitFormat;
+    use crate::emit::emit;
+
+    /// Everything `ram validate` prints about a program
+    fn validate(source: &str) -> (String, String, String) {
+        let (_, body, pipeline, context, diagnostics) =
+            analyze_program(source, &DiagnosticConfig::default());
+        let pipeline =
+            pipeline.export_dependency_graph(ExportFormat::Json, &ExportOptions::default());
+        let dfg = emit(EmitFormat::DfgJson, source, &body, &context).unwrap();
+        (format!("{diagnostics:?}"), pipeline, dfg)
+    }
+
+    #[test]
+    fn test_validate_is_deterministic() {
+        // Many uninitialized reads and unused writes, so that any dependence
+        // on the order of a hash map shows up in the diagnostics
+        let source = (0..32)
+            .map(|address| format!("LOAD {address}\nSTORE {}\n", address + 100))
+            .chain(["JUMP missing\nunused: HALT\n".to_string()])
+            .collect::<String>();
+
+        let expected = validate(&source);
+        assert!(expected.0.contains("missing"));
+        for _ in 0..8 {
+            assert_eq!(validate(&source), expected);
+        }
+    }
+
+    #[test]
+    fn test_module_diagnostics() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("math.ram"), "double: LOAD 1\nHALT\n").unwrap();
+        let path = dir.path().join("main.ram");
+        let source = "use math::*\nuse io::*\ndouble: LOAD 2\nHALT\n";
+
+        // The missing module io is skipped
+        let (diagnostics, _sources) =
+            module_diagnostics(&path, source, &DiagnosticConfig::default());
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("Expected a single diagnostic, got {diagnostics:?}");
+        };
+        assert_eq!(diagnostic.code.as_deref(), Some(ram_diagnostics::codes::DUPLICATE_LABEL.code));
+        assert_eq!(
+            diagnostic.external_spans[0].file,
+            dir.path().join("math.ram").display().to_string()
+        );
 
-    (program, body, pipeline, analysis_context, miette_errors)
+        // Suppression comments apply to them like to any other diagnostic
+        let source = "use math::*\ndouble: LOAD 2 # ram: allow(E003)\nHALT\n";
+        assert!(module_diagnostics(&path, source, &DiagnosticConfig::default()).0.is_empty());
+    }
 }
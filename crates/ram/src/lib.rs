@@ -3,6 +3,7 @@ use std::io::Write;
 use std::process::ExitCode;
 
 use anstream::println;
+use base_db::LineIndex;
 use clap::{CommandFactory, Parser};
 use human_panic::{Metadata, setup_panic};
 use miette::*;
@@ -11,17 +12,39 @@ use serde::Serialize;
 use shadow_rs::shadow;
 use tracing::{debug, error};
 
-use crate::cli::{Cli, Command, VersionFormat};
+use crate::cache::{Cache, CacheKey};
+use crate::cli::{CacheCommand, Cli, Command, SelfCommand, VersionFormat};
 use crate::color::ColorChoice;
 use crate::tracing_setup::TracingControls;
 pub use crate::tracing_setup::{init_tracing, init_tracing_from_cli};
 pub use crate::version::*;
 
+pub mod artifact;
+pub mod ast;
+pub mod cache;
+pub mod check;
+pub mod chrome_trace;
 pub mod cli;
 pub mod color;
+pub mod completions;
+pub mod debug;
+pub mod diff;
+pub mod diff_trace;
+pub mod emit;
+pub mod equiv;
 pub mod error;
+pub mod explain;
+pub mod grade;
+pub mod heatmap;
+pub mod inspect;
 pub mod language;
+pub mod manpages;
+pub mod plugins;
+pub mod record;
 pub mod run;
+pub mod self_update;
+pub mod similarity;
+pub mod stats;
 pub mod tracing_setup;
 pub mod version;
 
@@ -56,6 +79,9 @@ where
             .support("- Open an issue on GitHub: https://github.com/hadronomy/ram/issues/new")
     );
 
+    // Answer the completion requests of shells before printing anything
+    completions::complete_from_env();
+
     let tracing_controls = init_tracing();
 
     let cli = match Cli::try_parse_from(args) {
@@ -104,7 +130,12 @@ async fn handle_command(cli: Cli, tracing_controls: &TracingControls) -> Result<
         return Ok(ExitCode::SUCCESS);
     }
 
-    handle_command_iner(cli.top_level.global_args, cli.command, tracing_controls).await
+    let result =
+        handle_command_iner(cli.top_level.global_args, cli.command, tracing_controls).await;
+    if let Err(err) = tracing_controls.finish_trace() {
+        eprintln!("Failed to write the trace: {}", err);
+    }
+    result
 }
 
 async fn handle_command_iner(
@@ -117,17 +148,95 @@ async fn handle_command_iner(
     // Create a color config from user preference
     let color_config = color::ColorConfig::new(global_args.color.unwrap_or(ColorChoice::Auto));
 
+    // Locate the analysis cache, unless it is disabled
+    let cache_dir =
+        global_args.cache_dir.clone().unwrap_or_else(|| cache::DEFAULT_CACHE_DIR.into());
+    let cache = (!global_args.no_cache).then(|| Cache::new(&cache_dir));
+
     match *command {
         // execute help
         Command::Help(_) => {
             Cli::command().print_help().into_diagnostic()?;
             Ok::<_, Error>(ExitCode::SUCCESS)
         }
-        Command::Validate { program, ast, reprint, show_pipeline, show_cfg, show_hir } => {
-            let src = std::fs::read_to_string(program.clone())
+        Command::Ast { program, output_format, range, kinds } => {
+            let selection = ast::AstSelection { range, kinds };
+            ast::print_ast(&program, &selection, output_format)
+                .map(|printed| if printed > 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+                .map_err(Error::RunError)
+        }
+        Command::Hir { program, json } => {
+            inspect::print_hir(&program, json).map(|_| ExitCode::SUCCESS).map_err(Error::RunError)
+        }
+        Command::ItemTree { program, json } => inspect::print_item_tree(&program, json)
+            .map(|_| ExitCode::SUCCESS)
+            .map_err(Error::RunError),
+        Command::Validate {
+            program: path,
+            ast,
+            reprint,
+            canonical,
+            show_pipeline,
+            show_cfg,
+            interprocedural,
+            reachable_only,
+            collapse_blocks,
+            hide_trivia,
+            edge_color,
+            theme,
+            rank_dir,
+            show_dfg,
+            show_constants,
+            show_dominators,
+            show_memory,
+            strict_ram,
+            passes,
+            emit,
+        } => {
+            let src = std::fs::read_to_string(&path)
                 .into_diagnostic()
-                .wrap_err(format!("Failed to read file: {}", program))?;
-            let (program, body, pipeline, context, errors) = language::parser()(&src);
+                .wrap_err(format!("Failed to read file: {}", path))?;
+            let mut config =
+                language::diagnostic_config_for(std::path::Path::new(&path), strict_ram)?;
+            if !passes.is_empty() {
+                config = config.with_enabled_passes(passes);
+            }
+            let key = CacheKey::new(&src, &config, &ram_core::standard_instructions());
+            // Modules can change without the program, so these are never cached
+            let (module_diagnostics, sources) =
+                language::module_diagnostics(std::path::Path::new(&path), &src, &config);
+
+            // Reporting the diagnostics does not need the analyzed program
+            let inspect = ast
+                || reprint
+                || canonical
+                || show_pipeline
+                || show_cfg
+                || show_dfg
+                || show_constants
+                || show_dominators
+                || show_memory
+                || !emit.is_empty();
+            if !inspect && let Some(diagnostics) = cache.as_ref().and_then(|cache| cache.get(key)) {
+                debug!("Using cached diagnostics for {}", path);
+                let diagnostics = diagnostics.into_iter().chain(module_diagnostics).collect();
+                for error in language::report_diagnostics_in(&sources, diagnostics) {
+                    eprintln!("{:?}", error);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let (program, body, pipeline, context, diagnostics) =
+                language::analyze_program(&src, &config);
+            if let Some(cache) = &cache
+                && let Err(err) = cache.put(key, &diagnostics)
+            {
+                debug!("Failed to cache diagnostics for {}: {}", path, err);
+            }
+            let errors = language::report_diagnostics_in(
+                &sources,
+                diagnostics.into_iter().chain(module_diagnostics).collect(),
+            );
 
             // Report any errors
             for error in errors {
@@ -144,41 +253,337 @@ async fn handle_command_iner(
                 println!("{program}");
             }
 
-            if show_hir {
-                println!("{body:#?}");
+            if canonical {
+                print!("{}", hir::canonical::print_canonical(&body));
+            }
+
+            if show_memory {
+                println!("{}", context.memory_stats());
             }
 
+            for format in emit {
+                println!("{}", emit::emit(format, &src, &body, &context)?);
+            }
+
+            let export_options = hir_analysis::ExportOptions {
+                interprocedural,
+                reachable_only,
+                collapse_blocks,
+                hide_trivia,
+                theme: theme.map(Into::into),
+                edge_colors: edge_color.into_iter().collect(),
+                rank_direction: rank_dir.into(),
+                ..Default::default()
+            };
+
             if show_cfg {
                 // Get the control flow graph from the context
                 if let Ok(cfg) =
                     context.get_result::<hir_analysis::analyzers::ControlFlowAnalysis>()
                 {
                     // Convert the CFG to a mermaid diagram with detailed instruction information
-                    let mermaid = cfg.to_mermaid_with_context(&context);
+                    let mermaid = cfg.export(
+                        hir_analysis::ExportFormat::Mermaid,
+                        &export_options,
+                        Some(&context),
+                    );
                     open_mermaid(mermaid)?;
                 } else {
                     error!("Failed to get control flow graph from context");
                 }
             }
 
+            if show_dfg {
+                if let Ok(dfg) = context.get_result::<hir_analysis::DataFlowAnalysis>() {
+                    open_mermaid(dfg.to_mermaid_with_context(&context))?;
+                } else {
+                    error!("Failed to get data flow graph from context");
+                }
+            }
+
+            if show_constants {
+                if let Ok(constants) =
+                    context.get_result::<hir_analysis::ConstantPropagationAnalysis>()
+                {
+                    print!("{}", constants.to_table(&body, &LineIndex::new(&src)));
+                } else {
+                    error!("Failed to get constant propagation results from context");
+                }
+            }
+
+            if show_dominators {
+                if let (Ok(cfg), Ok(info)) = (
+                    context.get_result::<hir_analysis::analyzers::ControlFlowAnalysis>(),
+                    context.get_result::<hir_analysis::analyzers::DominanceAnalysis>(),
+                ) {
+                    let mermaid = info.dominators.export(
+                        hir_analysis::ExportFormat::Mermaid,
+                        &cfg,
+                        Some(&context),
+                    );
+                    open_mermaid(mermaid)?;
+                } else {
+                    error!("Failed to get the dominator tree from context");
+                }
+            }
+
             if show_pipeline {
                 open_mermaid(pipeline.export_dependency_graph(
                     hir_analysis::ExportFormat::Mermaid,
-                    &Default::default(),
+                    &export_options,
                 ))?;
             }
 
             Ok::<_, Error>(ExitCode::SUCCESS)
         }
-        Command::Run { program, input, memory: _ } => {
+        Command::Run {
+            program,
+            input,
+            memory,
+            seed,
+            instruction_set,
+            max_steps,
+            save_state,
+            resume,
+            strict_ram,
+            heatmap,
+            output_format,
+            fuse,
+            arithmetic,
+            record,
+            replay,
+        } => {
+            let options = run::RunOptions {
+                seed,
+                instruction_set,
+                max_steps,
+                save_state,
+                strict_ram,
+                heatmap,
+                output_format,
+                fuse,
+                arithmetic: arithmetic.into(),
+                record,
+            };
+            let input =
+                input.as_deref().map(run::parse_input).transpose().map_err(Error::RunError)?;
+            let memory = memory
+                .as_deref()
+                .map(run::parse_memory)
+                .transpose()
+                .map_err(Error::RunError)?
+                .unwrap_or_default();
+            let result = match (resume, replay, program) {
+                (Some(state_path), _, _) => {
+                    run::resume_program(&state_path, &options, cache.as_ref())
+                }
+                (None, Some(recording_path), _) => {
+                    run::replay_program(&recording_path, &options, cache.as_ref())
+                }
+                (None, None, Some(program)) => {
+                    let program_path = std::path::Path::new(&program);
+                    run::run_program(program_path, input, memory, &options, cache.as_ref())
+                }
+                (None, None, None) => {
+                    Err(miette!("A program is required unless resuming or replaying a run"))
+                }
+            };
+            result.map(|outcome| outcome.exit_code()).map_err(Error::RunError)
+        }
+        Command::ExplainRun { program, input, instruction_set, max_steps, markdown } => {
+            let input = input
+                .as_deref()
+                .map(run::parse_input)
+                .transpose()
+                .map_err(Error::RunError)?
+                .unwrap_or_default();
+            let options = explain::ExplainOptions { instruction_set, max_steps, markdown };
+            explain::explain_run(std::path::Path::new(&program), input, &options, cache.as_ref())
+                .map(|_| ExitCode::SUCCESS)
+                .map_err(Error::RunError)
+        }
+        Command::Debug { program, input, memory, instruction_set, watch, timeout, source_map } => {
+            let input = input
+                .as_deref()
+                .map(run::parse_input)
+                .transpose()
+                .map_err(Error::RunError)?
+                .unwrap_or_default();
+            let memory = memory
+                .as_deref()
+                .map(run::parse_memory)
+                .transpose()
+                .map_err(Error::RunError)?
+                .unwrap_or_default();
+            let timeout = timeout
+                .map(std::time::Duration::try_from_secs_f64)
+                .transpose()
+                .map_err(|e| Error::RunError(miette!("Invalid timeout: {}", e)))?;
+            let options =
+                debug::DebugOptions { instruction_set, watchpoints: watch, timeout, source_map };
+            debug::debug_run(
+                std::path::Path::new(&program),
+                input,
+                memory,
+                &options,
+                cache.as_ref(),
+            )
+            .map(|_| ExitCode::SUCCESS)
+            .map_err(Error::RunError)
+        }
+        Command::Check { paths, strict_ram, passes, jobs } => {
+            let options = check::CheckOptions { strict_ram, passes, jobs };
+            check::report_check(&paths, &options, cache.as_ref())
+                .map(|failed| if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+                .map_err(Error::RunError)
+        }
+        Command::Grade { spec, submissions, instruction_set, output_format, timeout } => {
+            grade::grade_submissions(
+                &spec,
+                &submissions,
+                &instruction_set,
+                output_format,
+                timeout.map(std::time::Duration::from_secs),
+                cache.as_ref(),
+            )
+            .map(|_| ExitCode::SUCCESS)
+            .map_err(Error::RunError)
+        }
+        Command::Equiv { left, right, inputs, random, seed, instruction_set, max_steps } => {
+            let domain = equiv::parse_domain(&inputs).map_err(Error::RunError)?;
+            let options = equiv::EquivOptions { instruction_set, max_steps, random, seed };
+            equiv::check_programs(&left, &right, &domain, &options, cache.as_ref())
+                .map(
+                    |report| {
+                        if report.equivalent() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+                    },
+                )
+                .map_err(Error::RunError)
+        }
+        Command::Diff { left, right, output_format } => {
+            diff::diff_programs(&left, &right, output_format)
+                .map(|diff| if diff.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+                .map_err(Error::RunError)
+        }
+        Command::DiffTrace { left, right, input, instruction_set, max_steps } => {
+            let input = input
+                .as_deref()
+                .map(run::parse_input)
+                .transpose()
+                .map_err(Error::RunError)?
+                .unwrap_or_default();
+            let options = diff_trace::DiffTraceOptions { instruction_set, max_steps };
+            let mut out = color_config.stdout();
+            diff_trace::diff_programs(&left, &right, input, &options, cache.as_ref(), &mut out)
+                .map(|diff| if diff.identical() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+                .map_err(Error::RunError)
+        }
+        Command::Similarity { submissions, ngram, threshold, output_format } => {
+            similarity::report_similarity(&submissions, ngram, threshold, output_format)
+                .map(|_| ExitCode::SUCCESS)
+                .map_err(Error::RunError)
+        }
+        Command::Stats { dir, output_format } => stats::report_stats(&dir, output_format)
+            .map(|_| ExitCode::SUCCESS)
+            .map_err(Error::RunError),
+        Command::Build { program, output, strict_ram } => {
             let program_path = std::path::Path::new(&program);
-            run::run_program(program_path, input, None)
+            let output = artifact::build_artifact(
+                program_path,
+                output.as_deref(),
+                strict_ram,
+                cache.as_ref(),
+            )
+            .map_err(Error::RunError)?;
+            let mut out = color_config.stdout();
+            writeln!(out, "Wrote {}", output.display()).into_diagnostic()?;
+            writeln!(out, "Wrote {}", artifact::source_map_path(&output).display())
+                .into_diagnostic()?;
+            Ok::<_, Error>(ExitCode::SUCCESS)
+        }
+        Command::VerifyArtifact { artifact: path, source } => {
+            artifact::verify_artifact(&path, source.as_deref())
                 .map(|_| ExitCode::SUCCESS)
                 .map_err(Error::RunError)
         }
-        Command::Server => {
+        Command::Completions { shell } => {
+            completions::write_completions(shell, &mut std::io::stdout());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Manpages { dir } => {
+            let paths = manpages::write_manpages(&dir).map_err(Error::RunError)?;
+            println!("Wrote {} manual pages to {}", paths.len(), dir.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Explain { code } => {
+            let code = ram_diagnostics::codes::lookup(&code)
+                .ok_or_else(|| Error::CommandError(format!("Unknown diagnostic code '{code}'")))?;
+
+            // The stream strips the styling when colors are disabled
+            let heading = owo_colors::Style::new().bold();
+            let mut out = color_config.stdout();
+            writeln!(out, "{}", heading.style(format!("{}: {}", code.code, code.title)))
+                .into_diagnostic()?;
+            writeln!(out).into_diagnostic()?;
+            writeln!(out, "{}", code.explanation.trim_end()).into_diagnostic()?;
+            writeln!(out).into_diagnostic()?;
+            writeln!(out, "See {} for more details.", code.url()).into_diagnostic()?;
+            Ok::<_, Error>(ExitCode::SUCCESS)
+        }
+        Command::Cache { command } => {
+            let cache = Cache::new(&cache_dir);
+            let mut out = color_config.stdout();
+            match command {
+                CacheCommand::Clean => {
+                    let stats = cache.clean().into_diagnostic()?;
+                    writeln!(
+                        out,
+                        "Removed {} cache entries ({} bytes) from {}",
+                        stats.entries,
+                        stats.size,
+                        cache.dir().display()
+                    )
+                    .into_diagnostic()?;
+                }
+                CacheCommand::Stats { paths } => {
+                    let stats = cache.stats().into_diagnostic()?;
+                    writeln!(out, "Cache directory: {}", cache.dir().display())
+                        .into_diagnostic()?;
+                    writeln!(out, "Entries: {}", stats.entries).into_diagnostic()?;
+                    writeln!(out, "Size: {} bytes", stats.size).into_diagnostic()?;
+
+                    if !paths.is_empty() {
+                        let programs = check::find_programs(&paths)?;
+                        let config = match programs.first() {
+                            Some(program) => language::diagnostic_config_for(program, false)?,
+                            None => ram_diagnostics::DiagnosticConfig::default(),
+                        };
+                        let lru = cache::lru_config(&config).into_diagnostic()?;
+                        writeln!(out).into_diagnostic()?;
+                        writeln!(out, "Queries of {} programs:", programs.len())
+                            .into_diagnostic()?;
+                        for stats in cache::database_stats(&programs, lru)? {
+                            writeln!(out, "  {stats}").into_diagnostic()?;
+                        }
+                    }
+                }
+            }
+            Ok::<_, Error>(ExitCode::SUCCESS)
+        }
+        Command::Self_ { command } => match command {
+            SelfCommand::Update { channel, dry_run } => {
+                self_update::self_update(channel, dry_run).map_err(Error::RunError)?;
+                Ok(ExitCode::SUCCESS)
+            }
+        },
+        Command::Server { tcp, websocket } => {
             tracing_controls.set_stdout_enabled(false);
-            ram_lsp::run()
+            let transport = match tcp {
+                Some(port) if websocket => ram_lsp::Transport::WebSocket(port),
+                Some(port) => ram_lsp::Transport::Tcp(port),
+                None => ram_lsp::Transport::Stdio,
+            };
+            ram_lsp::run(transport)
                 .await
                 .wrap_err("Failed to run LSP server")
                 .map(|_| ExitCode::SUCCESS)
@@ -201,7 +606,7 @@ async fn handle_command_iner(
                 VersionFormat::Json => {
                     #[cfg(feature = "serde")]
                     {
-                        let json = serde_json::to_string_pretty(&Version::new()).unwrap();
+                        let json = serde_json::to_string_pretty(&BuildInfo::new()).unwrap();
                         writeln!(out, "{json}").into_diagnostic()?;
                     }
                     #[cfg(not(feature = "serde"))]
@@ -264,4 +669,4 @@ fn open_mermaid(mermaid_str: impl Into<String>) -> Result<(), ErrReport> {
     debug!("Opening URL: {}", url);
     open::that(url).into_diagnostic()?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,57 @@
+//! Module for the manual pages of the command line
+//!
+//! One page is generated for `ram` and one for each of its commands, such as
+//! `ram-run.1`, from the same clap definitions the help messages come from.
+
+use std::path::{Path, PathBuf};
+
+use clap::CommandFactory;
+use clap_mangen::Man;
+use miette::{IntoDiagnostic, Result};
+
+use crate::cli::Cli;
+
+/// Write the manual pages of `ram` and its commands to a directory
+///
+/// The directory is created if it does not exist. Returns the written files,
+/// the page of `ram` last.
+pub fn write_manpages(dir: &Path) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir).into_diagnostic()?;
+    let mut command = Cli::command();
+    command.build();
+
+    let mut paths = Vec::new();
+    write_pages(command, dir, &mut paths)?;
+    Ok(paths)
+}
+
+/// Write the pages of a command and its subcommands, the subcommands first
+fn write_pages(command: clap::Command, dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for subcommand in command.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()) {
+        write_pages(subcommand.clone(), dir, paths)?;
+    }
+    paths.push(Man::new(command).generate_to(dir).into_diagnostic()?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_manpages() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = write_manpages(&dir.path().join("man1")).unwrap();
+
+        let names = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names.last().map(String::as_str), Some("ram.1"));
+        assert!(names.contains(&"ram-run.1".to_string()));
+        assert!(names.contains(&"ram-cache-clean.1".to_string()));
+
+        let page = std::fs::read_to_string(dir.path().join("man1/ram-run.1")).unwrap();
+        assert!(page.contains("\\-\\-max\\-steps"));
+    }
+}
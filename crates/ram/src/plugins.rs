@@ -0,0 +1,298 @@
+//! Module for the analysis passes contributed by plugins
+//!
+//! The [passes of plugins](PluginAnalysisPass) all run inside one
+//! [`PluginAnalysis`] pass of the pipeline, after the built-in passes any of
+//! them depends on. Each one receives the program as the `ram.hir` document
+//! of `ram validate --emit hir-json`, and the diagnostics it returns are
+//! reported with those of the built-in passes.
+//!
+//! A pass of a plugin is disabled by its ID in the `[analysis.passes]`
+//! section of the manifest, and `ram validate --passes` selects all of them
+//! with the ID `plugins`.
+
+use std::any::TypeId;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use hir_analysis::{AnalysisContext, AnalysisPass, AnalysisPipeline};
+use miette::{MietteDiagnostic, Result, miette};
+use ram_core::{PluginAnalysisPass, PluginDiagnostic, PluginSeverity};
+use ram_diagnostics::Diagnostic;
+
+use crate::emit::hir_document;
+
+/// The ID of the pass running the passes of plugins
+pub const PLUGIN_ANALYSIS_ID: &str = "plugins";
+
+/// The pass running the analysis passes of plugins
+pub struct PluginAnalysis {
+    /// The passes, each after the passes of plugins it depends on
+    passes: Vec<Arc<dyn PluginAnalysisPass>>,
+    /// The built-in passes any of the passes depends on
+    dependencies: Vec<TypeId>,
+}
+
+impl PluginAnalysis {
+    /// Prepare the passes of plugins to run in a pipeline
+    ///
+    /// The built-in passes they depend on must already be registered with
+    /// `pipeline`. Fails if a pass depends on a pass that is neither
+    /// registered nor contributed by a plugin, if two passes have the same
+    /// ID, or if passes depend on each other in a cycle.
+    pub fn new(
+        pipeline: &AnalysisPipeline,
+        passes: Vec<Arc<dyn PluginAnalysisPass>>,
+    ) -> Result<Self> {
+        let mut names = HashSet::new();
+        for pass in &passes {
+            if pipeline.pass_type_id(pass.name()).is_some() || !names.insert(pass.name()) {
+                return Err(miette!("more than one analysis pass has the ID '{}'", pass.name()));
+            }
+        }
+
+        let mut dependencies = Vec::new();
+        for pass in &passes {
+            for dependency in pass.dependencies() {
+                if names.contains(dependency.as_str()) {
+                    continue;
+                }
+                let type_id = pipeline.pass_type_id(&dependency).ok_or_else(|| {
+                    miette!("plugin pass '{}' depends on unknown pass '{dependency}'", pass.name())
+                })?;
+                if !dependencies.contains(&type_id) {
+                    dependencies.push(type_id);
+                }
+            }
+        }
+
+        // Take the passes whose dependencies are all taken until none are left
+        let mut sorted: Vec<Arc<dyn PluginAnalysisPass>> = Vec::with_capacity(passes.len());
+        let mut remaining = passes.clone();
+        while !remaining.is_empty() {
+            let (ready, blocked): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|pass| {
+                pass.dependencies().iter().all(|dependency| {
+                    !names.contains(dependency.as_str())
+                        || sorted.iter().any(|sorted| sorted.name() == dependency.as_str())
+                })
+            });
+            if ready.is_empty() {
+                let cycle = blocked.iter().map(|pass| pass.name()).collect::<Vec<_>>();
+                return Err(miette!(
+                    "plugin passes depend on each other in a cycle: {}",
+                    cycle.join(", ")
+                ));
+            }
+            sorted.extend(ready);
+            remaining = blocked;
+        }
+
+        Ok(Self { passes: sorted, dependencies })
+    }
+
+    /// Get the passes, in the order they run
+    pub fn passes(&self) -> &[Arc<dyn PluginAnalysisPass>] {
+        &self.passes
+    }
+}
+
+impl AnalysisPass for PluginAnalysis {
+    /// The IDs of the passes that ran
+    type Output = Vec<String>;
+
+    fn name(&self) -> &'static str {
+        "PluginAnalysis"
+    }
+
+    fn id(&self) -> &'static str {
+        PLUGIN_ANALYSIS_ID
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        self.dependencies.clone()
+    }
+
+    fn run(&self, ctx: &mut AnalysisContext) -> Result<Self::Output, Box<dyn miette::Diagnostic>> {
+        let body = serde_json::to_string(&hir_document(ctx.body())).map_err(|err| {
+            Box::new(MietteDiagnostic::new(err.to_string())) as Box<dyn miette::Diagnostic>
+        })?;
+        let config = Arc::clone(ctx.config());
+
+        let mut ran = Vec::new();
+        for pass in &self.passes {
+            let name = pass.name();
+            // A restriction to `plugins` selects every pass of a plugin
+            let enabled = config.only().is_some() || config.is_enabled(name);
+            let ready = pass.dependencies().iter().all(|dependency| {
+                !self.passes.iter().any(|pass| pass.name() == dependency.as_str())
+                    || ran.contains(dependency)
+            });
+            if !enabled || !ready {
+                continue;
+            }
+
+            match pass.analyze(&body) {
+                Ok(diagnostics) => {
+                    for diagnostic in diagnostics {
+                        ctx.add_diagnostic(to_diagnostic(name, diagnostic));
+                    }
+                    ran.push(name.to_string());
+                }
+                Err(message) => ctx.error(
+                    format!("Plugin pass '{name}' failed: {message}"),
+                    "The passes depending on it were skipped",
+                    None,
+                ),
+            }
+        }
+        Ok(ran)
+    }
+}
+
+/// Convert a diagnostic of the pass of a plugin
+fn to_diagnostic(pass: &str, diagnostic: PluginDiagnostic) -> Diagnostic {
+    let PluginDiagnostic { severity, code, message, help, span } = diagnostic;
+    let converted = match severity {
+        PluginSeverity::Error => Diagnostic::error(message, help, span),
+        PluginSeverity::Warning => Diagnostic::warning(message, help, span),
+        PluginSeverity::Advice => Diagnostic::advice(message, help, span),
+    };
+    let converted = converted.with_note(format!("reported by the plugin pass '{pass}'"));
+    match code {
+        Some(code) => converted.with_code(code),
+        None => converted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_core::{InstructionRegistry, PluginManager, RamPlugin};
+    use ram_diagnostics::DiagnosticConfig;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::language;
+
+    /// A pass that reports every instruction with an opcode
+    struct OpcodeLint {
+        name: &'static str,
+        opcode: &'static str,
+        dependencies: Vec<String>,
+    }
+
+    impl PluginAnalysisPass for OpcodeLint {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn dependencies(&self) -> Vec<String> {
+            self.dependencies.clone()
+        }
+
+        fn analyze(&self, body: &str) -> Result<Vec<PluginDiagnostic>, String> {
+            let body: Value = serde_json::from_str(body).map_err(|err| err.to_string())?;
+            let instructions = body["instructions"].as_array().ok_or("no instructions")?;
+            Ok(instructions
+                .iter()
+                .filter(|instruction| instruction["opcode"] == self.opcode)
+                .map(|instruction| {
+                    let offset = |key: &str| {
+                        usize::try_from(instruction["span"][key].as_u64().unwrap()).unwrap()
+                    };
+                    PluginDiagnostic::new(
+                        PluginSeverity::Warning,
+                        format!("{} is not allowed in this course", self.opcode),
+                        "Use the instructions seen in class",
+                        offset("start")..offset("end"),
+                    )
+                    .with_code(format!("course::{}", self.name))
+                })
+                .collect())
+        }
+    }
+
+    struct CoursePlugin {
+        passes: Vec<Arc<dyn PluginAnalysisPass>>,
+    }
+
+    impl RamPlugin for CoursePlugin {
+        fn name(&self) -> &str {
+            "course"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn description(&self) -> &str {
+            "Lint rules of a course"
+        }
+
+        fn register(&self, _registry: &mut InstructionRegistry) {}
+
+        fn analysis_passes(&self) -> Vec<Arc<dyn PluginAnalysisPass>> {
+            self.passes.clone()
+        }
+    }
+
+    fn lint(name: &'static str, opcode: &'static str, dependencies: &[&str]) -> Arc<OpcodeLint> {
+        Arc::new(OpcodeLint {
+            name,
+            opcode,
+            dependencies: dependencies.iter().map(ToString::to_string).collect(),
+        })
+    }
+
+    fn plugins(passes: Vec<Arc<dyn PluginAnalysisPass>>) -> PluginManager {
+        let mut plugins = PluginManager::new();
+        plugins.register_plugin(Arc::new(CoursePlugin { passes }));
+        plugins
+    }
+
+    #[test]
+    fn test_plugin_diagnostics() {
+        let plugins = plugins(vec![lint("no-div", "DIV", &["cfg"])]);
+        let source = "LOAD 1\nDIV 2\nHALT\n";
+        let (_, _, _, context, diagnostics) =
+            language::analyze_program_with_plugins(source, &DiagnosticConfig::default(), &plugins);
+
+        assert_eq!(*context.get_result::<PluginAnalysis>().unwrap(), ["no-div"]);
+        let diagnostic = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.code.as_deref() == Some("course::no-div"))
+            .unwrap();
+        assert_eq!(diagnostic.labeled_spans[0].0, 7..12);
+        assert_eq!(diagnostic.notes, ["reported by the plugin pass 'no-div'"]);
+
+        // The pass is disabled by its ID in the manifest
+        let config = DiagnosticConfig::default().with_pass_enabled("no-div", false);
+        let (_, _, _, context, diagnostics) =
+            language::analyze_program_with_plugins(source, &config, &plugins);
+        assert!(context.get_result::<PluginAnalysis>().unwrap().is_empty());
+        assert!(!diagnostics.iter().any(|diagnostic| {
+            diagnostic.code.as_deref().is_some_and(|code| code.starts_with("course::"))
+        }));
+    }
+
+    #[test]
+    fn test_plugin_dependencies() {
+        let (_, _, mut pipeline, _, _) = language::analyze_program("HALT\n", &Default::default());
+
+        // Passes run after the passes of plugins they depend on
+        let passes: Vec<Arc<dyn PluginAnalysisPass>> =
+            vec![lint("b", "DIV", &["a", "dataflow"]), lint("a", "MUL", &[])];
+        let analysis = PluginAnalysis::new(&pipeline, passes).unwrap();
+        let order = analysis.passes().iter().map(|pass| pass.name()).collect::<Vec<_>>();
+        assert_eq!(order, ["a", "b"]);
+        assert_eq!(analysis.dependencies, [pipeline.pass_type_id("dataflow").unwrap()]);
+        pipeline.register_pass(analysis).unwrap();
+
+        for passes in [
+            vec![lint("a", "DIV", &["missing"])],
+            vec![lint("cfg", "DIV", &[])],
+            vec![lint("a", "DIV", &["b"]), lint("b", "DIV", &["a"])],
+        ] {
+            let passes = passes.into_iter().map(|pass| pass as Arc<dyn PluginAnalysisPass>);
+            assert!(PluginAnalysis::new(&pipeline, passes.collect()).is_err());
+        }
+    }
+}
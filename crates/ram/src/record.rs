@@ -0,0 +1,205 @@
+//! Module for recording runs and replaying them
+//!
+//! `ram run --record` writes everything a run depends on to a recording: the
+//! input values, the initial memory, the seed of the random numbers, the
+//! instruction set and the plugins, along with the version of `ram` that made
+//! it and the outcome of the run. `ram run --replay` runs the same program
+//! again from the recording and fails if the outcome is not the same, so a
+//! bug report or a grading dispute can be reproduced exactly.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use miette::{IntoDiagnostic, Result, miette};
+use ram_core::PLUGIN_ABI_VERSION;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Arithmetic;
+use crate::run::{RunOutcome, RunStatus};
+use crate::version::VERSION;
+
+/// The version of the format of recordings
+pub const RECORDING_FORMAT_VERSION: u32 = 1;
+
+/// A plugin a recorded run had loaded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedPlugin {
+    /// The name of the plugin
+    pub name: String,
+    /// The version of the plugin
+    pub version: String,
+}
+
+/// How a recorded run ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedOutcome {
+    /// How the run ended
+    pub status: RunStatus,
+    /// The number of instructions executed
+    pub steps: u64,
+    /// The values written by the program
+    pub output: Vec<i64>,
+    /// The value of the accumulator when the run stopped
+    pub accumulator: i64,
+    /// The runtime error the program failed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<&RunOutcome> for RecordedOutcome {
+    fn from(outcome: &RunOutcome) -> Self {
+        Self {
+            status: outcome.status,
+            steps: outcome.steps,
+            output: outcome.output.clone(),
+            accumulator: outcome.accumulator,
+            error: outcome.error.clone(),
+        }
+    }
+}
+
+impl RecordedOutcome {
+    /// Describe how a replayed run differs from the recorded one
+    ///
+    /// Returns `None` if the replay ended the same way.
+    pub fn divergence(&self, replayed: &RecordedOutcome) -> Option<String> {
+        let mut differences = String::new();
+        if self.status != replayed.status {
+            let _ = write!(
+                differences,
+                "\n  status: recorded {:?}, replayed {:?}",
+                self.status, replayed.status
+            );
+        }
+        if self.steps != replayed.steps {
+            let _ = write!(
+                differences,
+                "\n  steps: recorded {}, replayed {}",
+                self.steps, replayed.steps
+            );
+        }
+        if self.output != replayed.output {
+            let _ = write!(
+                differences,
+                "\n  output: recorded {:?}, replayed {:?}",
+                self.output, replayed.output
+            );
+        }
+        if self.accumulator != replayed.accumulator {
+            let _ = write!(
+                differences,
+                "\n  accumulator: recorded {}, replayed {}",
+                self.accumulator, replayed.accumulator
+            );
+        }
+        if self.error != replayed.error {
+            let _ = write!(
+                differences,
+                "\n  error: recorded {:?}, replayed {:?}",
+                self.error, replayed.error
+            );
+        }
+        (!differences.is_empty()).then_some(differences)
+    }
+}
+
+/// Everything a run depends on, saved with `--record` and run again with `--replay`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    /// The version of the format of the recording
+    pub format: u32,
+    /// The version of `ram` that recorded the run
+    pub ram_version: String,
+    /// The version of the plugin ABI of that `ram`
+    pub plugin_abi: u32,
+    /// The program that ran
+    pub program: PathBuf,
+    /// The hash of the program file, to detect changes before replaying
+    pub program_hash: u64,
+    /// The instruction set the program ran with
+    pub instruction_set: String,
+    /// The plugins loaded during the run
+    pub plugins: Vec<RecordedPlugin>,
+    /// The input values of the program
+    pub input: Vec<i64>,
+    /// The registers set before the run, with their values
+    pub memory: Vec<(i64, i64)>,
+    /// The seed of the random numbers
+    pub seed: u64,
+    /// The maximum number of instructions executed
+    pub max_steps: Option<usize>,
+    /// Whether the program was held to the classic RAM model
+    pub strict_ram: bool,
+    /// What arithmetic instructions did on overflow
+    pub arithmetic: Arithmetic,
+    /// How the run ended, once it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<RecordedOutcome>,
+}
+
+impl Recording {
+    /// Read a recording from a file
+    ///
+    /// Fails if the file is not a recording, or one of a format this version
+    /// of `ram` can not read.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read(path).into_diagnostic()?;
+        let recording: Self = serde_json::from_slice(&contents)
+            .map_err(|e| miette!("Invalid recording {}: {}", path.display(), e))?;
+        if recording.format != RECORDING_FORMAT_VERSION {
+            return Err(miette!(
+                "Cannot replay {}: it has format version {}, but this version of ram reads version {}",
+                path.display(),
+                recording.format,
+                RECORDING_FORMAT_VERSION
+            ));
+        }
+        Ok(recording)
+    }
+
+    /// Write the recording to a file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self).into_diagnostic()?;
+        std::fs::write(path, json).into_diagnostic()
+    }
+
+    /// Get the version metadata of the running `ram`
+    pub fn current_version() -> (String, u32) {
+        (VERSION.pkg_version().to_string(), PLUGIN_ABI_VERSION)
+    }
+
+    /// Check whether this `ram` can replay the recording exactly
+    ///
+    /// Returns a warning if it was recorded by another version of `ram`, as
+    /// the run may then end differently. Fails if the run had plugins loaded,
+    /// as `ram run` does not load any.
+    pub fn check_compatibility(&self) -> Result<Option<String>> {
+        if !self.plugins.is_empty() {
+            let plugins = self
+                .plugins
+                .iter()
+                .map(|plugin| format!("{} {}", plugin.name, plugin.version))
+                .collect::<Vec<_>>();
+            return Err(miette!(
+                "Cannot replay the run: it had the plugins {} loaded",
+                plugins.join(", ")
+            ));
+        }
+        let (version, plugin_abi) = Self::current_version();
+        if self.ram_version != version || self.plugin_abi != plugin_abi {
+            return Ok(Some(format!(
+                "made by ram {} (plugin ABI {}), replaying with ram {} (plugin ABI {})",
+                self.ram_version, self.plugin_abi, version, plugin_abi
+            )));
+        }
+        Ok(None)
+    }
+}
+
+/// Pick a seed for a recorded run that was not given one
+///
+/// The seed is saved in the recording, so the numbers of the run can be
+/// drawn again when it is replayed.
+pub fn pick_seed() -> u64 {
+    ram_vm::Rng::from_time().next_u64()
+}
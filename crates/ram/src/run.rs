@@ -1,73 +1,632 @@
 //! Module for running RAM programs
 
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
 
 use miette::{IntoDiagnostic, Result, miette};
-use ram_vm::{VecInput, VecOutput, VirtualMachine, VmDatabaseImpl};
+use ram_core::error::VmError;
+use ram_core::{ArithmeticMode, INSTRUCTION_SET_REGISTRY};
+use ram_diagnostics::config::SettingValue;
+use ram_vm::{
+    RuntimeError, Snapshot, VecInput, VecOutput, VirtualMachine, VmDatabase, VmDatabaseImpl,
+    bytecode,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
 
-use crate::language;
+use crate::cache::{Cache, CacheKey};
+use crate::cli::RunFormat;
+use crate::record::{RECORDING_FORMAT_VERSION, RecordedOutcome, Recording};
+use crate::{artifact, heatmap, language, record};
+
+/// Options of the virtual machine running a program
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// The seed of the random numbers, which are unpredictable if not given
+    pub seed: Option<u64>,
+    /// The name of the instruction set the program runs with
+    pub instruction_set: String,
+    /// The maximum number of instructions to execute before pausing the run
+    pub max_steps: Option<usize>,
+    /// The file the state of the machine is saved to when the run stops
+    pub save_state: Option<PathBuf>,
+    /// Whether the program is held to the classic RAM model
+    pub strict_ram: bool,
+    /// The file the HTML heatmap of the run is written to
+    pub heatmap: Option<PathBuf>,
+    /// How the result of the run is printed
+    pub output_format: RunFormat,
+    /// Whether common pairs of instructions run as superinstructions
+    pub fuse: bool,
+    /// What arithmetic instructions do when their result does not fit
+    pub arithmetic: ArithmeticMode,
+    /// The file the run is recorded to, to replay it later
+    pub record: Option<PathBuf>,
+}
+
+/// How a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunStatus {
+    /// The program executed `HALT`
+    Halted,
+    /// The run stopped after the maximum number of steps and can be resumed
+    Paused,
+    /// The program failed with a runtime error
+    Failed,
+}
+
+/// The result of a run, printed by `ram run`
+///
+/// The process exits with [`RunOutcome::exit_code`], so scripts can tell how
+/// the run ended without parsing its output.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunOutcome {
+    /// How the run ended
+    pub status: RunStatus,
+    /// The number of instructions executed
+    pub steps: u64,
+    /// The values written by the program
+    pub output: Vec<i64>,
+    /// The characters written by the program
+    pub text: String,
+    /// The value of the accumulator when the run stopped
+    pub accumulator: i64,
+    /// The exit value of the program, the accumulator when it halted
+    pub exit_value: Option<i64>,
+    /// The runtime error the program failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunOutcome {
+    /// The exit code of a halted run
+    pub const HALTED: u8 = 0;
+    /// The exit code of a run that failed with a runtime error
+    pub const FAILED: u8 = 1;
+    /// The exit code of a run paused after the maximum number of steps
+    pub const PAUSED: u8 = 2;
+
+    /// The exit code of the process for this outcome
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self.status {
+            RunStatus::Halted => Self::HALTED,
+            RunStatus::Failed => Self::FAILED,
+            RunStatus::Paused => Self::PAUSED,
+        })
+    }
+}
+
+/// The state of a paused run, saved with `--save-state` and resumed with `--resume`
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedState {
+    /// The program being run
+    program: PathBuf,
+    /// The hash of the program file, to detect changes before resuming
+    program_hash: u64,
+    /// The instruction set the program runs with
+    instruction_set: String,
+    /// The state of the virtual machine
+    vm: Snapshot<VecInput, VecOutput>,
+}
+
+/// What a run does with a recording
+#[derive(Debug)]
+enum Session {
+    /// Save the run, once it stops, to the file
+    Record(Recording, PathBuf),
+    /// Check that the run ends as the recorded one did
+    Replay(RecordedOutcome),
+}
 
 /// Run a RAM program from a file path
+///
+/// The program reads `input_values`, or a line from standard input if none
+/// are given, and starts with the registers in `memory` set to their values.
+///
+/// If a `cache` is given and the program was analyzed before, the cached
+/// diagnostics are reported and the program is only lowered. Bytecode
+/// artifacts are verified instead of analyzed.
+///
+/// A recorded run without a seed gets a random one, saved in the recording.
 pub fn run_program(
     program_path: &Path,
     input_values: Option<Vec<i64>>,
-    _memory_path: Option<&Path>,
-) -> Result<()> {
-    // Read the program file
-    let program_text = std::fs::read_to_string(program_path).into_diagnostic()?;
-
-    // Parse and Validate using the full language pipeline
-    // This runs lexer -> parser -> hir lowering -> analysis pipeline
-    let (_ast, body, _pipeline, _context, errors) = language::parse_program(&program_text);
-
-    // Check for validation errors
-    if !errors.is_empty() {
-        // Print all errors
-        for error in &errors {
-            eprintln!("{:?}", error);
-        }
-        // Fail the run command
-        return Err(miette!("Program validation failed with {} errors", errors.len()));
-    }
+    memory: Vec<(i64, i64)>,
+    options: &RunOptions,
+    cache: Option<&Cache>,
+) -> Result<RunOutcome> {
+    let db = instruction_set_db(&options.instruction_set)?;
+    let program = load_program(program_path, &db, options.strict_ram, options.arithmetic, cache)?;
 
     // Determine input values: use provided CLI args or prompt interactively
     let values = if let Some(vals) = input_values {
         vals
     } else {
-        print!("Input: ");
-        std::io::stdout().flush().into_diagnostic()?;
+        if options.output_format == RunFormat::Text {
+            print!("Input: ");
+            std::io::stdout().flush().into_diagnostic()?;
+        }
         let mut buffer = String::new();
         std::io::stdin().read_line(&mut buffer).into_diagnostic()?;
+        parse_values(&buffer)?
+    };
+
+    let Some(record_path) = &options.record else {
+        return run_fresh(program, db, program_path, values, memory, options, None);
+    };
+    let seed = options.seed.unwrap_or_else(record::pick_seed);
+    let (ram_version, plugin_abi) = Recording::current_version();
+    let recording = Recording {
+        format: RECORDING_FORMAT_VERSION,
+        ram_version,
+        plugin_abi,
+        program: std::fs::canonicalize(program_path).into_diagnostic()?,
+        program_hash: program_hash(program_path)?,
+        instruction_set: options.instruction_set.clone(),
+        // `ram run` loads no plugins
+        plugins: Vec::new(),
+        input: values.clone(),
+        memory: memory.clone(),
+        seed,
+        max_steps: options.max_steps,
+        strict_ram: options.strict_ram,
+        arithmetic: options.arithmetic.into(),
+        outcome: None,
+    };
+    let options = RunOptions { seed: Some(seed), ..options.clone() };
+    let session = Session::Record(recording, record_path.clone());
+    run_fresh(program, db, program_path, values, memory, &options, Some(session))
+}
 
-        // Replace commas with spaces to allow comma-separated input (e.g. "1, 2, 3")
-        buffer
-            .replace(',', " ")
-            .split_whitespace()
-            .map(|token| {
-                token.parse::<i64>().map_err(|e| miette!("Invalid number '{}': {}", token, e))
-            })
-            .collect::<Result<Vec<i64>>>()?
+/// Replay a run recorded with `--record`
+///
+/// The program, its input, the initial memory, the random numbers and the
+/// limits of the run all come from the recording, and the program must not
+/// have changed since then. Fails if the run does not end as the recorded
+/// one did.
+pub fn replay_program(
+    recording_path: &Path,
+    options: &RunOptions,
+    cache: Option<&Cache>,
+) -> Result<RunOutcome> {
+    let recording = Recording::load(recording_path)?;
+    if let Some(warning) = recording.check_compatibility()? {
+        eprintln!("warning: recording {}", warning);
+    }
+    if program_hash(&recording.program)? != recording.program_hash {
+        return Err(miette!(
+            "Cannot replay: {} changed since the run was recorded",
+            recording.program.display()
+        ));
+    }
+    let Some(recorded) = recording.outcome else {
+        return Err(miette!("Cannot replay {}: the run never stopped", recording_path.display()));
     };
 
+    let options = RunOptions {
+        seed: Some(recording.seed),
+        instruction_set: recording.instruction_set,
+        max_steps: recording.max_steps,
+        strict_ram: recording.strict_ram,
+        arithmetic: recording.arithmetic.into(),
+        record: None,
+        ..options.clone()
+    };
+    let db = instruction_set_db(&options.instruction_set)?;
+    let program =
+        load_program(&recording.program, &db, options.strict_ram, options.arithmetic, cache)?;
+    let session = Some(Session::Replay(recorded));
+    run_fresh(program, db, &recording.program, recording.input, recording.memory, &options, session)
+}
+
+/// Run a program on a new virtual machine
+fn run_fresh(
+    program: ram_vm::Program,
+    db: Arc<VmDatabaseImpl>,
+    program_path: &Path,
+    values: Vec<i64>,
+    memory: Vec<(i64, i64)>,
+    options: &RunOptions,
+    session: Option<Session>,
+) -> Result<RunOutcome> {
     let input = VecInput::new(values);
     let output = VecOutput::new();
 
-    // Create a new database for VM execution
-    let db = Arc::new(VmDatabaseImpl::new());
+    // Create a virtual machine
+    let mut builder =
+        VirtualMachine::builder(program, input, output, db).with_memory_values(memory);
+    if let Some(seed) = options.seed {
+        builder = builder.with_seed(seed);
+    }
+    if let Some(source_map) = artifact::find_source_map(program_path)? {
+        builder = builder.with_source_map(Arc::new(source_map));
+    }
+    let mut vm = builder.build();
 
-    // Convert the validated HIR Body to a VM Program
-    let program = ram_vm::Program::from_hir(&body, &*db)
-        .map_err(|e| miette!("Failed to compile to VM program: {}", e))?;
+    execute(&mut vm, program_path, options, session)
+}
 
-    // Create a virtual machine
-    let mut vm = VirtualMachine::new(program, input, output, db);
+/// Parse the `--input` argument, a list of values or a file containing them
+pub fn parse_input(arg: &str) -> Result<Vec<i64>> {
+    let path = Path::new(arg);
+    if path.is_file() {
+        let contents = std::fs::read_to_string(path).into_diagnostic()?;
+        parse_values(&contents).map_err(|e| miette!("Invalid input file {}: {}", arg, e))
+    } else {
+        parse_values(arg)
+    }
+}
+
+/// Parse the `--memory` argument, a comma-separated list of `address=value` pairs
+pub fn parse_memory(arg: &str) -> Result<Vec<(i64, i64)>> {
+    arg.split(',')
+        .map(str::trim)
+        .filter(|assignment| !assignment.is_empty())
+        .map(|assignment| {
+            let (address, value) = assignment.split_once('=').ok_or_else(|| {
+                miette!("Invalid memory assignment '{}', expected `address=value`", assignment)
+            })?;
+            let address = parse_value(address.trim())?;
+            if address < 0 {
+                return Err(miette!(
+                    "Invalid memory assignment '{}': negative address",
+                    assignment
+                ));
+            }
+            Ok((address, parse_value(value.trim())?))
+        })
+        .collect()
+}
 
-    // Run the program
-    vm.run().map_err(|e| miette!("Failed to run program: {}", e))?;
+/// Parse values separated by whitespace or commas, such as `1, 2 3`
+fn parse_values(text: &str) -> Result<Vec<i64>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(parse_value)
+        .collect()
+}
+
+/// Parse a single value
+fn parse_value(token: &str) -> Result<i64> {
+    token.parse::<i64>().map_err(|e| miette!("Invalid number '{}': {}", token, e))
+}
 
-    println!("Output: {:?}", vm.output.values);
+/// Resume a run from the state saved with `--save-state`
+///
+/// The program, its input, the random numbers and the instruction set all
+/// come from the saved state. The program must not have changed since then.
+pub fn resume_program(
+    state_path: &Path,
+    options: &RunOptions,
+    cache: Option<&Cache>,
+) -> Result<RunOutcome> {
+    let contents = std::fs::read(state_path).into_diagnostic()?;
+    let state: SavedState = serde_json::from_slice(&contents)
+        .map_err(|e| miette!("Invalid state file {}: {}", state_path.display(), e))?;
 
+    if program_hash(&state.program)? != state.program_hash {
+        return Err(miette!(
+            "Cannot resume: {} changed since the state was saved",
+            state.program.display()
+        ));
+    }
+
+    // The run goes on with the instruction set and arithmetic it started with
+    let options = RunOptions {
+        instruction_set: state.instruction_set,
+        arithmetic: state.vm.arithmetic,
+        ..options.clone()
+    };
+    let db = instruction_set_db(&options.instruction_set)?;
+    let program = load_program(&state.program, &db, options.strict_ram, options.arithmetic, cache)?;
+    let mut vm = VirtualMachine::from_snapshot(program, state.vm, db);
+
+    execute(&mut vm, &state.program, &options, None)
+}
+
+/// Create a database with the instructions of the named instruction set
+pub(crate) fn instruction_set_db(name: &str) -> Result<Arc<VmDatabaseImpl>> {
+    let instruction_set = INSTRUCTION_SET_REGISTRY.get_case_insensitive(name).ok_or_else(|| {
+        let mut names = INSTRUCTION_SET_REGISTRY.names().collect::<Vec<_>>();
+        names.sort();
+        miette!("Unknown instruction set '{}', expected one of: {}", name, names.join(", "))
+    })?;
+    Ok(Arc::new(VmDatabaseImpl::with_instruction_set(&instruction_set)))
+}
+
+/// Compile a RAM program, or verify and load it if it is a bytecode artifact
+pub(crate) fn load_program(
+    program_path: &Path,
+    db: &VmDatabaseImpl,
+    strict_ram: bool,
+    arithmetic: ArithmeticMode,
+    cache: Option<&Cache>,
+) -> Result<ram_vm::Program> {
+    if program_path.extension().is_some_and(|ext| ext == bytecode::FILE_EXTENSION) {
+        Ok(artifact::load_artifact(program_path, &db.instruction_registry())?.program)
+    } else {
+        let program_text = std::fs::read_to_string(program_path).into_diagnostic()?;
+        compile_program(program_path, &program_text, db, strict_ram, arithmetic, cache)
+    }
+}
+
+/// Hash the contents of a program file
+fn program_hash(program_path: &Path) -> Result<u64> {
+    let bytes = std::fs::read(program_path).into_diagnostic()?;
+    Ok(bytecode::content_hash(&bytes))
+}
+
+/// Run a virtual machine, saving its state afterwards if requested
+///
+/// The outcome is printed in the format of the options. Printed as text, a
+/// failed run is reported as an error pointing at the failing instruction.
+/// A recorded run is saved even if it failed, and a replayed run fails if it
+/// does not end as the recorded one did.
+fn execute(
+    vm: &mut VirtualMachine<VecInput, VecOutput>,
+    program_path: &Path,
+    options: &RunOptions,
+    session: Option<Session>,
+) -> Result<RunOutcome> {
+    vm.set_fusion(options.fuse);
+    vm.set_arithmetic(options.arithmetic);
+    let result = match options.max_steps {
+        Some(max_steps) => vm.run_steps(max_steps),
+        None => vm.run(),
+    };
+
+    let status = match &result {
+        Err(_) => RunStatus::Failed,
+        Ok(()) if vm.is_finished() => RunStatus::Halted,
+        Ok(()) => RunStatus::Paused,
+    };
+    let outcome = RunOutcome {
+        status,
+        steps: vm.steps(),
+        output: vm.output.values.clone(),
+        text: vm.output.text.clone(),
+        accumulator: vm.accumulator(),
+        exit_value: (status == RunStatus::Halted).then(|| vm.accumulator()),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+
+    match session {
+        Some(Session::Record(mut recording, record_path)) => {
+            recording.outcome = Some(RecordedOutcome::from(&outcome));
+            recording.save(&record_path)?;
+        }
+        Some(Session::Replay(recorded)) => {
+            if let Some(divergence) = recorded.divergence(&RecordedOutcome::from(&outcome)) {
+                return Err(miette!("The replay does not match the recording:{}", divergence));
+            }
+        }
+        None => {}
+    }
+
+    // A failed run can not be resumed, so its state is not saved
+    if let Some(state_path) = &options.save_state
+        && status != RunStatus::Failed
+    {
+        let state = SavedState {
+            program: std::fs::canonicalize(program_path).into_diagnostic()?,
+            program_hash: program_hash(program_path)?,
+            instruction_set: options.instruction_set.clone(),
+            vm: vm.snapshot(),
+        };
+        let json = serde_json::to_vec_pretty(&state).into_diagnostic()?;
+        std::fs::write(state_path, json).into_diagnostic()?;
+    }
+    if let Some(report_path) = &options.heatmap {
+        heatmap::write_report(program_path, vm.execution_counts(), report_path)?;
+    }
+
+    match options.output_format {
+        RunFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&outcome).into_diagnostic()?);
+        }
+        RunFormat::Text => {
+            if let Err(error) = &result {
+                return Err(runtime_error(vm, error, program_path));
+            }
+            print_outcome(&outcome, options)?
+        }
+    }
+    Ok(outcome)
+}
+
+/// Report a runtime error with the source snippet of the failing instruction
+///
+/// Bytecode artifacts have no source to show, so their errors are reported
+/// with the message alone, and the original location of the instruction if
+/// the artifact has a source map.
+fn runtime_error(
+    vm: &VirtualMachine<VecInput, VecOutput>,
+    error: &VmError,
+    program_path: &Path,
+) -> miette::Report {
+    let source = match program_path.extension() {
+        Some(ext) if ext == bytecode::FILE_EXTENSION => None,
+        _ => std::fs::read_to_string(program_path).ok(),
+    };
+    let report = source.and_then(|source| {
+        let (_, body, _) = language::lower_program(&source);
+        RuntimeError::new(error, vm, &body, program_path.display().to_string(), source)
+    });
+    let location = vm.last_pc().and_then(|pc| vm.source_location(pc));
+    match (report, location) {
+        (Some(report), _) => miette::Report::new(report),
+        (None, Some(location)) => miette!("Failed to run program at {}: {}", location, error),
+        (None, None) => miette!("Failed to run program: {}", error),
+    }
+}
+
+/// Print the outcome of a run as text
+fn print_outcome(outcome: &RunOutcome, options: &RunOptions) -> Result<()> {
+    println!("Output:      {:?}", outcome.output);
+    if !outcome.text.is_empty() {
+        println!("Text:        {}", outcome.text);
+    }
+    println!("Accumulator: {}", outcome.accumulator);
+    println!("Steps:       {}", outcome.steps);
+
+    if outcome.status == RunStatus::Paused {
+        match &options.save_state {
+            Some(state_path) => println!(
+                "Paused after {} steps, resume with `ram run --resume {}`",
+                outcome.steps,
+                state_path.display()
+            ),
+            None => println!("Stopped after {} steps", outcome.steps),
+        }
+    }
     Ok(())
 }
+
+/// Validate the text of a RAM program and compile it to a VM program
+///
+/// The diagnostics of the program are reported, and compiling fails if any of
+/// them is an error. Diagnostics found in `cache` are reported without
+/// analyzing the program, which is still lowered to build the VM program.
+/// With `strict_ram`, the program is held to the classic RAM model. Arithmetic
+/// that may overflow is only reported if `arithmetic` stops the run on
+/// overflow.
+pub fn compile_program(
+    program_path: &Path,
+    program_text: &str,
+    db: &VmDatabaseImpl,
+    strict_ram: bool,
+    arithmetic: ArithmeticMode,
+    cache: Option<&Cache>,
+) -> Result<ram_vm::Program> {
+    // Parse and Validate using the full language pipeline
+    // This runs lexer -> parser -> hir lowering -> analysis pipeline, validating
+    // the instructions against the ones the VM can execute
+    let config = language::diagnostic_config_for(program_path, strict_ram)?.with_pass_setting(
+        "alias",
+        hir_analysis::analyzers::alias::ARITHMETIC,
+        SettingValue::String(arithmetic.name().to_string()),
+    );
+    let instructions = db.instruction_registry();
+    let key = CacheKey::new(program_text, &config, &instructions);
+    let (body, diagnostics) = match cache.and_then(|cache| cache.get(key)) {
+        Some(diagnostics) => (language::lower_program(program_text).1, diagnostics),
+        None => {
+            let (_ast, body, _pipeline, _context, diagnostics) =
+                language::analyze_program_with_instructions(program_text, &config, instructions);
+            if let Some(cache) = cache
+                && let Err(err) = cache.put(key, &diagnostics)
+            {
+                debug!("Failed to cache diagnostics: {}", err);
+            }
+            (body, diagnostics)
+        }
+    };
+    let errors = language::report_diagnostics(
+        &program_path.display().to_string(),
+        program_text,
+        diagnostics,
+    );
+
+    // Print all diagnostics
+    for error in &errors {
+        eprintln!("{:?}", error);
+    }
+
+    // Fail the command if any of the diagnostics is an error
+    let error_count = errors
+        .iter()
+        .filter_map(|error| error.downcast_ref::<ram_error::Report>())
+        .flat_map(|report| &report.errors)
+        .filter(|error| error.is_error())
+        .count();
+    if error_count > 0 {
+        return Err(miette!("Program validation failed with {} errors", error_count));
+    }
+
+    // Convert the validated HIR Body to a VM Program
+    ram_vm::Program::from_hir(&body, db)
+        .map_err(|e| miette!("Failed to compile to VM program: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_vm::Location;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("5 7").unwrap(), vec![5, 7]);
+        assert_eq!(parse_input("1, -2,3").unwrap(), vec![1, -2, 3]);
+        assert!(parse_input("1 two").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.txt");
+        std::fs::write(&path, "4\n8\n15\n").unwrap();
+        assert_eq!(parse_input(path.to_str().unwrap()).unwrap(), vec![4, 8, 15]);
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("1=5,2=7").unwrap(), vec![(1, 5), (2, 7)]);
+        assert_eq!(parse_memory(" 0 = -1 , ").unwrap(), vec![(0, -1)]);
+        assert!(parse_memory("1").is_err());
+        assert!(parse_memory("-1=5").is_err());
+        assert!(parse_memory("a=5").is_err());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_path = dir.path().join("increment.ram");
+        std::fs::write(&program_path, "READ 1\nLOAD 1\nADD =1\nSTORE 2\nWRITE 2\nHALT\n").unwrap();
+        let recording_path = dir.path().join("session.ramrec");
+        let options = RunOptions {
+            instruction_set: "standard".to_string(),
+            output_format: RunFormat::Json,
+            record: Some(recording_path.clone()),
+            ..Default::default()
+        };
+        let outcome = run_program(&program_path, Some(vec![4]), vec![], &options, None).unwrap();
+        assert_eq!(outcome.output, vec![5]);
+
+        let mut recording = Recording::load(&recording_path).unwrap();
+        assert_eq!((recording.input.clone(), recording.plugins.len()), (vec![4], 0));
+        assert_eq!(recording.outcome.as_ref().unwrap().steps, 6);
+
+        let options = RunOptions { record: None, ..options };
+        let replayed = replay_program(&recording_path, &options, None).unwrap();
+        assert_eq!((replayed.output, replayed.steps), (vec![5], 6));
+
+        // A replay that ends differently fails
+        recording.outcome.as_mut().unwrap().output = vec![6];
+        recording.save(&recording_path).unwrap();
+        let error = replay_program(&recording_path, &options, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The replay does not match the recording:\n  output: recorded [6], replayed [5]"
+        );
+
+        std::fs::write(&program_path, "HALT\n").unwrap();
+        assert!(replay_program(&recording_path, &options, None).is_err());
+    }
+
+    #[test]
+    fn test_runtime_error_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("divide.ram");
+        std::fs::write(&path, "READ 1\nLOAD =1\nDIV 1\nHALT\n").unwrap();
+        let db = instruction_set_db("standard").unwrap();
+        let program = load_program(&path, &db, false, ArithmeticMode::default(), None).unwrap();
+
+        let mut vm = VirtualMachine::new(program, VecInput::new(vec![0]), VecOutput::new(), db);
+        let error = vm.run().unwrap_err();
+        let report = runtime_error(&vm, &error, &path);
+        let report = report.downcast_ref::<RuntimeError>().unwrap();
+        assert_eq!((report.step, report.pc), (3, 2));
+        assert_eq!(report.context, vec![(Location::Accumulator, 1), (Location::Register(1), 0)]);
+    }
+}
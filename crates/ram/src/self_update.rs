@@ -0,0 +1,353 @@
+//! Module for updating the `ram` binary to a newer release
+//!
+//! The releases are listed from GitHub, and the newest one of the chosen
+//! channel is picked: `stable` only considers full releases, while `alpha`
+//! also considers pre-releases. The archive for the target the binary was
+//! built for is downloaded, checked against the SHA-256 checksum published
+//! next to it, and the binary inside it replaces the running one atomically.
+
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use clap::ValueEnum;
+use miette::{IntoDiagnostic, Result, miette};
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{VERSION, build};
+
+/// The number of lines of the release notes shown in a dry run
+const CHANGELOG_EXCERPT_LINES: usize = 15;
+
+/// The name of the binary inside the release archives
+const BINARY_NAME: &str = if cfg!(windows) { "ram.exe" } else { "ram" };
+
+/// The release channel to update from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    /// Only full releases.
+    #[default]
+    Stable,
+    /// Pre-releases as well as full releases.
+    Alpha,
+}
+
+impl Channel {
+    /// Check whether a version belongs to the channel
+    pub fn includes(self, version: &Version) -> bool {
+        match self {
+            Channel::Stable => version.pre.is_empty(),
+            Channel::Alpha => true,
+        }
+    }
+}
+
+/// A release of the repository, as listed by the GitHub API
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    /// The tag of the release, such as `v0.1.0`
+    pub tag_name: String,
+    /// The release notes, in Markdown
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Whether the release is a draft, which is never installed
+    #[serde(default)]
+    pub draft: bool,
+    /// Whether the release is marked as a pre-release
+    #[serde(default)]
+    pub prerelease: bool,
+    /// The files attached to the release
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    /// Get the version of the release, from its tag
+    ///
+    /// Tags are the version with a `v` prefix, such as `v0.2.0`, or with the
+    /// name of the package before it, such as `ramlang-v0.2.0`.
+    pub fn version(&self) -> Option<Version> {
+        let tag = self.tag_name.as_str();
+        match tag.strip_prefix('v') {
+            Some(version) => Version::parse(version).ok(),
+            // The name of the package may hold a `-v` of its own
+            None => tag
+                .match_indices("-v")
+                .find_map(|(index, _)| Version::parse(&tag[index + 2..]).ok())
+                .or_else(|| Version::parse(tag).ok()),
+        }
+    }
+
+    /// Get the archive with the binary for a target, such as `x86_64-unknown-linux-gnu`
+    pub fn archive_for(&self, target: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| {
+            asset.name.contains(target)
+                && (asset.name.ends_with(".tar.xz") || asset.name.ends_with(".zip"))
+        })
+    }
+
+    /// Get the checksum file of an asset
+    pub fn checksum_for(&self, asset: &Asset) -> Option<&Asset> {
+        let name = format!("{}.sha256", asset.name);
+        self.assets.iter().find(|candidate| candidate.name == name)
+    }
+
+    /// Get the first lines of the release notes, for a quick look at what changed
+    pub fn changelog_excerpt(&self, max_lines: usize) -> String {
+        let body = self.body.as_deref().unwrap_or_default().trim();
+        let lines = body.lines().collect::<Vec<_>>();
+        let mut excerpt = lines.iter().take(max_lines).copied().collect::<Vec<_>>().join("\n");
+        if lines.len() > max_lines {
+            excerpt.push_str("\n...");
+        }
+        excerpt
+    }
+}
+
+/// A file attached to a release
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    /// The name of the file
+    pub name: String,
+    /// The URL the file is downloaded from
+    pub browser_download_url: String,
+}
+
+/// Pick the newest release of a channel that is newer than `current`
+pub fn select_release<'a>(
+    releases: &'a [Release],
+    channel: Channel,
+    current: &Version,
+) -> Option<(&'a Release, Version)> {
+    releases
+        .iter()
+        .filter(|release| !release.draft)
+        .filter(|release| channel == Channel::Alpha || !release.prerelease)
+        .filter_map(|release| Some((release, release.version()?)))
+        .filter(|(_, version)| channel.includes(version) && version > current)
+        .max_by(|(_, a), (_, b)| a.cmp(b))
+}
+
+/// Update the running binary to the newest release of a channel
+///
+/// With `dry_run`, only the version it would update to and the start of its
+/// release notes are printed.
+pub fn self_update(channel: Channel, dry_run: bool) -> Result<()> {
+    let current = Version::parse(build::PKG_VERSION)
+        .map_err(|e| miette!("Invalid version of the running binary: {}", e))?;
+    let releases: Vec<Release> = serde_json::from_reader(fetch(&releases_url())?.into_reader())
+        .map_err(|e| miette!("Invalid list of releases: {}", e))?;
+
+    let Some((release, version)) = select_release(&releases, channel, &current) else {
+        println!("ram {} is the newest version of the {:?} channel", current, channel);
+        return Ok(());
+    };
+    let target = build::BUILD_TARGET;
+    let archive = release
+        .archive_for(target)
+        .ok_or_else(|| miette!("Release {} has no binary for {}", release.tag_name, target))?;
+    let checksum = release.checksum_for(archive).ok_or_else(|| {
+        miette!("Release {} has no checksum for {}", release.tag_name, archive.name)
+    })?;
+
+    println!("Updating ram {} -> {}", current, version);
+    if dry_run {
+        let excerpt = release.changelog_excerpt(CHANGELOG_EXCERPT_LINES);
+        if !excerpt.is_empty() {
+            println!("\n{}\n", excerpt);
+        }
+        println!("Would download {}", archive.browser_download_url);
+        return Ok(());
+    }
+
+    println!("Downloading {}", archive.browser_download_url);
+    let bytes = download(&archive.browser_download_url)?;
+    let expected = String::from_utf8(download(&checksum.browser_download_url)?)
+        .map_err(|_| miette!("Invalid checksum file {}", checksum.name))?;
+    verify_checksum(&bytes, &expected)?;
+
+    let binary = extract_binary(&archive.name, &bytes)?;
+    replace_current_binary(&binary)?;
+    println!("Updated ram to {}", version);
+    Ok(())
+}
+
+/// Check that the SHA-256 hash of some bytes matches a checksum file
+///
+/// The file holds the hash in hexadecimal, optionally followed by the name of
+/// the file it is the hash of.
+pub fn verify_checksum(bytes: &[u8], checksum_file: &str) -> Result<()> {
+    let expected = checksum_file
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| miette!("The checksum file is empty"))?
+        .to_lowercase();
+    let actual =
+        Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    if actual != expected {
+        return Err(miette!("Checksum mismatch: expected {}, got {}", expected, actual));
+    }
+    Ok(())
+}
+
+/// Get the URL of the releases of the repository in the GitHub API
+fn releases_url() -> String {
+    let repository = env!("CARGO_PKG_REPOSITORY").trim_end_matches('/');
+    let path = repository.trim_start_matches("https://github.com/");
+    format!("https://api.github.com/repos/{}/releases?per_page=30", path)
+}
+
+/// Send a GET request, identifying the binary as GitHub asks clients to
+fn fetch(url: &str) -> Result<ureq::Response> {
+    ureq::get(url)
+        .set("User-Agent", &format!("ram/{}", VERSION.pkg_version()))
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .map_err(|e| miette!("Failed to fetch {}: {}", url, e))
+}
+
+/// Download a file into memory
+fn download(url: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    fetch(url)?.into_reader().read_to_end(&mut bytes).into_diagnostic()?;
+    Ok(bytes)
+}
+
+/// Extract the binary from a release archive
+fn extract_binary(archive_name: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+    let is_binary = |path: &Path| path.file_name().is_some_and(|name| name == BINARY_NAME);
+    let mut binary = Vec::new();
+    if archive_name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).into_diagnostic()?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).into_diagnostic()?;
+            if file.enclosed_name().is_some_and(|path| is_binary(&path)) {
+                file.read_to_end(&mut binary).into_diagnostic()?;
+                return Ok(binary);
+            }
+        }
+    } else {
+        let mut archive = tar::Archive::new(xz2::read::XzDecoder::new(bytes));
+        for entry in archive.entries().into_diagnostic()? {
+            let mut entry = entry.into_diagnostic()?;
+            if is_binary(&entry.path().into_diagnostic()?) {
+                entry.read_to_end(&mut binary).into_diagnostic()?;
+                return Ok(binary);
+            }
+        }
+    }
+    Err(miette!("{} does not contain {}", archive_name, BINARY_NAME))
+}
+
+/// Replace the running binary with a new one
+///
+/// The new binary is written next to the running one and moved over it, so
+/// the running binary is never left half written.
+fn replace_current_binary(binary: &[u8]) -> Result<()> {
+    let current = std::env::current_exe().into_diagnostic()?;
+    let dir = current.parent().ok_or_else(|| miette!("The binary has no parent directory"))?;
+    let new_binary = tempfile::NamedTempFile::new_in(dir).into_diagnostic()?;
+    std::fs::write(new_binary.path(), binary).into_diagnostic()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = std::fs::Permissions::from_mode(0o755);
+        std::fs::set_permissions(new_binary.path(), permissions).into_diagnostic()?;
+    }
+    self_replace::self_replace(new_binary.path()).into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a release with assets of the given names
+    fn release(tag: &str, prerelease: bool, assets: &[&str]) -> Release {
+        Release {
+            tag_name: tag.to_string(),
+            body: None,
+            draft: false,
+            prerelease,
+            assets: assets
+                .iter()
+                .map(|name| Asset {
+                    name: name.to_string(),
+                    browser_download_url: format!("https://example.com/{}", name),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_release_by_channel() {
+        let releases = vec![
+            release("v0.1.0", false, &[]),
+            release("v0.2.0-alpha.1", true, &[]),
+            release("v0.1.1", false, &[]),
+            Release { draft: true, ..release("v0.3.0", false, &[]) },
+        ];
+        let current = Version::parse("0.1.0").unwrap();
+
+        let (stable, version) = select_release(&releases, Channel::Stable, &current).unwrap();
+        assert_eq!((stable.tag_name.as_str(), version.to_string()), ("v0.1.1", "0.1.1".into()));
+        let (alpha, _) = select_release(&releases, Channel::Alpha, &current).unwrap();
+        assert_eq!(alpha.tag_name, "v0.2.0-alpha.1");
+
+        let newest = Version::parse("0.2.0-alpha.1").unwrap();
+        assert!(select_release(&releases, Channel::Alpha, &newest).is_none());
+    }
+
+    #[test]
+    fn test_release_assets() {
+        let release = release(
+            "ramlang-v0.2.0",
+            false,
+            &[
+                "ramlang-x86_64-unknown-linux-gnu.tar.xz",
+                "ramlang-x86_64-unknown-linux-gnu.tar.xz.sha256",
+                "ramlang-x86_64-pc-windows-msvc.zip",
+            ],
+        );
+        assert_eq!(release.version(), Some(Version::new(0, 2, 0)));
+
+        let archive = release.archive_for("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(archive.name, "ramlang-x86_64-unknown-linux-gnu.tar.xz");
+        let checksum = release.checksum_for(archive).unwrap();
+        assert_eq!(checksum.name, "ramlang-x86_64-unknown-linux-gnu.tar.xz.sha256");
+
+        let windows = release.archive_for("x86_64-pc-windows-msvc").unwrap();
+        assert!(release.checksum_for(windows).is_none());
+        assert!(release.archive_for("aarch64-apple-darwin").is_none());
+    }
+
+    #[test]
+    fn test_release_versions() {
+        let version = |tag: &str| release(tag, true, &[]).version().map(|v| v.to_string());
+        assert_eq!(version("v0.2.0-dev").as_deref(), Some("0.2.0-dev"));
+        assert_eq!(version("v1.0.0-preview.1").as_deref(), Some("1.0.0-preview.1"));
+        assert_eq!(version("ramlang-v1.0.0-preview.1").as_deref(), Some("1.0.0-preview.1"));
+        assert_eq!(version("ram-vm-v0.3.0-rc.1").as_deref(), Some("0.3.0-rc.1"));
+        assert_eq!(version("0.1.0").as_deref(), Some("0.1.0"));
+        assert_eq!(version("nightly"), None);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let hash = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert!(verify_checksum(b"hello", &format!("{}  ram.tar.xz\n", hash)).is_ok());
+        assert!(verify_checksum(b"hello", &hash.to_uppercase()).is_ok());
+        assert!(verify_checksum(b"hello!", hash).is_err());
+        assert!(verify_checksum(b"hello", "").is_err());
+    }
+
+    #[test]
+    fn test_changelog_excerpt() {
+        let mut release = release("v0.2.0", false, &[]);
+        assert_eq!(release.changelog_excerpt(2), "");
+
+        release.body = Some("## Features\n- Watchpoints\n- Heatmaps\n".to_string());
+        assert_eq!(release.changelog_excerpt(2), "## Features\n- Watchpoints\n...");
+        assert_eq!(release.changelog_excerpt(3), "## Features\n- Watchpoints\n- Heatmaps");
+    }
+}
@@ -0,0 +1,261 @@
+//! Module for finding similar programs among submissions
+//!
+//! Every program is lowered to HIR, brought to its canonical form and reduced
+//! to a fingerprint: the set of n-grams of its instructions, with addresses
+//! renamed in the order they first appear. Renaming labels, moving data to other registers or
+//! changing comments and whitespace leaves the fingerprint unchanged, so the
+//! similarity of two fingerprints points out submissions worth a closer look.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use hir::body::{AddressingMode, Body, ExprKind, Literal};
+use hir::canonical::canonicalize;
+use hir::expr::ExprId;
+use miette::{IntoDiagnostic, Result};
+use serde::Serialize;
+
+use crate::cli::SimilarityFormat;
+use crate::language;
+
+/// The length of the instruction sequences compared by default
+pub const DEFAULT_NGRAM: usize = 3;
+
+/// The normalized fingerprint of a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fingerprint {
+    ngrams: BTreeSet<u64>,
+}
+
+impl Fingerprint {
+    /// Compute the fingerprint of a body from its n-grams of `n` instructions
+    ///
+    /// Bodies shorter than `n` instructions have a single n-gram with all of them.
+    pub fn new(body: &Body, n: usize) -> Self {
+        let body = &canonicalize(body);
+        let mut canonicalizer = Canonicalizer::default();
+        let tokens = body
+            .instructions
+            .values()
+            .map(|instruction| match instruction.operand {
+                Some(operand) => {
+                    format!("{} {}", instruction.kind.name(), canonicalizer.operand(body, operand))
+                }
+                None => instruction.kind.name().to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        let ngrams = if tokens.is_empty() {
+            BTreeSet::new()
+        } else {
+            tokens.windows(n.clamp(1, tokens.len())).map(hash_ngram).collect()
+        };
+        Self { ngrams }
+    }
+
+    /// The similarity of two fingerprints, from 0 for unrelated programs to 1
+    ///
+    /// This is the Jaccard index of their n-grams.
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        let union = self.ngrams.union(&other.ngrams).count();
+        if union == 0 {
+            return 1.0;
+        }
+        self.ngrams.intersection(&other.ngrams).count() as f64 / union as f64
+    }
+}
+
+/// Hash a sequence of canonical instructions
+fn hash_ngram(ngram: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    ngram.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Renames addresses in the order they first appear
+#[derive(Debug, Default)]
+struct Canonicalizer {
+    addresses: HashMap<i64, usize>,
+}
+
+impl Canonicalizer {
+    /// Render an operand with its addresses renamed
+    ///
+    /// Immediate values are kept, as they are part of what a program computes.
+    fn operand(&mut self, body: &Body, expr_id: ExprId) -> String {
+        let Some(expr) = body.expr(expr_id) else {
+            return String::new();
+        };
+        match &expr.kind {
+            ExprKind::MemoryRef(mem_ref) => match mem_ref.mode {
+                AddressingMode::Direct => self.value(body, mem_ref.address),
+                AddressingMode::Indirect => format!("*{}", self.value(body, mem_ref.address)),
+                AddressingMode::Immediate => format!("={}", self.immediate(body, mem_ref.address)),
+            },
+            ExprKind::Literal(Literal::Label(_)) | ExprKind::LabelRef(_) => {
+                self.value(body, expr_id)
+            }
+            _ => format!("={}", self.immediate(body, expr_id)),
+        }
+    }
+
+    /// Render an address or a label
+    fn value(&mut self, body: &Body, expr_id: ExprId) -> String {
+        let Some(expr) = body.expr(expr_id) else {
+            return String::new();
+        };
+        match &expr.kind {
+            ExprKind::Literal(Literal::Int(address)) => {
+                let next = self.addresses.len();
+                format!("r{}", self.addresses.entry(*address).or_insert(next))
+            }
+            ExprKind::Literal(Literal::String(name) | Literal::Label(name)) => name.clone(),
+            ExprKind::LabelRef(label_ref) => body
+                .labels
+                .values()
+                .find(|label| label.id == label_ref.label_id.local_id)
+                .map(|label| label.name.clone())
+                .unwrap_or_default(),
+            ExprKind::ArrayAccess(access) => {
+                format!("{}[{}]", self.value(body, access.array), self.value(body, access.index))
+            }
+            ExprKind::Binary(binary) => format!(
+                "{}{}{}",
+                self.value(body, binary.lhs),
+                binary.op.symbol(),
+                self.value(body, binary.rhs)
+            ),
+            ExprKind::MemoryRef(_) | ExprKind::InstructionCall(_) => String::new(),
+        }
+    }
+
+    /// Render an immediate value, keeping the number it holds
+    fn immediate(&mut self, body: &Body, expr_id: ExprId) -> String {
+        match body.expr(expr_id).map(|expr| &expr.kind) {
+            Some(ExprKind::Literal(Literal::Int(value))) => value.to_string(),
+            _ => self.value(body, expr_id),
+        }
+    }
+}
+
+/// The similarity of two submissions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SimilarPair {
+    /// The first submission
+    pub first: PathBuf,
+    /// The second submission
+    pub second: PathBuf,
+    /// How similar the submissions are, from 0 to 1
+    pub similarity: f64,
+}
+
+/// Compare every pair of RAM programs in a directory and print their similarity
+///
+/// Pairs are sorted from the most to the least similar, and pairs less similar
+/// than `threshold` are left out.
+pub fn report_similarity(
+    submissions: &Path,
+    n: usize,
+    threshold: f64,
+    format: SimilarityFormat,
+) -> Result<Vec<SimilarPair>> {
+    let mut paths = std::fs::read_dir(submissions)
+        .into_diagnostic()?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "ram"));
+    paths.sort();
+
+    let fingerprints = paths
+        .into_iter()
+        .map(|path| {
+            let text = std::fs::read_to_string(&path).into_diagnostic()?;
+            let (_program, body, _diagnostics) = language::lower_program(&text);
+            Ok((path, Fingerprint::new(&body, n)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let pairs = similar_pairs(&fingerprints, threshold);
+    match format {
+        SimilarityFormat::Text => {
+            for pair in &pairs {
+                println!(
+                    "{:.2}  {}  {}",
+                    pair.similarity,
+                    pair.first.display(),
+                    pair.second.display()
+                );
+            }
+        }
+        SimilarityFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&pairs).into_diagnostic()?)
+        }
+    }
+    Ok(pairs)
+}
+
+/// Compute the similarity of every pair of fingerprints, from the most similar
+pub fn similar_pairs(fingerprints: &[(PathBuf, Fingerprint)], threshold: f64) -> Vec<SimilarPair> {
+    let mut pairs = Vec::new();
+    for (index, (first, fingerprint)) in fingerprints.iter().enumerate() {
+        for (second, other) in &fingerprints[index + 1..] {
+            let similarity = fingerprint.similarity(other);
+            if similarity >= threshold {
+                pairs.push(SimilarPair {
+                    first: first.clone(),
+                    second: second.clone(),
+                    similarity,
+                });
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(source: &str) -> Fingerprint {
+        let (_program, body, _diagnostics) = language::lower_program(source);
+        Fingerprint::new(&body, DEFAULT_NGRAM)
+    }
+
+    #[test]
+    fn test_renamed_programs_are_identical() {
+        let original = fingerprint(
+            "READ 1\nREAD 2\nloop: LOAD 1\nJZERO done\nSUB =1\nSTORE 1\nJUMP loop\ndone: WRITE 2\nHALT\n",
+        );
+        let renamed = fingerprint(
+            "# Copied\nREAD 5\nREAD 9\nagain: LOAD 5\nJZERO end\nSUB =1\nSTORE 5\nJUMP again\nend: WRITE 9\nHALT\n",
+        );
+        assert_eq!(original, renamed);
+        assert_eq!(original.similarity(&renamed), 1.0);
+
+        let constants = fingerprint(
+            "READ 1\nREAD 2\nloop: LOAD 1\nJZERO done\nSUB =2\nSTORE 1\nJUMP loop\ndone: WRITE 2\nHALT\n",
+        );
+        let similarity = original.similarity(&constants);
+        assert!(similarity > 0.0 && similarity < 1.0);
+    }
+
+    #[test]
+    fn test_similar_pairs() {
+        let fingerprints = vec![
+            (PathBuf::from("a.ram"), fingerprint("READ 1\nLOAD 1\nADD =1\nWRITE 0\nHALT\n")),
+            (PathBuf::from("b.ram"), fingerprint("READ 3\nLOAD 3\nADD =1\nWRITE 0\nHALT\n")),
+            (PathBuf::from("c.ram"), fingerprint("LOAD =1\nMUL =2\nSTORE 4\nWRITE 4\nHALT\n")),
+        ];
+        let pairs = similar_pairs(&fingerprints, 0.5);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].first, PathBuf::from("a.ram"));
+        assert_eq!(pairs[0].second, PathBuf::from("b.ram"));
+        assert_eq!(pairs[0].similarity, 1.0);
+
+        assert_eq!(similar_pairs(&fingerprints, 0.0).len(), 3);
+    }
+}
@@ -0,0 +1,343 @@
+//! Module for the usage statistics of a corpus of programs
+//!
+//! Every program under a directory is analyzed on its own, and the results are
+//! aggregated into statistics of the whole corpus: how often each instruction
+//! is used, how long programs are, how many loops they have, how their labels
+//! are used and which diagnostics they run into most. Nothing leaves the
+//! machine; the report is only printed, for course staff to see which parts of
+//! the language students use and struggle with.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use hir::body::{Body, ExprKind, Literal};
+use hir::expr::ExprId;
+use hir_analysis::{AnalysisContext, DominanceAnalysis};
+use miette::{IntoDiagnostic, Result};
+use ram_diagnostics::Diagnostic;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::cli::StatsFormat;
+use crate::language;
+
+/// The number of diagnostics listed in the table
+const TABLE_DIAGNOSTICS: usize = 10;
+
+/// The statistics of a single program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+    /// The canonical names of the instructions, in program order
+    pub instructions: Vec<String>,
+    /// The number of loops of the control flow graph
+    pub loops: usize,
+    /// The names of the labels the program defines
+    pub labels: Vec<String>,
+    /// The names of the labels the instructions refer to
+    pub referenced_labels: HashSet<String>,
+    /// The code and message of every diagnostic
+    pub diagnostics: Vec<(Option<String>, String)>,
+}
+
+impl ProgramStats {
+    /// Collect the statistics of an analyzed program
+    pub fn new(body: &Body, context: &AnalysisContext, diagnostics: &[Diagnostic]) -> Self {
+        let instructions = body
+            .instructions
+            .values()
+            .map(|instruction| instruction.kind.name().to_uppercase())
+            .collect();
+        let labels = body.labels.values().map(|label| label.name.clone()).collect();
+        let referenced_labels = body
+            .instructions
+            .values()
+            .filter_map(|instruction| referred_label(body, instruction.operand?))
+            .collect();
+        let diagnostics = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code.clone(), diagnostic.message.clone()))
+            .collect();
+
+        Self { instructions, loops: count_loops(context), labels, referenced_labels, diagnostics }
+    }
+}
+
+/// Get the name of the label an operand refers to, if any
+fn referred_label(body: &Body, expr_id: ExprId) -> Option<String> {
+    match &body.expr(expr_id)?.kind {
+        ExprKind::Literal(Literal::Label(name)) => Some(name.clone()),
+        ExprKind::LabelRef(label_ref) => body
+            .labels
+            .values()
+            .find(|label| label.id == label_ref.label_id.local_id)
+            .map(|label| label.name.clone()),
+        _ => None,
+    }
+}
+
+/// Count the loops of a program
+///
+/// A loop is identified by its header, the target of the edges that jump back
+/// to an instruction dominating their source.
+fn count_loops(context: &AnalysisContext) -> usize {
+    context.get_result::<DominanceAnalysis>().map_or(0, |info| info.loops.len())
+}
+
+/// How often an instruction is used across the corpus
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstructionFrequency {
+    /// The canonical name of the instruction
+    pub instruction: String,
+    /// The number of times it is used
+    pub count: usize,
+    /// The share of all instructions it accounts for, from 0 to 1
+    pub share: f64,
+}
+
+/// How labels are used across the corpus
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LabelUsage {
+    /// The number of labels defined
+    pub defined: usize,
+    /// The number of defined labels some instruction refers to
+    pub referenced: usize,
+    /// The number of defined labels no instruction refers to
+    pub unused: usize,
+}
+
+/// How often a diagnostic is reported across the corpus
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticFrequency {
+    /// The code of the diagnostic, if it has one
+    pub code: Option<String>,
+    /// The message of its first report
+    pub message: String,
+    /// The number of times it is reported
+    pub count: usize,
+    /// The number of programs it is reported for
+    pub programs: usize,
+}
+
+/// The aggregate statistics of a corpus of programs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CorpusStats {
+    /// The number of programs
+    pub programs: usize,
+    /// The number of instructions of all programs
+    pub instructions: usize,
+    /// The average number of instructions of a program
+    pub average_length: f64,
+    /// The number of loops of all programs
+    pub loops: usize,
+    /// The number of programs with at least one loop
+    pub programs_with_loops: usize,
+    /// The instructions, from the most to the least used
+    pub instruction_frequency: Vec<InstructionFrequency>,
+    /// How labels are used
+    pub labels: LabelUsage,
+    /// The diagnostics, from the most to the least reported
+    pub diagnostics: Vec<DiagnosticFrequency>,
+}
+
+impl CorpusStats {
+    /// Aggregate the statistics of the programs of a corpus
+    pub fn new(programs: &[ProgramStats]) -> Self {
+        let mut instruction_counts = BTreeMap::<&str, usize>::new();
+        let mut diagnostic_counts = BTreeMap::<&str, DiagnosticFrequency>::new();
+        let mut labels = LabelUsage::default();
+        for program in programs {
+            for instruction in &program.instructions {
+                *instruction_counts.entry(instruction).or_default() += 1;
+            }
+
+            labels.defined += program.labels.len();
+            labels.referenced += program
+                .labels
+                .iter()
+                .filter(|label| program.referenced_labels.contains(*label))
+                .count();
+
+            let mut seen = HashSet::new();
+            for (code, message) in &program.diagnostics {
+                let key = code.as_deref().unwrap_or(message);
+                let frequency =
+                    diagnostic_counts.entry(key).or_insert_with(|| DiagnosticFrequency {
+                        code: code.clone(),
+                        message: message.clone(),
+                        count: 0,
+                        programs: 0,
+                    });
+                frequency.count += 1;
+                if seen.insert(key) {
+                    frequency.programs += 1;
+                }
+            }
+        }
+        labels.unused = labels.defined - labels.referenced;
+
+        let instructions = programs.iter().map(|program| program.instructions.len()).sum();
+        let mut instruction_frequency = instruction_counts
+            .into_iter()
+            .map(|(instruction, count)| InstructionFrequency {
+                instruction: instruction.to_string(),
+                count,
+                share: count as f64 / instructions as f64,
+            })
+            .collect::<Vec<_>>();
+        instruction_frequency.sort_by(|a, b| b.count.cmp(&a.count));
+        let mut diagnostics = diagnostic_counts.into_values().collect::<Vec<_>>();
+        diagnostics.sort_by(|a, b| b.count.cmp(&a.count));
+
+        Self {
+            programs: programs.len(),
+            instructions,
+            average_length: if programs.is_empty() {
+                0.0
+            } else {
+                instructions as f64 / programs.len() as f64
+            },
+            loops: programs.iter().map(|program| program.loops).sum(),
+            programs_with_loops: programs.iter().filter(|program| program.loops > 0).count(),
+            instruction_frequency,
+            labels,
+            diagnostics,
+        }
+    }
+
+    /// Render the statistics as tables of plain text
+    pub fn to_table(&self) -> String {
+        let mut table = String::new();
+        table.push_str(&format!("Programs:        {}\n", self.programs));
+        table.push_str(&format!("Instructions:    {}\n", self.instructions));
+        table.push_str(&format!("Average length:  {:.1}\n", self.average_length));
+        table.push_str(&format!(
+            "Loops:           {} in {} programs\n",
+            self.loops, self.programs_with_loops
+        ));
+        table.push_str(&format!(
+            "Labels:          {} defined, {} referenced, {} unused\n",
+            self.labels.defined, self.labels.referenced, self.labels.unused
+        ));
+
+        if !self.instruction_frequency.is_empty() {
+            table.push_str("\nInstruction     Count   Share  Histogram\n");
+            let largest = self.instruction_frequency[0].count;
+            for frequency in &self.instruction_frequency {
+                table.push_str(&format!(
+                    "{:<14} {:>6} {:>6.1}%  {}\n",
+                    frequency.instruction,
+                    frequency.count,
+                    frequency.share * 100.0,
+                    "#".repeat((frequency.count * 30).div_ceil(largest))
+                ));
+            }
+        }
+
+        if !self.diagnostics.is_empty() {
+            table.push_str("\nDiagnostic      Count  Programs  Message\n");
+            for frequency in self.diagnostics.iter().take(TABLE_DIAGNOSTICS) {
+                table.push_str(&format!(
+                    "{:<14} {:>6} {:>9}  {}\n",
+                    frequency.code.as_deref().unwrap_or("-"),
+                    frequency.count,
+                    frequency.programs,
+                    frequency.message
+                ));
+            }
+        }
+        table
+    }
+}
+
+/// Analyze every RAM program under a directory and print the statistics of them
+pub fn report_stats(dir: &Path, format: StatsFormat) -> Result<CorpusStats> {
+    let mut paths = WalkDir::new(dir)
+        .into_iter()
+        .map(|entry| entry.map(|entry| entry.into_path()))
+        .collect::<walkdir::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "ram"));
+    paths.sort();
+
+    let programs = paths
+        .iter()
+        .map(|path| {
+            let text = std::fs::read_to_string(path).into_diagnostic()?;
+            let config = language::diagnostic_config_for(path, false)?;
+            let (_program, body, _pipeline, context, diagnostics) =
+                language::analyze_program(&text, &config);
+            Ok(ProgramStats::new(&body, &context, &diagnostics))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let stats = CorpusStats::new(&programs);
+    match format {
+        StatsFormat::Table => print!("{}", stats.to_table()),
+        StatsFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&stats).into_diagnostic()?)
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use ram_diagnostics::DiagnosticConfig;
+
+    use super::*;
+
+    fn program_stats(source: &str) -> ProgramStats {
+        let (_program, body, _pipeline, context, diagnostics) =
+            language::analyze_program(source, &DiagnosticConfig::default());
+        ProgramStats::new(&body, &context, &diagnostics)
+    }
+
+    #[test]
+    fn test_program_stats() {
+        let stats = program_stats(
+            "READ 1\nloop: LOAD 1\nJZERO done\nSUB =1\nSTORE 1\nJUMP loop\nunused: WRITE 1\ndone: HALT\n",
+        );
+        assert_eq!(
+            stats.instructions,
+            ["READ", "LOAD", "JZERO", "SUB", "STORE", "JUMP", "WRITE", "HALT"]
+        );
+        assert_eq!(stats.loops, 1);
+        assert_eq!(stats.labels, ["loop", "unused", "done"]);
+        assert_eq!(
+            stats.referenced_labels,
+            HashSet::from(["loop".to_string(), "done".to_string()])
+        );
+
+        assert_eq!(program_stats("READ 1\nWRITE 1\nHALT\n").loops, 0);
+    }
+
+    #[test]
+    fn test_corpus_stats() {
+        let programs = [
+            program_stats(
+                "READ 1\nloop: LOAD 1\nJZERO done\nSUB =1\nSTORE 1\nJUMP loop\ndone: HALT\n",
+            ),
+            program_stats("READ 1\nLOAD 1\nHALT\n"),
+            program_stats("LOAD 1\nJUMP missing\n"),
+        ];
+        let stats = CorpusStats::new(&programs);
+        assert_eq!(stats.programs, 3);
+        assert_eq!(stats.instructions, 12);
+        assert_eq!(stats.average_length, 4.0);
+        assert_eq!(stats.loops, 1);
+        assert_eq!(stats.programs_with_loops, 1);
+        assert_eq!(stats.labels, LabelUsage { defined: 2, referenced: 2, unused: 0 });
+
+        let load = &stats.instruction_frequency[0];
+        assert_eq!((load.instruction.as_str(), load.count), ("LOAD", 3));
+        assert_eq!(load.share, 0.25);
+
+        // Only the jump to a missing label is reported
+        assert!(!stats.diagnostics.is_empty());
+        assert!(stats.diagnostics.iter().all(|frequency| frequency.programs == 1));
+
+        let table = stats.to_table();
+        assert!(table.contains("Average length:  4.0"));
+        assert!(table.contains("LOAD"));
+    }
+}
@@ -12,9 +12,10 @@ use tracing::level_filters::LevelFilter;
 use tracing_subscriber::fmt::time::UtcTime;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{EnvFilter, Registry, reload};
+use tracing_subscriber::{EnvFilter, Layer, Registry, reload};
 
-use crate::cli::Cli;
+use crate::chrome_trace::{ChromeTraceLayer, DEFAULT_TRACE_FILE};
+use crate::cli::{Cli, TraceFormat};
 use crate::color;
 use crate::color::ColorChoice;
 
@@ -29,6 +30,7 @@ pub struct TracingControls {
     pub file_enabled: Arc<RwLock<bool>>,
     pub log_path: Arc<RwLock<Option<PathBuf>>>,
     pub use_ansi: Arc<RwLock<bool>>,
+    pub chrome_trace: ChromeTraceLayer,
 }
 
 impl TracingControls {
@@ -49,9 +51,6 @@ impl TracingControls {
         // Create a reloadable filter layer
         let (filter_layer, filter_reload) = reload::Layer::new(initial_filter);
 
-        // Start with base registry + filter
-        let subscriber = registry.with(filter_layer);
-
         // Create conditional stdout layer
         let stdout_enabled_clone = Arc::clone(&stdout_enabled);
         let use_ansi_clone = Arc::clone(&use_ansi);
@@ -85,11 +84,19 @@ impl TracingControls {
             .with_ansi(false)
             .with_timer(UtcTime::rfc_3339());
 
-        // Initialize the subscriber with all configured layers
-        subscriber.with(stdout_layer).with(file_layer).init();
+        // Record spans for traces regardless of the log filter
+        let chrome_trace = ChromeTraceLayer::new();
+        let trace_layer = chrome_trace.clone().with_filter(chrome_trace.clone());
+
+        // Initialize the subscriber with all configured layers, the filter
+        // only applying to the logs
+        registry
+            .with(stdout_layer.and_then(file_layer).with_filter(filter_layer))
+            .with(trace_layer)
+            .init();
 
         // Return handles to control logging at runtime
-        Self { filter_reload, stdout_enabled, file_enabled, log_path, use_ansi }
+        Self { filter_reload, stdout_enabled, file_enabled, log_path, use_ansi, chrome_trace }
     }
 
     /// Create tracing controls from CLI arguments
@@ -145,6 +152,11 @@ impl TracingControls {
         self.log_path.read().unwrap().clone()
     }
 
+    /// Write the recorded trace to its file, if tracing was enabled
+    pub fn finish_trace(&self) -> io::Result<()> {
+        self.chrome_trace.finish()
+    }
+
     /// Update controls from CLI arguments
     pub fn update_from_cli(&self, cli: &Cli) {
         // Update log level
@@ -166,6 +178,12 @@ impl TracingControls {
         let log_path = cli.top_level.global_args.mirror.clone();
         self.set_log_path(log_path.clone());
         self.set_file_enabled(log_path.is_some());
+
+        // Update trace output
+        let trace_path = cli.top_level.global_args.trace_output.map(|TraceFormat::Json| {
+            cli.top_level.global_args.trace_file.clone().unwrap_or(DEFAULT_TRACE_FILE.into())
+        });
+        self.chrome_trace.set_path(trace_path);
     }
 }
 
@@ -2,6 +2,8 @@ use core::fmt;
 use std::sync::LazyLock;
 
 use owo_colors::{OwoColorize, Style};
+use ram_core::{INSTRUCTION_SET_REGISTRY, PLUGIN_ABI_VERSION};
+use ram_vm::bytecode;
 use serde::Serialize;
 use shadow_rs::formatcp;
 use taplo::formatter::Options;
@@ -13,6 +15,9 @@ use crate::color::ColorChoice;
 /// Global VERSION instance that can be accessed from anywhere
 pub static VERSION: LazyLock<Version<'static>> = LazyLock::new(Version::default);
 
+/// The cargo features of the binary, and whether each one is enabled
+const FEATURES: &[(&str, bool)] = &[("serde", cfg!(feature = "serde"))];
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Version<'a> {
     #[serde(rename = "version")]
@@ -115,6 +120,69 @@ impl Default for Version<'_> {
     }
 }
 
+/// What the binary supports, for tools to check instead of comparing versions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The cargo features the binary was built with
+    pub features: Vec<&'static str>,
+    /// The instruction sets programs can use, as passed to `--instruction-set`
+    pub instruction_sets: Vec<String>,
+    /// The version of the interface plugins must be built against
+    pub plugin_abi_version: u32,
+    /// The version of the Language Server Protocol `ram server` implements
+    pub lsp_protocol_version: &'static str,
+    /// The version of the bytecode format `ram build` writes and `ram run` loads
+    pub bytecode_format_version: u16,
+}
+
+impl Capabilities {
+    /// Collects the capabilities of this binary
+    pub fn new() -> Self {
+        let features =
+            FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect();
+        let mut instruction_sets =
+            INSTRUCTION_SET_REGISTRY.sets().map(|set| set.name.to_lowercase()).collect::<Vec<_>>();
+        instruction_sets.sort();
+
+        Self {
+            features,
+            instruction_sets,
+            plugin_abi_version: PLUGIN_ABI_VERSION,
+            lsp_protocol_version: ram_lsp::PROTOCOL_VERSION,
+            bytecode_format_version: bytecode::FORMAT_VERSION,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The version of the binary with its capabilities, as printed in JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo<'a> {
+    /// The version and build details
+    #[serde(flatten)]
+    pub version: Version<'a>,
+    /// What the binary supports
+    pub capabilities: Capabilities,
+}
+
+impl BuildInfo<'_> {
+    /// Collects the version and capabilities of this binary
+    pub fn new() -> Self {
+        Self { version: Version::new(), capabilities: Capabilities::new() }
+    }
+}
+
+impl Default for BuildInfo<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl fmt::Display for Version<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let toml = toml::to_string(&self).map_err(|_| fmt::Error)?;
@@ -167,3 +235,24 @@ impl Default for Styles {
         Self { name_style: Style::new().blue(), value_style: Style::new().yellow() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_json() {
+        let json = serde_json::to_value(BuildInfo::new()).unwrap();
+        assert_eq!(json["version"], VERSION.pkg_version());
+
+        let capabilities = &json["capabilities"];
+        assert_eq!(capabilities["features"], serde_json::json!(["serde"]));
+        assert_eq!(
+            capabilities["instruction_sets"],
+            serde_json::json!(["extended", "standard", "subroutines"])
+        );
+        assert_eq!(capabilities["plugin_abi_version"], PLUGIN_ABI_VERSION);
+        assert_eq!(capabilities["lsp_protocol_version"], ram_lsp::PROTOCOL_VERSION);
+        assert_eq!(capabilities["bytecode_format_version"], bytecode::FORMAT_VERSION);
+    }
+}
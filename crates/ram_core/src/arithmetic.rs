@@ -0,0 +1,98 @@
+//! Arithmetic semantics of the RAM virtual machine
+//!
+//! The accumulator and the memory hold 64 bit integers, so the result of an
+//! arithmetic instruction may not fit. The [`ArithmeticMode`] of a machine
+//! decides what happens then: by default the machine stops with an
+//! [`VmError::Overflow`], but it can also wrap around or saturate at the
+//! bounds instead.
+
+use std::fmt;
+
+use crate::error::VmError;
+
+/// An arithmetic operation of the accumulator with a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithmeticOp {
+    /// Addition, as done by `ADD`
+    Add,
+    /// Subtraction, as done by `SUB`
+    Sub,
+    /// Multiplication, as done by `MUL`
+    Mul,
+    /// Division, as done by `DIV`
+    Div,
+}
+
+impl fmt::Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Sub => "-",
+            ArithmeticOp::Mul => "*",
+            ArithmeticOp::Div => "/",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// What the machine does when the result of an operation does not fit in 64 bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ArithmeticMode {
+    /// Stop with an [`VmError::Overflow`]
+    #[default]
+    Checked,
+    /// Wrap around at the bounds, like two's complement hardware does
+    Wrapping,
+    /// Clamp the result to the nearest bound
+    Saturating,
+}
+
+impl ArithmeticMode {
+    /// Every mode
+    pub const ALL: [ArithmeticMode; 3] =
+        [ArithmeticMode::Checked, ArithmeticMode::Wrapping, ArithmeticMode::Saturating];
+
+    /// Get the name of the mode, as written in settings
+    pub fn name(self) -> &'static str {
+        match self {
+            ArithmeticMode::Checked => "checked",
+            ArithmeticMode::Wrapping => "wrapping",
+            ArithmeticMode::Saturating => "saturating",
+        }
+    }
+
+    /// Find a mode by its name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Apply an operation to the accumulator and a value
+    ///
+    /// Dividing by zero is an error in every mode.
+    pub fn apply(self, op: ArithmeticOp, lhs: i64, rhs: i64) -> Result<i64, VmError> {
+        if op == ArithmeticOp::Div && rhs == 0 {
+            return Err(VmError::DivisionByZero);
+        }
+        let result = match self {
+            ArithmeticMode::Checked => match op {
+                ArithmeticOp::Add => lhs.checked_add(rhs),
+                ArithmeticOp::Sub => lhs.checked_sub(rhs),
+                ArithmeticOp::Mul => lhs.checked_mul(rhs),
+                ArithmeticOp::Div => lhs.checked_div(rhs),
+            },
+            ArithmeticMode::Wrapping => Some(match op {
+                ArithmeticOp::Add => lhs.wrapping_add(rhs),
+                ArithmeticOp::Sub => lhs.wrapping_sub(rhs),
+                ArithmeticOp::Mul => lhs.wrapping_mul(rhs),
+                ArithmeticOp::Div => lhs.wrapping_div(rhs),
+            }),
+            ArithmeticMode::Saturating => Some(match op {
+                ArithmeticOp::Add => lhs.saturating_add(rhs),
+                ArithmeticOp::Sub => lhs.saturating_sub(rhs),
+                ArithmeticOp::Mul => lhs.saturating_mul(rhs),
+                ArithmeticOp::Div => lhs.saturating_div(rhs),
+            }),
+        };
+        result.ok_or(VmError::Overflow { operation: op, lhs, rhs })
+    }
+}
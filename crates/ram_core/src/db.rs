@@ -4,6 +4,7 @@ use std::sync::Arc;
 
 use salsa::Database;
 
+use crate::arithmetic::ArithmeticMode;
 use crate::error::VmError;
 use crate::instruction::{InstructionDefinition, InstructionKind};
 use crate::operand::Operand;
@@ -73,4 +74,28 @@ pub trait VmState {
 
     /// Resolve a label to a program counter value
     fn resolve_label(&self, label: &str) -> Result<usize, VmError>;
+
+    /// Get what the machine does when an arithmetic result does not fit
+    fn arithmetic_mode(&self) -> ArithmeticMode {
+        ArithmeticMode::Checked
+    }
+
+    /// Get the next number of the random number generator of the machine
+    ///
+    /// Machines without a generator can not run the instructions that need one.
+    fn next_random(&mut self) -> Result<u64, VmError> {
+        Err(VmError::InvalidInstruction("This machine does not support random numbers".to_string()))
+    }
+
+    /// Push the address a subroutine returns to onto the return stack
+    ///
+    /// Machines without a return stack can not call subroutines.
+    fn push_return_address(&mut self, _address: usize) -> Result<(), VmError> {
+        Err(VmError::InvalidInstruction("This machine does not support subroutines".to_string()))
+    }
+
+    /// Pop the address the current subroutine returns to from the return stack
+    fn pop_return_address(&mut self) -> Result<usize, VmError> {
+        Err(VmError::InvalidInstruction("This machine does not support subroutines".to_string()))
+    }
 }
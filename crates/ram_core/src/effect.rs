@@ -0,0 +1,189 @@
+//! Effects of instructions on the state of the machine
+//!
+//! Instructions declare what they read and write, whether they jump and
+//! whether they halt. Analyses interpret these effects instead of matching
+//! instruction names, so custom instructions are analyzed like standard ones.
+
+/// How an instruction computes the new value of the accumulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccumulatorValue {
+    /// The value of the operand
+    Operand,
+    /// The accumulator plus the value of the operand
+    Add,
+    /// The accumulator minus the value of the operand
+    Sub,
+    /// The accumulator times the value of the operand
+    Mul,
+    /// The accumulator divided by the value of the operand
+    Div,
+    /// A value that cannot be determined statically
+    Unknown,
+}
+
+impl AccumulatorValue {
+    /// Compute the new value of the accumulator
+    ///
+    /// Returns `None` if it cannot be determined from the given values, as
+    /// when either is unknown, the operation overflows or divides by zero.
+    pub fn evaluate(self, accumulator: Option<i64>, operand: Option<i64>) -> Option<i64> {
+        match self {
+            AccumulatorValue::Operand => operand,
+            AccumulatorValue::Add => accumulator?.checked_add(operand?),
+            AccumulatorValue::Sub => accumulator?.checked_sub(operand?),
+            AccumulatorValue::Mul => accumulator?.checked_mul(operand?),
+            AccumulatorValue::Div => accumulator?.checked_div(operand?),
+            AccumulatorValue::Unknown => None,
+        }
+    }
+}
+
+/// The condition under which a jump is taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JumpCondition {
+    /// The jump is always taken
+    Always,
+    /// The jump is taken if the accumulator is greater than zero
+    Positive,
+    /// The jump is taken if the accumulator is zero
+    Zero,
+}
+
+impl JumpCondition {
+    /// Check whether the jump is taken with the given accumulator value
+    pub fn holds(self, accumulator: i64) -> bool {
+        match self {
+            JumpCondition::Always => true,
+            JumpCondition::Positive => accumulator > 0,
+            JumpCondition::Zero => accumulator == 0,
+        }
+    }
+}
+
+/// An effect of an instruction on the state of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Effect {
+    /// Reads the accumulator
+    ReadsAccumulator,
+    /// Writes the accumulator
+    WritesAccumulator(AccumulatorValue),
+    /// Reads the value of the operand, from memory unless it is immediate
+    ReadsOperand,
+    /// Writes the register the operand refers to
+    WritesOperand,
+    /// Jumps to the label of the operand
+    Jumps(JumpCondition),
+    /// Calls the subroutine at the label of the operand
+    Calls,
+    /// Returns from the current subroutine
+    Returns,
+    /// Stops the program
+    Halts,
+    /// Reads a value from the input
+    ReadsInput,
+    /// Writes a value to the output
+    WritesOutput,
+}
+
+/// The effects of an instruction
+///
+/// An instruction without effects does nothing and continues with the next
+/// instruction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstructionEffects {
+    /// The effects, in no particular order
+    effects: Vec<Effect>,
+}
+
+impl InstructionEffects {
+    /// Create an empty set of effects
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The effects assumed for instructions that do not declare theirs
+    ///
+    /// The instruction reads the accumulator and its operand, and leaves an
+    /// unknown value in the accumulator.
+    pub fn unknown() -> Self {
+        Self::new()
+            .with(Effect::ReadsAccumulator)
+            .with(Effect::ReadsOperand)
+            .with(Effect::WritesAccumulator(AccumulatorValue::Unknown))
+    }
+
+    /// Add an effect
+    pub fn with(mut self, effect: Effect) -> Self {
+        if !self.effects.contains(&effect) {
+            self.effects.push(effect);
+        }
+        self
+    }
+
+    /// Get all effects
+    pub fn iter(&self) -> impl Iterator<Item = &Effect> {
+        self.effects.iter()
+    }
+
+    /// Check whether the instruction has an effect
+    pub fn contains(&self, effect: Effect) -> bool {
+        self.effects.contains(&effect)
+    }
+
+    /// Check whether the instruction reads the accumulator
+    pub fn reads_accumulator(&self) -> bool {
+        self.contains(Effect::ReadsAccumulator)
+    }
+
+    /// Get how the instruction computes the accumulator, if it writes it
+    pub fn accumulator_value(&self) -> Option<AccumulatorValue> {
+        self.effects.iter().find_map(|effect| match effect {
+            Effect::WritesAccumulator(value) => Some(*value),
+            _ => None,
+        })
+    }
+
+    /// Check whether the instruction reads its operand
+    pub fn reads_operand(&self) -> bool {
+        self.contains(Effect::ReadsOperand)
+    }
+
+    /// Check whether the instruction writes the register its operand refers to
+    pub fn writes_operand(&self) -> bool {
+        self.contains(Effect::WritesOperand)
+    }
+
+    /// Get the condition under which the instruction jumps, if it does
+    pub fn jump_condition(&self) -> Option<JumpCondition> {
+        self.effects.iter().find_map(|effect| match effect {
+            Effect::Jumps(condition) => Some(*condition),
+            _ => None,
+        })
+    }
+
+    /// Check whether the instruction calls a subroutine
+    pub fn calls(&self) -> bool {
+        self.contains(Effect::Calls)
+    }
+
+    /// Check whether the instruction returns from a subroutine
+    pub fn returns(&self) -> bool {
+        self.contains(Effect::Returns)
+    }
+
+    /// Check whether the instruction stops the program
+    pub fn halts(&self) -> bool {
+        self.contains(Effect::Halts)
+    }
+
+    /// Check whether the instruction reads from the input or writes to the output
+    pub fn performs_io(&self) -> bool {
+        self.contains(Effect::ReadsInput) || self.contains(Effect::WritesOutput)
+    }
+}
+
+impl FromIterator<Effect> for InstructionEffects {
+    fn from_iter<I: IntoIterator<Item = Effect>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::new(), Self::with)
+    }
+}
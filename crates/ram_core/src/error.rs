@@ -3,6 +3,9 @@
 use miette::*;
 use thiserror::Error;
 
+use crate::arithmetic::ArithmeticOp;
+use crate::operand::OperandKind;
+
 /// Errors that can occur during VM execution
 #[derive(Debug, Diagnostic, Error)]
 pub enum VmError {
@@ -18,6 +21,17 @@ pub enum VmError {
     #[error("Division by zero")]
     DivisionByZero,
 
+    /// The result of an arithmetic instruction does not fit in 64 bits
+    #[error("Arithmetic overflow: {lhs} {operation} {rhs} does not fit in 64 bits")]
+    Overflow {
+        /// The operation that overflowed
+        operation: ArithmeticOp,
+        /// The value of the accumulator
+        lhs: i64,
+        /// The value of the operand
+        rhs: i64,
+    },
+
     /// Invalid instruction
     #[error("Invalid instruction: {0}")]
     InvalidInstruction(String),
@@ -30,10 +44,95 @@ pub enum VmError {
     #[error("IO error: {0}")]
     IoError(String),
 
+    /// A return was executed outside of a subroutine
+    #[error("Return without a matching call")]
+    EmptyReturnStack,
+
+    /// Too many subroutine calls are nested
+    #[error("Return stack overflow: more than {0} nested calls")]
+    ReturnStackOverflow(usize),
+
+    /// Execution continued past the last instruction without halting
+    #[error("Execution fell off the end of the program without a HALT")]
+    FellOffEnd,
+
+    /// The program read or wrote a different number of values than its
+    /// input/output contract declares
+    #[error(
+        "Contract violation: the program {verb} {actual} values, but its contract declares {declared}"
+    )]
+    ContractViolation {
+        /// What the program did with the values, `read` or `written`
+        verb: &'static str,
+        /// The number of values the program read or wrote
+        actual: usize,
+        /// The number of values the contract declares
+        declared: usize,
+    },
+
     /// Program terminated
     #[error("Program terminated")]
     ProgramTerminated,
 
+    /// The run was cancelled before the program halted
+    #[error("Execution was cancelled after {0} steps")]
+    Cancelled(u64),
+
     #[error("Unknown error: {0}")]
     UnknownError(String),
 }
+
+/// Why an operand is not valid for an instruction
+///
+/// Both the analysis of a program and the virtual machine check operands
+/// with [`InstructionDefinition::validate_operand`], so a program is rejected
+/// for the same reasons whether it is checked or run.
+///
+/// [`InstructionDefinition::validate_operand`]: crate::InstructionDefinition::validate_operand
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OperandError {
+    /// The instruction requires an operand, but has none
+    #[error("{instruction} requires an operand")]
+    Missing {
+        /// The name of the instruction
+        instruction: String,
+    },
+
+    /// The instruction takes no operand, but has one
+    #[error("{instruction} does not take an operand")]
+    Unexpected {
+        /// The name of the instruction
+        instruction: String,
+    },
+
+    /// The instruction does not accept operands of this kind
+    #[error("{instruction} does not accept {kind} operands")]
+    UnsupportedKind {
+        /// The name of the instruction
+        instruction: String,
+        /// The kind of the operand
+        kind: OperandKind,
+        /// The kinds the instruction accepts
+        allowed: Vec<OperandKind>,
+    },
+
+    /// A jump or call has an immediate operand instead of a label
+    #[error("{instruction} can not jump to an immediate value")]
+    ImmediateJump {
+        /// The name of the instruction
+        instruction: String,
+    },
+
+    /// The operand refers to a negative address
+    #[error("Negative address: {address}")]
+    NegativeAddress {
+        /// The negative address
+        address: i64,
+    },
+}
+
+impl From<OperandError> for VmError {
+    fn from(error: OperandError) -> Self {
+        VmError::InvalidOperand(error.to_string())
+    }
+}
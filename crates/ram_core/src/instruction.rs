@@ -4,7 +4,9 @@ use std::fmt;
 use std::sync::Arc;
 
 use crate::db::VmState;
-use crate::error::VmError;
+use crate::effect::{AccumulatorValue, Effect, InstructionEffects, JumpCondition};
+use crate::error::{OperandError, VmError};
+use crate::metadata::{CostModel, InstructionMetadata};
 use crate::operand::{Operand, OperandKind};
 
 /// Information about an instruction
@@ -18,6 +20,8 @@ pub struct InstructionInfo {
     pub allowed_operand_kinds: Vec<OperandKind>,
     /// A description of the instruction
     pub description: String,
+    /// The documentation and other metadata of the instruction
+    pub metadata: InstructionMetadata,
 }
 
 /// An instruction in the RAM virtual machine
@@ -27,27 +31,49 @@ pub struct Instruction {
     pub kind: InstructionKind,
     /// The operand for the instruction (if any)
     pub operand: Option<Operand>,
+    /// How the values the instruction writes to the output are shown
+    pub output_format: OutputFormat,
+}
+
+/// How values written to the output are shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// As numbers
+    #[default]
+    Number,
+    /// As the characters whose code points they are
+    Char,
 }
 
 impl Instruction {
     /// Create a new instruction
     pub fn new(kind: InstructionKind, operand: Option<Operand>) -> Self {
-        Self { kind, operand }
+        Self { kind, operand, output_format: OutputFormat::default() }
     }
 
     /// Create a new instruction with no operand
     pub fn without_operand(kind: InstructionKind) -> Self {
-        Self { kind, operand: None }
+        Self::new(kind, None)
     }
 
     /// Create a new instruction with an operand
     pub fn with_operand(kind: InstructionKind, operand: Operand) -> Self {
-        Self { kind, operand: Some(operand) }
+        Self::new(kind, Some(operand))
+    }
+
+    /// Set how the values the instruction writes to the output are shown
+    pub fn with_output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
     }
 
     /// Validate that the instruction has the correct operands
     pub fn validate(&self) -> Result<(), VmError> {
-        self.kind.validate_operand(self.operand.as_ref())
+        self.kind.validate_arity(self.operand.is_some())?;
+        if let Some(operand) = &self.operand {
+            self.kind.validate_operand(operand)?;
+        }
+        Ok(())
     }
 
     /// Execute the instruction on the given VM state
@@ -76,37 +102,65 @@ pub trait InstructionDefinition: Send + Sync + 'static {
     /// Get the allowed operand kinds for this instruction
     fn allowed_operand_kinds(&self) -> &[OperandKind];
 
-    /// Validate that the operand is valid for this instruction
-    fn validate_operand(&self, operand: Option<&Operand>) -> Result<(), VmError> {
-        if self.requires_operand() && operand.is_none() {
-            return Err(VmError::InvalidOperand(format!("{} requires an operand", self.name())));
-        }
+    /// Get the documentation and other metadata of the instruction
+    ///
+    /// Instructions without metadata are documented by their name alone.
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionMetadata::default()
+    }
+
+    /// Get the effects of the instruction on the state of the machine
+    ///
+    /// Instructions that do not declare their effects are assumed to have
+    /// [unknown effects](InstructionEffects::unknown).
+    fn effects(&self) -> InstructionEffects {
+        InstructionEffects::unknown()
+    }
 
-        if !self.requires_operand() && operand.is_some() {
-            return Err(VmError::InvalidOperand(format!(
-                "{} does not accept an operand",
-                self.name()
-            )));
+    /// Validate that the instruction has an operand exactly when it requires one
+    fn validate_arity(&self, has_operand: bool) -> Result<(), OperandError> {
+        let instruction = self.name().to_string();
+        match (self.requires_operand(), has_operand) {
+            (true, false) => Err(OperandError::Missing { instruction }),
+            (false, true) => Err(OperandError::Unexpected { instruction }),
+            _ => Ok(()),
         }
+    }
 
-        if let Some(operand) = operand
-            && !self.allowed_operand_kinds().contains(&operand.kind)
+    /// Validate that the instruction accepts operands of a kind
+    ///
+    /// Jumps and calls go to a label, so they never accept immediate operands,
+    /// whatever kinds they allow.
+    fn validate_operand_kind(&self, kind: OperandKind) -> Result<(), OperandError> {
+        let effects = self.effects();
+        if kind == OperandKind::Immediate && (effects.jump_condition().is_some() || effects.calls())
         {
-            return Err(VmError::InvalidOperand(format!(
-                "{} does not accept {} operands",
-                self.name(),
-                match operand.kind {
-                    OperandKind::Direct => "direct",
-                    OperandKind::Indirect => "indirect",
-                    OperandKind::Immediate => "immediate",
-                    OperandKind::Indexed => "indexed",
-                }
-            )));
+            return Err(OperandError::ImmediateJump { instruction: self.name().to_string() });
+        }
+
+        let allowed = self.allowed_operand_kinds();
+        if !allowed.contains(&kind) {
+            return Err(OperandError::UnsupportedKind {
+                instruction: self.name().to_string(),
+                kind,
+                allowed: allowed.to_vec(),
+            });
         }
 
         Ok(())
     }
 
+    /// Validate that the operand is valid for this instruction
+    ///
+    /// Besides its kind, the operand must not refer to a negative address.
+    fn validate_operand(&self, operand: &Operand) -> Result<(), OperandError> {
+        self.validate_operand_kind(operand.kind)?;
+        match operand.negative_address() {
+            Some(address) => Err(OperandError::NegativeAddress { address }),
+            None => Ok(()),
+        }
+    }
+
     /// Execute the instruction with the given operand and VM state
     fn execute(&self, operand: Option<&Operand>, vm_state: &mut dyn VmState)
     -> Result<(), VmError>;
@@ -168,6 +222,16 @@ impl InstructionKind {
         !matches!(self, Self::Halt)
     }
 
+    /// Check if the instruction jumps to a label, conditionally or not
+    pub fn is_jump(&self) -> bool {
+        matches!(self, Self::Jump | Self::JumpGtz | Self::JumpZero)
+    }
+
+    /// Check if the instruction jumps to a label depending on the accumulator
+    pub fn is_conditional_jump(&self) -> bool {
+        matches!(self, Self::JumpGtz | Self::JumpZero)
+    }
+
     /// Get the allowed operand kinds for this instruction
     pub fn allowed_operand_kinds(&self) -> &[OperandKind] {
         static ALL_KINDS: [OperandKind; 4] = [
@@ -202,6 +266,142 @@ impl InstructionKind {
         }
     }
 
+    /// Get the documentation and other metadata of the instruction
+    ///
+    /// The summary is the description of the instruction. Custom instructions
+    /// have no further metadata.
+    pub fn metadata(&self) -> InstructionMetadata {
+        const VALUE: &str = "A value: immediate (`=n`), direct (`n`), indirect (`*n`) or indexed \
+                             (`n[m]`).";
+        const ADDRESS: &str = "A register: direct (`n`), indirect (`*n`) or indexed (`n[m]`). \
+                               Immediate operands are not allowed.";
+        const LABEL: &str = "The label of the instruction to jump to.";
+
+        let metadata = InstructionMetadata::new(self.description());
+        match self {
+            Self::Load => metadata
+                .with_operand_constraints(VALUE)
+                .with_semantics("Sets the accumulator to the value of the operand.")
+                .with_example("LOAD =5", "Sets the accumulator to 5")
+                .with_example("LOAD 3", "Copies register 3 into the accumulator")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "t(a)"),
+            Self::Store => metadata
+                .with_operand_constraints(ADDRESS)
+                .with_semantics("Copies the accumulator into the register the operand refers to.")
+                .with_example("STORE 2", "Copies the accumulator into register 2")
+                .with_example("STORE 3[1]", "Copies the accumulator into register 3 + c(1)")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0)) + t(a)"),
+            Self::Add => metadata
+                .with_operand_constraints(VALUE)
+                .with_semantics("Adds the value of the operand to the accumulator.")
+                .with_example("ADD =1", "Increments the accumulator")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0)) + t(a)"),
+            Self::Sub => metadata
+                .with_operand_constraints(VALUE)
+                .with_semantics("Subtracts the value of the operand from the accumulator.")
+                .with_example("SUB 2", "Subtracts register 2 from the accumulator")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0)) + t(a)"),
+            Self::Mul => metadata
+                .with_operand_constraints(VALUE)
+                .with_semantics("Multiplies the accumulator by the value of the operand.")
+                .with_example("MUL =2", "Doubles the accumulator")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0)) + t(a)"),
+            Self::Div => metadata
+                .with_operand_constraints(VALUE)
+                .with_semantics(
+                    "Divides the accumulator by the value of the operand, discarding the \
+                     remainder. Dividing by zero is an error.",
+                )
+                .with_example("DIV =2", "Halves the accumulator")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0)) + t(a)"),
+            Self::Jump => metadata
+                .with_operand_constraints(LABEL)
+                .with_semantics("Continues execution at the label.")
+                .with_example("JUMP loop", "Continues at `loop`")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "1"),
+            Self::JumpGtz => metadata
+                .with_operand_constraints(LABEL)
+                .with_semantics(
+                    "Continues execution at the label if the accumulator is greater than zero, \
+                     and at the next instruction otherwise.",
+                )
+                .with_example("JGTZ loop", "Continues at `loop` while the accumulator is positive")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0))"),
+            Self::JumpZero => metadata
+                .with_operand_constraints(LABEL)
+                .with_semantics(
+                    "Continues execution at the label if the accumulator is zero, and at the \
+                     next instruction otherwise.",
+                )
+                .with_example("JZERO done", "Continues at `done` once the accumulator is zero")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(c(0))"),
+            Self::Read => metadata
+                .with_operand_constraints(ADDRESS)
+                .with_semantics(
+                    "Reads the next value of the input into the register the operand refers to.",
+                )
+                .with_example("READ 1", "Reads the next input value into register 1")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "l(input) + t(a)"),
+            Self::Write => metadata
+                .with_operand_constraints(VALUE)
+                .with_semantics("Appends the value of the operand to the output.")
+                .with_example("WRITE 0", "Writes the accumulator to the output")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "t(a)"),
+            Self::Halt => metadata
+                .with_operand_constraints("No operand.")
+                .with_semantics("Stops the program.")
+                .with_example("HALT", "Stops the program")
+                .with_cost(CostModel::Uniform, "1")
+                .with_cost(CostModel::Logarithmic, "1"),
+            Self::Custom(_) => metadata,
+        }
+    }
+
+    /// Get the effects of the instruction on the state of the machine
+    ///
+    /// Custom instructions have [unknown effects](InstructionEffects::unknown).
+    pub fn effects(&self) -> InstructionEffects {
+        let effects = InstructionEffects::new();
+        let arithmetic = |value| {
+            InstructionEffects::new()
+                .with(Effect::ReadsAccumulator)
+                .with(Effect::ReadsOperand)
+                .with(Effect::WritesAccumulator(value))
+        };
+        match self {
+            Self::Load => effects
+                .with(Effect::ReadsOperand)
+                .with(Effect::WritesAccumulator(AccumulatorValue::Operand)),
+            Self::Store => effects.with(Effect::ReadsAccumulator).with(Effect::WritesOperand),
+            Self::Add => arithmetic(AccumulatorValue::Add),
+            Self::Sub => arithmetic(AccumulatorValue::Sub),
+            Self::Mul => arithmetic(AccumulatorValue::Mul),
+            Self::Div => arithmetic(AccumulatorValue::Div),
+            Self::Jump => effects.with(Effect::Jumps(JumpCondition::Always)),
+            Self::JumpGtz => {
+                effects.with(Effect::ReadsAccumulator).with(Effect::Jumps(JumpCondition::Positive))
+            }
+            Self::JumpZero => {
+                effects.with(Effect::ReadsAccumulator).with(Effect::Jumps(JumpCondition::Zero))
+            }
+            Self::Read => effects.with(Effect::ReadsInput).with(Effect::WritesOperand),
+            Self::Write => effects.with(Effect::ReadsOperand).with(Effect::WritesOutput),
+            Self::Halt => effects.with(Effect::Halts),
+            Self::Custom(_) => InstructionEffects::unknown(),
+        }
+    }
+
     /// Get information about the instruction
     pub fn info(&self) -> InstructionInfo {
         InstructionInfo {
@@ -209,6 +409,7 @@ impl InstructionKind {
             requires_operand: self.requires_operand(),
             allowed_operand_kinds: self.allowed_operand_kinds().to_vec(),
             description: self.description().to_string(),
+            metadata: self.metadata(),
         }
     }
 
@@ -267,34 +468,6 @@ impl InstructionKind {
         }
     }
 
-    /// Validate that the operand is valid for this instruction
-    pub fn validate_operand(&self, operand: Option<&Operand>) -> Result<(), VmError> {
-        if self.requires_operand() && operand.is_none() {
-            return Err(VmError::InvalidOperand(format!("{} requires an operand", self.name())));
-        }
-        if !self.requires_operand() && operand.is_some() {
-            return Err(VmError::InvalidOperand(format!(
-                "{} does not accept an operand",
-                self.name()
-            )));
-        }
-        if let Some(operand) = operand
-            && !self.allowed_operand_kinds().contains(&operand.kind)
-        {
-            return Err(VmError::InvalidOperand(format!(
-                "{} does not accept {} operands",
-                self.name(),
-                match operand.kind {
-                    OperandKind::Direct => "direct",
-                    OperandKind::Indirect => "indirect",
-                    OperandKind::Immediate => "immediate",
-                    OperandKind::Indexed => "indexed",
-                }
-            )));
-        }
-        Ok(())
-    }
-
     /// Execute the instruction with the given operand and VM state
     pub fn execute(
         &self,
@@ -326,9 +499,14 @@ impl InstructionDefinition for InstructionKind {
         self.allowed_operand_kinds()
     }
 
-    /// Validate that the operand is valid for this instruction
-    fn validate_operand(&self, operand: Option<&Operand>) -> Result<(), VmError> {
-        self.validate_operand(operand)
+    /// Get the documentation and other metadata of the instruction
+    fn metadata(&self) -> InstructionMetadata {
+        self.metadata()
+    }
+
+    /// Get the effects of the instruction on the state of the machine
+    fn effects(&self) -> InstructionEffects {
+        self.effects()
     }
 
     /// Execute the instruction with the given operand and VM state
@@ -9,7 +9,7 @@ use dashmap::DashMap;
 use once_cell::sync::Lazy;
 
 use crate::instruction::{InstructionDefinition, InstructionInfo, InstructionKind};
-use crate::instructions::standard_instructions;
+use crate::instructions::{extended_instructions, standard_instructions, subroutine_instructions};
 use crate::registry::InstructionRegistry;
 
 /// A set of instructions for the RAM virtual machine
@@ -140,6 +140,22 @@ impl InstructionSet {
         STANDARD_INSTRUCTION_SET.clone()
     }
 
+    /// Create the extended instruction set
+    ///
+    /// It adds instructions beyond the textbook RAM, such as `RAND`, to the
+    /// standard ones.
+    pub fn extended() -> Self {
+        EXTENDED_INSTRUCTION_SET.clone()
+    }
+
+    /// Create the subroutine instruction set
+    ///
+    /// It adds `CALL` and `RET`, which call subroutines through a return stack,
+    /// to the standard instructions.
+    pub fn subroutines() -> Self {
+        SUBROUTINE_INSTRUCTION_SET.clone()
+    }
+
     /// Merge another instruction set into this one
     pub fn merge(&mut self, other: &InstructionSet) -> &mut Self {
         // Merge the registries
@@ -183,6 +199,48 @@ pub static STANDARD_INSTRUCTION_SET: Lazy<InstructionSet> = Lazy::new(|| {
     set
 });
 
+/// The extended instruction set for the RAM virtual machine
+pub static EXTENDED_INSTRUCTION_SET: Lazy<InstructionSet> = Lazy::new(|| {
+    let mut set = InstructionSet::new(
+        "Extended",
+        "The standard instruction set with additional instructions, such as RAND",
+    );
+
+    set.add_metadata("version", "1.0.0")
+        .add_metadata("author", "RAM VM Team")
+        .add_metadata("license", "MIT");
+
+    let registry = extended_instructions();
+    for kind in registry.kinds() {
+        if let Some(definition) = registry.get(&kind) {
+            set.add_instruction(kind, definition);
+        }
+    }
+
+    set
+});
+
+/// The subroutine instruction set for the RAM virtual machine
+pub static SUBROUTINE_INSTRUCTION_SET: Lazy<InstructionSet> = Lazy::new(|| {
+    let mut set = InstructionSet::new(
+        "Subroutines",
+        "The standard instruction set with subroutine calls, through CALL and RET",
+    );
+
+    set.add_metadata("version", "1.0.0")
+        .add_metadata("author", "RAM VM Team")
+        .add_metadata("license", "MIT");
+
+    let registry = subroutine_instructions();
+    for kind in registry.kinds() {
+        if let Some(definition) = registry.get(&kind) {
+            set.add_instruction(kind, definition);
+        }
+    }
+
+    set
+});
+
 /// A global registry of all available instruction sets
 pub struct InstructionSetRegistry {
     /// Map of instruction set names to instruction sets
@@ -194,8 +252,10 @@ impl InstructionSetRegistry {
     pub fn new() -> Self {
         let mut registry = Self { sets: DashMap::new() };
 
-        // Register the standard instruction set
+        // Register the built-in instruction sets
         registry.register(Arc::new(InstructionSet::standard()));
+        registry.register(Arc::new(InstructionSet::extended()));
+        registry.register(Arc::new(InstructionSet::subroutines()));
 
         registry
     }
@@ -210,6 +270,14 @@ impl InstructionSetRegistry {
         self.sets.get(name).map(|entry| entry.value().clone())
     }
 
+    /// Get an instruction set by name, ignoring case
+    pub fn get_case_insensitive(&self, name: &str) -> Option<Arc<InstructionSet>> {
+        self.sets
+            .iter()
+            .find(|entry| entry.key().eq_ignore_ascii_case(name))
+            .map(|entry| entry.value().clone())
+    }
+
     /// Check if the registry contains an instruction set
     pub fn contains(&self, name: &str) -> bool {
         self.sets.contains_key(name)
@@ -4,9 +4,12 @@ use std::sync::Arc;
 
 use tracing::debug;
 
+use crate::arithmetic::ArithmeticOp;
 use crate::db::VmState;
+use crate::effect::{AccumulatorValue, Effect, InstructionEffects};
 use crate::error::VmError;
 use crate::instruction::{InstructionDefinition, InstructionKind};
+use crate::metadata::{CostModel, InstructionMetadata};
 use crate::operand::{Operand, OperandKind};
 use crate::operand_resolver::{DefaultOperandResolver, OperandResolver, StoreTarget};
 use crate::registry::InstructionRegistry;
@@ -28,6 +31,14 @@ impl InstructionDefinition for LoadInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Load.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Load.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -62,6 +73,14 @@ impl InstructionDefinition for StoreInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Store.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Store.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -104,6 +123,14 @@ impl InstructionDefinition for AddInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Add.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Add.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -118,7 +145,8 @@ impl InstructionDefinition for AddInstruction {
 
         // Add the value to the accumulator
         let acc = vm_state.accumulator();
-        vm_state.set_accumulator(acc + value);
+        let result = vm_state.arithmetic_mode().apply(ArithmeticOp::Add, acc, value)?;
+        vm_state.set_accumulator(result);
 
         Ok(())
     }
@@ -141,6 +169,14 @@ impl InstructionDefinition for SubInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Sub.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Sub.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -155,7 +191,8 @@ impl InstructionDefinition for SubInstruction {
 
         // Subtract the value from the accumulator
         let acc = vm_state.accumulator();
-        vm_state.set_accumulator(acc - value);
+        let result = vm_state.arithmetic_mode().apply(ArithmeticOp::Sub, acc, value)?;
+        vm_state.set_accumulator(result);
 
         Ok(())
     }
@@ -178,6 +215,14 @@ impl InstructionDefinition for MulInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Mul.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Mul.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -192,7 +237,8 @@ impl InstructionDefinition for MulInstruction {
 
         // Multiply the accumulator by the value
         let acc = vm_state.accumulator();
-        vm_state.set_accumulator(acc * value);
+        let result = vm_state.arithmetic_mode().apply(ArithmeticOp::Mul, acc, value)?;
+        vm_state.set_accumulator(result);
 
         Ok(())
     }
@@ -215,6 +261,14 @@ impl InstructionDefinition for DivInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Div.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Div.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -234,7 +288,8 @@ impl InstructionDefinition for DivInstruction {
 
         // Divide the accumulator by the value
         let acc = vm_state.accumulator();
-        vm_state.set_accumulator(acc / value);
+        let result = vm_state.arithmetic_mode().apply(ArithmeticOp::Div, acc, value)?;
+        vm_state.set_accumulator(result);
 
         Ok(())
     }
@@ -257,6 +312,14 @@ impl InstructionDefinition for JumpInstruction {
         &[OperandKind::Direct]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Jump.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Jump.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -294,6 +357,14 @@ impl InstructionDefinition for JumpGtzInstruction {
         &[OperandKind::Direct]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::JumpGtz.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::JumpGtz.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -336,6 +407,14 @@ impl InstructionDefinition for JumpZeroInstruction {
         &[OperandKind::Direct]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::JumpZero.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::JumpZero.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -378,6 +457,14 @@ impl InstructionDefinition for ReadInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Read.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Read.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -420,6 +507,14 @@ impl InstructionDefinition for WriteInstruction {
         &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Write.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Write.effects()
+    }
+
     fn execute(
         &self,
         operand: Option<&Operand>,
@@ -457,6 +552,14 @@ impl InstructionDefinition for HaltInstruction {
         &[]
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionKind::Halt.metadata()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionKind::Halt.effects()
+    }
+
     fn execute(
         &self,
         _operand: Option<&Operand>,
@@ -486,3 +589,207 @@ pub fn standard_instructions() -> InstructionRegistry {
 
     registry
 }
+
+/// RAND instruction implementation
+///
+/// RAND is part of the extended instruction set. It draws from the random
+/// number generator of the machine, so seeding the machine makes runs
+/// reproducible.
+#[derive(Debug, Clone)]
+pub struct RandInstruction;
+
+impl RandInstruction {
+    /// The name of the instruction
+    pub const NAME: &'static str = "RAND";
+}
+
+impl InstructionDefinition for RandInstruction {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn requires_operand(&self) -> bool {
+        true
+    }
+
+    fn allowed_operand_kinds(&self) -> &[OperandKind] {
+        &[OperandKind::Direct, OperandKind::Indirect, OperandKind::Immediate, OperandKind::Indexed]
+    }
+
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionMetadata::new("Load a random number into the accumulator")
+            .with_operand_constraints(
+                "A positive value: immediate (`=n`), direct (`n`), indirect (`*n`) or indexed \
+                 (`n[m]`).",
+            )
+            .with_semantics(
+                "Sets the accumulator to a random number from 0 up to, but not including, the \
+                 value of the operand.",
+            )
+            .with_example("RAND =6", "Rolls a die, from 0 to 5")
+            .with_example("RAND 1", "Draws a number below the value of register 1")
+            .with_cost(CostModel::Uniform, "1")
+            .with_cost(CostModel::Logarithmic, "t(a)")
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        // The value is unknown to analyses even if the operand is constant
+        InstructionEffects::new()
+            .with(Effect::ReadsOperand)
+            .with(Effect::WritesAccumulator(AccumulatorValue::Unknown))
+    }
+
+    fn execute(
+        &self,
+        operand: Option<&Operand>,
+        vm_state: &mut dyn VmState,
+    ) -> Result<(), VmError> {
+        let operand = operand
+            .ok_or_else(|| VmError::InvalidOperand("RAND requires an operand".to_string()))?;
+
+        let resolver = DefaultOperandResolver;
+        let bound = resolver.resolve_operand_value(operand, vm_state)?;
+        if bound <= 0 {
+            return Err(VmError::InvalidOperand(format!(
+                "RAND requires a positive bound, got {}",
+                bound
+            )));
+        }
+
+        let value = vm_state.next_random()? % bound as u64;
+        vm_state.set_accumulator(value as i64);
+        Ok(())
+    }
+}
+
+/// Create a registry with the standard instructions and the extended ones
+pub fn extended_instructions() -> InstructionRegistry {
+    let mut registry = standard_instructions();
+    registry.register(InstructionKind::from_name(RandInstruction::NAME), Arc::new(RandInstruction));
+    registry
+}
+
+/// CALL instruction implementation
+///
+/// CALL is part of the subroutine instruction set. It pushes the address of
+/// the next instruction onto the return stack of the machine and jumps to the
+/// label of its operand.
+#[derive(Debug, Clone)]
+pub struct CallInstruction;
+
+impl CallInstruction {
+    /// The name of the instruction
+    pub const NAME: &'static str = "CALL";
+}
+
+impl InstructionDefinition for CallInstruction {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn requires_operand(&self) -> bool {
+        true
+    }
+
+    fn allowed_operand_kinds(&self) -> &[OperandKind] {
+        &[OperandKind::Direct]
+    }
+
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionMetadata::new("Call a subroutine")
+            .with_operand_constraints("The label of the subroutine to call.")
+            .with_semantics(
+                "Pushes the address of the next instruction onto the return stack and \
+                 transfers control to the instruction at the label.",
+            )
+            .with_example("CALL square", "Runs the subroutine at `square`, then continues here")
+            .with_cost(CostModel::Uniform, "1")
+            .with_cost(CostModel::Logarithmic, "1")
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionEffects::new().with(Effect::Calls)
+    }
+
+    fn execute(
+        &self,
+        operand: Option<&Operand>,
+        vm_state: &mut dyn VmState,
+    ) -> Result<(), VmError> {
+        let operand = operand
+            .ok_or_else(|| VmError::InvalidOperand("CALL requires an operand".to_string()))?;
+
+        let resolver = DefaultOperandResolver;
+        let target = resolver.resolve_jump_target(operand, vm_state)?;
+
+        // The program counter already points to the instruction after the call
+        let return_address = vm_state.program_counter();
+        vm_state.push_return_address(return_address)?;
+        debug!("CALL: Calling {} from {}", target, return_address);
+        vm_state.set_program_counter(target);
+
+        Ok(())
+    }
+}
+
+/// RET instruction implementation
+///
+/// RET is part of the subroutine instruction set. It pops the return stack and
+/// continues after the CALL that started the current subroutine.
+#[derive(Debug, Clone)]
+pub struct RetInstruction;
+
+impl RetInstruction {
+    /// The name of the instruction
+    pub const NAME: &'static str = "RET";
+}
+
+impl InstructionDefinition for RetInstruction {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn requires_operand(&self) -> bool {
+        false
+    }
+
+    fn allowed_operand_kinds(&self) -> &[OperandKind] {
+        &[]
+    }
+
+    fn metadata(&self) -> InstructionMetadata {
+        InstructionMetadata::new("Return from a subroutine")
+            .with_operand_constraints("No operand.")
+            .with_semantics(
+                "Pops the return stack and transfers control to the instruction after the \
+                 matching CALL. Returning outside of a subroutine is an error.",
+            )
+            .with_example("RET", "Returns to the caller")
+            .with_cost(CostModel::Uniform, "1")
+            .with_cost(CostModel::Logarithmic, "1")
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        InstructionEffects::new().with(Effect::Returns)
+    }
+
+    fn execute(
+        &self,
+        _operand: Option<&Operand>,
+        vm_state: &mut dyn VmState,
+    ) -> Result<(), VmError> {
+        let return_address = vm_state.pop_return_address()?;
+        debug!("RET: Returning to {}", return_address);
+        vm_state.set_program_counter(return_address);
+
+        Ok(())
+    }
+}
+
+/// Create a registry with the standard instructions and the subroutine ones
+pub fn subroutine_instructions() -> InstructionRegistry {
+    let mut registry = standard_instructions();
+    registry.register(InstructionKind::from_name(CallInstruction::NAME), Arc::new(CallInstruction));
+    registry.register(InstructionKind::from_name(RetInstruction::NAME), Arc::new(RetInstruction));
+    registry
+}
@@ -5,11 +5,14 @@
 //!
 //! The crate also provides a plugin system for extending the VM with custom instructions.
 
+pub mod arithmetic;
 pub mod db;
+pub mod effect;
 pub mod error;
 pub mod instruction;
 pub mod instruction_set;
 pub mod instructions;
+pub mod metadata;
 pub mod operand;
 pub mod operand_resolver;
 pub mod plugin;
@@ -18,25 +21,37 @@ pub mod registry;
 #[cfg(feature = "examples")]
 pub mod examples;
 
+pub use crate::arithmetic::{ArithmeticMode, ArithmeticOp};
 pub use crate::db::InstructionDb;
-pub use crate::error::VmError;
+pub use crate::effect::{AccumulatorValue, Effect, InstructionEffects, JumpCondition};
+pub use crate::error::{OperandError, VmError};
 pub use crate::instruction::{
-    Instruction, InstructionDefinition, InstructionInfo, InstructionKind,
+    Instruction, InstructionDefinition, InstructionInfo, InstructionKind, OutputFormat,
 };
 pub use crate::instruction_set::{
-    INSTRUCTION_SET_REGISTRY, InstructionSet, InstructionSetRegistry, STANDARD_INSTRUCTION_SET,
+    EXTENDED_INSTRUCTION_SET, INSTRUCTION_SET_REGISTRY, InstructionSet, InstructionSetRegistry,
+    STANDARD_INSTRUCTION_SET, SUBROUTINE_INSTRUCTION_SET,
 };
-pub use crate::instructions::standard_instructions;
+pub use crate::instructions::{
+    extended_instructions, standard_instructions, subroutine_instructions,
+};
+pub use crate::metadata::{CostModel, InstructionCost, InstructionExample, InstructionMetadata};
 pub use crate::operand::{Operand, OperandKind, OperandValue};
 pub use crate::operand_resolver::{
     DefaultOperandResolver, OperandResolver, resolve_jump_target, resolve_operand_value,
     resolve_store_address,
 };
-pub use crate::plugin::{InstructionBuilder, PluginManager, RamPlugin};
+pub use crate::plugin::{
+    InstructionBuilder, PLUGIN_ABI_VERSION, PluginAnalysisPass, PluginDiagnostic, PluginManager,
+    PluginSeverity, RamPlugin,
+};
 pub use crate::registry::InstructionRegistry;
 
 #[cfg(test)]
 mod tests {
+    pub mod arithmetic_tests;
+    pub mod effect_tests;
     pub mod instruction_info_tests;
     pub mod instruction_set_tests;
+    pub mod operand_tests;
 }
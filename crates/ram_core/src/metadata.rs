@@ -0,0 +1,112 @@
+//! Documentation and other metadata of instructions
+//!
+//! The metadata of an instruction is the single source of the text tools show
+//! about it, such as the documentation of completions and hovers in the
+//! language server.
+
+use std::fmt;
+
+/// A criterion for measuring the time an instruction takes to execute
+///
+/// Under the logarithmic cost model, `l(n)` is the number of bits of the
+/// integer `n`, `c(n)` is the content of register `n` and `t(a)` is the cost
+/// of resolving the operand `a`: `l(n)` for `=n`, `l(n) + l(c(n))` for `n`,
+/// `l(n) + l(c(n)) + l(c(c(n)))` for `*n` and `l(n) + l(m) + l(c(m)) +
+/// l(c(n + c(m)))` for `n[m]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CostModel {
+    /// Every instruction takes one unit of time
+    Uniform,
+    /// Instructions take time proportional to the size of the values they use
+    Logarithmic,
+}
+
+impl CostModel {
+    /// Every cost model
+    pub const ALL: [CostModel; 2] = [CostModel::Uniform, CostModel::Logarithmic];
+
+    /// Get the name of the cost model
+    pub fn name(self) -> &'static str {
+        match self {
+            CostModel::Uniform => "uniform",
+            CostModel::Logarithmic => "logarithmic",
+        }
+    }
+}
+
+impl fmt::Display for CostModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The cost of an instruction under a cost model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionCost {
+    /// The cost model
+    pub model: CostModel,
+    /// The cost, as a formula in the notation of the cost model
+    pub cost: String,
+}
+
+/// An example use of an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionExample {
+    /// The example code
+    pub code: String,
+    /// What the example does
+    pub explanation: String,
+}
+
+/// Documentation and other metadata of an instruction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstructionMetadata {
+    /// A one-line summary of the instruction
+    pub summary: String,
+    /// The operands the instruction accepts
+    pub operand_constraints: String,
+    /// What executing the instruction does
+    pub semantics: String,
+    /// Example uses of the instruction
+    pub examples: Vec<InstructionExample>,
+    /// The cost of the instruction under each cost model
+    pub costs: Vec<InstructionCost>,
+}
+
+impl InstructionMetadata {
+    /// Create metadata with a one-line summary
+    pub fn new(summary: impl Into<String>) -> Self {
+        Self { summary: summary.into(), ..Self::default() }
+    }
+
+    /// Set the description of the operands the instruction accepts
+    pub fn with_operand_constraints(mut self, constraints: impl Into<String>) -> Self {
+        self.operand_constraints = constraints.into();
+        self
+    }
+
+    /// Set the description of what executing the instruction does
+    pub fn with_semantics(mut self, semantics: impl Into<String>) -> Self {
+        self.semantics = semantics.into();
+        self
+    }
+
+    /// Add an example use of the instruction
+    pub fn with_example(mut self, code: impl Into<String>, explanation: impl Into<String>) -> Self {
+        self.examples
+            .push(InstructionExample { code: code.into(), explanation: explanation.into() });
+        self
+    }
+
+    /// Set the cost of the instruction under a cost model
+    pub fn with_cost(mut self, model: CostModel, cost: impl Into<String>) -> Self {
+        self.costs.retain(|existing| existing.model != model);
+        self.costs.push(InstructionCost { model, cost: cost.into() });
+        self
+    }
+
+    /// Get the cost of the instruction under a cost model
+    pub fn cost(&self, model: CostModel) -> Option<&str> {
+        self.costs.iter().find(|cost| cost.model == model).map(|cost| cost.cost.as_str())
+    }
+}
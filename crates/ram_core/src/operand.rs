@@ -85,6 +85,21 @@ impl Operand {
     pub fn indexed(base: i64, index: i64) -> Self {
         Self { kind: OperandKind::Indexed, value: OperandValue::Indexed(base, index) }
     }
+
+    /// Get the first negative address the operand refers to, if any
+    ///
+    /// Immediate operands are values rather than addresses, and labels are
+    /// only resolved when the program runs, so neither is ever negative.
+    pub fn negative_address(&self) -> Option<i64> {
+        if self.kind == OperandKind::Immediate {
+            return None;
+        }
+        match self.value {
+            OperandValue::Number(address) => (address < 0).then_some(address),
+            OperandValue::Indexed(base, index) => [base, index].into_iter().find(|&a| a < 0),
+            OperandValue::String(_) => None,
+        }
+    }
 }
 
 impl fmt::Display for Operand {
@@ -110,3 +125,21 @@ pub enum OperandKind {
     /// Indexed addressing (e.g., 5[2])
     Indexed,
 }
+
+impl OperandKind {
+    /// Get the name of the addressing mode
+    pub fn name(self) -> &'static str {
+        match self {
+            OperandKind::Direct => "direct",
+            OperandKind::Indirect => "indirect",
+            OperandKind::Immediate => "immediate",
+            OperandKind::Indexed => "indexed",
+        }
+    }
+}
+
+impl fmt::Display for OperandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -3,10 +3,19 @@
 //! This module provides a plugin system that allows for registering custom
 //! instructions with the RAM virtual machine. Plugins can be loaded dynamically
 //! at runtime, and can provide multiple instructions.
+//!
+//! Plugins can also contribute analysis passes, such as the lint rules of a
+//! course, as [`PluginAnalysisPass`]es. These passes are not linked against
+//! the analysis of the compiler: they receive the program as the `ram.hir`
+//! JSON document printed by `ram validate --emit hir-json`, and return the
+//! diagnostics they find.
 
+use std::ops::Range;
 use std::sync::Arc;
 
+use crate::effect::InstructionEffects;
 use crate::instruction::InstructionDefinition;
+use crate::metadata::InstructionMetadata;
 use crate::registry::InstructionRegistry;
 
 // Define a type alias for the execution function to reduce complexity
@@ -20,6 +29,12 @@ type ExecuteFn = Box<
         + 'static,
 >;
 
+/// The version of the interface plugins are built against
+///
+/// Bumped whenever [`RamPlugin`] or the instructions it registers change in a
+/// way that breaks plugins built for an earlier version.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
 /// A plugin for the RAM virtual machine
 pub trait RamPlugin: Send + Sync + 'static {
     /// Get the name of the plugin
@@ -33,6 +48,85 @@ pub trait RamPlugin: Send + Sync + 'static {
 
     /// Register the plugin's instructions with the registry
     fn register(&self, registry: &mut InstructionRegistry);
+
+    /// Get the analysis passes the plugin contributes
+    ///
+    /// By default a plugin contributes no passes.
+    fn analysis_passes(&self) -> Vec<Arc<dyn PluginAnalysisPass>> {
+        Vec::new()
+    }
+}
+
+/// An analysis pass contributed by a plugin
+pub trait PluginAnalysisPass: Send + Sync + 'static {
+    /// Get the ID of the pass
+    ///
+    /// The ID names the pass in the `[analysis.passes]` section of the
+    /// manifest and in the dependencies of other passes, so it should not
+    /// clash with the ID of a built-in pass.
+    fn name(&self) -> &str;
+
+    /// Get the IDs of the passes that must run before this one
+    ///
+    /// A dependency is either a built-in pass, such as `cfg`, or a pass of a
+    /// plugin. The pass does not run if one of them is disabled.
+    fn dependencies(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Analyze a program
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The lowered program, as a `ram.hir` JSON document.
+    ///
+    /// Returns the diagnostics found, or a message if the pass failed.
+    fn analyze(&self, body: &str) -> Result<Vec<PluginDiagnostic>, String>;
+}
+
+/// How severe a diagnostic of a plugin pass is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginSeverity {
+    /// The program is wrong
+    Error,
+    /// The program is likely wrong
+    Warning,
+    /// The program could be improved
+    Advice,
+}
+
+/// A diagnostic found by an analysis pass of a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginDiagnostic {
+    /// How severe the diagnostic is
+    pub severity: PluginSeverity,
+    /// The code of the diagnostic, which its severity can be configured by
+    pub code: Option<String>,
+    /// The message of the diagnostic
+    pub message: String,
+    /// How to fix the problem
+    pub help: String,
+    /// The bytes of the source the diagnostic points at, as in the spans of
+    /// the `ram.hir` document
+    pub span: Range<usize>,
+}
+
+impl PluginDiagnostic {
+    /// Create a diagnostic without a code
+    pub fn new(
+        severity: PluginSeverity,
+        message: impl Into<String>,
+        help: impl Into<String>,
+        span: Range<usize>,
+    ) -> Self {
+        Self { severity, code: None, message: message.into(), help: help.into(), span }
+    }
+
+    /// Set the code of the diagnostic
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
 }
 
 /// A plugin manager for the RAM virtual machine
@@ -69,6 +163,12 @@ impl PluginManager {
             plugin.register(registry);
         }
     }
+
+    /// Get the analysis passes of all plugins, in the order the plugins were
+    /// registered
+    pub fn analysis_passes(&self) -> Vec<Arc<dyn PluginAnalysisPass>> {
+        self.plugins.iter().flat_map(|plugin| plugin.analysis_passes()).collect()
+    }
 }
 
 /// A builder for creating instruction definitions
@@ -79,6 +179,10 @@ pub struct InstructionBuilder {
     requires_operand: bool,
     /// The allowed operand kinds
     allowed_operand_kinds: Vec<crate::operand::OperandKind>,
+    /// The documentation and other metadata
+    metadata: InstructionMetadata,
+    /// The effects on the state of the machine
+    effects: InstructionEffects,
     /// The execution function
     execute_fn: ExecuteFn,
 }
@@ -90,6 +194,8 @@ impl InstructionBuilder {
             name: name.into(),
             requires_operand: true,
             allowed_operand_kinds: vec![],
+            metadata: InstructionMetadata::default(),
+            effects: InstructionEffects::unknown(),
             execute_fn: Box::new(|_, _| {
                 Err(crate::error::VmError::InvalidInstruction(
                     "Instruction not implemented".to_string(),
@@ -110,6 +216,21 @@ impl InstructionBuilder {
         self
     }
 
+    /// Set the documentation and other metadata
+    pub fn metadata(mut self, metadata: InstructionMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Set the effects on the state of the machine
+    ///
+    /// Without them, analyses assume the instruction has
+    /// [unknown effects](InstructionEffects::unknown).
+    pub fn effects(mut self, effects: InstructionEffects) -> Self {
+        self.effects = effects;
+        self
+    }
+
     /// Set the execution function
     pub fn execute<F>(mut self, f: F) -> Self
     where
@@ -131,6 +252,8 @@ impl InstructionBuilder {
             name: self.name,
             requires_operand: self.requires_operand,
             allowed_operand_kinds: self.allowed_operand_kinds,
+            metadata: self.metadata,
+            effects: self.effects,
             execute_fn: self.execute_fn,
         })
     }
@@ -144,6 +267,10 @@ struct BuiltInstruction {
     requires_operand: bool,
     /// The allowed operand kinds
     allowed_operand_kinds: Vec<crate::operand::OperandKind>,
+    /// The documentation and other metadata
+    metadata: InstructionMetadata,
+    /// The effects on the state of the machine
+    effects: InstructionEffects,
     /// The execution function
     execute_fn: ExecuteFn,
 }
@@ -161,6 +288,14 @@ impl InstructionDefinition for BuiltInstruction {
         &self.allowed_operand_kinds
     }
 
+    fn metadata(&self) -> InstructionMetadata {
+        self.metadata.clone()
+    }
+
+    fn effects(&self) -> InstructionEffects {
+        self.effects.clone()
+    }
+
     fn execute(
         &self,
         operand: Option<&crate::operand::Operand>,
@@ -115,22 +115,35 @@ impl InstructionRegistry {
     }
 
     /// Get information about a registered instruction by kind
+    ///
+    /// The metadata of the registered definition takes precedence over the
+    /// metadata of the kind, so plugins can document custom instructions.
     pub fn get_info(&self, kind: &InstructionKind) -> Option<InstructionInfo> {
-        Some(kind.info())
+        let mut info = kind.info();
+        if let Some(definition) = self.get(kind) {
+            let metadata = definition.metadata();
+            if !metadata.summary.is_empty() {
+                info.description = metadata.summary.clone();
+            }
+            if metadata != Default::default() {
+                info.metadata = metadata;
+            }
+        }
+        Some(info)
     }
 
     /// Get information about a registered instruction by name (case-sensitive)
     pub fn get_info_by_name(&self, name: &str) -> Option<InstructionInfo> {
-        self.kind_by_name(name).map(|kind| kind.info())
+        self.kind_by_name(name).and_then(|kind| self.get_info(&kind))
     }
 
     /// Get information about a registered instruction by name (case-insensitive)
     pub fn get_info_by_name_case_insensitive(&self, name: &str) -> Option<InstructionInfo> {
-        self.kind_by_name_case_insensitive(name).map(|kind| kind.info())
+        self.kind_by_name_case_insensitive(name).and_then(|kind| self.get_info(&kind))
     }
 
     /// Get information about all registered instructions
     pub fn get_all_info(&self) -> Vec<InstructionInfo> {
-        self.kinds().map(|kind| kind.info()).collect()
+        self.kinds().filter_map(|kind| self.get_info(&kind)).collect()
     }
 }
@@ -0,0 +1,38 @@
+//! Tests for the arithmetic modes of the virtual machine
+
+use crate::arithmetic::{ArithmeticMode, ArithmeticOp};
+use crate::error::VmError;
+
+#[test]
+fn test_checked_arithmetic() {
+    let mode = ArithmeticMode::Checked;
+    assert_eq!(mode.apply(ArithmeticOp::Add, 2, 3).unwrap(), 5);
+    assert_eq!(mode.apply(ArithmeticOp::Div, 7, 2).unwrap(), 3);
+    assert!(matches!(
+        mode.apply(ArithmeticOp::Add, i64::MAX, 1),
+        Err(VmError::Overflow { operation: ArithmeticOp::Add, lhs: i64::MAX, rhs: 1 })
+    ));
+    assert!(matches!(
+        mode.apply(ArithmeticOp::Div, i64::MIN, -1),
+        Err(VmError::Overflow { operation: ArithmeticOp::Div, .. })
+    ));
+    assert_eq!(
+        mode.apply(ArithmeticOp::Mul, i64::MAX, 2).unwrap_err().to_string(),
+        format!("Arithmetic overflow: {} * 2 does not fit in 64 bits", i64::MAX)
+    );
+}
+
+#[test]
+fn test_wrapping_and_saturating_arithmetic() {
+    assert_eq!(ArithmeticMode::Wrapping.apply(ArithmeticOp::Add, i64::MAX, 1).unwrap(), i64::MIN);
+    assert_eq!(ArithmeticMode::Wrapping.apply(ArithmeticOp::Sub, i64::MIN, 1).unwrap(), i64::MAX);
+    assert_eq!(ArithmeticMode::Saturating.apply(ArithmeticOp::Add, i64::MAX, 1).unwrap(), i64::MAX);
+    assert_eq!(ArithmeticMode::Saturating.apply(ArithmeticOp::Mul, i64::MIN, 2).unwrap(), i64::MIN);
+}
+
+#[test]
+fn test_division_by_zero_in_every_mode() {
+    for mode in ArithmeticMode::ALL {
+        assert!(matches!(mode.apply(ArithmeticOp::Div, 1, 0), Err(VmError::DivisionByZero)));
+    }
+}
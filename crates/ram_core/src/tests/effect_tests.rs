@@ -0,0 +1,64 @@
+//! Tests for the instruction effects API
+
+use crate::effect::{AccumulatorValue, Effect, InstructionEffects, JumpCondition};
+use crate::instruction::InstructionKind;
+use crate::plugin::InstructionBuilder;
+
+#[test]
+fn test_standard_instruction_effects() {
+    let load = InstructionKind::Load.effects();
+    assert!(load.reads_operand());
+    assert!(!load.reads_accumulator());
+    assert_eq!(load.accumulator_value(), Some(AccumulatorValue::Operand));
+
+    let store = InstructionKind::Store.effects();
+    assert!(store.reads_accumulator());
+    assert!(store.writes_operand());
+    assert_eq!(store.accumulator_value(), None);
+
+    assert_eq!(InstructionKind::Jump.effects().jump_condition(), Some(JumpCondition::Always));
+    assert_eq!(InstructionKind::JumpGtz.effects().jump_condition(), Some(JumpCondition::Positive));
+    assert_eq!(InstructionKind::JumpZero.effects().jump_condition(), Some(JumpCondition::Zero));
+    assert!(InstructionKind::Halt.effects().halts());
+    assert!(InstructionKind::Read.effects().performs_io());
+    assert!(InstructionKind::Write.effects().performs_io());
+
+    // Custom instructions are assumed to clobber the accumulator
+    let custom = InstructionKind::Custom(std::sync::Arc::from("CUSTOM")).effects();
+    assert_eq!(custom, InstructionEffects::unknown());
+    assert_eq!(custom.accumulator_value(), Some(AccumulatorValue::Unknown));
+}
+
+#[test]
+fn test_accumulator_value_evaluation() {
+    assert_eq!(AccumulatorValue::Operand.evaluate(None, Some(3)), Some(3));
+    assert_eq!(AccumulatorValue::Add.evaluate(Some(2), Some(3)), Some(5));
+    assert_eq!(AccumulatorValue::Sub.evaluate(Some(2), Some(3)), Some(-1));
+    assert_eq!(AccumulatorValue::Mul.evaluate(Some(2), Some(3)), Some(6));
+    assert_eq!(AccumulatorValue::Div.evaluate(Some(7), Some(2)), Some(3));
+
+    // Unknown inputs, division by zero and overflow have no static value
+    assert_eq!(AccumulatorValue::Add.evaluate(None, Some(3)), None);
+    assert_eq!(AccumulatorValue::Div.evaluate(Some(7), Some(0)), None);
+    assert_eq!(AccumulatorValue::Mul.evaluate(Some(i64::MAX), Some(2)), None);
+    assert_eq!(AccumulatorValue::Unknown.evaluate(Some(1), Some(1)), None);
+}
+
+#[test]
+fn test_builder_effects() {
+    let definition = InstructionBuilder::new("INC")
+        .requires_operand(false)
+        .effects(
+            [Effect::ReadsAccumulator, Effect::WritesAccumulator(AccumulatorValue::Unknown)]
+                .into_iter()
+                .collect(),
+        )
+        .build();
+    let effects = definition.effects();
+    assert!(effects.reads_accumulator());
+    assert!(!effects.reads_operand());
+
+    // Instructions built without effects have unknown effects
+    let definition = InstructionBuilder::new("NOP").build();
+    assert_eq!(definition.effects(), InstructionEffects::unknown());
+}
@@ -1,7 +1,9 @@
 //! Tests for the instruction info API
 
 use crate::instruction::InstructionKind;
+use crate::metadata::{CostModel, InstructionMetadata};
 use crate::operand::OperandKind;
+use crate::plugin::InstructionBuilder;
 use crate::registry::InstructionRegistry;
 
 #[test]
@@ -37,6 +39,16 @@ fn test_instruction_info() {
     assert!(halt_info.allowed_operand_kinds.is_empty());
 }
 
+#[test]
+fn test_jump_kinds() {
+    assert!(InstructionKind::Jump.is_jump());
+    assert!(!InstructionKind::Jump.is_conditional_jump());
+    assert!(InstructionKind::JumpGtz.is_conditional_jump());
+    assert!(InstructionKind::JumpZero.is_conditional_jump());
+    assert!(!InstructionKind::Halt.is_jump());
+    assert!(!InstructionKind::Custom(std::sync::Arc::from("JMP2")).is_jump());
+}
+
 #[test]
 fn test_registry_info_methods() {
     // Create a registry
@@ -61,3 +73,51 @@ fn test_registry_info_methods() {
     let custom_info = registry.get_info(&custom_kind).expect("Failed to get CUSTOM info");
     assert_eq!(custom_info.name, "CUSTOM");
 }
+
+#[test]
+fn test_instruction_metadata() {
+    let store_info = InstructionKind::Store.info();
+    let metadata = &store_info.metadata;
+
+    // The summary is the description of the instruction
+    assert_eq!(metadata.summary, store_info.description);
+    assert!(metadata.operand_constraints.contains("Immediate operands are not allowed"));
+    assert!(!metadata.semantics.is_empty());
+    assert!(metadata.examples.iter().any(|example| example.code == "STORE 2"));
+    assert_eq!(metadata.cost(CostModel::Uniform), Some("1"));
+    assert_eq!(metadata.cost(CostModel::Logarithmic), Some("l(c(0)) + t(a)"));
+
+    // Every standard instruction is documented under every cost model
+    for info in InstructionKind::standard_instructions_info() {
+        assert!(!info.metadata.semantics.is_empty(), "{} has no semantics", info.name);
+        assert!(!info.metadata.examples.is_empty(), "{} has no examples", info.name);
+        for model in CostModel::ALL {
+            assert!(info.metadata.cost(model).is_some(), "{} has no {} cost", info.name, model);
+        }
+    }
+
+    // Custom instructions only have a summary
+    let custom_info = InstructionKind::Custom(std::sync::Arc::from("CUSTOM")).info();
+    assert_eq!(custom_info.metadata, InstructionMetadata::new("Custom instruction"));
+}
+
+#[test]
+fn test_registry_uses_definition_metadata() {
+    let mut registry = InstructionRegistry::new();
+    let kind = InstructionKind::Custom(std::sync::Arc::from("SQUARE"));
+    let definition = InstructionBuilder::new("SQUARE")
+        .requires_operand(false)
+        .metadata(
+            InstructionMetadata::new("Square the accumulator")
+                .with_semantics("Multiplies the accumulator by itself.")
+                .with_cost(CostModel::Uniform, "1"),
+        )
+        .build();
+    registry.register(kind.clone(), definition);
+
+    let info = registry.get_info_by_name("SQUARE").expect("Failed to get SQUARE info");
+    assert_eq!(info.description, "Square the accumulator");
+    assert_eq!(info.metadata.semantics, "Multiplies the accumulator by itself.");
+    assert_eq!(info.metadata.cost(CostModel::Uniform), Some("1"));
+    assert_eq!(info.metadata.cost(CostModel::Logarithmic), None);
+}
@@ -2,8 +2,9 @@
 
 use std::sync::Arc;
 
+use crate::effect::AccumulatorValue;
 use crate::instruction::InstructionKind;
-use crate::instruction_set::{InstructionSet, STANDARD_INSTRUCTION_SET};
+use crate::instruction_set::{InstructionSet, InstructionSetRegistry, STANDARD_INSTRUCTION_SET};
 use crate::plugin::InstructionBuilder;
 
 #[test]
@@ -131,3 +132,47 @@ fn test_static_standard_instruction_set() {
         assert!(STANDARD_INSTRUCTION_SET.contains(&kind), "Standard set should contain {:?}", kind);
     }
 }
+
+#[test]
+fn test_extended_instruction_set() {
+    let extended_set = InstructionSet::extended();
+    assert_eq!(extended_set.name, "Extended");
+
+    // The extended set is a superset of the standard one
+    for kind in InstructionKind::standard_kinds() {
+        assert!(extended_set.contains(&kind), "Extended set should contain {:?}", kind);
+    }
+    assert!(extended_set.contains_name("RAND"));
+    assert!(!STANDARD_INSTRUCTION_SET.contains_name("RAND"));
+
+    // Random numbers are unknown to the analyses
+    let rand = extended_set.get_by_name("RAND").expect("Failed to get RAND");
+    let effects = rand.effects();
+    assert!(effects.reads_operand());
+    assert_eq!(effects.accumulator_value(), Some(AccumulatorValue::Unknown));
+
+    // Sets are found by name regardless of case
+    let registry = InstructionSetRegistry::new();
+    assert_eq!(
+        registry.get_case_insensitive("extended").map(|set| set.name.clone()),
+        Some("Extended".to_string())
+    );
+    assert!(registry.get_case_insensitive("unknown").is_none());
+}
+
+#[test]
+fn test_subroutine_instruction_set() {
+    let subroutine_set = InstructionSet::subroutines();
+    assert_eq!(subroutine_set.name, "Subroutines");
+    assert!(subroutine_set.contains_name("CALL"));
+    assert!(subroutine_set.contains_name("RET"));
+    assert!(!STANDARD_INSTRUCTION_SET.contains_name("CALL"));
+
+    // Calls take a label, returns take no operand
+    let call = subroutine_set.get_by_name("CALL").expect("Failed to get CALL");
+    assert!(call.requires_operand());
+    assert!(call.effects().calls());
+    let ret = subroutine_set.get_by_name("RET").expect("Failed to get RET");
+    assert!(!ret.requires_operand());
+    assert!(ret.effects().returns());
+}
@@ -0,0 +1,76 @@
+//! Tests for the operand rules of instruction definitions
+
+use crate::error::{OperandError, VmError};
+use crate::instruction::{Instruction, InstructionDefinition, InstructionKind};
+use crate::instructions::standard_instructions;
+use crate::operand::{Operand, OperandKind};
+
+#[test]
+fn test_operand_arity() {
+    assert_eq!(InstructionKind::Load.validate_arity(true), Ok(()));
+    assert_eq!(
+        InstructionKind::Load.validate_arity(false),
+        Err(OperandError::Missing { instruction: "LOAD".to_string() })
+    );
+    assert_eq!(
+        InstructionKind::Halt.validate_arity(true),
+        Err(OperandError::Unexpected { instruction: "HALT".to_string() })
+    );
+}
+
+#[test]
+fn test_operand_kinds_of_definitions() {
+    let registry = standard_instructions();
+    let store = registry.get(&InstructionKind::Store).unwrap();
+
+    assert_eq!(store.validate_operand(&Operand::indirect(1)), Ok(()));
+    assert_eq!(
+        store.validate_operand(&Operand::immediate(1)),
+        Err(OperandError::UnsupportedKind {
+            instruction: "STORE".to_string(),
+            kind: OperandKind::Immediate,
+            allowed: vec![OperandKind::Direct, OperandKind::Indirect, OperandKind::Indexed],
+        })
+    );
+}
+
+#[test]
+fn test_jumps_reject_immediate_operands() {
+    // Even when its kinds allow immediate operands, a jump goes to a label
+    assert!(InstructionKind::Jump.allowed_operand_kinds().contains(&OperandKind::Immediate));
+    assert_eq!(
+        InstructionKind::Jump.validate_operand(&Operand::immediate(3)),
+        Err(OperandError::ImmediateJump { instruction: "JUMP".to_string() })
+    );
+    assert_eq!(InstructionKind::Jump.validate_operand(&Operand::direct_str("loop")), Ok(()));
+
+    // Immediate values are not addresses, so they may be negative
+    assert_eq!(InstructionKind::Load.validate_operand(&Operand::immediate(-3)), Ok(()));
+}
+
+#[test]
+fn test_negative_addresses() {
+    assert_eq!(Operand::direct(-1).negative_address(), Some(-1));
+    assert_eq!(Operand::indirect(-2).negative_address(), Some(-2));
+    assert_eq!(Operand::indexed(3, -4).negative_address(), Some(-4));
+    assert_eq!(Operand::indexed(3, 4).negative_address(), None);
+    assert_eq!(Operand::immediate(-1).negative_address(), None);
+    assert_eq!(Operand::direct_str("loop").negative_address(), None);
+
+    assert_eq!(
+        InstructionKind::Load.validate_operand(&Operand::indexed(-2, 1)),
+        Err(OperandError::NegativeAddress { address: -2 })
+    );
+}
+
+#[test]
+fn test_instructions_validate_with_the_definition_rules() {
+    let instruction = Instruction::with_operand(InstructionKind::JumpZero, Operand::immediate(0));
+    let Err(VmError::InvalidOperand(message)) = instruction.validate() else {
+        panic!("Expected an invalid operand error");
+    };
+    assert_eq!(message, "JZERO can not jump to an immediate value");
+
+    assert!(Instruction::without_operand(InstructionKind::Halt).validate().is_ok());
+    assert!(Instruction::without_operand(InstructionKind::Load).validate().is_err());
+}
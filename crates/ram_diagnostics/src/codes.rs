@@ -0,0 +1,190 @@
+//! The registry of diagnostic codes.
+//!
+//! Every diagnostic code reported by the compiler is registered here together
+//! with a long-form explanation. Codes are stable: once a code is published it
+//! keeps its meaning, and retired codes are never reused.
+//!
+//! Error codes start with `E` and warning codes with `W`. The severity of a
+//! diagnostic can still be changed through the project configuration.
+
+use std::fmt;
+
+/// The base URL of the generated explanation pages.
+pub const EXPLANATIONS_URL: &str = "https://ram.hadronomy.dev/docs/diagnostics";
+
+/// A registered diagnostic code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticCode {
+    /// The code itself, e.g. `E001`
+    pub code: &'static str,
+    /// A one-line summary of the diagnostic
+    pub title: &'static str,
+    /// The long-form explanation in Markdown, with examples
+    pub explanation: &'static str,
+}
+
+impl DiagnosticCode {
+    /// Returns the URL of the explanation page for this code.
+    pub fn url(&self) -> String {
+        format!("{EXPLANATIONS_URL}/{}", self.code.to_lowercase())
+    }
+}
+
+impl fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+/// A label is not followed by an instruction.
+pub const LABEL_WITHOUT_INSTRUCTION: DiagnosticCode = DiagnosticCode {
+    code: "E001",
+    title: "Label must be followed by an instruction",
+    explanation: include_str!("codes/E001.md"),
+};
+
+/// An unexpected token at the start of a statement.
+pub const UNEXPECTED_TOKEN: DiagnosticCode = DiagnosticCode {
+    code: "E002",
+    title: "Unexpected token",
+    explanation: include_str!("codes/E002.md"),
+};
+
+/// A label is defined more than once.
+pub const DUPLICATE_LABEL: DiagnosticCode = DiagnosticCode {
+    code: "E003",
+    title: "Label is defined multiple times",
+    explanation: include_str!("codes/E003.md"),
+};
+
+/// A label name is not followed by a colon.
+pub const MISSING_LABEL_COLON: DiagnosticCode = DiagnosticCode {
+    code: "E004",
+    title: "Expected a colon after label name",
+    explanation: include_str!("codes/E004.md"),
+};
+
+/// An array accessor is missing its closing bracket.
+pub const UNCLOSED_BRACKET: DiagnosticCode = DiagnosticCode {
+    code: "E005",
+    title: "Unclosed bracket in array accessor",
+    explanation: include_str!("codes/E005.md"),
+};
+
+/// An opcode is not a known instruction.
+pub const UNKNOWN_INSTRUCTION: DiagnosticCode = DiagnosticCode {
+    code: "E006",
+    title: "Unknown instruction",
+    explanation: include_str!("codes/E006.md"),
+};
+
+/// An operand refers to a label that is not defined.
+pub const UNDEFINED_LABEL: DiagnosticCode = DiagnosticCode {
+    code: "E007",
+    title: "Undefined label",
+    explanation: include_str!("codes/E007.md"),
+};
+
+/// Arithmetic in an operand can not be evaluated at compile time.
+pub const NON_CONSTANT_OPERAND: DiagnosticCode = DiagnosticCode {
+    code: "E008",
+    title: "Operand expression is not constant",
+    explanation: include_str!("codes/E008.md"),
+};
+
+/// A program does not follow the classic RAM model in strict mode.
+pub const STRICT_RAM_VIOLATION: DiagnosticCode = DiagnosticCode {
+    code: "E009",
+    title: "Program does not follow the classic RAM model",
+    explanation: include_str!("codes/E009.md"),
+};
+
+/// A program reads or writes a different number of values than it declares.
+pub const IO_CONTRACT_VIOLATION: DiagnosticCode = DiagnosticCode {
+    code: "E010",
+    title: "Program breaks its input/output contract",
+    explanation: include_str!("codes/E010.md"),
+};
+
+/// A label has the same name as a module.
+pub const LABEL_SHADOWS_MODULE: DiagnosticCode = DiagnosticCode {
+    code: "W001",
+    title: "Label shadows a module",
+    explanation: include_str!("codes/W001.md"),
+};
+
+/// A label has the same name as an imported symbol.
+pub const LABEL_SHADOWS_IMPORT: DiagnosticCode = DiagnosticCode {
+    code: "W002",
+    title: "Label shadows an imported symbol",
+    explanation: include_str!("codes/W002.md"),
+};
+
+/// A label is never referenced.
+pub const UNUSED_LABEL: DiagnosticCode = DiagnosticCode {
+    code: "W003",
+    title: "Label is never used",
+    explanation: include_str!("codes/W003.md"),
+};
+
+/// Execution can continue past the last instruction of a program.
+pub const FALLS_OFF_END: DiagnosticCode = DiagnosticCode {
+    code: "W004",
+    title: "Execution can fall off the end of the program",
+    explanation: include_str!("codes/W004.md"),
+};
+
+/// Instructions after an unconditional jump or `HALT` can never run.
+pub const DEAD_CODE: DiagnosticCode = DiagnosticCode {
+    code: "W005",
+    title: "Code after an unconditional jump or HALT never runs",
+    explanation: include_str!("codes/W005.md"),
+};
+
+/// A jump goes to the instruction right after it.
+pub const JUMP_TO_NEXT: DiagnosticCode = DiagnosticCode {
+    code: "W006",
+    title: "Jump to the next instruction",
+    explanation: include_str!("codes/W006.md"),
+};
+
+/// Two jumps in a row can be a single jump.
+pub const REDUNDANT_JUMP: DiagnosticCode = DiagnosticCode {
+    code: "W007",
+    title: "Jumps in a row can be a single jump",
+    explanation: include_str!("codes/W007.md"),
+};
+
+/// A program may not halt.
+pub const MAY_NOT_HALT: DiagnosticCode = DiagnosticCode {
+    code: "W008",
+    title: "Program may not halt",
+    explanation: include_str!("codes/W008.md"),
+};
+
+/// All registered diagnostic codes, in order.
+pub const REGISTRY: &[DiagnosticCode] = &[
+    LABEL_WITHOUT_INSTRUCTION,
+    UNEXPECTED_TOKEN,
+    DUPLICATE_LABEL,
+    MISSING_LABEL_COLON,
+    UNCLOSED_BRACKET,
+    UNKNOWN_INSTRUCTION,
+    UNDEFINED_LABEL,
+    NON_CONSTANT_OPERAND,
+    STRICT_RAM_VIOLATION,
+    IO_CONTRACT_VIOLATION,
+    LABEL_SHADOWS_MODULE,
+    LABEL_SHADOWS_IMPORT,
+    UNUSED_LABEL,
+    FALLS_OFF_END,
+    DEAD_CODE,
+    JUMP_TO_NEXT,
+    REDUNDANT_JUMP,
+    MAY_NOT_HALT,
+];
+
+/// Look up a registered diagnostic code, ignoring case.
+pub fn lookup(code: &str) -> Option<&'static DiagnosticCode> {
+    REGISTRY.iter().find(|registered| registered.code.eq_ignore_ascii_case(code))
+}
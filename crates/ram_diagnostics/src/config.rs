@@ -0,0 +1,583 @@
+//! Diagnostic severity configuration.
+//!
+//! Severities can be configured per diagnostic code in the project manifest
+//! (`ram.toml`) and silenced for a single statement with a suppression comment:
+//!
+//! ```toml
+//! [diagnostics]
+//! W003 = "allow"
+//! W001 = "error"
+//! ```
+//!
+//! The `[analysis]` section enables additional checks, such as the strict mode
+//! that holds programs to the classic RAM model and the check that programs
+//! halt, opts into conventions such as
+//! the sections that labels start, and configures the analysis passes by their
+//! ID, enabling or disabling them and setting the values they read:
+//!
+//! ```toml
+//! [analysis]
+//! strict-ram = true
+//! termination = true
+//! sections = true
+//!
+//! [analysis.passes.alias]
+//! widening-limit = 16
+//!
+//! [analysis.passes.unused-labels]
+//! enabled = false
+//! ```
+//!
+//! The `[database.lru]` section sets how many results of each query the
+//! databases of the compiler keep, by query name:
+//!
+//! ```toml
+//! [database.lru]
+//! parse = 64
+//! file-text = 8
+//! ```
+//!
+//! ```text
+//! # ram: allow(W003)
+//! unused: HALT
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{Diagnostic, DiagnosticKind};
+
+/// The file name of the project manifest.
+pub const MANIFEST_FILE_NAME: &str = "ram.toml";
+
+/// The prefix of a suppression comment.
+const SUPPRESSION_PREFIX: &str = "ram:";
+
+/// The severity level configured for a diagnostic code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    /// Report the diagnostic as an error.
+    Error,
+    /// Report the diagnostic as a warning.
+    Warn,
+    /// Do not report the diagnostic.
+    Allow,
+}
+
+impl FromStr for Level {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" | "deny" => Ok(Level::Error),
+            "warn" | "warning" => Ok(Level::Warn),
+            "allow" => Ok(Level::Allow),
+            _ => Err(ConfigError::InvalidLevel(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warn"),
+            Level::Allow => write!(f, "allow"),
+        }
+    }
+}
+
+/// An error raised while loading a diagnostic configuration.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The manifest could not be read.
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        /// The path of the manifest
+        path: PathBuf,
+        /// The underlying I/O error
+        source: std::io::Error,
+    },
+    /// The manifest is not valid TOML.
+    #[error("Invalid manifest: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// The `[diagnostics]` section is not a table of strings.
+    #[error("Invalid [diagnostics] section: {0}")]
+    InvalidSection(String),
+    /// The `[analysis]` section has an unknown or invalid setting.
+    #[error("Invalid [analysis] section: {0}")]
+    InvalidAnalysis(String),
+    /// The `[database]` section has an unknown or invalid setting.
+    #[error("Invalid [database] section: {0}")]
+    InvalidDatabase(String),
+    /// A severity level is not one of `error`, `warn` or `allow`.
+    #[error("Invalid severity level '{0}', expected one of 'error', 'warn' or 'allow'")]
+    InvalidLevel(String),
+}
+
+/// The value of a setting of an analysis pass.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SettingValue {
+    /// A toggle.
+    Bool(bool),
+    /// A number, such as a threshold.
+    Integer(i64),
+    /// A string.
+    String(String),
+}
+
+impl SettingValue {
+    /// Get the name of the type of the value, as written in the manifest.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SettingValue::Bool(_) => "boolean",
+            SettingValue::Integer(_) => "integer",
+            SettingValue::String(_) => "string",
+        }
+    }
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Bool(value) => write!(f, "{value}"),
+            SettingValue::Integer(value) => write!(f, "{value}"),
+            SettingValue::String(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+/// The configuration of an analysis pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PassSettings {
+    /// Whether the pass is enabled, if configured
+    pub enabled: Option<bool>,
+    /// The values of the settings of the pass, by name
+    pub values: BTreeMap<String, SettingValue>,
+}
+
+/// Per-code severity levels for diagnostics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiagnosticConfig {
+    /// The configured level of each diagnostic code
+    levels: HashMap<String, Level>,
+    /// Whether programs are held to the classic RAM model
+    strict_ram: bool,
+    /// Whether programs are checked to halt
+    termination: bool,
+    /// Whether labels start named sections that are measured
+    sections: bool,
+    /// The configuration of each analysis pass, by pass ID
+    passes: BTreeMap<String, PassSettings>,
+    /// The only analysis passes to run, besides their dependencies, if restricted
+    enabled_passes: Option<BTreeSet<String>>,
+    /// The LRU capacity of each query of the databases, by query name
+    lru_capacities: BTreeMap<String, u16>,
+}
+
+impl Hash for DiagnosticConfig {
+    /// Hash the levels in code order, so equal configurations hash equally.
+    ///
+    /// The LRU capacities are left out, since they do not change the
+    /// diagnostics.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut levels = self.levels.iter().collect::<Vec<_>>();
+        levels.sort_by_key(|(code, _)| *code);
+        levels.hash(state);
+        self.strict_ram.hash(state);
+        self.termination.hash(state);
+        self.sections.hash(state);
+        self.passes.hash(state);
+        self.enabled_passes.hash(state);
+    }
+}
+
+impl DiagnosticConfig {
+    /// Create an empty configuration that keeps every diagnostic as reported.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the level of a diagnostic code.
+    #[must_use]
+    pub fn with_level(mut self, code: impl Into<String>, level: Level) -> Self {
+        self.levels.insert(code.into(), level);
+        self
+    }
+
+    /// Hold programs to the classic RAM model, or stop doing so.
+    #[must_use]
+    pub fn with_strict_ram(mut self, strict_ram: bool) -> Self {
+        self.strict_ram = strict_ram;
+        self
+    }
+
+    /// Check that programs halt, or stop doing so.
+    #[must_use]
+    pub fn with_termination(mut self, termination: bool) -> Self {
+        self.termination = termination;
+        self
+    }
+
+    /// Treat labels as the start of named sections, or stop doing so.
+    #[must_use]
+    pub fn with_sections(mut self, sections: bool) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    /// Enable or disable an analysis pass.
+    #[must_use]
+    pub fn with_pass_enabled(mut self, pass: impl Into<String>, enabled: bool) -> Self {
+        self.passes.entry(pass.into()).or_default().enabled = Some(enabled);
+        self
+    }
+
+    /// Set a setting of an analysis pass.
+    #[must_use]
+    pub fn with_pass_setting(
+        mut self,
+        pass: impl Into<String>,
+        name: impl Into<String>,
+        value: SettingValue,
+    ) -> Self {
+        self.passes.entry(pass.into()).or_default().values.insert(name.into(), value);
+        self
+    }
+
+    /// Run only the given analysis passes and the passes they depend on.
+    ///
+    /// This overrides the passes enabled or disabled in the manifest.
+    #[must_use]
+    pub fn with_enabled_passes(
+        mut self,
+        passes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.enabled_passes = Some(passes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the LRU capacity of a query of the databases.
+    #[must_use]
+    pub fn with_lru_capacity(mut self, query: impl Into<String>, capacity: u16) -> Self {
+        self.lru_capacities.insert(query.into(), capacity);
+        self
+    }
+
+    /// Get the configured LRU capacity of each query of the databases, by
+    /// query name.
+    pub fn lru_capacities(&self) -> &BTreeMap<String, u16> {
+        &self.lru_capacities
+    }
+
+    /// Get the configuration of each analysis pass, by pass ID.
+    pub fn passes(&self) -> &BTreeMap<String, PassSettings> {
+        &self.passes
+    }
+
+    /// Get the only analysis passes to run, if restricted.
+    pub fn enabled_passes(&self) -> Option<&BTreeSet<String>> {
+        self.enabled_passes.as_ref()
+    }
+
+    /// Get the configured level of a diagnostic code.
+    pub fn level(&self, code: &str) -> Option<Level> {
+        self.levels.get(code).copied()
+    }
+
+    /// Returns `true` if programs are held to the classic RAM model.
+    pub fn strict_ram(&self) -> bool {
+        self.strict_ram
+    }
+
+    /// Returns `true` if programs are checked to halt.
+    pub fn termination(&self) -> bool {
+        self.termination
+    }
+
+    /// Returns `true` if labels start named sections that are measured.
+    pub fn sections(&self) -> bool {
+        self.sections
+    }
+
+    /// Parse the `[diagnostics]`, `[analysis]` and `[database]` sections of a
+    /// manifest.
+    pub fn from_manifest(manifest: &str) -> Result<Self, ConfigError> {
+        let manifest = manifest.parse::<toml::Table>()?;
+
+        let mut config = Self::new();
+        if let Some(section) = manifest.get("analysis") {
+            config.parse_analysis(section)?;
+        }
+        if let Some(section) = manifest.get("database") {
+            config.parse_database(section)?;
+        }
+        let Some(section) = manifest.get("diagnostics") else {
+            return Ok(config);
+        };
+        let section = section
+            .as_table()
+            .ok_or_else(|| ConfigError::InvalidSection("expected a table".to_string()))?;
+
+        for (code, level) in section {
+            let level = level.as_str().ok_or_else(|| {
+                ConfigError::InvalidSection(format!("the level of '{code}' must be a string"))
+            })?;
+            config.levels.insert(code.clone(), level.parse()?);
+        }
+
+        Ok(config)
+    }
+
+    /// Parse the settings of the `[analysis]` section.
+    fn parse_analysis(&mut self, section: &toml::Value) -> Result<(), ConfigError> {
+        let section = section
+            .as_table()
+            .ok_or_else(|| ConfigError::InvalidAnalysis("expected a table".to_string()))?;
+
+        if let Some(key) = section.keys().find(|key| {
+            !["strict-ram", "termination", "sections", "passes"].contains(&key.as_str())
+        }) {
+            return Err(ConfigError::InvalidAnalysis(format!("unknown setting '{key}'")));
+        }
+        if let Some(value) = section.get("strict-ram") {
+            self.strict_ram = value.as_bool().ok_or_else(|| {
+                ConfigError::InvalidAnalysis("'strict-ram' must be a boolean".to_string())
+            })?;
+        }
+        if let Some(value) = section.get("termination") {
+            self.termination = value.as_bool().ok_or_else(|| {
+                ConfigError::InvalidAnalysis("'termination' must be a boolean".to_string())
+            })?;
+        }
+        if let Some(value) = section.get("sections") {
+            self.sections = value.as_bool().ok_or_else(|| {
+                ConfigError::InvalidAnalysis("'sections' must be a boolean".to_string())
+            })?;
+        }
+        if let Some(passes) = section.get("passes") {
+            let passes = passes.as_table().ok_or_else(|| {
+                ConfigError::InvalidAnalysis("'passes' must be a table of passes".to_string())
+            })?;
+            for (pass, settings) in passes {
+                self.passes.insert(pass.clone(), parse_pass_settings(pass, settings)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the settings of the `[database]` section.
+    ///
+    /// Whether the queries exist is checked by the databases, which know the
+    /// queries they keep results of.
+    fn parse_database(&mut self, section: &toml::Value) -> Result<(), ConfigError> {
+        let invalid = |message: &str| ConfigError::InvalidDatabase(message.to_string());
+        let section = section.as_table().ok_or_else(|| invalid("expected a table"))?;
+
+        if let Some(key) = section.keys().find(|key| key.as_str() != "lru") {
+            return Err(ConfigError::InvalidDatabase(format!("unknown setting '{key}'")));
+        }
+        let Some(lru) = section.get("lru") else {
+            return Ok(());
+        };
+        let lru = lru.as_table().ok_or_else(|| invalid("'lru' must be a table of queries"))?;
+        for (query, capacity) in lru {
+            let capacity = capacity
+                .as_integer()
+                .and_then(|capacity| u16::try_from(capacity).ok())
+                .ok_or_else(|| {
+                    ConfigError::InvalidDatabase(format!(
+                        "the capacity of 'lru.{query}' must be a number up to 65535"
+                    ))
+                })?;
+            self.lru_capacities.insert(query.clone(), capacity);
+        }
+        Ok(())
+    }
+
+    /// Load the configuration from a manifest file.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let manifest = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+        Self::from_manifest(&manifest)
+    }
+
+    /// Find the manifest in `dir` or the closest of its ancestors.
+    pub fn discover(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors().map(|dir| dir.join(MANIFEST_FILE_NAME)).find(|path| path.is_file())
+    }
+
+    /// Apply the configured levels and the suppression comments of `source`.
+    ///
+    /// Allowed and suppressed diagnostics are removed. Diagnostics whose level
+    /// was changed by the configuration are annotated with a note saying so.
+    pub fn apply(
+        &self,
+        source: &str,
+        diagnostics: impl IntoIterator<Item = Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let suppressions = Suppressions::from_source(source);
+
+        diagnostics
+            .into_iter()
+            .filter(|diagnostic| !suppressions.is_suppressed(diagnostic))
+            .filter_map(|diagnostic| self.apply_level(diagnostic))
+            .collect()
+    }
+
+    /// Apply the configured level to a single diagnostic.
+    fn apply_level(&self, mut diagnostic: Diagnostic) -> Option<Diagnostic> {
+        let Some(code) = diagnostic.code.as_deref() else {
+            return Some(diagnostic);
+        };
+        let Some(level) = self.level(code) else {
+            return Some(diagnostic);
+        };
+        let kind = match level {
+            Level::Allow => return None,
+            Level::Error => DiagnosticKind::Error,
+            Level::Warn => DiagnosticKind::Warning,
+        };
+
+        if diagnostic.kind != kind {
+            let note = format!("`{code}` is set to `{level}` in {MANIFEST_FILE_NAME}");
+            diagnostic.kind = kind;
+            diagnostic.notes.push(note);
+        }
+        Some(diagnostic)
+    }
+}
+
+/// Parse the table of an analysis pass in `[analysis.passes]`.
+///
+/// Whether the settings exist and have the right types is checked by the
+/// analysis pipeline, which knows the settings each pass declares.
+fn parse_pass_settings(pass: &str, settings: &toml::Value) -> Result<PassSettings, ConfigError> {
+    let settings = settings.as_table().ok_or_else(|| {
+        ConfigError::InvalidAnalysis(format!("the settings of pass '{pass}' must be a table"))
+    })?;
+
+    let mut pass_settings = PassSettings::default();
+    for (name, value) in settings {
+        let value = match value {
+            toml::Value::Boolean(value) => SettingValue::Bool(*value),
+            toml::Value::Integer(value) => SettingValue::Integer(*value),
+            toml::Value::String(value) => SettingValue::String(value.clone()),
+            _ => {
+                return Err(ConfigError::InvalidAnalysis(format!(
+                    "'{pass}.{name}' must be a boolean, an integer or a string"
+                )));
+            }
+        };
+        match (name.as_str(), value) {
+            ("enabled", SettingValue::Bool(enabled)) => pass_settings.enabled = Some(enabled),
+            ("enabled", _) => {
+                return Err(ConfigError::InvalidAnalysis(format!(
+                    "'{pass}.enabled' must be a boolean"
+                )));
+            }
+            (_, value) => {
+                pass_settings.values.insert(name.clone(), value);
+            }
+        }
+    }
+    Ok(pass_settings)
+}
+
+/// Suppression comments found in a source file.
+///
+/// A comment of the form `# ram: allow(E001, W003)` silences the listed codes
+/// for the statement on the same line, or for the next statement when the
+/// comment is on a line of its own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Suppressions {
+    /// The suppressed codes and the byte range of the statement they apply to
+    entries: Vec<(Range<usize>, Vec<String>)>,
+}
+
+impl Suppressions {
+    /// Collect the suppression comments of a source file.
+    pub fn from_source(source: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut pending: Vec<String> = Vec::new();
+        let mut offset = 0;
+
+        for line in source.split_inclusive('\n') {
+            let range = offset..offset + line.trim_end_matches(['\n', '\r']).len();
+            offset += line.len();
+
+            let (code, comment) = match comment_start(line) {
+                Some(index) => (&line[..index], Some(&line[index..])),
+                None => (line, None),
+            };
+            pending.extend(comment.map(parse_suppression).unwrap_or_default());
+
+            // A comment on its own line applies to the next statement
+            if !code.trim().is_empty() && !pending.is_empty() {
+                entries.push((range, std::mem::take(&mut pending)));
+            }
+        }
+
+        Self { entries }
+    }
+
+    /// Returns `true` if the primary span of `diagnostic` is suppressed.
+    pub fn is_suppressed(&self, diagnostic: &Diagnostic) -> bool {
+        let (Some(code), Some((span, _))) = (&diagnostic.code, diagnostic.labeled_spans.first())
+        else {
+            return false;
+        };
+
+        self.entries.iter().any(|(range, codes)| {
+            range.start <= span.start && span.start <= range.end && codes.contains(code)
+        })
+    }
+}
+
+/// Find the `#` that starts the comment of a line, if it has one.
+///
+/// A `#` inside a string or character literal, such as the operand of
+/// `LOAD '#'`, does not start a comment. Literals are skipped as the lexer
+/// reads them: up to the closing quote or the end of the line, with `\`
+/// escaping the next character.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut chars = line.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '#' => return Some(index),
+            '"' | '\'' => {
+                while let Some((_, next)) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '\n' => break,
+                        next if next == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse the codes of a `# ram: allow(...)` comment.
+fn parse_suppression(comment: &str) -> Vec<String> {
+    let text = comment.trim_start_matches('#').trim();
+    let Some(directive) = text.strip_prefix(SUPPRESSION_PREFIX) else {
+        return Vec::new();
+    };
+    let Some(codes) =
+        directive.trim().strip_prefix("allow(").and_then(|codes| codes.split_once(')'))
+    else {
+        return Vec::new();
+    };
+
+    codes.0.split(',').map(str::trim).filter(|code| !code.is_empty()).map(String::from).collect()
+}
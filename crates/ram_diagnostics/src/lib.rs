@@ -32,9 +32,23 @@
 
 use std::ops::Range;
 
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+pub mod codes;
+pub mod config;
+pub mod sources;
+pub mod suggestion;
+
+pub use crate::codes::DiagnosticCode;
+pub use crate::config::{DiagnosticConfig, Level, Suppressions};
+pub use crate::sources::{ExternalSpan, SourceFile, SourceMap};
+pub use crate::suggestion::{Applicability, Suggestion};
+
 /// A diagnostic type used during compilation.
 /// This is compatible with ariadne's Report type and can be converted to ram_error::SingleParserError.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Diagnostic {
     /// The error message.
     pub message: String,
@@ -48,10 +62,17 @@ pub struct Diagnostic {
     pub code: Option<String>,
     /// Optional notes to provide additional context
     pub notes: Vec<String>,
+    /// Concrete edits that resolve this diagnostic
+    pub suggestions: Vec<Suggestion>,
+    /// The name of the file the labeled spans belong to, if not the main file
+    pub file: Option<String>,
+    /// Labeled spans in other files
+    pub external_spans: Vec<ExternalSpan>,
 }
 
 /// The kind of diagnostic being reported.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum DiagnosticKind {
     /// A critical error that prevents the program from continuing.
     Error,
@@ -63,6 +84,25 @@ pub enum DiagnosticKind {
     Custom(&'static str),
 }
 
+/// Custom kinds borrow their name, so only the built-in kinds can be deserialized.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DiagnosticKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        enum Kind {
+            Error,
+            Warning,
+            Advice,
+        }
+
+        Ok(match <Kind as serde::Deserialize>::deserialize(deserializer)? {
+            Kind::Error => DiagnosticKind::Error,
+            Kind::Warning => DiagnosticKind::Warning,
+            Kind::Advice => DiagnosticKind::Advice,
+        })
+    }
+}
+
 impl From<DiagnosticKind> for miette::Severity {
     fn from(kind: DiagnosticKind) -> Self {
         match kind {
@@ -83,6 +123,9 @@ impl Diagnostic {
             kind: DiagnosticKind::Error,
             code: None,
             notes: Vec::new(),
+            suggestions: Vec::new(),
+            file: None,
+            external_spans: Vec::new(),
         }
     }
 
@@ -99,6 +142,9 @@ impl Diagnostic {
             kind: DiagnosticKind::Warning,
             code: None,
             notes: Vec::new(),
+            suggestions: Vec::new(),
+            file: None,
+            external_spans: Vec::new(),
         }
     }
 
@@ -111,6 +157,9 @@ impl Diagnostic {
             kind: DiagnosticKind::Advice,
             code: None,
             notes: Vec::new(),
+            suggestions: Vec::new(),
+            file: None,
+            external_spans: Vec::new(),
         }
     }
 
@@ -142,6 +191,31 @@ impl Diagnostic {
         self
     }
 
+    /// Add a suggested edit to this diagnostic.
+    #[must_use]
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    /// Set the name of the file this diagnostic is reported in.
+    #[must_use]
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Returns true if both diagnostics have the same code and primary span.
+    ///
+    /// Diagnostics without a code are never considered duplicates.
+    pub fn is_duplicate_of(&self, other: &Diagnostic) -> bool {
+        self.code.is_some()
+            && self.code == other.code
+            && self.file == other.file
+            && self.labeled_spans.first().map(|(span, _)| span)
+                == other.labeled_spans.first().map(|(span, _)| span)
+    }
+
     /// Create a new diagnostic builder.
     pub fn builder() -> DiagnosticBuilder {
         DiagnosticBuilder::new()
@@ -163,6 +237,12 @@ pub struct DiagnosticBuilder {
     code: Option<String>,
     /// Optional notes to provide additional context
     notes: Vec<String>,
+    /// Concrete edits that resolve the diagnostic
+    suggestions: Vec<Suggestion>,
+    /// The name of the file the labeled spans belong to
+    file: Option<String>,
+    /// Labeled spans in other files
+    external_spans: Vec<ExternalSpan>,
 }
 
 impl DiagnosticBuilder {
@@ -213,6 +293,18 @@ impl DiagnosticBuilder {
         self
     }
 
+    /// Add a span in another file to the diagnostic.
+    #[must_use]
+    pub fn with_external_span(
+        mut self,
+        file: impl Into<String>,
+        span: Range<usize>,
+        label: impl Into<String>,
+    ) -> Self {
+        self.external_spans.push(ExternalSpan { file: file.into(), span, label: label.into() });
+        self
+    }
+
     /// Add multiple spans to the diagnostic.
     #[must_use]
     pub fn with_spans(mut self, spans: Vec<(Range<usize>, String)>) -> Self {
@@ -227,6 +319,13 @@ impl DiagnosticBuilder {
         self
     }
 
+    /// Set the name of the file the diagnostic is reported in.
+    #[must_use]
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
     /// Add a note to the diagnostic.
     #[must_use]
     pub fn with_note(mut self, note: impl Into<String>) -> Self {
@@ -241,6 +340,18 @@ impl DiagnosticBuilder {
         self
     }
 
+    /// Add a suggested edit to the diagnostic.
+    #[must_use]
+    pub fn with_suggestion(
+        mut self,
+        span: Range<usize>,
+        replacement: impl Into<String>,
+        applicability: Applicability,
+    ) -> Self {
+        self.suggestions.push(Suggestion::new(span, replacement, applicability));
+        self
+    }
+
     /// Build the diagnostic.
     ///
     /// # Panics
@@ -259,6 +370,9 @@ impl DiagnosticBuilder {
             kind,
             code: self.code,
             notes: self.notes,
+            suggestions: self.suggestions,
+            file: self.file,
+            external_spans: self.external_spans,
         }
     }
 
@@ -299,23 +413,23 @@ impl DiagnosticBuilder {
 /// Convert internal Diagnostic to ram_error types.
 ///
 /// This function converts our internal Diagnostic to the ram_error types
-/// that can be used with miette for nice error reporting.
-pub fn convert_errors(source: &str, errors: Vec<Diagnostic>) -> ram_error::Report {
-    use miette::LabeledSpan;
+/// that can be used with miette for nice error reporting. Each diagnostic is
+/// rendered against the file it is reported in, and spans in other files are
+/// rendered against their own file, as long as `sources` contains it.
+pub fn convert_errors(sources: &SourceMap, errors: Vec<Diagnostic>) -> ram_error::Report {
     use ram_error::{Report, SingleReport};
 
+    let main = sources.main();
+
     // Convert each Diagnostic to a SingleParserError
     let single_errors = errors
         .into_iter()
         .map(|e| {
-            // Convert labeled spans to miette LabeledSpans
-            let labels = e
-                .labeled_spans
-                .iter()
-                .map(|(span, label)| {
-                    LabeledSpan::new(Some(label.clone()), span.start, span.end - span.start)
-                })
-                .collect::<Vec<_>>();
+            let file = sources.resolve(e.file.as_deref());
+
+            // Convert labeled spans to miette LabeledSpans, unless the file is unknown
+            let labels =
+                if file.is_some() { to_labeled_spans(&e.labeled_spans) } else { Vec::new() };
 
             // Create a SingleParserError with appropriate message based on diagnostic kind
             let message = match e.kind {
@@ -325,12 +439,100 @@ pub fn convert_errors(source: &str, errors: Vec<Diagnostic>) -> ram_error::Repor
                 DiagnosticKind::Custom(name) => format!("{}: {}", name, e.message),
             };
 
-            SingleReport { message, labels, severity: Some(e.kind.into()), code: e.code }
+            // Show the suggested edits as "did you mean" help
+            let mut help = e.help.clone();
+            let source = file.map_or("", |file| file.text.as_str());
+            for suggestion in &e.suggestions {
+                if let Some(line) = suggestion.apply_to_line(source) {
+                    if !help.is_empty() {
+                        help.push('\n');
+                    }
+                    help.push_str(&format!("did you mean `{line}`?"));
+                }
+            }
+            let help = if help.is_empty() { None } else { Some(help) };
+
+            // Only diagnostics outside of the main file need their own source
+            let src = file.filter(|file| Some(*file) != main).map(named_source);
+
+            SingleReport {
+                message,
+                labels,
+                severity: Some(e.kind.into()),
+                code: e.code,
+                help,
+                src,
+                related: external_reports(sources, &e.external_spans),
+            }
         })
         .collect();
 
     // Create a ParserError with all the SingleParserErrors
-    Report { src: miette::NamedSource::new("input.ram", source.to_string()), errors: single_errors }
+    let src = main.map_or_else(
+        || miette::NamedSource::new(sources::ANONYMOUS_FILE_NAME, String::new()),
+        named_source,
+    );
+    Report { src, errors: single_errors }
+}
+
+/// Convert labeled spans to miette LabeledSpans
+fn to_labeled_spans(spans: &[(Range<usize>, String)]) -> Vec<miette::LabeledSpan> {
+    spans
+        .iter()
+        .map(|(span, label)| {
+            miette::LabeledSpan::new(Some(label.clone()), span.start, span.end - span.start)
+        })
+        .collect()
+}
+
+/// Create the miette source of a file
+fn named_source(file: &SourceFile) -> miette::NamedSource<String> {
+    miette::NamedSource::new(&file.name, file.text.clone())
+}
+
+/// Group spans in other files into related reports, one per file
+fn external_reports(sources: &SourceMap, spans: &[ExternalSpan]) -> Vec<ram_error::SingleReport> {
+    let mut files = Vec::<&str>::new();
+    for span in spans {
+        if !files.contains(&span.file.as_str()) {
+            files.push(&span.file);
+        }
+    }
+
+    files
+        .into_iter()
+        .flat_map(|name| {
+            let in_file = spans.iter().filter(move |span| span.file == name);
+            match sources.get(name) {
+                Some(file) => {
+                    let labels = in_file
+                        .map(|span| (span.span.clone(), span.label.clone()))
+                        .collect::<Vec<_>>();
+                    let mut report = ram_error::SingleReport::new(
+                        format!("Related code in {name}"),
+                        to_labeled_spans(&labels),
+                    );
+                    report.severity = Some(miette::Severity::Advice);
+                    report.src = Some(named_source(file));
+                    vec![report]
+                }
+                // Without the text of the file, only the labels can be shown
+                None => in_file
+                    .map(|span| {
+                        let mut report = ram_error::SingleReport::new(
+                            format!(
+                                "{} ({name}, bytes {}..{})",
+                                span.label, span.span.start, span.span.end
+                            ),
+                            Vec::new(),
+                        );
+                        report.severity = Some(miette::Severity::Advice);
+                        report
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
 }
 
 /// A collection of diagnostics
@@ -442,13 +644,21 @@ impl DiagnosticCollection {
         self.diagnostics.iter().filter(|d| d.kind == DiagnosticKind::Warning).count()
     }
 
+    /// Apply a severity configuration and the suppression comments of `source`
+    pub fn apply_config(&mut self, config: &DiagnosticConfig, source: &str) {
+        self.diagnostics = config.apply(source, std::mem::take(&mut self.diagnostics));
+    }
+
     /// Extend this collection with diagnostics from another collection
     pub fn extend(&mut self, other: DiagnosticCollection) {
         self.diagnostics.extend(other.diagnostics);
     }
 
     /// Convert to a ram_error::Report
-    pub fn to_report(&self, source: &str) -> ram_error::Report {
-        convert_errors(source, self.diagnostics.clone())
+    pub fn to_report(&self, sources: &SourceMap) -> ram_error::Report {
+        convert_errors(sources, self.diagnostics.clone())
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,94 @@
+//! Source files referenced by diagnostics.
+//!
+//! Diagnostics refer to files by name: a path on the command line, or a URI in
+//! the language server. A [`SourceMap`] provides the text of those files, so
+//! spans in other files can be rendered next to the file a diagnostic is
+//! reported in.
+
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+/// The name used for source text that does not come from a file.
+pub const ANONYMOUS_FILE_NAME: &str = "<input>";
+
+/// A labeled span in a file other than the one a diagnostic is reported in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExternalSpan {
+    /// The name of the file containing the span
+    pub file: String,
+    /// The byte range of the span in that file
+    pub span: Range<usize>,
+    /// The label shown next to the span
+    pub label: String,
+}
+
+/// A named source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    /// The name of the file
+    pub name: String,
+    /// The text of the file
+    pub text: String,
+}
+
+/// The source files a set of diagnostics refers to.
+///
+/// The first file added is the main file. Diagnostics that do not name the
+/// file they are reported in belong to the main file.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// The files, with the main file first
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Create an empty source map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a source map with a single main file.
+    pub fn single(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let mut sources = Self::new();
+        sources.add(name, text);
+        sources
+    }
+
+    /// Add a file, replacing the text of a file with the same name.
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) {
+        let name = name.into();
+        let text = text.into();
+        match self.files.iter_mut().find(|file| file.name == name) {
+            Some(file) => file.text = text,
+            None => self.files.push(SourceFile { name, text }),
+        }
+    }
+
+    /// Add a file, returning the updated source map.
+    #[must_use]
+    pub fn with_file(mut self, name: impl Into<String>, text: impl Into<String>) -> Self {
+        self.add(name, text);
+        self
+    }
+
+    /// Returns the main file, if any file was added.
+    pub fn main(&self) -> Option<&SourceFile> {
+        self.files.first()
+    }
+
+    /// Returns the file with the given name.
+    pub fn get(&self, name: &str) -> Option<&SourceFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// Returns the file with the given name, or the main file if `name` is `None`.
+    pub fn resolve(&self, name: Option<&str>) -> Option<&SourceFile> {
+        match name {
+            Some(name) => self.get(name),
+            None => self.main(),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Structured fix-its for diagnostics.
+//!
+//! A [`Suggestion`] describes a concrete edit to the source that resolves a
+//! diagnostic. Tools decide whether to apply it automatically based on its
+//! [`Applicability`].
+
+use std::ops::Range;
+
+use line_index::LineIndex;
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+/// How confident we are that a suggestion is correct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Applicability {
+    /// The suggestion is definitely what the user intended and can be applied automatically.
+    MachineApplicable,
+    /// The suggestion may be what the user intended, but should be reviewed first.
+    MaybeIncorrect,
+    /// The suggestion contains placeholders that have to be filled in by the user.
+    HasPlaceholders,
+    /// The applicability of the suggestion is unknown.
+    Unspecified,
+}
+
+/// A concrete edit that resolves a diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Suggestion {
+    /// The byte range to replace. An empty range inserts the replacement.
+    pub span: Range<usize>,
+    /// The text to replace the span with.
+    pub replacement: String,
+    /// How confident we are that the suggestion is correct.
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    /// Create a new suggestion.
+    pub fn new(
+        span: Range<usize>,
+        replacement: impl Into<String>,
+        applicability: Applicability,
+    ) -> Self {
+        Self { span, replacement: replacement.into(), applicability }
+    }
+
+    /// Apply the suggestion to the line of `source` that contains it.
+    ///
+    /// Returns the edited line without its line terminator, or `None` if the
+    /// span is out of bounds.
+    pub fn apply_to_line(&self, source: &str) -> Option<String> {
+        if self.span.start > self.span.end
+            || self.span.end > source.len()
+            || !source.is_char_boundary(self.span.start)
+            || !source.is_char_boundary(self.span.end)
+        {
+            return None;
+        }
+
+        let line_index = LineIndex::new(source);
+        let line_start = line_index.line_range(line_index.line_col(self.span.start).line)?.start;
+        let line_end =
+            line_index.line_range(line_index.line_col(self.span.end).line)?.end.max(self.span.end);
+
+        let mut line = String::new();
+        line.push_str(&source[line_start..self.span.start]);
+        line.push_str(&self.replacement);
+        line.push_str(&source[self.span.end..line_end]);
+        Some(line.trim().to_string())
+    }
+}
+
+/// Compute the edit distance between two strings.
+///
+/// This is the optimal string alignment distance: the number of insertions,
+/// deletions, substitutions and transpositions of adjacent characters needed
+/// to turn `a` into `b`. Transpositions count as a single edit, since they are
+/// the most common kind of typo.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j - 1] + cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Find the candidate closest to `name`, ignoring case.
+///
+/// Only candidates within a third of the length of `name` (at least one edit)
+/// are considered, so unrelated names are never suggested.
+pub fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let name = name.to_uppercase();
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &candidate.to_uppercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
@@ -0,0 +1,275 @@
+//! Tests for diagnostic configuration, the code registry, suggestions and reports
+
+use crate::config::{
+    ConfigError, DiagnosticConfig, Level, PassSettings, SettingValue, Suppressions,
+};
+use crate::suggestion::{Applicability, Suggestion, closest_match, edit_distance};
+use crate::{Diagnostic, DiagnosticCollection, DiagnosticKind, SourceMap};
+
+/// Create a warning with the given code at `span`
+fn warning(code: &str, span: std::ops::Range<usize>) -> Diagnostic {
+    Diagnostic::warning("message", "help", span).with_code(code)
+}
+
+#[test]
+fn test_manifest_levels() {
+    let config = DiagnosticConfig::from_manifest(
+        r#"
+        [diagnostics]
+        W003 = "allow"
+        W001 = "error"
+        E002 = "warn"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.level("W003"), Some(Level::Allow));
+    assert_eq!(config.level("W001"), Some(Level::Error));
+    assert_eq!(config.level("E002"), Some(Level::Warn));
+    assert_eq!(config.level("E001"), None);
+}
+
+#[test]
+fn test_manifest_without_diagnostics_section() {
+    let config = DiagnosticConfig::from_manifest("[package]\nname = \"demo\"\n").unwrap();
+    assert_eq!(config, DiagnosticConfig::default());
+}
+
+#[test]
+fn test_manifest_invalid_level() {
+    let result = DiagnosticConfig::from_manifest("[diagnostics]\nW003 = \"loud\"\n");
+    assert!(matches!(result, Err(ConfigError::InvalidLevel(level)) if level == "loud"));
+}
+
+#[test]
+fn test_manifest_strict_ram() {
+    let config = DiagnosticConfig::from_manifest("[analysis]\nstrict-ram = true\n").unwrap();
+    assert!(config.strict_ram());
+    assert_eq!(config, DiagnosticConfig::new().with_strict_ram(true));
+    assert!(!DiagnosticConfig::default().strict_ram());
+
+    let config = DiagnosticConfig::from_manifest("[analysis]\nsections = true\n").unwrap();
+    assert!(config.sections() && !config.strict_ram());
+    assert_eq!(config, DiagnosticConfig::new().with_sections(true));
+    assert!(!DiagnosticConfig::default().sections());
+
+    let config = DiagnosticConfig::from_manifest("[analysis]\ntermination = true\n").unwrap();
+    assert_eq!(config, DiagnosticConfig::new().with_termination(true));
+    assert!(!DiagnosticConfig::default().termination());
+
+    let result = DiagnosticConfig::from_manifest("[analysis]\nstrict-ram = \"yes\"\n");
+    assert!(matches!(result, Err(ConfigError::InvalidAnalysis(_))));
+    let result = DiagnosticConfig::from_manifest("[analysis]\nstrict = true\n");
+    assert!(matches!(result, Err(ConfigError::InvalidAnalysis(_))));
+}
+
+#[test]
+fn test_manifest_passes() {
+    let config = DiagnosticConfig::from_manifest(
+        r#"
+        [analysis.passes.alias]
+        widening-limit = 16
+        report-unused-writes = false
+
+        [analysis.passes.unused-labels]
+        enabled = false
+        "#,
+    )
+    .unwrap();
+
+    let alias = &config.passes()["alias"];
+    assert_eq!(alias.enabled, None);
+    assert_eq!(alias.values["widening-limit"], SettingValue::Integer(16));
+    assert_eq!(alias.values["report-unused-writes"], SettingValue::Bool(false));
+    assert_eq!(
+        config.passes()["unused-labels"],
+        PassSettings { enabled: Some(false), values: Default::default() }
+    );
+    assert_eq!(
+        config,
+        DiagnosticConfig::new()
+            .with_pass_setting("alias", "widening-limit", SettingValue::Integer(16))
+            .with_pass_setting("alias", "report-unused-writes", SettingValue::Bool(false))
+            .with_pass_enabled("unused-labels", false)
+    );
+    assert_eq!(config.enabled_passes(), None);
+
+    let result = DiagnosticConfig::from_manifest(
+        "[analysis.passes.alias]
+enabled = 1
+",
+    );
+    assert!(matches!(result, Err(ConfigError::InvalidAnalysis(_))));
+    let result = DiagnosticConfig::from_manifest(
+        "[analysis.passes]
+alias = true
+",
+    );
+    assert!(matches!(result, Err(ConfigError::InvalidAnalysis(_))));
+    let result = DiagnosticConfig::from_manifest(
+        "[analysis.passes.alias]
+limits = [1]
+",
+    );
+    assert!(matches!(result, Err(ConfigError::InvalidAnalysis(_))));
+}
+
+#[test]
+fn test_manifest_lru_capacities() {
+    let config =
+        DiagnosticConfig::from_manifest("[database.lru]\nparse = 64\nfile-text = 0\n").unwrap();
+    assert_eq!(
+        config,
+        DiagnosticConfig::new().with_lru_capacity("parse", 64).with_lru_capacity("file-text", 0)
+    );
+    assert_eq!(config.lru_capacities()["parse"], 64);
+
+    for manifest in [
+        "[database.lru]\nparse = 70000\n",
+        "[database.lru]\nparse = \"64\"\n",
+        "[database]\nlru-cap = 1\n",
+    ] {
+        let result = DiagnosticConfig::from_manifest(manifest);
+        assert!(matches!(result, Err(ConfigError::InvalidDatabase(_))));
+    }
+}
+
+#[test]
+fn test_apply_changes_and_annotates_levels() {
+    let config =
+        DiagnosticConfig::new().with_level("W001", Level::Error).with_level("W003", Level::Allow);
+
+    let mut collection = DiagnosticCollection::new();
+    collection.add(warning("W001", 0..3));
+    collection.add(warning("W003", 4..8));
+    collection.add(warning("W002", 9..12));
+    collection.apply_config(&config, "");
+
+    let diagnostics = collection.diagnostics();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::Error);
+    assert_eq!(diagnostics[0].notes, vec!["`W001` is set to `error` in ram.toml".to_string()]);
+    assert_eq!(diagnostics[1].kind, DiagnosticKind::Warning);
+    assert!(diagnostics[1].notes.is_empty());
+}
+
+#[test]
+fn test_suppression_comment_applies_to_next_statement() {
+    let source = "# ram: allow(W003)\nfoo: HALT\nbar: HALT\n";
+    let suppressions = Suppressions::from_source(source);
+
+    assert!(suppressions.is_suppressed(&warning("W003", 19..23)));
+    assert!(!suppressions.is_suppressed(&warning("W003", 29..33)));
+    assert!(!suppressions.is_suppressed(&warning("W001", 19..23)));
+}
+
+#[test]
+fn test_quoted_hash_does_not_start_a_comment() {
+    let source = "LOAD '#' # ram: allow(W001)\nSTORE \"# ram: allow(W003)\"\nHALT\n";
+    let suppressions = Suppressions::from_source(source);
+
+    assert!(suppressions.is_suppressed(&warning("W001", 0..4)));
+    assert!(!suppressions.is_suppressed(&warning("W003", 28..33)));
+    assert!(!suppressions.is_suppressed(&warning("W003", 55..59)));
+}
+
+#[test]
+fn test_trailing_suppression_comment() {
+    let source = "foo: HALT # ram: allow(W001, W003)\nbar: HALT\n";
+    let config = DiagnosticConfig::default();
+
+    let diagnostics = config
+        .apply(source, vec![warning("W003", 0..4), warning("W001", 0..4), warning("W003", 35..39)]);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].labeled_spans[0].0, 35..39);
+}
+
+#[test]
+fn test_registry_codes_are_unique() {
+    let mut seen = std::collections::HashSet::new();
+    for code in crate::codes::REGISTRY {
+        assert!(seen.insert(code.code), "duplicate diagnostic code {}", code.code);
+        assert!(!code.explanation.trim().is_empty(), "{} has no explanation", code.code);
+    }
+}
+
+#[test]
+fn test_registry_lookup() {
+    let code = crate::codes::lookup("w003").unwrap();
+    assert_eq!(code, &crate::codes::UNUSED_LABEL);
+    assert_eq!(code.url(), "https://ram.hadronomy.dev/docs/diagnostics/w003");
+    assert!(crate::codes::lookup("E999").is_none());
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(edit_distance("LOAD", "LOAD"), 0);
+    assert_eq!(edit_distance("LAOD", "LOAD"), 1);
+    assert_eq!(edit_distance("JUMP", "JZERO"), 4);
+    assert_eq!(edit_distance("STOR", "STORE"), 1);
+    assert_eq!(edit_distance("", "HALT"), 4);
+}
+
+#[test]
+fn test_closest_match() {
+    let names = ["LOAD", "STORE", "WRITE", "HALT"];
+    assert_eq!(closest_match("laod", names), Some("LOAD"));
+    assert_eq!(closest_match("WRTIE", names), Some("WRITE"));
+    assert_eq!(closest_match("SQRT", names), None);
+}
+
+#[test]
+fn test_suggestion_apply_to_line() {
+    let source = "start: LOAD 1\nloop LOAD 2\nHALT\n";
+    let suggestion = Suggestion::new(18..18, ":", Applicability::MachineApplicable);
+    assert_eq!(suggestion.apply_to_line(source).as_deref(), Some("loop: LOAD 2"));
+
+    let suggestion = Suggestion::new(40..41, "", Applicability::MachineApplicable);
+    assert_eq!(suggestion.apply_to_line(source), None);
+}
+
+#[test]
+fn test_convert_errors_shows_suggestions() {
+    let source = "LAOD 1\n";
+    let diagnostic = Diagnostic::error("Unknown instruction `LAOD`", "", 0..4)
+        .with_suggestion(Suggestion::new(0..4, "LOAD", Applicability::MaybeIncorrect));
+
+    let report = crate::convert_errors(&SourceMap::single("main.ram", source), vec![diagnostic]);
+    let help = report.errors[0].help.as_deref();
+    assert_eq!(help, Some("did you mean `LOAD 1`?"));
+}
+
+#[test]
+fn test_convert_errors_uses_file_names() {
+    let sources =
+        SourceMap::single("main.ram", "start: HALT\n").with_file("lib.ram", "start: LOAD 1\n");
+    let diagnostics = vec![
+        Diagnostic::error("in main", "", 0..5),
+        Diagnostic::error("in lib", "", 7..11).with_file("lib.ram"),
+    ];
+
+    let report = crate::convert_errors(&sources, diagnostics);
+    assert_eq!(report.src.name(), "main.ram");
+    assert!(report.errors[0].src.is_none());
+    assert_eq!(report.errors[1].src.as_ref().map(miette::NamedSource::name), Some("lib.ram"));
+}
+
+#[test]
+fn test_convert_errors_renders_external_spans() {
+    let sources =
+        SourceMap::single("main.ram", "start: HALT\n").with_file("lib.ram", "start: LOAD 1\n");
+    let diagnostic = Diagnostic::builder()
+        .with_message("Label 'start' is defined multiple times")
+        .with_primary_span(0..5, "label redefined here")
+        .with_external_span("lib.ram", 0..5, "first defined here")
+        .with_external_span("missing.ram", 3..8, "also defined here")
+        .build_error();
+
+    let report = crate::convert_errors(&sources, vec![diagnostic]);
+    let related = &report.errors[0].related;
+    assert_eq!(related.len(), 2);
+    assert_eq!(related[0].src.as_ref().map(miette::NamedSource::name), Some("lib.ram"));
+    assert_eq!(related[0].labels.len(), 1);
+    assert!(related[1].src.is_none());
+    assert!(related[1].message.contains("missing.ram"));
+}
@@ -65,11 +65,30 @@ pub struct SingleReport {
     pub labels: Vec<LabeledSpan>,
     pub severity: Option<miette::Severity>,
     pub code: Option<String>,
+    pub help: Option<String>,
+    /// The source of the labels, if it is not the source of the parent report
+    pub src: Option<NamedSource<String>>,
+    /// Reports for related code, possibly in other sources
+    pub related: Vec<SingleReport>,
 }
 
 impl SingleReport {
     pub fn new(message: String, labels: Vec<LabeledSpan>) -> Self {
-        Self { message, labels, severity: None, code: None }
+        Self {
+            message,
+            labels,
+            severity: None,
+            code: None,
+            help: None,
+            src: None,
+            related: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if this report has error severity
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        matches!(self.severity, None | Some(miette::Severity::Error))
     }
 }
 
@@ -85,6 +104,21 @@ impl miette::Diagnostic for SingleReport {
     fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
         self.code.as_ref().map(|c| Box::new(c) as Box<dyn std::fmt::Display>)
     }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.help.as_ref().map(|h| Box::new(h) as Box<dyn std::fmt::Display>)
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        self.src.as_ref().map(|src| src as &dyn miette::SourceCode)
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        if self.related.is_empty() {
+            return None;
+        }
+        Some(Box::new(self.related.iter().map(|report| report as &dyn Diagnostic)))
+    }
 }
 
 #[derive(Error, Diagnostic, Debug, Clone, Eq, PartialEq)]
@@ -98,3 +132,11 @@ pub struct Report {
     #[related]
     pub errors: Vec<SingleReport>,
 }
+
+impl Report {
+    /// Returns `true` if any of the reports has error severity
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(SingleReport::is_error)
+    }
+}
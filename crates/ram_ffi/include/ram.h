@@ -0,0 +1,183 @@
+/* C bindings for embedding the RAM virtual machine. */
+
+#ifndef RAM_H
+#define RAM_H
+
+/* This file is generated by `cargo xtask ffi-header`. Do not edit it. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The result of a function that can fail
+ */
+typedef enum RamResult {
+  /**
+   * The function succeeded
+   */
+  RAM_RESULT_OK = 0,
+  /**
+   * A pointer argument was null
+   */
+  RAM_RESULT_NULL_POINTER = 1,
+  /**
+   * The virtual machine reported an error
+   */
+  RAM_RESULT_RUNTIME = 2,
+  /**
+   * A register address was negative or past the memory limit
+   */
+  RAM_RESULT_INVALID_ADDRESS = 3,
+  /**
+   * The library panicked, which is a bug
+   */
+  RAM_RESULT_PANICKED = 4,
+} RamResult;
+
+/**
+ * How a run ended
+ */
+typedef enum RamStatus {
+  /**
+   * The program executed `HALT`
+   */
+  RAM_STATUS_HALTED = 0,
+  /**
+   * The run stopped after the maximum number of steps and can be resumed
+   */
+  RAM_STATUS_PAUSED = 1,
+  /**
+   * The program failed with a runtime error
+   */
+  RAM_STATUS_FAILED = 2,
+} RamStatus;
+
+/**
+ * A loaded program and the virtual machine running it
+ *
+ * The machine is created by the first run, from the input and registers set
+ * before it. Setting them again restarts the program.
+ */
+typedef struct RamMachine RamMachine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Load a program from its source
+ *
+ * Returns null if the source is null, not UTF-8 or not a valid program; see
+ * `ram_last_error` for why. The machine must be freed with `ram_machine_free`.
+ *
+ * # Safety
+ *
+ * `source` must be null or point to a nul-terminated string.
+ */
+struct RamMachine *ram_machine_load(const char *source);
+
+/**
+ * Free a machine created by `ram_machine_load`
+ *
+ * # Safety
+ *
+ * `machine` must be null or a machine returned by `ram_machine_load` that was
+ * not freed yet.
+ */
+void ram_machine_free(struct RamMachine *machine);
+
+/**
+ * Set the values the program reads, restarting it
+ *
+ * # Safety
+ *
+ * `machine` must be a live machine and `values` must point to `len` values,
+ * or be null if `len` is 0.
+ */
+enum RamResult ram_machine_set_input(struct RamMachine *machine, const int64_t *values, size_t len);
+
+/**
+ * Set the initial value of a register, restarting the program
+ *
+ * Register 0 is the accumulator. A negative address, or one past the memory
+ * limit, is rejected with `InvalidAddress` and leaves the registers as they
+ * are.
+ *
+ * # Safety
+ *
+ * `machine` must be a live machine.
+ */
+enum RamResult ram_machine_set_register(struct RamMachine *machine, int64_t address, int64_t value);
+
+/**
+ * Run the program, executing at most `max_steps` instructions
+ *
+ * A `max_steps` of 0 runs the program until it halts. A paused run continues
+ * where it stopped when run again. A failed run sets the last error to the
+ * runtime error.
+ *
+ * # Safety
+ *
+ * `machine` must be a live machine.
+ */
+enum RamStatus ram_machine_run(struct RamMachine *machine, uint64_t max_steps);
+
+/**
+ * Get the number of instructions executed since the program started
+ *
+ * # Safety
+ *
+ * `machine` must be null or a live machine.
+ */
+uint64_t ram_machine_steps(const struct RamMachine *machine);
+
+/**
+ * Get the value of the accumulator
+ *
+ * # Safety
+ *
+ * `machine` must be null or a live machine.
+ */
+int64_t ram_machine_accumulator(const struct RamMachine *machine);
+
+/**
+ * Read the value of a register into `value`
+ *
+ * Register 0 is the accumulator. Registers the program never wrote are 0.
+ *
+ * # Safety
+ *
+ * `machine` must be a live machine and `value` must point to writable memory.
+ */
+enum RamResult ram_machine_register(struct RamMachine *machine, int64_t address, int64_t *value);
+
+/**
+ * Copy the values written by the program into `buffer`
+ *
+ * At most `capacity` values are copied. Returns the number of values written
+ * by the program, which may be more than `capacity`: call with a null buffer
+ * to learn how large it has to be.
+ *
+ * # Safety
+ *
+ * `machine` must be null or a live machine, and `buffer` must be null or
+ * point to room for `capacity` values.
+ */
+size_t ram_machine_output(const struct RamMachine *machine, int64_t *buffer, size_t capacity);
+
+/**
+ * Get the message of the last error on the calling thread
+ *
+ * Returns null if no function failed on this thread. The message is valid
+ * until the next call that fails on the same thread.
+ */
+const char *ram_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAM_H */
@@ -0,0 +1,379 @@
+//! C bindings for embedding the RAM virtual machine
+//!
+//! A program is loaded from its source into a [`RamMachine`], given its input
+//! and the initial values of its registers, and run with a limit on the number
+//! of instructions executed. Its output, accumulator and registers can be read
+//! back afterwards. The functions are declared in `include/ram.h`, which is
+//! generated with `cargo xtask ffi-header`, so the library can be used from C,
+//! C++ or Python through ctypes or cffi.
+//!
+//! Functions that can fail return a [`RamResult`], and the message of the last
+//! error on the calling thread is available from [`ram_last_error`]. A panic
+//! never unwinds into the caller: it is reported like an error, with
+//! [`RamResult::Panicked`] where the function returns a result.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::fmt;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::Arc;
+
+use ram_core::db::VmState;
+use ram_vm::{Memory, Program, VecInput, VecOutput, VirtualMachine, VmDatabase, VmDatabaseImpl};
+
+#[cfg(test)]
+mod tests;
+
+thread_local! {
+    /// The message of the last error on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The result of a function that can fail
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamResult {
+    /// The function succeeded
+    Ok = 0,
+    /// A pointer argument was null
+    NullPointer = 1,
+    /// The virtual machine reported an error
+    Runtime = 2,
+    /// A register address was negative or past the memory limit
+    InvalidAddress = 3,
+    /// The library panicked, which is a bug
+    Panicked = 4,
+}
+
+/// How a run ended
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamStatus {
+    /// The program executed `HALT`
+    Halted = 0,
+    /// The run stopped after the maximum number of steps and can be resumed
+    Paused = 1,
+    /// The program failed with a runtime error
+    Failed = 2,
+}
+
+/// A loaded program and the virtual machine running it
+///
+/// The machine is created by the first run, from the input and registers set
+/// before it. Setting them again restarts the program.
+pub struct RamMachine {
+    /// The program to run
+    program: Program,
+    /// The database of the instructions the program runs with
+    db: Arc<VmDatabaseImpl>,
+    /// The values the program reads
+    input: Vec<i64>,
+    /// The initial values of the registers
+    registers: Vec<(i64, i64)>,
+    /// The machine running the program, once it was started
+    vm: Option<VirtualMachine<VecInput, VecOutput>>,
+}
+
+impl RamMachine {
+    /// Get the machine running the program, starting it if needed
+    fn vm(&mut self) -> &mut VirtualMachine<VecInput, VecOutput> {
+        self.vm.get_or_insert_with(|| {
+            VirtualMachine::builder(
+                self.program.clone(),
+                VecInput::new(self.input.clone()),
+                VecOutput::new(),
+                Arc::clone(&self.db),
+            )
+            .with_memory_values(self.registers.iter().copied())
+            .build()
+        })
+    }
+}
+
+/// Record the message of an error on this thread
+fn set_last_error(message: impl fmt::Display) {
+    // Interior nul bytes would truncate the message, so they are dropped
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|error| *error.borrow_mut() = CString::new(message).ok());
+}
+
+/// Run the body of an exported function, returning `on_panic` if it panics
+///
+/// Unwinding into foreign code is undefined behavior, so the panic stops here
+/// and its message becomes the last error.
+fn guard<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        set_last_error(format!("the library panicked: {message}"));
+        on_panic
+    })
+}
+
+/// Load a program from its source
+///
+/// Returns null if the source is null, not UTF-8 or not a valid program; see
+/// `ram_last_error` for why. The machine must be freed with `ram_machine_free`.
+///
+/// # Safety
+///
+/// `source` must be null or point to a nul-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_load(source: *const c_char) -> *mut RamMachine {
+    guard(std::ptr::null_mut(), || {
+        if source.is_null() {
+            set_last_error("the source is null");
+            return std::ptr::null_mut();
+        }
+        // SAFETY: the caller guarantees that `source` is a nul-terminated string
+        let source = match unsafe { CStr::from_ptr(source) }.to_str() {
+            Ok(source) => source,
+            Err(err) => {
+                set_last_error(format!("the source is not valid UTF-8: {err}"));
+                return std::ptr::null_mut();
+            }
+        };
+
+        let db = Arc::new(VmDatabaseImpl::new());
+        match db.parse_to_vm_program(source) {
+            Ok(program) => Box::into_raw(Box::new(RamMachine {
+                program,
+                db,
+                input: Vec::new(),
+                registers: Vec::new(),
+                vm: None,
+            })),
+            Err(err) => {
+                set_last_error(err);
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Free a machine created by `ram_machine_load`
+///
+/// # Safety
+///
+/// `machine` must be null or a machine returned by `ram_machine_load` that was
+/// not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_free(machine: *mut RamMachine) {
+    guard((), || {
+        if !machine.is_null() {
+            // SAFETY: the caller guarantees that the machine is owned and live
+            drop(unsafe { Box::from_raw(machine) });
+        }
+    })
+}
+
+/// Set the values the program reads, restarting it
+///
+/// # Safety
+///
+/// `machine` must be a live machine and `values` must point to `len` values,
+/// or be null if `len` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_set_input(
+    machine: *mut RamMachine,
+    values: *const i64,
+    len: usize,
+) -> RamResult {
+    guard(RamResult::Panicked, || {
+        // SAFETY: the caller guarantees that the machine is live
+        let Some(machine) = (unsafe { machine.as_mut() }) else {
+            set_last_error("the machine is null");
+            return RamResult::NullPointer;
+        };
+        machine.input = if len == 0 {
+            Vec::new()
+        } else if values.is_null() {
+            set_last_error("the input values are null");
+            return RamResult::NullPointer;
+        } else {
+            // SAFETY: the caller guarantees that `values` points to `len` values
+            unsafe { std::slice::from_raw_parts(values, len) }.to_vec()
+        };
+        machine.vm = None;
+        RamResult::Ok
+    })
+}
+
+/// Set the initial value of a register, restarting the program
+///
+/// Register 0 is the accumulator. A negative address, or one past the memory
+/// limit, is rejected with `InvalidAddress` and leaves the registers as they
+/// are.
+///
+/// # Safety
+///
+/// `machine` must be a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_set_register(
+    machine: *mut RamMachine,
+    address: i64,
+    value: i64,
+) -> RamResult {
+    guard(RamResult::Panicked, || {
+        // SAFETY: the caller guarantees that the machine is live
+        let Some(machine) = (unsafe { machine.as_mut() }) else {
+            set_last_error("the machine is null");
+            return RamResult::NullPointer;
+        };
+        if let Err(err) = Memory::check_address(address) {
+            set_last_error(err);
+            return RamResult::InvalidAddress;
+        }
+        machine.registers.retain(|(other, _)| *other != address);
+        machine.registers.push((address, value));
+        machine.vm = None;
+        RamResult::Ok
+    })
+}
+
+/// Run the program, executing at most `max_steps` instructions
+///
+/// A `max_steps` of 0 runs the program until it halts. A paused run continues
+/// where it stopped when run again. A failed run sets the last error to the
+/// runtime error.
+///
+/// # Safety
+///
+/// `machine` must be a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_run(machine: *mut RamMachine, max_steps: u64) -> RamStatus {
+    guard(RamStatus::Failed, || {
+        // SAFETY: the caller guarantees that the machine is live
+        let Some(machine) = (unsafe { machine.as_mut() }) else {
+            set_last_error("the machine is null");
+            return RamStatus::Failed;
+        };
+        let vm = machine.vm();
+        let result = match usize::try_from(max_steps) {
+            Ok(0) | Err(_) => vm.run(),
+            Ok(max_steps) => vm.run_steps(max_steps),
+        };
+
+        match result {
+            Err(err) => {
+                set_last_error(err);
+                RamStatus::Failed
+            }
+            Ok(()) if vm.is_finished() => RamStatus::Halted,
+            Ok(()) => RamStatus::Paused,
+        }
+    })
+}
+
+/// Get the number of instructions executed since the program started
+///
+/// # Safety
+///
+/// `machine` must be null or a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_steps(machine: *const RamMachine) -> u64 {
+    guard(0, || {
+        // SAFETY: the caller guarantees that the machine is null or live
+        unsafe { machine.as_ref() }
+            .and_then(|machine| machine.vm.as_ref())
+            .map_or(0, VirtualMachine::steps)
+    })
+}
+
+/// Get the value of the accumulator
+///
+/// # Safety
+///
+/// `machine` must be null or a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_accumulator(machine: *const RamMachine) -> i64 {
+    guard(0, || {
+        // SAFETY: the caller guarantees that the machine is null or live
+        unsafe { machine.as_ref() }
+            .and_then(|machine| machine.vm.as_ref())
+            .map_or(0, VirtualMachine::accumulator)
+    })
+}
+
+/// Read the value of a register into `value`
+///
+/// Register 0 is the accumulator. Registers the program never wrote are 0.
+///
+/// # Safety
+///
+/// `machine` must be a live machine and `value` must point to writable memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_register(
+    machine: *mut RamMachine,
+    address: i64,
+    value: *mut i64,
+) -> RamResult {
+    guard(RamResult::Panicked, || {
+        // SAFETY: the caller guarantees that the machine is live
+        let Some(machine) = (unsafe { machine.as_mut() }) else {
+            set_last_error("the machine is null");
+            return RamResult::NullPointer;
+        };
+        if value.is_null() {
+            set_last_error("the value is null");
+            return RamResult::NullPointer;
+        }
+        match machine.vm().get_register(address) {
+            Ok(register) => {
+                // SAFETY: the caller guarantees that `value` is writable
+                unsafe { value.write(register) };
+                RamResult::Ok
+            }
+            Err(err) => {
+                set_last_error(err);
+                RamResult::Runtime
+            }
+        }
+    })
+}
+
+/// Copy the values written by the program into `buffer`
+///
+/// At most `capacity` values are copied. Returns the number of values written
+/// by the program, which may be more than `capacity`: call with a null buffer
+/// to learn how large it has to be.
+///
+/// # Safety
+///
+/// `machine` must be null or a live machine, and `buffer` must be null or
+/// point to room for `capacity` values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ram_machine_output(
+    machine: *const RamMachine,
+    buffer: *mut i64,
+    capacity: usize,
+) -> usize {
+    guard(0, || {
+        // SAFETY: the caller guarantees that the machine is null or live
+        let Some(vm) = unsafe { machine.as_ref() }.and_then(|machine| machine.vm.as_ref()) else {
+            return 0;
+        };
+        let output = &vm.output.values;
+        if !buffer.is_null() {
+            let len = output.len().min(capacity);
+            // SAFETY: the caller guarantees that `buffer` has room for `capacity` values
+            unsafe { std::ptr::copy_nonoverlapping(output.as_ptr(), buffer, len) };
+        }
+        output.len()
+    })
+}
+
+/// Get the message of the last error on the calling thread
+///
+/// Returns null if no function failed on this thread. The message is valid
+/// until the next call that fails on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn ram_last_error() -> *const c_char {
+    guard(std::ptr::null(), || {
+        LAST_ERROR
+            .with(|error| error.borrow().as_ref().map_or(std::ptr::null(), |error| error.as_ptr()))
+    })
+}
@@ -0,0 +1,110 @@
+//! Tests for loading, configuring and running programs through the C API
+
+use std::ffi::{CStr, CString};
+
+use super::*;
+
+/// Load a program, panicking with the last error if it is invalid
+fn load(source: &str) -> *mut RamMachine {
+    let source = CString::new(source).unwrap();
+    let machine = unsafe { ram_machine_load(source.as_ptr()) };
+    assert!(!machine.is_null(), "{}", last_error());
+    machine
+}
+
+/// Get the message of the last error
+fn last_error() -> String {
+    let error = ram_last_error();
+    assert!(!error.is_null());
+    unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+}
+
+/// Get the values written by the program
+fn output(machine: *const RamMachine) -> Vec<i64> {
+    let len = unsafe { ram_machine_output(machine, std::ptr::null_mut(), 0) };
+    let mut buffer = vec![0; len];
+    assert_eq!(unsafe { ram_machine_output(machine, buffer.as_mut_ptr(), len) }, len);
+    buffer
+}
+
+#[test]
+fn test_run_program() {
+    let machine = load("READ 1\nLOAD 1\nADD 2\nSTORE 3\nWRITE 3\nHALT\n");
+    let input = [5];
+    unsafe {
+        assert_eq!(ram_machine_set_input(machine, input.as_ptr(), input.len()), RamResult::Ok);
+        assert_eq!(ram_machine_set_register(machine, 2, 10), RamResult::Ok);
+        assert_eq!(ram_machine_run(machine, 0), RamStatus::Halted);
+        assert_eq!(ram_machine_accumulator(machine), 15);
+        assert_eq!(ram_machine_steps(machine), 6);
+    }
+    assert_eq!(output(machine), vec![15]);
+
+    let mut value = 0;
+    unsafe {
+        assert_eq!(ram_machine_register(machine, 3, &mut value), RamResult::Ok);
+        ram_machine_free(machine);
+    }
+    assert_eq!(value, 15);
+}
+
+#[test]
+fn test_step_limit() {
+    let machine = load("loop: LOAD 1\nADD =1\nSTORE 1\nJUMP loop\n");
+    unsafe {
+        assert_eq!(ram_machine_run(machine, 10), RamStatus::Paused);
+        assert_eq!(ram_machine_steps(machine), 10);
+
+        // A paused run continues where it stopped
+        assert_eq!(ram_machine_run(machine, 10), RamStatus::Paused);
+        assert_eq!(ram_machine_steps(machine), 20);
+
+        // Setting a register restarts the program
+        assert_eq!(ram_machine_set_register(machine, 1, 100), RamResult::Ok);
+        assert_eq!(ram_machine_steps(machine), 0);
+        assert_eq!(ram_machine_run(machine, 3), RamStatus::Paused);
+        assert_eq!(ram_machine_accumulator(machine), 101);
+        ram_machine_free(machine);
+    }
+}
+
+#[test]
+fn test_errors() {
+    let source = CString::new("LOAD @1\n").unwrap();
+    assert!(unsafe { ram_machine_load(source.as_ptr()) }.is_null());
+    assert!(!last_error().is_empty());
+    assert!(unsafe { ram_machine_load(std::ptr::null()) }.is_null());
+    assert_eq!(last_error(), "the source is null");
+
+    // Reading past the end of the input is a runtime error
+    let machine = load("READ 1\nHALT\n");
+    unsafe {
+        assert_eq!(ram_machine_run(machine, 0), RamStatus::Failed);
+        assert_eq!(ram_machine_set_input(machine, std::ptr::null(), 1), RamResult::NullPointer);
+        ram_machine_free(machine);
+        assert_eq!(ram_machine_run(std::ptr::null_mut(), 0), RamStatus::Failed);
+    }
+}
+
+#[test]
+fn test_invalid_register_address() {
+    let machine = load("LOAD 1\nHALT\n");
+    unsafe {
+        assert_eq!(ram_machine_set_register(machine, -1, 5), RamResult::InvalidAddress);
+        assert!(last_error().contains("negative"));
+        assert_eq!(ram_machine_set_register(machine, i64::MAX, 5), RamResult::InvalidAddress);
+        assert!(last_error().contains("too large"));
+
+        // The registers that were rejected are not set
+        assert_eq!(ram_machine_run(machine, 0), RamStatus::Halted);
+        assert_eq!(ram_machine_accumulator(machine), 0);
+        ram_machine_free(machine);
+    }
+}
+
+#[test]
+fn test_panics_do_not_unwind() {
+    assert_eq!(guard(RamResult::Panicked, || panic!("out of cheese")), RamResult::Panicked);
+    assert_eq!(last_error(), "the library panicked: out of cheese");
+    assert_eq!(guard(RamResult::Panicked, || RamResult::Ok), RamResult::Ok);
+}
@@ -0,0 +1,422 @@
+//! Analysis of the text of a file.
+//!
+//! A file is parsed, lowered to an item tree and a HIR body, and run through
+//! the analysis passes, each stage ending at a cancellation checkpoint. The
+//! analysis needs nothing but its [`AnalysisInput`], so it can run on any
+//! thread while the inputs of its host keep changing.
+//!
+//! Given the [`PassResults`] of the last analysis of the file, the passes are
+//! only run again if the parts of the body they read changed. Labels are
+//! lowered with the IDs they had then, so items added above them change
+//! nothing but the spans.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use base_db::LineIndex;
+use hir::body::Body;
+use hir::lower::LabelIds;
+use hir_analysis::analyzers::constant_propagation::{
+    ConstantPropagationAnalysis, ConstantPropagationResult,
+};
+use hir_analysis::analyzers::control_flow_optimizer::ControlFlowOptimizer;
+use hir_analysis::{
+    AccumulatorVersions, AliasAnalysis, AnalysisConfig, AnalysisContext, AnalysisPipeline,
+    ControlFlowAnalysis, ControlFlowGraph, DataFlowAnalysis, DominanceAnalysis,
+    InstructionValidationAnalysis, IoContractAnalysis, SchedulingAnalysis, Section,
+    SectionAnalysis, SsaAnalysis, StrictRamAnalysis, TerminationAnalysis, UnusedLabelAnalysis,
+};
+use hir_def::item_tree::{ItemTree, ModulePath};
+use ram_core::InstructionRegistry;
+use ram_diagnostics::{Diagnostic, DiagnosticCollection, DiagnosticConfig};
+use ram_parser::parse;
+use ram_syntax::{AstNode, Program, ResolvedNode, SyntaxNode};
+
+use crate::cancellation::{CancellationToken, Cancelled};
+
+/// The result of analyzing the text of a file
+#[derive(Debug, Clone)]
+pub struct FileAnalysis {
+    /// The analyzed text
+    pub text: String,
+    /// The line index of the analyzed text
+    pub line_index: Arc<LineIndex>,
+    /// The syntax tree of the text
+    pub syntax_tree: ResolvedNode,
+    /// The diagnostics found in the text
+    pub diagnostics: DiagnosticCollection,
+    /// The paths imported by the `use` statements of the text, or `None` if
+    /// the text could not be lowered
+    pub imports: Option<Vec<ModulePath>>,
+    /// The HIR body of the text, or `None` if the text could not be lowered
+    pub body: Option<Arc<Body>>,
+    /// The control flow graph of the body, or `None` if it could not be built
+    pub control_flow: Option<Arc<ControlFlowGraph>>,
+    /// The versions of the accumulator of the body, or `None` if they could
+    /// not be numbered
+    pub accumulator_versions: Option<Arc<AccumulatorVersions>>,
+    /// The values of the accumulator known statically after each instruction,
+    /// or `None` if they could not be propagated
+    pub constants: Option<Arc<ConstantPropagationResult>>,
+    /// The label sections of the body and their metrics, or `None` if the
+    /// sections convention is off or they could not be measured
+    pub sections: Option<Arc<Vec<Section>>>,
+    /// The results of the analysis passes, or `None` if they could not run
+    pub passes: Option<Arc<PassResults>>,
+    /// The revision the inputs of the file last changed at before the analysis
+    pub file_revision: u64,
+    /// The revisions the imported modules last changed at before the analysis
+    pub dependency_revisions: Vec<u64>,
+    /// The version of the document the client had when it was analyzed
+    pub version: Option<i32>,
+}
+
+impl FileAnalysis {
+    /// Get the ID identifying the diagnostics of this analysis in pull requests
+    ///
+    /// Diagnostics depend on the modules the file imports, so the ID changes
+    /// with their revisions too.
+    pub fn result_id(&self) -> String {
+        std::iter::once(self.file_revision)
+            .chain(self.dependency_revisions.iter().copied())
+            .map(|revision| revision.to_string())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Everything needed to analyze a file without access to its host
+#[derive(Debug, Clone)]
+pub struct AnalysisInput {
+    /// The text of the file
+    pub text: String,
+    /// The line index of the text
+    pub line_index: Arc<LineIndex>,
+    /// The labels other files import, or `None` if every label is exported
+    pub exported: Option<HashSet<String>>,
+    /// The severity configuration
+    pub config: DiagnosticConfig,
+    /// The instructions available to the file, or `None` for the standard ones
+    pub instructions: Option<Arc<InstructionRegistry>>,
+    /// The modules the file imports with a wildcard
+    pub modules: Vec<ModuleSource>,
+    /// The results of the passes of the last analysis of the file, to reuse
+    /// those the changes of the body leave valid
+    pub previous: Option<Arc<PassResults>>,
+    /// The revision the inputs of the file last changed at
+    pub file_revision: u64,
+    /// The revisions the inputs of the imported modules last changed at, in
+    /// the order of `modules`
+    pub dependency_revisions: Vec<u64>,
+    /// The version of the document, if the client opened it
+    pub version: Option<i32>,
+}
+
+/// A module imported by the analyzed file
+#[derive(Debug, Clone)]
+pub struct ModuleSource {
+    /// The name of the file defining the module, used in diagnostics
+    pub file_name: String,
+    /// The text of the file
+    pub text: String,
+}
+
+/// The results of the analysis passes run on the body of a file
+#[derive(Debug)]
+pub struct PassResults {
+    /// The context the passes ran in
+    context: AnalysisContext,
+    /// The labels the unused label pass was told other files import
+    exported: Option<HashSet<String>>,
+    /// The IDs the labels of the body were lowered with
+    labels: LabelIds,
+}
+
+/// A stage of the analysis of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisStage {
+    /// Parsing the text into a syntax tree
+    Parsing,
+    /// Lowering the syntax tree to an item tree and a HIR body
+    ItemTree,
+    /// Running the analysis passes on the HIR body
+    Passes,
+}
+
+impl AnalysisStage {
+    /// Every stage, in the order they run in
+    pub const ALL: [AnalysisStage; 3] =
+        [AnalysisStage::Parsing, AnalysisStage::ItemTree, AnalysisStage::Passes];
+
+    /// Get the name of the stage shown in progress reports
+    pub fn name(self) -> &'static str {
+        match self {
+            AnalysisStage::Parsing => "parsing",
+            AnalysisStage::ItemTree => "building item tree",
+            AnalysisStage::Passes => "running analysis passes",
+        }
+    }
+}
+
+/// Parse and analyze the text of a file
+///
+/// `on_stage` is called when each stage of the analysis starts. Stops with
+/// [`Cancelled`] at the checkpoints between the stages once `token` is
+/// cancelled.
+pub fn analyze_file(
+    input: &AnalysisInput,
+    token: &CancellationToken,
+    mut on_stage: impl FnMut(AnalysisStage),
+) -> Result<FileAnalysis, Cancelled> {
+    let text = input.text.as_str();
+
+    // Parse the file
+    on_stage(AnalysisStage::Parsing);
+    let (syntax_tree, parser_diagnostics) = parse_file(text);
+    token.check()?;
+
+    // Create a diagnostic collection
+    let mut diagnostic_collection = DiagnosticCollection::new();
+
+    // Parser diagnostics already use ram_diagnostics::Diagnostic
+    for parser_diag in parser_diagnostics {
+        diagnostic_collection.add(parser_diag);
+    }
+
+    // Try to perform semantic analysis if the syntax is valid
+    let mut imports = None;
+    let mut hir_body = None;
+    let mut control_flow = None;
+    let mut accumulator_versions = None;
+    let mut constants = None;
+    let mut sections = None;
+    let mut passes = None;
+    if !diagnostic_collection.has_errors() {
+        // Convert syntax tree to AST Program
+        if let Some(program) = Program::cast(syntax_tree.clone()) {
+            // Lower the program, reporting any problems found in its items
+            on_stage(AnalysisStage::ItemTree);
+            let item_tree = ItemTree::lower(&program, base_db::input::FileId(0));
+            for diagnostic in &item_tree.diagnostics {
+                diagnostic_collection.add(diagnostic.to_diagnostic());
+            }
+            for diagnostic in imported_module_diagnostics(&item_tree, &input.modules) {
+                diagnostic_collection.add(diagnostic);
+            }
+            imports =
+                Some(item_tree.use_stmts.iter().map(|use_def| use_def.path.clone()).collect());
+            // Labels keep their IDs across edits, so passes on them can be reused
+            let label_ids = input.previous.as_ref().map(|previous| &previous.labels);
+            let body = Arc::new(create_hir_body_from_program(&program, &item_tree, label_ids));
+            let labels = LabelIds::of(&body, &item_tree);
+            token.check()?;
+
+            // Run HIR analysis
+            on_stage(AnalysisStage::Passes);
+            let mut pipeline = AnalysisPipeline::new();
+            if let Some(instructions) = &input.instructions {
+                pipeline.set_instruction_registry(Arc::clone(instructions));
+            }
+
+            // Register analysis passes
+            pipeline.register::<InstructionValidationAnalysis>().ok();
+            pipeline.register::<ControlFlowAnalysis>().ok();
+            pipeline.register::<DominanceAnalysis>().ok();
+            pipeline.register::<DataFlowAnalysis>().ok();
+            pipeline.register::<SsaAnalysis>().ok();
+            pipeline.register::<ConstantPropagationAnalysis>().ok();
+            pipeline.register::<AliasAnalysis>().ok();
+            pipeline.register::<ControlFlowOptimizer>().ok();
+            pipeline.register::<SchedulingAnalysis>().ok();
+            pipeline.register::<IoContractAnalysis>().ok();
+            if input.config.strict_ram() {
+                pipeline.register::<StrictRamAnalysis>().ok();
+            }
+            if input.config.termination() {
+                pipeline.register::<TerminationAnalysis>().ok();
+            }
+            if input.config.sections() {
+                pipeline.register::<SectionAnalysis>().ok();
+            }
+            if let Some(exported) = input.exported.clone() {
+                pipeline.register_pass(UnusedLabelAnalysis::with_exported(exported)).ok();
+            }
+            // Report a configuration the passes reject at the start of the file
+            if let Err(err) = pipeline.set_config(AnalysisConfig::from(&input.config)) {
+                diagnostic_collection.add(Diagnostic::error(
+                    err.to_string(),
+                    "Check the [analysis.passes] section of ram.toml".to_string(),
+                    0..0,
+                ));
+            }
+
+            // Run the analysis, rerunning only the passes the edit outdated.
+            // The unused label pass is given the exported labels, so it can
+            // only be reused if they are the same.
+            let result = match &input.previous {
+                Some(previous) if previous.exported == input.exported => {
+                    pipeline.reanalyze(&previous.context, Arc::clone(&body))
+                }
+                _ => pipeline.analyze(Arc::clone(&body)),
+            };
+            if let Ok(context) = result {
+                // Add semantic diagnostics to our collection
+                diagnostic_collection.extend(context.diagnostics().clone());
+                control_flow = context.get_result::<ControlFlowAnalysis>().ok();
+                accumulator_versions = context.get_result::<SsaAnalysis>().ok();
+                constants = context.get_result::<ConstantPropagationAnalysis>().ok();
+                sections = context.get_result::<SectionAnalysis>().ok();
+                passes = Some(Arc::new(PassResults {
+                    context,
+                    exported: input.exported.clone(),
+                    labels,
+                }));
+            }
+            hir_body = Some(body);
+            token.check()?;
+        }
+    }
+
+    // Apply the configured severities and suppression comments
+    diagnostic_collection.apply_config(&input.config, text);
+
+    Ok(FileAnalysis {
+        text: input.text.clone(),
+        line_index: Arc::clone(&input.line_index),
+        syntax_tree,
+        diagnostics: diagnostic_collection,
+        imports,
+        body: hir_body,
+        control_flow,
+        accumulator_versions,
+        constants,
+        sections,
+        passes,
+        file_revision: input.file_revision,
+        dependency_revisions: input.dependency_revisions.clone(),
+        version: input.version,
+    })
+}
+
+/// Report the labels of a file that are also defined in the modules it imports
+///
+/// Each module is lowered to an item tree of its own, so the first definition
+/// is reported in the file of the module.
+pub fn imported_module_diagnostics(
+    item_tree: &ItemTree,
+    modules: &[ModuleSource],
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (index, module) in modules.iter().enumerate() {
+        let Some(program) = Program::cast(parse_file(&module.text).0) else {
+            continue;
+        };
+        // File 0 is the analyzed file
+        let module_file = base_db::input::FileId(index as u32 + 1);
+        let module_tree = ItemTree::lower(&program, module_file);
+        diagnostics.extend(item_tree.duplicate_labels_in(&module_tree).iter().map(|diagnostic| {
+            diagnostic.to_diagnostic_with_files(|file_id| {
+                (file_id == module_file).then(|| module.file_name.clone())
+            })
+        }));
+    }
+    diagnostics
+}
+
+/// Parse the text of a file into a syntax tree
+pub fn parse_file(text: &str) -> (ResolvedNode, Vec<Diagnostic>) {
+    let (events, diagnostics) = parse(text);
+
+    // Build the syntax tree
+    let (green_node, interner) = ram_parser::build_tree(events);
+    (SyntaxNode::new_root_with_resolver(green_node, interner), diagnostics)
+}
+
+/// Create a HIR body from an AST Program
+/// Uses the proper lowering logic from the hir crate, giving the labels the IDs
+/// of the last analysis if any
+fn create_hir_body_from_program(
+    program: &Program,
+    item_tree: &ItemTree,
+    label_ids: Option<&LabelIds>,
+) -> Body {
+    // Create a dummy file ID for this program
+    let file_id = base_db::input::FileId(0);
+
+    // Create a dummy DefId for the program
+    let def_id = hir::ids::DefId { file_id, local_id: hir::ids::LocalDefId(0) };
+
+    // Lower the AST Program to a HIR Body
+    let body = match label_ids {
+        Some(label_ids) => {
+            hir::lower::lower_program_with_label_ids(program, def_id, file_id, item_tree, label_ids)
+        }
+        None => hir::lower::lower_program(program, def_id, file_id, item_tree),
+    };
+    match body {
+        Ok(body) => body,
+        Err(err) => {
+            // Log the error
+            tracing::error!("Failed to lower program to HIR: {:?}", err);
+            // Return an empty body as fallback
+            Body::default()
+        }
+    }
+}
+
+/// Get the paths imported by the `use` statements of a text
+///
+/// Only the text is parsed and lowered to an item tree, so the imports of
+/// every file are known before any of them is analyzed.
+pub fn file_imports(text: &str) -> Vec<ModulePath> {
+    let Some(program) = Program::cast(parse_file(text).0) else {
+        return Vec::new();
+    };
+    let item_tree = ItemTree::lower(&program, base_db::input::FileId(0));
+    item_tree.use_stmts.iter().map(|use_def| use_def.path.clone()).collect()
+}
+
+/// Get the modules imported whole by the `use` statements of a text
+pub fn wildcard_imports(text: &str) -> Vec<String> {
+    let Some(program) = Program::cast(parse_file(text).0) else {
+        return Vec::new();
+    };
+    let item_tree = ItemTree::lower(&program, base_db::input::FileId(0));
+    item_tree.wildcard_imports().into_iter().map(str::to_string).collect()
+}
+
+/// Collect the labels of module `module_name` imported by other files
+///
+/// Returns `None` when another file imports the whole module with a
+/// wildcard.
+pub fn exported_labels<'a>(
+    module_name: &str,
+    imports: impl IntoIterator<Item = &'a ModulePath>,
+) -> Option<HashSet<String>> {
+    let mut exported = HashSet::new();
+    for path in imports {
+        let Some((module, symbol)) = path.module_and_symbol() else {
+            continue;
+        };
+
+        if module != module_name {
+            continue;
+        }
+        match symbol {
+            Some(symbol) => {
+                exported.insert(symbol.to_string());
+            }
+            None => return None,
+        }
+    }
+    Some(exported)
+}
+
+/// Get the name of the module a file is imported as, which is its file stem
+pub fn module_name(path: &str) -> String {
+    path.rsplit(['/', '\\'])
+        .next()
+        .and_then(|file_name| file_name.split('.').next())
+        .unwrap_or_default()
+        .to_string()
+}
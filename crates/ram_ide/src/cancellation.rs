@@ -0,0 +1,82 @@
+//! Cancellation of analyses.
+//!
+//! As with salsa queries, the inputs of an analysis host have a revision that
+//! every change bumps, and work started at an older revision is cancelled at
+//! its next checkpoint. Work can also be cancelled explicitly, as language
+//! servers do when a client cancels a request.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Returned by work that stopped because it was cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the work was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// The revision of the inputs of the database
+#[derive(Debug, Clone, Default)]
+pub struct Revision(Arc<AtomicU64>);
+
+impl Revision {
+    /// Get the current revision
+    pub fn current(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Start a new revision, cancelling all work started at older revisions
+    ///
+    /// Returns the new revision.
+    pub fn bump(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Create a token for work started at the current revision
+    pub fn token(&self) -> CancellationToken {
+        CancellationToken {
+            revision: self.clone(),
+            started_at: self.current(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Tells long-running work whether it should stop
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    /// The revision of the database
+    revision: Revision,
+    /// The revision the work was started at
+    started_at: u64,
+    /// Set when the request the work is done for was cancelled
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Get the revision the work was started at
+    pub fn revision(&self) -> u64 {
+        self.started_at
+    }
+
+    /// Cancel the work, independently of the revision
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Check whether the work was cancelled, explicitly or by a new revision
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire) || self.revision.current() != self.started_at
+    }
+
+    /// A checkpoint of long-running work: stop if it was cancelled
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() { Err(Cancelled) } else { Ok(()) }
+    }
+}
@@ -0,0 +1,399 @@
+//! Context-aware completion.
+//!
+//! The tokens before the cursor decide what is completed: opcodes at the start
+//! of a statement, labels after a jump or call, modules after `use`, the labels
+//! of a module after `use module::`, and the memory addresses used in the file
+//! after `*`, `=` or an instruction that writes its operand, such as `STORE`.
+//!
+//! The analysis of the file ranks the completions, through [`CompletionHints`]:
+//! the labels of the loop around the cursor come first, as do the addresses
+//! loaded before a `STORE`, and instructions the active instruction set does
+//! not allow come last.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use hir_analysis::DominanceInfo;
+use ram_core::instructions::CallInstruction;
+use ram_core::{InstructionKind, InstructionRegistry};
+use ram_syntax::cursor::TokenCursor;
+use ram_syntax::{AstNode, Program, ResolvedNode, SyntaxKind};
+
+use crate::analysis::FileAnalysis;
+use crate::docs::instruction_documentation;
+
+/// What a completion inserts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// The opcode of an instruction
+    Instruction,
+    /// A label
+    Label,
+    /// A module
+    Module,
+    /// A memory address or value
+    Address,
+}
+
+/// A completion offered at the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionItem {
+    /// The text inserted by the completion
+    pub label: String,
+    /// What the completion inserts
+    pub kind: CompletionKind,
+    /// A short description shown next to the label
+    pub detail: Option<String>,
+    /// The documentation of the completion, as markdown
+    pub documentation: Option<String>,
+    /// The text completions are sorted by, if not their label
+    pub sort_text: Option<String>,
+}
+
+impl CompletionItem {
+    /// Create a completion without details
+    fn new(label: String, kind: CompletionKind) -> Self {
+        Self { label, kind, detail: None, documentation: None, sort_text: None }
+    }
+}
+
+/// What the analysis of a file tells about the cursor, to rank completions
+#[derive(Debug, Clone, Default)]
+pub struct CompletionHints {
+    /// The labels of the innermost loop around the cursor
+    pub loop_labels: HashSet<String>,
+    /// The instructions the active instruction set allows, or `None` if it
+    /// allows every standard instruction
+    pub instructions: Option<Arc<InstructionRegistry>>,
+}
+
+impl CompletionHints {
+    /// Summarize the analysis of a file for completions at `offset`
+    ///
+    /// The loop around the cursor is the innermost loop of the instruction
+    /// the cursor is in or after. The analysis may be of an older text, while
+    /// the file is being edited, in which case the loop is only a guess.
+    pub fn from_analysis(
+        analysis: &FileAnalysis,
+        offset: usize,
+        instructions: Option<Arc<InstructionRegistry>>,
+    ) -> Self {
+        let mut loop_labels = HashSet::new();
+        if let (Some(body), Some(cfg)) = (&analysis.body, &analysis.control_flow)
+            && let Some(instr) = body
+                .instructions
+                .values()
+                .filter(|instr| instr.span.start <= offset)
+                .max_by_key(|instr| instr.span.start)
+            && let Some(node_idx) = cfg.get_node_by_instruction(instr.id)
+        {
+            let dominance = DominanceInfo::new(cfg);
+            if let Some(natural_loop) = dominance.innermost_loop(node_idx) {
+                loop_labels = body
+                    .instructions
+                    .values()
+                    .filter(|instr| {
+                        cfg.get_node_by_instruction(instr.id)
+                            .is_some_and(|node_idx| natural_loop.nodes.contains(&node_idx))
+                    })
+                    .filter_map(|instr| instr.label_name.clone())
+                    .collect();
+            }
+        }
+        Self { loop_labels, instructions }
+    }
+
+    /// Check whether the active instruction set allows an instruction
+    fn allows(&self, name: &str) -> bool {
+        self.instructions
+            .as_ref()
+            .is_none_or(|registry| registry.get_by_name_case_insensitive(name).is_some())
+    }
+}
+
+/// Complete the text at `offset`
+///
+/// `modules` are the other modules of the workspace, with the syntax tree of
+/// their file if it was parsed, which complete `use` statements and the
+/// labels imported from them. `hints` rank the completions.
+pub fn completions(
+    syntax_tree: &ResolvedNode,
+    offset: usize,
+    modules: &[(String, Option<ResolvedNode>)],
+    hints: &CompletionHints,
+) -> Vec<CompletionItem> {
+    let module_labels = |module: &str| {
+        modules
+            .iter()
+            .find(|(name, _)| name == module)
+            .and_then(|(_, syntax_tree)| syntax_tree.as_ref())
+            .map(defined_labels)
+            .unwrap_or_default()
+    };
+
+    match completion_context(syntax_tree, offset) {
+        Some(CompletionContext::Instruction) => InstructionKind::standard_instructions_info()
+            .into_iter()
+            .map(|info| {
+                // Instructions the instruction set does not allow come last
+                let (rank, detail) = if hints.allows(&info.name) {
+                    (0, info.metadata.summary.clone())
+                } else {
+                    (1, format!("{} (not in the active instruction set)", info.metadata.summary))
+                };
+                CompletionItem {
+                    detail: Some(detail),
+                    documentation: Some(instruction_documentation(&info)),
+                    sort_text: Some(format!("{rank}{}", info.name)),
+                    ..CompletionItem::new(info.name.clone(), CompletionKind::Instruction)
+                }
+            })
+            .collect(),
+        Some(CompletionContext::Label) => {
+            let mut items = defined_labels(syntax_tree)
+                .into_iter()
+                .map(|label| {
+                    // The labels of the loop around the cursor come first
+                    let rank = if hints.loop_labels.contains(&label) { 0 } else { 1 };
+                    CompletionItem {
+                        sort_text: Some(format!("{rank}{label}")),
+                        ..label_completion(label, None)
+                    }
+                })
+                .collect::<Vec<_>>();
+            for (module, symbol) in imported_modules(syntax_tree) {
+                let labels = match symbol {
+                    Some(symbol) => vec![symbol],
+                    None => module_labels(&module),
+                };
+                items.extend(labels.into_iter().map(|label| CompletionItem {
+                    sort_text: Some(format!("2{label}")),
+                    ..label_completion(label, Some(&module))
+                }));
+            }
+            items
+        }
+        Some(CompletionContext::Module) => modules
+            .iter()
+            .map(|(name, _)| CompletionItem::new(name.clone(), CompletionKind::Module))
+            .collect(),
+        Some(CompletionContext::ModuleSymbol(module)) => module_labels(&module)
+            .into_iter()
+            .map(|label| label_completion(label, Some(&module)))
+            .collect(),
+        Some(CompletionContext::Address) => memory_addresses(syntax_tree, offset)
+            .into_iter()
+            .enumerate()
+            .map(|(index, address)| CompletionItem {
+                detail: Some("Used in this file".to_string()),
+                // Keep the nearest addresses first
+                sort_text: Some(format!("{index:05}")),
+                ..CompletionItem::new(address, CompletionKind::Address)
+            })
+            .collect(),
+        Some(CompletionContext::Destination) => {
+            // The addresses loaded before the cursor come first, as the value
+            // is likely stored back where it came from
+            let loaded = loaded_addresses(syntax_tree, offset);
+            let others = memory_addresses(syntax_tree, offset)
+                .into_iter()
+                .filter(|address| !loaded.contains(address))
+                .collect::<Vec<_>>();
+            let loaded_count = loaded.len();
+            loaded
+                .into_iter()
+                .chain(others)
+                .enumerate()
+                .map(|(index, address)| CompletionItem {
+                    detail: Some(
+                        if index < loaded_count { "Loaded before" } else { "Used in this file" }
+                            .to_string(),
+                    ),
+                    sort_text: Some(format!("{index:05}")),
+                    ..CompletionItem::new(address, CompletionKind::Address)
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Complete a label, defined in this file or imported from `module`
+fn label_completion(label: String, module: Option<&str>) -> CompletionItem {
+    CompletionItem {
+        detail: Some(match module {
+            Some(module) => format!("Label from `{module}`"),
+            None => "Label".to_string(),
+        }),
+        ..CompletionItem::new(label, CompletionKind::Label)
+    }
+}
+
+/// What the cursor is completing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionContext {
+    /// The opcode of an instruction
+    Instruction,
+    /// The label a jump goes to
+    Label,
+    /// The module of a `use` statement
+    Module,
+    /// The symbol imported from a module by a `use` statement
+    ModuleSymbol(String),
+    /// The address or value of an indirect or immediate operand
+    Address,
+    /// The register an instruction such as `STORE` writes
+    Destination,
+}
+
+/// Classify what is being completed at `offset`
+///
+/// Returns `None` where nothing can be completed, such as inside comments or
+/// after the operand of an instruction.
+pub fn completion_context(syntax_tree: &ResolvedNode, offset: usize) -> Option<CompletionContext> {
+    let mut tokens = TokenCursor::new(syntax_tree)
+        .into_tokens()
+        .into_iter()
+        .filter(|token| token.range.start < offset)
+        .collect::<Vec<_>>();
+
+    // The word being typed is replaced by the completion
+    if let Some(last) = tokens.last() {
+        if last.parent.is_comment() && offset <= last.range.end {
+            return None;
+        }
+        if matches!(last.kind, SyntaxKind::IDENTIFIER | SyntaxKind::NUMBER)
+            && offset <= last.range.end
+        {
+            tokens.pop();
+        }
+    }
+
+    let anchor = tokens.iter().rev().find(|token| token.kind != SyntaxKind::WHITESPACE);
+    let Some(anchor) = anchor.filter(|token| token.kind != SyntaxKind::NEWLINE) else {
+        return Some(CompletionContext::Instruction);
+    };
+
+    match anchor.kind {
+        SyntaxKind::COLON if anchor.parent == SyntaxKind::MODULE_PATH => {
+            // The module is the last segment of the path before the `::`
+            let module = tokens.iter().rev().find(|token| {
+                token.parent == SyntaxKind::MODULE_PATH && token.kind == SyntaxKind::IDENTIFIER
+            })?;
+            Some(CompletionContext::ModuleSymbol(module.text.clone()))
+        }
+        SyntaxKind::COLON if anchor.parent == SyntaxKind::LABEL_DEF => {
+            Some(CompletionContext::Instruction)
+        }
+        SyntaxKind::USE_KW => Some(CompletionContext::Module),
+        SyntaxKind::STAR | SyntaxKind::EQUALS if anchor.parent != SyntaxKind::MODULE_PATH => {
+            Some(CompletionContext::Address)
+        }
+        SyntaxKind::IDENTIFIER
+            if anchor.parent == SyntaxKind::INSTRUCTION
+                && (InstructionKind::from_name(&anchor.text).is_jump()
+                    || anchor.text.eq_ignore_ascii_case(CallInstruction::NAME)) =>
+        {
+            Some(CompletionContext::Label)
+        }
+        SyntaxKind::IDENTIFIER
+            if anchor.parent == SyntaxKind::INSTRUCTION
+                && InstructionKind::from_name(&anchor.text).effects().writes_operand() =>
+        {
+            Some(CompletionContext::Destination)
+        }
+        _ => None,
+    }
+}
+
+/// Get the names of the labels defined in a file
+pub fn defined_labels(syntax_tree: &ResolvedNode) -> Vec<String> {
+    Program::cast(syntax_tree.clone())
+        .map(|program| {
+            program.statements().filter_map(|statement| statement.label_def()?.name()).collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Get the modules imported by a file, with the symbol imported from each
+///
+/// The symbol is `None` for wildcard imports, which import every label.
+pub fn imported_modules(syntax_tree: &ResolvedNode) -> Vec<(String, Option<String>)> {
+    let Some(program) = Program::cast(syntax_tree.clone()) else {
+        return Vec::new();
+    };
+    program
+        .statements()
+        .filter_map(|statement| statement.use_stmt()?.path())
+        .filter_map(|path| {
+            let mut segments = path.segments();
+            if path.is_wildcard() {
+                Some((segments.pop()?, None))
+            } else {
+                let symbol = segments.pop()?;
+                Some((segments.pop()?, Some(symbol)))
+            }
+        })
+        .collect()
+}
+
+/// Get the addresses used by the operands of a file, nearest to `offset` first
+///
+/// The addresses before the cursor come first, as they are the ones most
+/// likely to be used again. The number being typed at `offset` is skipped.
+pub fn memory_addresses(syntax_tree: &ResolvedNode, offset: usize) -> Vec<String> {
+    let numbers = TokenCursor::new(syntax_tree)
+        .into_tokens()
+        .into_iter()
+        .filter(|token| {
+            token.kind == SyntaxKind::NUMBER
+                && token.parent == SyntaxKind::OPERAND_VALUE
+                && !(token.range.start..=token.range.end).contains(&offset)
+        })
+        .collect::<Vec<_>>();
+    let (before, after): (Vec<_>, Vec<_>) =
+        numbers.into_iter().partition(|token| token.range.end < offset);
+
+    let mut seen = HashSet::new();
+    before
+        .into_iter()
+        .rev()
+        .chain(after)
+        .map(|token| token.text)
+        .filter(|address| seen.insert(address.clone()))
+        .collect()
+}
+
+/// Get the addresses loaded by direct `LOAD`s before `offset`, nearest first
+pub fn loaded_addresses(syntax_tree: &ResolvedNode, offset: usize) -> Vec<String> {
+    let mut loaded = Vec::new();
+    let mut opcode = None;
+    let mut previous = None;
+    for token in TokenCursor::new(syntax_tree).into_tokens() {
+        if token.range.end >= offset {
+            break;
+        }
+        match token.kind {
+            SyntaxKind::NEWLINE => opcode = None,
+            SyntaxKind::IDENTIFIER if token.parent == SyntaxKind::INSTRUCTION => {
+                opcode = Some(InstructionKind::from_name(&token.text));
+            }
+            SyntaxKind::NUMBER
+                if token.parent == SyntaxKind::OPERAND_VALUE
+                    && opcode == Some(InstructionKind::Load)
+                    && previous.is_none_or(|kind| {
+                        kind != SyntaxKind::EQUALS && kind != SyntaxKind::STAR
+                    }) =>
+            {
+                loaded.push(token.text.clone());
+            }
+            _ => {}
+        }
+        if token.kind != SyntaxKind::WHITESPACE {
+            previous = Some(token.kind);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    loaded.into_iter().rev().filter(|address| seen.insert(address.clone())).collect()
+}
@@ -0,0 +1,137 @@
+//! Documentation of instructions shown by completions and hovers.
+//!
+//! The text is rendered from the metadata of the instructions in `ram_core`,
+//! so the editor shows the same documentation as every other tool. Hovers of
+//! analyzed files also tell where the accumulator an instruction reads was
+//! defined.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use hir_analysis::analyzers::VersionDef;
+use ram_core::{InstructionInfo, InstructionKind};
+use ram_syntax::{ResolvedNode, SyntaxKind, cstree};
+
+use crate::analysis::FileAnalysis;
+
+/// The documentation shown when hovering a part of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hover {
+    /// The range of the text the documentation is about
+    pub range: Range<usize>,
+    /// The documentation, as markdown
+    pub markdown: String,
+}
+
+/// Get the documentation of the instruction whose opcode is at `offset`
+pub fn hover(syntax_tree: &ResolvedNode, offset: usize) -> Option<Hover> {
+    let (info, range) = instruction_at(syntax_tree, offset)?;
+    Some(Hover { range, markdown: instruction_documentation(&info) })
+}
+
+/// Get the documentation of the instruction whose opcode is at `offset` in
+/// an analyzed file, with where the accumulator it reads was defined
+pub fn analysis_hover(analysis: &FileAnalysis, offset: usize) -> Option<Hover> {
+    let mut hover = hover(&analysis.syntax_tree, offset)?;
+    if let Some(definitions) = accumulator_definitions(analysis, hover.range.start) {
+        write!(hover.markdown, "\n**Accumulator:** {definitions}\n").unwrap();
+    }
+    Some(hover)
+}
+
+/// Describe where the value of the accumulator read by the instruction at
+/// `offset` was defined
+///
+/// Returns `None` if the instruction does not read the accumulator or is
+/// unreachable.
+fn accumulator_definitions(analysis: &FileAnalysis, offset: usize) -> Option<String> {
+    let (body, versions) = (analysis.body.as_ref()?, analysis.accumulator_versions.as_ref()?);
+    let instr = body.instructions.values().find(|instr| instr.span.contains(&offset))?;
+    let version = versions.version_read(instr.id)?;
+
+    let mut lines = Vec::new();
+    let mut from_entry = false;
+    for definition in versions.reaching_definitions(version) {
+        match versions.definition(definition) {
+            Some(VersionDef::Instruction(def_id)) => {
+                let def = body.instructions.values().find(|def| def.id == *def_id)?;
+                lines.push(analysis.line_index.line_col(def.span.start).line + 1);
+            }
+            _ => from_entry = true,
+        }
+    }
+    lines.sort();
+    lines.dedup();
+
+    let mut sources = Vec::new();
+    if from_entry {
+        sources.push("holds its initial value 0".to_string());
+    }
+    match lines.as_slice() {
+        [] => {}
+        [line] => sources.push(format!("is defined at line {line}")),
+        lines => {
+            let lines = lines.iter().map(u32::to_string).collect::<Vec<_>>();
+            sources.push(format!("is defined at lines {}", lines.join(", ")));
+        }
+    }
+    Some(format!("the value read here ({version}) {}", sources.join(" or ")))
+}
+
+/// Render the documentation of an instruction as markdown
+pub fn instruction_documentation(info: &InstructionInfo) -> String {
+    let metadata = &info.metadata;
+    let mut docs = format!("**{}**: {}\n", info.name, metadata.summary);
+
+    if !metadata.semantics.is_empty() {
+        write!(docs, "\n{}\n", metadata.semantics).unwrap();
+    }
+    if !metadata.operand_constraints.is_empty() {
+        write!(docs, "\n**Operand:** {}\n", metadata.operand_constraints).unwrap();
+    }
+    if !metadata.costs.is_empty() {
+        docs.push_str("\n**Cost:**\n");
+        for cost in &metadata.costs {
+            writeln!(docs, "- {}: `{}`", cost.model, cost.cost).unwrap();
+        }
+    }
+    if !metadata.examples.is_empty() {
+        docs.push_str("\n**Examples:**\n\n```ram\n");
+        let width = metadata.examples.iter().map(|example| example.code.len()).max().unwrap_or(0);
+        for example in &metadata.examples {
+            writeln!(docs, "{:width$} # {}", example.code, example.explanation).unwrap();
+        }
+        docs.push_str("```\n");
+    }
+    docs
+}
+
+/// Find the standard instruction whose opcode is at `offset`
+///
+/// Returns the information about the instruction and the span of its opcode.
+/// An offset right after the opcode still refers to it, as editors place the
+/// cursor there after typing it.
+pub fn instruction_at(
+    syntax_tree: &ResolvedNode,
+    offset: usize,
+) -> Option<(InstructionInfo, Range<usize>)> {
+    syntax_tree
+        .descendants()
+        .filter(|node| node.kind() == SyntaxKind::INSTRUCTION)
+        .filter_map(|node| {
+            node.children_with_tokens()
+                .filter_map(cstree::util::NodeOrToken::into_token)
+                .find(|token| token.kind() == SyntaxKind::IDENTIFIER)
+        })
+        .find_map(|token| {
+            let range = token.text_range();
+            let span = usize::from(range.start())..usize::from(range.end());
+            if !(span.start..=span.end).contains(&offset) {
+                return None;
+            }
+            match InstructionKind::from_name(token.text()) {
+                InstructionKind::Custom(_) => None,
+                kind => Some((kind.info(), span)),
+            }
+        })
+}
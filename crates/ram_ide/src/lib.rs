@@ -0,0 +1,246 @@
+//! Language intelligence for RAM programs, without a protocol.
+//!
+//! [`AnalysisHost`] owns the files of a workspace, the diagnostic
+//! configuration and the available instructions, and answers questions about
+//! them: the diagnostics of a file, the documentation of the instruction at
+//! an offset, the completions there and where a label is defined. Everything
+//! is synchronous and addressed by byte offsets, so editors, web services and
+//! other tools can embed it without speaking LSP; the language server is one
+//! such client.
+//!
+//! ```
+//! use ram_ide::AnalysisHost;
+//!
+//! let mut host = AnalysisHost::new();
+//! let file_id = host.set_file_text("main.ram", "loop: LOAD 1\nJUMP loop\n");
+//! let definition = host.goto_definition(file_id, 18).unwrap();
+//! assert_eq!(definition.name, "loop");
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use base_db::LineIndex;
+use ram_core::InstructionRegistry;
+use ram_diagnostics::{Diagnostic, DiagnosticConfig};
+
+pub mod analysis;
+pub mod cancellation;
+pub mod completion;
+pub mod docs;
+pub mod navigation;
+
+#[cfg(test)]
+mod tests;
+
+use crate::analysis::{
+    AnalysisInput, ModuleSource, analyze_file, exported_labels, file_imports, parse_file,
+    wildcard_imports,
+};
+pub use crate::analysis::{FileAnalysis, module_name};
+use crate::cancellation::Revision;
+pub use crate::completion::{CompletionHints, CompletionItem, CompletionKind};
+pub use crate::docs::Hover;
+pub use crate::navigation::NavigationTarget;
+
+/// The ID of a file of an analysis host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
+/// A file of an analysis host
+#[derive(Debug, Clone)]
+struct SourceFile {
+    /// The path of the file, which names the module it defines
+    path: String,
+    /// The text of the file
+    text: String,
+    /// The line index of the text
+    line_index: Arc<LineIndex>,
+    /// The revision the inputs of the file last changed at
+    revision: u64,
+}
+
+/// The files of a workspace and the analyses of them
+///
+/// Files are analyzed the first time something is asked about them, and the
+/// analysis is reused until their text, the diagnostic configuration or the
+/// available instructions change.
+#[derive(Debug, Default)]
+pub struct AnalysisHost {
+    /// Map from FileId to the file
+    files: HashMap<FileId, SourceFile>,
+    /// Map from FileId to the latest analysis of the file
+    analyses: Mutex<HashMap<FileId, Arc<FileAnalysis>>>,
+    /// The severity configuration
+    config: DiagnosticConfig,
+    /// The instructions available to programs, or `None` for the standard ones
+    instructions: Option<Arc<InstructionRegistry>>,
+    /// The revision of the inputs
+    revision: Revision,
+}
+
+impl AnalysisHost {
+    /// Create a host without files
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file at `path` or replace its text
+    ///
+    /// The file defines the module named after its file stem.
+    pub fn set_file_text(&mut self, path: &str, text: &str) -> FileId {
+        let revision = self.revision.bump();
+        let file_id = self.file_id(path).unwrap_or_else(|| {
+            FileId(self.files.keys().map(|file_id| file_id.0 + 1).max().unwrap_or(0))
+        });
+        self.files.insert(
+            file_id,
+            SourceFile {
+                path: path.to_string(),
+                text: text.to_string(),
+                line_index: Arc::new(LineIndex::new(text)),
+                revision,
+            },
+        );
+        // The labels other files import may have changed
+        self.touch_files();
+        file_id
+    }
+
+    /// Remove a file
+    pub fn remove_file(&mut self, file_id: FileId) {
+        if self.files.remove(&file_id).is_some() {
+            self.revision.bump();
+            self.analyses.get_mut().unwrap().remove(&file_id);
+            self.touch_files();
+        }
+    }
+
+    /// Replace the diagnostic configuration
+    pub fn set_diagnostic_config(&mut self, config: DiagnosticConfig) {
+        self.revision.bump();
+        self.config = config;
+        self.touch_files();
+    }
+
+    /// Replace the instructions available to programs
+    pub fn set_instruction_registry(&mut self, instructions: Arc<InstructionRegistry>) {
+        self.revision.bump();
+        self.instructions = Some(instructions);
+        self.touch_files();
+    }
+
+    /// Get the ID of the file at `path`
+    pub fn file_id(&self, path: &str) -> Option<FileId> {
+        self.files.iter().find(|(_, file)| file.path == path).map(|(file_id, _)| *file_id)
+    }
+
+    /// Get the text of a file
+    pub fn file_text(&self, file_id: FileId) -> Option<&str> {
+        self.files.get(&file_id).map(|file| file.text.as_str())
+    }
+
+    /// Get the line index of a file, to convert offsets to lines and columns
+    pub fn line_index(&self, file_id: FileId) -> Option<Arc<LineIndex>> {
+        self.files.get(&file_id).map(|file| Arc::clone(&file.line_index))
+    }
+
+    /// Get the analysis of a file, analyzing it if its inputs changed
+    pub fn analysis(&self, file_id: FileId) -> Option<Arc<FileAnalysis>> {
+        let file = self.files.get(&file_id)?;
+        let mut analyses = self.analyses.lock().unwrap();
+        if let Some(analysis) = analyses.get(&file_id)
+            && analysis.file_revision == file.revision
+        {
+            return Some(Arc::clone(analysis));
+        }
+
+        let imports = self
+            .files
+            .iter()
+            .filter(|(other, _)| **other != file_id)
+            .flat_map(|(_, other)| file_imports(&other.text))
+            .collect::<Vec<_>>();
+        let wildcard_imports = wildcard_imports(&file.text);
+        let mut imported = self
+            .files
+            .iter()
+            .filter(|(other, other_file)| {
+                **other != file_id && wildcard_imports.contains(&module_name(&other_file.path))
+            })
+            .map(|(_, other)| other)
+            .collect::<Vec<_>>();
+        imported.sort_by(|a, b| a.path.cmp(&b.path));
+        let modules = imported
+            .iter()
+            .map(|other| ModuleSource { file_name: other.path.clone(), text: other.text.clone() })
+            .collect();
+        let input = AnalysisInput {
+            text: file.text.clone(),
+            line_index: Arc::clone(&file.line_index),
+            exported: exported_labels(&module_name(&file.path), &imports),
+            config: self.config.clone(),
+            instructions: self.instructions.clone(),
+            modules,
+            previous: analyses.get(&file_id).and_then(|analysis| analysis.passes.clone()),
+            file_revision: file.revision,
+            dependency_revisions: imported.iter().map(|other| other.revision).collect(),
+            version: None,
+        };
+        // The inputs cannot change while the host is borrowed
+        let analysis = Arc::new(analyze_file(&input, &self.revision.token(), |_| {}).ok()?);
+        analyses.insert(file_id, Arc::clone(&analysis));
+        Some(analysis)
+    }
+
+    /// Get the diagnostics of a file, with the configured severities
+    pub fn diagnostics(&self, file_id: FileId) -> Vec<Diagnostic> {
+        self.analysis(file_id)
+            .map(|analysis| analysis.diagnostics.diagnostics().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Get the documentation of the instruction at `offset`, with where the
+    /// accumulator it reads was defined
+    pub fn hover(&self, file_id: FileId, offset: usize) -> Option<Hover> {
+        docs::analysis_hover(&self.analysis(file_id)?, offset)
+    }
+
+    /// Complete the text at `offset`
+    pub fn completions(&self, file_id: FileId, offset: usize) -> Vec<CompletionItem> {
+        let Some(analysis) = self.analysis(file_id) else {
+            return Vec::new();
+        };
+        let mut modules = self
+            .files
+            .iter()
+            .filter(|(other, _)| **other != file_id)
+            .map(|(_, other)| (module_name(&other.path), Some(parse_file(&other.text).0)))
+            .collect::<Vec<_>>();
+        modules.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let hints = CompletionHints::from_analysis(&analysis, offset, self.instructions.clone());
+        completion::completions(&analysis.syntax_tree, offset, &modules, &hints)
+    }
+
+    /// Find the definition of the label at `offset`
+    pub fn goto_definition(&self, file_id: FileId, offset: usize) -> Option<NavigationTarget> {
+        let body = self.analysis(file_id)?.body.clone()?;
+        navigation::goto_definition(file_id, &body, offset)
+    }
+
+    /// Find the definition of the label at `offset` and the references to it
+    pub fn references(&self, file_id: FileId, offset: usize) -> Vec<NavigationTarget> {
+        match self.analysis(file_id).and_then(|analysis| analysis.body.clone()) {
+            Some(body) => navigation::references(file_id, &body, offset),
+            None => Vec::new(),
+        }
+    }
+
+    /// Mark the inputs of every file as changed
+    fn touch_files(&mut self) {
+        let revision = self.revision.current();
+        for file in self.files.values_mut() {
+            file.revision = revision;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Navigation between labels and the instructions referring to them.
+//!
+//! Labels are resolved on the HIR body of a file: the operand of a jump names
+//! a label either as written, when it could not be resolved, or through a
+//! reference to the label it was resolved to.
+
+use std::ops::Range;
+
+use hir::body::{Body, Expr, ExprKind, Label, Literal};
+
+use crate::FileId;
+
+/// A place of a file to navigate to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavigationTarget {
+    /// The file of the target
+    pub file_id: FileId,
+    /// The name of what is at the target
+    pub name: String,
+    /// The range of the target in the text of the file
+    pub range: Range<usize>,
+}
+
+/// Find the label defined or referred to at `offset`
+///
+/// An offset right after the label still refers to it, as editors place the
+/// cursor there after typing it.
+pub fn label_at(body: &Body, offset: usize) -> Option<&Label> {
+    let contains = |span: &Range<usize>| span.start <= offset && offset <= span.end;
+    if let Some(label) = body.labels.values().find(|label| contains(&label.span)) {
+        return Some(label);
+    }
+
+    operands(body).find(|expr| contains(&expr.span)).and_then(|expr| referred_label(body, expr))
+}
+
+/// Find the definition of the label at `offset`
+pub fn goto_definition(file_id: FileId, body: &Body, offset: usize) -> Option<NavigationTarget> {
+    let label = label_at(body, offset)?;
+    Some(NavigationTarget { file_id, name: label.name.clone(), range: label.span.clone() })
+}
+
+/// Find the definition of the label at `offset` and the operands referring to it
+///
+/// The definition comes first, then the references in the order they are
+/// written.
+pub fn references(file_id: FileId, body: &Body, offset: usize) -> Vec<NavigationTarget> {
+    let Some(label) = label_at(body, offset) else {
+        return Vec::new();
+    };
+    let target = |range: &Range<usize>| NavigationTarget {
+        file_id,
+        name: label.name.clone(),
+        range: range.clone(),
+    };
+
+    let mut references = operands(body)
+        .filter(|expr| referred_label(body, expr).is_some_and(|other| other.id == label.id))
+        .map(|expr| target(&expr.span))
+        .collect::<Vec<_>>();
+    references.sort_by_key(|target| target.range.start);
+    references.insert(0, target(&label.span));
+    references
+}
+
+/// Get the operands of the instructions of a body
+fn operands(body: &Body) -> impl Iterator<Item = &Expr> {
+    body.instructions
+        .values()
+        .filter_map(|instruction| instruction.operand)
+        .filter_map(|operand| body.expr(operand))
+}
+
+/// Get the label of the body an operand refers to
+fn referred_label<'a>(body: &'a Body, expr: &Expr) -> Option<&'a Label> {
+    match &expr.kind {
+        ExprKind::Literal(Literal::Label(name)) => {
+            body.labels.values().find(|label| &label.name == name)
+        }
+        ExprKind::LabelRef(label_ref) if label_ref.label_id.file_id == body.owner.file_id => {
+            body.labels.values().find(|label| label.id.0 == label_ref.label_id.local_id.0)
+        }
+        _ => None,
+    }
+}
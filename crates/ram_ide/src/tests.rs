@@ -0,0 +1,203 @@
+//! Tests for the analysis host: diagnostics, hover, completion and navigation
+
+use std::sync::Arc;
+
+use hir_analysis::analyzers::unused_labels::UNUSED_LABEL_CODE;
+use ram_core::plugin::InstructionBuilder;
+use ram_core::{InstructionKind, InstructionRegistry, OperandKind};
+use ram_diagnostics::codes;
+
+use crate::{AnalysisHost, CompletionKind, FileAnalysis};
+
+/// Check whether a diagnostic with `code` is reported for a file
+fn has_diagnostic(host: &AnalysisHost, file_id: crate::FileId, code: &str) -> bool {
+    host.diagnostics(file_id).iter().any(|diagnostic| diagnostic.code.as_deref() == Some(code))
+}
+
+#[test]
+fn test_diagnostics() {
+    let mut host = AnalysisHost::new();
+    let file_id = host.set_file_text("main.ram", "LAOD 1\nHALT\n");
+    assert!(host.diagnostics(file_id).iter().any(|diagnostic| diagnostic.message.contains("LAOD")));
+
+    // The analysis is reused until the text changes
+    let analysis = host.analysis(file_id).unwrap();
+    assert!(Arc::ptr_eq(&analysis, &host.analysis(file_id).unwrap()));
+    assert_eq!(host.set_file_text("main.ram", "LOAD 1\nHALT\n"), file_id);
+    assert!(host.diagnostics(file_id).is_empty());
+
+    host.remove_file(file_id);
+    assert!(host.analysis(file_id).is_none());
+}
+
+#[test]
+fn test_items_added_above_labels_keep_their_ids() {
+    let mut host = AnalysisHost::new();
+    let file_id = host.set_file_text("main.ram", "start: LOAD 1\nJGTZ start\nHALT\n");
+    let analysis = host.analysis(file_id).unwrap();
+
+    // The label is the second item of the file now
+    host.set_file_text("main.ram", "mod math\nstart: LOAD 1\nJGTZ start\nHALT\n");
+    let edited = host.analysis(file_id).unwrap();
+    let label_id = |analysis: &FileAnalysis| {
+        analysis.body.as_ref().unwrap().labels.values().next().unwrap().id
+    };
+    assert_eq!(label_id(&edited), label_id(&analysis));
+    assert!(Arc::ptr_eq(
+        edited.control_flow.as_ref().unwrap(),
+        analysis.control_flow.as_ref().unwrap()
+    ));
+}
+
+#[test]
+fn test_instructions_close_to_standard_ones() {
+    let mut host = AnalysisHost::new();
+    let file_id = host.set_file_text("main.ram", "LOADI =1\nLAOD 1\nHALT\n");
+    let unknown = |host: &AnalysisHost| {
+        host.diagnostics(file_id)
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.code.as_deref() == Some(codes::UNKNOWN_INSTRUCTION.code)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(unknown(&host).len(), 2);
+
+    // An instruction of a plugin is not taken for a misspelled standard one
+    let mut registry = ram_core::standard_instructions();
+    let loadi = InstructionBuilder::new("LOADI")
+        .requires_operand(true)
+        .allow_operand_kind(OperandKind::Immediate)
+        .build();
+    registry.register(InstructionKind::Custom("LOADI".into()), loadi);
+    host.set_instruction_registry(Arc::new(registry));
+    let unknown = unknown(&host);
+    assert_eq!(unknown.len(), 1);
+    assert_eq!(unknown[0].suggestions[0].replacement, "LOAD");
+}
+
+#[test]
+fn test_exported_labels() {
+    let mut host = AnalysisHost::new();
+    let lib = host.set_file_text("lib/math.ram", "double: LOAD 1\nHALT\n");
+    assert!(has_diagnostic(&host, lib, UNUSED_LABEL_CODE));
+
+    // A label imported by another file is used
+    let main = host.set_file_text("main.ram", "use math::double\nHALT\n");
+    assert!(!has_diagnostic(&host, lib, UNUSED_LABEL_CODE));
+    host.remove_file(main);
+    assert!(has_diagnostic(&host, lib, UNUSED_LABEL_CODE));
+}
+
+#[test]
+fn test_labels_duplicated_in_imported_modules() {
+    let mut host = AnalysisHost::new();
+    host.set_file_text("lib/math.ram", "double: LOAD 1\nHALT\n");
+    let main = host.set_file_text("main.ram", "use math::*\ndouble: LOAD 2\nHALT\n");
+
+    // The first definition is the one of the module
+    let diagnostics = host.diagnostics(main);
+    let duplicate = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.code.as_deref() == Some(codes::DUPLICATE_LABEL.code))
+        .unwrap();
+    assert_eq!(duplicate.labeled_spans[0].0, 12..19);
+    assert_eq!(duplicate.external_spans[0].file, "lib/math.ram");
+    assert_eq!(duplicate.external_spans[0].span, 0..7);
+
+    // A label imported by name is reported as shadowing the import instead
+    host.set_file_text("main.ram", "use math::double\ndouble: LOAD 2\nHALT\n");
+    assert!(!has_diagnostic(&host, main, codes::DUPLICATE_LABEL.code));
+}
+
+#[test]
+fn test_hover() {
+    let mut host = AnalysisHost::new();
+    let file_id = host.set_file_text("main.ram", "LOAD 1\nHALT\n");
+
+    let hover = host.hover(file_id, 2).unwrap();
+    assert_eq!(hover.range, 0..4);
+    assert!(hover.markdown.starts_with("**LOAD**"));
+    assert!(host.hover(file_id, 5).is_none());
+}
+
+#[test]
+fn test_hover_accumulator_definitions() {
+    let mut host = AnalysisHost::new();
+    let text = "READ 1\nLOAD 1\nJZERO end\nADD =1\nend: STORE 2\nHALT\n";
+    let file_id = host.set_file_text("main.ram", text);
+    let hover = |opcode: &str| host.hover(file_id, text.find(opcode).unwrap()).unwrap().markdown;
+
+    assert!(
+        hover("JZERO").contains("**Accumulator:** the value read here (acc1) is defined at line 2")
+    );
+    assert!(hover("STORE").contains("is defined at lines 2, 4"));
+
+    // `LOAD` does not read the accumulator
+    assert!(!hover("LOAD").contains("**Accumulator:**"));
+}
+
+#[test]
+fn test_completions() {
+    let mut host = AnalysisHost::new();
+    host.set_file_text("math.ram", "double: ADD 0\nHALT\n");
+    let text = "use \nloop: LOAD 1\nJUMP \n";
+    let file_id = host.set_file_text("main.ram", text);
+
+    let items = host.completions(file_id, text.find("JUMP").unwrap() + 5);
+    let labels = items.iter().map(|item| item.label.as_str()).collect::<Vec<_>>();
+    assert_eq!(labels, vec!["loop"]);
+    assert_eq!(items[0].kind, CompletionKind::Label);
+
+    let items = host.completions(file_id, 4);
+    assert_eq!(items.len(), 1);
+    assert_eq!((items[0].label.as_str(), items[0].kind), ("math", CompletionKind::Module));
+
+    let items = host.completions(file_id, text.find("loop:").unwrap());
+    assert!(items.iter().any(|item| item.label == "LOAD" && item.documentation.is_some()));
+}
+
+#[test]
+fn test_completion_ranking() {
+    let mut host = AnalysisHost::new();
+    let text = "start: LOAD 1\nloop: SUB =1\ninner: JZERO done\nJUMP loop\ndone: STORE 2\nHALT\n";
+    let file_id = host.set_file_text("main.ram", text);
+    let sorted = |mut items: Vec<crate::CompletionItem>| {
+        items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+        items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+    };
+
+    // The labels of the loop around the cursor come first
+    let items = host.completions(file_id, text.find("JUMP loop").unwrap() + 6);
+    assert_eq!(sorted(items), ["inner", "loop", "done", "start"]);
+
+    // Instructions the instruction set does not allow come last
+    let standard = ram_core::standard_instructions();
+    let mut registry = InstructionRegistry::new();
+    for kind in [InstructionKind::Load, InstructionKind::Halt] {
+        registry.register(kind.clone(), standard.get(&kind).unwrap());
+    }
+    host.set_instruction_registry(Arc::new(registry));
+    let items = sorted(host.completions(file_id, 0));
+    assert_eq!(items[..2], ["HALT", "LOAD"]);
+    let mul = host.completions(file_id, 0).into_iter().find(|item| item.label == "MUL").unwrap();
+    assert!(mul.detail.unwrap().ends_with("(not in the active instruction set)"));
+}
+
+#[test]
+fn test_navigation() {
+    let mut host = AnalysisHost::new();
+    let text = "loop: LOAD 1\nJZERO end\nJUMP loop\nend: HALT\n";
+    let file_id = host.set_file_text("main.ram", text);
+
+    let jump = text.find("JUMP loop").unwrap() + 5;
+    let definition = host.goto_definition(file_id, jump).unwrap();
+    assert_eq!(definition.name, "loop");
+    assert_eq!(definition.range.start, 0);
+    assert!(host.goto_definition(file_id, text.find("LOAD").unwrap()).is_none());
+
+    let references = host.references(file_id, 0);
+    let ranges = references.iter().map(|target| target.range.start).collect::<Vec<_>>();
+    assert_eq!(ranges, vec![0, jump]);
+    assert!(references.iter().all(|target| target.file_id == file_id && target.name == "loop"));
+}
@@ -0,0 +1,48 @@
+//! Background work and its cancellation.
+//!
+//! Analyses run on tokio's blocking thread pool, so handlers of notifications
+//! such as `didChange` never wait for them. As with salsa queries, the inputs
+//! of the database have a revision that every change bumps, and work started
+//! at an older revision is cancelled at its next checkpoint. Work done for a
+//! request is also cancelled when the client sends `$/cancelRequest`, which
+//! makes tower-lsp drop the future of the handler. The revision and the tokens
+//! themselves are those of `ram_ide`.
+
+pub use ram_ide::cancellation::{CancellationToken, Cancelled, Revision};
+
+/// Cancels a token when dropped
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Run `work` on the blocking thread pool
+///
+/// Dropping the returned future, as tower-lsp does when the client cancels the
+/// request, cancels `token`, so the work stops at its next checkpoint. Work
+/// that completes is still checked against the current revision, so callers
+/// never see results computed from outdated inputs.
+pub async fn spawn<T, F>(token: CancellationToken, work: F) -> Result<T, Cancelled>
+where
+    T: Send + 'static,
+    F: FnOnce(&CancellationToken) -> Result<T, Cancelled> + Send + 'static,
+{
+    let guard = CancelOnDrop(token.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        let result = work(&token)?;
+        token.check()?;
+        Ok(result)
+    })
+    .await;
+    drop(guard);
+
+    match result {
+        Ok(result) => result,
+        // Re-raise panics of the work in the handler
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(_) => Err(Cancelled),
+    }
+}
@@ -1,34 +1,87 @@
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use base_db::{LineIndex, Query, QueryStats};
 use dashmap::DashMap;
-use hir::body::Body;
-use hir_analysis::analyzers::constant_propagation::ConstantPropagationAnalysis;
-use hir_analysis::analyzers::control_flow_optimizer::ControlFlowOptimizer;
-use hir_analysis::{
-    AnalysisPipeline, ControlFlowAnalysis, DataFlowAnalysis, InstructionValidationAnalysis,
-};
-use ram_diagnostics::DiagnosticCollection;
-use ram_parser::parse;
-use ram_syntax::{AstNode, Program, ResolvedNode, SyntaxNode};
+use hir_def::item_tree::ModulePath;
+use ram_core::InstructionRegistry;
+use ram_diagnostics::{DiagnosticCollection, DiagnosticConfig};
+pub use ram_ide::FileId;
+pub use ram_ide::analysis::{AnalysisInput, AnalysisStage, FileAnalysis, analyze_file, parse_file};
+use ram_ide::analysis::{ModuleSource, wildcard_imports};
+use ram_syntax::ResolvedNode;
 use tower_lsp::lsp_types::Url;
+use vfs::{ChangeKind, ChangedFile, Vfs, VfsPath};
 
-/// A file ID for the LSP database
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct FileId(pub u32);
+use crate::cancellation::{CancellationToken, Revision};
 
 /// LSP database for the RAM language server
+///
+/// Updating the inputs of the database is cheap: analysis happens in
+/// [`analyze_file`], outside of any lock, and its result is stored with
+/// [`LspDatabase::store_analysis`]. Every update bumps the [`Revision`],
+/// cancelling the analyses that are still running.
+///
+/// The texts of the files are kept in a [`Vfs`], where the text of a document
+/// open in the client overlays the text saved on disk.
 #[derive(Debug, Default)]
 pub struct LspDatabase {
-    /// Map from FileId to file content
-    files: DashMap<FileId, String>,
-    /// Map from URL to FileId
-    url_to_file: DashMap<Url, FileId>,
-    /// Map from FileId to URL
+    /// The texts of the files, saved and open in the client
+    vfs: Vfs,
+    /// Map from FileId to the line index of its content
+    line_indexes: DashMap<FileId, Arc<LineIndex>>,
+    /// Map from FileId to the revision its inputs last changed at
+    file_revisions: DashMap<FileId, u64>,
+    /// Map from FileId to the version of the document opened by the client
+    versions: DashMap<FileId, i32>,
+    /// Map from FileId to the URL the client knows it by
     file_to_url: DashMap<FileId, Url>,
-    /// Map from FileId to diagnostics
-    diagnostics: DashMap<FileId, DiagnosticCollection>,
-    /// Map from FileId to syntax tree
-    syntax_trees: DashMap<FileId, ResolvedNode>,
+    /// Map from FileId to the latest analysis of its content
+    analyses: DashMap<FileId, Arc<FileAnalysis>>,
+    /// Map from FileId to the paths imported by its `use` statements
+    imports: DashMap<FileId, Vec<ModulePath>>,
+    /// Severity configuration loaded from the project manifest
+    config: DiagnosticConfig,
+    /// The instructions available to programs, including those of plugins,
+    /// or `None` for the standard instructions
+    instructions: Option<Arc<InstructionRegistry>>,
+    /// The revision of the inputs
+    revision: Revision,
+    /// The lookups of line indexes
+    line_index_lookups: Lookups,
+    /// The lookups of analyses of the current inputs
+    analysis_lookups: Lookups,
+}
+
+/// The number of lookups of a memo table that found a result, and that did not
+#[derive(Debug, Default)]
+struct Lookups {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Lookups {
+    /// Count a lookup, and pass its result on
+    fn record<T>(&self, result: Option<T>) -> Option<T> {
+        let counter = if result.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Get the statistics of the lookups of a query with `memos` results
+    ///
+    /// The database keeps a result for every file, so nothing is evicted.
+    fn stats(&self, query: Query, memos: usize) -> QueryStats {
+        QueryStats {
+            query,
+            memos,
+            capacity: 0,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: 0,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -40,7 +93,7 @@ impl LspDatabase {
 
     /// Get the file ID for a URL
     pub fn file_id_for_url(&self, url: &Url) -> Option<FileId> {
-        self.url_to_file.get(url).map(|id| *id)
+        self.vfs.file_id(&vfs_path(url))
     }
 
     /// Get the URL for a file ID
@@ -50,143 +103,342 @@ impl LspDatabase {
 
     /// Get the text of a file
     pub fn file_text(&self, file_id: FileId) -> Option<String> {
-        self.files.get(&file_id).map(|text| text.clone())
+        self.vfs.file_text(file_id).map(|text| text.to_string())
+    }
+
+    /// Get the line index of a file
+    pub fn line_index(&self, file_id: FileId) -> Option<Arc<LineIndex>> {
+        self.line_index_lookups
+            .record(self.line_indexes.get(&file_id).map(|index| Arc::clone(&index)))
+    }
+
+    /// Get the revision the inputs of a file last changed at
+    ///
+    /// The inputs of a file are its text and the diagnostic configuration.
+    pub fn file_revision(&self, file_id: FileId) -> Option<u64> {
+        self.file_revisions.get(&file_id).map(|revision| *revision)
+    }
+
+    /// Get the version of a document opened by the client
+    pub fn version(&self, file_id: FileId) -> Option<i32> {
+        self.versions.get(&file_id).map(|version| *version)
+    }
+
+    /// Create a cancellation token for work started at the current revision
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.revision.token()
     }
 
     /// Add or update a file in the database
-    pub fn add_file(&mut self, url: Url, text: &str) -> FileId {
-        // Check if we already have this file
-        if let Some(file_id) = self.file_id_for_url(&url) {
-            // Update the file text
-            self.files.insert(file_id, text.to_string());
-            // Update the syntax tree and diagnostics
-            self.update_file_analysis(file_id, text);
-            file_id
-        } else {
-            // Create a new file ID
-            let file_id = FileId(self.url_to_file.len() as u32);
-
-            // Add the file to the database
-            self.files.insert(file_id, text.to_string());
-
-            // Add the URL mappings
-            self.url_to_file.insert(url.clone(), file_id);
-            self.file_to_url.insert(file_id, url);
-
-            // Update the syntax tree and diagnostics
-            self.update_file_analysis(file_id, text);
-
-            file_id
+    ///
+    /// `version` is the version of the document given by the client, if any;
+    /// the text then is the one open in the client, and otherwise the one
+    /// saved on disk, with the previous version kept. The file is not
+    /// analyzed; see [`LspDatabase::analysis_input`].
+    pub fn add_file(&mut self, url: Url, text: &str, version: Option<i32>) -> FileId {
+        let revision = self.revision.bump();
+
+        let path = vfs_path(&url);
+        let file_id = match version {
+            Some(_) => self.vfs.set_overlay(path, Some(text.to_string())),
+            None => self.vfs.set_file_contents(path, Some(text.to_string())),
+        };
+        self.apply_vfs_changes(revision);
+
+        // A new version of the document is analyzed again, even with the same text
+        self.file_to_url.insert(file_id, url);
+        self.file_revisions.insert(file_id, revision);
+        if let Some(version) = version {
+            self.versions.insert(file_id, version);
         }
+        file_id
     }
 
-    /// Remove a file from the database
-    pub fn remove_file(&mut self, url: &Url) {
-        if let Some(file_id) = self.file_id_for_url(url) {
-            self.files.remove(&file_id);
-            self.url_to_file.remove(url);
-            self.file_to_url.remove(&file_id);
-            self.diagnostics.remove(&file_id);
-            self.syntax_trees.remove(&file_id);
+    /// Set or remove the text of a file saved on disk
+    ///
+    /// A file open in the client keeps the text of the client until it is
+    /// closed. Returns how the text of the file changed, if it did.
+    pub fn set_saved_text(&mut self, url: &Url, text: Option<&str>) -> Option<ChangeKind> {
+        let revision = self.revision.bump();
+        let file_id = self.vfs.set_file_contents(vfs_path(url), text.map(str::to_string));
+        let kind = self.apply_vfs_changes(revision).last().map(|changed| changed.kind);
+        // The URL the client opened the file with stays
+        if self.vfs.exists(file_id) {
+            self.file_to_url.entry(file_id).or_insert_with(|| url.clone());
         }
+        kind
+    }
+
+    /// Close a document open in the client
+    ///
+    /// The file goes back to the text saved on disk. Returns the ID of the
+    /// file, or `None` if it has no saved text and was removed.
+    pub fn close_file(&mut self, url: &Url) -> Option<FileId> {
+        let revision = self.revision.bump();
+        let file_id = self.vfs.set_overlay(vfs_path(url), None);
+        self.versions.remove(&file_id);
+        self.apply_vfs_changes(revision);
+        self.vfs.exists(file_id).then_some(file_id)
     }
 
-    /// Update the syntax tree and diagnostics for a file
-    fn update_file_analysis(&mut self, file_id: FileId, text: &str) {
-        // Parse the file
-        let (events, parser_diagnostics) = parse(text);
-
-        // Build the syntax tree
-        let (green_node, interner) = ram_parser::build_tree(events);
-        let syntax_tree = SyntaxNode::new_root_with_resolver(green_node, interner);
-
-        // Store the syntax tree
-        self.syntax_trees.insert(file_id, syntax_tree.clone());
-
-        // Create a diagnostic collection
-        let mut diagnostic_collection = DiagnosticCollection::new();
-
-        // Convert parser diagnostics to ram_diagnostics::Diagnostic
-        for parser_diag in parser_diagnostics {
-            let ram_diag = ram_diagnostics::Diagnostic {
-                message: parser_diag.message,
-                help: parser_diag.help,
-                labeled_spans: parser_diag.labeled_spans,
-                kind: match parser_diag.kind {
-                    ram_parser::DiagnosticKind::Error => ram_diagnostics::DiagnosticKind::Error,
-                    ram_parser::DiagnosticKind::Warning => ram_diagnostics::DiagnosticKind::Warning,
-                    ram_parser::DiagnosticKind::Advice => ram_diagnostics::DiagnosticKind::Advice,
-                    ram_parser::DiagnosticKind::Custom(name) => {
-                        ram_diagnostics::DiagnosticKind::Custom(name)
-                    }
-                },
-                code: parser_diag.code,
-                notes: parser_diag.notes,
-            };
-            diagnostic_collection.add(ram_diag);
+    /// Replace the diagnostic configuration
+    ///
+    /// Returns the files that have to be analyzed again.
+    pub fn set_diagnostic_config(&mut self, config: DiagnosticConfig) -> Vec<(FileId, Url)> {
+        let revision = self.revision.bump();
+        self.config = config;
+        for mut file_revision in self.file_revisions.iter_mut() {
+            *file_revision = revision;
         }
 
-        // Try to perform semantic analysis if the syntax is valid
-        if !diagnostic_collection.has_errors() {
-            // Convert syntax tree to AST Program
-            if let Some(program) = Program::cast(syntax_tree) {
-                // Create a dummy HIR body for analysis
-                // In a real implementation, we would use the proper lowering logic
-                let body = self.create_hir_body_from_program(&program);
-
-                // Run HIR analysis
-                let mut pipeline = AnalysisPipeline::new();
-
-                // Register analysis passes
-                pipeline.register::<InstructionValidationAnalysis>().ok();
-                pipeline.register::<ControlFlowAnalysis>().ok();
-                pipeline.register::<DataFlowAnalysis>().ok();
-                pipeline.register::<ConstantPropagationAnalysis>().ok();
-                pipeline.register::<ControlFlowOptimizer>().ok();
-
-                // Run the analysis
-                if let Ok(context) = pipeline.analyze(Arc::new(body)) {
-                    // Add semantic diagnostics to our collection
-                    diagnostic_collection.extend(context.diagnostics().clone());
-                }
-            }
+        self.file_to_url.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
+    }
+
+    /// Get the instructions available to programs, or `None` for the standard ones
+    pub fn instructions(&self) -> Option<Arc<InstructionRegistry>> {
+        self.instructions.clone()
+    }
+
+    /// Replace the instructions available to programs
+    ///
+    /// Returns the files that have to be analyzed again.
+    pub fn set_instruction_registry(
+        &mut self,
+        instructions: Arc<InstructionRegistry>,
+    ) -> Vec<(FileId, Url)> {
+        let revision = self.revision.bump();
+        self.instructions = Some(instructions);
+        for mut file_revision in self.file_revisions.iter_mut() {
+            *file_revision = revision;
         }
 
-        // Store the diagnostics
-        self.diagnostics.insert(file_id, diagnostic_collection);
+        self.file_to_url.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
     }
 
-    /// Create a HIR body from an AST Program
-    /// Uses the proper lowering logic from the hir crate
-    fn create_hir_body_from_program(&self, program: &Program) -> Body {
-        // Create a dummy file ID for this program
-        let file_id = base_db::input::FileId(0);
+    /// Remove a file from the database, both its saved and open text
+    pub fn remove_file(&mut self, url: &Url) {
+        if self.file_id_for_url(url).is_some() {
+            let revision = self.revision.bump();
+            let path = vfs_path(url);
+            self.vfs.set_overlay(path.clone(), None);
+            self.vfs.set_file_contents(path, None);
+            self.apply_vfs_changes(revision);
+        }
+    }
 
-        // Create a dummy DefId for the program
-        let def_id = hir::ids::DefId { file_id, local_id: hir::ids::LocalDefId(0) };
+    /// Move a file to a new URL, keeping its ID and text
+    ///
+    /// The file is imported under the name of its new URL, so it is analyzed
+    /// again. Returns the ID of the file, or `None` if it is not in the
+    /// database.
+    pub fn rename_file(&mut self, from: &Url, to: Url) -> Option<FileId> {
+        let revision = self.revision.bump();
+        let file_id = self.vfs.rename(&vfs_path(from), vfs_path(&to))?;
+        self.apply_vfs_changes(revision);
+        self.file_to_url.insert(file_id, to);
+        Some(file_id)
+    }
 
-        // Create an ItemTree for the program
-        let item_tree = hir_def::item_tree::ItemTree::lower(program, file_id);
+    /// Check whether a file is open in the client, which then owns its text
+    pub fn is_open(&self, file_id: FileId) -> bool {
+        self.vfs.has_overlay(file_id)
+    }
 
-        // Lower the AST Program to a HIR Body
-        match hir::lower::lower_program(program, def_id, file_id, &item_tree) {
-            Ok(body) => body,
-            Err(err) => {
-                // Log the error
-                tracing::error!("Failed to lower program to HIR: {:?}", err);
-                // Return an empty body as fallback
-                Body::default()
+    /// Bring the state derived from the texts of the files up to date with
+    /// the changes of the VFS
+    ///
+    /// Returns the changes.
+    fn apply_vfs_changes(&mut self, revision: u64) -> Vec<ChangedFile> {
+        let changes = self.vfs.take_changes();
+        for changed in &changes {
+            let file_id = changed.file_id;
+            match self.vfs.file_text(file_id) {
+                Some(text) => {
+                    self.line_indexes.insert(file_id, Arc::new(LineIndex::new(&text)));
+                    self.file_revisions.insert(file_id, revision);
+                }
+                None => {
+                    self.line_indexes.remove(&file_id);
+                    self.file_revisions.remove(&file_id);
+                    self.versions.remove(&file_id);
+                    self.file_to_url.remove(&file_id);
+                    self.analyses.remove(&file_id);
+                    self.imports.remove(&file_id);
+                }
             }
         }
+        changes
+    }
+
+    /// Get all the files of the database with their URLs
+    pub fn file_urls(&self) -> Vec<(FileId, Url)> {
+        self.file_to_url.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
+    }
+
+    /// Collect what is needed to analyze a file
+    pub fn analysis_input(&self, file_id: FileId) -> Option<AnalysisInput> {
+        let text = self.file_text(file_id)?;
+        let imported = self.imported_files(file_id, &wildcard_imports(&text));
+        let modules = imported
+            .iter()
+            .filter_map(|&other| {
+                Some(ModuleSource {
+                    file_name: self.url_for_file_id(other)?.to_string(),
+                    text: self.file_text(other)?,
+                })
+            })
+            .collect();
+        Some(AnalysisInput {
+            text,
+            line_index: self.line_index(file_id)?,
+            exported: self.exported_labels(file_id),
+            config: self.config.clone(),
+            instructions: self.instructions.clone(),
+            modules,
+            previous: self.analysis(file_id).and_then(|analysis| analysis.passes.clone()),
+            file_revision: self.file_revision(file_id)?,
+            dependency_revisions: self.file_revisions(&imported),
+            version: self.version(file_id),
+        })
+    }
+
+    /// Store the analysis of a file
+    ///
+    /// The analysis is discarded if the inputs changed since `token` was
+    /// created. Returns whether it was stored.
+    pub fn store_analysis(
+        &self,
+        file_id: FileId,
+        analysis: FileAnalysis,
+        token: &CancellationToken,
+    ) -> bool {
+        if token.is_cancelled() || !self.vfs.exists(file_id) {
+            return false;
+        }
+        // Keep the imports of the last text that could be lowered
+        if let Some(imports) = &analysis.imports {
+            self.imports.insert(file_id, imports.clone());
+        }
+        self.analyses.insert(file_id, Arc::new(analysis));
+        true
+    }
+
+    /// Collect the labels of a file that other open files import
+    ///
+    /// A file is imported as the module named after its file stem. Returns
+    /// `None` when another file imports the whole module with a wildcard.
+    fn exported_labels(&self, file_id: FileId) -> Option<HashSet<String>> {
+        let module_name = module_name(&self.url_for_file_id(file_id)?);
+        let imports =
+            self.imports.iter().filter(|entry| *entry.key() != file_id).collect::<Vec<_>>();
+        ram_ide::analysis::exported_labels(
+            &module_name,
+            imports.iter().flat_map(|entry| entry.value()),
+        )
+    }
+
+    /// Get the files of the modules a file imports with a wildcard
+    ///
+    /// Modules are named in diagnostics by the URLs of their files.
+    fn imported_files(&self, file_id: FileId, wildcard_imports: &[String]) -> Vec<FileId> {
+        self.modules()
+            .into_iter()
+            .filter(|(name, other)| *other != file_id && wildcard_imports.contains(name))
+            .map(|(_, other)| other)
+            .collect()
+    }
+
+    /// Get the revisions the inputs of files last changed at, in order
+    fn file_revisions(&self, file_ids: &[FileId]) -> Vec<u64> {
+        file_ids.iter().filter_map(|&file_id| self.file_revision(file_id)).collect()
+    }
+
+    /// Get the modules of the workspace and the files defining them
+    pub fn modules(&self) -> Vec<(String, FileId)> {
+        let mut modules = self
+            .file_to_url
+            .iter()
+            .map(|entry| (module_name(entry.value()), *entry.key()))
+            .collect::<Vec<_>>();
+        modules.sort_by(|(a, _), (b, _)| a.cmp(b));
+        modules
+    }
+
+    /// Get the number of files in the database
+    pub fn file_count(&self) -> usize {
+        self.vfs.iter().count()
+    }
+
+    /// Get the latest analyses of all files
+    pub fn analyses(&self) -> Vec<Arc<FileAnalysis>> {
+        self.analyses.iter().map(|analysis| Arc::clone(&analysis)).collect()
+    }
+
+    /// Get the latest analysis of a file
+    ///
+    /// The analysis may be of older inputs than the current ones while the
+    /// file is being analyzed again; see [`LspDatabase::current_analysis`].
+    pub fn analysis(&self, file_id: FileId) -> Option<Arc<FileAnalysis>> {
+        self.analyses.get(&file_id).map(|analysis| Arc::clone(&analysis))
+    }
+
+    /// Get the analysis of a file, if it is of the current inputs
+    ///
+    /// The inputs include the modules the file imports, so an analysis is
+    /// outdated once one of them changes too.
+    pub fn current_analysis(&self, file_id: FileId) -> Option<Arc<FileAnalysis>> {
+        self.analysis_lookups.record(self.analysis(file_id).filter(|analysis| {
+            Some(analysis.file_revision) == self.file_revision(file_id)
+                && analysis.dependency_revisions == self.dependency_revisions(file_id, analysis)
+        }))
+    }
+
+    /// Get the current revisions of the modules the text of an analysis imports
+    fn dependency_revisions(&self, file_id: FileId, analysis: &FileAnalysis) -> Vec<u64> {
+        let wildcard_imports = analysis
+            .imports
+            .iter()
+            .flatten()
+            .filter_map(|path| match path.module_and_symbol() {
+                Some((module, None)) => Some(module.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self.file_revisions(&self.imported_files(file_id, &wildcard_imports))
+    }
+
+    /// Get the memo count and hit rate of the line indexes and of the
+    /// analyses, which hold the syntax trees of the files
+    pub fn query_stats(&self) -> Vec<QueryStats> {
+        vec![
+            self.line_index_lookups.stats(Query::FileText, self.line_indexes.len()),
+            self.analysis_lookups.stats(Query::Parse, self.analyses.len()),
+        ]
     }
 
     /// Get the diagnostics for a file
     pub fn diagnostics_for_file(&self, file_id: FileId) -> Option<DiagnosticCollection> {
-        self.diagnostics.get(&file_id).map(|d| d.clone())
+        self.analyses.get(&file_id).map(|analysis| analysis.diagnostics.clone())
     }
 
     /// Get the syntax tree for a file
     pub fn syntax_tree_for_file(&self, file_id: FileId) -> Option<ResolvedNode> {
-        self.syntax_trees.get(&file_id).map(|t| t.clone())
+        self.analyses.get(&file_id).map(|analysis| analysis.syntax_tree.clone())
+    }
+}
+
+/// Get the path of a file in the VFS
+///
+/// Files on disk are known by their path, and other documents, such as
+/// unsaved ones, by their URL.
+fn vfs_path(url: &Url) -> VfsPath {
+    match url.to_file_path() {
+        Ok(path) => VfsPath::Real(path),
+        Err(()) => VfsPath::Virtual(url.to_string()),
     }
 }
+
+/// Get the name of the module a file is imported as, which is its file stem
+pub fn module_name(url: &Url) -> String {
+    ram_ide::analysis::module_name(url.path())
+}
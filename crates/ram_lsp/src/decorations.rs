@@ -0,0 +1,131 @@
+//! Control flow decorations shown in the editor gutter.
+//!
+//! Clients that set `experimental.controlFlowDecorations` in their
+//! capabilities receive a `ram/controlFlowDecorations` notification for each
+//! version of an open document, with an edge from every jump or call to the
+//! instruction it goes to, so they can draw arrows in the gutter.
+//!
+//! The edges come from the control flow graph, so fall-throughs and returns
+//! are left out. Typing quickly does not flood the client: the notification
+//! is only sent once the document stayed unchanged for a short while.
+
+use std::ops::Range;
+use std::time::Duration;
+
+use hir::body::{Body, Instruction};
+use hir::ids::LocalDefId;
+use hir_analysis::ControlFlowGraph;
+use hir_analysis::analyzers::control_flow::EdgeKind;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::{ClientCapabilities, Range as LspRange};
+use url::Url;
+
+/// The experimental capability clients set to receive control flow decorations
+pub const CONTROL_FLOW_DECORATIONS_CAPABILITY: &str = "controlFlowDecorations";
+
+/// How long a document has to stay unchanged before its decorations are sent
+pub const DECORATIONS_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// The `ram/controlFlowDecorations` notification
+#[derive(Debug)]
+pub enum ControlFlowDecorationsNotification {}
+
+impl Notification for ControlFlowDecorationsNotification {
+    type Params = ControlFlowDecorationsParams;
+    const METHOD: &'static str = "ram/controlFlowDecorations";
+}
+
+/// How control goes from an instruction to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FlowKind {
+    /// An unconditional jump
+    Jump,
+    /// A conditional jump, taken when its condition holds
+    Branch,
+    /// A call of a subroutine
+    Call,
+}
+
+/// An edge from a jump to its target, as byte ranges of the document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowEdge {
+    /// The jump instruction
+    pub from: Range<usize>,
+    /// The label of the target, or the target itself if it has no label
+    pub to: Range<usize>,
+    /// How the jump goes to the target
+    pub kind: FlowKind,
+}
+
+/// An edge from a jump to its target in the document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlFlowEdge {
+    /// The jump instruction
+    pub from: LspRange,
+    /// The label of the target, or the target itself if it has no label
+    pub to: LspRange,
+    /// How the jump goes to the target
+    pub kind: FlowKind,
+}
+
+/// The parameters of the `ram/controlFlowDecorations` notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlFlowDecorationsParams {
+    /// The document the edges are in
+    pub uri: Url,
+    /// The version of the document the edges were computed for
+    pub version: Option<i32>,
+    /// The edges, in the order of their jumps
+    pub edges: Vec<ControlFlowEdge>,
+}
+
+/// Find the edges from the jumps and calls of a body to their targets
+pub fn control_flow_edges(body: &Body, cfg: &ControlFlowGraph) -> Vec<FlowEdge> {
+    let mut edges = Vec::new();
+    for instruction in body.instructions.values() {
+        let Some(node) = cfg.get_node_by_instruction(instruction.id) else {
+            continue;
+        };
+        for (target, kind) in cfg.get_outgoing_edges(node) {
+            let kind = match kind {
+                // Falling through to the next instruction is not drawn
+                EdgeKind::Unconditional if instruction.kind.is_jump() => FlowKind::Jump,
+                EdgeKind::ConditionalTrue => FlowKind::Branch,
+                EdgeKind::Call => FlowKind::Call,
+                _ => continue,
+            };
+            let Some(target) = instruction_of(body, cfg.get_node(target).instruction_id) else {
+                continue;
+            };
+            let to = body
+                .labels
+                .values()
+                .find(|label| label.instruction_id == Some(target.id))
+                .map_or(&target.span, |label| &label.span);
+            edges.push(FlowEdge { from: instruction.span.clone(), to: to.clone(), kind });
+        }
+    }
+    edges.sort_by_key(|edge| (edge.from.start, edge.to.start));
+    edges.dedup();
+    edges
+}
+
+/// Get the instruction a node of the control flow graph stands for
+fn instruction_of(body: &Body, id: Option<LocalDefId>) -> Option<&Instruction> {
+    body.instructions.values().find(|instruction| Some(instruction.id) == id)
+}
+
+/// Check whether the client wants `ram/controlFlowDecorations` notifications
+pub fn client_supports_control_flow_decorations(capabilities: &ClientCapabilities) -> bool {
+    capabilities
+        .experimental
+        .as_ref()
+        .and_then(|experimental| experimental.get(CONTROL_FLOW_DECORATIONS_CAPABILITY))
+        .and_then(Value::as_bool)
+        == Some(true)
+}
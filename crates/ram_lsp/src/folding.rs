@@ -0,0 +1,66 @@
+//! Folding ranges.
+//!
+//! Two kinds of regions fold:
+//!
+//! - The comments on the lines above a statement, and comments standing on
+//!   their own.
+//! - The statements under a label, up to the next label definition.
+//!
+//! Comments belong to the statement the parser attaches them to, so the
+//! documentation of a label is never folded into the block before it.
+
+use std::ops::Range;
+
+use ram_syntax::{AstNode, Program, ResolvedNode, cstree};
+
+/// What a folding range holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldKind {
+    /// A run of comments
+    Comment,
+    /// The statements under a label
+    Block,
+}
+
+/// Get the ranges that fold in a file, in the order they start
+pub fn folding_ranges(syntax_tree: &ResolvedNode) -> Vec<(Range<usize>, FoldKind)> {
+    let Some(program) = Program::cast(syntax_tree.clone()) else {
+        return Vec::new();
+    };
+
+    let mut ranges = Vec::new();
+    let mut block: Option<Range<usize>> = None;
+    for statement in program.statements() {
+        let full = to_range(statement.full_range());
+        match statement.code_range().map(to_range) {
+            None => ranges.push((full.clone(), FoldKind::Comment)),
+            Some(code) => {
+                if let Some(comments) = statement
+                    .leading_comments()
+                    .iter()
+                    .map(|comment| to_range(comment.syntax().text_range()))
+                    .reduce(|first, last| first.start..last.end)
+                {
+                    ranges.push((comments, FoldKind::Comment));
+                }
+                if statement.label_def().is_some() {
+                    ranges.extend(block.take().map(|block| (block, FoldKind::Block)));
+                    block = Some(code.start..full.end);
+                    continue;
+                }
+            }
+        }
+        if let Some(block) = &mut block {
+            block.end = full.end;
+        }
+    }
+    ranges.extend(block.map(|block| (block, FoldKind::Block)));
+
+    ranges.sort_by_key(|(range, _)| range.start);
+    ranges
+}
+
+/// Convert a range of the syntax tree to a range of offsets
+fn to_range(range: cstree::text::TextRange) -> Range<usize> {
+    usize::from(range.start())..usize::from(range.end())
+}
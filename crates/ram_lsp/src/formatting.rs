@@ -0,0 +1,213 @@
+//! Formatting while typing.
+//!
+//! Editors ask for edits after a trigger character is typed:
+//!
+//! - `:` after a label moves the label to the start of the line and aligns the
+//!   instruction after it with the indentation of the other instructions.
+//! - A newline after a label indents the next line, where its instruction goes.
+//! - `#` after an instruction aligns the trailing comment to the comment column.
+//!
+//! The edits only look at the lines around the cursor, as the rest of the
+//! file may not parse while it is being typed. Whether a comment is trailing
+//! is decided by the parser, the same way documentation comments are attached
+//! to what they document.
+
+use ram_syntax::edit::TextEdit;
+use ram_syntax::{AstNode, Program};
+use tower_lsp::lsp_types::{FormattingOptions, FormattingProperty};
+
+use crate::db::parse_file;
+
+/// The property of the formatting options that sets the comment column
+pub const COMMENT_COLUMN_PROPERTY: &str = "ram.commentColumn";
+
+/// The column trailing comments are aligned to when it is not configured
+const DEFAULT_COMMENT_COLUMN: usize = 24;
+
+/// Settings of the formatter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatSettings {
+    /// The width of the indentation of instructions, in columns
+    pub indent_width: usize,
+    /// Whether to indent with spaces rather than tabs
+    pub insert_spaces: bool,
+    /// The column trailing comments are aligned to
+    pub comment_column: usize,
+}
+
+impl Default for FormatSettings {
+    fn default() -> Self {
+        Self { indent_width: 4, insert_spaces: true, comment_column: DEFAULT_COMMENT_COLUMN }
+    }
+}
+
+impl FormatSettings {
+    /// Get the settings from the formatting options sent by the client
+    ///
+    /// The comment column is read from the `ram.commentColumn` property.
+    pub fn from_options(options: &FormattingOptions) -> Self {
+        let comment_column = match options.properties.get(COMMENT_COLUMN_PROPERTY) {
+            Some(FormattingProperty::Number(column)) if *column > 0 => *column as usize,
+            _ => DEFAULT_COMMENT_COLUMN,
+        };
+        Self {
+            indent_width: options.tab_size.max(1) as usize,
+            insert_spaces: options.insert_spaces,
+            comment_column,
+        }
+    }
+
+    /// Get the indentation of instructions
+    fn indent(&self) -> String {
+        if self.insert_spaces { " ".repeat(self.indent_width) } else { "\t".to_string() }
+    }
+
+    /// Get the whitespace that follows `width` columns of text to reach the indentation
+    fn pad_to_indent(&self, width: usize) -> String {
+        if !self.insert_spaces {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.indent_width.saturating_sub(width).max(1))
+        }
+    }
+}
+
+/// Get the edits to apply after `ch` was typed right before `offset`
+pub fn on_type_formatting(
+    text: &str,
+    offset: usize,
+    ch: &str,
+    settings: &FormatSettings,
+) -> Vec<TextEdit> {
+    if offset > text.len() || !text.is_char_boundary(offset) {
+        return Vec::new();
+    }
+
+    match ch {
+        ":" => align_label(text, offset, settings),
+        "\n" => indent_after_label(text, offset, settings),
+        "#" => align_comment(text, offset, settings),
+        _ => Vec::new(),
+    }
+}
+
+/// Move the label ending at `offset` to the start of its line
+///
+/// If an instruction follows the label, it is aligned with the indentation.
+fn align_label(text: &str, offset: usize, settings: &FormatSettings) -> Vec<TextEdit> {
+    let line = line_at(text, offset);
+    let Some(name) = text[line.start..offset].strip_suffix(':') else {
+        return Vec::new();
+    };
+    let name_start = line.start + (name.len() - name.trim_start().len());
+    if !is_label_name(name.trim_start()) {
+        return Vec::new();
+    }
+
+    let mut edits = Vec::new();
+    if name_start > line.start {
+        edits.push(TextEdit::delete(line.start..name_start));
+    }
+
+    let rest = &text[offset..line.end];
+    let instruction_start = offset + (rest.len() - rest.trim_start().len());
+    if instruction_start > offset && !rest.trim_start().starts_with('#') {
+        let label_width = text[name_start..offset].chars().count();
+        let padding = settings.pad_to_indent(label_width);
+        if text[offset..instruction_start] != padding {
+            edits.push(TextEdit::replace(offset..instruction_start, padding));
+        }
+    }
+    edits
+}
+
+/// Indent the line starting at `offset` if the previous line is a label
+fn indent_after_label(text: &str, offset: usize, settings: &FormatSettings) -> Vec<TextEdit> {
+    if offset == 0 {
+        return Vec::new();
+    }
+    let previous = line_at(text, offset - 1);
+    let code = strip_comment(&text[previous]);
+    let Some((name, _)) = code.split_once(':') else {
+        return Vec::new();
+    };
+    if !is_label_name(name.trim_start()) {
+        return Vec::new();
+    }
+
+    let line = line_at(text, offset);
+    let current = &text[line.clone()];
+    let indent_end = line.start + (current.len() - current.trim_start().len());
+    let indent = settings.indent();
+    if text[line.start..indent_end] == indent {
+        return Vec::new();
+    }
+    vec![TextEdit::replace(line.start..indent_end, indent)]
+}
+
+/// Align the comment starting right before `offset` to the comment column
+///
+/// Only trailing comments are aligned, as the parser attaches them to the
+/// statement on their line. Comments on a line of their own, or a `#` typed
+/// inside an existing comment, are left in place.
+fn align_comment(text: &str, offset: usize, settings: &FormatSettings) -> Vec<TextEdit> {
+    let line = line_at(text, offset);
+    let Some(before) = text[line.start..offset].strip_suffix('#') else {
+        return Vec::new();
+    };
+    if !is_trailing_comment(&text[line.clone()], before.len()) {
+        return Vec::new();
+    }
+    let code = before.trim_end();
+
+    let code_end = line.start + code.len();
+    let hash = offset - 1;
+    let width = columns(code, settings);
+    let padding = " ".repeat(settings.comment_column.saturating_sub(width).max(1));
+    if text[code_end..hash] == padding {
+        return Vec::new();
+    }
+    vec![TextEdit::replace(code_end..hash, padding)]
+}
+
+/// Get the range of the line containing `offset`, without its line break
+fn line_at(text: &str, offset: usize) -> std::ops::Range<usize> {
+    let start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let end = text[offset..].find('\n').map_or(text.len(), |newline| offset + newline);
+    let end = if text[start..end].ends_with('\r') { end - 1 } else { end };
+    start..end
+}
+
+/// Check whether the comment starting at `start` in `line` is a trailing comment
+fn is_trailing_comment(line: &str, start: usize) -> bool {
+    let Some(program) = Program::cast(parse_file(line).0) else {
+        return false;
+    };
+    program
+        .statements()
+        .filter_map(|statement| statement.trailing_comment())
+        .any(|comment| usize::from(comment.syntax().text_range().start()) == start)
+}
+
+/// Remove the comment at the end of a line
+fn strip_comment(line: &str) -> &str {
+    line.split_once('#').map_or(line, |(code, _)| code)
+}
+
+/// Check whether `name` is a valid label name
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Get the number of columns `text` takes, with tabs reaching the next tab stop
+fn columns(text: &str, settings: &FormatSettings) -> usize {
+    text.chars().fold(0, |column, c| {
+        if c == '\t' {
+            (column / settings.indent_width + 1) * settings.indent_width
+        } else {
+            column + 1
+        }
+    })
+}
@@ -0,0 +1,189 @@
+//! Execution heatmaps shown in the editor.
+//!
+//! The `ram.server.runHeatmap` command runs a program with the given input
+//! and counts how many times the instructions on each line were executed.
+//! Clients that set `experimental.executionHeatmap` in their capabilities
+//! also receive the counts in a `ram/executionHeatmap` notification, to render
+//! them as line decorations or inlay hints that point out the hot loops.
+//!
+//! Runs go on in the background, so a program that never halts does not block
+//! the server. The `ram.server.stopRun` command, a new run of the same
+//! document, or the client cancelling the request stops a run, which still
+//! reports the lines executed so far.
+
+use std::sync::Arc;
+
+use ram_core::error::VmError;
+use ram_vm::{CancelFlag, Cancellation};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::ClientCapabilities;
+use tower_lsp::lsp_types::notification::Notification;
+use url::Url;
+
+use crate::db::FileAnalysis;
+
+/// The command that runs a program and returns its heatmap
+pub const RUN_HEATMAP_COMMAND: &str = "ram.server.runHeatmap";
+
+/// The command that stops the run of a document
+pub const STOP_RUN_COMMAND: &str = "ram.server.stopRun";
+
+/// The experimental capability clients set to receive heatmap notifications
+pub const HEATMAP_CAPABILITY: &str = "executionHeatmap";
+
+/// The number of instructions a run executes if the command sets no limit
+pub const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// The `ram/executionHeatmap` notification
+#[derive(Debug)]
+pub enum ExecutionHeatmapNotification {}
+
+impl Notification for ExecutionHeatmapNotification {
+    type Params = ExecutionHeatmapParams;
+    const METHOD: &'static str = "ram/executionHeatmap";
+}
+
+/// The argument of the `ram.server.runHeatmap` command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunHeatmapArgs {
+    /// The document of the program to run
+    pub uri: Url,
+    /// The input values of the program
+    #[serde(default)]
+    pub input: Vec<i64>,
+    /// The maximum number of instructions to execute
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+}
+
+/// The argument of the `ram.server.stopRun` command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopRunArgs {
+    /// The document whose run to stop
+    pub uri: Url,
+}
+
+/// The number of executions of a line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeatmapLine {
+    /// The line, counting from 0
+    pub line: u32,
+    /// The number of times the instructions on the line were executed
+    pub count: u64,
+    /// How hot the line is, from 0 for never executed to 1 for the hottest lines
+    pub heat: f64,
+}
+
+/// The parameters of the `ram/executionHeatmap` notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionHeatmapParams {
+    /// The document of the program that ran
+    pub uri: Url,
+    /// The version of the document that ran
+    pub version: Option<i32>,
+    /// The lines with instructions, in order
+    pub lines: Vec<HeatmapLine>,
+    /// The highest count of a line
+    pub max_count: u64,
+    /// The number of instructions executed
+    pub steps: u64,
+    /// Whether the program ran to its end within the maximum number of steps
+    pub finished: bool,
+    /// Whether the run was stopped before its end
+    #[serde(default)]
+    pub cancelled: bool,
+    /// The runtime error the run stopped at, if any
+    pub error: Option<String>,
+}
+
+/// Run the program of an analysis and count the executions of its lines
+///
+/// The run stops early once `cancellation` says so. Fails if the program has
+/// errors, as it could not be compiled.
+pub fn run_heatmap(
+    analysis: &FileAnalysis,
+    args: RunHeatmapArgs,
+    cancellation: Arc<dyn Cancellation>,
+) -> Result<ExecutionHeatmapParams, String> {
+    let body = match &analysis.body {
+        Some(body) if analysis.diagnostics.error_count() == 0 => body,
+        _ => return Err("The program has errors, fix them to run it".to_string()),
+    };
+    let db = Arc::new(ram_vm::VmDatabaseImpl::new());
+    let program = ram_vm::Program::from_hir(body, db.as_ref()).map_err(|e| e.to_string())?;
+    let mut vm = ram_vm::VirtualMachine::new(
+        program,
+        ram_vm::VecInput::new(args.input),
+        ram_vm::VecOutput::new(),
+        db,
+    );
+    vm.set_cancellation(cancellation);
+    let result = vm.run_steps(args.max_steps.unwrap_or(DEFAULT_MAX_STEPS));
+
+    let heatmap = ram_vm::Heatmap::new(&analysis.text, body, vm.execution_counts());
+    let lines = heatmap
+        .lines
+        .iter()
+        .map(|heat| HeatmapLine {
+            line: heat.line,
+            count: heat.count,
+            heat: heatmap.heat(heat.line),
+        })
+        .collect();
+    Ok(ExecutionHeatmapParams {
+        uri: args.uri,
+        version: analysis.version,
+        lines,
+        max_count: heatmap.max_count,
+        steps: vm.steps(),
+        finished: vm.is_finished(),
+        cancelled: matches!(result, Err(VmError::Cancelled(_))),
+        error: result.err().filter(|e| !matches!(e, VmError::Cancelled(_))).map(|e| e.to_string()),
+    })
+}
+
+/// Stops a run when dropped
+pub struct StopOnDrop(pub CancelFlag);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Run the program of an analysis on the blocking thread pool
+///
+/// Setting `flag` stops the run, and so does dropping the returned future, as
+/// tower-lsp does when the client cancels the request.
+pub async fn spawn_heatmap(
+    analysis: Arc<FileAnalysis>,
+    args: RunHeatmapArgs,
+    flag: CancelFlag,
+) -> Result<ExecutionHeatmapParams, String> {
+    let guard = StopOnDrop(flag.clone());
+    let result =
+        tokio::task::spawn_blocking(move || run_heatmap(&analysis, args, Arc::new(flag))).await;
+    drop(guard);
+
+    match result {
+        Ok(result) => result,
+        // Re-raise panics of the run in the handler
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Check whether the client wants `ram/executionHeatmap` notifications
+pub fn client_supports_heatmap(capabilities: &ClientCapabilities) -> bool {
+    capabilities
+        .experimental
+        .as_ref()
+        .and_then(|experimental| experimental.get(HEATMAP_CAPABILITY))
+        .and_then(Value::as_bool)
+        == Some(true)
+}
@@ -1,8 +1,27 @@
-use ram_syntax::{ResolvedNode, SyntaxKind, cstree};
+use std::ops::Range;
+
+use hir::body::{AddressingMode, ExprKind};
+use ram_syntax::cursor::{TokenCursor, TokenRole};
+use ram_syntax::{ResolvedNode, SyntaxKind, TokenSet, cstree};
 use tower_lsp::lsp_types::{
     SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensLegend,
 };
 
+use crate::db::FileAnalysis;
+use crate::position::PositionConverter;
+
+/// The modifier of immediate operands, which are constants
+const READONLY: u32 = 1 << 2;
+/// The modifier of unreachable instructions, dimmed like deprecated code
+const UNREACHABLE: u32 = 1 << 4;
+/// The modifier of operands naming the register an instruction writes
+const MODIFICATION: u32 = 1 << 5;
+/// The modifier of instructions after which the accumulator holds a known value
+const CONSTANT_ACCUMULATOR: u32 = 1 << 6;
+
+/// The custom modifier of instructions after which the accumulator is constant
+pub const CONSTANT_ACCUMULATOR_MODIFIER: &str = "constantAccumulator";
+
 /// Get the semantic tokens legend for RAM
 pub fn semantic_tokens_legend() -> SemanticTokensLegend {
     SemanticTokensLegend {
@@ -19,32 +38,90 @@ pub fn semantic_tokens_legend() -> SemanticTokensLegend {
             SemanticTokenType::ENUM_MEMBER, // 9: Enum members
         ],
         token_modifiers: vec![
-            SemanticTokenModifier::DECLARATION, // 0: Declaration
-            SemanticTokenModifier::DEFINITION,  // 1: Definition
-            SemanticTokenModifier::READONLY,    // 2: Readonly
-            SemanticTokenModifier::STATIC,      // 3: Static
-            SemanticTokenModifier::DEPRECATED,  // 4: Deprecated
+            SemanticTokenModifier::DECLARATION,  // 0: Declaration
+            SemanticTokenModifier::DEFINITION,   // 1: Definition
+            SemanticTokenModifier::READONLY,     // 2: Readonly
+            SemanticTokenModifier::STATIC,       // 3: Static
+            SemanticTokenModifier::DEPRECATED,   // 4: Deprecated (unreachable)
+            SemanticTokenModifier::MODIFICATION, // 5: Modification
+            SemanticTokenModifier::new(CONSTANT_ACCUMULATOR_MODIFIER), // 6: Constant accumulator
         ],
     }
 }
 
-/// Get semantic tokens for a syntax tree
-pub fn semantic_tokens_for_tree(syntax_tree: &ResolvedNode) -> Vec<SemanticToken> {
-    // Get the text of the entire file
-    let text = syntax_tree.text();
-    let text_str = text.to_string();
-
-    // Split the text into lines for position calculation
-    let lines: Vec<&str> = text_str.split('\n').collect();
-    let mut line_starts = Vec::with_capacity(lines.len());
-    let mut current_start = 0;
-
-    // Calculate the start offset of each line
-    for line in &lines {
-        line_starts.push(current_start);
-        current_start += line.len() + 1; // +1 for the newline character
+/// Token modifiers derived from the analysis of a file, by source range
+///
+/// Syntax alone tells what a token is; the analysis tells what it does: which
+/// instructions never run, which operands are written to, and where the value
+/// of the accumulator is known statically.
+#[derive(Debug, Default)]
+pub struct AnalysisModifiers {
+    /// The ranges of the source and the modifiers of the tokens within them
+    ranges: Vec<(Range<usize>, u32)>,
+}
+
+impl AnalysisModifiers {
+    /// Collect the modifiers from the results of an analysis
+    pub fn from_analysis(analysis: &FileAnalysis) -> Self {
+        let mut ranges = Vec::new();
+        let Some(body) = &analysis.body else {
+            return Self { ranges };
+        };
+
+        if let Some(cfg) = &analysis.control_flow {
+            for node_idx in cfg.find_unreachable_nodes() {
+                let instr = cfg
+                    .get_node(node_idx)
+                    .instruction_id
+                    .and_then(|id| body.instructions.values().find(|instr| instr.id == id));
+                if let Some(instr) = instr {
+                    ranges.push((instr.span.clone(), UNREACHABLE));
+                }
+            }
+        }
+
+        for instr in body.instructions.values() {
+            let known = analysis
+                .constants
+                .as_ref()
+                .and_then(|constants| constants.constant_values.get(&instr.id).copied().flatten());
+            if known.is_some() {
+                ranges.push((instr.span.clone(), CONSTANT_ACCUMULATOR));
+            }
+
+            let Some(operand) = instr.operand.and_then(|id| body.expr(id)) else {
+                continue;
+            };
+            if let ExprKind::MemoryRef(memory) = &operand.kind
+                && memory.mode == AddressingMode::Immediate
+            {
+                ranges.push((operand.span.clone(), READONLY));
+            } else if instr.kind.effects().writes_operand() {
+                ranges.push((operand.span.clone(), MODIFICATION));
+            }
+        }
+
+        Self { ranges }
     }
 
+    /// Get the modifiers of a token, from the ranges that contain it
+    fn bitset(&self, start: usize, end: usize) -> u32 {
+        self.ranges
+            .iter()
+            .filter(|(range, _)| range.start <= start && end <= range.end)
+            .fold(0, |bitset, (_, modifiers)| bitset | modifiers)
+    }
+}
+
+/// Get semantic tokens for a syntax tree
+///
+/// Positions and lengths are counted in the encoding used by `converter`.
+/// Tokens within the ranges of `modifiers` get their modifiers.
+pub fn semantic_tokens_for_tree(
+    syntax_tree: &ResolvedNode,
+    converter: &PositionConverter,
+    modifiers: &AnalysisModifiers,
+) -> Vec<SemanticToken> {
     // Create a structure to hold token information before converting to LSP format
     #[derive(Debug)]
     struct TokenInfo {
@@ -52,51 +129,30 @@ pub fn semantic_tokens_for_tree(syntax_tree: &ResolvedNode) -> Vec<SemanticToken
         character: usize,
         length: usize,
         token_type: usize,
+        modifiers: u32,
     }
 
     // Collect all tokens first
     let mut token_infos = Vec::new();
 
-    // Process all tokens in the tree
-    for element in syntax_tree.descendants_with_tokens() {
-        // We only want to process tokens in this pass
-        let token = match element {
-            cstree::util::NodeOrToken::Token(token) => token,
-            _ => continue,
-        };
-
+    // Process all tokens in the tree, by what they stand for
+    for token in TokenCursor::new(syntax_tree).tokens() {
         // Skip tokens we don't want to highlight
-        let token_type = token_type_for_syntax_kind(token.kind());
-        if token_type.is_none() {
+        let Some(token_type) = token_type_for_role(token.role()) else {
             continue;
-        }
-
-        // Get token range
-        let token_range = token.text_range();
-        let token_start = usize::from(token_range.start());
-        let token_end = usize::from(token_range.end());
-        let token_len = token_end - token_start;
+        };
 
         // Find the line and character position
-        let mut token_line = 0;
-        let mut token_character = 0;
-
-        for (i, start) in line_starts.iter().enumerate() {
-            if *start <= token_start
-                && (i == line_starts.len() - 1 || line_starts[i + 1] > token_start)
-            {
-                token_line = i;
-                token_character = token_start - start;
-                break;
-            }
-        }
+        let (token_line, token_character, token_len) =
+            token_position(converter, token.range.start, token.range.end);
 
         // Add token info
         token_infos.push(TokenInfo {
             line: token_line,
             character: token_character,
             length: token_len,
-            token_type: token_type.unwrap(),
+            token_type,
+            modifiers: modifiers.bitset(token.range.start, token.range.end),
         });
     }
 
@@ -107,63 +163,35 @@ pub fn semantic_tokens_for_tree(syntax_tree: &ResolvedNode) -> Vec<SemanticToken
 
         // Only process specific node types that need highlighting
         // and don't have corresponding tokens
-        match kind {
-            SyntaxKind::LABEL_DEF
-            | SyntaxKind::INSTRUCTION
-            | SyntaxKind::OPERAND
-            | SyntaxKind::DIRECT_OPERAND
-            | SyntaxKind::INDIRECT_OPERAND
-            | SyntaxKind::IMMEDIATE_OPERAND
-            | SyntaxKind::OPERAND_VALUE
-            | SyntaxKind::ARRAY_ACCESSOR
-            | SyntaxKind::MOD_STMT
-            | SyntaxKind::USE_STMT
-            | SyntaxKind::MODULE_PATH => {
-                // Get the token type
-                let node_type = token_type_for_syntax_kind(kind);
-                if node_type.is_none() {
-                    continue;
-                }
+        let Some(node_type) = token_type_for_node(kind) else {
+            continue;
+        };
 
-                // Check if this node has any identifier tokens
-                let has_identifier = element.descendants_with_tokens()
-                    .any(|e| matches!(e, cstree::util::NodeOrToken::Token(t) if t.kind() == SyntaxKind::IDENTIFIER));
+        // Check if this node has any identifier tokens
+        let has_identifier = element.descendants_with_tokens()
+            .any(|e| matches!(e, cstree::util::NodeOrToken::Token(t) if t.kind() == SyntaxKind::IDENTIFIER));
 
-                // Skip if it has identifiers - they'll be highlighted separately
-                if has_identifier {
-                    continue;
-                }
+        // Skip if it has identifiers - they'll be highlighted separately
+        if has_identifier {
+            continue;
+        }
 
-                // Get node range
-                let node_range = element.text_range();
-                let node_start = usize::from(node_range.start());
-                let node_end = usize::from(node_range.end());
-                let node_len = node_end - node_start;
-
-                // Find the line and character position
-                let mut node_line = 0;
-                let mut node_character = 0;
-
-                for (i, start) in line_starts.iter().enumerate() {
-                    if *start <= node_start
-                        && (i == line_starts.len() - 1 || line_starts[i + 1] > node_start)
-                    {
-                        node_line = i;
-                        node_character = node_start - start;
-                        break;
-                    }
-                }
+        // Get node range
+        let node_range = element.text_range();
+        let node_start = usize::from(node_range.start());
+        let node_end = usize::from(node_range.end());
 
-                // Add token info
-                token_infos.push(TokenInfo {
-                    line: node_line,
-                    character: node_character,
-                    length: node_len,
-                    token_type: node_type.unwrap(),
-                });
-            }
-            _ => continue,
-        }
+        // Find the line and character position
+        let (node_line, node_character, node_len) = token_position(converter, node_start, node_end);
+
+        // Add token info
+        token_infos.push(TokenInfo {
+            line: node_line,
+            character: node_character,
+            length: node_len,
+            token_type: node_type,
+            modifiers: modifiers.bitset(node_start, node_end),
+        });
     }
 
     // Sort tokens by position
@@ -187,7 +215,7 @@ pub fn semantic_tokens_for_tree(syntax_tree: &ResolvedNode) -> Vec<SemanticToken
             delta_start,
             length: info.length as u32,
             token_type: info.token_type as u32,
-            token_modifiers_bitset: 0,
+            token_modifiers_bitset: info.modifiers,
         });
 
         prev_line = info.line as u32;
@@ -197,65 +225,49 @@ pub fn semantic_tokens_for_tree(syntax_tree: &ResolvedNode) -> Vec<SemanticToken
     tokens
 }
 
-/// Get the token type for a syntax kind
-fn token_type_for_syntax_kind(kind: SyntaxKind) -> Option<usize> {
+/// Get the line, character and length of a range, in the encoding used by `converter`
+///
+/// Ranges spanning several lines keep their length in bytes.
+fn token_position(
+    converter: &PositionConverter,
+    start: usize,
+    end: usize,
+) -> (usize, usize, usize) {
+    let (start_pos, end_pos) = (converter.position(start), converter.position(end));
+
+    let length = if start_pos.line == end_pos.line {
+        (end_pos.character - start_pos.character) as usize
+    } else {
+        end - start
+    };
+    (start_pos.line as usize, start_pos.character as usize, length)
+}
+
+/// Get the token type for a token, by what it stands for
+fn token_type_for_role(role: TokenRole) -> Option<usize> {
+    match role {
+        TokenRole::Opcode => Some(0), // KEYWORD (keyword.control.instruction.ram)
+        TokenRole::Keyword => Some(0), // KEYWORD
+        TokenRole::LabelDef => Some(1), // FUNCTION (entity.name.function.ram)
+        TokenRole::Number => Some(2), // NUMBER (constant.numeric.integer.ram)
+        TokenRole::Punct => Some(3),  // OPERATOR (keyword.operator.ram)
+        TokenRole::Comment => Some(4), // COMMENT (comment.line.number-sign.ram)
+        TokenRole::Literal => Some(5), // STRING (constant.character.ram)
+        TokenRole::Module => Some(8), // TYPE
+
+        // Names in operands are left to the grammar, they may be labels or constants
+        TokenRole::Name => None,
+        TokenRole::Whitespace | TokenRole::Error => None,
+    }
+}
+
+/// Get the token type for a node that has no identifier to highlight
+fn token_type_for_node(kind: SyntaxKind) -> Option<usize> {
     match kind {
-        // Instructions and keywords
-        SyntaxKind::INSTRUCTION => Some(0), // KEYWORD (keyword.control.instruction.ram)
-        SyntaxKind::MOD_KW => Some(0),      // KEYWORD
-        SyntaxKind::USE_KW => Some(0),      // KEYWORD
-
-        // Functions and labels
-        SyntaxKind::LABEL_DEF => Some(1), // FUNCTION (entity.name.function.ram)
-
-        // Literals
-        SyntaxKind::NUMBER => Some(2), // NUMBER (constant.numeric.integer.ram)
-
-        // Operators and punctuation
-        SyntaxKind::COLON => Some(3), // OPERATOR (punctuation.definition.label.ram)
-        SyntaxKind::COMMA => Some(3), // OPERATOR
-        SyntaxKind::LBRACE => Some(3), // OPERATOR
-        SyntaxKind::RBRACE => Some(3), // OPERATOR
-        SyntaxKind::LBRACKET => Some(3), // OPERATOR (punctuation.section.brackets.begin.ram)
-        SyntaxKind::RBRACKET => Some(3), // OPERATOR (punctuation.section.brackets.end.ram)
-        SyntaxKind::STAR => Some(3),  // OPERATOR (keyword.operator.indirect.ram)
-        SyntaxKind::EQUALS => Some(3), // OPERATOR (keyword.operator.immediate.ram)
-
-        // Comments
-        SyntaxKind::COMMENT => Some(4), // COMMENT (comment.line.number-sign.ram)
-        SyntaxKind::DOC_COMMENT => Some(4), // COMMENT
-        SyntaxKind::COMMENT_GROUP => Some(4), // COMMENT
-        SyntaxKind::HASH => Some(4),    // COMMENT
-        SyntaxKind::HASH_STAR => Some(4), // COMMENT
-        SyntaxKind::COMMENT_TEXT => Some(4), // COMMENT
-
-        // Strings
-        SyntaxKind::STRING => Some(5), // STRING
-
-        // Variables and identifiers
-        SyntaxKind::IDENTIFIER => None,
-
-        // Parameters and operands
-        SyntaxKind::OPERAND => Some(7),           // PARAMETER
-        SyntaxKind::DIRECT_OPERAND => Some(7),    // PARAMETER
-        SyntaxKind::INDIRECT_OPERAND => Some(7),  // PARAMETER
-        SyntaxKind::IMMEDIATE_OPERAND => Some(7), // PARAMETER
-        SyntaxKind::OPERAND_VALUE => Some(7),     // PARAMETER
-        SyntaxKind::ARRAY_ACCESSOR => Some(7),    // PARAMETER
-
-        // Types and modules
-        SyntaxKind::MODULE_PATH => Some(8), // TYPE
-        SyntaxKind::MOD_STMT => Some(8),    // TYPE
-        SyntaxKind::USE_STMT => Some(8),    // TYPE
-
-        // Skip these
-        SyntaxKind::WHITESPACE => None,  // Skip whitespace
-        SyntaxKind::NEWLINE => None,     // Skip newlines
-        SyntaxKind::ERROR => None,       // Skip errors
-        SyntaxKind::ERROR_NODE => None,  // Skip error nodes
-        SyntaxKind::ERROR_TOKEN => None, // Skip error tokens
-
-        // Default for anything else
+        SyntaxKind::INSTRUCTION => Some(0),                  // KEYWORD
+        SyntaxKind::LABEL_DEF => Some(1),                    // FUNCTION
+        kind if kind.is_operand() => Some(7),                // PARAMETER
+        kind if TokenSet::MODULES.contains(kind) => Some(8), // TYPE
         _ => None,
     }
 }
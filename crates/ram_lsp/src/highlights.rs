@@ -0,0 +1,107 @@
+//! Highlights of the control flow related to the cursor.
+//!
+//! - On a label, or a reference to it, the label and every instruction jumping
+//!   to it or calling it are highlighted.
+//! - On a jump, the jump and the instructions it can continue at are
+//!   highlighted: the target of the jump and, for conditional jumps, the
+//!   instruction it falls through to.
+//!
+//! The related instructions come from the edges of the control flow graph, so
+//! a label is only related to the jumps that can actually reach it.
+
+use std::ops::Range;
+
+use hir::body::{Body, Instruction, Label};
+use hir::ids::LocalDefId;
+use hir_analysis::ControlFlowGraph;
+use hir_analysis::analyzers::control_flow::EdgeKind;
+use ram_ide::navigation::label_at;
+use tower_lsp::lsp_types::DocumentHighlightKind;
+
+/// Find the control flow related to the label or jump at `offset`
+///
+/// A label under the cursor is highlighted as written and the jumps to it as
+/// read, a jump under the cursor as text and its targets as read. Returns no
+/// highlights if there is neither at the offset.
+pub fn control_flow_highlights(
+    body: &Body,
+    cfg: &ControlFlowGraph,
+    offset: usize,
+) -> Vec<(Range<usize>, DocumentHighlightKind)> {
+    let contains = |span: &Range<usize>| span.start <= offset && offset <= span.end;
+
+    if let Some(label) = label_at(body, offset) {
+        return jumps_to_label(body, cfg, label);
+    }
+
+    let Some(instruction) = body
+        .instructions
+        .values()
+        .find(|instruction| instruction.kind.is_jump() && contains(&instruction.span))
+    else {
+        return Vec::new();
+    };
+    let Some(node) = cfg.get_node_by_instruction(instruction.id) else {
+        return Vec::new();
+    };
+
+    let mut highlights = vec![(instruction.span.clone(), DocumentHighlightKind::TEXT)];
+    for (target, kind) in cfg.get_outgoing_edges(node) {
+        if matches!(kind, EdgeKind::Call | EdgeKind::Return) {
+            continue;
+        }
+        let Some(target) = instruction_of(body, cfg.get_node(target).instruction_id) else {
+            continue;
+        };
+        if kind != EdgeKind::ConditionalFalse
+            && let Some(label) =
+                body.labels.values().find(|label| label.instruction_id == Some(target.id))
+        {
+            highlights.push((label.span.clone(), DocumentHighlightKind::READ));
+        }
+        highlights.push((target.span.clone(), DocumentHighlightKind::READ));
+    }
+    dedup(highlights)
+}
+
+/// Highlight a label and the instructions jumping to it or calling it
+fn jumps_to_label(
+    body: &Body,
+    cfg: &ControlFlowGraph,
+    label: &Label,
+) -> Vec<(Range<usize>, DocumentHighlightKind)> {
+    let mut highlights = vec![(label.span.clone(), DocumentHighlightKind::WRITE)];
+    let Some(node) = label.instruction_id.and_then(|id| cfg.get_node_by_instruction(id)) else {
+        return highlights;
+    };
+
+    for (source, kind) in cfg.get_incoming_edges(node) {
+        let Some(source) = instruction_of(body, cfg.get_node(source).instruction_id) else {
+            continue;
+        };
+        // Falling through to the labeled instruction does not go through the label
+        let through_label = match kind {
+            EdgeKind::ConditionalTrue | EdgeKind::Call => true,
+            EdgeKind::Unconditional => source.kind.is_jump(),
+            EdgeKind::ConditionalFalse | EdgeKind::Return => false,
+        };
+        if through_label {
+            highlights.push((source.span.clone(), DocumentHighlightKind::READ));
+        }
+    }
+    dedup(highlights)
+}
+
+/// Get the instruction a node of the control flow graph stands for
+fn instruction_of(body: &Body, id: Option<LocalDefId>) -> Option<&Instruction> {
+    body.instructions.values().find(|instruction| Some(instruction.id) == id)
+}
+
+/// Sort highlights by position and drop repeated ones
+fn dedup(
+    mut highlights: Vec<(Range<usize>, DocumentHighlightKind)>,
+) -> Vec<(Range<usize>, DocumentHighlightKind)> {
+    highlights.sort_by_key(|(range, _)| (range.start, range.end));
+    highlights.dedup_by(|(a, _), (b, _)| a == b);
+    highlights
+}
@@ -0,0 +1,145 @@
+//! Code lenses for running programs and their tests.
+//!
+//! A "▶ Run" lens is placed above the first instruction of a program, and a
+//! "Run test" lens above every doc comment holding a test annotation:
+//!
+//! ```text
+//! #* #[test(input = [5, 7], output = [12])]
+//! ```
+//!
+//! Lenses are sent without a command and resolved when the client shows them.
+//! The commands are executed by the client, which runs the program with the
+//! VM in a subprocess and shows the result in its terminal.
+
+use std::ops::Range;
+
+use ram_syntax::{AstNode, DocComment, Program, ResolvedNode};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use tower_lsp::lsp_types::Command;
+use url::Url;
+
+/// The client command that runs a program
+pub const RUN_PROGRAM_COMMAND: &str = "ram.runProgram";
+
+/// The client command that runs a program with the input of a test
+pub const RUN_TEST_COMMAND: &str = "ram.runTest";
+
+/// The annotation of a doc comment describing a test of the program
+pub const TEST_ANNOTATION: &str = "#[test";
+
+/// A test embedded in a program with a test annotation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedTest {
+    /// The input values of the program
+    pub input: Vec<i64>,
+    /// The values the program must write
+    pub output: Vec<i64>,
+}
+
+/// What a code lens does when clicked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LensAction {
+    /// Run the program
+    Run,
+    /// Run a test of the program
+    Test(EmbeddedTest),
+}
+
+/// The data a code lens is sent with, to resolve its command later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LensData {
+    /// The document the lens is in
+    pub uri: Url,
+    /// What the lens does
+    pub action: LensAction,
+}
+
+impl LensData {
+    /// Get the command the client executes for the lens
+    pub fn command(&self) -> Command {
+        match &self.action {
+            LensAction::Run => Command {
+                title: "▶ Run".to_string(),
+                command: RUN_PROGRAM_COMMAND.to_string(),
+                arguments: Some(vec![json!(self.uri)]),
+            },
+            LensAction::Test(test) => Command {
+                title: "Run test".to_string(),
+                command: RUN_TEST_COMMAND.to_string(),
+                arguments: Some(vec![json!(self.uri), json!(test)]),
+            },
+        }
+    }
+}
+
+/// Find where lenses go in a file and what they do
+pub fn code_lenses(syntax_tree: &ResolvedNode) -> Vec<(Range<usize>, LensAction)> {
+    let mut lenses = Vec::new();
+
+    let entry = Program::cast(syntax_tree.clone())
+        .and_then(|program| program.statements().find_map(|statement| statement.instruction()));
+    if let Some(instruction) = entry {
+        lenses.push((range_of(instruction.syntax()), LensAction::Run));
+    }
+
+    for doc_comment in syntax_tree.descendants().filter_map(|node| DocComment::cast(node.clone())) {
+        if let Some(test) = doc_comment.text().as_deref().and_then(parse_test_annotation) {
+            lenses.push((range_of(doc_comment.syntax()), LensAction::Test(test)));
+        }
+    }
+
+    lenses.sort_by_key(|(range, _)| range.start);
+    lenses
+}
+
+/// Parse a test annotation such as `#[test(input = [5, 7], output = [12])]`
+///
+/// The input may be left out if the program reads nothing. Returns `None` if
+/// the text is not a valid test annotation.
+pub fn parse_test_annotation(text: &str) -> Option<EmbeddedTest> {
+    let mut arguments = text
+        .trim()
+        .strip_prefix(TEST_ANNOTATION)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(")]")?
+        .trim();
+
+    let mut input = None;
+    let mut output = None;
+    while !arguments.is_empty() {
+        let (key, rest) = arguments.split_once('=')?;
+        let (values, rest) = rest.trim_start().strip_prefix('[')?.split_once(']')?;
+        let values = values
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<i64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let slot = match key.trim() {
+            "input" => &mut input,
+            "output" => &mut output,
+            _ => return None,
+        };
+        if slot.replace(values).is_some() {
+            return None;
+        }
+
+        let rest = rest.trim_start();
+        arguments = match rest.strip_prefix(',') {
+            Some(rest) => rest.trim_start(),
+            None if rest.is_empty() => rest,
+            None => return None,
+        };
+    }
+
+    Some(EmbeddedTest { input: input.unwrap_or_default(), output: output? })
+}
+
+/// Get the range of a node
+fn range_of(node: &ResolvedNode) -> Range<usize> {
+    let range = node.text_range();
+    usize::from(range.start())..usize::from(range.end())
+}
@@ -1,31 +1,98 @@
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
 
-use miette::Result;
-use ram_diagnostics::{Diagnostic, DiagnosticKind};
+use base_db::LineIndex;
+use dashmap::DashMap;
+use hir_def::item_tree::ItemTree;
+use miette::{IntoDiagnostic, Result};
+use ram_diagnostics::config::MANIFEST_FILE_NAME;
+use ram_diagnostics::{Applicability, Diagnostic, DiagnosticConfig, DiagnosticKind, SourceMap};
+use ram_ide::completion::{CompletionHints, CompletionKind, completions};
+use ram_ide::docs::{analysis_hover, hover};
+use ram_syntax::{AstNode, Program, ResolvedNode, Statement};
+use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tower_lsp::jsonrpc::Result as LspResult;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use url::Url;
+use vfs::ChangeKind;
 
 use crate::db::FileId;
 
+mod cancellation;
 mod db;
+mod decorations;
+mod folding;
+mod formatting;
+mod heatmap;
 mod highlighting;
-
-use crate::db::LspDatabase;
+mod highlights;
+mod lenses;
+mod position;
+mod progress;
+mod selection;
+mod status;
+mod symbols;
+mod test_results;
+pub mod transport;
+
+#[cfg(test)]
+mod tests;
+
+use crate::cancellation::Cancelled;
+use crate::db::{AnalysisStage, FileAnalysis, LspDatabase, analyze_file, parse_file};
+use crate::decorations::{
+    CONTROL_FLOW_DECORATIONS_CAPABILITY, ControlFlowDecorationsNotification,
+    ControlFlowDecorationsParams, ControlFlowEdge, DECORATIONS_DEBOUNCE,
+    client_supports_control_flow_decorations, control_flow_edges,
+};
+use crate::folding::{FoldKind, folding_ranges};
+use crate::formatting::{FormatSettings, on_type_formatting};
+use crate::heatmap::{
+    ExecutionHeatmapNotification, HEATMAP_CAPABILITY, RUN_HEATMAP_COMMAND, RunHeatmapArgs,
+    STOP_RUN_COMMAND, StopRunArgs, client_supports_heatmap, spawn_heatmap,
+};
 use crate::highlighting::{
-    semantic_tokens_for_tree, semantic_tokens_legend, to_lsp_semantic_tokens,
+    AnalysisModifiers, semantic_tokens_for_tree, semantic_tokens_legend, to_lsp_semantic_tokens,
+};
+use crate::highlights::control_flow_highlights;
+use crate::lenses::{LensData, code_lenses};
+use crate::position::{PositionConverter, PositionEncoding};
+use crate::progress::ProgressReporter;
+use crate::selection::selection_ranges;
+use crate::status::{
+    SHOW_STATUS_COMMAND, STATUS_CAPABILITY, ServerStatusNotification, ServerStatusParams,
+    StatusState, client_supports_status, query_stats_markdown,
 };
+use crate::symbols::{document_symbols, workspace_symbols};
+use crate::test_results::{
+    RUN_TESTS_COMMAND, RunTestsArgs, TEST_RESULTS_CAPABILITY, TestResult, TestResultsNotification,
+    TestResultsParams, TestRun, client_supports_test_results, spawn_tests,
+};
+pub use crate::transport::Transport;
 
 /// The version of the LSP server
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// The version of the Language Server Protocol the server implements
+pub const PROTOCOL_VERSION: &str = "3.17";
+
 /// The restart command ID
 const RESTART_COMMAND: &str = "ram.server.restart";
 
-#[derive(Debug)]
+/// The registration ID of the project manifest watcher
+const MANIFEST_WATCHER_ID: &str = "ram.manifest.watcher";
+
+/// The most symbols returned for a workspace symbol query
+const WORKSPACE_SYMBOL_LIMIT: usize = 128;
+
+/// The extension of RAM source files
+const SOURCE_FILE_EXTENSION: &str = "ram";
+
+#[derive(Debug, Clone)]
 struct Backend {
     /// The LSP client
     client: Client,
@@ -33,19 +100,63 @@ struct Backend {
     db: Arc<RwLock<LspDatabase>>,
     /// Flag to indicate if the server should restart
     should_restart: Arc<Mutex<bool>>,
+    /// The position encoding negotiated with the client
+    position_encoding: Arc<RwLock<PositionEncoding>>,
+    /// The root of the workspace, if the client opened one
+    workspace_root: Arc<RwLock<Option<PathBuf>>>,
+    /// The capabilities of the client
+    client_capabilities: Arc<RwLock<ClientCapabilities>>,
+    /// The work done progresses currently shown by the client
+    progress: Arc<DashMap<ProgressToken, ProgressReporter>>,
+    /// The status of the server, reported to the client when it changes
+    status: Arc<Mutex<StatusState>>,
+    /// The flags stopping the programs running in each document
+    runs: Arc<DashMap<Url, ram_vm::CancelFlag>>,
+    /// The outcomes of the last run of the embedded tests of each document
+    test_results: Arc<DashMap<Url, TestRun>>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> LspResult<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> LspResult<InitializeResult> {
         self.client.log_message(MessageType::INFO, "Initializing RAM Language Server").await;
 
+        // Load the diagnostic configuration of the workspace, if any
+        #[allow(deprecated)]
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| folder.uri.clone())
+            .or(params.root_uri)
+            .and_then(|uri| uri.to_file_path().ok());
+        if let Some(root) = &root {
+            self.reload_diagnostic_config(DiagnosticConfig::discover(root)).await;
+        }
+        *self.workspace_root.write().unwrap() = root;
+
+        // Negotiate how the columns of positions are counted
+        let position_encoding = PositionEncoding::negotiate(&params.capabilities);
+        *self.position_encoding.write().unwrap() = position_encoding;
+
+        *self.client_capabilities.write().unwrap() = params.capabilities;
+
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "RAM Language Server".to_string(),
                 version: Some(VERSION.to_string()),
             }),
             capabilities: ServerCapabilities {
+                position_encoding: Some(position_encoding.kind()),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("ram".to_string()),
+                        // Labels exported to other files are not reported as unused
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: false,
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
                 text_document_sync: Some(TextDocumentSyncCapability::Options(
                     TextDocumentSyncOptions {
                         open_close: Some(true),
@@ -59,13 +170,36 @@ impl LanguageServer for Backend {
                 )),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
-                    trigger_characters: Some(vec![".".to_string()]),
+                    trigger_characters: Some([".", "*", "=", ":"].map(String::from).to_vec()),
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
                     ..Default::default()
                 }),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: ":".to_string(),
+                    more_trigger_character: Some(vec!["\n".to_string(), "#".to_string()]),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        ..Default::default()
+                    },
+                )),
+                code_lens_provider: Some(CodeLensOptions { resolve_provider: Some(true) }),
                 execute_command_provider: Some(ExecuteCommandOptions {
-                    commands: vec![RESTART_COMMAND.to_string()],
+                    commands: vec![
+                        RESTART_COMMAND.to_string(),
+                        SHOW_STATUS_COMMAND.to_string(),
+                        RUN_HEATMAP_COMMAND.to_string(),
+                        STOP_RUN_COMMAND.to_string(),
+                        RUN_TESTS_COMMAND.to_string(),
+                    ],
                     ..Default::default()
                 }),
                 workspace: Some(WorkspaceServerCapabilities {
@@ -73,7 +207,11 @@ impl LanguageServer for Backend {
                         supported: Some(true),
                         change_notifications: Some(OneOf::Left(true)),
                     }),
-                    ..Default::default()
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        did_rename: Some(source_file_operations()),
+                        did_delete: Some(source_file_operations()),
+                        ..Default::default()
+                    }),
                 }),
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
@@ -91,6 +229,12 @@ impl LanguageServer for Backend {
                         },
                     ),
                 ),
+                experimental: Some(serde_json::json!({
+                    STATUS_CAPABILITY: true,
+                    HEATMAP_CAPABILITY: true,
+                    CONTROL_FLOW_DECORATIONS_CAPABILITY: true,
+                    TEST_RESULTS_CAPABILITY: true,
+                })),
                 ..ServerCapabilities::default()
             },
         })
@@ -98,6 +242,31 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         self.client.log_message(MessageType::INFO, "RAM Language Server initialized").await;
+
+        // Watch the project manifest so severity changes apply live, and the
+        // source files so the modules other files import stay up to date
+        let watchers = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{MANIFEST_FILE_NAME}")),
+                    kind: None,
+                },
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/*.{SOURCE_FILE_EXTENSION}")),
+                    kind: None,
+                },
+            ],
+        };
+        let registration = Registration {
+            id: MANIFEST_WATCHER_ID.to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(watchers).ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            debug!("Failed to watch {}: {}", MANIFEST_FILE_NAME, err);
+        }
+
+        self.index_workspace().await;
     }
 
     async fn shutdown(&self) -> LspResult<()> {
@@ -113,8 +282,70 @@ impl LanguageServer for Backend {
         self.client.log_message(MessageType::INFO, "Configuration changed").await;
     }
 
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
         self.client.log_message(MessageType::INFO, "Watched files changed").await;
+
+        let manifest_change =
+            params.changes.iter().rfind(|change| change.uri.path().ends_with(MANIFEST_FILE_NAME));
+        if let Some(change) = manifest_change {
+            let manifest = match change.typ {
+                FileChangeType::DELETED => None,
+                _ => change.uri.to_file_path().ok(),
+            };
+            self.reload_diagnostic_config(manifest).await;
+        }
+
+        // Read the changed source files before locking the database
+        let source_changes = params
+            .changes
+            .into_iter()
+            .filter(|change| change.uri.path().ends_with(&format!(".{SOURCE_FILE_EXTENSION}")))
+            .map(|change| {
+                let text = match change.typ {
+                    FileChangeType::DELETED => None,
+                    _ => self.workspace_file_text(&change.uri),
+                };
+                (change.uri, text)
+            })
+            .collect::<Vec<_>>();
+        self.update_source_files(source_changes).await;
+    }
+
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        let mut renamed = Vec::new();
+        {
+            let mut db = self.db.write().unwrap();
+            for rename in params.files {
+                let (Ok(from), Ok(to)) = (Url::parse(&rename.old_uri), Url::parse(&rename.new_uri))
+                else {
+                    continue;
+                };
+                debug!("File renamed: {} -> {}", from, to);
+                if db.rename_file(&from, to).is_some() {
+                    renamed.push(from);
+                }
+            }
+        }
+        if renamed.is_empty() {
+            return;
+        }
+
+        // The diagnostics of a renamed file are published under its new URL
+        for uri in renamed {
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
+        let files = self.db.read().unwrap().file_urls();
+        self.schedule_workspace_analysis(files, "Updating workspace");
+    }
+
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        let deleted = params
+            .files
+            .into_iter()
+            .filter_map(|file| Url::parse(&file.uri).ok())
+            .map(|uri| (uri, None))
+            .collect();
+        self.update_source_files(deleted).await;
     }
 
     async fn execute_command(&self, params: ExecuteCommandParams) -> LspResult<Option<Value>> {
@@ -135,6 +366,122 @@ impl LanguageServer for Backend {
 
                 Ok(None)
             }
+            SHOW_STATUS_COMMAND => {
+                let document = {
+                    let db = self.db.read().unwrap();
+                    let status = ServerStatusParams::new(&db, &self.status.lock().unwrap());
+                    status.to_markdown() + &query_stats_markdown(&db.query_stats())
+                };
+                Ok(Some(Value::String(document)))
+            }
+            RUN_HEATMAP_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .and_then(|argument| serde_json::from_value::<RunHeatmapArgs>(argument).ok())
+                    .ok_or_else(|| {
+                        tower_lsp::jsonrpc::Error::invalid_params(
+                            "Expected the URI of a program and its input",
+                        )
+                    })?;
+                let analysis = {
+                    let db = self.db.read().unwrap();
+                    db.file_id_for_url(&args.uri).and_then(|file_id| db.analysis(file_id))
+                };
+                let Some(analysis) = analysis else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params("Unknown document"));
+                };
+
+                // A new run of the document replaces the previous one
+                let uri = args.uri.clone();
+                let flag = ram_vm::CancelFlag::new();
+                if let Some(previous) = self.runs.insert(uri.clone(), flag.clone()) {
+                    previous.cancel();
+                }
+                let heatmap = spawn_heatmap(analysis, args, flag.clone()).await;
+                self.runs.remove_if(&uri, |_, running| *running == flag);
+                let heatmap = heatmap.map_err(tower_lsp::jsonrpc::Error::invalid_params)?;
+                if client_supports_heatmap(&self.client_capabilities.read().unwrap()) {
+                    self.client
+                        .send_notification::<ExecutionHeatmapNotification>(heatmap.clone())
+                        .await;
+                }
+                Ok(serde_json::to_value(heatmap).ok())
+            }
+            RUN_TESTS_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .and_then(|argument| serde_json::from_value::<RunTestsArgs>(argument).ok())
+                    .ok_or_else(|| {
+                        tower_lsp::jsonrpc::Error::invalid_params("Expected the URI of a program")
+                    })?;
+                let (file_id, analysis) = {
+                    let db = self.db.read().unwrap();
+                    let file_id = db.file_id_for_url(&args.uri);
+                    (file_id, file_id.and_then(|file_id| db.analysis(file_id)))
+                };
+                let (Some(file_id), Some(analysis)) = (file_id, analysis) else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params("Unknown document"));
+                };
+
+                // A new run of the document replaces the previous one
+                let uri = args.uri;
+                let flag = ram_vm::CancelFlag::new();
+                if let Some(previous) = self.runs.insert(uri.clone(), flag.clone()) {
+                    previous.cancel();
+                }
+                let outcomes =
+                    spawn_tests(Arc::clone(&analysis), args.max_steps, flag.clone()).await;
+                self.runs.remove_if(&uri, |_, running| *running == flag);
+                let outcomes = outcomes.map_err(tower_lsp::jsonrpc::Error::invalid_params)?;
+
+                let converter = self.converter(Arc::clone(&analysis.line_index));
+                let results = outcomes
+                    .iter()
+                    .map(|outcome| TestResult {
+                        range: converter.range(outcome.range.clone()),
+                        passed: outcome.passed(),
+                        message: outcome.message(),
+                        duration_ms: outcome.duration.as_secs_f64() * 1000.0,
+                        expected: outcome.test.output.clone(),
+                        output: outcome.result.output.clone(),
+                    })
+                    .collect();
+                let params =
+                    TestResultsParams { uri: uri.clone(), version: analysis.version, results };
+
+                let generation = self.test_results.get(&uri).map_or(0, |run| run.generation + 1);
+                let run = TestRun { text: analysis.text.clone(), outcomes, generation };
+                self.test_results.insert(uri.clone(), run);
+                if client_supports_test_results(&self.client_capabilities.read().unwrap()) {
+                    self.client.send_notification::<TestResultsNotification>(params.clone()).await;
+                }
+
+                // Show the outcomes next to the tests
+                if !self.uses_pull_diagnostics() {
+                    self.publish_diagnostics(file_id, uri).await;
+                } else if self.supports_diagnostic_refresh()
+                    && let Err(err) = self.client.workspace_diagnostic_refresh().await
+                {
+                    debug!("Failed to refresh diagnostics: {}", err);
+                }
+                Ok(serde_json::to_value(params).ok())
+            }
+            STOP_RUN_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .and_then(|argument| serde_json::from_value::<StopRunArgs>(argument).ok())
+                    .ok_or_else(|| {
+                        tower_lsp::jsonrpc::Error::invalid_params("Expected the URI of a program")
+                    })?;
+                let stopped = self.runs.remove(&args.uri).map(|(_, flag)| flag.cancel());
+                Ok(Some(Value::Bool(stopped.is_some())))
+            }
             _ => {
                 self.client
                     .log_message(
@@ -150,17 +497,18 @@ impl LanguageServer for Backend {
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri;
         let text = params.text_document.text;
+        let version = params.text_document.version;
 
         debug!("File opened: {}", uri);
 
         // Add the file to the database
         let file_id = {
             let mut db = self.db.write().unwrap();
-            db.add_file(uri.clone(), &text)
+            db.add_file(uri.clone(), &text, Some(version))
         };
 
-        // Publish diagnostics
-        self.publish_diagnostics(file_id, uri).await;
+        // Analyze the file and publish its diagnostics
+        self.schedule_analysis(file_id, uri);
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
@@ -168,53 +516,48 @@ impl LanguageServer for Backend {
 
         debug!("File changed: {}", uri);
 
-        // Get the file ID
-        let file_id = {
-            let db = self.db.read().unwrap();
-            match db.file_id_for_url(&uri) {
-                Some(id) => id,
-                None => {
-                    error!("File not found in database: {}", uri);
-                    return;
-                }
-            }
+        // Changes before the last replacement of the whole document have no effect
+        let changes = match params.content_changes.iter().rposition(|change| change.range.is_none())
+        {
+            Some(index) => &params.content_changes[index..],
+            None => &params.content_changes[..],
         };
 
         // Apply the changes
-        {
+        let file_id = {
+            let encoding = self.position_encoding();
             let mut db = self.db.write().unwrap();
 
-            // Get the current text
-            let current_text = match db.file_text(file_id) {
-                Some(text) => text,
-                None => {
-                    error!("File text not found for file ID: {:?}", file_id);
+            // Get the current text, which a replacement of the whole document
+            // does not need, so that one still applies if the file went missing
+            let current_text = db.file_id_for_url(&uri).and_then(|file_id| db.file_text(file_id));
+            let mut new_text = match (current_text, changes.first()) {
+                (Some(text), _) => text,
+                (None, Some(change)) if change.range.is_none() => String::new(),
+                (None, _) => {
+                    warn!("Ignoring changes to a file that is not open: {}", uri);
                     return;
                 }
             };
 
             // Apply the changes to get the new text
-            let mut new_text = current_text;
-            for change in params.content_changes {
+            for change in changes {
                 if let Some(range) = change.range {
-                    // Convert LSP range to string indices
-                    let start_pos = position_to_index(&new_text, range.start);
-                    let end_pos = position_to_index(&new_text, range.end);
-
-                    // Apply the change
-                    new_text.replace_range(start_pos..end_pos, &change.text);
+                    if !apply_change(&mut new_text, range, &change.text, encoding) {
+                        warn!("Ignoring a change to {} that ends before it starts", uri);
+                    }
                 } else {
                     // Full document update
-                    new_text = change.text;
+                    new_text = change.text.clone();
                 }
             }
 
             // Update the file in the database
-            db.add_file(uri.clone(), &new_text);
-        }
+            db.add_file(uri.clone(), &new_text, Some(params.text_document.version))
+        };
 
-        // Publish diagnostics
-        self.publish_diagnostics(file_id, uri).await;
+        // Analyze the file and publish its diagnostics
+        self.schedule_analysis(file_id, uri);
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
@@ -237,62 +580,546 @@ impl LanguageServer for Backend {
         // If text is provided, update the file
         if let Some(text) = params.text {
             let mut db = self.db.write().unwrap();
-            db.add_file(uri.clone(), &text);
+            db.add_file(uri.clone(), &text, None);
         }
 
-        // Publish diagnostics
-        self.publish_diagnostics(file_id, uri).await;
+        // Analyze the file and publish its diagnostics
+        self.schedule_analysis(file_id, uri);
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let uri = params.text_document.uri;
 
         debug!("File closed: {}", uri);
+        self.test_results.remove(&uri);
+
+        // A file of the workspace stays as a module other files can import,
+        // with the text saved on disk instead of the edits that were not saved
+        let saved_text = self.workspace_file_text(&uri);
+        let file_id = {
+            let mut db = self.db.write().unwrap();
+            db.set_saved_text(&uri, saved_text.as_deref());
+            db.close_file(&uri)
+        };
+
+        match file_id {
+            Some(file_id) => self.schedule_analysis(file_id, uri),
+            // Clear diagnostics for the file
+            None => self.client.publish_diagnostics(uri, vec![], None).await,
+        }
+    }
+
+    async fn completion(&self, params: CompletionParams) -> LspResult<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let (text, analysis, converter, token, modules, instructions) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            // The other files of the workspace, with their latest syntax tree
+            let modules = db
+                .modules()
+                .into_iter()
+                .filter(|(_, module_id)| *module_id != file_id)
+                .map(|(name, module_id)| (name, db.syntax_tree_for_file(module_id)))
+                .collect::<Vec<_>>();
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                    modules,
+                    db.instructions(),
+                ),
+                _ => return Ok(None),
+            }
+        };
+
+        let offset = converter.offset(position);
+        let items = cancellation::spawn(token, move |_| {
+            // The latest analysis ranks the completions even if the text
+            // changed since, as it does with every keystroke
+            let hints = match &analysis {
+                Some(analysis) => CompletionHints::from_analysis(analysis, offset, instructions),
+                None => CompletionHints { instructions, ..Default::default() },
+            };
+            let syntax_tree = match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => analysis.syntax_tree.clone(),
+                None => parse_file(&text).0,
+            };
+            Ok(completions(&syntax_tree, offset, &modules, &hints))
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
 
-        // Clear diagnostics for the file
-        self.client.publish_diagnostics(uri.clone(), vec![], None).await;
-    }
-
-    async fn completion(&self, _: CompletionParams) -> LspResult<Option<CompletionResponse>> {
-        // Basic completion for RAM instructions
-        Ok(Some(CompletionResponse::Array(vec![
-            CompletionItem::new_simple(
-                "LOAD".to_string(),
-                "Load a value into the accumulator".to_string(),
-            ),
-            CompletionItem::new_simple(
-                "STORE".to_string(),
-                "Store the accumulator value in memory".to_string(),
-            ),
-            CompletionItem::new_simple(
-                "ADD".to_string(),
-                "Add a value to the accumulator".to_string(),
-            ),
-            CompletionItem::new_simple(
-                "SUB".to_string(),
-                "Subtract a value from the accumulator".to_string(),
-            ),
-            CompletionItem::new_simple(
-                "MUL".to_string(),
-                "Multiply the accumulator by a value".to_string(),
-            ),
-            CompletionItem::new_simple(
-                "DIV".to_string(),
-                "Divide the accumulator by a value".to_string(),
-            ),
-            CompletionItem::new_simple("JMP".to_string(), "Jump to a label".to_string()),
-            CompletionItem::new_simple(
-                "JGTZ".to_string(),
-                "Jump if the accumulator is greater than zero".to_string(),
-            ),
-            CompletionItem::new_simple(
-                "JZERO".to_string(),
-                "Jump if the accumulator is zero".to_string(),
-            ),
-            CompletionItem::new_simple("HALT".to_string(), "Halt the program".to_string()),
-            CompletionItem::new_simple("READ".to_string(), "Read a value from input".to_string()),
-            CompletionItem::new_simple("WRITE".to_string(), "Write a value to output".to_string()),
-        ])))
+        let items = items.into_iter().map(to_lsp_completion).collect();
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    async fn hover(&self, params: HoverParams) -> LspResult<Option<Hover>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let (text, analysis, converter, token) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                ),
+                _ => return Ok(None),
+            }
+        };
+
+        // Find the instruction under the cursor, reusing the analysis unless
+        // the file changed since it was analyzed
+        let offset = converter.offset(position);
+        let hover = cancellation::spawn(token, move |_| {
+            Ok(match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => analysis_hover(&analysis, offset),
+                None => hover(&parse_file(&text).0, offset),
+            })
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
+
+        Ok(hover.map(|hover| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: hover.markdown,
+            }),
+            range: Some(converter.range(hover.range)),
+        }))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> LspResult<Option<Vec<DocumentHighlight>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+
+        let file_id = self.db.read().unwrap().file_id_for_url(&uri);
+        let analysis = match file_id {
+            Some(file_id) => self.current_analysis(file_id, &uri).await,
+            None => None,
+        };
+        let Some(analysis) = analysis else {
+            return Ok(None);
+        };
+        let (Some(body), Some(cfg)) = (&analysis.body, &analysis.control_flow) else {
+            return Ok(None);
+        };
+
+        let converter = self.converter(Arc::clone(&analysis.line_index));
+        let offset = converter.offset(params.text_document_position_params.position);
+        let highlights = control_flow_highlights(body, cfg, offset)
+            .into_iter()
+            .map(|(range, kind)| DocumentHighlight {
+                range: converter.range(range),
+                kind: Some(kind),
+            })
+            .collect::<Vec<_>>();
+        Ok((!highlights.is_empty()).then_some(highlights))
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> LspResult<Option<Vec<SelectionRange>>> {
+        let uri = params.text_document.uri;
+
+        let (text, analysis, converter, token) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                ),
+                _ => return Ok(None),
+            }
+        };
+
+        let offsets = params.positions.iter().map(|&position| converter.offset(position));
+        let offsets = offsets.collect::<Vec<_>>();
+        let ranges = cancellation::spawn(token, move |_| {
+            let syntax_tree = match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => analysis.syntax_tree.clone(),
+                None => parse_file(&text).0,
+            };
+            Ok(offsets
+                .iter()
+                .map(|&offset| selection_ranges(&syntax_tree, offset))
+                .collect::<Vec<_>>())
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
+
+        // Each range is the parent of the one inside it, and positions without
+        // ranges select nothing
+        let selections = ranges
+            .into_iter()
+            .zip(params.positions)
+            .map(|(ranges, position)| {
+                ranges
+                    .into_iter()
+                    .rev()
+                    .fold(None, |parent, range| {
+                        Some(SelectionRange {
+                            range: converter.range(range),
+                            parent: parent.map(Box::new),
+                        })
+                    })
+                    .unwrap_or(SelectionRange {
+                        range: Range { start: position, end: position },
+                        parent: None,
+                    })
+            })
+            .collect();
+        Ok(Some(selections))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> LspResult<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri;
+
+        let (text, analysis, converter, token) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                ),
+                _ => return Ok(None),
+            }
+        };
+
+        let labels = cancellation::spawn(token, move |_| {
+            // The sections are only measured for the text they were analyzed from
+            Ok(match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => document_symbols(
+                    &analysis.syntax_tree,
+                    analysis.sections.as_deref().map(Vec::as_slice),
+                ),
+                None => document_symbols(&parse_file(&text).0, None),
+            })
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
+
+        #[allow(deprecated)]
+        let symbols = labels
+            .into_iter()
+            .map(|label| DocumentSymbol {
+                name: label.name,
+                detail: label.detail,
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                deprecated: None,
+                range: converter.range(label.range),
+                selection_range: converter.range(label.selection_range),
+                children: None,
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> LspResult<Option<Vec<SymbolInformation>>> {
+        let (files, token) = {
+            let db = self.db.read().unwrap();
+            let files = db
+                .file_urls()
+                .into_iter()
+                .filter_map(|(file_id, uri)| {
+                    let converter = self.converter(db.line_index(file_id)?);
+                    Some((uri, db.file_text(file_id)?, db.analysis(file_id), converter))
+                })
+                .collect::<Vec<_>>();
+            (files, db.cancellation_token())
+        };
+
+        let query = params.query;
+        let symbols = cancellation::spawn(token, move |token| {
+            let mut symbols = Vec::new();
+            for (uri, text, analysis, converter) in files {
+                token.check()?;
+                // Files are parsed again if their analysis is out of date
+                let syntax_tree = match analysis.filter(|analysis| analysis.text == text) {
+                    Some(analysis) => analysis.syntax_tree.clone(),
+                    None => parse_file(&text).0,
+                };
+                let module = db::module_name(&uri);
+                #[allow(deprecated)]
+                symbols.extend(workspace_symbols(&module, &syntax_tree, &query).into_iter().map(
+                    |symbol| {
+                        let information = SymbolInformation {
+                            name: symbol.name,
+                            kind: if symbol.is_module {
+                                SymbolKind::MODULE
+                            } else {
+                                SymbolKind::FUNCTION
+                            },
+                            tags: None,
+                            deprecated: None,
+                            location: Location::new(uri.clone(), converter.range(symbol.range)),
+                            container_name: (!symbol.is_module).then_some(symbol.module),
+                        };
+                        (symbol.score, information)
+                    },
+                ));
+            }
+
+            // The best matches first, then by name and file
+            symbols.sort_by(|(a_score, a), (b_score, b)| {
+                (a_score, &a.name, a.location.uri.as_str()).cmp(&(
+                    b_score,
+                    &b.name,
+                    b.location.uri.as_str(),
+                ))
+            });
+            Ok(symbols
+                .into_iter()
+                .take(WORKSPACE_SYMBOL_LIMIT)
+                .map(|(_, symbol)| symbol)
+                .collect::<Vec<_>>())
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
+
+        Ok(Some(symbols))
+    }
+
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> LspResult<Option<Vec<FoldingRange>>> {
+        let uri = params.text_document.uri;
+
+        let (text, analysis, converter, token) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                ),
+                _ => return Ok(None),
+            }
+        };
+
+        let ranges = cancellation::spawn(token, move |_| {
+            let syntax_tree = match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => analysis.syntax_tree.clone(),
+                None => parse_file(&text).0,
+            };
+            Ok(folding_ranges(&syntax_tree))
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
+
+        // Only ranges over more than one line fold
+        let folds = ranges
+            .into_iter()
+            .map(|(range, kind)| (converter.range(range), kind))
+            .filter(|(range, _)| range.end.line > range.start.line)
+            .map(|(range, kind)| FoldingRange {
+                start_line: range.start.line,
+                start_character: Some(range.start.character),
+                end_line: range.end.line,
+                end_character: Some(range.end.character),
+                kind: Some(match kind {
+                    FoldKind::Comment => FoldingRangeKind::Comment,
+                    FoldKind::Block => FoldingRangeKind::Region,
+                }),
+                collapsed_text: None,
+            })
+            .collect::<Vec<_>>();
+        Ok((!folds.is_empty()).then_some(folds))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> LspResult<Option<Vec<TextEdit>>> {
+        let uri = params.text_document_position.text_document.uri;
+
+        let (text, converter) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (text, self.converter(line_index)),
+                _ => return Ok(None),
+            }
+        };
+
+        let offset = converter.offset(params.text_document_position.position);
+        let settings = FormatSettings::from_options(&params.options);
+        let edits = on_type_formatting(&text, offset, &params.ch, &settings)
+            .into_iter()
+            .map(|edit| TextEdit { range: converter.range(edit.range), new_text: edit.new_text })
+            .collect::<Vec<_>>();
+        Ok((!edits.is_empty()).then_some(edits))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> LspResult<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        let (file_text, converter, modules) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            // The other files of the workspace, with their latest syntax tree
+            let modules = db
+                .modules()
+                .into_iter()
+                .filter(|(_, module_id)| *module_id != file_id)
+                .filter_map(|(name, module_id)| Some((name, db.syntax_tree_for_file(module_id)?)))
+                .collect::<Vec<_>>();
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (text, self.converter(line_index), modules),
+                _ => return Ok(None),
+            }
+        };
+
+        let actions = params
+            .context
+            .diagnostics
+            .iter()
+            .flat_map(|diagnostic| {
+                quick_fix_for_diagnostic(&uri, &file_text, &converter, diagnostic)
+                    .into_iter()
+                    .chain(suggested_fixes_for_diagnostic(&uri, diagnostic))
+                    .chain(import_fixes_for_diagnostic(
+                        &uri, &file_text, &converter, diagnostic, &modules,
+                    ))
+            })
+            .map(CodeActionOrCommand::CodeAction)
+            .collect::<Vec<_>>();
+
+        Ok(if actions.is_empty() { None } else { Some(actions) })
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> LspResult<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+
+        let (text, analysis, converter, token) = {
+            let db = self.db.read().unwrap();
+            let Some(file_id) = db.file_id_for_url(&uri) else {
+                return Ok(None);
+            };
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                ),
+                _ => return Ok(None),
+            }
+        };
+
+        let lenses = cancellation::spawn(token, move |_| {
+            let syntax_tree = match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => analysis.syntax_tree.clone(),
+                None => parse_file(&text).0,
+            };
+            Ok(code_lenses(&syntax_tree))
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
+
+        // Commands are filled in when the client resolves the lenses it shows
+        let lenses = lenses
+            .into_iter()
+            .map(|(range, action)| CodeLens {
+                range: converter.range(range),
+                command: None,
+                data: serde_json::to_value(LensData { uri: uri.clone(), action }).ok(),
+            })
+            .collect::<Vec<_>>();
+        Ok((!lenses.is_empty()).then_some(lenses))
+    }
+
+    async fn code_lens_resolve(&self, mut lens: CodeLens) -> LspResult<CodeLens> {
+        let data = lens.data.take().and_then(|data| serde_json::from_value::<LensData>(data).ok());
+        match data {
+            Some(data) => lens.command = Some(data.command()),
+            None => return Err(tower_lsp::jsonrpc::Error::invalid_params("Unknown code lens")),
+        }
+        Ok(lens)
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> LspResult<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri;
+
+        debug!("Diagnostics requested: {}", uri);
+
+        let file_id = self.db.read().unwrap().file_id_for_url(&uri);
+        let analysis = match file_id {
+            Some(file_id) => self.current_analysis(file_id, &uri).await,
+            None => None,
+        };
+        let Some(analysis) = analysis else {
+            error!("File not found in database: {}", uri);
+            return Ok(DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(
+                RelatedFullDocumentDiagnosticReport::default(),
+            )));
+        };
+
+        // The client already has the diagnostics of this analysis
+        let result_id = self.diagnostics_result_id(&uri, &analysis);
+        if params.previous_result_id.as_ref() == Some(&result_id) {
+            return Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id,
+                    },
+                }),
+            ));
+        }
+
+        Ok(DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(
+            RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items: self.lsp_diagnostics(&uri, &analysis),
+                },
+            },
+        )))
     }
 
     async fn semantic_tokens_full(
@@ -301,8 +1128,8 @@ impl LanguageServer for Backend {
     ) -> LspResult<Option<SemanticTokensResult>> {
         let uri = params.text_document.uri;
 
-        // Get the file ID and syntax tree - clone what we need to avoid holding locks across await points
-        let syntax_tree = {
+        // Get the file text and analysis - clone what we need to avoid holding locks across await points
+        let (text, analysis, converter, token) = {
             let db = self.db.read().unwrap();
             let file_id = match db.file_id_for_url(&uri) {
                 Some(id) => id,
@@ -312,17 +1139,34 @@ impl LanguageServer for Backend {
                 }
             };
 
-            match db.syntax_tree_for_file(file_id) {
-                Some(tree) => tree.clone(),
-                None => {
-                    error!("Syntax tree not found for file: {}", uri);
+            match (db.file_text(file_id), db.line_index(file_id)) {
+                (Some(text), Some(line_index)) => (
+                    text,
+                    db.analysis(file_id),
+                    self.converter(line_index),
+                    db.cancellation_token(),
+                ),
+                _ => {
+                    error!("File text not found for file: {}", uri);
                     return Ok(None);
                 }
             }
         };
 
-        // Get semantic tokens
-        let tokens = semantic_tokens_for_tree(&syntax_tree);
+        // Get semantic tokens on the blocking thread pool, reusing the syntax
+        // tree of the analysis and its modifiers unless the file changed since
+        // it was analyzed
+        let tokens = cancellation::spawn(token, move |_| {
+            let (syntax_tree, modifiers) = match analysis.filter(|analysis| analysis.text == text) {
+                Some(analysis) => {
+                    (analysis.syntax_tree.clone(), AnalysisModifiers::from_analysis(&analysis))
+                }
+                None => (parse_file(&text).0, AnalysisModifiers::default()),
+            };
+            Ok(semantic_tokens_for_tree(&syntax_tree, &converter, &modifiers))
+        })
+        .await
+        .map_err(|Cancelled| tower_lsp::jsonrpc::Error::content_modified())?;
         let lsp_tokens = to_lsp_semantic_tokens(tokens);
 
         Ok(Some(SemanticTokensResult::Tokens(lsp_tokens)))
@@ -351,103 +1195,780 @@ impl LanguageServer for Backend {
 }
 
 impl Backend {
+    /// Get the position encoding negotiated with the client
+    fn position_encoding(&self) -> PositionEncoding {
+        *self.position_encoding.read().unwrap()
+    }
+
+    /// Check whether the client supports work done progress created by the server
+    fn supports_work_done_progress(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities.window.as_ref().and_then(|window| window.work_done_progress) == Some(true)
+    }
+
+    /// Check whether the client pulls diagnostics instead of having them published
+    fn uses_pull_diagnostics(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities.text_document.as_ref().is_some_and(|document| document.diagnostic.is_some())
+    }
+
+    /// Check whether the client can be asked to pull diagnostics again
+    fn supports_diagnostic_refresh(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            == Some(true)
+    }
+
+    /// Send the status of the server to the client, if it changed since it was last sent
+    ///
+    /// Nothing is sent to clients that did not ask for status notifications.
+    async fn send_status(&self) {
+        if !client_supports_status(&self.client_capabilities.read().unwrap()) {
+            return;
+        }
+        let status = {
+            let db = self.db.read().unwrap();
+            let mut state = self.status.lock().unwrap();
+            let status = ServerStatusParams::new(&db, &state);
+            if state.last_sent.as_ref() == Some(&status) {
+                return;
+            }
+            state.last_sent = Some(status.clone());
+            status
+        };
+        self.client.send_notification::<ServerStatusNotification>(status).await;
+    }
+
+    /// Create a position converter for a document using the negotiated encoding
+    fn converter(&self, line_index: Arc<LineIndex>) -> PositionConverter {
+        PositionConverter::new(line_index, self.position_encoding())
+    }
+
+    /// Load the diagnostic configuration from a manifest and republish diagnostics
+    ///
+    /// Without a manifest every diagnostic is reported with its default severity.
+    async fn reload_diagnostic_config(&self, manifest: Option<std::path::PathBuf>) {
+        let config = match manifest.as_deref().map(DiagnosticConfig::load) {
+            Some(Ok(config)) => config,
+            Some(Err(err)) => {
+                self.status.lock().unwrap().config_error = Some(err.to_string());
+                self.send_status().await;
+                self.client.show_message(MessageType::WARNING, err.to_string()).await;
+                return;
+            }
+            None => DiagnosticConfig::default(),
+        };
+        self.status.lock().unwrap().config_error = None;
+
+        let files = {
+            let mut db = self.db.write().unwrap();
+            db.set_diagnostic_config(config)
+        };
+        self.schedule_workspace_analysis(files, "Applying diagnostic configuration");
+    }
+
+    /// Load the source files of the workspace and analyze them
+    ///
+    /// Files already opened by the client keep their text.
+    async fn index_workspace(&self) {
+        let Some(root) = self.workspace_root.read().unwrap().clone() else {
+            return;
+        };
+
+        let sources = tokio::task::spawn_blocking(move || {
+            find_source_files(&root)
+                .into_iter()
+                .filter_map(|path| {
+                    let uri = Url::from_file_path(&path).ok()?;
+                    Some((uri, std::fs::read_to_string(&path).ok()?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        let mut files = Vec::new();
+        {
+            let mut db = self.db.write().unwrap();
+            for (uri, text) in sources {
+                if db.set_saved_text(&uri, Some(&text)) == Some(ChangeKind::Create)
+                    && let Some(file_id) = db.file_id_for_url(&uri)
+                {
+                    files.push((file_id, uri));
+                }
+            }
+        }
+        self.schedule_workspace_analysis(files, "Indexing workspace");
+    }
+
+    /// Read the saved text of a source file of the workspace
+    ///
+    /// Returns `None` for files outside of the workspace or missing on disk.
+    fn workspace_file_text(&self, uri: &Url) -> Option<String> {
+        let root = self.workspace_root.read().unwrap().clone()?;
+        let path = uri.to_file_path().ok()?;
+        if !path.starts_with(&root)
+            || path.extension().is_none_or(|extension| extension != SOURCE_FILE_EXTENSION)
+        {
+            return None;
+        }
+        std::fs::read_to_string(path).ok()
+    }
+
+    /// Apply changes made to source files outside of the client
+    ///
+    /// Each change is the saved text of a file, or `None` if the file was
+    /// deleted. Files open in the client keep the text of the client, which
+    /// overlays the saved one. Every file is analyzed again, as the modules
+    /// they import may have changed.
+    async fn update_source_files(&self, changes: Vec<(Url, Option<String>)>) {
+        let mut removed = Vec::new();
+        let files = {
+            let mut db = self.db.write().unwrap();
+            let mut changed = false;
+            for (uri, text) in changes {
+                match db.set_saved_text(&uri, text.as_deref()) {
+                    Some(ChangeKind::Delete) => removed.push(uri),
+                    Some(_) => {}
+                    None => continue,
+                }
+                changed = true;
+            }
+            if !changed {
+                return;
+            }
+            db.file_urls()
+        };
+
+        for uri in removed {
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
+        self.schedule_workspace_analysis(files, "Updating workspace");
+    }
+
+    /// Cancel the work whose progress the client cancelled
+    async fn work_done_progress_cancel(&self, params: WorkDoneProgressCancelParams) {
+        if let Some(progress) = self.progress.get(&params.token) {
+            debug!("Client cancelled progress {:?}", params.token);
+            progress.cancel();
+        }
+    }
+
+    /// Analyze a file in the background and publish its diagnostics
+    ///
+    /// Returns immediately, so notification handlers never wait for the
+    /// analysis. Clients pulling diagnostics ask for them when they need
+    /// them, so nothing is scheduled for those, unless they also want the
+    /// control flow decorations of the file.
+    fn schedule_analysis(&self, file_id: FileId, uri: Url) {
+        if self.uses_pull_diagnostics() && !self.supports_control_flow_decorations() {
+            return;
+        }
+        let backend = self.clone();
+        tokio::spawn(async move { backend.analyze(file_id, uri, |_| {}).await });
+    }
+
+    /// Analyze several files in the background and publish their diagnostics
+    ///
+    /// The progress is reported to the client under `title`, if it supports
+    /// work done progress. The client can cancel the files not analyzed yet.
+    fn schedule_workspace_analysis(&self, files: Vec<(FileId, Url)>, title: &'static str) {
+        if files.is_empty() {
+            return;
+        }
+
+        let backend = self.clone();
+        tokio::spawn(async move {
+            backend.status.lock().unwrap().indexing += 1;
+            backend.send_status().await;
+            backend.analyze_files(files, title).await;
+            backend.status.lock().unwrap().indexing -= 1;
+            backend.send_status().await;
+
+            // Clients pulling diagnostics have to ask for the new ones
+            if backend.uses_pull_diagnostics()
+                && backend.supports_diagnostic_refresh()
+                && let Err(err) = backend.client.workspace_diagnostic_refresh().await
+            {
+                debug!("Failed to refresh diagnostics: {}", err);
+            }
+        });
+    }
+
+    /// Analyze several files one after the other, reporting the progress
+    async fn analyze_files(&self, files: Vec<(FileId, Url)>, title: &str) {
+        let progress = if self.supports_work_done_progress() {
+            ProgressReporter::begin(&self.client, title).await
+        } else {
+            None
+        };
+        let Some(progress) = progress else {
+            for (file_id, uri) in files {
+                self.analyze(file_id, uri, |_| {}).await;
+            }
+            return;
+        };
+
+        self.progress.insert(progress.token().clone(), progress.clone());
+        let count = files.len();
+        let mut analyzed = 0;
+        for (index, (file_id, uri)) in files.into_iter().enumerate() {
+            if progress.is_cancelled() {
+                break;
+            }
+            let file_name = file_name(&uri).to_string();
+            let reporter = progress.clone();
+            let on_stage = move |stage| reporter.report_stage(stage, &file_name, index, count);
+            self.analyze(file_id, uri, on_stage).await;
+            analyzed += 1;
+        }
+        self.progress.remove(progress.token());
+
+        let message = if analyzed < count {
+            format!("Cancelled after analyzing {analyzed} of {count} files")
+        } else {
+            format!("Analyzed {count} files")
+        };
+        progress.end(message);
+    }
+
+    /// Analyze a file on the blocking thread pool and publish its diagnostics
+    ///
+    /// `on_stage` is called when each stage of the analysis starts. An
+    /// analysis cancelled by a change to the inputs is started again, unless
+    /// the inputs of the file itself changed: the analysis scheduled for that
+    /// change publishes the diagnostics instead. Diagnostics are not published
+    /// to clients that pull them. The control flow decorations of open
+    /// documents are sent once the document stops changing.
+    async fn analyze<F>(&self, file_id: FileId, uri: Url, on_stage: F)
+    where
+        F: Fn(AnalysisStage) + Clone + Send + 'static,
+    {
+        loop {
+            let (input, token) = {
+                let db = self.db.read().unwrap();
+                match db.analysis_input(file_id) {
+                    Some(input) => (input, db.cancellation_token()),
+                    None => return,
+                }
+            };
+            let file_revision = input.file_revision;
+
+            let on_stage = on_stage.clone();
+            let analysis = cancellation::spawn(token.clone(), move |token| {
+                analyze_file(&input, token, &on_stage)
+            })
+            .await;
+            let stored = match analysis {
+                Ok(analysis) => self.db.read().unwrap().store_analysis(file_id, analysis, &token),
+                Err(Cancelled) => false,
+            };
+            if stored {
+                if self.supports_control_flow_decorations() {
+                    self.schedule_decorations(file_id, uri.clone(), file_revision);
+                }
+                if !self.uses_pull_diagnostics() {
+                    self.publish_diagnostics(file_id, uri).await;
+                }
+                self.send_status().await;
+                return;
+            }
+
+            let file_changed =
+                self.db.read().unwrap().file_revision(file_id) != Some(file_revision);
+            if file_changed {
+                debug!("Analysis of {} superseded by a newer change", uri);
+                return;
+            }
+            debug!("Analysis of {} cancelled, starting again", uri);
+        }
+    }
+
+    /// Get the analysis of the current inputs of a file, analyzing it if needed
+    ///
+    /// Returns `None` if the file is not in the database.
+    async fn current_analysis(&self, file_id: FileId, uri: &Url) -> Option<Arc<FileAnalysis>> {
+        loop {
+            {
+                let db = self.db.read().unwrap();
+                if let Some(analysis) = db.current_analysis(file_id) {
+                    return Some(analysis);
+                }
+                db.file_revision(file_id)?;
+            }
+            self.analyze(file_id, uri.clone(), |_| {}).await;
+        }
+    }
+
     /// Publish diagnostics for a file
+    ///
+    /// The diagnostics are tagged with the version of the document they were
+    /// computed for. Diagnostics of outdated inputs are never published: the
+    /// analysis of the current inputs publishes its own.
     async fn publish_diagnostics(&self, file_id: FileId, uri: Url) {
-        // Get the diagnostics and file text from the database
-        // We need to clone the data we need so we don't hold the lock across await points
-        let (diagnostics, file_text) = {
+        // Clone the data we need so we don't hold the lock across await points
+        let analysis = {
             let db = self.db.read().unwrap();
-            let diags = match db.diagnostics_for_file(file_id) {
-                Some(diags) => diags.clone(),
+            match db.current_analysis(file_id) {
+                Some(analysis) => analysis,
                 None => {
-                    debug!("No diagnostics found for file: {}", uri);
+                    debug!("No current diagnostics found for file: {}", uri);
                     return;
                 }
-            };
+            }
+        };
 
-            let text = match db.file_text(file_id) {
-                Some(text) => text.to_string(),
-                None => {
-                    error!("File text not found for file ID: {:?}", file_id);
-                    return;
+        // Publish the diagnostics
+        let lsp_diagnostics = self.lsp_diagnostics(&uri, &analysis);
+        self.client.publish_diagnostics(uri, lsp_diagnostics, analysis.version).await;
+    }
+
+    /// Check whether the client wants control flow decorations
+    fn supports_control_flow_decorations(&self) -> bool {
+        client_supports_control_flow_decorations(&self.client_capabilities.read().unwrap())
+    }
+
+    /// Send the control flow decorations of a document once it stops changing
+    ///
+    /// The decorations are computed from the analysis of `file_revision`
+    /// after [`DECORATIONS_DEBOUNCE`], and dropped if the document changed in
+    /// the meantime: the analysis of the change schedules its own. Files the
+    /// client did not open get no decorations.
+    fn schedule_decorations(&self, file_id: FileId, uri: Url, file_revision: u64) {
+        let backend = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(DECORATIONS_DEBOUNCE).await;
+            let analysis = {
+                let db = backend.db.read().unwrap();
+                match db.current_analysis(file_id) {
+                    Some(analysis) if analysis.file_revision == file_revision => analysis,
+                    _ => return,
                 }
             };
+            if analysis.version.is_none() {
+                return;
+            }
 
-            (diags, text)
-        };
+            let converter = backend.converter(Arc::clone(&analysis.line_index));
+            let edges = match (&analysis.body, &analysis.control_flow) {
+                (Some(body), Some(cfg)) => control_flow_edges(body, cfg)
+                    .into_iter()
+                    .map(|edge| ControlFlowEdge {
+                        from: converter.range(edge.from),
+                        to: converter.range(edge.to),
+                        kind: edge.kind,
+                    })
+                    .collect(),
+                // Clear the arrows of the last version that could be analyzed
+                _ => Vec::new(),
+            };
+            let params = ControlFlowDecorationsParams { uri, version: analysis.version, edges };
+            backend.client.send_notification::<ControlFlowDecorationsNotification>(params).await;
+        });
+    }
 
-        // Convert to LSP diagnostics
-        let lsp_diagnostics = diagnostics
-            .diagnostics()
-            .iter()
-            .map(|diagnostic| convert_diagnostic_to_lsp(&file_text, diagnostic))
-            .collect::<Vec<_>>();
+    /// Get the ID identifying the diagnostics of an analysis of the document
+    /// `uri` in pull requests
+    ///
+    /// The outcomes of the embedded tests are shown among the diagnostics, so
+    /// each run of the tests changes the ID too.
+    fn diagnostics_result_id(&self, uri: &Url, analysis: &FileAnalysis) -> String {
+        match self.test_results.get(uri) {
+            Some(run) if run.text == analysis.text => {
+                format!("{}-{}", analysis.result_id(), run.generation)
+            }
+            _ => analysis.result_id(),
+        }
+    }
 
-        // Publish the diagnostics
-        self.client.publish_diagnostics(uri, lsp_diagnostics, None).await;
+    /// Convert the diagnostics of an analysis of the document `uri` to LSP diagnostics
+    fn lsp_diagnostics(
+        &self,
+        uri: &Url,
+        analysis: &FileAnalysis,
+    ) -> Vec<tower_lsp::lsp_types::Diagnostic> {
+        let mut diagnostics = analysis.diagnostics.diagnostics().to_vec();
+        if let Some(run) = self.test_results.get(uri) {
+            diagnostics.extend(run.diagnostics(&analysis.text));
+        }
+
+        // Collect the text of the other files the diagnostics refer to
+        let mut sources = SourceMap::single(uri.as_str(), analysis.text.clone());
+        {
+            let db = self.db.read().unwrap();
+            for span in diagnostics.iter().flat_map(|d| &d.external_spans) {
+                let text = Url::parse(&span.file)
+                    .ok()
+                    .and_then(|url| db.file_id_for_url(&url))
+                    .and_then(|file_id| db.file_text(file_id));
+                if let Some(text) = text {
+                    sources.add(span.file.clone(), text);
+                }
+            }
+        }
+
+        let converter = self.converter(Arc::clone(&analysis.line_index));
+        diagnostics
+            .iter()
+            .map(|diagnostic| convert_diagnostic_to_lsp(uri, &sources, &converter, diagnostic))
+            .collect()
     }
 }
 
-/// Convert a position to an index in the text
-fn position_to_index(text: &str, position: Position) -> usize {
-    let mut line = 0;
-    let mut column = 0;
-    let mut index = 0;
+/// Convert a completion to its LSP representation
+fn to_lsp_completion(item: ram_ide::CompletionItem) -> CompletionItem {
+    CompletionItem {
+        label: item.label,
+        kind: Some(match item.kind {
+            CompletionKind::Instruction => CompletionItemKind::KEYWORD,
+            CompletionKind::Label => CompletionItemKind::REFERENCE,
+            CompletionKind::Module => CompletionItemKind::MODULE,
+            CompletionKind::Address => CompletionItemKind::VALUE,
+        }),
+        detail: item.detail,
+        documentation: item.documentation.map(|value| {
+            Documentation::MarkupContent(MarkupContent { kind: MarkupKind::Markdown, value })
+        }),
+        sort_text: item.sort_text,
+        ..Default::default()
+    }
+}
 
-    for c in text.chars() {
-        if line == position.line as usize && column == position.character as usize {
-            return index;
-        }
+/// The file operations on RAM source files the server wants to hear about
+fn source_file_operations() -> FileOperationRegistrationOptions {
+    FileOperationRegistrationOptions {
+        filters: vec![FileOperationFilter {
+            scheme: Some("file".to_string()),
+            pattern: FileOperationPattern {
+                glob: format!("**/*.{SOURCE_FILE_EXTENSION}"),
+                matches: Some(FileOperationPatternKind::File),
+                options: None,
+            },
+        }],
+    }
+}
 
-        if c == '\n' {
-            line += 1;
-            column = 0;
-        } else {
-            column += 1;
+/// Find the RAM source files under `root`
+///
+/// Hidden directories and build outputs are skipped.
+fn find_source_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if path.is_dir() {
+                if !name.starts_with('.') && name != "target" && name != "node_modules" {
+                    directories.push(path);
+                }
+            } else if path.extension().is_some_and(|extension| extension == SOURCE_FILE_EXTENSION) {
+                files.push(path);
+            }
         }
-
-        index += c.len_utf8();
     }
+    files.sort();
+    files
+}
 
-    index
+/// Replace a range of a text with the text of a change sent by the client
+///
+/// The ends of the range are moved back to the start of the characters they
+/// fall inside of. Returns `false`, leaving the text unchanged, when the range
+/// ends before it starts.
+fn apply_change(text: &mut String, range: Range, change: &str, encoding: PositionEncoding) -> bool {
+    let converter = PositionConverter::for_text(text, encoding);
+    let start = text.floor_char_boundary(converter.offset(range.start));
+    let end = text.floor_char_boundary(converter.offset(range.end));
+    if start > end {
+        return false;
+    }
+    text.replace_range(start..end, change);
+    true
 }
 
-/// Convert a byte offset to LSP Position
-fn position_at_offset(text: &str, offset: usize) -> Position {
-    let offset = offset.min(text.len());
+/// Get the name of the file a URL points to
+fn file_name(uri: &Url) -> &str {
+    uri.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default()
+}
 
-    let mut line = 0;
-    let mut line_start = 0;
+/// Build the quick fix for an LSP diagnostic, if one is available
+fn quick_fix_for_diagnostic(
+    uri: &Url,
+    source: &str,
+    converter: &PositionConverter,
+    diagnostic: &tower_lsp::lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    let code = match &diagnostic.code {
+        Some(NumberOrString::String(code)) => code.as_str(),
+        _ => return None,
+    };
 
-    for (i, c) in text.char_indices() {
-        if i >= offset {
-            break;
+    match code {
+        hir_analysis::analyzers::unused_labels::UNUSED_LABEL_CODE => {
+            let start = converter.offset(diagnostic.range.start);
+            let program = Program::cast(parse_file(source).0)?;
+            let (statement, label) = program.statements().find_map(|statement| {
+                let label = statement.label_def()?;
+                let label_start = usize::from(label.syntax().text_range().start());
+                (label_start == start).then_some((statement, label))
+            })?;
+
+            let removal = ram_syntax::edit::remove_label(&statement)?;
+            let label = label.name()?;
+            Some(quick_fix(
+                uri,
+                converter,
+                diagnostic,
+                format!("Remove unused label '{}'", label),
+                vec![removal],
+            ))
         }
-        if c == '\n' {
-            line += 1;
-            line_start = i + 1;
+        hir_analysis::analyzers::dominance::DEAD_CODE_CODE => {
+            let statements = statements_in_range(source, converter, diagnostic.range);
+            if statements.is_empty() {
+                return None;
+            }
+            let removals = statements.iter().map(ram_syntax::edit::remove_statement).collect();
+            Some(quick_fix(uri, converter, diagnostic, "Remove dead code".to_string(), removals))
         }
+        hir_analysis::analyzers::scheduling::JUMP_TO_NEXT_CODE => {
+            // A labeled jump keeps its statement, as the label must stay
+            let [jump] =
+                statements_in_range(source, converter, diagnostic.range).try_into().ok()?;
+            if jump.label_def().is_some() {
+                return None;
+            }
+            let removal = ram_syntax::edit::remove_statement(&jump);
+            Some(quick_fix(
+                uri,
+                converter,
+                diagnostic,
+                "Remove the jump".to_string(),
+                vec![removal],
+            ))
+        }
+        hir_analysis::analyzers::scheduling::REDUNDANT_JUMP_CODE => {
+            match <[Statement; 2]>::try_from(statements_in_range(
+                source,
+                converter,
+                diagnostic.range,
+            )) {
+                // A conditional jump followed by a jump to the same label
+                Ok([first, second]) => {
+                    let opcode = second.instruction()?.opcode()?;
+                    let replacement =
+                        ram_syntax::edit::replace_opcode(&first.instruction()?, &opcode)?;
+                    let removal = ram_syntax::edit::remove_statement(&second);
+                    Some(quick_fix(
+                        uri,
+                        converter,
+                        diagnostic,
+                        format!("Replace both jumps with `{}`", opcode),
+                        vec![replacement, removal],
+                    ))
+                }
+                // A jump that is never taken
+                Err(statements) => {
+                    let [jump] = statements.try_into().ok()?;
+                    let removal = ram_syntax::edit::remove_statement(&jump);
+                    Some(quick_fix(
+                        uri,
+                        converter,
+                        diagnostic,
+                        "Remove the jump".to_string(),
+                        vec![removal],
+                    ))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Build a preferred quick fix for an LSP diagnostic from edits of the source
+fn quick_fix(
+    uri: &Url,
+    converter: &PositionConverter,
+    diagnostic: &tower_lsp::lsp_types::Diagnostic,
+    title: String,
+    edits: Vec<ram_syntax::edit::TextEdit>,
+) -> CodeAction {
+    let edits = edits
+        .into_iter()
+        .map(|edit| TextEdit { range: converter.range(edit.range), new_text: edit.new_text })
+        .collect();
+
+    CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some([(uri.clone(), edits)].into_iter().collect()),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
     }
+}
+
+/// Get the statements whose instruction lies within a range of the source
+fn statements_in_range(
+    source: &str,
+    converter: &PositionConverter,
+    range: Range,
+) -> Vec<Statement> {
+    let (start, end) = (converter.offset(range.start), converter.offset(range.end));
+    let Some(program) = Program::cast(parse_file(source).0) else {
+        return Vec::new();
+    };
+    program
+        .statements()
+        .filter(|statement| {
+            statement.instruction().is_some_and(|instruction| {
+                let range = instruction.syntax().text_range();
+                usize::from(range.start()) >= start && usize::from(range.end()) <= end
+            })
+        })
+        .collect()
+}
 
-    let character = (offset - line_start) as u32;
-    Position::new(line as u32, character)
+/// Build the quick fixes importing an undefined label from the modules that define it
+///
+/// `modules` are the other files of the workspace, by module name, with their
+/// syntax trees.
+fn import_fixes_for_diagnostic(
+    uri: &Url,
+    source: &str,
+    converter: &PositionConverter,
+    diagnostic: &tower_lsp::lsp_types::Diagnostic,
+    modules: &[(String, ResolvedNode)],
+) -> Vec<CodeAction> {
+    let undefined_label = hir_analysis::analyzers::instruction_validation::UNDEFINED_LABEL_CODE;
+    if !matches!(&diagnostic.code, Some(NumberOrString::String(code)) if code == undefined_label) {
+        return Vec::new();
+    }
+
+    // The operand may be prefixed by its addressing mode, so the label is
+    // taken from the end of the operand
+    let (start, end) =
+        (converter.offset(diagnostic.range.start), converter.offset(diagnostic.range.end));
+    let Some(operand) = source.get(start..end) else {
+        return Vec::new();
+    };
+    let name = operand.trim_start_matches(|c: char| !(c.is_alphanumeric() || c == '_'));
+    if name.is_empty() {
+        return Vec::new();
+    }
+
+    let Some(program) = Program::cast(parse_file(source).0) else {
+        return Vec::new();
+    };
+    let item_tree = ItemTree::lower(&program, base_db::input::FileId(0));
+    let module_trees = modules
+        .iter()
+        .filter_map(|(module, syntax_tree)| {
+            let program = Program::cast(syntax_tree.clone())?;
+            Some((module.as_str(), ItemTree::lower(&program, base_db::input::FileId(0))))
+        })
+        .collect::<Vec<_>>();
+
+    let suggestions = hir::name_resolution::suggest_imports(
+        &item_tree,
+        name,
+        module_trees.iter().map(|(module, tree)| (*module, tree)),
+    );
+    let preferred = suggestions.len() == 1;
+    suggestions
+        .into_iter()
+        .map(|suggestion| {
+            let (offset, text) = suggestion.insertion(source, &item_tree);
+            let edit = ram_syntax::edit::TextEdit { range: offset..offset, new_text: text };
+            let mut action = quick_fix(
+                uri,
+                converter,
+                diagnostic,
+                format!("Add `{}`", suggestion.use_stmt()),
+                vec![edit],
+            );
+            action.is_preferred = Some(preferred);
+            action
+        })
+        .collect()
+}
+
+/// An edit suggested by a diagnostic, stored in the `data` field of the LSP diagnostic
+#[derive(Debug, Serialize, Deserialize)]
+struct SuggestedEdit {
+    /// The range to replace
+    range: Range,
+    /// The replacement text
+    replacement: String,
+    /// Whether the edit can be applied without review
+    preferred: bool,
+}
+
+/// Build the quick fixes for the edits suggested by an LSP diagnostic
+fn suggested_fixes_for_diagnostic(
+    uri: &Url,
+    diagnostic: &tower_lsp::lsp_types::Diagnostic,
+) -> Vec<CodeAction> {
+    let Some(edits) = diagnostic
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<Vec<SuggestedEdit>>(data).ok())
+    else {
+        return Vec::new();
+    };
+
+    edits
+        .into_iter()
+        .map(|edit| {
+            let title = if edit.replacement.is_empty() {
+                "Remove this".to_string()
+            } else {
+                format!("Replace with '{}'", edit.replacement)
+            };
+            let text_edit = TextEdit { range: edit.range, new_text: edit.replacement };
+
+            CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some([(uri.clone(), vec![text_edit])].into_iter().collect()),
+                    ..Default::default()
+                }),
+                is_preferred: Some(edit.preferred),
+                ..Default::default()
+            }
+        })
+        .collect()
 }
 
 /// Convert a diagnostic to an LSP diagnostic
+///
+/// The diagnostic is reported in the document `uri`, which is the main file of `sources`.
+/// Its positions are computed by `converter`.
 fn convert_diagnostic_to_lsp(
-    source: &str,
+    uri: &Url,
+    sources: &SourceMap,
+    converter: &PositionConverter,
     diagnostic: &Diagnostic,
 ) -> tower_lsp::lsp_types::Diagnostic {
     // Get the primary span
     let primary_span = diagnostic.labeled_spans.first().cloned().unwrap_or((0..0, "".to_string()));
 
-    let file_text = source;
-
     // Convert the span to an LSP range
-    let range = Range {
-        start: position_at_offset(file_text, primary_span.0.start),
-        end: position_at_offset(file_text, primary_span.0.end),
-    };
+    let range = converter.range(primary_span.0);
 
     // Convert the diagnostic kind to an LSP severity
     let severity = match diagnostic.kind {
@@ -457,29 +1978,25 @@ fn convert_diagnostic_to_lsp(
         DiagnosticKind::Custom(_) => Some(DiagnosticSeverity::ERROR),
     };
 
-    // Create related information for secondary spans
-    let related_information = if diagnostic.labeled_spans.len() > 1 {
-        let related_info = diagnostic
-            .labeled_spans
-            .iter()
-            .skip(1)
-            .map(|(span, label)| DiagnosticRelatedInformation {
-                location: Location {
-                    uri: Url::parse("file:///")
-                        .unwrap_or_else(|_| Url::parse("about:blank").unwrap()),
-                    range: Range {
-                        start: position_at_offset(file_text, span.start),
-                        end: position_at_offset(file_text, span.end),
-                    },
-                },
-                message: label.clone(),
-            })
-            .collect::<Vec<_>>();
-
-        if !related_info.is_empty() { Some(related_info) } else { None }
-    } else {
-        None
-    };
+    // Create related information for secondary spans and spans in other files
+    let secondary =
+        diagnostic.labeled_spans.iter().skip(1).map(|(span, label)| DiagnosticRelatedInformation {
+            location: Location { uri: uri.clone(), range: converter.range(span.clone()) },
+            message: label.clone(),
+        });
+    let external = diagnostic.external_spans.iter().filter_map(|span| {
+        let uri = Url::parse(&span.file).ok()?;
+        // Without the text of the file, point at its start
+        let range = sources.get(&span.file).map_or_else(Range::default, |file| {
+            PositionConverter::for_text(&file.text, converter.encoding()).range(span.span.clone())
+        });
+        Some(DiagnosticRelatedInformation {
+            location: Location { uri, range },
+            message: span.label.clone(),
+        })
+    });
+    let related_info = secondary.chain(external).collect::<Vec<_>>();
+    let related_information = if related_info.is_empty() { None } else { Some(related_info) };
 
     // Create the LSP diagnostic
     let mut message = diagnostic.message.clone();
@@ -500,21 +2017,90 @@ fn convert_diagnostic_to_lsp(
         range,
         severity,
         code: diagnostic.code.clone().map(NumberOrString::String),
-        code_description: None,
+        code_description: diagnostic
+            .code
+            .as_deref()
+            .and_then(ram_diagnostics::codes::lookup)
+            .and_then(|code| Url::parse(&code.url()).ok())
+            .map(|href| CodeDescription { href }),
         source: Some("ram-lsp".to_string()),
         message,
         related_information,
-        tags: None,
-        data: None,
+        tags: match diagnostic.code.as_deref() {
+            Some(
+                hir_analysis::analyzers::unused_labels::UNUSED_LABEL_CODE
+                | hir_analysis::analyzers::dominance::DEAD_CODE_CODE
+                | hir_analysis::analyzers::scheduling::JUMP_TO_NEXT_CODE,
+            ) => Some(vec![DiagnosticTag::UNNECESSARY]),
+            _ => None,
+        },
+        data: suggested_edits(converter, diagnostic),
     }
 }
 
-/// Run the LSP server
-pub async fn run() -> Result<()> {
+/// Convert the suggestions of a diagnostic to the `data` of an LSP diagnostic
+fn suggested_edits(converter: &PositionConverter, diagnostic: &Diagnostic) -> Option<Value> {
+    if diagnostic.suggestions.is_empty() {
+        return None;
+    }
+
+    let edits = diagnostic
+        .suggestions
+        .iter()
+        .map(|suggestion| SuggestedEdit {
+            range: converter.range(suggestion.span.clone()),
+            replacement: suggestion.replacement.clone(),
+            preferred: suggestion.applicability == Applicability::MachineApplicable,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_value(edits).ok()
+}
+
+/// Run the LSP server over `transport`
+///
+/// Over stdio, the server stops when its client exits. Over TCP and
+/// WebSockets, it serves every client that connects until it is killed.
+pub async fn run(transport: Transport) -> Result<()> {
+    match transport {
+        Transport::Stdio => serve(tokio::io::stdin(), tokio::io::stdout()).await,
+        Transport::Tcp(port) => {
+            let listener = transport::listen(port).await.into_diagnostic()?;
+            loop {
+                let (stream, address) = listener.accept().await.into_diagnostic()?;
+                info!("Client connected from {}", address);
+                tokio::spawn(async move {
+                    let (input, output) = stream.into_split();
+                    serve(input, output).await
+                });
+            }
+        }
+        Transport::WebSocket(port) => {
+            let listener = transport::listen(port).await.into_diagnostic()?;
+            loop {
+                let (stream, address) = listener.accept().await.into_diagnostic()?;
+                info!("Client connected from {}", address);
+                tokio::spawn(async move {
+                    match transport::accept_websocket(stream).await {
+                        Ok((input, output)) => serve(input, output).await,
+                        Err(err) => {
+                            error!("WebSocket handshake with {} failed: {}", address, err);
+                            Ok(())
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Serve a client over its input and output until it exits
+async fn serve(
+    mut input: impl AsyncRead + Unpin,
+    mut output: impl AsyncWrite + Unpin,
+) -> Result<()> {
     // Use a loop to handle server restarts
     loop {
         info!("Starting RAM Language Server");
-        let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
 
         // Create the database
         let db = Arc::new(RwLock::new(LspDatabase::new()));
@@ -523,14 +2109,23 @@ pub async fn run() -> Result<()> {
         let should_restart = Arc::new(Mutex::new(false));
 
         // Create the service
-        let (service, socket) = LspService::new(|client| Backend {
+        let (service, socket) = LspService::build(|client| Backend {
             client,
             db: Arc::clone(&db),
             should_restart: Arc::clone(&should_restart),
-        });
+            position_encoding: Arc::default(),
+            workspace_root: Arc::default(),
+            client_capabilities: Arc::default(),
+            progress: Arc::default(),
+            status: Arc::default(),
+            runs: Arc::default(),
+            test_results: Arc::default(),
+        })
+        .custom_method("window/workDoneProgress/cancel", Backend::work_done_progress_cancel)
+        .finish();
 
         // Create the server
-        let server = Server::new(stdin, stdout, socket);
+        let server = Server::new(&mut input, &mut output, socket);
 
         // Run the server
         server.serve(service).await;
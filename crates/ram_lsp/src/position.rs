@@ -0,0 +1,127 @@
+//! Conversion between byte offsets and LSP positions.
+//!
+//! The server works with byte offsets, while LSP positions count columns in
+//! the code units of the encoding negotiated with the client during
+//! initialization. UTF-16 is used when the client does not offer one.
+
+use std::sync::Arc;
+
+use base_db::{LineCol, LineIndex, WideEncoding, WideLineCol};
+use tower_lsp::lsp_types::{self, ClientCapabilities, Position, PositionEncodingKind};
+
+/// The encoding the columns of LSP positions are counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// Columns count UTF-8 bytes
+    Utf8,
+    /// Columns count the code units of a wide encoding
+    Wide(WideEncoding),
+}
+
+impl Default for PositionEncoding {
+    /// UTF-16 is the encoding every client has to support
+    fn default() -> Self {
+        PositionEncoding::Wide(WideEncoding::Utf16)
+    }
+}
+
+impl PositionEncoding {
+    /// Pick the encoding the client prefers among those it offers
+    ///
+    /// Clients list their encodings in order of preference. Falls back to
+    /// UTF-16 if the client offers none.
+    pub fn negotiate(capabilities: &ClientCapabilities) -> Self {
+        capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_ref())
+            .and_then(|kinds| kinds.iter().find_map(Self::from_kind))
+            .unwrap_or_default()
+    }
+
+    /// Get the encoding for an LSP position encoding kind, if it is supported
+    pub fn from_kind(kind: &PositionEncodingKind) -> Option<Self> {
+        if *kind == PositionEncodingKind::UTF8 {
+            Some(PositionEncoding::Utf8)
+        } else if *kind == PositionEncodingKind::UTF16 {
+            Some(PositionEncoding::Wide(WideEncoding::Utf16))
+        } else if *kind == PositionEncodingKind::UTF32 {
+            Some(PositionEncoding::Wide(WideEncoding::Utf32))
+        } else {
+            None
+        }
+    }
+
+    /// Get the LSP position encoding kind announced to the client
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Wide(WideEncoding::Utf16) => PositionEncodingKind::UTF16,
+            PositionEncoding::Wide(WideEncoding::Utf32) => PositionEncodingKind::UTF32,
+        }
+    }
+}
+
+/// Converts between byte offsets in a document and LSP positions
+#[derive(Debug, Clone)]
+pub struct PositionConverter {
+    /// The line index of the document
+    line_index: Arc<LineIndex>,
+    /// The negotiated position encoding
+    encoding: PositionEncoding,
+}
+
+impl PositionConverter {
+    /// Create a converter for the document indexed by `line_index`
+    pub fn new(line_index: Arc<LineIndex>, encoding: PositionEncoding) -> Self {
+        Self { line_index, encoding }
+    }
+
+    /// Create a converter for a document that has not been indexed yet
+    pub fn for_text(text: &str, encoding: PositionEncoding) -> Self {
+        Self::new(Arc::new(LineIndex::new(text)), encoding)
+    }
+
+    /// Get the negotiated position encoding
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /// Convert an LSP position to a byte offset
+    ///
+    /// Positions past the end of a line refer to the end of that line, and
+    /// positions past the last line refer to the end of the document.
+    pub fn offset(&self, position: Position) -> usize {
+        let line_col = match self.encoding {
+            PositionEncoding::Utf8 => {
+                Some(LineCol { line: position.line, col: position.character })
+            }
+            PositionEncoding::Wide(encoding) => self
+                .line_index
+                .to_utf8(encoding, WideLineCol { line: position.line, col: position.character }),
+        };
+        line_col
+            .and_then(|line_col| self.line_index.offset(line_col))
+            .unwrap_or(self.line_index.len())
+    }
+
+    /// Convert a byte offset to an LSP position
+    pub fn position(&self, offset: usize) -> Position {
+        let line_col = self.line_index.line_col(offset);
+        match self.encoding {
+            PositionEncoding::Utf8 => Position::new(line_col.line, line_col.col),
+            PositionEncoding::Wide(encoding) => {
+                let wide = self
+                    .line_index
+                    .to_wide(encoding, line_col)
+                    .unwrap_or(WideLineCol { line: line_col.line, col: line_col.col });
+                Position::new(wide.line, wide.col)
+            }
+        }
+    }
+
+    /// Convert a byte range to an LSP range
+    pub fn range(&self, span: std::ops::Range<usize>) -> lsp_types::Range {
+        lsp_types::Range { start: self.position(span.start), end: self.position(span.end) }
+    }
+}
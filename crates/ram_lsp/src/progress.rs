@@ -0,0 +1,131 @@
+//! Work done progress reported to the client.
+//!
+//! Analyzing many files at once, when the workspace is indexed or the
+//! diagnostic configuration changes, is reported with `window/workDoneProgress`
+//! so editors can show what the server is doing. The client can cancel the
+//! work from its progress indicator with `window/workDoneProgress/cancel`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use tokio::sync::mpsc;
+use tower_lsp::Client;
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
+};
+
+use crate::db::AnalysisStage;
+
+/// The number of progress tokens created so far, used to make them unique
+static NEXT_TOKEN: AtomicU32 = AtomicU32::new(0);
+
+/// A work done progress shown by the client
+///
+/// Reports are sent in order by a background task, so they can be made from
+/// any thread, including the blocking threads analyses run on.
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    /// The token identifying the progress
+    token: ProgressToken,
+    /// The channel the reports are sent to the client through
+    sender: mpsc::UnboundedSender<WorkDoneProgress>,
+    /// Set when the client cancels the progress
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ProgressReporter {
+    /// Ask the client to show a new progress and begin it
+    ///
+    /// Returns `None` if the client refused to create the progress.
+    pub async fn begin(client: &Client, title: impl Into<String>) -> Option<Self> {
+        let id = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("ram/progress/{id}"));
+        let params = WorkDoneProgressCreateParams { token: token.clone() };
+        if let Err(err) = client.send_request::<WorkDoneProgressCreate>(params).await {
+            tracing::debug!("Client refused to create a progress: {}", err);
+            return None;
+        }
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let client = client.clone();
+        let progress_token = token.clone();
+        tokio::spawn(async move {
+            while let Some(value) = receiver.recv().await {
+                let params = ProgressParams {
+                    token: progress_token.clone(),
+                    value: ProgressParamsValue::WorkDone(value),
+                };
+                client.send_notification::<Progress>(params).await;
+            }
+        });
+
+        let reporter = Self::new(token, sender);
+        reporter.send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.into(),
+            cancellable: Some(true),
+            message: None,
+            percentage: Some(0),
+        }));
+        Some(reporter)
+    }
+
+    /// Create a reporter sending its reports through `sender`
+    pub(crate) fn new(
+        token: ProgressToken,
+        sender: mpsc::UnboundedSender<WorkDoneProgress>,
+    ) -> Self {
+        Self { token, sender, cancelled: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// Get the token identifying the progress
+    pub fn token(&self) -> &ProgressToken {
+        &self.token
+    }
+
+    /// Report a message and how much of the work is done, in percent
+    pub fn report(&self, message: impl Into<String>, percentage: u32) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(true),
+            message: Some(message.into()),
+            percentage: Some(percentage.min(100)),
+        }));
+    }
+
+    /// Report that a file reached a stage of its analysis
+    ///
+    /// `index` is the position of the file among the `count` files analyzed.
+    pub fn report_stage(&self, stage: AnalysisStage, file_name: &str, index: usize, count: usize) {
+        let stages = AnalysisStage::ALL.len();
+        let done = index * stages + stage as usize;
+        let percentage = done * 100 / (count * stages).max(1);
+        self.report(
+            format!("{} ({}/{}): {}", file_name, index + 1, count, stage.name()),
+            u32::try_from(percentage).unwrap_or(100),
+        );
+    }
+
+    /// Mark the progress as cancelled by the client
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Check whether the client cancelled the progress
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// End the progress, with a final message
+    pub fn end(self, message: impl Into<String>) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd { message: Some(message.into()) }));
+    }
+
+    /// Send a progress value to the client
+    fn send(&self, value: WorkDoneProgress) {
+        // The forwarding task only stops once every reporter is dropped
+        self.sender.send(value).ok();
+    }
+}
@@ -0,0 +1,81 @@
+//! Selection ranges used to expand the selection.
+//!
+//! Editors grow the selection through the syntax tree: from the value of an
+//! operand to the operand, its instruction and its statement, then to the
+//! statements under the same label and finally to the whole file.
+
+use std::ops::Range;
+
+use ram_syntax::cursor::TokenCursor;
+use ram_syntax::{AstNode, Program, ResolvedNode, SyntaxKind, cstree};
+
+/// Get the ranges the selection expands through at `offset`, innermost first
+///
+/// Returns no ranges if the offset is outside of the file.
+pub fn selection_ranges(syntax_tree: &ResolvedNode, offset: usize) -> Vec<Range<usize>> {
+    let file = range_of(syntax_tree);
+    if !(file.start..=file.end).contains(&offset) {
+        return Vec::new();
+    }
+
+    let mut ranges = Vec::new();
+    if let Some(token) =
+        TokenCursor::new(syntax_tree).token_at(offset).map(|token| token.range.clone())
+    {
+        ranges.push(token.clone());
+
+        // Nodes come in preorder, so ancestors of the token come outermost first
+        let mut nodes = syntax_tree
+            .descendants()
+            .filter(|node| node.kind() != SyntaxKind::ROOT)
+            .map(range_of)
+            .filter(|range| range.start <= token.start && token.end <= range.end)
+            .collect::<Vec<_>>();
+        nodes.reverse();
+        ranges.extend(nodes);
+
+        if let Some(block) =
+            Program::cast(syntax_tree.clone()).and_then(|program| label_block(&program, &token))
+        {
+            ranges.push(block);
+        }
+    }
+    ranges.push(file);
+
+    ranges.dedup();
+    ranges
+}
+
+/// Get the range of the statements under the label the token belongs to
+///
+/// The block starts at the statement defining the label and ends before the
+/// next label definition.
+fn label_block(program: &Program, token: &Range<usize>) -> Option<Range<usize>> {
+    let mut block: Option<Range<usize>> = None;
+    let mut contains_token = false;
+
+    for statement in program.statements() {
+        let range = range_of(statement.syntax());
+        if statement.label_def().is_some() {
+            if contains_token {
+                break;
+            }
+            block = Some(range.clone());
+        } else if let Some(block) = &mut block {
+            block.end = range.end;
+        }
+        contains_token |= range.start <= token.start && token.end <= range.end;
+    }
+
+    block.filter(|_| contains_token)
+}
+
+/// Get the range of a node
+fn range_of(node: &ResolvedNode) -> Range<usize> {
+    to_range(node.text_range())
+}
+
+/// Convert a range of the syntax tree to a range of offsets
+fn to_range(range: cstree::text::TextRange) -> Range<usize> {
+    usize::from(range.start())..usize::from(range.end())
+}
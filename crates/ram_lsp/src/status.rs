@@ -0,0 +1,167 @@
+//! Status of the server reported to the client.
+//!
+//! Clients that set `experimental.serverStatusNotification` in their
+//! capabilities receive a `ram/serverStatus` notification whenever the status
+//! changes: whether the workspace is being indexed, how many files are known
+//! and how many errors they have, and what went wrong loading the project
+//! manifest or plugins. The `ram.server.showStatus` command returns the same
+//! status as a Markdown document for the client to show, along with how many
+//! results the database keeps for each query and how often they are reused.
+
+use base_db::QueryStats;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::ClientCapabilities;
+use tower_lsp::lsp_types::notification::Notification;
+
+use crate::db::LspDatabase;
+
+/// The command that returns the status document
+pub const SHOW_STATUS_COMMAND: &str = "ram.server.showStatus";
+
+/// The experimental capability clients set to receive status notifications
+pub const STATUS_CAPABILITY: &str = "serverStatusNotification";
+
+/// The `ram/serverStatus` notification
+#[derive(Debug)]
+pub enum ServerStatusNotification {}
+
+impl Notification for ServerStatusNotification {
+    type Params = ServerStatusParams;
+    const METHOD: &'static str = "ram/serverStatus";
+}
+
+/// How well the server is working
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Health {
+    /// Everything works
+    Ok,
+    /// The server works, but not as configured
+    Warning,
+    /// Some programs can not be analyzed correctly
+    Error,
+}
+
+/// The parameters of the `ram/serverStatus` notification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatusParams {
+    /// How well the server is working
+    pub health: Health,
+    /// Whether files of the workspace are being analyzed in the background
+    pub indexing: bool,
+    /// The number of files known to the server
+    pub files: usize,
+    /// The number of files with errors
+    pub files_with_errors: usize,
+    /// The number of errors in all files
+    pub errors: usize,
+    /// The plugins that could not be loaded, with the reason
+    pub plugin_failures: Vec<String>,
+    /// The reason the project manifest could not be loaded, if it could not
+    pub config_error: Option<String>,
+}
+
+/// The parts of the status the database does not know about
+#[derive(Debug, Default)]
+pub struct StatusState {
+    /// The number of workspace analyses running
+    pub indexing: usize,
+    /// The reason the project manifest could not be loaded
+    pub config_error: Option<String>,
+    /// The plugins that could not be loaded, with the reason
+    pub plugin_failures: Vec<String>,
+    /// The status last sent to the client
+    pub last_sent: Option<ServerStatusParams>,
+}
+
+impl ServerStatusParams {
+    /// Collect the status of the server
+    pub fn new(db: &LspDatabase, state: &StatusState) -> Self {
+        let error_counts = db
+            .analyses()
+            .iter()
+            .map(|analysis| analysis.diagnostics.error_count())
+            .filter(|&errors| errors > 0)
+            .collect::<Vec<_>>();
+
+        let health = if !state.plugin_failures.is_empty() {
+            Health::Error
+        } else if state.config_error.is_some() {
+            Health::Warning
+        } else {
+            Health::Ok
+        };
+
+        Self {
+            health,
+            indexing: state.indexing > 0,
+            files: db.file_count(),
+            files_with_errors: error_counts.len(),
+            errors: error_counts.iter().sum(),
+            plugin_failures: state.plugin_failures.clone(),
+            config_error: state.config_error.clone(),
+        }
+    }
+
+    /// Render the status as a Markdown document
+    pub fn to_markdown(&self) -> String {
+        let health = match self.health {
+            Health::Ok => "ok",
+            Health::Warning => "warning",
+            Health::Error => "error",
+        };
+        let mut document = format!(
+            "# RAM Language Server\n\n\
+             - Version: {}\n\
+             - Health: {}\n\
+             - Indexing: {}\n\
+             - Files: {}\n\
+             - Errors: {} in {} files\n",
+            crate::VERSION,
+            health,
+            if self.indexing { "yes" } else { "no" },
+            self.files,
+            self.errors,
+            self.files_with_errors,
+        );
+
+        if let Some(error) = &self.config_error {
+            document.push_str(&format!("\n## Project manifest\n\n{}\n", error));
+        }
+        if !self.plugin_failures.is_empty() {
+            document.push_str("\n## Plugins that failed to load\n\n");
+            for failure in &self.plugin_failures {
+                document.push_str(&format!("- {}\n", failure));
+            }
+        }
+        document
+    }
+}
+
+/// Render the statistics of the queries of the database as a Markdown section
+pub fn query_stats_markdown(stats: &[QueryStats]) -> String {
+    let mut section = String::from("\n## Queries\n\n");
+    for stats in stats {
+        let hit_rate = match stats.hit_rate() {
+            Some(rate) => format!("{:.1}%", rate * 100.0),
+            None => "n/a".to_string(),
+        };
+        section.push_str(&format!(
+            "- {}: {} memos, {} hits, {} misses ({hit_rate} hit rate)\n",
+            stats.query, stats.memos, stats.hits, stats.misses
+        ));
+    }
+    section
+}
+
+/// Check whether the client wants `ram/serverStatus` notifications
+pub fn client_supports_status(capabilities: &ClientCapabilities) -> bool {
+    capabilities
+        .experimental
+        .as_ref()
+        .and_then(|experimental| experimental.get(STATUS_CAPABILITY))
+        .and_then(Value::as_bool)
+        == Some(true)
+}
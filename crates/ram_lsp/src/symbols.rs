@@ -0,0 +1,122 @@
+//! Document symbols.
+//!
+//! Every label of a file is a symbol, spanning its statement with the
+//! comments that document it. Under the sections convention a label also
+//! spans the section it starts, up to the next label, and the metrics of the
+//! section are shown as the detail of its symbol.
+//!
+//! Workspace symbols are the labels and modules of every file of the
+//! workspace whose names fuzzily match a query.
+
+use std::ops::Range;
+
+use hir_analysis::Section;
+use ram_syntax::{AstNode, Program, ResolvedNode, cstree};
+
+/// A label of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSymbol {
+    /// The name of the label
+    pub name: String,
+    /// The range of the label with its documentation and section
+    pub range: Range<usize>,
+    /// The range of the label definition itself
+    pub selection_range: Range<usize>,
+    /// The metrics of the section the label starts, if measured
+    pub detail: Option<String>,
+}
+
+/// Get the labels of a file, in order
+///
+/// `sections` are the measured sections of the file, if the sections
+/// convention is on.
+pub fn document_symbols(
+    syntax_tree: &ResolvedNode,
+    sections: Option<&[Section]>,
+) -> Vec<LabelSymbol> {
+    let Some(program) = Program::cast(syntax_tree.clone()) else {
+        return Vec::new();
+    };
+
+    program
+        .statements()
+        .filter_map(|statement| {
+            let label = statement.label_def()?;
+            let name = label.name()?;
+            let mut range = to_range(statement.full_range());
+            let section = sections
+                .into_iter()
+                .flatten()
+                .find(|section| section.name.as_deref() == Some(name.as_str()));
+            if let Some(section) = section {
+                range.end = range.end.max(section.span.end);
+            }
+            Some(LabelSymbol {
+                name,
+                range,
+                selection_range: to_range(label.syntax().text_range()),
+                detail: section.map(Section::summary),
+            })
+        })
+        .collect()
+}
+
+/// A label or module of the workspace that matches a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolMatch {
+    /// The name of the label or module
+    pub name: String,
+    /// Whether the symbol is a module rather than a label
+    pub is_module: bool,
+    /// The module the symbol is defined in, named after its file
+    pub module: String,
+    /// The range of the name of the label, or the start of the module
+    pub range: Range<usize>,
+    /// How well the name matches, lower is better
+    pub score: usize,
+}
+
+/// Get the module of a file and its labels that match a query
+pub fn workspace_symbols(
+    module: &str,
+    syntax_tree: &ResolvedNode,
+    query: &str,
+) -> Vec<SymbolMatch> {
+    let module_match = fuzzy_score(query, module).map(|score| SymbolMatch {
+        name: module.to_string(),
+        is_module: true,
+        module: module.to_string(),
+        range: 0..0,
+        score,
+    });
+    let label_matches = document_symbols(syntax_tree, None).into_iter().filter_map(|label| {
+        Some(SymbolMatch {
+            score: fuzzy_score(query, &label.name)?,
+            name: label.name,
+            is_module: false,
+            module: module.to_string(),
+            range: label.selection_range,
+        })
+    });
+    module_match.into_iter().chain(label_matches).collect()
+}
+
+/// Match a query against a name, fuzzily
+///
+/// The characters of the query must appear in the name in the same order,
+/// ignoring case. The score counts the characters skipped before and between
+/// them, so names that start with the query score 0 and come first. An empty
+/// query matches every name. Returns `None` if the name does not match.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let mut name = name.chars().flat_map(char::to_lowercase);
+    let mut score = 0;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        score += name.by_ref().position(|ch| ch == wanted)?;
+    }
+    Some(score)
+}
+
+/// Convert a range of the syntax tree to a range of offsets
+fn to_range(range: cstree::text::TextRange) -> Range<usize> {
+    usize::from(range.start())..usize::from(range.end())
+}
@@ -0,0 +1,246 @@
+//! Results of embedded tests shown inline in the editor.
+//!
+//! The `ram.server.runTests` command runs the tests annotated in a document,
+//! the same ones the "Run test" code lenses run, and reports whether each one
+//! passed and how long it took. Clients that set `experimental.testResults`
+//! in their capabilities receive the results in a `ram/testResults`
+//! notification, and every client gets them as advice diagnostics on the
+//! lines of the test annotations, until the document changes.
+//!
+//! Runs go on in the background like heatmap runs, and are stopped the same
+//! way with the `ram.server.stopRun` command.
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ram_diagnostics::Diagnostic;
+use ram_vm::grader::{
+    CaseResult, CaseStatus, GradingSpec, Limits, TestCase, grade_with_cancellation,
+};
+use ram_vm::{CancelFlag, Cancellation};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::{ClientCapabilities, Range as LspRange};
+use url::Url;
+
+use crate::db::FileAnalysis;
+use crate::heatmap::{DEFAULT_MAX_STEPS, StopOnDrop};
+use crate::lenses::{EmbeddedTest, LensAction, code_lenses};
+
+/// The command that runs the embedded tests of a document
+pub const RUN_TESTS_COMMAND: &str = "ram.server.runTests";
+
+/// The experimental capability clients set to receive test result notifications
+pub const TEST_RESULTS_CAPABILITY: &str = "testResults";
+
+/// The code of the diagnostics showing the result of a test
+pub const TEST_RESULT_CODE: &str = "test-result";
+
+/// The `ram/testResults` notification
+#[derive(Debug)]
+pub enum TestResultsNotification {}
+
+impl Notification for TestResultsNotification {
+    type Params = TestResultsParams;
+    const METHOD: &'static str = "ram/testResults";
+}
+
+/// The argument of the `ram.server.runTests` command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunTestsArgs {
+    /// The document whose tests to run
+    pub uri: Url,
+    /// The maximum number of instructions each test executes
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+}
+
+/// How an embedded test ran, with the byte range of its annotation
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    /// The doc comment holding the test annotation
+    pub range: Range<usize>,
+    /// The test that ran
+    pub test: EmbeddedTest,
+    /// How the test ended and what the program wrote
+    pub result: CaseResult,
+    /// How long the test took to run
+    pub duration: Duration,
+}
+
+impl TestOutcome {
+    /// Check whether the test passed
+    pub fn passed(&self) -> bool {
+        self.result.passed()
+    }
+
+    /// Describe how the test ended, as shown next to its annotation
+    pub fn message(&self) -> String {
+        let duration = format_duration(self.duration);
+        match &self.result.status {
+            CaseStatus::Passed => format!("Test passed in {}", duration),
+            CaseStatus::WrongOutput => format!(
+                "Test failed in {}: expected {:?}, got {:?}",
+                duration, self.test.output, self.result.output
+            ),
+            CaseStatus::StepLimitExceeded => format!(
+                "Test failed in {}: the program did not halt within {} steps",
+                duration, self.result.steps
+            ),
+            CaseStatus::RuntimeError(error) => format!("Test failed in {}: {}", duration, error),
+            CaseStatus::Cancelled => "Test cancelled".to_string(),
+        }
+    }
+
+    /// Get the diagnostic showing the outcome on the lines of the annotation
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let help = format!(
+            "Ran with input {:?} for {} steps, writing {:?}",
+            self.test.input, self.result.steps, self.result.output
+        );
+        Diagnostic::advice(self.message(), help, self.range.clone()).with_code(TEST_RESULT_CODE)
+    }
+}
+
+/// The outcomes of the tests of a document, for the text they ran on
+#[derive(Debug, Clone)]
+pub struct TestRun {
+    /// The text of the document the tests ran on
+    pub text: String,
+    /// The outcomes, in the order of the tests in the document
+    pub outcomes: Vec<TestOutcome>,
+    /// The number of runs of the document before this one, to tell the
+    /// diagnostics of each run apart
+    pub generation: u64,
+}
+
+impl TestRun {
+    /// Get the diagnostics showing the outcomes of an analysis of `text`
+    ///
+    /// Returns no diagnostics if the document changed since the tests ran.
+    pub fn diagnostics(&self, text: &str) -> Vec<Diagnostic> {
+        if self.text != text {
+            return Vec::new();
+        }
+        self.outcomes.iter().map(TestOutcome::to_diagnostic).collect()
+    }
+}
+
+/// The result of an embedded test, as sent to the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResult {
+    /// The doc comment holding the test annotation
+    pub range: LspRange,
+    /// Whether the test passed
+    pub passed: bool,
+    /// Describes how the test ended
+    pub message: String,
+    /// How long the test took to run, in milliseconds
+    pub duration_ms: f64,
+    /// The values the test expects the program to write
+    pub expected: Vec<i64>,
+    /// The values the program wrote
+    pub output: Vec<i64>,
+}
+
+/// The parameters of the `ram/testResults` notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResultsParams {
+    /// The document the tests are in
+    pub uri: Url,
+    /// The version of the document the tests ran on
+    pub version: Option<i32>,
+    /// The results, in the order of the tests in the document
+    pub results: Vec<TestResult>,
+}
+
+/// Run the embedded tests of the program of an analysis
+///
+/// Each test runs on a fresh machine. Once `cancellation` says so, the test
+/// running stops and the ones after it are reported as cancelled. Fails if
+/// the program has errors, as it could not be compiled.
+pub fn run_tests(
+    analysis: &FileAnalysis,
+    max_steps: Option<usize>,
+    cancellation: Arc<dyn Cancellation>,
+) -> Result<Vec<TestOutcome>, String> {
+    let body = match &analysis.body {
+        Some(body) if analysis.diagnostics.error_count() == 0 => body,
+        _ => return Err("The program has errors, fix them to run its tests".to_string()),
+    };
+    let db = Arc::new(ram_vm::VmDatabaseImpl::new());
+    let program = ram_vm::Program::from_hir(body, db.as_ref()).map_err(|e| e.to_string())?;
+    let limits = Limits { max_steps: max_steps.unwrap_or(DEFAULT_MAX_STEPS), ..Limits::default() };
+
+    let mut outcomes = Vec::new();
+    for (range, action) in code_lenses(&analysis.syntax_tree) {
+        let LensAction::Test(test) = action else {
+            continue;
+        };
+        let case = TestCase {
+            name: format!("test at {}", range.start),
+            input: test.input.clone(),
+            output: test.output.clone(),
+        };
+        let spec = GradingSpec { cases: vec![case], limits: limits.clone(), ..Default::default() };
+
+        let start = Instant::now();
+        let report = grade_with_cancellation(&program, &db, &spec, Arc::clone(&cancellation));
+        let duration = start.elapsed();
+        let Some(result) = report.cases.into_iter().next() else {
+            continue;
+        };
+        outcomes.push(TestOutcome { range, test, result, duration });
+    }
+    Ok(outcomes)
+}
+
+/// Run the embedded tests of the program of an analysis on the blocking
+/// thread pool
+///
+/// Setting `flag` stops the run, and so does dropping the returned future, as
+/// tower-lsp does when the client cancels the request.
+pub async fn spawn_tests(
+    analysis: Arc<FileAnalysis>,
+    max_steps: Option<usize>,
+    flag: CancelFlag,
+) -> Result<Vec<TestOutcome>, String> {
+    let guard = StopOnDrop(flag.clone());
+    let result =
+        tokio::task::spawn_blocking(move || run_tests(&analysis, max_steps, Arc::new(flag))).await;
+    drop(guard);
+
+    match result {
+        Ok(result) => result,
+        // Re-raise panics of the run in the handler
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Check whether the client wants `ram/testResults` notifications
+pub fn client_supports_test_results(capabilities: &ClientCapabilities) -> bool {
+    capabilities
+        .experimental
+        .as_ref()
+        .and_then(|experimental| experimental.get(TEST_RESULTS_CAPABILITY))
+        .and_then(Value::as_bool)
+        == Some(true)
+}
+
+/// Format a duration for people, in the unit that suits it best
+fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    if micros < 1000 {
+        format!("{} µs", micros)
+    } else if micros < 1_000_000 {
+        format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
@@ -0,0 +1,1077 @@
+//! Tests for position conversion, background work, progress reporting,
+//! instruction documentation, completion contexts, syntax edits, quick fixes,
+//! selection and folding ranges, document and workspace symbols, on-type
+//! formatting, server status, execution heatmaps, code lenses, embedded test
+//! results, semantic token modifiers, control flow highlights and
+//! decorations, file overlays and the framing of messages sent over WebSockets
+
+use std::sync::Arc;
+
+use base_db::WideEncoding;
+use hir_analysis::analyzers::dominance::DEAD_CODE_CODE;
+use hir_analysis::analyzers::instruction_validation::UNDEFINED_LABEL_CODE;
+use hir_analysis::analyzers::scheduling::{JUMP_TO_NEXT_CODE, REDUNDANT_JUMP_CODE};
+use ram_core::InstructionKind;
+use ram_diagnostics::{Diagnostic, DiagnosticKind, SourceMap, codes};
+use ram_ide::completion::{
+    CompletionContext, completion_context, defined_labels, imported_modules, loaded_addresses,
+    memory_addresses,
+};
+use ram_ide::docs::{instruction_at, instruction_documentation};
+use ram_syntax::edit::{
+    append_instruction, remove_label, remove_operand, remove_statement, rename_label,
+    replace_operand, statement_lines,
+};
+use ram_syntax::{AstNode, Program, make};
+use ram_vm::CancelFlag;
+use tokio::sync::mpsc;
+use tower_lsp::lsp_types::{
+    ClientCapabilities, DocumentHighlightKind, GeneralClientCapabilities, NumberOrString, Position,
+    PositionEncodingKind, SemanticTokenModifier, Url, WorkDoneProgress,
+};
+use vfs::ChangeKind;
+
+use crate::cancellation::{self, Cancelled, Revision};
+use crate::db::{AnalysisStage, LspDatabase, analyze_file, parse_file};
+use crate::decorations::{FlowKind, client_supports_control_flow_decorations, control_flow_edges};
+use crate::folding::{FoldKind, folding_ranges};
+use crate::formatting::{FormatSettings, on_type_formatting};
+use crate::heatmap::{RunHeatmapArgs, client_supports_heatmap, run_heatmap};
+use crate::highlighting::{
+    AnalysisModifiers, CONSTANT_ACCUMULATOR_MODIFIER, semantic_tokens_for_tree,
+    semantic_tokens_legend,
+};
+use crate::highlights::control_flow_highlights;
+use crate::lenses::{
+    EmbeddedTest, LensAction, LensData, RUN_TEST_COMMAND, code_lenses, parse_test_annotation,
+};
+use crate::position::{PositionConverter, PositionEncoding};
+use crate::progress::ProgressReporter;
+use crate::selection::selection_ranges;
+use crate::status::{
+    Health, ServerStatusParams, StatusState, client_supports_status, query_stats_markdown,
+};
+use crate::symbols::{document_symbols, fuzzy_score, workspace_symbols};
+use crate::test_results::{TEST_RESULT_CODE, TestRun, client_supports_test_results, run_tests};
+use crate::transport::{frame, read_message};
+use crate::{
+    apply_change, convert_diagnostic_to_lsp, import_fixes_for_diagnostic, quick_fix_for_diagnostic,
+};
+
+/// A comment with accents and an emoji, followed by an instruction
+///
+/// `é` and `à` take 2 bytes in UTF-8 and 1 code unit in UTF-16, `😀` takes
+/// 4 bytes in UTF-8 and 2 code units in UTF-16.
+const SOURCE: &str = "# déjà vu 😀\n😀 LAOD 1 # é\nHALT";
+
+/// Client capabilities offering the given position encodings
+fn capabilities(kinds: Option<Vec<PositionEncodingKind>>) -> ClientCapabilities {
+    ClientCapabilities {
+        general: Some(GeneralClientCapabilities {
+            position_encodings: kinds,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_negotiate_position_encoding() {
+    let utf16 = PositionEncoding::Wide(WideEncoding::Utf16);
+
+    assert_eq!(PositionEncoding::negotiate(&ClientCapabilities::default()), utf16);
+    assert_eq!(PositionEncoding::negotiate(&capabilities(None)), utf16);
+    assert_eq!(PositionEncoding::negotiate(&capabilities(Some(vec![]))), utf16);
+
+    // The first supported encoding in the client's order of preference wins
+    let offered = vec![PositionEncodingKind::new("latin-1"), PositionEncodingKind::UTF8];
+    assert_eq!(PositionEncoding::negotiate(&capabilities(Some(offered))), PositionEncoding::Utf8);
+    let offered = vec![PositionEncodingKind::UTF32, PositionEncodingKind::UTF8];
+    assert_eq!(
+        PositionEncoding::negotiate(&capabilities(Some(offered))),
+        PositionEncoding::Wide(WideEncoding::Utf32)
+    );
+
+    for encoding in [PositionEncoding::Utf8, utf16, PositionEncoding::Wide(WideEncoding::Utf32)] {
+        assert_eq!(PositionEncoding::from_kind(&encoding.kind()), Some(encoding));
+    }
+}
+
+#[test]
+fn test_utf16_positions() {
+    let converter = PositionConverter::for_text(SOURCE, PositionEncoding::default());
+
+    // (byte offset, line, UTF-16 column)
+    let cases = [(0, 0, 0), (5, 0, 4), (8, 0, 6), (16, 0, 12), (22, 1, 3), (26, 1, 7), (33, 1, 13)];
+    for (offset, line, character) in cases {
+        assert_eq!(converter.position(offset), Position::new(line, character), "offset {offset}");
+        assert_eq!(converter.offset(Position::new(line, character)), offset);
+    }
+}
+
+#[test]
+fn test_utf8_positions() {
+    let converter = PositionConverter::for_text(SOURCE, PositionEncoding::Utf8);
+
+    let cases = [(0, 0, 0), (5, 0, 5), (16, 0, 16), (22, 1, 5), (33, 1, 16), (34, 2, 0)];
+    for (offset, line, character) in cases {
+        assert_eq!(converter.position(offset), Position::new(line, character), "offset {offset}");
+        assert_eq!(converter.offset(Position::new(line, character)), offset);
+    }
+}
+
+#[test]
+fn test_utf32_positions() {
+    let converter =
+        PositionConverter::for_text(SOURCE, PositionEncoding::Wide(WideEncoding::Utf32));
+
+    let cases = [(0, 0, 0), (5, 0, 4), (12, 0, 10), (16, 0, 11), (22, 1, 2), (33, 1, 12)];
+    for (offset, line, character) in cases {
+        assert_eq!(converter.position(offset), Position::new(line, character), "offset {offset}");
+        assert_eq!(converter.offset(Position::new(line, character)), offset);
+    }
+}
+
+#[test]
+fn test_positions_out_of_range() {
+    let converter = PositionConverter::for_text(SOURCE, PositionEncoding::default());
+
+    // Past the end of a line
+    assert_eq!(converter.offset(Position::new(0, 100)), 16);
+    // Past the last line
+    assert_eq!(converter.offset(Position::new(10, 0)), SOURCE.len());
+    // Past the end of the document
+    assert_eq!(converter.position(1000), Position::new(2, 4));
+}
+
+#[test]
+fn test_malformed_changes() {
+    let range = |start, end| tower_lsp::lsp_types::Range::new(start, end);
+
+    // Columns inside a character are moved back to its start
+    let mut text = SOURCE.to_string();
+    assert!(apply_change(
+        &mut text,
+        range(Position::new(0, 4), Position::new(0, 14)),
+        "x",
+        PositionEncoding::Utf8
+    ));
+    assert_eq!(text, "# dx😀\n😀 LAOD 1 # é\nHALT");
+
+    // A range that ends before it starts is ignored
+    let mut text = SOURCE.to_string();
+    assert!(!apply_change(
+        &mut text,
+        range(Position::new(1, 0), Position::new(0, 2)),
+        "x",
+        PositionEncoding::default()
+    ));
+    assert_eq!(text, SOURCE);
+}
+
+#[test]
+fn test_diagnostic_range_after_emoji() {
+    let uri = Url::parse("file:///main.ram").unwrap();
+    let sources = SourceMap::single(uri.as_str(), SOURCE);
+    let diagnostic = Diagnostic::error("Unknown instruction".to_string(), String::new(), 22..26);
+
+    let expected = [
+        (PositionEncoding::Utf8, 5, 9),
+        (PositionEncoding::Wide(WideEncoding::Utf16), 3, 7),
+        (PositionEncoding::Wide(WideEncoding::Utf32), 2, 6),
+    ];
+    for (encoding, start, end) in expected {
+        let converter = PositionConverter::for_text(SOURCE, encoding);
+        let lsp_diagnostic = convert_diagnostic_to_lsp(&uri, &sources, &converter, &diagnostic);
+        assert_eq!(lsp_diagnostic.range.start, Position::new(1, start));
+        assert_eq!(lsp_diagnostic.range.end, Position::new(1, end));
+    }
+}
+
+#[test]
+fn test_new_revision_cancels_token() {
+    let revision = Revision::default();
+    let token = revision.token();
+    assert_eq!(token.check(), Ok(()));
+
+    assert_eq!(revision.bump(), token.revision() + 1);
+    assert_eq!(token.check(), Err(Cancelled));
+    assert_eq!(revision.token().check(), Ok(()));
+}
+
+#[tokio::test]
+async fn test_spawn_discards_outdated_results() {
+    let revision = Revision::default();
+    assert_eq!(cancellation::spawn(revision.token(), |_| Ok(42)).await, Ok(42));
+
+    // The inputs change while the work runs
+    let bump = revision.clone();
+    let result = cancellation::spawn(revision.token(), move |_| {
+        bump.bump();
+        Ok(42)
+    })
+    .await;
+    assert_eq!(result, Err(Cancelled));
+}
+
+#[tokio::test]
+async fn test_dropping_spawned_work_cancels_it() {
+    let revision = Revision::default();
+    let token = revision.token();
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+
+    let work = tokio::spawn(cancellation::spawn(token.clone(), move |token| {
+        started_tx.send(()).unwrap();
+        // Stand in for a long analysis that checks for cancellation
+        while !token.is_cancelled() {
+            std::thread::yield_now();
+        }
+        token.check()
+    }));
+    tokio::task::spawn_blocking(move || started_rx.recv().unwrap()).await.unwrap();
+
+    // tower-lsp drops the handler future when the client cancels a request
+    work.abort();
+    assert!(work.await.unwrap_err().is_cancelled());
+    assert!(token.is_cancelled());
+}
+
+#[test]
+fn test_progress_reports_stages() {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let progress = ProgressReporter::new(NumberOrString::Number(0), sender);
+
+    // Two files with three stages each
+    let mut reports = Vec::new();
+    for (index, file_name) in ["a.ram", "b.ram"].into_iter().enumerate() {
+        for stage in AnalysisStage::ALL {
+            progress.report_stage(stage, file_name, index, 2);
+            match receiver.try_recv().unwrap() {
+                WorkDoneProgress::Report(report) => {
+                    reports.push((report.message.unwrap(), report.percentage.unwrap()));
+                }
+                other => panic!("expected a report, got {other:?}"),
+            }
+        }
+    }
+    assert_eq!(reports[0], ("a.ram (1/2): parsing".to_string(), 0));
+    assert_eq!(reports[2], ("a.ram (1/2): running analysis passes".to_string(), 33));
+    assert_eq!(reports[4], ("b.ram (2/2): building item tree".to_string(), 66));
+
+    assert!(!progress.is_cancelled());
+    progress.clone().cancel();
+    assert!(progress.is_cancelled());
+
+    progress.end("Done");
+    assert!(matches!(receiver.try_recv(), Ok(WorkDoneProgress::End(_))));
+}
+
+#[test]
+fn test_analysis_of_outdated_inputs_is_not_current() {
+    let mut db = LspDatabase::new();
+    let uri = Url::parse("file:///main.ram").unwrap();
+    let file_id = db.add_file(uri.clone(), "LOAD 1\nHALT\n", Some(1));
+
+    let input = db.analysis_input(file_id).unwrap();
+    let token = db.cancellation_token();
+    let analysis = analyze_file(&input, &token, |_| {}).unwrap();
+    assert_eq!(analysis.version, Some(1));
+    assert!(db.store_analysis(file_id, analysis, &token));
+    let result_id = db.current_analysis(file_id).unwrap().result_id();
+
+    // An edit outdates the stored analysis and cancels the analyses in flight
+    db.add_file(uri.clone(), "LOAD 2\nHALT\n", Some(2));
+    assert!(db.current_analysis(file_id).is_none());
+    assert_eq!(db.analysis(file_id).unwrap().version, Some(1));
+    assert_eq!(analyze_file(&input, &token, |_| {}).unwrap_err(), Cancelled);
+
+    let input = db.analysis_input(file_id).unwrap();
+    let token = db.cancellation_token();
+    let analysis = analyze_file(&input, &token, |_| {}).unwrap();
+    assert!(db.store_analysis(file_id, analysis, &token));
+    let analysis = db.current_analysis(file_id).unwrap();
+    assert_eq!(analysis.version, Some(2));
+    assert_ne!(analysis.result_id(), result_id);
+
+    // Saving keeps the version of the document
+    db.add_file(uri, "LOAD 2\nHALT\n", None);
+    assert_eq!(db.version(file_id), Some(2));
+}
+
+#[test]
+fn test_labels_duplicated_in_imported_modules() {
+    let mut db = LspDatabase::new();
+    let math = Url::parse("file:///math.ram").unwrap();
+    db.add_file(math.clone(), "double: LOAD 1\nHALT\n", None);
+    let main = db.add_file(
+        Url::parse("file:///main.ram").unwrap(),
+        "use math::*\ndouble: LOAD 2\nHALT\n",
+        Some(1),
+    );
+
+    let input = db.analysis_input(main).unwrap();
+    let analysis = analyze_file(&input, &db.cancellation_token(), |_| {}).unwrap();
+    let duplicate = analysis
+        .diagnostics
+        .diagnostics()
+        .iter()
+        .find(|diagnostic| diagnostic.code.as_deref() == Some(codes::DUPLICATE_LABEL.code))
+        .unwrap();
+    assert_eq!(duplicate.external_spans[0].file, math.to_string());
+}
+
+#[test]
+fn test_edits_reuse_the_results_of_unaffected_passes() {
+    let mut db = LspDatabase::new();
+    let uri = Url::parse("file:///main.ram").unwrap();
+    let file_id = db.add_file(uri.clone(), "LOAD 1\nend: HALT\n", Some(1));
+    let analyze = |db: &LspDatabase| {
+        let input = db.analysis_input(file_id).unwrap();
+        let token = db.cancellation_token();
+        assert!(db.store_analysis(file_id, analyze_file(&input, &token, |_| {}).unwrap(), &token));
+        let analysis = db.current_analysis(file_id).unwrap();
+        let unused_label = analysis
+            .diagnostics
+            .diagnostics()
+            .iter()
+            .find(|diagnostic| diagnostic.code.as_deref() == Some(codes::UNUSED_LABEL.code))
+            .map(|diagnostic| diagnostic.labeled_spans[0].0.clone())
+            .unwrap();
+        (analysis.control_flow.clone().unwrap(), unused_label)
+    };
+    let (control_flow, unused_label) = analyze(&db);
+
+    // A comment leaves the body as it was
+    db.add_file(uri.clone(), "LOAD 1\nend: HALT\n# done\n", Some(2));
+    assert!(Arc::ptr_eq(&analyze(&db).0, &control_flow));
+
+    // A comment in the middle only moves the code after it, and the
+    // diagnostics on it
+    db.add_file(uri.clone(), "LOAD 1\n# next\nend: HALT\n# done\n", Some(3));
+    let (reused, moved) = analyze(&db);
+    assert!(Arc::ptr_eq(&reused, &control_flow));
+    assert_eq!(moved, unused_label.start + 7..unused_label.end + 7);
+
+    db.add_file(uri, "LOAD 2\n# next\nend: HALT\n# done\n", Some(4));
+    assert!(!Arc::ptr_eq(&analyze(&db).0, &control_flow));
+}
+
+#[test]
+fn test_changes_to_imported_modules_outdate_the_analysis() {
+    let mut db = LspDatabase::new();
+    let math = Url::parse("file:///math.ram").unwrap();
+    db.add_file(math.clone(), "double: LOAD 1\nHALT\n", None);
+    let main = db.add_file(
+        Url::parse("file:///main.ram").unwrap(),
+        "use math::*\nstart: LOAD 2\nHALT\n",
+        Some(1),
+    );
+
+    let input = db.analysis_input(main).unwrap();
+    let token = db.cancellation_token();
+    assert!(db.store_analysis(main, analyze_file(&input, &token, |_| {}).unwrap(), &token));
+    let result_id = db.current_analysis(main).unwrap().result_id();
+
+    // The module now defines a label of the importing file too
+    db.add_file(math, "double: LOAD 1\nstart: HALT\n", None);
+    assert!(db.current_analysis(main).is_none());
+
+    let input = db.analysis_input(main).unwrap();
+    let token = db.cancellation_token();
+    assert!(db.store_analysis(main, analyze_file(&input, &token, |_| {}).unwrap(), &token));
+    let analysis = db.current_analysis(main).unwrap();
+    assert_ne!(analysis.result_id(), result_id);
+    assert!(
+        analysis
+            .diagnostics
+            .diagnostics()
+            .iter()
+            .any(|diagnostic| diagnostic.code.as_deref() == Some(codes::DUPLICATE_LABEL.code))
+    );
+}
+
+#[test]
+fn test_remove_and_rename_files() {
+    let mut db = LspDatabase::new();
+    let main = Url::parse("file:///main.ram").unwrap();
+    let lib = Url::parse("file:///lib.ram").unwrap();
+    let main_id = db.add_file(main.clone(), "HALT\n", Some(1));
+    let lib_id = db.add_file(lib.clone(), "HALT\n", None);
+    assert!(db.is_open(main_id));
+    assert!(!db.is_open(lib_id));
+
+    // The IDs of removed files are not given to new ones
+    db.remove_file(&main);
+    assert_eq!(db.file_id_for_url(&main), None);
+    assert_eq!(db.file_text(main_id), None);
+    let other_id = db.add_file(Url::parse("file:///other.ram").unwrap(), "HALT\n", None);
+    assert_ne!(other_id, lib_id);
+
+    // A renamed file keeps its ID and text, and is imported by its new name
+    let renamed = Url::parse("file:///util.ram").unwrap();
+    let revision = db.file_revision(lib_id);
+    assert_eq!(db.rename_file(&lib, renamed.clone()), Some(lib_id));
+    assert_eq!(db.file_id_for_url(&lib), None);
+    assert_eq!(db.url_for_file_id(lib_id), Some(renamed.clone()));
+    assert_eq!(db.file_text(lib_id).as_deref(), Some("HALT\n"));
+    assert_ne!(db.file_revision(lib_id), revision);
+    assert!(db.modules().contains(&("util".to_string(), lib_id)));
+    assert_eq!(db.rename_file(&lib, renamed), None);
+    assert_eq!(db.file_urls().len(), 2);
+}
+
+#[test]
+fn test_open_files_overlay_saved_text() {
+    let mut db = LspDatabase::new();
+    let uri = Url::parse("file:///main.ram").unwrap();
+    assert_eq!(db.set_saved_text(&uri, Some("HALT\n")), Some(ChangeKind::Create));
+    let file_id = db.add_file(uri.clone(), "LOAD 1\nHALT\n", Some(1));
+
+    // Changes on disk do not show while the client has the file open
+    assert_eq!(db.set_saved_text(&uri, Some("LOAD 2\nHALT\n")), None);
+    assert_eq!(db.file_text(file_id).as_deref(), Some("LOAD 1\nHALT\n"));
+    assert_eq!(db.set_saved_text(&uri, None), None);
+    assert!(db.is_open(file_id));
+
+    // Closing goes back to the saved text, or removes a file that has none
+    assert_eq!(db.set_saved_text(&uri, Some("LOAD 2\nHALT\n")), None);
+    assert_eq!(db.close_file(&uri), Some(file_id));
+    assert_eq!(db.file_text(file_id).as_deref(), Some("LOAD 2\nHALT\n"));
+    assert_eq!(db.version(file_id), None);
+
+    let unsaved = Url::parse("untitled:Untitled-1").unwrap();
+    let unsaved_id = db.add_file(unsaved.clone(), "HALT\n", Some(1));
+    assert_eq!(db.url_for_file_id(unsaved_id), Some(unsaved.clone()));
+    assert_eq!(db.close_file(&unsaved), None);
+    assert_eq!(db.file_count(), 1);
+}
+
+#[test]
+fn test_instruction_documentation() {
+    let docs = instruction_documentation(&InstructionKind::Store.info());
+
+    assert!(docs.starts_with("**STORE**: "));
+    assert!(docs.contains("Immediate operands are not allowed"));
+    assert!(docs.contains("- uniform: `1`\n- logarithmic: `l(c(0)) + t(a)`\n"));
+    assert!(docs.contains("```ram\nSTORE 2    # Copies the accumulator into register 2\n"));
+}
+
+#[test]
+fn test_instruction_at() {
+    let text = "start: LOAD =1\n  jzero start\nFOO 2\n";
+    let (syntax_tree, _) = parse_file(text);
+
+    let (info, span) = instruction_at(&syntax_tree, 8).unwrap();
+    assert_eq!(info.name, "LOAD");
+    assert_eq!(span, 7..11);
+
+    // Opcodes are case-insensitive, and the end of the opcode still counts
+    let (info, span) = instruction_at(&syntax_tree, 22).unwrap();
+    assert_eq!(info.name, "JZERO");
+    assert_eq!(span, 17..22);
+
+    // Operands, labels and unknown instructions have no documentation
+    assert!(instruction_at(&syntax_tree, 13).is_none());
+    assert!(instruction_at(&syntax_tree, 2).is_none());
+    assert!(instruction_at(&syntax_tree, 30).is_none());
+}
+
+#[test]
+fn test_syntax_edits() {
+    let text = "loop: LOAD 1\n  JUMP loop\nend:\n  HALT\n";
+    let program = Program::cast(parse_file(text).0).unwrap();
+    let statements = program.statements().collect::<Vec<_>>();
+    let load = statements[0].instruction().unwrap();
+    let halt = statements[2].instruction().unwrap();
+
+    // Removing a label keeps the instruction where it was
+    let edit = remove_label(&statements[0]).unwrap();
+    assert_eq!(edit.apply(text), "LOAD 1\n  JUMP loop\nend:\n  HALT\n");
+    let edit = remove_label(&statements[2]).unwrap();
+    assert_eq!(edit.apply(text), "loop: LOAD 1\n  JUMP loop\n  HALT\n");
+
+    let edit = rename_label(&statements[0].label_def().unwrap(), "start").unwrap();
+    assert_eq!(edit.apply(text), "start: LOAD 1\n  JUMP loop\nend:\n  HALT\n");
+
+    let edit = replace_operand(&load, &make::immediate_operand("5")).unwrap();
+    assert_eq!(edit.range, 11..12);
+    assert_eq!(edit.new_text, "=5");
+    let edit = replace_operand(&halt, &make::indirect_operand("3")).unwrap();
+    assert_eq!(edit.apply(text), "loop: LOAD 1\n  JUMP loop\nend:\n  HALT *3\n");
+    let edit = remove_operand(&load).unwrap();
+    assert_eq!(edit.apply(text), "loop: LOAD\n  JUMP loop\nend:\n  HALT\n");
+    assert!(remove_operand(&halt).is_none());
+
+    // Removing a statement takes the comments attached to it along
+    let text = "LOAD 1\n# Where it ends\n#* Stops\n  HALT # for good\n\n# Unrelated\nREAD 1\n";
+    let program = Program::cast(parse_file(text).0).unwrap();
+    let statements = program.statements().collect::<Vec<_>>();
+    assert_eq!(statement_lines(&statements[1]), 7..50);
+    let edit = remove_statement(&statements[1]);
+    assert_eq!(edit.apply(text), "LOAD 1\n\n# Unrelated\nREAD 1\n");
+    let edit = remove_statement(&statements[0]);
+    assert_eq!(edit.apply(text), &text[7..]);
+    let edit = remove_statement(&statements[2]);
+    assert_eq!(edit.apply(text), "LOAD 1\n# Where it ends\n#* Stops\n  HALT # for good\n\n");
+
+    let text = "loop: LOAD 1\n  JUMP loop\nend:\n  HALT\n";
+    let program = Program::cast(parse_file(text).0).unwrap();
+
+    // Appended instructions are aligned with the last one
+    let edit = append_instruction(&program, &make::instruction("HALT", None));
+    assert_eq!(edit.apply(text), format!("{text}  HALT\n"));
+
+    let jump = make::instruction("JUMP", Some(&make::direct_operand("loop")));
+    assert_eq!(jump.syntax().text().to_string(), "JUMP loop");
+    assert_eq!(
+        jump.operand().unwrap().as_direct().unwrap().value().unwrap().as_identifier(),
+        Some("loop".to_string())
+    );
+    assert_eq!(make::label_def("end").name(), Some("end".to_string()));
+}
+
+/// Apply the quick fix for a warning with a code at a span of a source
+fn apply_quick_fix(text: &str, code: &str, span: std::ops::Range<usize>) -> Option<String> {
+    let uri = Url::parse("file:///main.ram").unwrap();
+    let sources = SourceMap::single(uri.as_str(), text);
+    let converter = PositionConverter::for_text(text, PositionEncoding::Utf8);
+    let mut diagnostic = Diagnostic::warning(String::new(), String::new(), span);
+    diagnostic.code = Some(code.to_string());
+    let diagnostic = convert_diagnostic_to_lsp(&uri, &sources, &converter, &diagnostic);
+
+    let action = quick_fix_for_diagnostic(&uri, text, &converter, &diagnostic)?;
+    let mut edits = action.edit?.changes?.remove(&uri)?;
+    edits.sort_by_key(|edit| std::cmp::Reverse(converter.offset(edit.range.start)));
+    let mut result = text.to_string();
+    for edit in edits {
+        let range = converter.offset(edit.range.start)..converter.offset(edit.range.end);
+        result.replace_range(range, &edit.new_text);
+    }
+    Some(result)
+}
+
+#[test]
+fn test_jump_quick_fixes() {
+    // Dead code is removed with the labels in it
+    let text = "READ 1\nHALT\nWRITE 1\nx: WRITE 2\n";
+    assert_eq!(apply_quick_fix(text, DEAD_CODE_CODE, 12..30).unwrap(), "READ 1\nHALT\n");
+
+    let text = "READ 1\nJGTZ out\nout: WRITE 1\nHALT\n";
+    assert_eq!(
+        apply_quick_fix(text, JUMP_TO_NEXT_CODE, 7..15).unwrap(),
+        "READ 1\nout: WRITE 1\nHALT\n"
+    );
+    // A labeled jump keeps its label for the jumps to it
+    assert!(apply_quick_fix("a: JUMP b\nb: HALT\n", JUMP_TO_NEXT_CODE, 3..9).is_none());
+
+    // Both jumps become the unconditional one, keeping the label of the first
+    let text = "LOAD 1\nx: JZERO end\n  jump end\nend: HALT\n";
+    assert_eq!(
+        apply_quick_fix(text, REDUNDANT_JUMP_CODE, 10..30).unwrap(),
+        "LOAD 1\nx: jump end\nend: HALT\n"
+    );
+    let text = "loop: LOAD 1\nJZERO end\nJZERO loop\nend: HALT\n";
+    assert_eq!(
+        apply_quick_fix(text, REDUNDANT_JUMP_CODE, 23..33).unwrap(),
+        "loop: LOAD 1\nJZERO end\nend: HALT\n"
+    );
+}
+
+#[test]
+fn test_import_quick_fixes() {
+    let uri = Url::parse("file:///main.ram").unwrap();
+    let text = "use math::add\nJUMP print\n";
+    let sources = SourceMap::single(uri.as_str(), text);
+    let converter = PositionConverter::for_text(text, PositionEncoding::Utf8);
+    let mut diagnostic = Diagnostic::error(String::new(), String::new(), 19..24);
+    diagnostic.code = Some(UNDEFINED_LABEL_CODE.to_string());
+    let diagnostic = convert_diagnostic_to_lsp(&uri, &sources, &converter, &diagnostic);
+
+    let modules = vec![
+        ("io".to_string(), parse_file("print: WRITE 1\nHALT\n").0),
+        ("math".to_string(), parse_file("add: ADD 1\nHALT\n").0),
+    ];
+    let [action] = import_fixes_for_diagnostic(&uri, text, &converter, &diagnostic, &modules)
+        .try_into()
+        .unwrap();
+    assert_eq!(action.title, "Add `use io::print`");
+    assert_eq!(action.is_preferred, Some(true));
+
+    let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+    assert_eq!(edit.range.start, Position::new(1, 0));
+    assert_eq!(edit.new_text, "use io::print\n");
+
+    // Labels no module defines have nothing to import
+    let modules = vec![("math".to_string(), parse_file("add: ADD 1\nHALT\n").0)];
+    assert!(import_fixes_for_diagnostic(&uri, text, &converter, &diagnostic, &modules).is_empty());
+}
+
+#[test]
+fn test_selection_ranges() {
+    let text = "LOAD 1\nloop: LOAD =5\n  ADD 2[3]\nend: HALT\n";
+    let (syntax_tree, _) = parse_file(text);
+
+    // Value, operand, instruction, statement, label block and file
+    assert_eq!(
+        selection_ranges(&syntax_tree, 19),
+        vec![19..20, 18..20, 13..20, 7..20, 7..31, 0..42]
+    );
+    assert_eq!(
+        selection_ranges(&syntax_tree, 29),
+        vec![29..30, 28..31, 27..31, 23..31, 7..31, 0..42]
+    );
+
+    // Statements before the first label are not in a block
+    assert_eq!(selection_ranges(&syntax_tree, 1), vec![0..4, 0..6, 0..42]);
+    assert!(selection_ranges(&syntax_tree, 100).is_empty());
+}
+
+#[test]
+fn test_folding_ranges() {
+    let text = "# Helpers\n# for the loop\nloop: LOAD 1\n  JUMP loop\n\n#* The end\nend:\n  HALT\n";
+    let (syntax_tree, _) = parse_file(text);
+
+    // The documentation of a label folds on its own, not into the block before it
+    assert_eq!(
+        folding_ranges(&syntax_tree),
+        vec![
+            (0..24, FoldKind::Comment),
+            (25..49, FoldKind::Block),
+            (51..61, FoldKind::Comment),
+            (62..73, FoldKind::Block),
+        ]
+    );
+}
+
+#[test]
+fn test_document_symbols() {
+    let text = "# Doubles it\ntwice: ADD 1\n  RET\nmain: CALL twice\n  HALT\n";
+    let mut db = LspDatabase::new();
+    let file_id = db.add_file(Url::parse("file:///main.ram").unwrap(), text, None);
+
+    // Labels span their statement and the comments documenting it
+    let symbols = document_symbols(&parse_file(text).0, None);
+    let names = symbols.iter().map(|symbol| symbol.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["twice", "main"]);
+    assert_eq!(symbols[0].range, 0..25);
+    assert_eq!(symbols[0].selection_range.start, 13);
+    assert!(symbols[0].detail.is_none());
+
+    // Under the sections convention they span their section, with its metrics
+    let mut input = db.analysis_input(file_id).unwrap();
+    input.config = input.config.with_sections(true);
+    let analysis = analyze_file(&input, &db.cancellation_token(), |_| {}).unwrap();
+    let sections = analysis.sections.as_deref().map(Vec::as_slice);
+    let symbols = document_symbols(&analysis.syntax_tree, sections);
+    assert_eq!(symbols[0].range, 0..31);
+    assert_eq!(symbols[0].detail.as_deref(), Some("2 instructions, registers 1"));
+    assert_eq!(symbols[1].range, 32..55);
+    assert_eq!(symbols[1].detail.as_deref(), Some("2 instructions, calls twice"));
+}
+
+#[test]
+fn test_workspace_symbols() {
+    // The characters of the query appear in order, ignoring case
+    assert_eq!(fuzzy_score("lp", "loop"), Some(2));
+    assert_eq!(fuzzy_score("LOO", "loop"), Some(0));
+    assert_eq!(fuzzy_score("pl", "loop"), None);
+    assert_eq!(fuzzy_score("", "loop"), Some(0));
+
+    let text = "main: CALL print_sum\n  HALT\nprint_sum: WRITE 1\n  RET\n";
+    let syntax_tree = parse_file(text).0;
+    let symbols = workspace_symbols("sums", &syntax_tree, "sum");
+    let names = symbols.iter().map(|symbol| symbol.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["sums", "print_sum"]);
+
+    // Modules start at the start of their file, and labels at their name
+    assert!(symbols[0].is_module);
+    assert_eq!((symbols[0].range.clone(), symbols[0].score), (0..0, 0));
+    assert!(!symbols[1].is_module);
+    assert_eq!(symbols[1].module, "sums");
+    assert_eq!((symbols[1].range.start, symbols[1].score), (28, 6));
+}
+
+#[test]
+fn test_on_type_formatting() {
+    let settings = FormatSettings::default();
+    let format = |text: &str, offset: usize, ch: &str| {
+        let mut edits = on_type_formatting(text, offset, ch, &settings);
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+        edits.iter().fold(text.to_string(), |text, edit| edit.apply(&text))
+    };
+
+    // Labels move to the start of the line, with their instruction aligned
+    assert_eq!(format("    loop:\n", 9, ":"), "loop:\n");
+    assert_eq!(format("  a: LOAD 1\n", 4, ":"), "a:  LOAD 1\n");
+    assert_eq!(format("  loop: LOAD 1\n", 7, ":"), "loop: LOAD 1\n");
+    assert_eq!(format("LOAD 1 # a: b\n", 10, ":"), "LOAD 1 # a: b\n");
+
+    // The line after a label is indented
+    assert_eq!(format("loop:\n", 6, "\n"), "loop:\n    ");
+    assert_eq!(format("loop:\n  HALT", 6, "\n"), "loop:\n    HALT");
+    assert_eq!(format("LOAD 1\n", 7, "\n"), "LOAD 1\n");
+
+    // Trailing comments are aligned, comments on their own line are not
+    assert_eq!(format("LOAD 1 #", 8, "#"), format!("LOAD 1{}#", " ".repeat(18)));
+    assert_eq!(format("\tLOAD 1 #", 9, "#"), format!("\tLOAD 1{}#", " ".repeat(14)));
+    assert_eq!(format("    # note", 5, "#"), "    # note");
+}
+
+#[test]
+fn test_completion_context() {
+    let context = |text: &str| {
+        let offset = text.find('|').unwrap();
+        let text = text.replace('|', "");
+        completion_context(&parse_file(&text).0, offset)
+    };
+
+    assert_eq!(context("LO|"), Some(CompletionContext::Instruction));
+    assert_eq!(context("loop: |"), Some(CompletionContext::Instruction));
+    assert_eq!(context("loop: LOAD 1\n    JUMP |"), Some(CompletionContext::Label));
+    assert_eq!(context("square: MUL 0\n    CALL |"), Some(CompletionContext::Label));
+    assert_eq!(context("JGTZ lo|\nloop: HALT"), Some(CompletionContext::Label));
+    assert_eq!(context("use |"), Some(CompletionContext::Module));
+    assert_eq!(context("use ma|"), Some(CompletionContext::Module));
+    assert_eq!(context("use math::|"), Some(CompletionContext::ModuleSymbol("math".to_string())));
+    assert_eq!(context("LOAD *|"), Some(CompletionContext::Address));
+    assert_eq!(context("ADD =1|"), Some(CompletionContext::Address));
+    assert_eq!(context("LOAD 1\nSTORE |"), Some(CompletionContext::Destination));
+
+    // Nothing is completed after operands or in comments
+    assert_eq!(context("LOAD 1 |"), None);
+    assert_eq!(context("LOAD 1 # JUMP |"), None);
+}
+
+#[test]
+fn test_completion_sources() {
+    let text = "use math::*\nuse io::read\nstart: LOAD 5\n    STORE *3\n    ADD =5\n    LOAD 7\nend: HALT\n";
+    let (syntax_tree, _) = parse_file(text);
+
+    assert_eq!(defined_labels(&syntax_tree), vec!["start", "end"]);
+    assert_eq!(
+        imported_modules(&syntax_tree),
+        vec![("math".to_string(), None), ("io".to_string(), Some("read".to_string()))]
+    );
+
+    // The addresses before the cursor come first, nearest first
+    let offset = text.find("ADD").unwrap();
+    assert_eq!(memory_addresses(&syntax_tree, offset), vec!["3", "5", "7"]);
+
+    // Direct loads before the cursor, nearest first
+    let text = "LOAD 4\nADD 6\nLOAD =8\nLOAD 5\nLOAD 4\nSTORE \nLOAD 9\n";
+    let offset = text.find("STORE").unwrap() + 6;
+    assert_eq!(loaded_addresses(&parse_file(text).0, offset), vec!["4", "5"]);
+}
+
+#[test]
+fn test_server_status() {
+    let mut db = LspDatabase::new();
+    for (name, text) in [("ok.ram", "LOAD 1\nHALT\n"), ("bad.ram", "LAOD 1\nHALT\n")] {
+        let file_id = db.add_file(Url::parse(&format!("file:///{name}")).unwrap(), text, None);
+        let input = db.analysis_input(file_id).unwrap();
+        let token = db.cancellation_token();
+        let analysis = analyze_file(&input, &token, |_| {}).unwrap();
+        assert!(db.store_analysis(file_id, analysis, &token));
+    }
+
+    let mut state = StatusState { indexing: 1, ..Default::default() };
+    let status = ServerStatusParams::new(&db, &state);
+    assert_eq!(status.health, Health::Ok);
+    assert!(status.indexing);
+    assert_eq!((status.files, status.files_with_errors), (2, 1));
+    assert!(status.to_markdown().contains("- Files: 2\n"));
+
+    state.indexing = 0;
+    state.config_error = Some("invalid severity".to_string());
+    let status = ServerStatusParams::new(&db, &state);
+    assert_eq!(status.health, Health::Warning);
+    assert!(status.to_markdown().contains("## Project manifest\n\ninvalid severity\n"));
+
+    let value = serde_json::to_value(&status).unwrap();
+    assert_eq!(value["health"], "warning");
+    assert_eq!(value["filesWithErrors"], 1);
+
+    // Each analysis looked up the line index of its file
+    let stats = db.query_stats();
+    assert_eq!((stats[0].memos, stats[0].hits, stats[0].misses), (2, 2, 0));
+    let markdown = query_stats_markdown(&stats);
+    assert!(markdown.contains("- file-text: 2 memos, 2 hits, 0 misses (100.0% hit rate)\n"));
+    assert!(markdown.contains("- parse: 2 memos, 0 hits, 0 misses (n/a hit rate)\n"));
+}
+
+#[test]
+fn test_client_supports_status() {
+    assert!(!client_supports_status(&ClientCapabilities::default()));
+    let capabilities = ClientCapabilities {
+        experimental: Some(serde_json::json!({ "serverStatusNotification": true })),
+        ..Default::default()
+    };
+    assert!(client_supports_status(&capabilities));
+}
+
+#[test]
+fn test_execution_heatmap() {
+    let mut db = LspDatabase::new();
+    let uri = Url::parse("file:///loop.ram").unwrap();
+    let text = "READ 1\nLOAD 1\nloop: SUB =1\n  JGTZ loop\nHALT\n";
+    let file_id = db.add_file(uri.clone(), text, Some(3));
+    let input = db.analysis_input(file_id).unwrap();
+    let token = db.cancellation_token();
+    let analysis = analyze_file(&input, &token, |_| {}).unwrap();
+
+    let args: RunHeatmapArgs =
+        serde_json::from_value(serde_json::json!({ "uri": uri, "input": [3] })).unwrap();
+    let heatmap = run_heatmap(&analysis, args, Arc::new(CancelFlag::new())).unwrap();
+    let counts = heatmap.lines.iter().map(|line| (line.line, line.count)).collect::<Vec<_>>();
+    assert_eq!(counts, vec![(0, 1), (1, 1), (2, 3), (3, 3), (4, 1)]);
+    assert_eq!(heatmap.max_count, 3);
+    assert_eq!(heatmap.lines[2].heat, 1.0);
+    assert_eq!((heatmap.steps, heatmap.finished, heatmap.version), (9, true, Some(3)));
+
+    // A run that is cut short still reports the lines it executed
+    let args = RunHeatmapArgs { uri: uri.clone(), input: vec![3], max_steps: Some(2) };
+    let heatmap = run_heatmap(&analysis, args, Arc::new(CancelFlag::new())).unwrap();
+    assert!(!heatmap.finished);
+    assert_eq!(heatmap.lines[2].count, 0);
+    assert_eq!(serde_json::to_value(&heatmap).unwrap()["maxCount"], 1);
+
+    // A stopped run reports the lines it executed, without an error
+    let flag = CancelFlag::new();
+    flag.cancel();
+    let args = RunHeatmapArgs { uri: uri.clone(), input: vec![3], max_steps: None };
+    let heatmap = run_heatmap(&analysis, args, Arc::new(flag)).unwrap();
+    assert!(heatmap.cancelled && !heatmap.finished);
+    assert_eq!((heatmap.steps, heatmap.error), (0, None));
+
+    let capabilities = ClientCapabilities {
+        experimental: Some(serde_json::json!({ "executionHeatmap": true })),
+        ..Default::default()
+    };
+    assert!(client_supports_heatmap(&capabilities));
+}
+
+#[test]
+fn test_parse_test_annotation() {
+    assert_eq!(
+        parse_test_annotation(" #[test(input = [5, -7], output = [-2])]"),
+        Some(EmbeddedTest { input: vec![5, -7], output: vec![-2] })
+    );
+    assert_eq!(
+        parse_test_annotation("#[test(output = [1, 2,])]"),
+        Some(EmbeddedTest { input: vec![], output: vec![1, 2] })
+    );
+
+    assert_eq!(parse_test_annotation("#[test(input = [1])]"), None);
+    assert_eq!(parse_test_annotation("#[test(output = [a])]"), None);
+    assert_eq!(parse_test_annotation("#[test(output = [1], output = [2])]"), None);
+    assert_eq!(parse_test_annotation("#[test(expected = [1])]"), None);
+    assert_eq!(parse_test_annotation("#[char]"), None);
+}
+
+#[test]
+fn test_code_lenses() {
+    let text = "# Adds two numbers\nREAD 1\nREAD 2\n#* #[test(input = [5, 7], output = [12])]\nLOAD 1\nADD 2\nWRITE 0\nHALT\n";
+    let (syntax_tree, _) = parse_file(text);
+    let lenses = code_lenses(&syntax_tree);
+
+    assert_eq!(lenses.len(), 2);
+    let (run_range, run_action) = &lenses[0];
+    assert!(text[run_range.clone()].starts_with("READ 1"));
+    assert_eq!(run_action, &LensAction::Run);
+    let (test_range, test_action) = &lenses[1];
+    assert!(text[test_range.clone()].contains("#[test("));
+    let test = EmbeddedTest { input: vec![5, 7], output: vec![12] };
+    assert_eq!(test_action, &LensAction::Test(test.clone()));
+
+    // The lens data survives the round trip through the client
+    let uri = Url::parse("file:///add.ram").unwrap();
+    let data = LensData { uri, action: LensAction::Test(test) };
+    let value = serde_json::to_value(&data).unwrap();
+    assert_eq!(value["action"]["kind"], "test");
+    assert_eq!(serde_json::from_value::<LensData>(value).unwrap(), data);
+    assert_eq!(data.command().command, RUN_TEST_COMMAND);
+
+    let (syntax_tree, _) = parse_file("# Nothing to run\n");
+    assert!(code_lenses(&syntax_tree).is_empty());
+}
+
+#[test]
+fn test_embedded_test_results() {
+    let mut db = LspDatabase::new();
+    let uri = Url::parse("file:///add.ram").unwrap();
+    let text = "#* #[test(input = [5, 7], output = [12])]\nREAD 1\nREAD 2\n#* #[test(input = [1, 1], output = [3])]\nLOAD 1\nADD 2\nWRITE 0\nHALT\n";
+    let file_id = db.add_file(uri.clone(), text, Some(1));
+    let input = db.analysis_input(file_id).unwrap();
+    let token = db.cancellation_token();
+    let analysis = analyze_file(&input, &token, |_| {}).unwrap();
+
+    let outcomes = run_tests(&analysis, None, Arc::new(CancelFlag::new())).unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert!(outcomes[0].passed());
+    assert!(outcomes[0].message().starts_with("Test passed in "));
+    assert!(text[outcomes[0].range.clone()].contains("output = [12]"));
+    assert!(!outcomes[1].passed());
+    assert!(outcomes[1].message().ends_with(": expected [3], got [2]"));
+
+    // The outcomes are shown as advice on the annotations of the text they ran on
+    let run = TestRun { text: text.to_string(), outcomes, generation: 0 };
+    let diagnostics = run.diagnostics(text);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[1].kind, DiagnosticKind::Advice);
+    assert_eq!(diagnostics[1].code.as_deref(), Some(TEST_RESULT_CODE));
+    assert!(run.diagnostics("HALT\n").is_empty());
+
+    // A stopped run reports the tests as cancelled
+    let flag = CancelFlag::new();
+    flag.cancel();
+    let outcomes = run_tests(&analysis, None, Arc::new(flag)).unwrap();
+    assert!(outcomes.iter().all(|outcome| outcome.message() == "Test cancelled"));
+
+    let capabilities = ClientCapabilities {
+        experimental: Some(serde_json::json!({ "testResults": true })),
+        ..Default::default()
+    };
+    assert!(client_supports_test_results(&capabilities));
+}
+
+#[test]
+fn test_control_flow_decorations() {
+    let text = "loop: LOAD 1\nJGTZ end\nCALL print\nJUMP loop\nprint: WRITE 1\nRET\nend: HALT\n";
+    let mut db = LspDatabase::new();
+    let file_id = db.add_file(Url::parse("file:///loop.ram").unwrap(), text, Some(1));
+    let input = db.analysis_input(file_id).unwrap();
+    let analysis = analyze_file(&input, &db.cancellation_token(), |_| {}).unwrap();
+    let (body, cfg) = (analysis.body.unwrap(), analysis.control_flow.unwrap());
+
+    // Falling through and returning from the subroutine are not drawn
+    let edges = control_flow_edges(&body, &cfg)
+        .into_iter()
+        .map(|edge| (text[edge.from].trim(), text[edge.to].trim(), edge.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        edges,
+        vec![
+            ("JGTZ end", "end:", FlowKind::Branch),
+            ("CALL print", "print:", FlowKind::Call),
+            ("JUMP loop", "loop:", FlowKind::Jump),
+        ]
+    );
+    assert_eq!(serde_json::to_value(FlowKind::Branch).unwrap(), "branch");
+
+    assert!(!client_supports_control_flow_decorations(&ClientCapabilities::default()));
+    let capabilities = ClientCapabilities {
+        experimental: Some(serde_json::json!({ "controlFlowDecorations": true })),
+        ..Default::default()
+    };
+    assert!(client_supports_control_flow_decorations(&capabilities));
+}
+
+#[test]
+fn test_semantic_token_modifiers() {
+    let text = "LOAD =5\nSTORE 1\nHALT\nWRITE 1\n";
+    let mut db = LspDatabase::new();
+    let file_id = db.add_file(Url::parse("file:///modifiers.ram").unwrap(), text, None);
+    let input = db.analysis_input(file_id).unwrap();
+    let analysis = analyze_file(&input, &db.cancellation_token(), |_| {}).unwrap();
+    let converter = PositionConverter::for_text(text, PositionEncoding::Utf8);
+    let modifiers = AnalysisModifiers::from_analysis(&analysis);
+
+    // The modifiers of the tokens, by line, character and length
+    let (mut line, mut character) = (0, 0);
+    let tokens = semantic_tokens_for_tree(&analysis.syntax_tree, &converter, &modifiers)
+        .into_iter()
+        .map(|token| {
+            line += token.delta_line;
+            character = if token.delta_line == 0 {
+                character + token.delta_start
+            } else {
+                token.delta_start
+            };
+            ((line, character, token.length), token.token_modifiers_bitset)
+        })
+        .collect::<Vec<_>>();
+    let modifiers_of = |position| {
+        tokens
+            .iter()
+            .filter(|(at, _)| *at == position)
+            .fold(0, |bits, (_, modifiers)| bits | modifiers)
+    };
+    let legend = semantic_tokens_legend().token_modifiers;
+    let bit =
+        |modifier: SemanticTokenModifier| 1 << legend.iter().position(|m| *m == modifier).unwrap();
+    let constant = bit(SemanticTokenModifier::new(CONSTANT_ACCUMULATOR_MODIFIER));
+
+    // The immediate operand is a constant, and the accumulator is known after the load
+    assert_eq!(modifiers_of((0, 0, 4)), constant);
+    assert_ne!(modifiers_of((0, 6, 1)) & bit(SemanticTokenModifier::READONLY), 0);
+    // The stored register is modified
+    let stored = modifiers_of((1, 6, 1));
+    assert_ne!(stored & bit(SemanticTokenModifier::MODIFICATION), 0);
+    assert_eq!(stored & bit(SemanticTokenModifier::READONLY), 0);
+    // The write after the halt never runs
+    assert_ne!(modifiers_of((3, 0, 5)) & bit(SemanticTokenModifier::DEPRECATED), 0);
+    assert_eq!(modifiers_of((2, 0, 4)) & bit(SemanticTokenModifier::DEPRECATED), 0);
+
+    // Without an analysis, tokens have no modifiers
+    let tokens =
+        semantic_tokens_for_tree(&analysis.syntax_tree, &converter, &AnalysisModifiers::default());
+    assert!(tokens.iter().all(|token| token.token_modifiers_bitset == 0));
+}
+
+#[test]
+fn test_control_flow_highlights() {
+    let text = "loop: LOAD 1\nJGTZ end\nJUMP loop\nend: HALT\n";
+    let mut db = LspDatabase::new();
+    let file_id = db.add_file(Url::parse("file:///loop.ram").unwrap(), text, None);
+    let input = db.analysis_input(file_id).unwrap();
+    let analysis = analyze_file(&input, &db.cancellation_token(), |_| {}).unwrap();
+    let (body, cfg) = (analysis.body.unwrap(), analysis.control_flow.unwrap());
+    let highlights = |offset| {
+        control_flow_highlights(&body, &cfg, offset)
+            .into_iter()
+            .map(|(range, kind)| (text[range].trim(), kind))
+            .collect::<Vec<_>>()
+    };
+
+    // A label, from its definition or a reference, and the jumps to it
+    let jumps_to_end =
+        vec![("JGTZ end", DocumentHighlightKind::READ), ("end:", DocumentHighlightKind::WRITE)];
+    assert_eq!(highlights(33), jumps_to_end);
+    assert_eq!(highlights(19), jumps_to_end);
+    assert_eq!(
+        highlights(1),
+        vec![("loop:", DocumentHighlightKind::WRITE), ("JUMP loop", DocumentHighlightKind::READ)]
+    );
+
+    // A conditional jump, its target and the instruction it falls through to
+    assert_eq!(
+        highlights(14),
+        vec![
+            ("JGTZ end", DocumentHighlightKind::TEXT),
+            ("JUMP loop", DocumentHighlightKind::READ),
+            ("end:", DocumentHighlightKind::READ),
+            ("HALT", DocumentHighlightKind::READ),
+        ]
+    );
+
+    // Other instructions have no related control flow
+    assert!(highlights(8).is_empty());
+}
+
+#[tokio::test]
+async fn test_message_framing() {
+    let first = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+    let second = r#"{"jsonrpc":"2.0","method":"exit","params":"déjà vu"}"#;
+    let mut input = [frame(first.as_bytes()), frame(second.as_bytes())].concat();
+    assert!(input.starts_with(format!("Content-Length: {}\r\n\r\n{{", first.len()).as_bytes()));
+
+    let mut reader = input.as_slice();
+    assert_eq!(read_message(&mut reader).await.unwrap().as_deref(), Some(first));
+    assert_eq!(read_message(&mut reader).await.unwrap().as_deref(), Some(second));
+    assert_eq!(read_message(&mut reader).await.unwrap(), None);
+
+    // Other headers are skipped, but the length is required
+    input = b"Content-Type: application/vscode-jsonrpc\r\ncontent-length: 2\r\n\r\n{}".to_vec();
+    assert_eq!(read_message(&mut input.as_slice()).await.unwrap().as_deref(), Some("{}"));
+    assert!(read_message(&mut b"Content-Type: json\r\n\r\n{}".as_slice()).await.is_err());
+}
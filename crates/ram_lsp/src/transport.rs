@@ -0,0 +1,129 @@
+//! Transports the server talks to its clients over.
+//!
+//! Editors usually start the server and talk to it over stdio. Over TCP, the
+//! server listens on a port of the loopback interface and serves every client
+//! that connects, each in a session of its own, which suits remote development
+//! through a forwarded port. Browser-based editors connect over WebSockets,
+//! where every message is a JSON-RPC message of its own, without the
+//! `Content-Length` header of the base protocol: the header is added to the
+//! messages coming in and stripped from those going out, so the server speaks
+//! the base protocol over every transport.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info};
+
+/// The size of the buffer between a WebSocket and the server
+const WEBSOCKET_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How the server talks to its clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// Standard input and output, for the client that started the server
+    Stdio,
+    /// TCP connections to a port
+    Tcp(u16),
+    /// WebSocket connections to a port
+    WebSocket(u16),
+}
+
+/// Listen for connections on a port of the loopback interface
+pub async fn listen(port: u16) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+    info!("Listening on {}", listener.local_addr()?);
+    Ok(listener)
+}
+
+/// Accept the WebSocket handshake of a connection
+///
+/// Returns the ends the server reads its messages from and writes them to, in
+/// the framing of the base protocol.
+pub async fn accept_websocket(
+    stream: TcpStream,
+) -> io::Result<(impl AsyncRead + Unpin, impl AsyncWrite + Unpin)> {
+    let websocket = tokio_tungstenite::accept_async(stream).await.map_err(io::Error::other)?;
+    let (mut sink, mut source) = websocket.split();
+    let (server, bridge) = tokio::io::duplex(WEBSOCKET_BUFFER_SIZE);
+    let (bridge_reader, mut bridge_writer) = tokio::io::split(bridge);
+
+    // Frame the messages of the client
+    tokio::spawn(async move {
+        while let Some(Ok(message)) = source.next().await {
+            let body = match message {
+                Message::Text(text) => text.as_bytes().to_vec(),
+                Message::Binary(bytes) => bytes.to_vec(),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if bridge_writer.write_all(&frame(&body)).await.is_err() {
+                break;
+            }
+        }
+        // The server stops when its input ends
+        bridge_writer.shutdown().await.ok();
+    });
+
+    // Send the messages of the server without their header
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(bridge_reader);
+        loop {
+            match read_message(&mut reader).await {
+                Ok(Some(body)) => {
+                    if sink.send(Message::text(body)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("Failed to read a message of the server: {}", err);
+                    break;
+                }
+            }
+        }
+        sink.close().await.ok();
+    });
+
+    Ok(tokio::io::split(server))
+}
+
+/// Add the header of the base protocol to the body of a message
+pub fn frame(body: &[u8]) -> Vec<u8> {
+    let mut message = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    message.extend_from_slice(body);
+    message
+}
+
+/// Read the body of the next message in the framing of the base protocol
+///
+/// Returns `None` at the end of the input.
+pub async fn read_message(reader: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            content_length = Some(value.trim().parse::<usize>().map_err(io::Error::other)?);
+        }
+    }
+
+    let length = content_length.ok_or_else(|| io::Error::other("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    String::from_utf8(body).map(Some).map_err(io::Error::other)
+}
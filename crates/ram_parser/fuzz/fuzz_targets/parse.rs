@@ -0,0 +1,33 @@
+//! Fuzzes the parser with arbitrary text.
+//!
+//! Checks the same invariants as the `invariants` property tests: the parser
+//! does not panic, the tree covers the text and prints back to it, and every
+//! error node comes with a diagnostic. The corpus is seeded with the example
+//! programs of the `inputs` directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ram_syntax::{SyntaxKind, SyntaxNode};
+
+fuzz_target!(|source: &str| {
+    let (events, errors) = ram_parser::parse(source);
+    let (green_node, interner) = ram_parser::build_tree(events);
+    let tree = SyntaxNode::new_root_with_resolver(green_node, interner);
+
+    let range = tree.text_range();
+    assert_eq!(usize::from(range.start()), 0);
+    assert_eq!(usize::from(range.end()), source.len());
+    assert_eq!(tree.text().to_string(), source);
+
+    for node in tree.descendants().filter(|node| node.kind() == SyntaxKind::ERROR) {
+        let range = node.text_range();
+        let (start, end) = (usize::from(range.start()), usize::from(range.end()));
+        assert!(
+            errors.iter().any(|error| {
+                error.labeled_spans.iter().any(|(span, _)| span.start <= end && start <= span.end)
+            }),
+            "error node at {start}..{end} has no diagnostic"
+        );
+    }
+});
@@ -12,11 +12,31 @@
 #![allow(clippy::wildcard_imports)]
 #![allow(clippy::enum_glob_use)]
 
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use ram_core::{InstructionKind, InstructionSet};
 use ram_syntax::SyntaxKind::*;
-use ram_syntax::T;
+use ram_syntax::{T, TokenSet};
+
+use crate::diagnostic::{Applicability, Diagnostic, DiagnosticKind, codes};
+use crate::parser::{CompletedMarker, Parser};
+
+/// Returns the names of the instructions in the standard instruction registry.
+fn instruction_names() -> &'static [String] {
+    static NAMES: OnceLock<Vec<String>> = OnceLock::new();
+    NAMES.get_or_init(|| {
+        let mut names = InstructionSet::standard().names().collect::<Vec<_>>();
+        names.sort();
+        names
+    })
+}
 
-use crate::diagnostic::{Diagnostic, DiagnosticKind};
-use crate::parser::{Parser, TokenSet};
+/// Returns true if `name` is a known instruction or alias, ignoring case.
+pub(crate) fn is_instruction_name(name: &str) -> bool {
+    !matches!(InstructionKind::from_name(name), InstructionKind::Custom(_))
+        || instruction_names().iter().any(|known| known.eq_ignore_ascii_case(name))
+}
 
 /// Entry point for the grammar
 pub(crate) mod entry {
@@ -114,8 +134,7 @@ mod stmt {
     use super::*;
 
     // Recovery token set for error handling
-    const RECOVERY_SET: TokenSet =
-        TokenSet::new(&[NEWLINE, T![#], T![#*], IDENTIFIER, T![mod], T![use]]);
+    const RECOVERY_SET: TokenSet = TokenSet::STATEMENT_START;
 
     /// Parses a statement.
     ///
@@ -173,7 +192,9 @@ mod stmt {
             T![mod] => parse_module_declaration(p),
             T![use] => parse_module_use(p),
             T![#] | T![#*] => parse_comment_statement(p),
-            IDENTIFIER if p.at_label_definition_start() => parse_label_statement(p),
+            IDENTIFIER if p.at_label_definition_start() || p.at_label_missing_colon() => {
+                parse_label_statement(p);
+            }
             _ if p.at_instruction_start() => parse_instruction_statement(p),
             _ => handle_unexpected_token_in_statement(p),
         }
@@ -268,7 +289,7 @@ mod stmt {
                         instr_span,
                         "instruction found here - place the label directly above this",
                     )
-                    .with_code("E001")
+                    .with_code(codes::LABEL_WITHOUT_INSTRUCTION.code)
                     .with_note("Labels must be followed by an instruction, either on the same line or the next line.")
                     .with_note("Consider moving this label directly above the instruction."),
                 DiagnosticKind::Error
@@ -280,7 +301,7 @@ mod stmt {
                     .with_message("Label must be followed by an instruction")
                     .with_help("Add an instruction after the label definition")
                     .with_primary_span(label_span, "label defined here")
-                    .with_code("E001")
+                    .with_code(codes::LABEL_WITHOUT_INSTRUCTION.code)
                     .with_note("Labels must be followed by an instruction, either on the same line or the next line.")
                     .with_note("Add an instruction like 'LOAD', 'STORE', 'ADD', etc. after this label."),
                 DiagnosticKind::Error
@@ -328,7 +349,7 @@ mod stmt {
             .with_message(message)
             .with_help(help)
             .with_primary_span(span, "here")
-            .with_code("E002");
+            .with_code(codes::UNEXPECTED_TOKEN.code);
 
         // Use a warning for unexpected closing brackets, error for other cases
         let kind =
@@ -349,7 +370,7 @@ mod modules {
     use super::*;
 
     // Constants for error recovery
-    const MODULE_PATH_RECOVERY: TokenSet = TokenSet::new(&[NEWLINE, EOF, T![#], T![#*]]);
+    const MODULE_PATH_RECOVERY: TokenSet = TokenSet::STATEMENT_END;
 
     /// Parse a module declaration statement.
     ///
@@ -506,8 +527,8 @@ mod expr {
                 "Unexpected closing bracket ']'",
                 "This closing bracket doesn't match any opening bracket",
             ),
-            NEWLINE | T![#] | T![#*] | EOF => {} // No operand, which is fine
-            _ => operand_expr(p),                // Parse operand
+            _ if p.at_ts(TokenSet::STATEMENT_END) => {} // No operand, which is fine
+            _ => operand_expr(p),                       // Parse operand
         }
 
         m.complete(p, INSTRUCTION);
@@ -556,15 +577,15 @@ mod expr {
                 );
             } else {
                 // Missing closing bracket
-                let spans = vec![
-                    (open_bracket_span.clone(), "here".to_string()),
-                    (open_bracket_span.clone(), "accessing nothing".to_string()),
-                ];
-
-                p.labeled_error(
-                    "Unclosed array accessor to nowhere",
-                    "Array accessors can only be used after an identifier or number and must be closed with ']'",
-                    spans,
+                let insert_at = p.prev_token_end();
+                p.error_with_builder(
+                    Diagnostic::builder()
+                        .with_message("Unclosed array accessor to nowhere")
+                        .with_help("Array accessors can only be used after an identifier or number and must be closed with ']'")
+                        .with_primary_span(open_bracket_span.clone(), "here")
+                        .with_secondary_span(open_bracket_span, "accessing nothing")
+                        .with_code(codes::UNCLOSED_BRACKET.code)
+                        .with_suggestion(insert_at..insert_at, "]", Applicability::MaybeIncorrect),
                 );
             }
         } else {
@@ -625,20 +646,20 @@ mod expr {
                 // Indirect addressing
                 let m_inner = p.start();
                 p.bump_any(); // Consume *
-                operand_value(p);
+                operand_value_expr(p);
                 m_inner.complete(p, INDIRECT_OPERAND);
             }
             T![=] => {
                 // Immediate addressing
                 let m_inner = p.start();
                 p.bump_any(); // Consume =
-                operand_value(p);
+                operand_value_expr(p);
                 m_inner.complete(p, IMMEDIATE_OPERAND);
             }
             _ => {
                 // Direct addressing (default)
                 let m_inner = p.start();
-                operand_value(p);
+                operand_value_expr(p);
                 m_inner.complete(p, DIRECT_OPERAND);
             }
         }
@@ -646,10 +667,123 @@ mod expr {
         m.complete(p, OPERAND);
     }
 
+    /// The tokens that end an operand
+    const OPERAND_END: TokenSet = TokenSet::STATEMENT_END;
+
+    /// Parses the value of an operand, with arithmetic on operand values.
+    ///
+    /// # Structure
+    /// Operand values joined by `+`, `-`, `*` and `/`. Multiplication and
+    /// division bind tighter than addition and subtraction, and operators of
+    /// the same precedence associate to the left.
+    ///
+    /// # Returns
+    /// Completes an [`OPERAND_VALUE`] syntax node, or a [`BINARY_EXPR`] node
+    /// whose operands are values or nested expressions.
+    ///
+    /// # Diagram
+    /// ```text
+    /// ┌─────────────── BINARY_EXPR ──────────────────┐
+    /// │                                              │
+    /// │  ┌─ OPERAND_VALUE ─┐     ┌─ BINARY_EXPR ──┐  │
+    /// │  │ buffer          │  +  │ N * 2          │  │
+    /// │  └─────────────────┘     └────────────────┘  │
+    /// │                                              │
+    /// └──────────────────────────────────────────────┘
+    /// ```
+    pub(super) fn operand_value_expr(p: &mut Parser<'_>) {
+        value_expr_bp(p, 0);
+    }
+
+    /// Parses operand values joined by operators that bind tighter than `min_power`.
+    fn value_expr_bp(p: &mut Parser<'_>, min_power: u8) {
+        let mut lhs = operand_value(p);
+
+        while let Some(power) = binary_operator_ahead(p)
+            && power > min_power
+        {
+            let m = lhs.precede(p);
+            whitespace::skip_ws(p);
+            let operator = p.token_text().to_string();
+            let operator_span = p.token_span();
+            p.bump_any(); // Consume the operator
+            whitespace::skip_ws(p);
+
+            if !at_value_start(p) {
+                missing_operator_value(p, &operator, operator_span);
+            }
+            if at_value_start(p) {
+                value_expr_bp(p, power);
+            }
+
+            lhs = m.complete(p, BINARY_EXPR);
+        }
+    }
+
+    /// Returns the binding power of the arithmetic operator after the current
+    /// whitespace, if there is one.
+    fn binary_operator_ahead(p: &Parser<'_>) -> Option<u8> {
+        let mut n = 0;
+        while p.nth_at(n, WHITESPACE) {
+            n += 1;
+        }
+        match p.nth(n) {
+            T![+] | T![-] => Some(1),
+            T![*] | T![/] => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Reports an arithmetic operator that is not followed by a value.
+    ///
+    /// # Behavior
+    /// Tokens that can not start a value are skipped into an [`ERROR`] node,
+    /// up to the next value or the end of the operand, so a stray token does
+    /// not derail the rest of the expression.
+    ///
+    /// # Diagram
+    /// ```text
+    /// ┌──────────────────────────┐
+    /// │ LOAD buffer + ) 2        │ ← Error: expected a value after `+`
+    /// └──────────────────────────┘
+    ///                 ^
+    ///                 skipped
+    /// ```
+    fn missing_operator_value(p: &mut Parser<'_>, operator: &str, operator_span: Range<usize>) {
+        let message = format!("Expected a value after `{operator}`");
+        let help = "Operators must be followed by a number, character or identifier";
+
+        if p.at_ts(OPERAND_END) {
+            // Nothing follows the operator, so it can simply be removed
+            p.error_with_builder(
+                Diagnostic::builder()
+                    .with_message(message)
+                    .with_help(help)
+                    .with_primary_span(operator_span.clone(), "operator without a value")
+                    .with_suggestion(operator_span, "", Applicability::MaybeIncorrect),
+            );
+            return;
+        }
+
+        let m = p.start();
+        p.error_with_builder(
+            Diagnostic::builder()
+                .with_message(message)
+                .with_help(help)
+                .with_primary_span(p.token_span(), "expected a value here")
+                .with_secondary_span(operator_span, "after this operator"),
+        );
+        while !p.at_ts(OPERAND_END) && !at_value_start(p) {
+            p.bump_any();
+        }
+        m.complete(p, ERROR);
+    }
+
     /// Parses an operand value.
     ///
     /// # Structure
-    /// An operand value is a number or identifier, optionally followed by an array accessor.
+    /// An operand value is a number or identifier, optionally followed by an array accessor,
+    /// a negative number such as `-5`, or a character literal such as `'A'`.
     ///
     /// # Returns
     /// Completes an [`OPERAND_VALUE`] syntax node.
@@ -678,11 +812,17 @@ mod expr {
     /// │                               │
     /// └───────────────────────────────┘
     /// ```
-    pub(super) fn operand_value(p: &mut Parser<'_>) {
+    pub(super) fn operand_value(p: &mut Parser<'_>) -> CompletedMarker {
         let m = p.start();
 
-        // Parse the base value (number or identifier)
-        if p.at(NUMBER) || p.at(IDENTIFIER) {
+        // Parse the base value (number, character or identifier)
+        if p.at(T![-]) && p.nth_at(1, NUMBER) {
+            // Negative number, the sign must be right before the digits
+            p.bump_any(); // Consume -
+            p.bump_any(); // Consume the number
+        } else if p.at(CHAR) {
+            character(p);
+        } else if p.at(NUMBER) || p.at(IDENTIFIER) {
             p.bump_any();
 
             // Check for array accessor [index]
@@ -698,7 +838,39 @@ mod expr {
             );
         }
 
-        m.complete(p, OPERAND_VALUE);
+        m.complete(p, OPERAND_VALUE)
+    }
+
+    /// Returns whether the current token starts an operand value.
+    fn at_value_start(p: &Parser<'_>) -> bool {
+        p.at_ts(TokenSet::VALUE_START) && (!p.at(T![-]) || p.nth_at(1, NUMBER))
+    }
+
+    /// Parses a character literal.
+    ///
+    /// # Behavior
+    /// Reports literals that are not closed, or that do not hold exactly one
+    /// character or escape sequence.
+    ///
+    /// # Diagram
+    /// ```text
+    /// ┌──────────────────────────┐
+    /// │ LOAD ='AB'               │ ← Error: invalid character literal
+    /// └──────────────────────────┘
+    ///        ^^^^
+    /// ```
+    fn character(p: &mut Parser<'_>) {
+        let span = p.token_span();
+        let valid = ram_syntax::parse_char_literal(p.token_text()).is_some();
+        p.bump(CHAR);
+
+        if !valid {
+            p.error(
+                "Invalid character literal",
+                "Character literals hold a single character or escape, such as 'A' or '\\n'",
+                span,
+            );
+        }
     }
 
     /// Parses an array accessor.
@@ -729,7 +901,8 @@ mod expr {
         p.bump_any(); // Consume '['
 
         // Parse the index (must be a number or identifier)
-        if p.at(NUMBER) || p.at(IDENTIFIER) {
+        let has_index = p.at(NUMBER) || p.at(IDENTIFIER);
+        if has_index {
             p.bump_any();
         } else {
             p.error(
@@ -744,11 +917,22 @@ mod expr {
             p.bump_any();
         } else {
             // Report unclosed bracket error
-            p.error(
-                "Unclosed bracket in array accessor",
-                "Add a closing bracket ']' to complete the array accessor",
-                open_bracket_span,
-            );
+            let mut builder = Diagnostic::builder()
+                .with_message("Unclosed bracket in array accessor")
+                .with_help("Add a closing bracket ']' to complete the array accessor")
+                .with_primary_span(open_bracket_span, "unclosed bracket")
+                .with_code(codes::UNCLOSED_BRACKET.code);
+
+            // Only suggest closing the bracket when the index itself is valid
+            if has_index {
+                let insert_at = p.prev_token_end();
+                builder = builder.with_suggestion(
+                    insert_at..insert_at,
+                    "]",
+                    Applicability::MachineApplicable,
+                );
+            }
+            p.error_with_builder(builder);
         }
 
         m.complete(p, ARRAY_ACCESSOR);
@@ -797,12 +981,16 @@ mod labels {
         let m = p.start();
 
         // Parse the label name
+        let name_span = p.token_span();
         if p.at(IDENTIFIER) {
             p.bump_any();
         } else {
             // This shouldn't happen due to the at_label_definition_start check
-            let span = p.token_span();
-            p.error("Expected a label name", "Label names must start with a letter", span);
+            p.error(
+                "Expected a label name",
+                "Label names must start with a letter",
+                name_span.clone(),
+            );
         }
 
         // Consume whitespace between label name and colon
@@ -812,9 +1000,19 @@ mod labels {
         if p.at(T![:]) {
             p.bump_any();
         } else {
-            // This shouldn't happen due to the at_label_definition_start check
-            let span = p.token_span();
-            p.error("Expected a colon after label name", "Add a colon after the label name", span);
+            // A label name directly followed by an instruction, such as `loop LOAD 1`
+            p.error_with_builder(
+                Diagnostic::builder()
+                    .with_message("Expected a colon after label name")
+                    .with_help("Add a colon after the label name")
+                    .with_primary_span(name_span.clone(), "label defined here")
+                    .with_code(codes::MISSING_LABEL_COLON.code)
+                    .with_suggestion(
+                        name_span.end..name_span.end,
+                        ":",
+                        Applicability::MachineApplicable,
+                    ),
+            );
         }
 
         m.complete(p, LABEL_DEF);
@@ -254,14 +254,18 @@ impl<'a> Lexer<'a> {
             Some(':') => Some(self.tokenize_single_char(COLON)),
             Some('*') => Some(self.tokenize_single_char(STAR)),
             Some('=') => Some(self.tokenize_single_char(EQUALS)),
+            Some('+') => Some(self.tokenize_single_char(PLUS)),
+            Some('-') => Some(self.tokenize_single_char(MINUS)),
+            Some('/') => Some(self.tokenize_single_char(SLASH)),
             Some('[') => Some(self.tokenize_single_char(LBRACKET)),
             Some(']') => Some(self.tokenize_single_char(RBRACKET)),
             Some('{') => Some(self.tokenize_single_char(LBRACE)),
             Some('}') => Some(self.tokenize_single_char(RBRACE)),
             Some(',') => Some(self.tokenize_single_char(COMMA)),
 
-            // String literals
-            Some(c @ ('"' | '\'')) => Some(self.tokenize_string(c)),
+            // String and character literals
+            Some('"') => Some(self.tokenize_string('"')),
+            Some('\'') => Some(Token { kind: CHAR, ..self.tokenize_string('\'') }),
 
             // Numbers and identifiers
             Some(c) if c.is_ascii_digit() => Some(self.tokenize_number()),
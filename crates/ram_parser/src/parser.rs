@@ -10,8 +10,8 @@ use std::cell::Cell;
 use std::ops::Range;
 
 use drop_bomb::DropBomb;
-use ram_syntax::SyntaxKind;
 use ram_syntax::SyntaxKind::*;
+use ram_syntax::{SyntaxKind, TokenSet};
 
 use crate::diagnostic::{Diagnostic, DiagnosticBuilder, DiagnosticKind};
 use crate::event::Event;
@@ -46,8 +46,11 @@ pub fn parse(source: &str) -> (Vec<Event>, Vec<Diagnostic>) {
 ///
 /// This function converts our internal Diagnostic to the ram_error types
 /// that can be used with miette for nice error reporting.
-pub fn convert_errors(source: &str, errors: Vec<Diagnostic>) -> ram_error::Report {
-    crate::diagnostic::convert_errors(source, errors)
+pub fn convert_errors(
+    sources: &crate::diagnostic::SourceMap,
+    errors: Vec<Diagnostic>,
+) -> ram_error::Report {
+    crate::diagnostic::convert_errors(sources, errors)
 }
 
 /// `Parser` struct provides the low-level API for
@@ -104,6 +107,18 @@ impl<'t> Parser<'t> {
         self.inp.token(self.pos).map_or(0..0, |t| t.span.clone())
     }
 
+    /// Returns the end of the last consumed token.
+    ///
+    /// This is where missing tokens are inserted by suggested edits.
+    pub(crate) fn prev_token_end(&self) -> usize {
+        self.pos.checked_sub(1).and_then(|pos| self.inp.token(pos)).map_or(0, |t| t.span.end)
+    }
+
+    /// Lookahead operation: returns the text of the next nth token.
+    pub(crate) fn nth_text(&self, n: usize) -> &str {
+        self.inp.token(self.pos + n).map_or("", |t| &t.text)
+    }
+
     /// Checks if the current token is `kind`.
     pub(crate) fn at(&self, kind: SyntaxKind) -> bool {
         self.current() == kind
@@ -374,7 +389,7 @@ impl<'t> Parser<'t> {
     /// Returns true if the current token looks like the start of an instruction.
     pub(crate) fn at_instruction_start(&self) -> bool {
         // All instructions are now identifiers
-        self.current() == IDENTIFIER
+        self.current().is_opcode_kw()
     }
 
     /// Returns true if the current token looks like the start of a label definition.
@@ -393,6 +408,23 @@ impl<'t> Parser<'t> {
         false
     }
 
+    /// Returns true if the current token looks like a label definition that is
+    /// missing its colon, such as `loop LOAD 1`.
+    ///
+    /// This is only the case when the identifier is not an instruction itself
+    /// and is followed by one on the same line.
+    pub(crate) fn at_label_missing_colon(&self) -> bool {
+        if !self.at(IDENTIFIER) || grammar::is_instruction_name(self.token_text()) {
+            return false;
+        }
+
+        let mut n = 1;
+        while self.nth_at(n, WHITESPACE) {
+            n += 1;
+        }
+        n > 1 && self.nth_at(n, IDENTIFIER) && grammar::is_instruction_name(self.nth_text(n))
+    }
+
     /// Returns the current position in the token stream.
     /// This is useful for tracking progress in the parser.
     pub(crate) fn current_pos(&self) -> usize {
@@ -476,32 +508,6 @@ impl Input {
     }
 }
 
-// Macro for token set operations
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) struct TokenSet(u128);
-
-impl TokenSet {
-    pub(crate) const EMPTY: TokenSet = TokenSet(0);
-
-    pub(crate) const fn new(kinds: &[SyntaxKind]) -> TokenSet {
-        let mut res = 0u128;
-        let mut i = 0;
-        while i < kinds.len() {
-            let kind = kinds[i] as usize;
-            if kind < 128 {
-                res |= 1u128 << kind;
-            }
-            i += 1;
-        }
-        TokenSet(res)
-    }
-
-    pub(crate) const fn contains(&self, kind: SyntaxKind) -> bool {
-        let kind = kind as usize;
-        kind < 128 && (self.0 & (1u128 << kind)) != 0
-    }
-}
-
 /// See [`Parser::start`].
 ///
 /// A marker remembers the position of a syntax tree node that is in the process
@@ -572,3 +572,228 @@ fn test_module_statements() {
     );
     assert!(has_use_stmt, "Missing USE_STMT node in events");
 }
+
+/// Helper function to find the first error with the given code
+fn find_code<'a>(errors: &'a [Diagnostic], code: &str) -> &'a Diagnostic {
+    errors
+        .iter()
+        .find(|e| e.code.as_deref() == Some(code))
+        .unwrap_or_else(|| panic!("Expected a {code} diagnostic, got: {errors:?}"))
+}
+
+#[test]
+fn test_suggestions() {
+    use crate::diagnostic::Applicability;
+
+    // Label missing its colon
+    let source = "loop LOAD 1\n     JUMP loop\n";
+    let (_events, errors) = parse_test(source);
+    let error = find_code(&errors, "E004");
+    assert_eq!(error.suggestions.len(), 1);
+    assert_eq!(error.suggestions[0].span, 4..4);
+    assert_eq!(error.suggestions[0].replacement, ":");
+    assert_eq!(error.suggestions[0].applicability, Applicability::MachineApplicable);
+    assert_eq!(error.suggestions[0].apply_to_line(source).as_deref(), Some("loop: LOAD 1"));
+
+    // Unclosed bracket in an array accessor
+    let source = "LOAD x[2\nHALT\n";
+    let (_events, errors) = parse_test(source);
+    let error = find_code(&errors, "E005");
+    assert_eq!(error.suggestions[0].apply_to_line(source).as_deref(), Some("LOAD x[2]"));
+
+    // Unknown opcodes, which may come from plugins, are left to semantic analysis
+    let (_events, errors) = parse_test("LAOD 1\nLOADI 1\nJMP end\nSQRT 1\nend: HALT\n");
+    assert_no_errors(&errors);
+}
+
+#[test]
+fn test_operand_expressions() {
+    let count_binary = |events: &[Event]| {
+        events
+            .iter()
+            .filter(|e| match e {
+                Event::Placeholder { kind_slot } => *kind_slot == SyntaxKind::BINARY_EXPR,
+                Event::StartNodeBefore { kind, .. } => *kind == SyntaxKind::BINARY_EXPR,
+                _ => false,
+            })
+            .count()
+    };
+
+    // Arithmetic on numbers and identifiers, with and without whitespace
+    let (events, errors) = parse_test("LOAD buffer + 2 * N\nSTORE =N*2-1 # Comment\nHALT\n");
+    assert_no_errors(&errors);
+    assert_eq!(count_binary(&events), 4);
+
+    // An operator without a value can be removed
+    let source = "LOAD buffer+\nHALT\n";
+    let (events, errors) = parse_test(source);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("Expected a value after `+`"));
+    assert_eq!(errors[0].suggestions[0].apply_to_line(source).as_deref(), Some("LOAD buffer"));
+    assert_eq!(count_binary(&events), 1);
+
+    // Stray tokens after an operator are skipped up to the next value
+    let (events, errors) = parse_test("LOAD 1 + ) 2\nHALT\n");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("Expected a value after `+`"));
+    assert!(
+        events.iter().any(
+            |e| matches!(e, Event::Placeholder { kind_slot } if *kind_slot == SyntaxKind::ERROR)
+        )
+    );
+    assert_eq!(count_binary(&events), 1);
+}
+
+#[test]
+fn test_negative_and_character_literals() {
+    let (_, errors) = parse_test("LOAD =-5\nLOAD ='A'\nLOAD ='\\n'\nSUB =2-'0'\nWRITE -1\n");
+    assert_no_errors(&errors);
+
+    let (_, errors) = parse_test("LOAD ='AB'\nHALT\n");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("Invalid character literal"));
+}
+
+#[test]
+fn test_token_sets() {
+    use ram_syntax::TokenSet;
+
+    assert!(SyntaxKind::IDENTIFIER.is_opcode_kw());
+    assert!(!SyntaxKind::NUMBER.is_opcode_kw());
+    for kind in [SyntaxKind::STAR, SyntaxKind::EQUALS, SyntaxKind::NUMBER, SyntaxKind::CHAR] {
+        assert!(kind.is_operand_start(), "{kind:?} starts an operand");
+    }
+    assert!(!SyntaxKind::LBRACKET.is_operand_start());
+    assert!(SyntaxKind::NEWLINE.is_trivia() && SyntaxKind::COMMENT_GROUP.is_trivia());
+    assert!(!SyntaxKind::IDENTIFIER.is_trivia());
+    assert!(SyntaxKind::HASH_STAR.is_comment() && !SyntaxKind::HASH_STAR.is_whitespace());
+    assert!(SyntaxKind::BINARY_EXPR.is_operand() && SyntaxKind::SLASH.is_punct());
+
+    let set = TokenSet::new(&[SyntaxKind::COLON]).union(TokenSet::STATEMENT_END);
+    assert!(set.contains(SyntaxKind::COLON) && set.contains(SyntaxKind::EOF));
+    assert!(!TokenSet::EMPTY.contains(SyntaxKind::ROOT));
+}
+
+#[test]
+fn test_token_cursor() {
+    use ram_syntax::cursor::{TokenCursor, TokenRole};
+    use ram_syntax::{SyntaxNode, TokenSet};
+
+    let (events, errors) = parse_test("loop: JGTZ loop # back\nHALT\n");
+    assert_no_errors(&errors);
+    let (green_node, interner) = crate::build_tree(events);
+    let tree = SyntaxNode::new_root_with_resolver(green_node, interner);
+    let mut cursor = TokenCursor::new(&tree);
+
+    let roles = cursor
+        .tokens()
+        .iter()
+        .filter(|token| !token.kind.is_whitespace())
+        .map(|token| (token.text.as_str(), token.role()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        roles,
+        vec![
+            ("loop", TokenRole::LabelDef),
+            (":", TokenRole::Punct),
+            ("JGTZ", TokenRole::Opcode),
+            ("loop", TokenRole::Name),
+            ("#", TokenRole::Comment),
+            (" back", TokenRole::Comment),
+            ("HALT", TokenRole::Opcode),
+        ]
+    );
+
+    // Right after a word, the cursor is on the word rather than the space
+    assert_eq!(cursor.token_at(10).map(|token| token.text.as_str()), Some("JGTZ"));
+    assert_eq!(cursor.token_at(6).map(|token| token.text.as_str()), Some("JGTZ"));
+
+    cursor.seek(10);
+    assert!(cursor.at(SyntaxKind::IDENTIFIER));
+    assert_eq!(cursor.bump().map(|token| token.text.as_str()), Some("JGTZ"));
+    cursor.skip(TokenSet::WHITESPACE);
+    assert_eq!(cursor.current().map(|token| token.role()), Some(TokenRole::Name));
+    assert_eq!(
+        cursor.prev_not_in(TokenSet::WHITESPACE).map(|token| token.role()),
+        Some(TokenRole::Opcode)
+    );
+}
+
+#[test]
+fn test_trivia_attachment() {
+    use ram_syntax::{AstNode, Program, SyntaxNode};
+
+    let source = "\
+# Reads the input
+#* The entry point
+start: READ 1   # Read it
+    LOAD 1 #* not a doc
+
+# Detached
+
+HALT
+";
+    let (events, errors) = parse_test(source);
+    assert_no_errors(&errors);
+    let (green_node, interner) = crate::build_tree(events);
+    let program = Program::cast(SyntaxNode::new_root_with_resolver(green_node, interner)).unwrap();
+    let statements = program.statements().collect::<Vec<_>>();
+    assert_eq!(statements.len(), 4);
+
+    // Comments above a statement lead it, comments on its line trail it
+    let start = &statements[0];
+    assert!(start.label_def().is_some() && !start.is_comment());
+    assert_eq!(start.leading_comments().len(), 2);
+    let docs = start.doc_comments().iter().filter_map(|doc| doc.text()).collect::<Vec<_>>();
+    assert_eq!(docs, vec![" The entry point".to_string()]);
+    let trailing = start.trailing_comment().unwrap();
+    assert_eq!(trailing.syntax().text().to_string(), "# Read it");
+
+    // A trailing doc comment does not document the statement
+    let load = &statements[1];
+    assert!(load.doc_comments().is_empty());
+    assert!(load.trailing_comment().is_some());
+
+    // Comments separated by a blank line stay statements of their own
+    assert!(statements[2].is_comment());
+    assert!(statements[2].leading_comments().is_empty());
+    assert!(statements[3].leading_comments().is_empty());
+
+    // Nodes end at their last significant token
+    assert_eq!(load.syntax().text().to_string(), "LOAD 1 #* not a doc");
+
+    // The full range of a statement takes its comments, the code range does not
+    let text = |range: cstree::text::TextRange| {
+        source[usize::from(range.start())..usize::from(range.end())].to_string()
+    };
+    assert_eq!(
+        text(start.full_range()),
+        "# Reads the input\n#* The entry point\nstart: READ 1   # Read it"
+    );
+    assert_eq!(text(start.code_range().unwrap()), "start: READ 1");
+    assert_eq!(text(load.code_range().unwrap()), "LOAD 1");
+    assert!(statements[2].code_range().is_none());
+    assert_eq!(statements[2].syntax().text().to_string(), "# Detached");
+}
+
+#[test]
+fn test_round_trip() {
+    use ram_syntax::{AstNode, Program, SyntaxNode};
+
+    let sources = [
+        "",
+        "\n\n",
+        "HALT",
+        "  LOAD 1   # trailing  \n\n\n# detached\n",
+        "#* doc\n#* more\nloop:\n\tJUMP loop # back\r\n",
+        "mod io\nuse io::*\n# note\nWRITE =-5\n",
+        "LOAD 1 ] 2\nfoo:\n",
+    ];
+    for source in sources {
+        let (events, _) = parse_test(source);
+        let (green_node, interner) = crate::build_tree(events);
+        let tree = SyntaxNode::new_root_with_resolver(green_node, interner);
+        let program = Program::cast(tree).unwrap();
+        assert_eq!(program.to_string(), source);
+    }
+}
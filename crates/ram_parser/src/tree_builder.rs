@@ -9,10 +9,25 @@ use crate::event::Event;
 /// This struct encapsulates the process of transforming parser events
 /// into a proper syntax tree structure. It handles several phases:
 ///
-/// 1. Cleaning events (removing tombstones, converting placeholders)
-/// 2. Processing hierarchical node relationships (StartNodeBefore events)
+/// 1. Processing hierarchical node relationships (StartNodeBefore events)
+/// 2. Cleaning events (removing tombstones, converting placeholders)
 /// 3. Ensuring the event stream is properly balanced
-/// 4. Building the final green tree
+/// 4. Deciding which node owns the whitespace and comments (trivia)
+/// 5. Building the final green tree
+///
+/// # Trivia
+///
+/// Whitespace and comments are kept in the tree, so it prints back to the
+/// exact source, and are owned by the following rules:
+///
+/// - Nodes end at their last significant token. The whitespace and newlines
+///   after a node belong to the node containing it.
+/// - A comment group on the line of a statement is its trailing comment.
+/// - Comment groups on the lines right above a statement, without a blank
+///   line in between, are its leading comments.
+///
+/// Attached comment groups are children of the statement they belong to.
+/// Comments separated from every statement stay statements of their own.
 pub struct TreeBuilder {
     events: Vec<Event>,
     builder: GreenNodeBuilder<'static, 'static, Ram>,
@@ -31,9 +46,11 @@ impl TreeBuilder {
     pub fn build(mut self) -> (GreenNode, impl Interner) {
         // Process the events in multiple passes to build a proper tree
         if !self.events.is_empty() {
-            self.clean_events();
             self.process_start_node_before();
+            self.clean_events();
             self.balance_events();
+            self.hoist_trailing_whitespace();
+            self.attach_comments();
         }
 
         self.build_tree()
@@ -52,39 +69,47 @@ impl TreeBuilder {
             .collect();
     }
 
-    /// Process StartNodeBefore events and convert them to regular StartNode/FinishNode pairs
+    /// Process StartNodeBefore events and convert them to regular StartNode events
+    ///
+    /// A StartNodeBefore event marks a node that was wrapped by a node started
+    /// later, at `before_pos`, with [`CompletedMarker::precede`]. The wrapping
+    /// nodes are started right before the wrapped one, outermost first, and
+    /// their original start events are removed. Their FinishNode events are
+    /// already in place, after the one of the wrapped node.
+    ///
+    /// This runs before tombstones are removed, so `before_pos` still points
+    /// at the start event of the wrapping node.
     ///
-    /// Handles the conversion of StartNodeBefore events (which represent nodes that should
-    /// "wrap around" previously created nodes) into proper StartNode/FinishNode pairs.
+    /// [`CompletedMarker::precede`]: crate::parser::CompletedMarker::precede
     fn process_start_node_before(&mut self) {
-        let mut result = Vec::with_capacity(self.events.len() * 2); // Allocate extra space
-        let mut i = 0;
-        let events = std::mem::take(&mut self.events);
+        let mut events = std::mem::take(&mut self.events);
+        let mut result = Vec::with_capacity(events.len());
+        let mut kinds = Vec::new();
 
-        while i < events.len() {
-            match &events[i] {
+        for i in 0..events.len() {
+            match std::mem::replace(&mut events[i], Event::Tombstone) {
                 Event::StartNodeBefore { kind, before_pos } => {
-                    let before_pos = *before_pos;
-                    let kind = *kind;
-
-                    // Insert a StartNode at the current position
-                    result.push(Event::StartNode { kind });
-
-                    // Copy all subsequent events until we reach before_pos
-                    let events_to_include = &events[i + 1..before_pos.min(events.len())];
-                    result.extend_from_slice(events_to_include);
+                    kinds.push(kind);
 
-                    // Add the FinishNode for our StartNode
-                    result.push(Event::FinishNode);
+                    // Follow the chain of wrapping nodes, removing their start events
+                    let mut pos = before_pos;
+                    while let Some(event) = events.get_mut(pos) {
+                        match std::mem::replace(event, Event::Tombstone) {
+                            Event::StartNodeBefore { kind, before_pos } => {
+                                kinds.push(kind);
+                                pos = before_pos;
+                            }
+                            Event::Placeholder { kind_slot: kind } | Event::StartNode { kind } => {
+                                kinds.push(kind);
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
 
-                    // Skip ahead to before_pos
-                    i = before_pos;
-                }
-                _ => {
-                    // Copy the event as-is
-                    result.push(events[i].clone());
-                    i += 1;
+                    result.extend(kinds.drain(..).rev().map(|kind| Event::StartNode { kind }));
                 }
+                event => result.push(event),
             }
         }
 
@@ -138,6 +163,72 @@ impl TreeBuilder {
         self.events = result;
     }
 
+    /// Move the whitespace at the end of nodes out of them
+    ///
+    /// The whitespace and newlines the parser consumed at the end of a node,
+    /// such as the line break after a comment group, move up until they reach
+    /// a node they are not at the end of. The root keeps the whitespace at its
+    /// end.
+    fn hoist_trailing_whitespace(&mut self) {
+        let mut result = Vec::with_capacity(self.events.len());
+        let mut depth = 0usize;
+
+        for event in self.events.drain(..) {
+            match event {
+                Event::StartNode { .. } => {
+                    depth += 1;
+                    result.push(event);
+                }
+                Event::FinishNode => {
+                    depth = depth.saturating_sub(1);
+                    let whitespace = if depth == 0 {
+                        0
+                    } else {
+                        result.iter().rev().take_while(|event| is_whitespace(event)).count()
+                    };
+                    let trailing = result.split_off(result.len() - whitespace);
+                    result.push(Event::FinishNode);
+                    result.extend(trailing);
+                }
+                _ => result.push(event),
+            }
+        }
+
+        self.events = result;
+    }
+
+    /// Move comment statements into the statements they belong to
+    ///
+    /// Trailing comments are attached first, so a comment on the line of a
+    /// statement is never taken as a leading comment of the next one.
+    fn attach_comments(&mut self) {
+        // The root must hold every other node for its children to be moved
+        let last = self.events.len().saturating_sub(1);
+        let mut depth = 0usize;
+        let single_root =
+            matches!(self.events.first(), Some(Event::StartNode { kind: SyntaxKind::ROOT }))
+                && self.events.iter().enumerate().all(|(index, event)| {
+                    match event {
+                        Event::StartNode { .. } => depth += 1,
+                        Event::FinishNode => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    depth > 0 || index == last
+                });
+        if !single_root {
+            return;
+        }
+
+        let mut events = std::mem::take(&mut self.events);
+        let finish = events.pop();
+        let mut events = events.into_iter();
+        let root = events.next();
+
+        let children = attach_leading_comments(attach_trailing_comments(split_children(events)));
+        self.events =
+            root.into_iter().chain(children.into_iter().flatten()).chain(finish).collect();
+    }
+
     /// Build the tree from the processed events
     fn build_tree(mut self) -> (GreenNode, impl Interner) {
         // Handle empty events by creating a minimal valid tree
@@ -182,3 +273,149 @@ impl TreeBuilder {
 pub fn build_tree(events: Vec<Event>) -> (GreenNode, impl Interner) {
     TreeBuilder::new(events).build()
 }
+
+/// The events of a child of the root: a token, or a node and its descendants
+type Child = Vec<Event>;
+
+/// What a child of the root is to the attachment of comments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// Whitespace, with the number of line breaks in it
+    Whitespace(usize),
+    /// A statement holding only comments
+    Comments,
+    /// A statement holding code, such as an instruction or a label
+    Statement,
+    /// Anything else
+    Other,
+}
+
+/// Check whether an event adds a whitespace token
+fn is_whitespace(event: &Event) -> bool {
+    matches!(event, Event::AddToken { kind, .. } if kind.is_whitespace())
+}
+
+/// Split the events inside the root into its children
+fn split_children(events: impl Iterator<Item = Event>) -> Vec<Child> {
+    let mut children = Vec::new();
+    let mut child = Vec::new();
+    let mut depth = 0usize;
+
+    for event in events {
+        match event {
+            Event::StartNode { .. } => depth += 1,
+            Event::FinishNode => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        child.push(event);
+        if depth == 0 {
+            children.push(std::mem::take(&mut child));
+        }
+    }
+    if !child.is_empty() {
+        children.push(child);
+    }
+    children
+}
+
+/// Find what a child of the root is
+fn role(child: &[Event]) -> Role {
+    match child {
+        [Event::AddToken { kind: SyntaxKind::WHITESPACE, .. }] => Role::Whitespace(0),
+        [Event::AddToken { kind: SyntaxKind::NEWLINE, .. }] => Role::Whitespace(1),
+        [Event::StartNode { kind: SyntaxKind::STMT }, ..] => {
+            let mut depth = 0usize;
+            let mut kinds = Vec::new();
+            for event in child {
+                match event {
+                    Event::StartNode { kind } => {
+                        if depth == 1 {
+                            kinds.push(*kind);
+                        }
+                        depth += 1;
+                    }
+                    Event::FinishNode => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            if !kinds.is_empty() && kinds.iter().all(|kind| *kind == SyntaxKind::COMMENT_GROUP) {
+                Role::Comments
+            } else {
+                Role::Statement
+            }
+        }
+        _ => Role::Other,
+    }
+}
+
+/// Take the events inside a node, without the node itself
+fn into_inner(mut node: Child) -> impl Iterator<Item = Event> {
+    node.pop();
+    node.into_iter().skip(1)
+}
+
+/// Move comment statements into the statement before them on the same line
+fn attach_trailing_comments(children: Vec<Child>) -> Vec<Child> {
+    let mut result: Vec<Child> = Vec::with_capacity(children.len());
+
+    for child in children {
+        if role(&child) == Role::Comments
+            && let Some(owner) = result.iter().rposition(|child| role(child) != Role::Whitespace(0))
+            && role(&result[owner]) == Role::Statement
+        {
+            let whitespace = result.split_off(owner + 1);
+            let statement = &mut result[owner];
+            let finish = statement.pop();
+            statement.extend(whitespace.into_iter().flatten());
+            statement.extend(into_inner(child));
+            statement.extend(finish);
+            continue;
+        }
+        result.push(child);
+    }
+    result
+}
+
+/// Move comment statements into the statement on the lines right below them
+fn attach_leading_comments(children: Vec<Child>) -> Vec<Child> {
+    let mut result = Vec::with_capacity(children.len());
+    // Comment statements and the whitespace after them, waiting for a statement
+    let mut pending: Vec<Child> = Vec::new();
+    let mut newlines = 0;
+
+    for child in children {
+        match role(&child) {
+            Role::Comments => {
+                pending.push(child);
+                newlines = 0;
+            }
+            Role::Whitespace(lines) if !pending.is_empty() => {
+                pending.push(child);
+                newlines += lines;
+                // A blank line separates the comments from what follows
+                if newlines > 1 {
+                    result.append(&mut pending);
+                }
+            }
+            Role::Statement if !pending.is_empty() => {
+                let mut child = child.into_iter();
+                let mut statement = child.next().into_iter().collect::<Child>();
+                for pending in pending.drain(..) {
+                    if role(&pending) == Role::Comments {
+                        statement.extend(into_inner(pending));
+                    } else {
+                        statement.extend(pending);
+                    }
+                }
+                statement.extend(child);
+                result.push(statement);
+            }
+            _ => {
+                result.append(&mut pending);
+                result.push(child);
+            }
+        }
+    }
+    result.append(&mut pending);
+    result
+}
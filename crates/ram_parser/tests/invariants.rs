@@ -0,0 +1,116 @@
+//! Property-based tests for the invariants the parser upholds on any input.
+//!
+//! Whatever the text, the parser must not panic, the tree it builds must cover
+//! the whole text and print back to it byte for byte, no node but the root
+//! may end with whitespace, and every error node must come with a diagnostic. Inputs are arbitrary strings and the example
+//! programs of the `inputs` directory, mutated.
+
+use std::fs;
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+use ram_parser::Diagnostic;
+use ram_syntax::{AstNode, Program, ResolvedNode, SyntaxKind, SyntaxNode};
+
+/// Parse a source and build its syntax tree
+fn parse_tree(source: &str) -> (ResolvedNode, Vec<Diagnostic>) {
+    let (events, errors) = ram_parser::parse(source);
+    let (green_node, interner) = ram_parser::build_tree(events);
+    (SyntaxNode::new_root_with_resolver(green_node, interner), errors)
+}
+
+/// Check the invariants of the tree built for a source
+fn check_invariants(source: &str) -> Result<(), TestCaseError> {
+    let (tree, errors) = parse_tree(source);
+
+    let range = tree.text_range();
+    prop_assert_eq!(usize::from(range.start()), 0, "tree does not start the text");
+    prop_assert_eq!(usize::from(range.end()), source.len(), "tree does not cover the text");
+    prop_assert_eq!(tree.text().to_string(), source, "tree does not print back to the text");
+    let program = Program::cast(tree.clone());
+    prop_assert_eq!(program.map(|program| program.to_string()).as_deref(), Some(source));
+
+    // Whitespace at the end of a node belongs to the node containing it
+    for node in tree.descendants().skip(1) {
+        let last = node.last_token();
+        prop_assert!(
+            last.is_none_or(|token| !token.kind().is_whitespace()),
+            "{:?} node ends with whitespace",
+            node.kind()
+        );
+    }
+
+    for node in tree.descendants().filter(|node| node.kind() == SyntaxKind::ERROR) {
+        let range = node.text_range();
+        let (start, end) = (usize::from(range.start()), usize::from(range.end()));
+        let reported = errors.iter().any(|error| {
+            error.labeled_spans.iter().any(|(span, _)| span.start <= end && start <= span.end)
+        });
+        prop_assert!(reported, "error node at {}..{} has no diagnostic", start, end);
+    }
+    Ok(())
+}
+
+/// Read the example programs of the `inputs` directory
+fn examples() -> Vec<String> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../inputs");
+    let mut examples = fs::read_dir(dir)
+        .expect("the inputs directory should exist")
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ram"))
+        .map(|path| fs::read_to_string(path).expect("examples should be valid UTF-8"))
+        .collect::<Vec<_>>();
+    examples.sort();
+    examples
+}
+
+// Generate an edit of a source: delete, replace or insert text at a position
+fn mutation_strategy() -> impl Strategy<Value = (prop::sample::Index, usize, String)> {
+    (any::<prop::sample::Index>(), 0..8usize, "[A-Z0-9 \n#*=:\\[\\]{}+-/,'\"]{0,4}|\\PC{0,2}")
+}
+
+/// Apply an edit to a source, keeping it valid UTF-8
+fn mutate(
+    source: &str,
+    (position, deleted, inserted): &(prop::sample::Index, usize, String),
+) -> String {
+    let boundaries =
+        (0..=source.len()).filter(|&index| source.is_char_boundary(index)).collect::<Vec<_>>();
+    let start = *position.get(&boundaries);
+    let end = boundaries
+        .iter()
+        .copied()
+        .filter(|&index| index >= start)
+        .nth(*deleted)
+        .unwrap_or(source.len());
+    format!("{}{}{}", &source[..start], inserted, &source[end..])
+}
+
+#[test]
+fn test_examples_uphold_invariants() {
+    for example in examples() {
+        check_invariants(&example).unwrap();
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_arbitrary_text(source in "\\PC*") {
+        check_invariants(&source)?;
+    }
+
+    #[test]
+    fn test_arbitrary_tokens(source in "[A-Za-z0-9_ \t\r\n#*=:\\[\\]{}()+\\-/,.'\"]{0,64}") {
+        check_invariants(&source)?;
+    }
+
+    #[test]
+    fn test_mutated_examples(
+        example in prop::sample::select(examples()),
+        mutations in prop::collection::vec(mutation_strategy(), 1..4)
+    ) {
+        let source = mutations.iter().fold(example, |source, mutation| mutate(&source, mutation));
+        check_invariants(&source)?;
+    }
+}
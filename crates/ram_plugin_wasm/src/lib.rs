@@ -2,7 +2,8 @@
 //!
 //! This crate provides the interface for implementing RAM plugins using
 //! the WebAssembly Component Model. Plugins can be loaded dynamically at runtime
-//! and provide custom instructions to the RAM virtual machine.
+//! and provide custom instructions and analysis passes to the RAM virtual
+//! machine.
 
 mod error;
 
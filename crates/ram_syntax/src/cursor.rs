@@ -0,0 +1,193 @@
+//! A cursor over the tokens of a syntax tree.
+//!
+//! The cursor flattens the tree into its tokens in source order, each with the
+//! kind of the node containing it, which tells what the token is for: an
+//! identifier is an opcode in an instruction and a label in a label
+//! definition. Tools that look at the tokens around an offset, such as
+//! completion, selection and highlighting, move the cursor instead of walking
+//! the tree.
+
+use std::ops::Range;
+
+use crate::{ResolvedNode, SyntaxKind, TokenSet, cstree};
+
+/// What a token stands for in the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenRole {
+    /// The opcode of an instruction
+    Opcode,
+    /// The name of a label where it is defined
+    LabelDef,
+    /// A name in the operand of an instruction, such as a label or a constant
+    Name,
+    /// A segment of a module path
+    Module,
+    /// A keyword of a module statement
+    Keyword,
+    /// A number
+    Number,
+    /// A character or string literal
+    Literal,
+    /// Punctuation or an operator
+    Punct,
+    /// A part of a comment
+    Comment,
+    /// Whitespace between tokens
+    Whitespace,
+    /// A token the parser could not make sense of
+    Error,
+}
+
+/// A token of the syntax tree, with the kind of the node containing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstToken {
+    /// The kind of the token
+    pub kind: SyntaxKind,
+    /// The kind of the node containing the token
+    pub parent: SyntaxKind,
+    /// The range of the token in the text
+    pub range: Range<usize>,
+    /// The text of the token
+    pub text: String,
+}
+
+impl CstToken {
+    /// Get what the token stands for
+    pub fn role(&self) -> TokenRole {
+        match self.kind {
+            SyntaxKind::IDENTIFIER => match self.parent {
+                SyntaxKind::INSTRUCTION => TokenRole::Opcode,
+                SyntaxKind::LABEL_DEF => TokenRole::LabelDef,
+                SyntaxKind::MODULE_PATH | SyntaxKind::MOD_STMT => TokenRole::Module,
+                _ => TokenRole::Name,
+            },
+            _ if self.parent.is_comment() => TokenRole::Comment,
+            SyntaxKind::MOD_KW | SyntaxKind::USE_KW => TokenRole::Keyword,
+            SyntaxKind::NUMBER => TokenRole::Number,
+            SyntaxKind::CHAR | SyntaxKind::STRING => TokenRole::Literal,
+            kind if kind.is_punct() => TokenRole::Punct,
+            kind if kind.is_comment() => TokenRole::Comment,
+            kind if kind.is_whitespace() => TokenRole::Whitespace,
+            _ => TokenRole::Error,
+        }
+    }
+
+    /// Check whether the token is in `set`
+    pub fn is_in(&self, set: TokenSet) -> bool {
+        set.contains(self.kind)
+    }
+}
+
+/// A position among the tokens of a syntax tree
+#[derive(Debug, Clone)]
+pub struct TokenCursor {
+    tokens: Vec<CstToken>,
+    index: usize,
+}
+
+impl TokenCursor {
+    /// Create a cursor at the first token of a syntax tree
+    pub fn new(syntax_tree: &ResolvedNode) -> Self {
+        let mut tokens = syntax_tree
+            .descendants()
+            .flat_map(|node| {
+                let parent = node.kind();
+                node.children_with_tokens().filter_map(cstree::util::NodeOrToken::into_token).map(
+                    move |token| {
+                        let range = token.text_range();
+                        CstToken {
+                            kind: token.kind(),
+                            parent,
+                            range: usize::from(range.start())..usize::from(range.end()),
+                            text: token.text().to_string(),
+                        }
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        tokens.sort_by_key(|token| token.range.start);
+        Self { tokens, index: 0 }
+    }
+
+    /// Get every token of the tree, in source order
+    pub fn tokens(&self) -> &[CstToken] {
+        &self.tokens
+    }
+
+    /// Take the tokens of the tree, in source order
+    pub fn into_tokens(self) -> Vec<CstToken> {
+        self.tokens
+    }
+
+    /// Get the token at the cursor, or `None` past the last token
+    pub fn current(&self) -> Option<&CstToken> {
+        self.tokens.get(self.index)
+    }
+
+    /// Check whether the token at the cursor is of `kind`
+    pub fn at(&self, kind: SyntaxKind) -> bool {
+        self.current().is_some_and(|token| token.kind == kind)
+    }
+
+    /// Check whether the token at the cursor is in `set`
+    pub fn at_ts(&self, set: TokenSet) -> bool {
+        self.current().is_some_and(|token| token.is_in(set))
+    }
+
+    /// Move to the next token, returning the one the cursor was at
+    pub fn bump(&mut self) -> Option<&CstToken> {
+        let index = self.index;
+        self.index = (self.index + 1).min(self.tokens.len());
+        self.tokens.get(index)
+    }
+
+    /// Move forward past the tokens in `set`
+    pub fn skip(&mut self, set: TokenSet) {
+        while self.at_ts(set) {
+            self.index += 1;
+        }
+    }
+
+    /// Get the last token before the cursor that is not in `set`
+    pub fn prev_not_in(&self, set: TokenSet) -> Option<&CstToken> {
+        self.tokens[..self.index].iter().rev().find(|token| !token.is_in(set))
+    }
+
+    /// Move to the token at `offset`, or the first token after it
+    ///
+    /// See [`TokenCursor::token_at`] for the token chosen at the boundary of
+    /// two tokens.
+    pub fn seek(&mut self, offset: usize) {
+        self.index = self.index_at(offset).unwrap_or_else(|| {
+            self.tokens
+                .iter()
+                .position(|token| offset < token.range.start)
+                .unwrap_or(self.tokens.len())
+        });
+    }
+
+    /// Get the token at `offset`
+    ///
+    /// At the boundary of two tokens, the one after the offset is preferred
+    /// unless it is whitespace, as editors place the cursor right after the
+    /// word it refers to.
+    pub fn token_at(&self, offset: usize) -> Option<&CstToken> {
+        self.index_at(offset).map(|index| &self.tokens[index])
+    }
+
+    /// Find the index of the token at `offset`
+    fn index_at(&self, offset: usize) -> Option<usize> {
+        let touching = (0..self.tokens.len())
+            .filter(|&index| {
+                let range = &self.tokens[index].range;
+                range.start <= offset && offset <= range.end
+            })
+            .collect::<Vec<_>>();
+        touching
+            .iter()
+            .rev()
+            .find(|&&index| !self.tokens[index].kind.is_whitespace())
+            .or_else(|| touching.iter().find(|&&index| offset < self.tokens[index].range.end))
+            .copied()
+    }
+}
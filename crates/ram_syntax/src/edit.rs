@@ -0,0 +1,207 @@
+//! Structured edits of syntax trees
+//!
+//! Refactorings such as code actions describe their changes with the functions
+//! of this module instead of manipulating the source text. Each function returns
+//! the minimal [`TextEdit`] that splices a node into the file or removes one,
+//! taking care of the whitespace around it.
+
+use std::ops::Range;
+
+use crate::ast::AstNode;
+use crate::nodes::{Instruction, LabelDef, Operand, Program, Statement};
+use crate::{ResolvedNode, SyntaxKind};
+
+/// A replacement of a range of the source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// The range of the source text to replace
+    pub range: Range<usize>,
+    /// The text to replace it with
+    pub new_text: String,
+}
+
+impl TextEdit {
+    /// Replace `range` with `new_text`
+    pub fn replace(range: Range<usize>, new_text: impl Into<String>) -> Self {
+        Self { range, new_text: new_text.into() }
+    }
+
+    /// Insert `new_text` at `offset`
+    pub fn insert(offset: usize, new_text: impl Into<String>) -> Self {
+        Self::replace(offset..offset, new_text)
+    }
+
+    /// Delete `range`
+    pub fn delete(range: Range<usize>) -> Self {
+        Self::replace(range, String::new())
+    }
+
+    /// Apply the edit to `text`
+    pub fn apply(&self, text: &str) -> String {
+        let mut result = text.to_string();
+        result.replace_range(self.range.clone(), &self.new_text);
+        result
+    }
+}
+
+/// Rename a label definition
+pub fn rename_label(label: &LabelDef, new_name: &str) -> Option<TextEdit> {
+    let name = first_token(label.syntax(), SyntaxKind::IDENTIFIER)?;
+    Some(TextEdit::replace(name, new_name))
+}
+
+/// Remove the label definition of a statement
+///
+/// The whitespace separating the label from its instruction is removed too.
+/// If the instruction is on the next line, the line break is removed instead,
+/// so the instruction keeps its indentation.
+pub fn remove_label(statement: &Statement) -> Option<TextEdit> {
+    let label = statement.label_def()?;
+    let range = node_range(label.syntax());
+
+    let mut end = range.end;
+    let trailing = child_tokens(statement.syntax()).filter(|(_, token)| token.start >= range.end);
+    for (kind, token) in trailing {
+        match kind {
+            SyntaxKind::WHITESPACE => end = token.end,
+            SyntaxKind::NEWLINE => {
+                end = token.end;
+                break;
+            }
+            _ => break,
+        }
+    }
+    Some(TextEdit::delete(range.start..end))
+}
+
+/// Get the lines a statement spans, with its attached comments
+///
+/// The range starts at the indentation of the first leading comment of the
+/// statement and ends after the line break of its last line. If the
+/// statement shares a line with other code, only its attached comments and
+/// the whitespace after it are included.
+pub fn statement_lines(statement: &Statement) -> Range<usize> {
+    let range = node_range(statement.syntax());
+    let Some(parent) = statement.syntax().parent() else {
+        return range;
+    };
+    let siblings = child_elements(parent).collect::<Vec<_>>();
+
+    let mut start = range.start;
+    let before = siblings.iter().rev().filter(|(_, element)| element.end <= range.start);
+    for (kind, element) in before {
+        match kind {
+            SyntaxKind::WHITESPACE => start = element.start,
+            SyntaxKind::NEWLINE => break,
+            // Keep the indentation if there is code before it on the line
+            _ => {
+                start = range.start;
+                break;
+            }
+        }
+    }
+
+    let mut end = range.end;
+    for (kind, element) in siblings.iter().filter(|(_, element)| element.start >= range.end) {
+        match kind {
+            SyntaxKind::WHITESPACE => end = element.end,
+            SyntaxKind::NEWLINE => {
+                end = element.end;
+                break;
+            }
+            _ => break,
+        }
+    }
+    start..end
+}
+
+/// Remove a statement with the comments attached to it
+///
+/// The lines of the statement are removed whole, so no comment documenting
+/// it is left behind and no blank line is left in its place.
+pub fn remove_statement(statement: &Statement) -> TextEdit {
+    TextEdit::delete(statement_lines(statement))
+}
+
+/// Replace the opcode of an instruction, keeping its operand
+pub fn replace_opcode(instruction: &Instruction, new_opcode: &str) -> Option<TextEdit> {
+    let opcode = first_token(instruction.syntax(), SyntaxKind::IDENTIFIER)?;
+    Some(TextEdit::replace(opcode, new_opcode))
+}
+
+/// Replace the operand of an instruction, adding it if there is none
+pub fn replace_operand(instruction: &Instruction, operand: &Operand) -> Option<TextEdit> {
+    let new_text = operand.syntax().text().to_string();
+    match instruction.operand() {
+        Some(existing) => Some(TextEdit::replace(node_range(existing.syntax()), new_text)),
+        None => {
+            let opcode = first_token(instruction.syntax(), SyntaxKind::IDENTIFIER)?;
+            Some(TextEdit::insert(opcode.end, format!(" {}", new_text)))
+        }
+    }
+}
+
+/// Remove the operand of an instruction, with the whitespace before it
+pub fn remove_operand(instruction: &Instruction) -> Option<TextEdit> {
+    let operand = instruction.operand()?;
+    let opcode = first_token(instruction.syntax(), SyntaxKind::IDENTIFIER)?;
+    Some(TextEdit::delete(opcode.end..node_range(operand.syntax()).end))
+}
+
+/// Append an instruction to the end of a program
+///
+/// The instruction is placed on a new line, aligned with the last instruction
+/// of the program.
+pub fn append_instruction(program: &Program, instruction: &Instruction) -> TextEdit {
+    let text = program.syntax().text().to_string();
+    let indent = program
+        .statements()
+        .filter_map(|statement| statement.instruction())
+        .last()
+        .map(|last| {
+            let start = node_range(last.syntax()).start;
+            let line_start = text[..start].rfind('\n').map_or(0, |newline| newline + 1);
+            // Keep tabs so the alignment holds, and blank out labels before it
+            text[line_start..start]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+
+    let separator = if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+    TextEdit::insert(
+        text.len(),
+        format!("{}{}{}\n", separator, indent, instruction.syntax().text()),
+    )
+}
+
+/// Get the range of a node in the source text
+fn node_range(node: &ResolvedNode) -> Range<usize> {
+    let range = node.text_range();
+    usize::from(range.start())..usize::from(range.end())
+}
+
+/// Get the kinds and ranges of the tokens that are direct children of a node
+fn child_tokens(node: &ResolvedNode) -> impl Iterator<Item = (SyntaxKind, Range<usize>)> + '_ {
+    node.children_with_tokens().filter_map(cstree::util::NodeOrToken::into_token).map(|token| {
+        let range = token.text_range();
+        (token.kind(), usize::from(range.start())..usize::from(range.end()))
+    })
+}
+
+/// Get the kinds and ranges of the children of a node, nodes and tokens alike
+fn child_elements(node: &ResolvedNode) -> impl Iterator<Item = (SyntaxKind, Range<usize>)> + '_ {
+    node.children_with_tokens().map(|element| {
+        let (kind, range) = match element {
+            cstree::util::NodeOrToken::Node(node) => (node.kind(), node.text_range()),
+            cstree::util::NodeOrToken::Token(token) => (token.kind(), token.text_range()),
+        };
+        (kind, usize::from(range.start())..usize::from(range.end()))
+    })
+}
+
+/// Get the range of the first token of a kind among the children of a node
+fn first_token(node: &ResolvedNode, kind: SyntaxKind) -> Option<Range<usize>> {
+    child_tokens(node).find(|(token_kind, _)| *token_kind == kind).map(|(_, range)| range)
+}
@@ -4,9 +4,14 @@
 //! It is used by the parser to build a syntax tree from source code.
 
 pub mod ast;
+pub mod cursor;
+pub mod edit;
+pub mod make;
 pub mod nodes;
 mod syntax_kind;
+mod token_set;
 
 pub use ast::*;
 pub use cstree;
 pub use syntax_kind::*;
+pub use token_set::TokenSet;
@@ -0,0 +1,93 @@
+//! Constructors for syntax nodes
+//!
+//! This module builds syntax nodes from their parts, with the same structure
+//! and canonical spacing the parser produces. The nodes are the roots of their
+//! own trees, so they are meant to be spliced into a file with the functions
+//! of the [`edit`](crate::edit) module rather than navigated upwards.
+
+use cstree::prelude::*;
+
+use crate::ast::AstNode;
+use crate::nodes::{Instruction, LabelDef, Operand};
+use crate::{Ram, ResolvedNode, SyntaxKind, SyntaxNode};
+
+/// Build an instruction, such as `LOAD 1` or `HALT`
+pub fn instruction(opcode: &str, operand: Option<&Operand>) -> Instruction {
+    let node = build(SyntaxKind::INSTRUCTION, |builder| {
+        builder.token(SyntaxKind::IDENTIFIER, opcode);
+        if let Some(operand) = operand {
+            builder.token(SyntaxKind::WHITESPACE, " ");
+            copy_node(builder, operand.syntax());
+        }
+    });
+    Instruction::cast(node).unwrap()
+}
+
+/// Build a label definition, such as `loop:`
+pub fn label_def(name: &str) -> LabelDef {
+    let node = build(SyntaxKind::LABEL_DEF, |builder| {
+        builder.token(SyntaxKind::IDENTIFIER, name);
+        builder.token(SyntaxKind::COLON, ":");
+    });
+    LabelDef::cast(node).unwrap()
+}
+
+/// Build a direct operand, such as `5` or `loop`
+pub fn direct_operand(value: &str) -> Operand {
+    operand(SyntaxKind::DIRECT_OPERAND, None, value)
+}
+
+/// Build an indirect operand, such as `*5`
+pub fn indirect_operand(value: &str) -> Operand {
+    operand(SyntaxKind::INDIRECT_OPERAND, Some((SyntaxKind::STAR, "*")), value)
+}
+
+/// Build an immediate operand, such as `=5`
+pub fn immediate_operand(value: &str) -> Operand {
+    operand(SyntaxKind::IMMEDIATE_OPERAND, Some((SyntaxKind::EQUALS, "=")), value)
+}
+
+/// Build an operand of the given addressing mode
+///
+/// The value is a number if it parses as one, and an identifier otherwise.
+fn operand(kind: SyntaxKind, prefix: Option<(SyntaxKind, &str)>, value: &str) -> Operand {
+    let node = build(SyntaxKind::OPERAND, |builder| {
+        builder.start_node(kind);
+        if let Some((prefix_kind, prefix)) = prefix {
+            builder.token(prefix_kind, prefix);
+        }
+        builder.start_node(SyntaxKind::OPERAND_VALUE);
+        let value_kind =
+            if value.parse::<i64>().is_ok() { SyntaxKind::NUMBER } else { SyntaxKind::IDENTIFIER };
+        builder.token(value_kind, value);
+        builder.finish_node();
+        builder.finish_node();
+    });
+    Operand::cast(node).unwrap()
+}
+
+/// Build a tree whose root is a node of `kind`, with the children added by `children`
+fn build(
+    kind: SyntaxKind,
+    children: impl FnOnce(&mut GreenNodeBuilder<'static, 'static, Ram>),
+) -> ResolvedNode {
+    let mut builder = GreenNodeBuilder::new();
+    builder.start_node(kind);
+    children(&mut builder);
+    builder.finish_node();
+
+    let (tree, cache) = builder.finish();
+    SyntaxNode::new_root_with_resolver(tree, cache.unwrap().into_interner().unwrap())
+}
+
+/// Add a copy of `node` and all its descendants to the node being built
+fn copy_node(builder: &mut GreenNodeBuilder<'static, 'static, Ram>, node: &ResolvedNode) {
+    builder.start_node(node.kind());
+    for child in node.children_with_tokens() {
+        match child {
+            cstree::util::NodeOrToken::Node(child) => copy_node(builder, child),
+            cstree::util::NodeOrToken::Token(token) => builder.token(token.kind(), token.text()),
+        }
+    }
+    builder.finish_node();
+}
@@ -4,6 +4,8 @@
 //! Each struct represents a specific node type in the tree and provides
 //! methods for accessing its children and properties.
 
+use cstree::text::TextRange;
+
 use crate::ast::{AstChildren, AstNode};
 use crate::{ResolvedNode, SyntaxKind};
 
@@ -63,6 +65,71 @@ impl Statement {
         AstChildren::<DocComment>::new(self.syntax()).next()
     }
 
+    /// Returns the comment group if this statement contains one
+    pub fn comment_group(&self) -> Option<CommentGroup> {
+        AstChildren::<CommentGroup>::new(self.syntax()).next()
+    }
+
+    /// Returns every comment group of this statement, leading and trailing
+    pub fn comment_groups(&self) -> AstChildren<'_, CommentGroup> {
+        AstChildren::<CommentGroup>::new(self.syntax())
+    }
+
+    /// Returns whether this statement only holds comments
+    ///
+    /// Comments are statements of their own when they are not attached to a
+    /// statement, such as comments followed by a blank line.
+    pub fn is_comment(&self) -> bool {
+        let mut children = self.syntax().children().peekable();
+        children.peek().is_some() && children.all(|node| node.kind() == SyntaxKind::COMMENT_GROUP)
+    }
+
+    /// Returns the comment groups on the lines right above this statement
+    pub fn leading_comments(&self) -> Vec<CommentGroup> {
+        if self.is_comment() {
+            return Vec::new();
+        }
+        self.syntax().children().map_while(|node| CommentGroup::cast(node.clone())).collect()
+    }
+
+    /// Returns the comment on the line of this statement, after its code
+    pub fn trailing_comment(&self) -> Option<CommentGroup> {
+        self.syntax()
+            .children()
+            .skip_while(|node| node.kind() == SyntaxKind::COMMENT_GROUP)
+            .find_map(|node| CommentGroup::cast(node.clone()))
+    }
+
+    /// Returns the range of this statement with its attached comments
+    ///
+    /// This spans from the first leading comment to the trailing comment, so
+    /// edits replacing or removing the statement take the comments that
+    /// document it along. The indentation before it and the line break after
+    /// it are not included.
+    pub fn full_range(&self) -> TextRange {
+        self.syntax().text_range()
+    }
+
+    /// Returns the range of the code of this statement, without its comments
+    ///
+    /// Returns `None` if the statement only holds comments.
+    pub fn code_range(&self) -> Option<TextRange> {
+        let mut code = self
+            .syntax()
+            .children()
+            .filter(|node| node.kind() != SyntaxKind::COMMENT_GROUP)
+            .map(|node| node.text_range());
+        let first = code.next()?;
+        Some(code.fold(first, |range, next| range.cover(next)))
+    }
+
+    /// Returns the documentation comments on the lines right above this statement
+    ///
+    /// These document the label, instruction or module of the statement.
+    pub fn doc_comments(&self) -> Vec<DocComment> {
+        self.leading_comments().iter().flat_map(CommentGroup::doc_comments).collect()
+    }
+
     /// Returns the module declaration if this statement contains one
     pub fn mod_stmt(&self) -> Option<ModStmt> {
         AstChildren::<ModStmt>::new(self.syntax()).next()
@@ -288,6 +355,11 @@ impl DirectOperand {
     pub fn value(&self) -> Option<OperandValue> {
         AstChildren::<OperandValue>::new(self.syntax()).next()
     }
+
+    /// Returns the value of the operand, which may be an arithmetic expression
+    pub fn expr(&self) -> Option<OperandExpr> {
+        AstChildren::<OperandExpr>::new(self.syntax()).next()
+    }
 }
 
 impl AstNode for DirectOperand {
@@ -313,6 +385,11 @@ impl IndirectOperand {
     pub fn value(&self) -> Option<OperandValue> {
         AstChildren::<OperandValue>::new(self.syntax()).next()
     }
+
+    /// Returns the value of the operand, which may be an arithmetic expression
+    pub fn expr(&self) -> Option<OperandExpr> {
+        AstChildren::<OperandExpr>::new(self.syntax()).next()
+    }
 }
 
 impl AstNode for IndirectOperand {
@@ -338,6 +415,11 @@ impl ImmediateOperand {
     pub fn value(&self) -> Option<OperandValue> {
         AstChildren::<OperandValue>::new(self.syntax()).next()
     }
+
+    /// Returns the value of the operand, which may be an arithmetic expression
+    pub fn expr(&self) -> Option<OperandExpr> {
+        AstChildren::<OperandExpr>::new(self.syntax()).next()
+    }
 }
 
 impl AstNode for ImmediateOperand {
@@ -359,13 +441,34 @@ impl AstNode for ImmediateOperand {
 pub struct OperandValue(pub(crate) ResolvedNode);
 
 impl OperandValue {
-    /// Returns the numeric value if this is a number
+    /// Returns the numeric value if this is a number, negated if it has a `-` sign
     pub fn as_number(&self) -> Option<i64> {
+        let tokens = self
+            .syntax()
+            .children_with_tokens()
+            .filter_map(cstree::util::NodeOrToken::into_token)
+            .collect::<Vec<_>>();
+        let negative = tokens.iter().any(|token| token.kind() == SyntaxKind::MINUS);
+        let number = tokens.iter().find(|token| token.kind() == SyntaxKind::NUMBER)?;
+        // Parsing the sign with the digits keeps `i64::MIN` in range
+        let text = if negative { format!("-{}", number.text()) } else { number.text().to_string() };
+        text.parse::<i64>().ok()
+    }
+
+    /// Returns the character if this is a character literal
+    pub fn as_char(&self) -> Option<char> {
         self.syntax()
             .children_with_tokens()
             .filter_map(cstree::util::NodeOrToken::into_token)
-            .find(|token| token.kind() == SyntaxKind::NUMBER)
-            .and_then(|token| token.text().parse::<i64>().ok())
+            .find(|token| token.kind() == SyntaxKind::CHAR)
+            .and_then(|token| parse_char_literal(token.text()))
+    }
+
+    /// Returns the integer value of a number or character literal
+    ///
+    /// A character stands for its Unicode code point.
+    pub fn as_int(&self) -> Option<i64> {
+        self.as_number().or_else(|| self.as_char().map(|c| i64::from(u32::from(c))))
     }
 
     /// Returns the identifier value if this is an identifier
@@ -397,6 +500,28 @@ impl AstNode for OperandValue {
     }
 }
 
+/// Parses the text of a character literal token, quotes included
+///
+/// The literal must hold exactly one character, or one of the escapes `\n`,
+/// `\r`, `\t`, `\0`, `\\` and `\'`. Returns `None` otherwise.
+pub fn parse_char_literal(text: &str) -> Option<char> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+    let c = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '\'') => c,
+            _ => return None,
+        },
+        '\'' => return None,
+        c => c,
+    };
+    if chars.next().is_some() { None } else { Some(c) }
+}
+
 /// Array accessor node (e.g., [5])
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ArrayAccessor(pub(crate) ResolvedNode);
@@ -426,6 +551,80 @@ impl AstNode for ArrayAccessor {
     }
 }
 
+/// Arithmetic expression node in an operand (e.g., buffer+2)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BinaryExpr(pub(crate) ResolvedNode);
+
+impl BinaryExpr {
+    /// Returns the left-hand side of the expression
+    pub fn lhs(&self) -> Option<OperandExpr> {
+        AstChildren::<OperandExpr>::new(self.syntax()).next()
+    }
+
+    /// Returns the right-hand side of the expression
+    pub fn rhs(&self) -> Option<OperandExpr> {
+        AstChildren::<OperandExpr>::new(self.syntax()).nth(1)
+    }
+
+    /// Returns the kind of the operator token (`+`, `-`, `*` or `/`)
+    pub fn op_kind(&self) -> Option<SyntaxKind> {
+        self.syntax()
+            .children_with_tokens()
+            .filter_map(cstree::util::NodeOrToken::into_token)
+            .map(|token| token.kind())
+            .find(|kind| {
+                matches!(
+                    kind,
+                    SyntaxKind::PLUS | SyntaxKind::MINUS | SyntaxKind::STAR | SyntaxKind::SLASH
+                )
+            })
+    }
+}
+
+impl AstNode for BinaryExpr {
+    fn can_cast(node: &ResolvedNode) -> bool {
+        node.kind() == SyntaxKind::BINARY_EXPR
+    }
+
+    fn cast(node: ResolvedNode) -> Option<Self> {
+        if Self::can_cast(&node) { Some(Self(node)) } else { None }
+    }
+
+    fn syntax(&self) -> &ResolvedNode {
+        &self.0
+    }
+}
+
+/// Value of an operand: a plain value or an arithmetic expression
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OperandExpr {
+    /// A number or identifier
+    Value(OperandValue),
+    /// Arithmetic on other operand expressions
+    Binary(BinaryExpr),
+}
+
+impl AstNode for OperandExpr {
+    fn can_cast(node: &ResolvedNode) -> bool {
+        OperandValue::can_cast(node) || BinaryExpr::can_cast(node)
+    }
+
+    fn cast(node: ResolvedNode) -> Option<Self> {
+        match node.kind() {
+            SyntaxKind::OPERAND_VALUE => Some(Self::Value(OperandValue(node))),
+            SyntaxKind::BINARY_EXPR => Some(Self::Binary(BinaryExpr(node))),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &ResolvedNode {
+        match self {
+            Self::Value(value) => value.syntax(),
+            Self::Binary(expr) => expr.syntax(),
+        }
+    }
+}
+
 /// Module declaration statement node
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ModStmt(pub(crate) ResolvedNode);
@@ -493,6 +692,24 @@ impl ModulePath {
             .find(|token| token.kind() == SyntaxKind::STRING)
             .map(|token| token.text().to_string())
     }
+
+    /// Returns the identifier segments of the path (e.g., `["module", "symbol"]`)
+    pub fn segments(&self) -> Vec<String> {
+        self.syntax()
+            .children_with_tokens()
+            .filter_map(cstree::util::NodeOrToken::into_token)
+            .filter(|token| token.kind() == SyntaxKind::IDENTIFIER)
+            .map(|token| token.text().to_string())
+            .collect()
+    }
+
+    /// Returns true if the path ends in a wildcard (e.g., `module::*`)
+    pub fn is_wildcard(&self) -> bool {
+        self.syntax()
+            .children_with_tokens()
+            .filter_map(cstree::util::NodeOrToken::into_token)
+            .any(|token| token.kind() == SyntaxKind::STAR)
+    }
 }
 
 impl AstNode for ModulePath {
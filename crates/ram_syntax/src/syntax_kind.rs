@@ -3,6 +3,8 @@ use ram_derive::FromStaticText;
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
+use crate::TokenSet;
+
 pub type Ram = SyntaxKind;
 pub type SyntaxNode = cstree::syntax::SyntaxNode<Ram>;
 pub type SyntaxToken = cstree::syntax::SyntaxToken<Ram>;
@@ -31,6 +33,7 @@ pub enum SyntaxKind {
     IMMEDIATE_OPERAND, // Immediate addressing (e.g., =5)
     OPERAND_VALUE,
     ARRAY_ACCESSOR, // Array accessor [index]
+    BINARY_EXPR,    // Arithmetic in an operand (e.g., buffer+2)
     MOD_STMT,       // Module declaration statement
     USE_STMT,       // Module use statement
     MODULE_PATH,    // Path in a module statement
@@ -61,6 +64,12 @@ pub enum SyntaxKind {
     STAR, // '*' for indirect addressing
     #[static_text("=")]
     EQUALS, // '=' for immediate addressing
+    #[static_text("+")]
+    PLUS, // '+' for addition in operands
+    #[static_text("-")]
+    MINUS, // '-' for subtraction in operands
+    #[static_text("/")]
+    SLASH, // '/' for division in operands
     #[static_text("[")]
     LBRACKET, // '[' for array access
     #[static_text("]")]
@@ -72,6 +81,7 @@ pub enum SyntaxKind {
     #[static_text(",")]
     COMMA, // ',' for separating import specifiers
     STRING,      // String literal for import paths
+    CHAR,        // Character literal in operands (e.g., 'A')
     ERROR_TOKEN, // Token for unrecognized characters
     EOF,         // Not usually represented in the tree, but needed for parsing
 }
@@ -104,6 +114,9 @@ macro_rules! T {
     [":"] => { $crate::SyntaxKind::COLON };
     ["*"] => { $crate::SyntaxKind::STAR };
     ["="] => { $crate::SyntaxKind::EQUALS };
+    ["+"] => { $crate::SyntaxKind::PLUS };
+    ["-"] => { $crate::SyntaxKind::MINUS };
+    ["/"] => { $crate::SyntaxKind::SLASH };
     ["["] => { $crate::SyntaxKind::LBRACKET };
     ["]"] => { $crate::SyntaxKind::RBRACKET };
     ["{"] => { $crate::SyntaxKind::LBRACE };
@@ -118,6 +131,9 @@ macro_rules! T {
     [:] => { $crate::SyntaxKind::COLON };
     [*] => { $crate::SyntaxKind::STAR };
     [=] => { $crate::SyntaxKind::EQUALS };
+    [+] => { $crate::SyntaxKind::PLUS };
+    [-] => { $crate::SyntaxKind::MINUS };
+    [/] => { $crate::SyntaxKind::SLASH };
     ['['] => { $crate::SyntaxKind::LBRACKET };
     [']'] => { $crate::SyntaxKind::RBRACKET };
     ['{'] => { $crate::SyntaxKind::LBRACE };
@@ -140,16 +156,49 @@ macro_rules! T {
 }
 
 impl SyntaxKind {
+    /// Returns true if this is whitespace or a comment, see [`TokenSet::TRIVIA`].
     #[inline]
     pub fn is_trivia(self) -> bool {
-        matches!(
-            self,
-            SyntaxKind::WHITESPACE
-                | SyntaxKind::NEWLINE
-                | SyntaxKind::COMMENT
-                | SyntaxKind::DOC_COMMENT
-                | SyntaxKind::COMMENT_GROUP
-        )
+        TokenSet::TRIVIA.contains(self)
+    }
+
+    /// Returns true if this is a space or a newline.
+    #[inline]
+    pub fn is_whitespace(self) -> bool {
+        TokenSet::WHITESPACE.contains(self)
+    }
+
+    /// Returns true if this is a comment node or a token of a comment.
+    #[inline]
+    pub fn is_comment(self) -> bool {
+        TokenSet::COMMENTS.contains(self)
+    }
+
+    /// Returns true if an opcode can be written with this token.
+    ///
+    /// Opcodes are identifiers, so the parent of the token tells an opcode
+    /// from a label, see [`TokenRole`](crate::cursor::TokenRole).
+    #[inline]
+    pub fn is_opcode_kw(self) -> bool {
+        TokenSet::OPCODES.contains(self)
+    }
+
+    /// Returns true if an operand can start with this token.
+    #[inline]
+    pub fn is_operand_start(self) -> bool {
+        TokenSet::OPERAND_START.contains(self)
+    }
+
+    /// Returns true if this is an operand node or a node inside an operand.
+    #[inline]
+    pub fn is_operand(self) -> bool {
+        TokenSet::OPERANDS.contains(self)
+    }
+
+    /// Returns true if this is punctuation or an operator.
+    #[inline]
+    pub fn is_punct(self) -> bool {
+        TokenSet::PUNCTUATION.contains(self)
     }
 
     /// Returns true if this is an identifier or a keyword.
@@ -0,0 +1,93 @@
+//! Sets of syntax kinds.
+//!
+//! The parser recovers at the kinds of a set, and the tools built on the
+//! syntax tree classify tokens with them, so every consumer agrees on which
+//! kinds start an operand or end a statement.
+
+use crate::SyntaxKind;
+use crate::SyntaxKind::*;
+
+/// A set of syntax kinds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenSet(u128);
+
+impl TokenSet {
+    /// The set without kinds
+    pub const EMPTY: TokenSet = TokenSet(0);
+
+    /// Whitespace, which separates tokens
+    pub const WHITESPACE: TokenSet = TokenSet::new(&[WHITESPACE, NEWLINE]);
+
+    /// Kinds that carry no meaning for the program: whitespace and comments
+    pub const TRIVIA: TokenSet =
+        TokenSet::new(&[WHITESPACE, NEWLINE, COMMENT, DOC_COMMENT, COMMENT_GROUP]);
+
+    /// The nodes of comments and the tokens they are made of
+    pub const COMMENTS: TokenSet =
+        TokenSet::new(&[COMMENT, DOC_COMMENT, COMMENT_GROUP, HASH, HASH_STAR, COMMENT_TEXT]);
+
+    /// The tokens opcodes are written with
+    ///
+    /// Instructions are not keywords, so any identifier at the start of a
+    /// statement that is not a label is an opcode.
+    pub const OPCODES: TokenSet = TokenSet::new(&[IDENTIFIER]);
+
+    /// The tokens a value of an operand starts with
+    ///
+    /// A `-` only starts a value when a number follows it.
+    pub const VALUE_START: TokenSet = TokenSet::new(&[NUMBER, IDENTIFIER, CHAR, MINUS]);
+
+    /// The tokens an operand starts with, its addressing mode or its value
+    pub const OPERAND_START: TokenSet = TokenSet::VALUE_START.union(TokenSet::new(&[STAR, EQUALS]));
+
+    /// The nodes of operands and their values
+    pub const OPERANDS: TokenSet = TokenSet::new(&[
+        OPERAND,
+        DIRECT_OPERAND,
+        INDIRECT_OPERAND,
+        IMMEDIATE_OPERAND,
+        OPERAND_VALUE,
+        ARRAY_ACCESSOR,
+        BINARY_EXPR,
+    ]);
+
+    /// The nodes of module statements
+    pub const MODULES: TokenSet = TokenSet::new(&[MOD_STMT, USE_STMT, MODULE_PATH]);
+
+    /// Punctuation and the operators of operands
+    pub const PUNCTUATION: TokenSet = TokenSet::new(&[
+        COLON, COMMA, LBRACE, RBRACE, LBRACKET, RBRACKET, STAR, EQUALS, PLUS, MINUS, SLASH,
+    ]);
+
+    /// The tokens that end a statement, after which only a comment may follow
+    pub const STATEMENT_END: TokenSet = TokenSet::new(&[NEWLINE, EOF, HASH, HASH_STAR]);
+
+    /// The tokens a statement can start with
+    pub const STATEMENT_START: TokenSet =
+        TokenSet::new(&[NEWLINE, HASH, HASH_STAR, IDENTIFIER, MOD_KW, USE_KW]);
+
+    /// Create a set of the given kinds
+    pub const fn new(kinds: &[SyntaxKind]) -> TokenSet {
+        let mut res = 0u128;
+        let mut i = 0;
+        while i < kinds.len() {
+            let kind = kinds[i] as usize;
+            if kind < 128 {
+                res |= 1u128 << kind;
+            }
+            i += 1;
+        }
+        TokenSet(res)
+    }
+
+    /// Create a set of the kinds in either set
+    pub const fn union(self, other: TokenSet) -> TokenSet {
+        TokenSet(self.0 | other.0)
+    }
+
+    /// Check whether the set contains `kind`
+    pub const fn contains(&self, kind: SyntaxKind) -> bool {
+        let kind = kind as usize;
+        kind < 128 && (self.0 & (1u128 << kind)) != 0
+    }
+}
@@ -0,0 +1,109 @@
+//! Benchmarks of the interpreter loop on loop-heavy programs
+//!
+//! `dispatch` compares finding the definition of each instruction in the
+//! registry, as the machine did on every step before programs were decoded,
+//! with indexing the jump table of a decoded program. `run` measures whole
+//! runs both ways: interpreted, finding the definition of each instruction in
+//! the registry and the target of each jump by its label, and decoded, as the
+//! machine runs programs.
+
+use std::hint::black_box;
+use std::sync::Arc;
+
+use codspeed_criterion_compat::{
+    BenchmarkId, Criterion, Throughput, criterion_group, criterion_main,
+};
+use ram_core::db::VmState;
+use ram_core::error::VmError;
+use ram_core::instruction::InstructionDefinition;
+use ram_vm::{
+    DecodedProgram, Program, VecInput, VecOutput, VirtualMachine, VmDatabase, VmDatabaseImpl,
+};
+
+/// Programs with hot loops, and the value they read to know how long to loop
+const PROGRAMS: &[(&str, &str, i64)] = &[
+    ("countdown", "READ 1\nLOAD 1\nloop: SUB =1\nJGTZ loop\nHALT\n", 100_000),
+    (
+        "sum",
+        "READ 1\nloop: LOAD 2\nADD 1\nSTORE 2\nLOAD 1\nSUB =1\nSTORE 1\nJGTZ loop\nWRITE 2\nHALT\n",
+        20_000,
+    ),
+];
+
+fn dispatch(c: &mut Criterion) {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut group = c.benchmark_group("dispatch");
+    for (name, source, _) in PROGRAMS {
+        let program = db.parse_to_vm_program(source).unwrap();
+        let decoded = DecodedProgram::decode(&program, &db);
+        group.throughput(Throughput::Elements(program.len() as u64));
+        group.bench_with_input(BenchmarkId::new("registry", name), &program, |b, program| {
+            b.iter(|| {
+                for instruction in &program.instructions {
+                    black_box(db.get_instruction_definition(&instruction.kind));
+                }
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("decoded", name), &decoded, |b, decoded| {
+            b.iter(|| {
+                for index in 0..decoded.len() {
+                    black_box(decoded.op(index).and_then(|op| decoded.handler(op)));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn run(c: &mut Criterion) {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut group = c.benchmark_group("run");
+    for (name, source, input) in PROGRAMS {
+        let program = db.parse_to_vm_program(source).unwrap();
+        group.bench_with_input(BenchmarkId::new("interpreted", name), &program, |b, program| {
+            b.iter(|| {
+                let input = VecInput::new(vec![*input]);
+                let mut vm =
+                    VirtualMachine::new(program.clone(), input, VecOutput::new(), db.clone());
+                black_box(interpret(&mut vm, program, &db))
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("decoded", name), &program, |b, program| {
+            b.iter(|| {
+                let input = VecInput::new(vec![*input]);
+                let mut vm =
+                    VirtualMachine::new(program.clone(), input, VecOutput::new(), db.clone());
+                vm.run().unwrap();
+                black_box(vm.steps())
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Run a machine as it ran before programs were decoded, returning the number
+/// of instructions executed
+///
+/// Outside of [`VirtualMachine::step`], the machine resolves labels by
+/// looking them up in the program.
+fn interpret(
+    vm: &mut VirtualMachine<VecInput, VecOutput>,
+    program: &Program,
+    db: &VmDatabaseImpl,
+) -> u64 {
+    let mut steps = 0;
+    loop {
+        let instruction = &program.instructions[vm.program_counter()];
+        let definition = db.get_instruction_definition(&instruction.kind).unwrap();
+        vm.set_program_counter(vm.program_counter() + 1);
+        steps += 1;
+        match definition.execute(instruction.operand.as_ref(), vm) {
+            Ok(()) => {}
+            Err(VmError::ProgramTerminated) => return steps,
+            Err(error) => panic!("{error}"),
+        }
+    }
+}
+
+criterion_group!(benches, dispatch, run);
+criterion_main!(benches);
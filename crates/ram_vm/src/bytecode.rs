@@ -0,0 +1,461 @@
+//! Bytecode artifacts of compiled programs
+//!
+//! An artifact stores a [`Program`] together with a signature identifying
+//! what it was built from: a hash of the source text, the version of the
+//! compiler and a fingerprint of the instruction set. Loading an artifact
+//! checks its integrity with a checksum, and [`Artifact::verify`] reports the
+//! differences with the current environment before the program is run.
+//!
+//! # Layout
+//!
+//! All integers are little-endian and strings are prefixed by their length
+//! as a `u32`.
+//!
+//! ```text
+//! magic            4 bytes  "RAMB"
+//! format version   u16
+//! checksum         u64      FNV-1a hash of everything after it
+//! source hash      u64
+//! compiler version string
+//! instruction set  u64
+//! labels           u32 count, then a name and a u32 instruction index each
+//! contract         u32 reads and u32 writes, `u32::MAX` for a count not declared
+//! instructions     u32 count, then an opcode, an operand and an output format each
+//! ```
+//!
+//! An operand is a tag byte, `0` for none or the addressing mode otherwise,
+//! followed by a tag byte for the kind of value and the value itself. The
+//! output format is a byte, `0` for numbers and `1` for characters.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use hir::body::IoContract;
+use ram_core::instruction::{Instruction, InstructionKind, OutputFormat};
+use ram_core::operand::{Operand, OperandKind, OperandValue};
+use ram_core::registry::InstructionRegistry;
+use thiserror::Error;
+
+use crate::program::Program;
+
+/// The file extension of bytecode artifacts
+pub const FILE_EXTENSION: &str = "rbc";
+
+/// The bytes every artifact starts with
+pub const MAGIC: [u8; 4] = *b"RAMB";
+
+/// The version of the layout of artifacts
+pub const FORMAT_VERSION: u16 = 3;
+
+/// The size of the header preceding the checksummed contents
+const HEADER_SIZE: usize = MAGIC.len() + 2 + 8;
+
+/// An error reading an artifact
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ArtifactError {
+    /// The file does not start with the magic bytes
+    #[error("Not a RAM bytecode artifact")]
+    NotAnArtifact,
+    /// The artifact uses a layout this version cannot read
+    #[error("Unsupported bytecode format version {0}, expected {FORMAT_VERSION}")]
+    UnsupportedFormat(u16),
+    /// The contents do not match the checksum
+    #[error("Checksum mismatch: expected {expected:016x}, found {actual:016x}")]
+    ChecksumMismatch {
+        /// The checksum stored in the artifact
+        expected: u64,
+        /// The checksum of the contents
+        actual: u64,
+    },
+    /// The contents end before the program does
+    #[error("Unexpected end of artifact")]
+    Truncated,
+    /// The contents are not a valid program
+    #[error("Invalid artifact: {0}")]
+    Invalid(String),
+}
+
+/// What an artifact was built from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// The hash of the source text, see [`content_hash`]
+    pub source_hash: u64,
+    /// The version of the compiler that built the artifact
+    pub compiler_version: String,
+    /// The fingerprint of the instruction set, see [`instruction_set_fingerprint`]
+    pub instruction_set: u64,
+}
+
+/// A difference between an artifact and the environment it is loaded in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// The artifact was built by another version of the compiler
+    CompilerVersion {
+        /// The version that built the artifact
+        artifact: String,
+        /// The current version
+        current: String,
+    },
+    /// The artifact was built for another instruction set
+    InstructionSet {
+        /// The fingerprint of the instruction set of the artifact
+        artifact: u64,
+        /// The fingerprint of the current instruction set
+        current: u64,
+    },
+    /// The artifact uses an instruction that is not available
+    UnknownInstruction(String),
+    /// The artifact was built from another source text
+    SourceChanged,
+}
+
+impl Incompatibility {
+    /// Check whether the program can not run because of this difference
+    ///
+    /// Other differences are reported as warnings.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::UnknownInstruction(_))
+    }
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CompilerVersion { artifact, current } => {
+                write!(f, "built by ram {}, but this is ram {}", artifact, current)
+            }
+            Self::InstructionSet { artifact, current } => write!(
+                f,
+                "built for instruction set {:016x}, but the current one is {:016x}",
+                artifact, current
+            ),
+            Self::UnknownInstruction(name) => write!(f, "uses unknown instruction '{}'", name),
+            Self::SourceChanged => write!(f, "the source changed since it was built"),
+        }
+    }
+}
+
+/// A compiled program and its signature
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// What the program was built from
+    pub signature: Signature,
+    /// The compiled program
+    pub program: Program,
+}
+
+impl Artifact {
+    /// Create an artifact of a program compiled from `source`
+    pub fn new(
+        program: Program,
+        source: &str,
+        compiler_version: impl Into<String>,
+        registry: &InstructionRegistry,
+    ) -> Self {
+        let signature = Signature {
+            source_hash: content_hash(source.as_bytes()),
+            compiler_version: compiler_version.into(),
+            instruction_set: instruction_set_fingerprint(registry),
+        };
+        Self { signature, program }
+    }
+
+    /// Encode the artifact
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut contents = Writer::default();
+        contents.u64(self.signature.source_hash);
+        contents.string(&self.signature.compiler_version);
+        contents.u64(self.signature.instruction_set);
+
+        // Sort the labels so equal programs are encoded equally
+        let mut labels = self.program.labels.iter().collect::<Vec<_>>();
+        labels.sort();
+        contents.u32(labels.len() as u32);
+        for (name, &index) in labels {
+            contents.string(name);
+            contents.u32(index as u32);
+        }
+
+        let contract = self.program.contract.unwrap_or_default();
+        contents.count(contract.reads);
+        contents.count(contract.writes);
+
+        contents.u32(self.program.instructions.len() as u32);
+        for instruction in &self.program.instructions {
+            contents.string(instruction.kind.name());
+            contents.operand(instruction.operand.as_ref());
+            contents.output_format(instruction.output_format);
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + contents.0.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&content_hash(&contents.0).to_le_bytes());
+        bytes.extend_from_slice(&contents.0);
+        bytes
+    }
+
+    /// Decode an artifact, checking its integrity
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ArtifactError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(ArtifactError::NotAnArtifact);
+        }
+        let mut header = Reader(&bytes[MAGIC.len()..]);
+        let format = header.u16()?;
+        if format != FORMAT_VERSION {
+            return Err(ArtifactError::UnsupportedFormat(format));
+        }
+        let expected = header.u64()?;
+        let actual = content_hash(header.0);
+        if expected != actual {
+            return Err(ArtifactError::ChecksumMismatch { expected, actual });
+        }
+
+        let mut contents = header;
+        let signature = Signature {
+            source_hash: contents.u64()?,
+            compiler_version: contents.string()?,
+            instruction_set: contents.u64()?,
+        };
+
+        let mut labels = HashMap::new();
+        for _ in 0..contents.u32()? {
+            let name = contents.string()?;
+            labels.insert(name, contents.u32()? as usize);
+        }
+
+        let contract = IoContract { reads: contents.count()?, writes: contents.count()? };
+        let contract = (contract != IoContract::default()).then_some(contract);
+
+        let count = contents.u32()? as usize;
+        let mut instructions = Vec::with_capacity(count.min(contents.0.len()));
+        for _ in 0..count {
+            let kind = InstructionKind::from_name(&contents.string()?);
+            let operand = contents.operand()?;
+            let output_format = contents.output_format()?;
+            instructions.push(Instruction::new(kind, operand).with_output_format(output_format));
+        }
+
+        if !contents.0.is_empty() {
+            return Err(ArtifactError::Invalid("trailing bytes after the program".to_string()));
+        }
+        if let Some((name, _)) = labels.iter().find(|(_, index)| **index > instructions.len()) {
+            return Err(ArtifactError::Invalid(format!("label '{}' is out of bounds", name)));
+        }
+
+        Ok(Self { signature, program: Program { instructions, labels, contract } })
+    }
+
+    /// Report the differences between the artifact and the current environment
+    ///
+    /// `source` is the text the artifact is expected to be built from, if known.
+    pub fn verify(
+        &self,
+        compiler_version: &str,
+        registry: &InstructionRegistry,
+        source: Option<&str>,
+    ) -> Vec<Incompatibility> {
+        let mut incompatibilities = Vec::new();
+
+        if self.signature.compiler_version != compiler_version {
+            incompatibilities.push(Incompatibility::CompilerVersion {
+                artifact: self.signature.compiler_version.clone(),
+                current: compiler_version.to_string(),
+            });
+        }
+
+        let current = instruction_set_fingerprint(registry);
+        if self.signature.instruction_set != current {
+            incompatibilities.push(Incompatibility::InstructionSet {
+                artifact: self.signature.instruction_set,
+                current,
+            });
+        }
+
+        let mut unknown = self
+            .program
+            .instructions
+            .iter()
+            .filter(|instruction| !registry.contains(&instruction.kind))
+            .map(|instruction| instruction.kind.name().to_string())
+            .collect::<Vec<_>>();
+        unknown.sort();
+        unknown.dedup();
+        incompatibilities.extend(unknown.into_iter().map(Incompatibility::UnknownInstruction));
+
+        if source
+            .is_some_and(|source| content_hash(source.as_bytes()) != self.signature.source_hash)
+        {
+            incompatibilities.push(Incompatibility::SourceChanged);
+        }
+
+        incompatibilities
+    }
+}
+
+/// Hash bytes with 64-bit FNV-1a
+///
+/// Unlike the hashers of the standard library, the hash is the same on every
+/// platform and release, so it can be stored in artifacts.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Compute the fingerprint of the instructions of a registry
+///
+/// The fingerprint covers the name and the accepted operands of each
+/// instruction, regardless of the order they were registered in.
+pub fn instruction_set_fingerprint(registry: &InstructionRegistry) -> u64 {
+    let mut instructions = registry
+        .get_all_info()
+        .into_iter()
+        .map(|info| {
+            let operands =
+                info.allowed_operand_kinds.iter().map(|kind| kind.name()).collect::<Vec<_>>();
+            format!("{}:{}:{}", info.name, info.requires_operand, operands.join(","))
+        })
+        .collect::<Vec<_>>();
+    instructions.sort();
+    content_hash(instructions.join(";").as_bytes())
+}
+
+/// Encodes the contents of an artifact
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn count(&mut self, count: Option<usize>) {
+        self.u32(count.map_or(u32::MAX, |count| count as u32));
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn operand(&mut self, operand: Option<&Operand>) {
+        let Some(operand) = operand else {
+            self.u8(0);
+            return;
+        };
+        self.u8(match operand.kind {
+            OperandKind::Direct => 1,
+            OperandKind::Indirect => 2,
+            OperandKind::Immediate => 3,
+            OperandKind::Indexed => 4,
+        });
+        match &operand.value {
+            OperandValue::Number(value) => {
+                self.u8(0);
+                self.i64(*value);
+            }
+            OperandValue::String(value) => {
+                self.u8(1);
+                self.string(value);
+            }
+            OperandValue::Indexed(base, index) => {
+                self.u8(2);
+                self.i64(*base);
+                self.i64(*index);
+            }
+        }
+    }
+
+    fn output_format(&mut self, output_format: OutputFormat) {
+        self.u8(match output_format {
+            OutputFormat::Number => 0,
+            OutputFormat::Char => 1,
+        });
+    }
+}
+
+/// Decodes the contents of an artifact
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], ArtifactError> {
+        let (bytes, rest) = self.0.split_first_chunk::<N>().ok_or(ArtifactError::Truncated)?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ArtifactError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ArtifactError> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ArtifactError> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ArtifactError> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn count(&mut self) -> Result<Option<usize>, ArtifactError> {
+        let count = self.u32()?;
+        Ok((count != u32::MAX).then_some(count as usize))
+    }
+
+    fn i64(&mut self) -> Result<i64, ArtifactError> {
+        Ok(i64::from_le_bytes(self.bytes()?))
+    }
+
+    fn string(&mut self) -> Result<String, ArtifactError> {
+        let len = self.u32()? as usize;
+        if self.0.len() < len {
+            return Err(ArtifactError::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| ArtifactError::Invalid("a string is not valid UTF-8".to_string()))
+    }
+
+    fn operand(&mut self) -> Result<Option<Operand>, ArtifactError> {
+        let kind = match self.u8()? {
+            0 => return Ok(None),
+            1 => OperandKind::Direct,
+            2 => OperandKind::Indirect,
+            3 => OperandKind::Immediate,
+            4 => OperandKind::Indexed,
+            tag => return Err(ArtifactError::Invalid(format!("unknown addressing mode {}", tag))),
+        };
+        let value = match self.u8()? {
+            0 => OperandValue::Number(self.i64()?),
+            1 => OperandValue::String(self.string()?),
+            2 => OperandValue::Indexed(self.i64()?, self.i64()?),
+            tag => return Err(ArtifactError::Invalid(format!("unknown operand value {}", tag))),
+        };
+        Ok(Some(Operand { kind, value }))
+    }
+
+    fn output_format(&mut self) -> Result<OutputFormat, ArtifactError> {
+        match self.u8()? {
+            0 => Ok(OutputFormat::Number),
+            1 => Ok(OutputFormat::Char),
+            tag => Err(ArtifactError::Invalid(format!("unknown output format {}", tag))),
+        }
+    }
+}
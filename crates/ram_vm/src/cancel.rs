@@ -0,0 +1,60 @@
+//! Cooperative cancellation of runs
+//!
+//! A program may never halt, so whoever starts a run, such as a debugger, an
+//! editor or a grader, can give the machine a [`Cancellation`] to ask it to
+//! stop. The machine checks it every [`CANCEL_CHECK_INTERVAL`] steps and
+//! stops with [`VmError::Cancelled`](ram_core::error::VmError::Cancelled),
+//! keeping its state, so the output written and the steps executed so far can
+//! still be reported.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The number of steps between two checks for cancellation
+pub const CANCEL_CHECK_INTERVAL: u64 = 1_000;
+
+/// Tells a running machine whether it should stop
+pub trait Cancellation: Send + Sync {
+    /// Check whether the run was cancelled
+    fn is_cancelled(&self) -> bool;
+}
+
+impl<F: Fn() -> bool + Send + Sync> Cancellation for F {
+    fn is_cancelled(&self) -> bool {
+        self()
+    }
+}
+
+/// A flag that cancels the runs it is given to once set
+///
+/// Clones share the flag, so one can be kept to cancel a run from another
+/// thread while the machine checks the other.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    /// Create a flag that is not set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the runs given this flag
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// Flags are equal if one is a clone of the other
+impl PartialEq for CancelFlag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelFlag {}
+
+impl Cancellation for CancelFlag {
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
@@ -1,11 +1,12 @@
 //! Database implementation for the RAM virtual machine
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use base_db::{
-    FileId, FileSourceRootInput, FileText, Files, SourceDatabase, SourceRoot, SourceRootId,
-    SourceRootInput,
+    FileId, FileSourceRootInput, FileText, Files, LineIndex, LruCache, LruConfig, Query,
+    QueryStats, SourceDatabase, SourceRoot, SourceRootId, SourceRootInput,
 };
 use hir::db::HirDatabase;
 use hir::name_resolution::ResolvedFile;
@@ -14,6 +15,7 @@ use miette::*;
 use ram_core::db::VmState;
 use ram_core::error::VmError;
 use ram_core::instruction::{InstructionDefinition, InstructionKind};
+use ram_core::instruction_set::InstructionSet;
 use ram_core::operand::Operand;
 use ram_core::registry::InstructionRegistry;
 use ram_core::standard_instructions;
@@ -21,6 +23,7 @@ use ram_parser::{Diagnostic, build_tree, parse};
 use ram_syntax::{AstNode, Program};
 use salsa::{Durability, Event};
 use tracing;
+
 /// The database trait for VM queries
 #[salsa::db]
 pub trait VmDatabase: HirDatabase {
@@ -83,23 +86,10 @@ pub trait VmDatabase: HirDatabase {
             VmError::InvalidInstruction(format!("Unknown instruction: {}", instruction))
         })?;
 
-        // Check if the instruction requires an operand
-        if definition.requires_operand() && operand.is_none() {
-            return Err(VmError::InvalidOperand(format!(
-                "Instruction {} requires an operand",
-                instruction
-            )));
-        }
-
-        // Check if the operand is valid for this instruction
+        // Check the operand with the same rules the analysis uses
+        definition.validate_arity(operand.is_some())?;
         if let Some(operand) = operand {
-            let allowed_kinds = definition.allowed_operand_kinds();
-            if !allowed_kinds.is_empty() && !allowed_kinds.contains(&operand.kind) {
-                return Err(VmError::InvalidOperand(format!(
-                    "Invalid operand kind for instruction {}",
-                    instruction
-                )));
-            }
+            definition.validate_operand(operand)?;
         }
 
         Ok(())
@@ -131,14 +121,29 @@ pub trait VmDatabase: HirDatabase {
     fn clear_all_diagnostics(&mut self);
 }
 
+/// A file parsed by the VM database
+#[derive(Clone)]
+struct ParsedFile {
+    /// The text the file was parsed from
+    text: Arc<str>,
+    program: Program,
+    errors: Vec<Diagnostic>,
+}
+
 /// Implementation of the VM database
+///
+/// The syntax trees and line indexes of the files are kept in LRU caches,
+/// whose capacities are set with a [`VmDatabaseBuilder`].
 #[salsa::db]
-#[derive(Default)]
 pub struct VmDatabaseImpl {
     storage: salsa::Storage<Self>,
     files: Arc<Files>,
     instruction_registry: Arc<Mutex<InstructionRegistry>>,
     diagnostics: Mutex<HashMap<FileId, Vec<Diagnostic>>>,
+    /// The syntax trees of the most recently parsed files
+    parses: Mutex<LruCache<FileId, ParsedFile>>,
+    /// The line indexes of the texts of the most recently used files
+    line_indexes: Mutex<LruCache<FileId, (Arc<str>, Arc<LineIndex>)>>,
 }
 
 // Explicitly implement Send and Sync for VmDatabaseImpl
@@ -162,10 +167,18 @@ impl Clone for VmDatabaseImpl {
             files: self.files.clone(),
             instruction_registry: self.instruction_registry.clone(),
             diagnostics: Mutex::new(self.diagnostics.lock().unwrap().clone()),
+            parses: Mutex::new(self.parses.lock().unwrap().clone()),
+            line_indexes: Mutex::new(self.line_indexes.lock().unwrap().clone()),
         }
     }
 }
 
+impl Default for VmDatabaseImpl {
+    fn default() -> Self {
+        VmDatabaseBuilder::new().build()
+    }
+}
+
 #[salsa::db]
 impl VmDatabase for VmDatabaseImpl {
     fn parse_program(&self, source: &str) -> (Program, Vec<Diagnostic>) {
@@ -209,7 +222,11 @@ impl VmDatabase for VmDatabaseImpl {
             // Convert all errors to a nice report format
             // FIXME: Refactor the DB API to be more consistent and
             // not repetitive
-            let report = ram_parser::convert_errors(source, errors);
+            let sources = ram_parser::diagnostic::SourceMap::single(
+                ram_parser::diagnostic::sources::ANONYMOUS_FILE_NAME,
+                source,
+            );
+            let report = ram_parser::convert_errors(&sources, errors);
             eprintln!("{:?}", miette::Error::new(report.clone()));
             return Err(VmError::ParseError(report));
         }
@@ -271,11 +288,10 @@ impl VmDatabase for VmDatabaseImpl {
 #[salsa::db]
 impl HirDefDatabase for VmDatabaseImpl {
     fn item_tree(&self, file_id: FileId) -> Arc<hir_def::item_tree::ItemTree> {
-        // Get the file text from the database
-        let file_text = self.file_text(file_id);
-
-        // Parse the file text into an AST Program
-        let (program, errors) = self.parse_program(file_text.text(self).as_ref());
+        // Parse the file text into an AST Program, a missing file has nothing in it
+        let Some((program, errors)) = self.parse_file(file_id) else {
+            return Arc::default();
+        };
 
         // Store the diagnostics for this file
         if !errors.is_empty() {
@@ -331,11 +347,10 @@ impl HirDatabase for VmDatabaseImpl {
         }
 
         // If not found, create a new body
-        // Get the file text from the database
-        let file_text = self.file_text(file_id);
-
-        // Parse the file text into an AST Program
-        let (program, _errors) = self.parse_program(file_text.text(self).as_ref());
+        // Parse the file text into an AST Program, a missing file has an empty body
+        let Some((program, _errors)) = self.parse_file(file_id) else {
+            return Arc::default();
+        };
 
         // Get the ItemTree for this file
         let item_tree = self.item_tree(file_id);
@@ -354,11 +369,10 @@ impl HirDatabase for VmDatabaseImpl {
         // Create a map to store the bodies
         let mut bodies = HashMap::new();
 
-        // Get the file text from the database
-        let file_text = self.file_text(file_id);
-
-        // Parse the file text into an AST Program
-        let (program, _errors) = self.parse_program(file_text.text(self).as_ref());
+        // Parse the file text into an AST Program, a missing file has nothing in it
+        let Some((program, _errors)) = self.parse_file(file_id) else {
+            return Arc::default();
+        };
 
         // Get the ItemTree for this file
         let item_tree = self.item_tree(file_id);
@@ -392,7 +406,11 @@ impl HirDatabase for VmDatabaseImpl {
 
 #[salsa::db]
 impl SourceDatabase for VmDatabaseImpl {
-    fn file_text(&self, file_id: FileId) -> FileText {
+    fn contains_file(&self, file_id: FileId) -> bool {
+        self.files.contains_file(file_id)
+    }
+
+    fn file_text(&self, file_id: FileId) -> Option<FileText> {
         self.files.file_text(file_id)
     }
 
@@ -415,17 +433,39 @@ impl SourceDatabase for VmDatabaseImpl {
         files.set_file_text_with_durability(self, file_id, text, durability);
     }
 
+    #[doc = " Remove a file, with its membership of a source root"]
+    fn remove_file(&mut self, file_id: FileId) -> bool {
+        // Clone the files reference to avoid borrowing issues
+        let files = Arc::clone(&self.files);
+        self.parses.lock().unwrap().remove(&file_id);
+        self.line_indexes.lock().unwrap().remove(&file_id);
+        files.remove_file(self, file_id)
+    }
+
+    #[doc = " Move a file to a new path, updating the source root it belongs to"]
+    fn rename_file(&mut self, file_id: FileId, path: PathBuf) -> bool {
+        // Clone the files reference to avoid borrowing issues
+        let files = Arc::clone(&self.files);
+        files.rename_file(self, file_id, path)
+    }
+
     #[doc = " Contents of the source root"]
-    fn source_root(&self, id: SourceRootId) -> SourceRootInput {
+    fn source_root(&self, id: SourceRootId) -> Option<SourceRootInput> {
         self.files.source_root(id)
     }
 
     #[doc = " Source root of the file"]
-    fn file_source_root(&self, _id: FileId) -> FileSourceRootInput {
-        // Try to get the source root ID for this file
-        // If not found, create a default one with source root ID 0
-        // In a real implementation, this would properly handle the case when a file doesn't have a source root
-        FileSourceRootInput::builder(SourceRootId(0)).new(self)
+    fn file_source_root(&self, id: FileId) -> Option<FileSourceRootInput> {
+        // Files of single programs are not assigned a source root, so they
+        // belong to the default one, with ID 0
+        if !self.files.contains_file(id) {
+            return None;
+        }
+        Some(
+            self.files
+                .file_source_root(id)
+                .unwrap_or_else(|| FileSourceRootInput::builder(SourceRootId(0)).new(self)),
+        )
     }
 
     #[doc = " Set the source root of a file with a specific durability"]
@@ -456,15 +496,51 @@ impl SourceDatabase for VmDatabaseImpl {
 impl VmDatabaseImpl {
     /// Create a new VM database
     pub fn new() -> Self {
-        let mut db = Self::default();
-        db.initialize_instructions();
-        db
+        Self::default()
+    }
+
+    /// Create a new VM database executing the instructions of a set
+    pub fn with_instruction_set(set: &InstructionSet) -> Self {
+        VmDatabaseBuilder::new().instruction_set(set).build()
+    }
+
+    /// Start building a VM database
+    pub fn builder() -> VmDatabaseBuilder {
+        VmDatabaseBuilder::new()
+    }
+
+    /// Get the line index of a file, or `None` if the file is unknown
+    pub fn line_index(&self, file_id: FileId) -> Option<Arc<LineIndex>> {
+        let text = self.file_text(file_id)?.text(self);
+        let mut line_indexes = self.line_indexes.lock().unwrap();
+        let (_, line_index) = line_indexes.get_or_insert_with(
+            file_id,
+            |(indexed, _)| Arc::ptr_eq(indexed, &text),
+            || (Arc::clone(&text), Arc::new(LineIndex::new(&text))),
+        );
+        Some(Arc::clone(line_index))
     }
 
-    /// Initialize the default instructions
-    fn initialize_instructions(&mut self) {
-        let registry = standard_instructions();
-        self.instruction_registry = Arc::new(Mutex::new(registry));
+    /// Get the memo count and hit rate of each query
+    pub fn query_stats(&self) -> Vec<QueryStats> {
+        vec![self.line_indexes.lock().unwrap().stats(), self.parses.lock().unwrap().stats()]
+    }
+
+    /// Parse a file, reusing its syntax tree until its text changes
+    ///
+    /// Returns `None` if the file is unknown.
+    fn parse_file(&self, file_id: FileId) -> Option<(Program, Vec<Diagnostic>)> {
+        let text = self.file_text(file_id)?.text(self);
+        let mut parses = self.parses.lock().unwrap();
+        let parsed = parses.get_or_insert_with(
+            file_id,
+            |parsed| Arc::ptr_eq(&parsed.text, &text),
+            || {
+                let (program, errors) = self.parse_program(&text);
+                ParsedFile { text: Arc::clone(&text), program, errors }
+            },
+        );
+        Some((parsed.program.clone(), parsed.errors.clone()))
     }
 
     /// Register a custom instruction
@@ -474,3 +550,72 @@ impl VmDatabaseImpl {
         registry.register(kind, definition);
     }
 }
+
+/// Builder of a [`VmDatabaseImpl`]
+///
+/// # Example
+///
+/// ```
+/// use base_db::{LruConfig, Query};
+/// use ram_vm::VmDatabaseImpl;
+///
+/// let db = VmDatabaseImpl::builder()
+///     .lru_config(LruConfig::new().with_capacity(Query::FileText, 4))
+///     .lru_capacity(Query::Parse, 32)
+///     .build();
+///
+/// let capacities = db.query_stats().iter().map(|stats| stats.capacity).collect::<Vec<_>>();
+/// assert_eq!(capacities, [4, 32]);
+/// ```
+#[derive(Clone, Default)]
+pub struct VmDatabaseBuilder {
+    /// The instructions to execute, or `None` for the standard instructions
+    instructions: Option<InstructionRegistry>,
+    /// The LRU capacity of each query
+    lru: LruConfig,
+}
+
+impl VmDatabaseBuilder {
+    /// Create a builder of a database executing the standard instructions,
+    /// with the default LRU capacities
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Execute the instructions of a set
+    #[must_use]
+    pub fn instruction_set(mut self, set: &InstructionSet) -> Self {
+        self.instructions = Some(set.registry().clone());
+        self
+    }
+
+    /// Set the LRU capacities of all queries
+    #[must_use]
+    pub fn lru_config(mut self, lru: LruConfig) -> Self {
+        self.lru = lru;
+        self
+    }
+
+    /// Set the LRU capacity of a query, where 0 keeps every result
+    #[must_use]
+    pub fn lru_capacity(mut self, query: Query, capacity: u16) -> Self {
+        self.lru = self.lru.with_capacity(query, capacity);
+        self
+    }
+
+    /// Build the database
+    pub fn build(self) -> VmDatabaseImpl {
+        let registry = self.instructions.unwrap_or_else(standard_instructions);
+        VmDatabaseImpl {
+            storage: salsa::Storage::default(),
+            files: Arc::default(),
+            instruction_registry: Arc::new(Mutex::new(registry)),
+            diagnostics: Mutex::default(),
+            parses: Mutex::new(LruCache::new(Query::Parse, self.lru.capacity(Query::Parse))),
+            line_indexes: Mutex::new(LruCache::new(
+                Query::FileText,
+                self.lru.capacity(Query::FileText),
+            )),
+        }
+    }
+}
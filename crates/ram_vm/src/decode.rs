@@ -0,0 +1,191 @@
+//! Pre-decoded programs for the interpreter loop
+//!
+//! Looking up the definition of an instruction in the registry of the
+//! database locks the registry and hashes the kind of the instruction, which
+//! costs more than executing most instructions. Before a machine runs, its
+//! program is lowered into a dense array of [`DecodedOp`]s instead: each one
+//! holds the index of its definition in a jump table of the definitions the
+//! program uses, its operand, and the instruction its label operand points
+//! to, if any. A step then dispatches by indexing two arrays.
+//!
+//! A peephole pass also marks the common pairs of instructions that can run
+//! as one [`Superinstruction`], such as `LOAD` followed by `ADD`. Machines
+//! with [fusion](crate::VirtualMachine::set_fusion) enabled execute both
+//! instructions of a pair in one iteration of their loop, skipping the checks
+//! between steps. The first instruction of a pair never jumps or halts, so
+//! the pair behaves as the two instructions one after the other, and a jump
+//! to the second one simply runs it alone.
+//!
+//! The `interpreter` benchmarks of this crate compare both ways to find the
+//! definition of an instruction, and whole runs of loop-heavy programs
+//! interpreted and decoded, with `cargo bench -p ram_vm --bench interpreter`.
+//! The programs they run and how their results are tracked are described in
+//! the execution page of the VM documentation.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use ram_core::instruction::{InstructionDefinition, InstructionKind, OutputFormat};
+use ram_core::operand::{Operand, OperandValue};
+
+use crate::db::{VmDatabase, VmDatabaseImpl};
+use crate::program::Program;
+
+/// An instruction ready to be dispatched
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedOp {
+    /// The index of the definition in the jump table, or `None` if the
+    /// instruction set has no definition for the instruction
+    pub handler: Option<usize>,
+    /// The operand of the instruction
+    pub operand: Option<Operand>,
+    /// The index of the instruction the label operand points to
+    pub target: Option<usize>,
+    /// How the values the instruction writes to the output are shown
+    pub output_format: OutputFormat,
+    /// The superinstruction the instruction forms with the next one, if any
+    pub fusion: Option<Superinstruction>,
+}
+
+/// A pair of instructions that runs as one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Superinstruction {
+    /// `LOAD` followed by `ADD`, `SUB`, `MUL` or `DIV`
+    LoadArithmetic,
+    /// `LOAD` followed by `STORE`, copying a value
+    LoadStore,
+    /// `ADD`, `SUB`, `MUL` or `DIV` followed by `STORE`
+    ArithmeticStore,
+    /// `LOAD` or `SUB` followed by `JZERO` or `JGTZ`, comparing and branching
+    CompareJump,
+}
+
+impl Superinstruction {
+    /// Get the superinstruction two instructions in a row form, if any
+    pub fn for_pair(first: &InstructionKind, second: &InstructionKind) -> Option<Self> {
+        use InstructionKind::*;
+
+        match (first, second) {
+            (Load, Add | Sub | Mul | Div) => Some(Self::LoadArithmetic),
+            (Load, Store) => Some(Self::LoadStore),
+            (Add | Sub | Mul | Div, Store) => Some(Self::ArithmeticStore),
+            (Load | Sub, JumpZero | JumpGtz) => Some(Self::CompareJump),
+            _ => None,
+        }
+    }
+}
+
+/// A program lowered into decoded operations
+#[derive(Clone, Default)]
+pub struct DecodedProgram {
+    /// The definitions of the instructions the program uses, once each
+    handlers: Vec<Arc<dyn InstructionDefinition>>,
+    /// The operations, by instruction index
+    ops: Vec<DecodedOp>,
+}
+
+impl DecodedProgram {
+    /// Decode a program against the instruction set of a database
+    ///
+    /// Instructions without a definition are still decoded, so the machine
+    /// only fails if it reaches them.
+    pub fn decode(program: &Program, db: &VmDatabaseImpl) -> Self {
+        let mut handlers = Vec::new();
+        let mut indices = HashMap::new();
+        let ops = program
+            .instructions
+            .iter()
+            .map(|instruction| {
+                let handler = match indices.get(&instruction.kind) {
+                    Some(&index) => index,
+                    None => {
+                        let index =
+                            db.get_instruction_definition(&instruction.kind).map(|definition| {
+                                handlers.push(definition);
+                                handlers.len() - 1
+                            });
+                        indices.insert(instruction.kind.clone(), index);
+                        index
+                    }
+                };
+                let target = match &instruction.operand {
+                    Some(Operand { value: OperandValue::String(label), .. }) => {
+                        program.labels.get(label).copied()
+                    }
+                    _ => None,
+                };
+                DecodedOp {
+                    handler,
+                    operand: instruction.operand.clone(),
+                    target,
+                    output_format: instruction.output_format,
+                    fusion: None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut decoded = Self { handlers, ops };
+        decoded.fuse(program);
+        decoded
+    }
+
+    /// Mark the pairs of instructions that form a superinstruction
+    ///
+    /// Both instructions of a pair must have a definition, so a pair never
+    /// fails to dispatch halfway.
+    fn fuse(&mut self, program: &Program) {
+        for (index, pair) in program.instructions.windows(2).enumerate() {
+            let defined =
+                self.ops[index].handler.is_some() && self.ops[index + 1].handler.is_some();
+            if defined {
+                self.ops[index].fusion = Superinstruction::for_pair(&pair[0].kind, &pair[1].kind);
+            }
+        }
+    }
+
+    /// Get the operation at an instruction index
+    pub fn op(&self, index: usize) -> Option<&DecodedOp> {
+        self.ops.get(index)
+    }
+
+    /// Get the definition that executes an operation
+    pub fn handler(&self, op: &DecodedOp) -> Option<&Arc<dyn InstructionDefinition>> {
+        op.handler.map(|index| &self.handlers[index])
+    }
+
+    /// Get the instruction that `label` points to, if it is the label operand
+    /// of the operation at an instruction index
+    pub fn target(&self, index: usize, label: &str) -> Option<usize> {
+        let op = self.ops.get(index)?;
+        match &op.operand {
+            Some(Operand { value: OperandValue::String(name), .. }) if name == label => op.target,
+            _ => None,
+        }
+    }
+
+    /// Get the number of pairs of instructions that form a superinstruction
+    pub fn fused_count(&self) -> usize {
+        self.ops.iter().filter(|op| op.fusion.is_some()).count()
+    }
+
+    /// Get the number of operations
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Check if there are no operations
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl fmt::Debug for DecodedProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let handlers = self.handlers.iter().map(|handler| handler.name()).collect::<Vec<_>>();
+        f.debug_struct("DecodedProgram")
+            .field("handlers", &handlers)
+            .field("ops", &self.ops)
+            .finish()
+    }
+}
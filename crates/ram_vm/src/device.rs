@@ -0,0 +1,336 @@
+//! Memory-mapped devices for the RAM virtual machine
+//!
+//! A device claims a range of addresses. Reads and writes to those addresses,
+//! through registers or heap memory alike, go to the device instead of memory.
+//! This models I/O beyond `READ` and `WRITE` in a uniform way.
+
+use std::any::Any;
+use std::ops::Range;
+
+use ram_core::error::VmError;
+
+/// A device mapped to a range of addresses
+///
+/// Offsets are relative to the start of the mapped range.
+pub trait Device: Any {
+    /// The name of the device, used in error messages
+    fn name(&self) -> &str;
+
+    /// Read the cell at `offset`
+    fn read(&self, offset: i64) -> Result<i64, VmError>;
+
+    /// Write `value` to the cell at `offset`
+    fn write(&mut self, offset: i64, value: i64) -> Result<(), VmError>;
+}
+
+/// The devices mapped into the address space of a virtual machine
+#[derive(Default)]
+pub struct DeviceMap {
+    /// The mapped devices with the addresses they claim
+    devices: Vec<(Range<i64>, Box<dyn Device>)>,
+}
+
+impl DeviceMap {
+    /// Create an empty device map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a device to a range of addresses
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty or overlaps the range of another device.
+    pub fn map(&mut self, range: Range<i64>, device: impl Device) {
+        assert!(!range.is_empty(), "Cannot map device '{}' to an empty range", device.name());
+        if let Some((mapped, other)) = self
+            .devices
+            .iter()
+            .find(|(mapped, _)| range.start < mapped.end && mapped.start < range.end)
+        {
+            panic!(
+                "Cannot map device '{}' to {:?}: it overlaps device '{}' at {:?}",
+                device.name(),
+                range,
+                other.name(),
+                mapped
+            );
+        }
+        self.devices.push((range, Box::new(device)));
+    }
+
+    /// Check if no device is mapped
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Iterate over the mapped devices and their ranges
+    pub fn iter(&self) -> impl Iterator<Item = (&Range<i64>, &dyn Device)> {
+        self.devices.iter().map(|(range, device)| (range, device.as_ref()))
+    }
+
+    /// Get the device of type `T` mapped at `address`
+    pub fn get<T: Device>(&self, address: i64) -> Option<&T> {
+        let (_, index) = self.find(address)?;
+        (self.devices[index].1.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    /// Read `address` from the device mapped there, if any
+    pub fn read(&self, address: i64) -> Option<Result<i64, VmError>> {
+        let (offset, index) = self.find(address)?;
+        Some(self.devices[index].1.read(offset))
+    }
+
+    /// Write `value` to the device mapped at `address`, if any
+    pub fn write(&mut self, address: i64, value: i64) -> Option<Result<(), VmError>> {
+        let (offset, index) = self.find(address)?;
+        Some(self.devices[index].1.write(offset, value))
+    }
+
+    /// Find the offset of `address` and the index of the device mapped there
+    fn find(&self, address: i64) -> Option<(i64, usize)> {
+        self.devices
+            .iter()
+            .position(|(range, _)| range.contains(&address))
+            .map(|index| (address - self.devices[index].0.start, index))
+    }
+}
+
+impl std::fmt::Debug for DeviceMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter().map(|(range, device)| (range, device.name()))).finish()
+    }
+}
+
+/// Fail an access to an offset the device does not have
+fn invalid_offset(device: &dyn Device, offset: i64) -> VmError {
+    VmError::InvalidMemoryAccess(format!(
+        "Device '{}' has no cell at offset {}",
+        device.name(),
+        offset
+    ))
+}
+
+/// Fail a write to a cell that can only be read
+fn read_only(device: &dyn Device, offset: i64) -> VmError {
+    VmError::InvalidMemoryAccess(format!(
+        "Cell at offset {} of device '{}' is read-only",
+        offset,
+        device.name()
+    ))
+}
+
+/// An input tape that the program reads through its head
+///
+/// | Offset | Access     | Cell                                     |
+/// |--------|------------|------------------------------------------|
+/// | 0      | read       | The value under the head, 0 past the end |
+/// | 1      | read/write | The position of the head                 |
+/// | 2      | read       | 1 if the head is past the end, else 0    |
+///
+/// Writing any value to offset 3 moves the head to the next cell.
+#[derive(Debug, Clone)]
+pub struct InputTape {
+    /// The values on the tape
+    values: Vec<i64>,
+    /// The position of the head
+    head: usize,
+}
+
+impl InputTape {
+    /// The cell holding the value under the head
+    pub const VALUE: i64 = 0;
+    /// The cell holding the position of the head
+    pub const HEAD: i64 = 1;
+    /// The cell telling if the head is past the end of the tape
+    pub const END: i64 = 2;
+    /// The cell that moves the head to the next cell when written
+    pub const ADVANCE: i64 = 3;
+    /// The number of cells of the device
+    pub const SIZE: i64 = 4;
+
+    /// Create an input tape with the head on its first value
+    pub fn new(values: Vec<i64>) -> Self {
+        Self { values, head: 0 }
+    }
+
+    /// The position of the head
+    pub fn head(&self) -> usize {
+        self.head
+    }
+}
+
+impl Device for InputTape {
+    fn name(&self) -> &str {
+        "input tape"
+    }
+
+    fn read(&self, offset: i64) -> Result<i64, VmError> {
+        match offset {
+            Self::VALUE => Ok(self.values.get(self.head).copied().unwrap_or(0)),
+            Self::HEAD => Ok(self.head as i64),
+            Self::END => Ok((self.head >= self.values.len()) as i64),
+            Self::ADVANCE => Ok(0),
+            _ => Err(invalid_offset(self, offset)),
+        }
+    }
+
+    fn write(&mut self, offset: i64, value: i64) -> Result<(), VmError> {
+        match offset {
+            Self::HEAD if value < 0 => Err(VmError::InvalidMemoryAccess(format!(
+                "Cannot move the head of the input tape to {}",
+                value
+            ))),
+            Self::HEAD => {
+                self.head = value as usize;
+                Ok(())
+            }
+            Self::ADVANCE => {
+                self.head += 1;
+                Ok(())
+            }
+            _ if (0..Self::SIZE).contains(&offset) => Err(read_only(self, offset)),
+            _ => Err(invalid_offset(self, offset)),
+        }
+    }
+}
+
+/// An output tape that the program appends values to
+///
+/// | Offset | Access     | Cell                                            |
+/// |--------|------------|-------------------------------------------------|
+/// | 0      | read/write | Writing appends a value, reading gives the last |
+/// | 1      | read       | The number of values on the tape                |
+#[derive(Debug, Clone, Default)]
+pub struct OutputTape {
+    /// The values written to the tape
+    pub values: Vec<i64>,
+}
+
+impl OutputTape {
+    /// The cell that appends the values written to it
+    pub const VALUE: i64 = 0;
+    /// The cell holding the number of values on the tape
+    pub const LEN: i64 = 1;
+    /// The number of cells of the device
+    pub const SIZE: i64 = 2;
+
+    /// Create an empty output tape
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for OutputTape {
+    fn name(&self) -> &str {
+        "output tape"
+    }
+
+    fn read(&self, offset: i64) -> Result<i64, VmError> {
+        match offset {
+            Self::VALUE => Ok(self.values.last().copied().unwrap_or(0)),
+            Self::LEN => Ok(self.values.len() as i64),
+            _ => Err(invalid_offset(self, offset)),
+        }
+    }
+
+    fn write(&mut self, offset: i64, value: i64) -> Result<(), VmError> {
+        match offset {
+            Self::VALUE => {
+                self.values.push(value);
+                Ok(())
+            }
+            Self::LEN => Err(read_only(self, offset)),
+            _ => Err(invalid_offset(self, offset)),
+        }
+    }
+}
+
+/// A grid of cells that programs draw on, stored row by row
+///
+/// The cell at column `x` and row `y` is at offset `y * width + x`.
+#[derive(Debug, Clone)]
+pub struct DisplayBuffer {
+    /// The number of columns
+    width: usize,
+    /// The number of rows
+    height: usize,
+    /// The cells, row by row
+    cells: Vec<i64>,
+}
+
+impl DisplayBuffer {
+    /// Create a blank display of `width` columns and `height` rows
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, cells: vec![0; width * height] }
+    }
+
+    /// The number of cells of the display, to map it to a range of that length
+    pub fn size(&self) -> i64 {
+        self.cells.len() as i64
+    }
+
+    /// The number of columns
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The value of the cell at column `x` and row `y`
+    pub fn get(&self, x: usize, y: usize) -> Option<i64> {
+        if x < self.width { self.cells.get(y * self.width + x).copied() } else { None }
+    }
+
+    /// Iterate over the rows of the display
+    pub fn rows(&self) -> impl Iterator<Item = &[i64]> {
+        self.cells.chunks(self.width.max(1))
+    }
+
+    /// Render the display as text, one line per row
+    ///
+    /// Cells holding a printable character code are drawn as that character,
+    /// blank cells as spaces and any other value as `#`.
+    pub fn render(&self) -> String {
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in self.rows() {
+            for &cell in row {
+                text.push(match u32::try_from(cell).ok().and_then(char::from_u32) {
+                    _ if cell == 0 => ' ',
+                    Some(c) if !c.is_control() => c,
+                    _ => '#',
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Get the cell at `offset`, failing if it is outside the display
+    fn index(&self, offset: i64) -> Result<usize, VmError> {
+        usize::try_from(offset)
+            .ok()
+            .filter(|&index| index < self.cells.len())
+            .ok_or_else(|| invalid_offset(self, offset))
+    }
+}
+
+impl Device for DisplayBuffer {
+    fn name(&self) -> &str {
+        "display buffer"
+    }
+
+    fn read(&self, offset: i64) -> Result<i64, VmError> {
+        Ok(self.cells[self.index(offset)?])
+    }
+
+    fn write(&mut self, offset: i64, value: i64) -> Result<(), VmError> {
+        let index = self.index(offset)?;
+        self.cells[index] = value;
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! Checking that two programs behave the same
+//!
+//! Two programs are observationally equivalent on an input if they write the
+//! same values, in the same order, and end the same way: both halt, both fail,
+//! or both run out of steps. The programs run in lockstep, one observable event
+//! at a time, so a divergence is found as soon as it happens, even if one of
+//! the programs would never halt.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::db::VmDatabaseImpl;
+use crate::grader::DEFAULT_MAX_STEPS;
+use crate::io::{VecInput, VecOutput};
+use crate::program::Program;
+use crate::rng::Rng;
+use crate::vm::VirtualMachine;
+
+/// The input values an equivalence check runs the programs with
+///
+/// Every input is a sequence with one value from each range, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDomain {
+    ranges: Vec<RangeInclusive<i64>>,
+}
+
+impl InputDomain {
+    /// Create a domain with one range for each input value
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is empty.
+    pub fn new(ranges: Vec<RangeInclusive<i64>>) -> Self {
+        assert!(ranges.iter().all(|range| !range.is_empty()), "Input ranges can not be empty");
+        Self { ranges }
+    }
+
+    /// The number of inputs in the domain, or `None` if it does not fit in a `u64`
+    pub fn size(&self) -> Option<u64> {
+        self.ranges.iter().try_fold(1u64, |size, range| {
+            let values = range.end().abs_diff(*range.start()).checked_add(1)?;
+            size.checked_mul(values)
+        })
+    }
+
+    /// Iterate over every input of the domain, varying the last value fastest
+    pub fn inputs(&self) -> impl Iterator<Item = Vec<i64>> + '_ {
+        let mut next = Some(self.ranges.iter().map(|range| *range.start()).collect::<Vec<_>>());
+        std::iter::from_fn(move || {
+            let input = next.take()?;
+            let mut following = input.clone();
+            for (value, range) in following.iter_mut().zip(&self.ranges).rev() {
+                if *value < *range.end() {
+                    *value += 1;
+                    next = Some(following);
+                    break;
+                }
+                *value = *range.start();
+            }
+            Some(input)
+        })
+    }
+
+    /// Draw a random input from the domain
+    pub fn sample(&self, rng: &mut Rng) -> Vec<i64> {
+        self.ranges
+            .iter()
+            .map(|range| {
+                let values = range.end().abs_diff(*range.start()).wrapping_add(1);
+                let offset = if values == 0 { rng.next_u64() } else { rng.next_u64() % values };
+                range.start().wrapping_add_unsigned(offset)
+            })
+            .collect()
+    }
+}
+
+/// Options of an equivalence check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EquivalenceOptions {
+    /// The maximum number of instructions each program executes on an input
+    pub max_steps: usize,
+    /// The seed of the random numbers of both programs
+    pub seed: u64,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> Self {
+        Self { max_steps: DEFAULT_MAX_STEPS, seed: 0 }
+    }
+}
+
+/// Something a program does that can be observed from outside
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The program wrote a value
+    Output(i64),
+    /// The program halted
+    Halted,
+    /// The program failed with a runtime error
+    Failed(String),
+    /// The program did not halt within the step limit
+    StepLimitExceeded,
+}
+
+impl Event {
+    /// Check if two events are indistinguishable
+    ///
+    /// Failures match regardless of their errors.
+    pub fn matches(&self, other: &Event) -> bool {
+        match (self, other) {
+            (Event::Failed(_), Event::Failed(_)) => true,
+            _ => self == other,
+        }
+    }
+
+    /// Check if the program does nothing after this event
+    pub fn is_final(&self) -> bool {
+        !matches!(self, Event::Output(_))
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Output(value) => write!(f, "writes {}", value),
+            Event::Halted => write!(f, "halts"),
+            Event::Failed(error) => write!(f, "fails: {}", error),
+            Event::StepLimitExceeded => write!(f, "is still running"),
+        }
+    }
+}
+
+/// The first point where two programs behave differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The input the programs diverge on
+    pub input: Vec<i64>,
+    /// The number of events both programs had in common before diverging
+    pub position: usize,
+    /// What the first program did
+    pub left: Event,
+    /// The number of instructions the first program executed until then
+    pub left_steps: u64,
+    /// What the second program did
+    pub right: Event,
+    /// The number of instructions the second program executed until then
+    pub right_steps: u64,
+}
+
+/// The result of an equivalence check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EquivalenceReport {
+    /// The number of inputs the programs ran with
+    pub inputs_checked: usize,
+    /// The first divergence found, if any
+    pub divergence: Option<Divergence>,
+}
+
+impl EquivalenceReport {
+    /// Check if the programs behaved the same on every input
+    pub fn equivalent(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/// Check two programs on every input, stopping at the first divergence
+pub fn check_equivalence(
+    left: &Program,
+    right: &Program,
+    db: &Arc<VmDatabaseImpl>,
+    inputs: impl IntoIterator<Item = Vec<i64>>,
+    options: &EquivalenceOptions,
+) -> EquivalenceReport {
+    let mut report = EquivalenceReport::default();
+    for input in inputs {
+        report.inputs_checked += 1;
+        report.divergence = compare_on_input(left, right, db, &input, options);
+        if report.divergence.is_some() {
+            break;
+        }
+    }
+    report
+}
+
+/// Run two programs in lockstep on an input and find where they diverge
+pub fn compare_on_input(
+    left: &Program,
+    right: &Program,
+    db: &Arc<VmDatabaseImpl>,
+    input: &[i64],
+    options: &EquivalenceOptions,
+) -> Option<Divergence> {
+    let mut left = Observer::new(left, db, input, options);
+    let mut right = Observer::new(right, db, input, options);
+
+    for position in 0.. {
+        let (left_event, right_event) = (left.next_event(), right.next_event());
+        if !left_event.matches(&right_event) {
+            return Some(Divergence {
+                input: input.to_vec(),
+                position,
+                left: left_event,
+                left_steps: left.vm.steps(),
+                right: right_event,
+                right_steps: right.vm.steps(),
+            });
+        }
+        if left_event.is_final() {
+            break;
+        }
+    }
+    None
+}
+
+/// Runs a program one observable event at a time
+struct Observer {
+    vm: VirtualMachine<VecInput, VecOutput>,
+    max_steps: u64,
+    reported_outputs: usize,
+    failure: Option<String>,
+}
+
+impl Observer {
+    fn new(
+        program: &Program,
+        db: &Arc<VmDatabaseImpl>,
+        input: &[i64],
+        options: &EquivalenceOptions,
+    ) -> Self {
+        let input = VecInput::new(input.to_vec());
+        let vm = VirtualMachine::builder(program.clone(), input, VecOutput::new(), db.clone())
+            .with_seed(options.seed)
+            .build();
+        Self { vm, max_steps: options.max_steps as u64, reported_outputs: 0, failure: None }
+    }
+
+    /// Run until the program writes a value or ends
+    ///
+    /// Values written by an instruction are reported before it fails.
+    fn next_event(&mut self) -> Event {
+        loop {
+            if let Some(&value) = self.vm.output.values.get(self.reported_outputs) {
+                self.reported_outputs += 1;
+                return Event::Output(value);
+            }
+            if let Some(error) = &self.failure {
+                return Event::Failed(error.clone());
+            }
+            if self.vm.is_finished() {
+                return Event::Halted;
+            }
+            if self.vm.steps() >= self.max_steps {
+                return Event::StepLimitExceeded;
+            }
+            if let Err(error) = self.vm.step() {
+                self.failure = Some(error.to_string());
+            }
+        }
+    }
+}
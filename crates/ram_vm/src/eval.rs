@@ -0,0 +1,446 @@
+//! Evaluation of expressions over the state of a machine
+//!
+//! Debuggers and interactive front ends let people ask about a paused run
+//! with expressions such as `R3 + M[R1] * 2` or `ACC == 0`. This module
+//! parses such expressions and evaluates them against an [`EvalContext`],
+//! which [`VirtualMachine`] implements, so every front end understands the
+//! same language.
+//!
+//! An expression is made of:
+//!
+//! - Numbers, such as `42` or `-7`.
+//! - `ACC` and `PC`, the accumulator and the index of the next instruction.
+//! - Registers, such as `R5`, or `R[expr]` with a computed address.
+//! - Cells of heap memory, such as `M[12]` or `M[R1 + 1]`.
+//! - Labels of the program, which stand for the index of their instruction.
+//! - The operators `+`, `-`, `*`, `/` and `%`, and the comparisons `==`,
+//!   `!=`, `<`, `<=`, `>` and `>=`, which give 1 if they hold and 0 if not.
+//! - Parentheses.
+//!
+//! Unlike operands of instructions and watchpoints, a bare number is a value,
+//! not a register. Errors are [`EvalError`] diagnostics pointing at the part of
+//! the expression they are about.
+
+use std::ops::Range;
+
+use miette::{Diagnostic, SourceSpan};
+use ram_core::arithmetic::{ArithmeticMode, ArithmeticOp};
+use thiserror::Error;
+
+use crate::io::{Input, Output};
+use crate::vm::VirtualMachine;
+
+/// The state an expression is evaluated against
+pub trait EvalContext {
+    /// Get the value of the accumulator
+    fn accumulator(&self) -> i64;
+
+    /// Get the index of the instruction that runs next
+    fn pc(&self) -> usize;
+
+    /// Get the value of a register
+    fn register(&self, address: i64) -> i64;
+
+    /// Get the value of a cell of heap memory
+    fn memory(&self, address: i64) -> i64;
+
+    /// Get the index of the instruction a label is at
+    fn label(&self, name: &str) -> Option<usize>;
+}
+
+impl<I: Input, O: Output> EvalContext for VirtualMachine<I, O> {
+    fn accumulator(&self) -> i64 {
+        VirtualMachine::accumulator(self)
+    }
+
+    fn pc(&self) -> usize {
+        VirtualMachine::pc(self)
+    }
+
+    fn register(&self, address: i64) -> i64 {
+        if address == 0 { self.accumulator() } else { self.get_register_value(address) }
+    }
+
+    fn memory(&self, address: i64) -> i64 {
+        self.get_heap_value(address)
+    }
+
+    fn label(&self, name: &str) -> Option<usize> {
+        self.program().labels.get(name).copied()
+    }
+}
+
+/// An error parsing or evaluating an expression
+#[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic)]
+#[error("{message}")]
+pub struct EvalError {
+    /// The message of the error
+    pub message: String,
+    /// The byte range of the expression the error is about
+    pub range: Range<usize>,
+    #[source_code]
+    source_code: String,
+    #[label("{label}")]
+    span: SourceSpan,
+    label: String,
+}
+
+impl EvalError {
+    fn new(
+        text: &str,
+        message: impl Into<String>,
+        label: impl Into<String>,
+        range: Range<usize>,
+    ) -> Self {
+        Self {
+            message: message.into(),
+            source_code: text.to_string(),
+            span: range.clone().into(),
+            range,
+            label: label.into(),
+        }
+    }
+}
+
+/// A binary operator of an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// An arithmetic operation, done like the instructions do it
+    Arithmetic(ArithmeticOp),
+    /// The remainder of a division
+    Rem,
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+}
+
+/// A parsed expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// A number
+    Number(i64),
+    /// The accumulator
+    Accumulator,
+    /// The index of the instruction that runs next
+    Pc,
+    /// A register, at a computed address
+    Register(Box<Expr>),
+    /// A cell of heap memory, at a computed address
+    Memory(Box<Expr>),
+    /// A label, with its byte range in the expression
+    Label(String, Range<usize>),
+    /// A negated expression
+    Neg(Box<Expr>, Range<usize>),
+    /// A binary operation, with the byte range of its operator
+    Binary(BinaryOp, Box<Expr>, Box<Expr>, Range<usize>),
+}
+
+impl Expr {
+    /// Parse an expression
+    pub fn parse(text: &str) -> Result<Self, EvalError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { text, tokens, position: 0 };
+        let expr = parser.comparison()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expr),
+            Some(token) => Err(EvalError::new(
+                text,
+                format!("Unexpected '{}' after the expression", &text[token.range.clone()]),
+                "expected the end of the expression",
+                token.range.clone(),
+            )),
+        }
+    }
+
+    /// Evaluate the expression against the state of a machine
+    ///
+    /// `text` is the text the expression was parsed from, for the errors to
+    /// point into.
+    pub fn eval(&self, text: &str, context: &impl EvalContext) -> Result<i64, EvalError> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Accumulator => Ok(context.accumulator()),
+            Expr::Pc => Ok(context.pc() as i64),
+            Expr::Register(address) => Ok(context.register(address.eval(text, context)?)),
+            Expr::Memory(address) => Ok(context.memory(address.eval(text, context)?)),
+            Expr::Label(name, range) => context.label(name).map(|pc| pc as i64).ok_or_else(|| {
+                EvalError::new(
+                    text,
+                    format!("Unknown label '{}'", name),
+                    "no label of the program has this name",
+                    range.clone(),
+                )
+            }),
+            Expr::Neg(operand, range) => {
+                let value = operand.eval(text, context)?;
+                value.checked_neg().ok_or_else(|| {
+                    EvalError::new(
+                        text,
+                        format!("Arithmetic overflow: -{} does not fit in 64 bits", value),
+                        "overflows",
+                        range.clone(),
+                    )
+                })
+            }
+            Expr::Binary(op, lhs, rhs, range) => {
+                let lhs = lhs.eval(text, context)?;
+                let rhs = rhs.eval(text, context)?;
+                let error = |message: String| EvalError::new(text, message, "here", range.clone());
+                match op {
+                    BinaryOp::Arithmetic(op) => ArithmeticMode::Checked
+                        .apply(*op, lhs, rhs)
+                        .map_err(|e| error(e.to_string())),
+                    BinaryOp::Rem if rhs == 0 => Err(error("Division by zero".to_string())),
+                    BinaryOp::Rem => lhs.checked_rem(rhs).ok_or_else(|| {
+                        error(format!(
+                            "Arithmetic overflow: {} % {} does not fit in 64 bits",
+                            lhs, rhs
+                        ))
+                    }),
+                    BinaryOp::Eq => Ok((lhs == rhs) as i64),
+                    BinaryOp::Ne => Ok((lhs != rhs) as i64),
+                    BinaryOp::Lt => Ok((lhs < rhs) as i64),
+                    BinaryOp::Le => Ok((lhs <= rhs) as i64),
+                    BinaryOp::Gt => Ok((lhs > rhs) as i64),
+                    BinaryOp::Ge => Ok((lhs >= rhs) as i64),
+                }
+            }
+        }
+    }
+}
+
+/// Parse and evaluate an expression against the state of a machine
+pub fn evaluate(text: &str, context: &impl EvalContext) -> Result<i64, EvalError> {
+    Expr::parse(text)?.eval(text, context)
+}
+
+/// A token of an expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    kind: TokenKind,
+    range: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Number(u64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// The symbols of expressions, the longer ones first
+const SYMBOLS: &[&str] =
+    &["==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "(", ")", "[", "]"];
+
+/// Split an expression into tokens
+fn tokenize(text: &str) -> Result<Vec<Token>, EvalError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.char_indices().peekable();
+    while let Some(&(start, c)) = rest.peek() {
+        if c.is_whitespace() {
+            rest.next();
+        } else if c.is_ascii_digit() {
+            let mut end = start;
+            while let Some(&(i, c)) = rest.peek()
+                && c.is_ascii_alphanumeric()
+            {
+                end = i + c.len_utf8();
+                rest.next();
+            }
+            let value = text[start..end].parse().map_err(|_| {
+                EvalError::new(
+                    text,
+                    format!("Invalid number '{}'", &text[start..end]),
+                    "too large for a 64 bit integer",
+                    start..end,
+                )
+            })?;
+            tokens.push(Token { kind: TokenKind::Number(value), range: start..end });
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = rest.peek()
+                && (c.is_alphanumeric() || c == '_')
+            {
+                end = i + c.len_utf8();
+                rest.next();
+            }
+            let name = text[start..end].to_string();
+            tokens.push(Token { kind: TokenKind::Name(name), range: start..end });
+        } else if let Some(symbol) =
+            SYMBOLS.iter().find(|symbol| text[start..].starts_with(**symbol))
+        {
+            for _ in 0..symbol.len() {
+                rest.next();
+            }
+            tokens.push(Token {
+                kind: TokenKind::Symbol(*symbol),
+                range: start..start + symbol.len(),
+            });
+        } else {
+            let range = start..start + c.len_utf8();
+            return Err(EvalError::new(
+                text,
+                format!("Unexpected character '{}'", c),
+                "not part of an expression",
+                range,
+            ));
+        }
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser of expressions
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    /// Consume the next token if it is one of `symbols`
+    fn eat(&mut self, symbols: &[&str]) -> Option<(&'static str, Range<usize>)> {
+        match self.tokens.get(self.position) {
+            Some(Token { kind: TokenKind::Symbol(symbol), range }) if symbols.contains(symbol) => {
+                self.position += 1;
+                Some((*symbol, range.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Fail with an error at the next token, or at the end of the expression
+    fn expected(&self, what: &str) -> EvalError {
+        match self.tokens.get(self.position) {
+            Some(token) => EvalError::new(
+                self.text,
+                format!("Expected {}, found '{}'", what, &self.text[token.range.clone()]),
+                format!("expected {}", what),
+                token.range.clone(),
+            ),
+            None => EvalError::new(
+                self.text,
+                format!("Expected {}, found the end of the expression", what),
+                format!("expected {}", what),
+                self.text.len()..self.text.len(),
+            ),
+        }
+    }
+
+    /// Parse a comparison, or an expression without one
+    fn comparison(&mut self) -> Result<Expr, EvalError> {
+        let lhs = self.sum()?;
+        let Some((symbol, range)) = self.eat(&["==", "!=", "<=", ">=", "<", ">"]) else {
+            return Ok(lhs);
+        };
+        let op = match symbol {
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "<=" => BinaryOp::Le,
+            ">=" => BinaryOp::Ge,
+            "<" => BinaryOp::Lt,
+            _ => BinaryOp::Gt,
+        };
+        let rhs = self.sum()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs), range))
+    }
+
+    /// Parse terms joined by `+` and `-`
+    fn sum(&mut self) -> Result<Expr, EvalError> {
+        let mut lhs = self.product()?;
+        while let Some((symbol, range)) = self.eat(&["+", "-"]) {
+            let op = if symbol == "+" { ArithmeticOp::Add } else { ArithmeticOp::Sub };
+            let rhs = self.product()?;
+            lhs = Expr::Binary(BinaryOp::Arithmetic(op), Box::new(lhs), Box::new(rhs), range);
+        }
+        Ok(lhs)
+    }
+
+    /// Parse factors joined by `*`, `/` and `%`
+    fn product(&mut self) -> Result<Expr, EvalError> {
+        let mut lhs = self.unary()?;
+        while let Some((symbol, range)) = self.eat(&["*", "/", "%"]) {
+            let op = match symbol {
+                "*" => BinaryOp::Arithmetic(ArithmeticOp::Mul),
+                "/" => BinaryOp::Arithmetic(ArithmeticOp::Div),
+                _ => BinaryOp::Rem,
+            };
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), range);
+        }
+        Ok(lhs)
+    }
+
+    /// Parse a factor, possibly negated
+    fn unary(&mut self) -> Result<Expr, EvalError> {
+        let Some((_, range)) = self.eat(&["-"]) else {
+            return self.primary();
+        };
+        // Negative numbers are folded, so that i64::MIN can be written
+        if let Some(Token { kind: TokenKind::Number(value), .. }) = self.tokens.get(self.position)
+            && let Some(value) = 0i64.checked_sub_unsigned(*value)
+        {
+            self.position += 1;
+            return Ok(Expr::Number(value));
+        }
+        let operand = self.unary()?;
+        Ok(Expr::Neg(Box::new(operand), range))
+    }
+
+    /// Parse a number, a place, a label or a parenthesized expression
+    fn primary(&mut self) -> Result<Expr, EvalError> {
+        let Some(token) = self.tokens.get(self.position).cloned() else {
+            return Err(self.expected("a value"));
+        };
+        match token.kind {
+            TokenKind::Number(value) => {
+                self.position += 1;
+                let value = i64::try_from(value).map_err(|_| {
+                    EvalError::new(
+                        self.text,
+                        format!("Invalid number '{}'", value),
+                        "too large for a 64 bit integer",
+                        token.range.clone(),
+                    )
+                })?;
+                Ok(Expr::Number(value))
+            }
+            TokenKind::Symbol("(") => {
+                self.position += 1;
+                let expr = self.comparison()?;
+                self.eat(&[")"]).ok_or_else(|| self.expected("')'"))?;
+                Ok(expr)
+            }
+            TokenKind::Name(name) => {
+                self.position += 1;
+                let upper = name.to_ascii_uppercase();
+                match upper.as_str() {
+                    "ACC" => Ok(Expr::Accumulator),
+                    "PC" => Ok(Expr::Pc),
+                    "R" | "M" if self.eat(&["["]).is_some() => {
+                        let address = self.comparison()?;
+                        self.eat(&["]"]).ok_or_else(|| self.expected("']'"))?;
+                        Ok(if upper == "R" {
+                            Expr::Register(Box::new(address))
+                        } else {
+                            Expr::Memory(Box::new(address))
+                        })
+                    }
+                    _ => match upper.strip_prefix('R').map(str::parse::<i64>) {
+                        Some(Ok(address)) => Ok(Expr::Register(Box::new(Expr::Number(address)))),
+                        _ => Ok(Expr::Label(name, token.range)),
+                    },
+                }
+            }
+            TokenKind::Symbol(_) => Err(self.expected("a value")),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Grading of RAM programs against test cases
+//!
+//! A grading spec lists test cases with their input and expected output,
+//! limits on the resources a program may use and instructions it may not use.
+//! Grading a program runs every case and reports which passed, with the
+//! violations of the spec found in the program itself.
+
+use std::fmt;
+use std::sync::Arc;
+
+use ram_core::error::VmError;
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cancel::Cancellation;
+use crate::db::VmDatabaseImpl;
+use crate::io::{VecInput, VecOutput};
+use crate::program::Program;
+use crate::vm::VirtualMachine;
+
+/// The number of steps a case may take if the spec does not limit it
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+/// A test case of a grading spec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct TestCase {
+    /// The name of the case, shown in reports
+    pub name: String,
+    /// The input values of the program
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub input: Vec<i64>,
+    /// The values the program must write
+    pub output: Vec<i64>,
+}
+
+/// The resources a program may use
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields, rename_all = "kebab-case"))]
+pub struct Limits {
+    /// The maximum number of instructions executed in each case
+    pub max_steps: usize,
+    /// The maximum number of instructions in the program
+    pub max_instructions: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_steps: DEFAULT_MAX_STEPS, max_instructions: None }
+    }
+}
+
+/// What a program is graded against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields, rename_all = "kebab-case"))]
+pub struct GradingSpec {
+    /// The instructions the program may not use, by name
+    pub banned_instructions: Vec<String>,
+    /// The resources the program may use
+    pub limits: Limits,
+    /// The test cases the program must pass
+    pub cases: Vec<TestCase>,
+}
+
+/// A way a program breaks the rules of a spec, regardless of its cases
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "kebab-case"))]
+pub enum Violation {
+    /// The program uses a banned instruction
+    BannedInstruction {
+        /// The name of the instruction
+        name: String,
+        /// The index of the first instruction using it
+        index: usize,
+    },
+    /// The program has more instructions than allowed
+    TooManyInstructions {
+        /// The number of instructions of the program
+        count: usize,
+        /// The maximum number of instructions
+        max: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::BannedInstruction { name, index } => {
+                write!(f, "uses banned instruction {} at instruction {}", name, index)
+            }
+            Violation::TooManyInstructions { count, max } => {
+                write!(f, "has {} instructions, more than the {} allowed", count, max)
+            }
+        }
+    }
+}
+
+/// How a test case ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "status", content = "error", rename_all = "kebab-case"))]
+pub enum CaseStatus {
+    /// The program halted with the expected output
+    Passed,
+    /// The program halted with another output
+    WrongOutput,
+    /// The program did not halt within the step limit
+    StepLimitExceeded,
+    /// The program failed with a runtime error
+    RuntimeError(String),
+    /// The grading was cancelled before the case finished, or started
+    Cancelled,
+}
+
+/// The result of a test case
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CaseResult {
+    /// The name of the case
+    pub name: String,
+    /// How the case ended
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub status: CaseStatus,
+    /// The number of instructions executed
+    pub steps: u64,
+    /// The values written by the program
+    pub output: Vec<i64>,
+}
+
+impl CaseResult {
+    /// Check if the case passed
+    pub fn passed(&self) -> bool {
+        self.status == CaseStatus::Passed
+    }
+}
+
+/// The result of grading a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GradingReport {
+    /// The violations of the spec found in the program
+    pub violations: Vec<Violation>,
+    /// The results of the test cases, in the order of the spec
+    pub cases: Vec<CaseResult>,
+}
+
+impl GradingReport {
+    /// The number of cases that passed
+    pub fn passed_cases(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed()).count()
+    }
+
+    /// Check if the program passed every case without violating the spec
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty() && self.cases.iter().all(CaseResult::passed)
+    }
+}
+
+/// Grade a program against a spec
+///
+/// Every case runs on a fresh machine, even if the program violates the spec,
+/// so the report tells how far the program is from passing.
+pub fn grade(program: &Program, db: &Arc<VmDatabaseImpl>, spec: &GradingSpec) -> GradingReport {
+    let cases =
+        spec.cases.iter().map(|case| run_case(program, db, case, &spec.limits, None)).collect();
+    GradingReport { violations: find_violations(program, spec), cases }
+}
+
+/// Grade a program against a spec, stopping when the grading is cancelled
+///
+/// The case running when the grading is cancelled reports the steps and the
+/// output up to that point, and the cases after it are not run. All of them
+/// have the status [`CaseStatus::Cancelled`].
+pub fn grade_with_cancellation(
+    program: &Program,
+    db: &Arc<VmDatabaseImpl>,
+    spec: &GradingSpec,
+    cancellation: Arc<dyn Cancellation>,
+) -> GradingReport {
+    let mut cases = Vec::with_capacity(spec.cases.len());
+    for case in &spec.cases {
+        let result = if cancellation.is_cancelled() {
+            CaseResult {
+                name: case.name.clone(),
+                status: CaseStatus::Cancelled,
+                steps: 0,
+                output: Vec::new(),
+            }
+        } else {
+            run_case(program, db, case, &spec.limits, Some(&cancellation))
+        };
+        cases.push(result);
+    }
+    GradingReport { violations: find_violations(program, spec), cases }
+}
+
+/// Find the violations of the spec in a program
+fn find_violations(program: &Program, spec: &GradingSpec) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for banned in &spec.banned_instructions {
+        let index = program
+            .instructions
+            .iter()
+            .position(|instruction| instruction.kind.name().eq_ignore_ascii_case(banned));
+        if let Some(index) = index {
+            violations.push(Violation::BannedInstruction { name: banned.to_uppercase(), index });
+        }
+    }
+    if let Some(max) = spec.limits.max_instructions
+        && program.len() > max
+    {
+        violations.push(Violation::TooManyInstructions { count: program.len(), max });
+    }
+    violations
+}
+
+/// Run a test case on a fresh machine
+fn run_case(
+    program: &Program,
+    db: &Arc<VmDatabaseImpl>,
+    case: &TestCase,
+    limits: &Limits,
+    cancellation: Option<&Arc<dyn Cancellation>>,
+) -> CaseResult {
+    let input = VecInput::new(case.input.clone());
+    let mut vm = VirtualMachine::new(program.clone(), input, VecOutput::new(), db.clone());
+    if let Some(cancellation) = cancellation {
+        vm.set_cancellation(Arc::clone(cancellation));
+    }
+    let status = match vm.run_steps(limits.max_steps) {
+        Err(VmError::Cancelled(_)) => CaseStatus::Cancelled,
+        Err(error) => CaseStatus::RuntimeError(error.to_string()),
+        Ok(()) if !vm.is_finished() => CaseStatus::StepLimitExceeded,
+        Ok(()) if vm.output.values == case.output => CaseStatus::Passed,
+        Ok(()) => CaseStatus::WrongOutput,
+    };
+    CaseResult { name: case.name.clone(), status, steps: vm.steps(), output: vm.output.values }
+}
@@ -0,0 +1,77 @@
+//! Execution heatmaps of programs
+//!
+//! A heatmap tells how many times the instructions on each line of a program
+//! were executed during a run, to find the hot loops of the program. It is
+//! built from the execution counts of a machine and the spans of the HIR body
+//! the program was compiled from.
+
+use base_db::LineIndex;
+use hir::body::Body;
+
+/// The number of executions of the instructions on a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineHeat {
+    /// The line, counting from 0
+    pub line: u32,
+    /// The number of times the instructions on the line were executed
+    pub count: u64,
+}
+
+/// The number of executions of every line with instructions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Heatmap {
+    /// The lines with instructions, in order, including the ones never executed
+    pub lines: Vec<LineHeat>,
+    /// The highest count of a line
+    pub max_count: u64,
+}
+
+impl Heatmap {
+    /// Build the heatmap of a run
+    ///
+    /// # Parameters
+    ///
+    /// * `source` - The text of the program.
+    /// * `body` - The HIR body the program was compiled from.
+    /// * `counts` - The number of times each instruction of the program was
+    ///   executed, by index, as given by
+    ///   [`VirtualMachine::execution_counts`](crate::VirtualMachine::execution_counts).
+    pub fn new(source: &str, body: &Body, counts: &[u64]) -> Self {
+        let line_index = LineIndex::new(source);
+        let mut lines: Vec<LineHeat> = Vec::new();
+        for (instruction, &count) in body.instructions.values().zip(counts) {
+            let line = line_index.line_col(instruction.span.start).line;
+            match lines.iter_mut().find(|heat| heat.line == line) {
+                Some(heat) => heat.count += count,
+                None => lines.push(LineHeat { line, count }),
+            }
+        }
+        lines.sort_by_key(|heat| heat.line);
+
+        let max_count = lines.iter().map(|heat| heat.count).max().unwrap_or(0);
+        Self { lines, max_count }
+    }
+
+    /// Get the number of executions of a line, or `None` if it has no instructions
+    pub fn count(&self, line: u32) -> Option<u64> {
+        self.lines.iter().find(|heat| heat.line == line).map(|heat| heat.count)
+    }
+
+    /// Get how hot a line is, from 0 for lines never executed to 1 for the
+    /// hottest lines
+    pub fn heat(&self, line: u32) -> f64 {
+        match self.count(line) {
+            Some(count) if self.max_count > 0 => count as f64 / self.max_count as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Get the hottest line, the first one if several are as hot
+    pub fn hottest(&self) -> Option<LineHeat> {
+        self.lines
+            .iter()
+            .copied()
+            .filter(|heat| heat.count > 0)
+            .max_by(|a, b| a.count.cmp(&b.count).then(b.line.cmp(&a.line)))
+    }
+}
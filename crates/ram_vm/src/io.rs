@@ -3,6 +3,8 @@
 use std::io::{self, BufRead, Write};
 
 use ram_core::error::VmError;
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
 
 /// Input source for the RAM virtual machine
 pub trait Input {
@@ -14,6 +16,22 @@ pub trait Input {
 pub trait Output {
     /// Write a value to the output
     fn write(&mut self, value: i64) -> Result<(), VmError>;
+
+    /// Write a value to the output as the character whose code point it is
+    ///
+    /// Outputs that cannot show characters write the value as a number.
+    fn write_char(&mut self, value: i64) -> Result<(), VmError> {
+        to_char(value)?;
+        self.write(value)
+    }
+}
+
+/// Convert a value to the character whose code point it is
+pub fn to_char(value: i64) -> Result<char, VmError> {
+    u32::try_from(value)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| VmError::IoError(format!("{} is not a character code", value)))
 }
 
 /// Standard input implementation
@@ -83,9 +101,16 @@ impl Output for StdoutOutput {
         println!("Output: {}", value);
         Ok(())
     }
+
+    fn write_char(&mut self, value: i64) -> Result<(), VmError> {
+        print!("{}", to_char(value)?);
+        io::stdout().flush().map_err(|e| VmError::IoError(e.to_string()))
+    }
 }
 
 /// Vector-based input implementation for testing
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct VecInput {
     /// The input values
     values: Vec<i64>,
@@ -98,6 +123,11 @@ impl VecInput {
     pub fn new(values: Vec<i64>) -> Self {
         Self { values, pos: 0 }
     }
+
+    /// The number of values read so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
 }
 
 impl Input for VecInput {
@@ -113,15 +143,19 @@ impl Input for VecInput {
 
 /// Vector-based output implementation for testing
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct VecOutput {
     /// The output values
     pub values: Vec<i64>,
+    /// The values written as characters
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub text: String,
 }
 
 impl VecOutput {
     /// Create a new empty vector output
     pub fn new() -> Self {
-        Self { values: Vec::new() }
+        Self { values: Vec::new(), text: String::new() }
     }
 }
 
@@ -136,4 +170,10 @@ impl Output for VecOutput {
         self.values.push(value);
         Ok(())
     }
+
+    fn write_char(&mut self, value: i64) -> Result<(), VmError> {
+        self.text.push(to_char(value)?);
+        self.values.push(value);
+        Ok(())
+    }
 }
@@ -3,20 +3,50 @@
 //! This crate implements the RAM virtual machine, which can execute RAM programs.
 //! It provides a convenient API for creating and running RAM programs.
 
+pub mod bytecode;
+pub mod cancel;
 pub mod db;
+pub mod decode;
+pub mod device;
+pub mod equivalence;
+pub mod eval;
+pub mod grader;
+pub mod heatmap;
 pub mod io;
 pub mod memory;
+pub mod orchestration;
 pub mod program;
+pub mod report;
+pub mod rng;
 pub mod runner;
+pub mod source_map;
 #[cfg(test)]
 mod tests;
+pub mod trace;
 pub mod vm;
+pub mod watch;
 
-pub use crate::db::{VmDatabase, VmDatabaseImpl};
+pub use crate::cancel::{CancelFlag, Cancellation};
+pub use crate::db::{VmDatabase, VmDatabaseBuilder, VmDatabaseImpl};
+pub use crate::decode::{DecodedOp, DecodedProgram, Superinstruction};
+pub use crate::device::{Device, DeviceMap, DisplayBuffer, InputTape, OutputTape};
+pub use crate::equivalence::{EquivalenceReport, InputDomain, check_equivalence};
+pub use crate::eval::{EvalContext, EvalError, Expr, evaluate};
+pub use crate::grader::{GradingReport, GradingSpec, grade, grade_with_cancellation};
+pub use crate::heatmap::{Heatmap, LineHeat};
 pub use crate::io::{Input, Output, VecInput, VecOutput};
 pub use crate::memory::Memory;
+pub use crate::orchestration::{
+    Channel, InputSource, MachineEvent, MachineId, MachineOptions, MachineState, Orchestrator,
+    Quota,
+};
 pub use crate::program::Program;
+pub use crate::report::RuntimeError;
+pub use crate::rng::Rng;
 pub use crate::runner::{
     RunResult, run_program, run_program_with_max_iterations, run_program_with_memory,
 };
-pub use crate::vm::{VirtualMachine, VirtualMachineBuilder};
+pub use crate::source_map::{SourceLocation, SourceMap};
+pub use crate::trace::{Location, TraceStep};
+pub use crate::vm::{Snapshot, VirtualMachine, VirtualMachineBuilder};
+pub use crate::watch::{Access, WatchAccess, WatchHit, Watchpoint, Watchpoints};
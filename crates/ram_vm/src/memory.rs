@@ -50,11 +50,10 @@ impl Memory {
         }
     }
 
-    /// Set a value in memory.
+    /// Check that a value can be written at an address.
     ///
-    /// Lazily allocates pages as needed.
-    #[inline(always)]
-    pub fn set(&mut self, address: i64, value: i64) -> Result<(), VmError> {
+    /// Negative addresses and addresses past the memory limit are rejected.
+    pub fn check_address(address: i64) -> Result<(), VmError> {
         if address < 0 {
             return Err(VmError::InvalidMemoryAccess(format!(
                 "Cannot access negative address: {}",
@@ -62,21 +61,30 @@ impl Memory {
             )));
         }
 
+        // Safety Check: Prevent arbitrary OOM if a program tries to write to a massive address
+        // Limit to approx 1 billion addresses (~8GB max theoretical usage)
+        if (address as usize >> PAGE_SHIFT) > 262_144 {
+            // 262144 * 4096 = ~1 Billion indices
+            return Err(VmError::InvalidMemoryAccess(format!(
+                "Memory limit exceeded: address {} is too large",
+                address
+            )));
+        }
+        Ok(())
+    }
+
+    /// Set a value in memory.
+    ///
+    /// Lazily allocates pages as needed.
+    #[inline(always)]
+    pub fn set(&mut self, address: i64, value: i64) -> Result<(), VmError> {
         let addr_usize = address as usize;
         let page_idx = addr_usize >> PAGE_SHIFT;
         let offset = addr_usize & PAGE_MASK;
 
         // Ensure the page directory is large enough
         if page_idx >= self.pages.len() {
-            // Safety Check: Prevent arbitrary OOM if a program tries to write to a massive address
-            // Limit to approx 1 billion addresses (~8GB max theoretical usage)
-            if page_idx > 262_144 {
-                // 262144 * 4096 = ~1 Billion indices
-                return Err(VmError::InvalidMemoryAccess(format!(
-                    "Memory limit exceeded: address {} is too large",
-                    address
-                )));
-            }
+            Self::check_address(address)?;
 
             // Expand the directory, filling new slots with None
             self.pages.resize(page_idx + 1, None);
@@ -100,4 +108,38 @@ impl Memory {
     pub fn clear(&mut self) {
         self.pages.clear();
     }
+
+    /// Iterate over the addresses and values of the cells that are not zero
+    pub fn cells(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page_idx, page)| page.as_ref().map(|page| (page_idx, page)))
+            .flat_map(|(page_idx, page)| {
+                page.iter().enumerate().filter(|(_, value)| **value != 0).map(
+                    move |(offset, value)| (((page_idx << PAGE_SHIFT) | offset) as i64, *value),
+                )
+            })
+    }
+}
+
+/// Memory is serialized sparsely, as the `[address, value]` pairs of the cells
+/// that are not zero
+#[cfg(feature = "serde")]
+impl serde::Serialize for Memory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.cells())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Memory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cells = <Vec<(i64, i64)> as serde::Deserialize>::deserialize(deserializer)?;
+        let mut memory = Memory::new();
+        for (address, value) in cells {
+            memory.set(address, value).map_err(serde::de::Error::custom)?;
+        }
+        Ok(memory)
+    }
 }
@@ -0,0 +1,407 @@
+//! Running several machines in one process
+//!
+//! An [`Orchestrator`] owns a group of virtual machines and runs them in
+//! lockstep, one instruction of each machine per round, which is what
+//! equivalence checks, tournaments between programs and the playground need.
+//! Each machine reads its own input values or a [`Channel`] it shares with
+//! other machines, and writes to its own output, to a channel, or to both.
+//! A machine that reads an empty channel waits until another machine writes
+//! to it, so programs can talk to each other.
+//!
+//! Every machine has a [`Quota`] of instructions and output values, and what
+//! the machines do is reported as one stream of [`MachineEvent`]s, in the
+//! order it happened.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use ram_core::effect::Effect;
+use ram_core::error::VmError;
+
+use crate::db::VmDatabaseImpl;
+use crate::equivalence::Event;
+use crate::io::{Input, Output, VecInput};
+use crate::program::Program;
+use crate::vm::VirtualMachine;
+
+/// A queue of values machines write to and read from
+///
+/// Clones share the same queue, so a channel given as the output of one
+/// machine and the input of another connects them.
+#[derive(Debug, Clone, Default)]
+pub struct Channel {
+    values: Arc<Mutex<VecDeque<i64>>>,
+}
+
+impl Channel {
+    /// Create an empty channel
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a channel holding values to read
+    pub fn with_values(values: impl IntoIterator<Item = i64>) -> Self {
+        Self { values: Arc::new(Mutex::new(values.into_iter().collect())) }
+    }
+
+    /// Add a value at the end of the channel
+    pub fn push(&self, value: i64) {
+        self.values.lock().unwrap().push_back(value);
+    }
+
+    /// The number of values waiting to be read
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    /// Check if no value is waiting to be read
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Input for Channel {
+    fn read(&mut self) -> Result<i64, VmError> {
+        self.values
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| VmError::IoError("The channel is empty".to_string()))
+    }
+}
+
+impl Output for Channel {
+    fn write(&mut self, value: i64) -> Result<(), VmError> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// Where a machine reads its input from
+#[derive(Debug, Clone)]
+pub enum InputSource {
+    /// Values of its own, which no other machine reads
+    Values(Vec<i64>),
+    /// A channel, possibly shared with other machines
+    Channel(Channel),
+}
+
+impl Default for InputSource {
+    fn default() -> Self {
+        InputSource::Values(Vec::new())
+    }
+}
+
+/// The input of an orchestrated machine
+#[derive(Debug, Clone)]
+pub enum MachineInput {
+    /// Values of its own
+    Values(VecInput),
+    /// A shared channel
+    Channel(Channel),
+}
+
+impl Input for MachineInput {
+    fn read(&mut self) -> Result<i64, VmError> {
+        match self {
+            MachineInput::Values(input) => input.read(),
+            MachineInput::Channel(channel) => channel.read(),
+        }
+    }
+}
+
+/// The output of an orchestrated machine
+///
+/// Every value is kept, to report it in the event stream, and also written
+/// to the channel, if the machine has one.
+#[derive(Debug, Clone, Default)]
+pub struct MachineOutput {
+    /// The values written so far
+    pub values: Vec<i64>,
+    /// The channel the values are also written to
+    channel: Option<Channel>,
+    /// The maximum number of values the machine may write
+    max_values: Option<usize>,
+}
+
+impl Output for MachineOutput {
+    fn write(&mut self, value: i64) -> Result<(), VmError> {
+        if let Some(max_values) = self.max_values
+            && self.values.len() >= max_values
+        {
+            return Err(VmError::IoError(format!(
+                "The output quota of {} values is exhausted",
+                max_values
+            )));
+        }
+        self.values.push(value);
+        match &mut self.channel {
+            Some(channel) => channel.write(value),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The resources a machine may use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// The maximum number of instructions the machine executes
+    pub max_steps: Option<u64>,
+    /// The maximum number of values the machine writes
+    pub max_outputs: Option<usize>,
+}
+
+/// How to create an orchestrated machine
+#[derive(Debug, Clone, Default)]
+pub struct MachineOptions {
+    /// Where the machine reads its input from
+    pub input: InputSource,
+    /// The channel the machine also writes its output to, if any
+    pub output: Option<Channel>,
+    /// The resources the machine may use
+    pub quota: Quota,
+    /// The seed of the random numbers of the machine
+    pub seed: u64,
+}
+
+/// The index of a machine in its orchestrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MachineId(pub usize);
+
+impl fmt::Display for MachineId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Whether an orchestrated machine can go on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineState {
+    /// The machine executes an instruction each round
+    Running,
+    /// The machine waits for a value in the channel it reads
+    Waiting,
+    /// The machine halted, failed or used up its quota
+    Done,
+}
+
+/// Something a machine did, in the combined event stream of an orchestrator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineEvent {
+    /// The round the event happened in, counting from 0
+    pub round: u64,
+    /// The machine that did it
+    pub machine: MachineId,
+    /// What the machine did
+    pub event: Event,
+}
+
+impl fmt::Display for MachineEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] machine {} {}", self.round, self.machine, self.event)
+    }
+}
+
+/// A machine of an orchestrator
+struct Machine {
+    /// The name of the machine, for reports
+    name: String,
+    /// The machine itself
+    vm: VirtualMachine<MachineInput, MachineOutput>,
+    /// The channel the machine reads, to know when it has to wait
+    input: Option<Channel>,
+    /// The resources the machine may use
+    quota: Quota,
+    /// Whether the machine can go on
+    state: MachineState,
+    /// The number of output values already reported as events
+    reported_outputs: usize,
+}
+
+/// Runs several virtual machines in lockstep
+pub struct Orchestrator {
+    /// The database the machines share
+    db: Arc<VmDatabaseImpl>,
+    /// The machines, by ID
+    machines: Vec<Machine>,
+    /// What the machines did, in order
+    events: Vec<MachineEvent>,
+    /// The number of rounds run so far
+    round: u64,
+}
+
+impl Orchestrator {
+    /// Create an orchestrator without machines
+    pub fn new(db: Arc<VmDatabaseImpl>) -> Self {
+        Self { db, machines: Vec::new(), events: Vec::new(), round: 0 }
+    }
+
+    /// Add a machine running a program
+    pub fn spawn(
+        &mut self,
+        name: impl Into<String>,
+        program: Program,
+        options: MachineOptions,
+    ) -> MachineId {
+        let (input, channel) = match options.input {
+            InputSource::Values(values) => (MachineInput::Values(VecInput::new(values)), None),
+            InputSource::Channel(channel) => {
+                (MachineInput::Channel(channel.clone()), Some(channel))
+            }
+        };
+        let output = MachineOutput {
+            values: Vec::new(),
+            channel: options.output,
+            max_values: options.quota.max_outputs,
+        };
+        let vm = VirtualMachine::builder(program, input, output, self.db.clone())
+            .with_seed(options.seed)
+            .build();
+
+        self.machines.push(Machine {
+            name: name.into(),
+            vm,
+            input: channel,
+            quota: options.quota,
+            state: MachineState::Running,
+            reported_outputs: 0,
+        });
+        MachineId(self.machines.len() - 1)
+    }
+
+    /// Run one round: one instruction of every machine that can go on
+    ///
+    /// Returns the events of the round. If every machine that is not done
+    /// waits for input, none can ever go on, and they fail.
+    pub fn step(&mut self) -> Vec<MachineEvent> {
+        let first_event = self.events.len();
+        let round = self.round;
+        let mut progressed = false;
+
+        for (index, machine) in self.machines.iter_mut().enumerate() {
+            if machine.state == MachineState::Done {
+                continue;
+            }
+            let id = MachineId(index);
+            let mut report = |event| self.events.push(MachineEvent { round, machine: id, event });
+
+            if machine.quota.max_steps.is_some_and(|max_steps| machine.vm.steps() >= max_steps) {
+                machine.state = MachineState::Done;
+                report(Event::StepLimitExceeded);
+                continue;
+            }
+            if machine.waits_for_input() {
+                machine.state = MachineState::Waiting;
+                continue;
+            }
+
+            machine.state = MachineState::Running;
+            progressed = true;
+            let result = machine.vm.step();
+
+            // Values written by an instruction are reported before it fails
+            for &value in &machine.vm.output.values[machine.reported_outputs..] {
+                report(Event::Output(value));
+            }
+            machine.reported_outputs = machine.vm.output.values.len();
+
+            if let Err(error) = result {
+                machine.state = MachineState::Done;
+                report(Event::Failed(error.to_string()));
+            } else if machine.vm.is_finished() {
+                machine.state = MachineState::Done;
+                report(Event::Halted);
+            }
+        }
+
+        if !progressed {
+            for (index, machine) in self.machines.iter_mut().enumerate() {
+                if machine.state == MachineState::Waiting {
+                    machine.state = MachineState::Done;
+                    self.events.push(MachineEvent {
+                        round,
+                        machine: MachineId(index),
+                        event: Event::Failed("Waiting for input no machine writes".to_string()),
+                    });
+                }
+            }
+        }
+
+        self.round += 1;
+        self.events[first_event..].to_vec()
+    }
+
+    /// Run rounds until every machine is done, or for at most `max_rounds`
+    ///
+    /// Returns whether every machine is done.
+    pub fn run(&mut self, max_rounds: u64) -> bool {
+        for _ in 0..max_rounds {
+            if self.is_finished() {
+                break;
+            }
+            self.step();
+        }
+        self.is_finished()
+    }
+
+    /// Check if every machine is done
+    pub fn is_finished(&self) -> bool {
+        self.machines.iter().all(|machine| machine.state == MachineState::Done)
+    }
+
+    /// The number of rounds run so far
+    pub fn rounds(&self) -> u64 {
+        self.round
+    }
+
+    /// What the machines did so far, in order
+    pub fn events(&self) -> &[MachineEvent] {
+        &self.events
+    }
+
+    /// The events of one machine, in order
+    pub fn events_of(&self, id: MachineId) -> impl Iterator<Item = &Event> {
+        self.events.iter().filter(move |event| event.machine == id).map(|event| &event.event)
+    }
+
+    /// The IDs of the machines, in the order they were added
+    pub fn machine_ids(&self) -> impl Iterator<Item = MachineId> {
+        (0..self.machines.len()).map(MachineId)
+    }
+
+    /// The name of a machine
+    pub fn name(&self, id: MachineId) -> Option<&str> {
+        self.machines.get(id.0).map(|machine| machine.name.as_str())
+    }
+
+    /// Whether a machine can go on
+    pub fn state(&self, id: MachineId) -> Option<MachineState> {
+        self.machines.get(id.0).map(|machine| machine.state)
+    }
+
+    /// The values a machine wrote so far
+    pub fn output(&self, id: MachineId) -> Option<&[i64]> {
+        self.machines.get(id.0).map(|machine| machine.vm.output.values.as_slice())
+    }
+
+    /// A machine, to inspect its state
+    pub fn vm(&self, id: MachineId) -> Option<&VirtualMachine<MachineInput, MachineOutput>> {
+        self.machines.get(id.0).map(|machine| &machine.vm)
+    }
+}
+
+impl Machine {
+    /// Check if the next instruction reads a channel that is empty
+    fn waits_for_input(&self) -> bool {
+        let Some(channel) = &self.input else {
+            return false;
+        };
+        channel.is_empty()
+            && self
+                .vm
+                .program()
+                .get_instruction(self.vm.pc())
+                .is_some_and(|instr| instr.kind.effects().contains(Effect::ReadsInput))
+    }
+}
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use hir::body;
 use hir::ids::DefId;
 use ram_core::error::VmError;
-use ram_core::instruction::Instruction;
+use ram_core::instruction::{Instruction, OutputFormat};
 use ram_core::operand::{Operand, OperandValue};
 use tracing::debug;
 
@@ -16,12 +16,15 @@ pub struct Program {
     pub instructions: Vec<Instruction>,
     /// Map of label names to instruction indices
     pub labels: HashMap<String, usize>,
+    /// The number of values the program declares it reads and writes, which
+    /// the machine checks as it runs
+    pub contract: Option<body::IoContract>,
 }
 
 impl Program {
     /// Create a new empty program
     pub fn new() -> Self {
-        Self { instructions: Vec::new(), labels: HashMap::new() }
+        Self { instructions: Vec::new(), labels: HashMap::new(), contract: None }
     }
 }
 
@@ -35,7 +38,7 @@ impl Program {
     /// Find a label by its DefId in the HIR body
     fn find_label_by_id(body: &body::Body, label_id: DefId) -> Result<&body::Label, VmError> {
         body.labels
-            .iter()
+            .values()
             .find(|l| {
                 let def_id = DefId { file_id: body.owner.file_id, local_id: l.id };
                 def_id == label_id
@@ -46,13 +49,14 @@ impl Program {
     }
 
     /// Create a program from a HIR representation
-    pub fn from_hir(body: &body::Body, _db: &dyn crate::db::VmDatabase) -> Result<Self, VmError> {
+    pub fn from_hir(body: &body::Body, db: &dyn crate::db::VmDatabase) -> Result<Self, VmError> {
         let mut program = Program::new();
+        program.contract = body.io_contract();
 
         // First pass: collect instruction ID mapping
         let mut instruction_indices: HashMap<u32, usize> = HashMap::new();
         debug!("HIR Instructions: {:?}", body.instructions);
-        for (idx, instr) in body.instructions.iter().enumerate() {
+        for (idx, instr) in body.instructions.values().enumerate() {
             if let Some(label) = &instr.label_name {
                 debug!("HIR Instruction: {:?} (Label: {})", instr, label);
             } else {
@@ -64,7 +68,7 @@ impl Program {
 
         // Second pass: process all labels to build an accurate label map
         debug!("HIR Labels: {:?}", body.labels);
-        for label in &body.labels {
+        for label in body.labels.values() {
             if let Some(instr_id) = label.instruction_id
                 && let Some(&idx) = instruction_indices.get(&instr_id.0)
             {
@@ -74,14 +78,14 @@ impl Program {
         }
 
         // Third pass: process all instructions
-        for instr in &body.instructions {
-            // Get the instruction kind from the opcode
-            let kind = ram_core::instruction::InstructionKind::from_name(&instr.opcode);
+        for instr in body.instructions.values() {
+            // The kind was resolved from the opcode during lowering
+            let kind = instr.kind.clone();
 
             // Get the operand if any
             let operand = if let Some(expr_id) = instr.operand {
                 // Find the expression
-                let expr = body.exprs.iter().find(|e| e.id == expr_id).ok_or_else(|| {
+                let expr = body.exprs.values().find(|e| e.id == expr_id).ok_or_else(|| {
                     VmError::InvalidInstruction(format!(
                         "Could not find expression with ID: {:?}",
                         expr_id
@@ -110,10 +114,14 @@ impl Program {
                         // The VM will resolve it at runtime
                         Some(Operand::direct_str(label.name.clone()))
                     }
+                    body::ExprKind::Binary(_) => {
+                        // Constant expressions are folded during lowering
+                        return Err(non_constant_operand(&expr.span));
+                    }
                     body::ExprKind::MemoryRef(mem_ref) => {
                         // Get the address expression
                         let addr_expr =
-                            body.exprs.iter().find(|e| e.id == mem_ref.address).ok_or_else(
+                            body.exprs.values().find(|e| e.id == mem_ref.address).ok_or_else(
                                 || {
                                     VmError::InvalidInstruction(format!(
                                         "Could not find address expression with ID: {:?}",
@@ -140,24 +148,22 @@ impl Program {
                                 // Use the label name as a string
                                 OperandValue::String(label.name.clone())
                             }
+                            body::ExprKind::Binary(_) => {
+                                return Err(non_constant_operand(&addr_expr.span));
+                            }
                             body::ExprKind::ArrayAccess(array_access) => {
                                 // Handle array access expressions
                                 // Get the base expression
-                                let base_expr = body
-                                    .exprs
-                                    .get(array_access.array.0 as usize)
-                                    .ok_or_else(|| {
-                                        VmError::InvalidInstruction(format!(
-                                            "Invalid array base expression: {:?}",
-                                            array_access.array
-                                        ))
-                                    })?;
+                                let base_expr = body.expr(array_access.array).ok_or_else(|| {
+                                    VmError::InvalidInstruction(format!(
+                                        "Invalid array base expression: {:?}",
+                                        array_access.array
+                                    ))
+                                })?;
 
                                 // Get the index expression
-                                let index_expr = body
-                                    .exprs
-                                    .get(array_access.index.0 as usize)
-                                    .ok_or_else(|| {
+                                let index_expr =
+                                    body.expr(array_access.index).ok_or_else(|| {
                                         VmError::InvalidInstruction(format!(
                                             "Invalid array index expression: {:?}",
                                             array_access.index
@@ -240,10 +246,18 @@ impl Program {
             };
 
             // Create the instruction
-            let instruction = ram_core::instruction::Instruction::new(kind, operand);
+            let mut instruction = ram_core::instruction::Instruction::new(kind, operand);
+            if instr.has_annotation(body::CHAR_ANNOTATION) {
+                instruction = instruction.with_output_format(OutputFormat::Char);
+            }
 
-            // Validate the instruction
-            instruction.validate()?;
+            // Validate the instruction against its definition, which may come
+            // from a plugin, and against the rules of its kind otherwise
+            if db.get_instruction_definition(&instruction.kind).is_some() {
+                db.validate_instruction(instruction.kind.clone(), instruction.operand.as_ref())?;
+            } else {
+                instruction.validate()?;
+            }
 
             // Add the instruction to the program
             program.instructions.push(instruction);
@@ -275,3 +289,14 @@ impl Program {
             .ok_or_else(|| VmError::InvalidInstruction(format!("Unknown label: {}", label)))
     }
 }
+
+/// Fail to generate code for an arithmetic operand that was not folded
+///
+/// Validation reports these before code generation, so this only happens when
+/// a program is built from a body that was not validated.
+fn non_constant_operand(span: &std::ops::Range<usize>) -> VmError {
+    VmError::InvalidInstruction(format!(
+        "Operand expression at {}..{} is not constant",
+        span.start, span.end
+    ))
+}
@@ -0,0 +1,112 @@
+//! Reports of runtime errors
+//!
+//! A runtime error of a program compiled from source is reported with the
+//! instruction it happened at, the values that instruction worked with and
+//! the step of the run it happened in, instead of the bare error message.
+
+use hir::body::Body;
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use ram_core::error::VmError;
+use ram_core::operand::{OperandKind, OperandValue};
+use thiserror::Error;
+
+use crate::io::{Input, Output};
+use crate::trace::Location;
+use crate::vm::VirtualMachine;
+
+/// A runtime error, pointing at the instruction in the source it happened at
+#[derive(Debug, Error, Diagnostic)]
+#[error("Runtime error: {message}")]
+pub struct RuntimeError {
+    /// The message of the error
+    pub message: String,
+    /// The step the error happened in, counting from 1
+    pub step: u64,
+    /// The index of the instruction the error happened at
+    pub pc: usize,
+    /// The places the instruction worked with and their values, the accumulator first
+    pub context: Vec<(Location, i64)>,
+    #[source_code]
+    source_code: NamedSource<String>,
+    #[label("failed here, at step {step}")]
+    span: SourceSpan,
+    #[help]
+    help: String,
+}
+
+impl RuntimeError {
+    /// Build the report of an error a machine stopped with
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The error the machine stopped with.
+    /// * `vm` - The machine, as it was when it stopped.
+    /// * `body` - The HIR body the program of the machine was compiled from.
+    /// * `name` - The name of the source file, shown above the snippet.
+    /// * `source` - The text of the program.
+    ///
+    /// Returns `None` if the machine has not executed any instruction, or the
+    /// instruction it stopped at is not in `body`.
+    pub fn new<I: Input, O: Output>(
+        error: &VmError,
+        vm: &VirtualMachine<I, O>,
+        body: &Body,
+        name: impl AsRef<str>,
+        source: impl Into<String>,
+    ) -> Option<Self> {
+        let pc = vm.last_pc()?;
+        let span = body.instruction(pc)?.span.clone();
+        let context = operand_context(vm, pc);
+        let help = context
+            .iter()
+            .map(|(location, value)| format!("{} = {}", location, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Some(Self {
+            message: error.to_string(),
+            step: vm.steps(),
+            pc,
+            context,
+            source_code: NamedSource::new(name, source.into()),
+            span: span.into(),
+            help,
+        })
+    }
+}
+
+/// Get the values of the accumulator and the places the operand of an
+/// instruction refers to
+fn operand_context<I: Input, O: Output>(
+    vm: &VirtualMachine<I, O>,
+    pc: usize,
+) -> Vec<(Location, i64)> {
+    let value = |location: Location| match location {
+        Location::Accumulator => vm.accumulator(),
+        Location::Register(address) => vm.get_register_value(address),
+        Location::Memory(address) => vm.get_heap_value(address),
+    };
+
+    let mut locations = vec![Location::Accumulator];
+    let operand =
+        vm.program().get_instruction(pc).and_then(|instruction| instruction.operand.as_ref());
+    match operand.map(|operand| (&operand.kind, &operand.value)) {
+        Some((OperandKind::Direct, OperandValue::Number(address))) => {
+            locations.push(Location::register(*address));
+        }
+        Some((OperandKind::Indirect, OperandValue::Number(address))) => {
+            let pointer = Location::register(*address);
+            locations.extend([pointer, Location::Memory(value(pointer))]);
+        }
+        Some((OperandKind::Indexed, OperandValue::Indexed(base, index))) => {
+            let index = Location::register(*index);
+            locations.extend([index, Location::Memory(base.wrapping_add(value(index)))]);
+        }
+        _ => {}
+    }
+
+    // Register 0 is the accumulator, and negative addresses hold no values
+    locations.dedup();
+    locations.retain(|location| !matches!(location, Location::Memory(address) if *address < 0));
+    locations.into_iter().map(|location| (location, value(location))).collect()
+}
@@ -0,0 +1,87 @@
+//! Source maps of transformed programs
+//!
+//! A program that runs from a bytecode artifact no longer has its source at
+//! hand, so pauses and errors could only name the index of an instruction. A
+//! source map links each instruction of the program to the file and the span
+//! of the source instruction it was compiled from, so a machine given one can
+//! report original locations instead.
+
+use std::fmt;
+use std::ops::Range;
+
+use base_db::LineIndex;
+use hir::body::Body;
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+/// The version of the format of source maps
+pub const SOURCE_MAP_VERSION: u32 = 1;
+
+/// Where an instruction of a transformed program comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SourceLocation {
+    /// The source file, as it was given to the compiler
+    pub file: String,
+    /// The byte range of the instruction in the file
+    pub span: Range<usize>,
+    /// The line of the instruction, counting from 1
+    pub line: u32,
+    /// The column of the instruction, counting from 1
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Map from the instructions of a program to their source locations
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SourceMap {
+    /// The version of the format of the source map
+    pub version: u32,
+    /// The location of each instruction, by index, if it has one
+    pub instructions: Vec<Option<SourceLocation>>,
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self { version: SOURCE_MAP_VERSION, instructions: Vec::new() }
+    }
+}
+
+impl SourceMap {
+    /// Map the instructions of a program compiled from a HIR body
+    ///
+    /// # Parameters
+    ///
+    /// * `body` - The HIR body the program was compiled from.
+    /// * `file` - The name of the source file, recorded in every location.
+    /// * `source` - The text of the source file, to find lines and columns.
+    pub fn from_body(body: &Body, file: impl Into<String>, source: &str) -> Self {
+        let file = file.into();
+        let line_index = LineIndex::new(source);
+        let instructions = body
+            .instructions
+            .values()
+            .map(|instruction| {
+                let position = line_index.line_col(instruction.span.start);
+                Some(SourceLocation {
+                    file: file.clone(),
+                    span: instruction.span.clone(),
+                    line: position.line + 1,
+                    column: position.col + 1,
+                })
+            })
+            .collect();
+        Self { version: SOURCE_MAP_VERSION, instructions }
+    }
+
+    /// Get the source location of the instruction at an index
+    pub fn location(&self, pc: usize) -> Option<&SourceLocation> {
+        self.instructions.get(pc)?.as_ref()
+    }
+}
@@ -1,13 +1,43 @@
 //! Tests for the RAM virtual machine
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use base_db::{FileId, LruConfig, Query, SourceDatabase};
+use hir::db::HirDatabase;
+use hir_def::db::HirDefDatabase;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use ram_core::arithmetic::{ArithmeticMode, ArithmeticOp};
 use ram_core::db::VmState;
+use ram_core::error::VmError;
 use ram_core::instruction::{Instruction, InstructionKind};
+use ram_core::instruction_set::InstructionSet;
 use ram_core::operand::Operand;
+use ram_core::registry::InstructionRegistry;
 
+use crate::bytecode::{Artifact, ArtifactError, Incompatibility};
+use crate::cancel::{CancelFlag, Cancellation};
+use crate::decode::{DecodedProgram, Superinstruction};
+use crate::device::{DisplayBuffer, InputTape, OutputTape};
+use crate::equivalence::{
+    Divergence, EquivalenceOptions, Event, InputDomain, check_equivalence, compare_on_input,
+};
+use crate::eval::{Expr, evaluate};
+use crate::grader::{
+    CaseStatus, GradingSpec, Limits, TestCase, Violation, grade, grade_with_cancellation,
+};
+use crate::heatmap::{Heatmap, LineHeat};
 use crate::io::{VecInput, VecOutput};
+use crate::orchestration::{
+    Channel, InputSource, MachineEvent, MachineId, MachineOptions, MachineState, Orchestrator,
+    Quota,
+};
 use crate::program::Program;
-use crate::{VirtualMachine, VmDatabaseImpl};
+use crate::report::RuntimeError;
+use crate::source_map::SourceMap;
+use crate::trace::Location;
+use crate::watch::{Access, WatchAccess, WatchHit, Watchpoint, Watchpoints};
+use crate::{VirtualMachine, VirtualMachineBuilder, VmDatabase, VmDatabaseImpl};
 
 #[test]
 fn test_simple_program() {
@@ -169,3 +199,968 @@ fn test_loop_with_jumps() {
     // Check the output
     assert_eq!(result.output, vec![1, 2, 3, 4, 5], "Output should be [1, 2, 3, 4, 5]");
 }
+
+#[test]
+fn test_bytecode_artifact() {
+    let source = "loop: READ 1\n    JZERO end\n    WRITE *1\n    JUMP loop\nend: HALT\n";
+    let db = VmDatabaseImpl::new();
+    let program = db.parse_to_vm_program(source).unwrap();
+    let registry = db.instruction_registry();
+
+    let artifact = Artifact::new(program.clone(), source, "1.0.0", &registry);
+    let bytes = artifact.to_bytes();
+
+    // The program and its signature survive a round trip
+    let decoded = Artifact::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.signature, artifact.signature);
+    assert_eq!(decoded.program.instructions, program.instructions);
+    assert_eq!(decoded.program.labels, program.labels);
+    assert!(decoded.verify("1.0.0", &registry, Some(source)).is_empty());
+
+    // Differences with the current environment are reported
+    assert_eq!(
+        decoded.verify("1.1.0", &registry, Some("HALT\n")),
+        vec![
+            Incompatibility::CompilerVersion {
+                artifact: "1.0.0".to_string(),
+                current: "1.1.0".to_string()
+            },
+            Incompatibility::SourceChanged,
+        ]
+    );
+    let incompatibilities = decoded.verify("1.0.0", &InstructionRegistry::new(), None);
+    assert!(matches!(incompatibilities[0], Incompatibility::InstructionSet { .. }));
+    assert!(incompatibilities[1..].iter().all(Incompatibility::is_fatal));
+
+    // Corrupted and foreign files are rejected
+    let mut corrupted = bytes.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        Artifact::from_bytes(&corrupted),
+        Err(ArtifactError::ChecksumMismatch { .. })
+    ));
+    assert_eq!(Artifact::from_bytes(&bytes[..8]).unwrap_err(), ArtifactError::Truncated);
+    assert_eq!(Artifact::from_bytes(source.as_bytes()).unwrap_err(), ArtifactError::NotAnArtifact);
+}
+
+#[test]
+fn test_character_output() {
+    let source = "LOAD =-5\nWRITE 0\n#* #[char]\nWRITE ='H'\n#* #[char]\nWRITE =105\nHALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program(source).unwrap();
+
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    vm.run().unwrap();
+    assert_eq!(vm.output.values, vec![-5, 72, 105]);
+    assert_eq!(vm.output.text, "Hi");
+
+    // Values that are not character codes cannot be written as characters
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program("LOAD =-1\n#* #[char]\nWRITE 0\n").unwrap();
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    assert!(matches!(vm.run(), Err(VmError::IoError(_))));
+}
+
+#[test]
+fn test_rejects_invalid_operands_when_loading() {
+    // The same operand rules as the analysis of the program
+    let db = Arc::new(VmDatabaseImpl::new());
+    for (source, message) in [
+        ("JUMP =1\nHALT\n", "JUMP can not jump to an immediate value"),
+        ("LOAD -1\nHALT\n", "Negative address: -1"),
+        ("STORE =1\nHALT\n", "STORE does not accept immediate operands"),
+    ] {
+        let Err(VmError::InvalidOperand(error)) = db.parse_to_vm_program(source) else {
+            panic!("Expected an invalid operand error for {source:?}");
+        };
+        assert_eq!(error, message);
+    }
+}
+
+#[test]
+fn test_seeded_random_numbers() {
+    // RAND =100, WRITE 0 three times, then HALT
+    let rand = InstructionKind::from_name("RAND");
+    let mut program = Program::new();
+    for _ in 0..3 {
+        program.instructions.push(Instruction::with_operand(rand.clone(), Operand::immediate(100)));
+        program
+            .instructions
+            .push(Instruction::with_operand(InstructionKind::Write, Operand::direct(0)));
+    }
+    program.instructions.push(Instruction::without_operand(InstructionKind::Halt));
+
+    let run = |seed: u64| {
+        let db = Arc::new(VmDatabaseImpl::with_instruction_set(&InstructionSet::extended()));
+        let mut vm =
+            VirtualMachine::builder(program.clone(), VecInput::new(vec![]), VecOutput::new(), db)
+                .with_seed(seed)
+                .build();
+        vm.run().unwrap();
+        vm.output.values
+    };
+
+    // The same seed gives the same numbers, within the bound
+    let output = run(42);
+    assert_eq!(output, run(42));
+    assert!(output.iter().all(|value| (0..100).contains(value)));
+    assert_ne!(output, run(7));
+
+    // The standard instruction set does not include RAND
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    assert!(vm.run().is_err());
+}
+
+#[test]
+fn test_subroutine_calls() {
+    // LOAD =3, CALL double, CALL double, WRITE 0, HALT, double: MUL =2, RET
+    let call = InstructionKind::from_name("CALL");
+    let mut program = Program::new();
+    program
+        .instructions
+        .push(Instruction::with_operand(InstructionKind::Load, Operand::immediate(3)));
+    program
+        .instructions
+        .push(Instruction::with_operand(call.clone(), Operand::direct_str("double")));
+    program.instructions.push(Instruction::with_operand(call, Operand::direct_str("double")));
+    program
+        .instructions
+        .push(Instruction::with_operand(InstructionKind::Write, Operand::direct(0)));
+    program.instructions.push(Instruction::without_operand(InstructionKind::Halt));
+    program.labels.insert("double".to_string(), program.instructions.len());
+    program
+        .instructions
+        .push(Instruction::with_operand(InstructionKind::Mul, Operand::immediate(2)));
+    program.instructions.push(Instruction::without_operand(InstructionKind::from_name("RET")));
+
+    let db = Arc::new(VmDatabaseImpl::with_instruction_set(&InstructionSet::subroutines()));
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    vm.run().unwrap();
+    assert_eq!(vm.output.values, vec![12]);
+
+    // Returning without a call is an error
+    let mut program = Program::new();
+    program.instructions.push(Instruction::without_operand(InstructionKind::from_name("RET")));
+    let db = Arc::new(VmDatabaseImpl::with_instruction_set(&InstructionSet::subroutines()));
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    assert!(matches!(vm.run(), Err(VmError::EmptyReturnStack)));
+}
+
+#[test]
+fn test_falling_off_the_end() {
+    // The loop ends when it reads a value that is not positive, with no HALT after it
+    let source = "loop: READ 1\n      WRITE 1\n      LOAD 1\n      JGTZ loop\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program(source).unwrap();
+
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![2, 0]), VecOutput::new(), db);
+    assert!(matches!(vm.run(), Err(VmError::FellOffEnd)));
+    assert_eq!(vm.output.values, vec![2, 0]);
+    assert!(vm.is_finished());
+
+    // Halting at the last instruction is not falling off
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program(&format!("{source}HALT\n")).unwrap();
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![0]), VecOutput::new(), db);
+    vm.run().unwrap();
+}
+
+#[test]
+fn test_io_contract() {
+    let source = "\
+#* @reads 2 @writes 1
+loop: READ 1
+      LOAD 1
+      JGTZ loop
+      WRITE 1
+      HALT
+";
+    let run = |input: Vec<i64>| {
+        let db = Arc::new(VmDatabaseImpl::new());
+        let program = db.parse_to_vm_program(source).unwrap();
+        let mut vm = VirtualMachine::new(program, VecInput::new(input), VecOutput::new(), db);
+        vm.run().map(|()| vm.output.values)
+    };
+
+    assert_eq!(run(vec![1, 0]).unwrap(), vec![0]);
+
+    // Reading one value too many fails right away, before the input runs out
+    assert!(matches!(
+        run(vec![1, 1]),
+        Err(VmError::ContractViolation { verb: "read", actual: 3, declared: 2 })
+    ));
+
+    // Halting with fewer values fails when the program halts
+    assert!(matches!(
+        run(vec![0]),
+        Err(VmError::ContractViolation { verb: "read", actual: 1, declared: 2 })
+    ));
+
+    // Artifacts keep the contract of their program
+    let db = VmDatabaseImpl::new();
+    let program = db.parse_to_vm_program(source).unwrap();
+    let artifact = Artifact::new(program.clone(), source, "1.0.0", &db.instruction_registry());
+    let decoded = Artifact::from_bytes(&artifact.to_bytes()).unwrap();
+    assert_eq!(decoded.program.contract, program.contract);
+    assert_eq!(program.contract.unwrap().writes, Some(1));
+}
+
+#[test]
+fn test_traced_steps() {
+    let source = "READ 1\nLOAD =4\nADD 1\nSTORE 2\nJGTZ end\nend: HALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program(source).unwrap();
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![3]), VecOutput::new(), db);
+
+    let read = vm.step_traced().unwrap();
+    assert_eq!(read.step, 1);
+    assert_eq!(read.written, Some((Location::Register(1), 3)));
+    assert_eq!(read.operand_value, None);
+
+    vm.step_traced().unwrap();
+    let add = vm.step_traced().unwrap();
+    assert_eq!(add.pc, 2);
+    assert_eq!((add.accumulator_before, add.accumulator_after), (4, 7));
+    assert_eq!(add.operand_value, Some(3));
+    assert!(!add.jumped());
+
+    let store = vm.step_traced().unwrap();
+    assert_eq!(store.written, Some((Location::Register(2), 7)));
+
+    let jump = vm.step_traced().unwrap();
+    assert_eq!(jump.next_pc, 5);
+    assert!(vm.step_traced().unwrap().effects.halts());
+    assert!(vm.is_finished());
+}
+
+#[test]
+fn test_grading() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program("READ 1\nREAD 2\nLOAD 1\nADD 2\nWRITE 0\nHALT\n").unwrap();
+    let case = |name: &str, input: Vec<i64>, output: Vec<i64>| TestCase {
+        name: name.to_string(),
+        input,
+        output,
+    };
+    let mut spec = GradingSpec {
+        cases: vec![case("adds", vec![5, 7], vec![12]), case("negative", vec![-1, 1], vec![1])],
+        ..Default::default()
+    };
+
+    let report = grade(&program, &db, &spec);
+    assert!(report.violations.is_empty());
+    assert_eq!(report.passed_cases(), 1);
+    assert_eq!(report.cases[0].steps, 6);
+    assert_eq!(report.cases[1].status, CaseStatus::WrongOutput);
+    assert_eq!(report.cases[1].output, vec![0]);
+
+    // Violations are reported, but the cases still run
+    spec.cases.truncate(1);
+    spec.banned_instructions = vec!["add".to_string()];
+    spec.limits = Limits { max_steps: 3, max_instructions: Some(4) };
+    let report = grade(&program, &db, &spec);
+    assert_eq!(
+        report.violations,
+        vec![
+            Violation::BannedInstruction { name: "ADD".to_string(), index: 3 },
+            Violation::TooManyInstructions { count: 6, max: 4 },
+        ]
+    );
+    assert_eq!(report.cases[0].status, CaseStatus::StepLimitExceeded);
+    assert!(!report.passed());
+
+    // Runtime errors fail the case
+    let program = db.parse_to_vm_program("READ 1\nHALT\n").unwrap();
+    let report = grade(
+        &program,
+        &db,
+        &GradingSpec { cases: vec![case("empty", vec![], vec![])], ..Default::default() },
+    );
+    assert!(matches!(report.cases[0].status, CaseStatus::RuntimeError(_)));
+}
+
+#[test]
+fn test_memory_mapped_devices() {
+    // Copy the input tape at 100 to the output tape at 110, doubling each value,
+    // then draw an "A" on the display at 200 through an indirect store
+    let mut program = Program::new();
+    let instructions = [
+        Instruction::with_operand(InstructionKind::Load, Operand::direct(102)),
+        Instruction::with_operand(InstructionKind::JumpGtz, Operand::direct_str("end")),
+        Instruction::with_operand(InstructionKind::Load, Operand::direct(100)),
+        Instruction::with_operand(InstructionKind::Mul, Operand::immediate(2)),
+        Instruction::with_operand(InstructionKind::Store, Operand::direct(110)),
+        Instruction::with_operand(InstructionKind::Store, Operand::direct(103)),
+        Instruction::with_operand(InstructionKind::Jump, Operand::direct_str("loop")),
+        Instruction::with_operand(InstructionKind::Load, Operand::immediate(203)),
+        Instruction::with_operand(InstructionKind::Store, Operand::direct(1)),
+        Instruction::with_operand(InstructionKind::Load, Operand::immediate(65)),
+        Instruction::with_operand(InstructionKind::Store, Operand::indirect(1)),
+        Instruction::without_operand(InstructionKind::Halt),
+    ];
+    program.labels.insert("loop".to_string(), 0);
+    program.labels.insert("end".to_string(), 7);
+    program.instructions.extend(instructions);
+
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut vm = VirtualMachine::builder(program, VecInput::new(vec![]), VecOutput::new(), db)
+        .map_device(100..100 + InputTape::SIZE, InputTape::new(vec![1, 2, 3]))
+        .map_device(110..110 + OutputTape::SIZE, OutputTape::new())
+        .map_device(200..206, DisplayBuffer::new(3, 2))
+        .build();
+    vm.run().unwrap();
+
+    assert_eq!(vm.device::<InputTape>(100).unwrap().head(), 3);
+    assert_eq!(vm.device::<OutputTape>(111).unwrap().values, vec![2, 4, 6]);
+    assert_eq!(vm.get_register(111).unwrap(), 3);
+    assert_eq!(vm.device::<DisplayBuffer>(200).unwrap().render(), "   \nA  \n");
+
+    // Addresses outside the devices still reach memory
+    assert_eq!(vm.get_register(1).unwrap(), 203);
+    assert!(vm.device::<OutputTape>(1).is_none());
+
+    // Writing to a read-only cell of a device is an error
+    assert!(matches!(vm.set_register(100, 5), Err(VmError::InvalidMemoryAccess(_))));
+}
+
+#[test]
+fn test_watchpoints() {
+    let source = "READ 1\nLOAD 1\nSTORE 5\nLOAD =0\nWRITE 5\nHALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program(source).unwrap();
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![3]), VecOutput::new(), db);
+    vm.watch("5".parse::<Watchpoint>().unwrap());
+
+    // The run pauses after the instruction writing the register
+    let hits = vm.run_until_watch(100).unwrap();
+    assert_eq!(
+        hits,
+        vec![WatchHit {
+            step: 3,
+            pc: 2,
+            location: Location::Register(5),
+            access: Access::Write,
+            value: 3
+        }]
+    );
+    assert_eq!(hits[0].to_string(), "R5 written with value 3");
+
+    // And again after the one reading it
+    let hits = vm.run_until_watch(100).unwrap();
+    assert_eq!((hits[0].pc, hits[0].access), (4, Access::Read));
+    assert!(vm.run_until_watch(100).unwrap().is_empty());
+    assert!(vm.is_finished());
+    assert_eq!(vm.output.values, vec![3]);
+
+    // Writes to the accumulator do not pause a watchpoint on reads
+    let watchpoint = "ACC:r".parse::<Watchpoint>().unwrap();
+    assert_eq!(watchpoint.access, WatchAccess::Read);
+    assert_eq!(watchpoint.to_string(), "ACC:r");
+    let mut watchpoints = Watchpoints::new();
+    watchpoints.insert(watchpoint);
+    assert!(watchpoints.matches(Location::Accumulator, Access::Read));
+    assert!(!watchpoints.matches(Location::Accumulator, Access::Write));
+    assert!("M[2]:x".parse::<Watchpoint>().is_err());
+}
+
+#[test]
+fn test_execution_heatmap() {
+    // The loop runs three times, with its test a fourth time to leave it
+    let source = "LOAD =3\nloop: JZERO end\n  SUB =1\n  JUMP loop\nend: HALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let (ast, errors) = db.parse_program(source);
+    assert!(errors.is_empty());
+    let file_id = base_db::input::FileId(0);
+    let item_tree = hir_def::item_tree::ItemTree::lower(&ast, file_id);
+    let def_id = hir::ids::DefId { file_id, local_id: hir::ids::LocalDefId(0) };
+    let body = hir::lower::lower_program(&ast, def_id, file_id, &item_tree).unwrap();
+    let program = db.hir_to_vm_program(&body).unwrap();
+
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    vm.run().unwrap();
+    assert_eq!(vm.execution_counts(), [1, 4, 3, 3, 1]);
+
+    let heatmap = Heatmap::new(source, &body, vm.execution_counts());
+    let counts = heatmap.lines.iter().map(|heat| (heat.line, heat.count)).collect::<Vec<_>>();
+    assert_eq!(counts, vec![(0, 1), (1, 4), (2, 3), (3, 3), (4, 1)]);
+    assert_eq!(heatmap.max_count, 4);
+    assert_eq!(heatmap.hottest(), Some(LineHeat { line: 1, count: 4 }));
+    assert_eq!(heatmap.heat(2), 0.75);
+    assert_eq!(heatmap.count(5), None);
+
+    vm.reset();
+    assert_eq!(vm.execution_counts(), [0; 5]);
+}
+
+#[test]
+fn test_runtime_error_report() {
+    let source = "LOAD =7\nSTORE 2\nLOAD =0\nSTORE 1\nLOAD 2\nDIV 1\nHALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let (ast, errors) = db.parse_program(source);
+    assert!(errors.is_empty());
+    let file_id = base_db::input::FileId(0);
+    let item_tree = hir_def::item_tree::ItemTree::lower(&ast, file_id);
+    let def_id = hir::ids::DefId { file_id, local_id: hir::ids::LocalDefId(0) };
+    let body = hir::lower::lower_program(&ast, def_id, file_id, &item_tree).unwrap();
+    let program = db.hir_to_vm_program(&body).unwrap();
+
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    let error = vm.run().unwrap_err();
+    assert!(matches!(error, VmError::DivisionByZero));
+    assert_eq!(vm.last_pc(), Some(5));
+
+    let report = RuntimeError::new(&error, &vm, &body, "divide.ram", source).unwrap();
+    assert_eq!(report.to_string(), "Runtime error: Division by zero");
+    assert_eq!((report.step, report.pc), (6, 5));
+    assert_eq!(report.context, vec![(Location::Accumulator, 7), (Location::Register(1), 0)]);
+    let span = miette::Diagnostic::labels(&report).unwrap().next().unwrap();
+    assert!(source[span.offset()..span.offset() + span.len()].contains("DIV 1"));
+    assert_eq!(miette::Diagnostic::help(&report).unwrap().to_string(), "ACC = 7, R1 = 0");
+
+    // Nothing points at an instruction before the machine ran one
+    vm.reset();
+    assert!(RuntimeError::new(&error, &vm, &body, "divide.ram", source).is_none());
+}
+
+#[test]
+fn test_arithmetic_modes() {
+    let source = "LOAD =4611686018427387904\nMUL =2\nHALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let (ast, errors) = db.parse_program(source);
+    assert!(errors.is_empty());
+    let file_id = base_db::input::FileId(0);
+    let item_tree = hir_def::item_tree::ItemTree::lower(&ast, file_id);
+    let def_id = hir::ids::DefId { file_id, local_id: hir::ids::LocalDefId(0) };
+    let body = hir::lower::lower_program(&ast, def_id, file_id, &item_tree).unwrap();
+    let program = db.hir_to_vm_program(&body).unwrap();
+
+    // Checked arithmetic stops at the instruction that overflows
+    let mut vm =
+        VirtualMachine::new(program.clone(), VecInput::new(vec![]), VecOutput::new(), db.clone());
+    let error = vm.run().unwrap_err();
+    assert!(matches!(error, VmError::Overflow { operation: ArithmeticOp::Mul, rhs: 2, .. }));
+    let report = RuntimeError::new(&error, &vm, &body, "overflow.ram", source).unwrap();
+    let span = miette::Diagnostic::labels(&report).unwrap().next().unwrap();
+    assert!(source[span.offset()..span.offset() + span.len()].contains("MUL =2"));
+
+    for (mode, expected) in
+        [(ArithmeticMode::Wrapping, i64::MIN), (ArithmeticMode::Saturating, i64::MAX)]
+    {
+        let mut vm = VirtualMachineBuilder::new(
+            program.clone(),
+            VecInput::new(vec![]),
+            VecOutput::new(),
+            db.clone(),
+        )
+        .with_arithmetic(mode)
+        .build();
+        vm.run().unwrap();
+        assert_eq!(vm.accumulator(), expected);
+    }
+}
+
+#[test]
+fn test_missing_files() {
+    let mut db = VmDatabaseImpl::new();
+    let file_id = base_db::input::FileId(7);
+    assert!(!db.contains_file(file_id));
+    assert!(db.file_text(file_id).is_none());
+    assert!(db.file_source_root(file_id).is_none());
+    assert!(db.source_root(base_db::SourceRootId(3)).is_none());
+
+    // Queries on a missing file find nothing in it instead of panicking
+    assert!(db.item_tree(file_id).labels.is_empty());
+    assert!(db.bodies_in_file(file_id).is_empty());
+
+    db.set_file_text(file_id, "HALT\n");
+    assert!(db.contains_file(file_id));
+    assert_eq!(db.file_text(file_id).unwrap().text(&db).as_ref(), "HALT\n");
+    assert!(db.file_source_root(file_id).is_some());
+}
+
+#[test]
+fn test_remove_and_rename_files() {
+    use std::path::{Path, PathBuf};
+
+    use base_db::{SourceRoot, SourceRootId};
+    use salsa::Durability;
+
+    let mut db = VmDatabaseImpl::new();
+    let (src, tests) = (SourceRootId(0), SourceRootId(1));
+    let file_id = base_db::input::FileId(0);
+    db.set_file_text(file_id, "HALT\n");
+    let mut root = SourceRoot::new(PathBuf::from("/project/src"));
+    root.add_file_with_path(file_id, PathBuf::from("/project/src/main.ram"));
+    db.set_source_root_with_durability(src, Arc::new(root), Durability::LOW);
+    let root = SourceRoot::new(PathBuf::from("/project/src/tests"));
+    db.set_source_root_with_durability(tests, Arc::new(root), Durability::LOW);
+    db.set_file_source_root_with_durability(file_id, src, Durability::LOW);
+
+    // A file moves to the source root most closely containing its new path
+    assert!(db.rename_file(file_id, PathBuf::from("/project/src/tests/main.ram")));
+    let source_root_id = db.file_source_root(file_id).unwrap().source_root_id(&db);
+    assert_eq!(source_root_id, tests);
+    assert!(!db.source_root(src).unwrap().source_root(&db).contains_file(file_id));
+    let root = db.source_root(tests).unwrap().source_root(&db);
+    assert_eq!(root.resolve_path(Path::new("main.ram")), Some(file_id));
+
+    // A removed file leaves its source root and is empty to queries
+    let text = db.file_text(file_id).unwrap();
+    assert!(db.remove_file(file_id));
+    assert_eq!(text.text(&db).as_ref(), "");
+    assert!(!db.contains_file(file_id));
+    assert!(!db.source_root(tests).unwrap().source_root(&db).contains_file(file_id));
+    assert!(!db.remove_file(file_id));
+    assert!(!db.rename_file(file_id, PathBuf::from("/project/src/main.ram")));
+}
+
+#[test]
+fn test_resume_from_snapshot() {
+    // READ 1, LOAD 1, MUL =2, STORE 2, WRITE 2, READ 1, WRITE 1, HALT
+    let mut program = Program::new();
+    program.instructions.extend([
+        Instruction::with_operand(InstructionKind::Read, Operand::direct(1)),
+        Instruction::with_operand(InstructionKind::Load, Operand::direct(1)),
+        Instruction::with_operand(InstructionKind::Mul, Operand::immediate(2)),
+        Instruction::with_operand(InstructionKind::Store, Operand::direct(2)),
+        Instruction::with_operand(InstructionKind::Write, Operand::direct(2)),
+        Instruction::with_operand(InstructionKind::Read, Operand::direct(1)),
+        Instruction::with_operand(InstructionKind::Write, Operand::direct(1)),
+        Instruction::without_operand(InstructionKind::Halt),
+    ]);
+
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut vm =
+        VirtualMachine::new(program.clone(), VecInput::new(vec![21, 5]), VecOutput::new(), db);
+    vm.set_arithmetic(ArithmeticMode::Wrapping);
+
+    // Running out of steps pauses the machine without an error
+    vm.run_steps(5).unwrap();
+    assert!(!vm.is_finished());
+    assert_eq!(vm.steps(), 5);
+    assert_eq!(vm.output.values, vec![42]);
+
+    let snapshot = vm.snapshot();
+    assert_eq!(snapshot.pc, 5);
+    assert_eq!(snapshot.input.position(), 1);
+    assert_eq!(snapshot.registers.cells().collect::<Vec<_>>(), vec![(1, 21), (2, 42)]);
+    assert_eq!(snapshot.arithmetic, ArithmeticMode::Wrapping);
+
+    // The restored machine continues where the original one stopped
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut resumed = VirtualMachine::from_snapshot(program, snapshot, db);
+    assert_eq!(resumed.arithmetic_mode(), ArithmeticMode::Wrapping);
+    resumed.run_steps(100).unwrap();
+    assert!(resumed.is_finished());
+    assert_eq!(resumed.steps(), 8);
+    assert_eq!(resumed.output.values, vec![42, 5]);
+}
+
+#[test]
+fn test_input_domain() {
+    let domain = InputDomain::new(vec![0..=1, 5..=7]);
+    assert_eq!(domain.size(), Some(6));
+    assert_eq!(
+        domain.inputs().collect::<Vec<_>>(),
+        vec![vec![0, 5], vec![0, 6], vec![0, 7], vec![1, 5], vec![1, 6], vec![1, 7]]
+    );
+    assert_eq!(InputDomain::new(vec![]).inputs().collect::<Vec<_>>(), vec![Vec::<i64>::new()]);
+    assert_eq!(InputDomain::new(vec![i64::MIN..=i64::MAX]).size(), None);
+
+    let mut rng = crate::Rng::new(7);
+    for _ in 0..100 {
+        let input = domain.sample(&mut rng);
+        assert!((0..=1).contains(&input[0]) && (5..=7).contains(&input[1]));
+    }
+}
+
+#[test]
+fn test_equivalence() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let double = db.parse_to_vm_program("READ 1\nLOAD 1\nMUL =2\nWRITE 0\nHALT\n").unwrap();
+    let add = db.parse_to_vm_program("READ 1\nLOAD 1\nADD 1\nWRITE 0\nHALT\n").unwrap();
+    let square = db.parse_to_vm_program("READ 1\nLOAD 1\nMUL 1\nWRITE 0\nHALT\n").unwrap();
+    let domain = InputDomain::new(vec![-3..=3]);
+    let options = EquivalenceOptions::default();
+
+    let report = check_equivalence(&double, &add, &db, domain.inputs(), &options);
+    assert!(report.equivalent());
+    assert_eq!(report.inputs_checked, 7);
+
+    // 2x and x² first differ on -3, after four instructions each
+    let report = check_equivalence(&double, &square, &db, domain.inputs(), &options);
+    assert_eq!(report.inputs_checked, 1);
+    assert_eq!(
+        report.divergence,
+        Some(Divergence {
+            input: vec![-3],
+            position: 0,
+            left: Event::Output(-6),
+            left_steps: 4,
+            right: Event::Output(9),
+            right_steps: 4,
+        })
+    );
+
+    // A program that never halts diverges once the other one halts
+    let forever =
+        db.parse_to_vm_program("READ 1\nLOAD 1\nMUL =2\nWRITE 0\nloop: JUMP loop\n").unwrap();
+    let options = EquivalenceOptions { max_steps: 50, ..Default::default() };
+    let divergence = compare_on_input(&double, &forever, &db, &[1], &options).unwrap();
+    assert_eq!(divergence.position, 1);
+    assert_eq!(divergence.left, Event::Halted);
+    assert_eq!(divergence.right, Event::StepLimitExceeded);
+}
+
+#[test]
+fn test_query_lru_capacities() {
+    let mut db = VmDatabaseImpl::builder().lru_capacity(Query::Parse, 1).build();
+    db.set_file_text(FileId(0), "start: LOAD 1\nJUMP start\n");
+    db.set_file_text(FileId(1), "HALT\n");
+
+    // The syntax tree of a file is reused until another file takes its place
+    db.item_tree(FileId(0));
+    db.item_tree(FileId(0));
+    db.item_tree(FileId(1));
+    let parse = db.query_stats()[1];
+    assert_eq!((parse.memos, parse.hits, parse.misses, parse.evictions), (1, 1, 2, 1));
+    assert_eq!(parse.hit_rate(), Some(1.0 / 3.0));
+
+    // A file is parsed again once its text changes
+    db.set_file_text(FileId(1), "end: HALT\n");
+    assert_eq!(db.item_tree(FileId(1)).labels.len(), 1);
+    assert_eq!(db.query_stats()[1].misses, 3);
+
+    let line_index = db.line_index(FileId(1)).unwrap();
+    assert_eq!(line_index.line_col(5.into()).line, 1);
+    assert!(Arc::ptr_eq(&line_index, &db.line_index(FileId(1)).unwrap()));
+    assert!(db.line_index(FileId(2)).is_none());
+
+    // Environment variables override the configured capacities
+    let lru = LruConfig::new()
+        .with_capacity(Query::Parse, 8)
+        .with_env_vars(|name| (name == "RAM_LRU_FILE_TEXT").then(|| "4".to_string()));
+    let lru = lru.unwrap();
+    assert_eq!((lru.capacity(Query::FileText), lru.capacity(Query::Parse)), (4, 8));
+    assert!(LruConfig::new().with_env_vars(|_| Some("many".to_string())).is_err());
+    assert!(LruConfig::new().with_overrides([("typecheck", 1)]).is_err());
+}
+
+/// A cancellation that cancels the run at its `checks`-th check
+fn cancel_after(checks: u64) -> Arc<dyn Cancellation> {
+    let count = AtomicU64::new(0);
+    Arc::new(move || count.fetch_add(1, Ordering::Relaxed) + 1 >= checks)
+}
+
+#[test]
+fn test_cancellation() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program("LOAD =1\nloop: WRITE 0\nJUMP loop\n").unwrap();
+
+    // The run stops at a check, keeping what it did so far
+    let mut vm =
+        VirtualMachine::new(program.clone(), VecInput::new(vec![]), VecOutput::new(), db.clone());
+    vm.set_cancellation(cancel_after(3));
+    assert!(matches!(vm.run(), Err(VmError::Cancelled(2000))));
+    assert_eq!(vm.steps(), 2000);
+    assert_eq!(vm.output.values.len(), 1000);
+
+    // A cancelled flag stops the run before its first step
+    let flag = CancelFlag::new();
+    let mut vm =
+        VirtualMachine::new(program.clone(), VecInput::new(vec![]), VecOutput::new(), db.clone());
+    vm.set_cancellation(Arc::new(flag.clone()));
+    flag.cancel();
+    assert!(matches!(vm.run(), Err(VmError::Cancelled(0))));
+
+    // The cases after the cancelled one are not run
+    let case = |name: &str| TestCase { name: name.to_string(), input: vec![], output: vec![1] };
+    let spec = GradingSpec {
+        cases: vec![case("first"), case("second")],
+        limits: Limits { max_steps: 10_000, max_instructions: None },
+        ..Default::default()
+    };
+    let report = grade_with_cancellation(&program, &db, &spec, cancel_after(3));
+    assert_eq!(report.cases[0].status, CaseStatus::Cancelled);
+    assert_eq!((report.cases[0].steps, report.cases[0].output.len()), (1000, 500));
+    assert_eq!(report.cases[1].status, CaseStatus::Cancelled);
+    assert_eq!(report.cases[1].steps, 0);
+}
+
+#[test]
+fn test_decoded_program() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let mut program =
+        db.parse_to_vm_program("READ 1\nLOAD 1\nloop: SUB =1\nJGTZ loop\nLOAD 2\nHALT\n").unwrap();
+
+    // Instructions of the same kind share their entry in the jump table
+    let decoded = DecodedProgram::decode(&program, &db);
+    assert_eq!(decoded.len(), 6);
+    assert_eq!(decoded.op(1).unwrap().handler, decoded.op(4).unwrap().handler);
+    assert_ne!(decoded.op(0).unwrap().handler, decoded.op(1).unwrap().handler);
+    assert_eq!(decoded.handler(decoded.op(3).unwrap()).unwrap().name(), "JGTZ");
+
+    // Label operands point to their instruction ahead of the run
+    assert_eq!(decoded.op(3).unwrap().target, Some(2));
+    assert_eq!(decoded.target(3, "loop"), Some(2));
+    assert_eq!(decoded.target(3, "end"), None);
+    assert_eq!(decoded.target(1, "loop"), None);
+
+    let mut vm =
+        VirtualMachine::new(program.clone(), VecInput::new(vec![3]), VecOutput::new(), db.clone());
+    vm.run().unwrap();
+    assert_eq!((vm.accumulator(), vm.steps()), (0, 10));
+
+    // Instructions the instruction set does not define only fail when reached
+    program.instructions[4].kind = InstructionKind::Custom("PUSH".into());
+    let decoded = DecodedProgram::decode(&program, &db);
+    assert_eq!(decoded.op(4).unwrap().handler, None);
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![3]), VecOutput::new(), db);
+    assert!(
+        matches!(vm.run(), Err(VmError::InvalidInstruction(message)) if message == "Unknown instruction: PUSH")
+    );
+}
+
+/// Everything a run shows: its result, output, accumulator, steps and the
+/// executions of each instruction
+type RunSummary = (Result<(), String>, Vec<i64>, i64, u64, Vec<u64>);
+
+/// Run a program for at most `max_steps` steps, with or without fusion
+fn run_summary(source: &str, input: &[i64], max_steps: usize, fusion: bool) -> RunSummary {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program(source).unwrap();
+    let mut vm =
+        VirtualMachine::builder(program, VecInput::new(input.to_vec()), VecOutput::new(), db)
+            .with_fusion(fusion)
+            .build();
+    let result = vm.run_steps(max_steps).map_err(|e| e.to_string());
+    (result, vm.output.values.clone(), vm.accumulator(), vm.steps(), vm.execution_counts().to_vec())
+}
+
+#[test]
+fn test_superinstructions() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let source =
+        "READ 1\nLOAD 1\nloop: SUB =1\nJGTZ loop\nLOAD 1\nADD =2\nSTORE 2\nWRITE 2\nHALT\n";
+    let program = db.parse_to_vm_program(source).unwrap();
+    let decoded = DecodedProgram::decode(&program, &db);
+    let fusions =
+        (0..decoded.len()).map(|index| decoded.op(index).unwrap().fusion).collect::<Vec<_>>();
+    assert_eq!(
+        fusions,
+        [
+            None,
+            Some(Superinstruction::LoadArithmetic),
+            Some(Superinstruction::CompareJump),
+            None,
+            Some(Superinstruction::LoadArithmetic),
+            Some(Superinstruction::ArithmeticStore),
+            None,
+            None,
+            None,
+        ]
+    );
+    assert_eq!(decoded.fused_count(), 4);
+
+    // Fused runs match unfused ones, even when the steps run out mid-pair
+    for max_steps in [3, 4, 5, 100] {
+        assert_eq!(
+            run_summary(source, &[5], max_steps, true),
+            run_summary(source, &[5], max_steps, false)
+        );
+    }
+    assert_eq!(run_summary(source, &[5], 100, true).1, vec![2]);
+
+    // The checks for cancellation fall on the same steps
+    let source = "READ 1\nLOAD 1\nloop: SUB =1\nJGTZ loop\nHALT\n";
+    for fusion in [true, false] {
+        let program = db.parse_to_vm_program(source).unwrap();
+        let mut vm = VirtualMachine::builder(
+            program,
+            VecInput::new(vec![5000]),
+            VecOutput::new(),
+            db.clone(),
+        )
+        .with_fusion(fusion)
+        .build();
+        vm.set_cancellation(cancel_after(3));
+        assert!(matches!(vm.run(), Err(VmError::Cancelled(2000))));
+    }
+}
+
+#[test]
+fn test_evaluate_expressions() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let program = db.parse_to_vm_program("LOAD =6\nSTORE 2\nend: HALT\n").unwrap();
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    vm.run().unwrap();
+
+    assert_eq!(evaluate("R2 * (ACC - 1) % 4", &vm), Ok(2));
+    assert_eq!(evaluate("R[1 + 1] == 6", &vm), Ok(1));
+    assert_eq!(evaluate("r0 < -9223372036854775808", &vm), Ok(0));
+    assert_eq!(evaluate("M[R2] + end", &vm), Ok(2));
+
+    let error = evaluate("R2 + nowhere", &vm).unwrap_err();
+    assert_eq!(error.message, "Unknown label 'nowhere'");
+    assert_eq!(error.range, 5..12);
+
+    let error = evaluate("ACC / (R2 - 6)", &vm).unwrap_err();
+    assert_eq!(error.message, "Division by zero");
+    assert_eq!(error.range, 4..5);
+
+    let error = Expr::parse("M[1").unwrap_err();
+    assert_eq!(error.message, "Expected ']', found the end of the expression");
+    assert_eq!(error.range, 3..3);
+
+    let error = Expr::parse("1 2").unwrap_err();
+    assert_eq!(error.message, "Unexpected '2' after the expression");
+    assert_eq!(Expr::parse("ACC $").unwrap_err().message, "Unexpected character '$'");
+}
+
+#[test]
+fn test_source_locations() {
+    let source = "start: LOAD =1\n\n    ADD =2\nHALT\n";
+    let db = Arc::new(VmDatabaseImpl::new());
+    let (ast, errors) = db.parse_program(source);
+    assert!(errors.is_empty());
+    let file_id = base_db::input::FileId(0);
+    let item_tree = hir_def::item_tree::ItemTree::lower(&ast, file_id);
+    let def_id = hir::ids::DefId { file_id, local_id: hir::ids::LocalDefId(0) };
+    let body = hir::lower::lower_program(&ast, def_id, file_id, &item_tree).unwrap();
+    let program = db.hir_to_vm_program(&body).unwrap();
+    let source_map = SourceMap::from_body(&body, "add.ram", source);
+    assert_eq!(source_map.instructions.len(), 3);
+
+    let mut vm = VirtualMachine::new(program, VecInput::new(vec![]), VecOutput::new(), db);
+    assert_eq!(vm.source_location(1), None);
+    vm.set_source_map(Arc::new(source_map));
+    let location = vm.source_location(1).unwrap();
+    assert_eq!(location.to_string(), "add.ram:3:5");
+    assert_eq!(location.span, 20..26);
+    assert_eq!(vm.source_location(3), None);
+}
+
+#[test]
+fn test_orchestrated_machines() {
+    let db = Arc::new(VmDatabaseImpl::new());
+    let producer =
+        db.parse_to_vm_program("LOAD =1\nSTORE 1\nWRITE 1\nLOAD =2\nSTORE 1\nWRITE 1\nHALT\n");
+    let doubler = db.parse_to_vm_program(
+        "loop: READ 1\nLOAD 1\nMUL =2\nSTORE 1\nWRITE 1\nLOAD 1\nSUB =4\nJZERO end\nJUMP loop\nend: HALT\n",
+    );
+
+    // The producer writes to a channel the doubler reads, and the doubler waits for it
+    let channel = Channel::new();
+    let mut orchestrator = Orchestrator::new(db.clone());
+    let first = orchestrator.spawn(
+        "producer",
+        producer.unwrap(),
+        MachineOptions { output: Some(channel.clone()), ..Default::default() },
+    );
+    let second = orchestrator.spawn(
+        "doubler",
+        doubler.unwrap(),
+        MachineOptions { input: InputSource::Channel(channel.clone()), ..Default::default() },
+    );
+    let events = orchestrator.step();
+    assert!(events.is_empty());
+    assert_eq!(orchestrator.state(second), Some(MachineState::Waiting));
+
+    assert!(orchestrator.run(100));
+    assert_eq!(orchestrator.output(first), Some(&[1, 2][..]));
+    assert_eq!(orchestrator.output(second), Some(&[2, 4][..]));
+    assert_eq!(orchestrator.name(second), Some("doubler"));
+    assert!(channel.is_empty());
+    let producer_events: Vec<_> = orchestrator.events_of(first).cloned().collect();
+    assert_eq!(producer_events, vec![Event::Output(1), Event::Output(2), Event::Halted]);
+    assert_eq!(
+        orchestrator.events()[0],
+        MachineEvent { round: 2, machine: MachineId(0), event: Event::Output(1) }
+    );
+    let rounds: Vec<_> = orchestrator.events().iter().map(|event| event.round).collect();
+    assert!(rounds.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    // Quotas stop a machine without stopping the others
+    let spinner = db.parse_to_vm_program("loop: WRITE 0\nJUMP loop\n").unwrap();
+    let mut orchestrator = Orchestrator::new(db.clone());
+    let limited = orchestrator.spawn(
+        "steps",
+        spinner.clone(),
+        MachineOptions {
+            quota: Quota { max_steps: Some(5), max_outputs: None },
+            ..Default::default()
+        },
+    );
+    let quiet = orchestrator.spawn(
+        "outputs",
+        spinner,
+        MachineOptions {
+            quota: Quota { max_steps: None, max_outputs: Some(2) },
+            ..Default::default()
+        },
+    );
+    assert!(orchestrator.run(100));
+    assert_eq!(orchestrator.events_of(limited).last(), Some(&Event::StepLimitExceeded));
+    assert_eq!(orchestrator.output(limited).unwrap().len(), 3);
+    assert_eq!(orchestrator.output(quiet), Some(&[0, 0][..]));
+    assert!(matches!(orchestrator.events_of(quiet).last(), Some(Event::Failed(_))));
+
+    // Machines reading a channel nobody writes fail instead of waiting forever
+    let reader = db.parse_to_vm_program("READ 1\nHALT\n").unwrap();
+    let mut orchestrator = Orchestrator::new(db);
+    let stuck = orchestrator.spawn(
+        "reader",
+        reader,
+        MachineOptions { input: InputSource::Channel(Channel::new()), ..Default::default() },
+    );
+    assert!(orchestrator.run(10));
+    assert_eq!(orchestrator.rounds(), 1);
+    assert!(matches!(orchestrator.events_of(stuck).last(), Some(Event::Failed(_))));
+}
+
+/// An instruction of a random program with `len` instructions
+fn instruction_strategy(len: usize) -> impl Strategy<Value = String> {
+    let register = 1..4i64;
+    let constant = 0..4i64;
+    let label = 0..len;
+    prop_oneof![
+        constant.clone().prop_map(|k| format!("LOAD ={k}")),
+        register.clone().prop_map(|r| format!("LOAD {r}")),
+        constant.clone().prop_map(|k| format!("ADD ={k}")),
+        register.clone().prop_map(|r| format!("SUB {r}")),
+        constant.prop_map(|k| format!("DIV ={k}")),
+        register.clone().prop_map(|r| format!("STORE {r}")),
+        register.clone().prop_map(|r| format!("READ {r}")),
+        register.prop_map(|r| format!("WRITE {r}")),
+        label.clone().prop_map(|l| format!("JZERO L{l}")),
+        label.clone().prop_map(|l| format!("JGTZ L{l}")),
+        label.prop_map(|l| format!("JUMP L{l}")),
+        Just("HALT".to_string()),
+    ]
+}
+
+/// A random program with a label on every instruction
+fn program_strategy() -> impl Strategy<Value = String> {
+    (1..12usize).prop_flat_map(|len| vec(instruction_strategy(len), len)).prop_map(|instructions| {
+        instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| format!("L{index}: {instruction}\n"))
+            .collect()
+    })
+}
+
+proptest! {
+    #[test]
+    fn test_fusion_matches_unfused(
+        source in program_strategy(),
+        input in vec(-5..5i64, 0..4),
+        max_steps in 1..200usize,
+    ) {
+        prop_assert_eq!(
+            run_summary(&source, &input, max_steps, true),
+            run_summary(&source, &input, max_steps, false)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Tracing of executed instructions
+//!
+//! A trace step records what an instruction read and wrote while it ran, so
+//! a run can be inspected or explained one instruction at a time.
+
+use std::fmt;
+
+use ram_core::effect::InstructionEffects;
+use ram_core::instruction::Instruction;
+
+/// A place in the machine an instruction can write to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Location {
+    /// The accumulator
+    Accumulator,
+    /// A register, addressed directly
+    Register(i64),
+    /// A cell of heap memory, addressed indirectly
+    Memory(i64),
+}
+
+impl Location {
+    /// Get the place a register refers to, where register 0 is the accumulator
+    pub fn register(address: i64) -> Self {
+        if address == 0 { Location::Accumulator } else { Location::Register(address) }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Accumulator => write!(f, "ACC"),
+            Location::Register(address) => write!(f, "R{}", address),
+            Location::Memory(address) => write!(f, "M[{}]", address),
+        }
+    }
+}
+
+/// The record of one executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// The number of the step, counting from 1
+    pub step: u64,
+    /// The index of the executed instruction
+    pub pc: usize,
+    /// The executed instruction
+    pub instruction: Instruction,
+    /// The effects of the instruction, as declared by its definition
+    pub effects: InstructionEffects,
+    /// The accumulator before the instruction ran
+    pub accumulator_before: i64,
+    /// The accumulator after the instruction ran
+    pub accumulator_after: i64,
+    /// The value read through the operand, if the instruction reads it
+    pub operand_value: Option<i64>,
+    /// The place written through the operand and its new value
+    pub written: Option<(Location, i64)>,
+    /// The index of the instruction that runs next
+    pub next_pc: usize,
+}
+
+impl TraceStep {
+    /// Check if the instruction continued somewhere other than the next instruction
+    pub fn jumped(&self) -> bool {
+        self.next_pc != self.pc + 1
+    }
+}
@@ -1,21 +1,44 @@
 //! Virtual machine implementation for executing RAM programs
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::sync::Arc;
 
+use ram_core::arithmetic::ArithmeticMode;
 use ram_core::db::VmState;
+use ram_core::effect::InstructionEffects;
 use ram_core::error::VmError;
-use tracing::debug;
-
-use crate::db::{VmDatabase, VmDatabaseImpl};
+use ram_core::instruction::OutputFormat;
+use ram_core::operand_resolver::{DefaultOperandResolver, OperandResolver, StoreTarget};
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+use tracing::{debug, debug_span, instrument};
+
+use crate::cancel::{CANCEL_CHECK_INTERVAL, Cancellation};
+use crate::db::VmDatabaseImpl;
+use crate::decode::DecodedProgram;
+use crate::device::{Device, DeviceMap};
 use crate::io::{Input, Output};
 use crate::memory::Memory;
 use crate::program::Program;
+use crate::rng::Rng;
+use crate::source_map::{SourceLocation, SourceMap};
+use crate::trace::{Location, TraceStep};
+use crate::watch::{Access, WatchHit, Watchpoint, Watchpoints};
+
+/// The maximum number of nested subroutine calls, to stop runaway recursion
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The number of instructions executed in each traced batch
+pub const BATCH_SIZE: usize = 10_000;
 
 /// Virtual machine for executing RAM programs
 pub struct VirtualMachine<I: Input, O: Output> {
     /// The program being executed
     program: Program,
+    /// The program lowered for dispatch, decoded when the machine is created
+    decoded: Arc<DecodedProgram>,
     /// The heap memory (arrays, indirect addressing targets)
     memory: Memory,
     /// The register file (variables, direct addressing targets)
@@ -30,15 +53,113 @@ pub struct VirtualMachine<I: Input, O: Output> {
     input: I,
     /// The output sink
     pub output: O,
-    /// The database for instruction definitions
-    db: Arc<VmDatabaseImpl>,
+    /// The random number generator, seeded from the time unless configured
+    rng: Rng,
+    /// The addresses the active subroutine calls return to, innermost last
+    return_stack: Vec<usize>,
+    /// The devices mapped over registers and heap memory
+    devices: DeviceMap,
+    /// The number of instructions executed, the cost of the run so far
+    steps: u64,
+    /// The number of values read from the input so far
+    values_read: usize,
+    /// The number of values written to the output so far
+    values_written: usize,
+    /// How the instruction being executed shows the values it writes
+    output_format: OutputFormat,
+    /// The watchpoints that pause [`Self::run_until_watch`]
+    watchpoints: Watchpoints,
+    /// The accesses to watched places since the hits were last taken
+    watch_hits: RefCell<Vec<WatchHit>>,
+    /// The index of the instruction being executed, while watchpoints are set
+    watched_pc: Option<usize>,
+    /// The number of times each instruction was executed, by index
+    execution_counts: Vec<u64>,
+    /// The index of the last instruction the machine started executing
+    last_pc: Option<usize>,
+    /// Asks the machine to stop, checked every [`CANCEL_CHECK_INTERVAL`] steps
+    cancellation: Option<Arc<dyn Cancellation>>,
+    /// Whether runs execute fused pairs of instructions as superinstructions
+    fusion: bool,
+    /// What arithmetic instructions do when their result does not fit
+    arithmetic: ArithmeticMode,
+    /// The source locations of the instructions, if the program was transformed
+    source_map: Option<Arc<SourceMap>>,
+}
+
+/// The execution state of a virtual machine, to save it and resume it later
+///
+/// Everything but the program, the instruction database and the mapped devices
+/// is captured, so a machine restored from a snapshot continues exactly where
+/// the original one stopped.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot<I, O> {
+    /// The program counter
+    pub pc: usize,
+    /// The accumulator register
+    pub accumulator: i64,
+    /// Whether the program has not halted yet
+    pub running: bool,
+    /// The register file
+    pub registers: Memory,
+    /// The heap memory
+    pub memory: Memory,
+    /// The input source, with its read position
+    pub input: I,
+    /// The output sink, with the values written so far
+    pub output: O,
+    /// The random number generator
+    pub rng: Rng,
+    /// The return addresses of the active subroutine calls
+    pub return_stack: Vec<usize>,
+    /// The number of instructions executed
+    pub steps: u64,
+    /// The number of values read from the input
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub values_read: usize,
+    /// The number of values written to the output
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub values_written: usize,
+    /// What arithmetic instructions do on overflow
+    #[cfg_attr(feature = "serde", serde(default, with = "arithmetic_mode"))]
+    pub arithmetic: ArithmeticMode,
+}
+
+/// Serialize an [`ArithmeticMode`] by its name, as `ram_core` has no serde support
+#[cfg(feature = "serde")]
+mod arithmetic_mode {
+    use ram_core::arithmetic::ArithmeticMode;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        mode: &ArithmeticMode,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(mode.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ArithmeticMode, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        ArithmeticMode::from_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown arithmetic mode '{name}'")))
+    }
 }
 
 impl<I: Input, O: Output> VirtualMachine<I, O> {
     /// Create a new virtual machine
+    ///
+    /// The program is decoded against the instruction set of `db` here, so
+    /// instructions registered later are unknown to the machine.
     pub fn new(program: Program, input: I, output: O, db: Arc<VmDatabaseImpl>) -> Self {
+        let execution_counts = vec![0; program.len()];
+        let decoded = Arc::new(DecodedProgram::decode(&program, &db));
         Self {
             program,
+            decoded,
             memory: Memory::new(),
             registers: Memory::new(),
             accumulator: 0,
@@ -46,7 +167,66 @@ impl<I: Input, O: Output> VirtualMachine<I, O> {
             running: true,
             input,
             output,
-            db,
+            rng: Rng::from_time(),
+            return_stack: Vec::new(),
+            devices: DeviceMap::new(),
+            steps: 0,
+            values_read: 0,
+            values_written: 0,
+            output_format: OutputFormat::default(),
+            watchpoints: Watchpoints::new(),
+            watch_hits: RefCell::new(Vec::new()),
+            watched_pc: None,
+            execution_counts,
+            last_pc: None,
+            cancellation: None,
+            fusion: false,
+            arithmetic: ArithmeticMode::default(),
+            source_map: None,
+        }
+    }
+
+    /// Restore a virtual machine from a snapshot of its state
+    pub fn from_snapshot(
+        program: Program,
+        snapshot: Snapshot<I, O>,
+        db: Arc<VmDatabaseImpl>,
+    ) -> Self {
+        let mut vm = Self::new(program, snapshot.input, snapshot.output, db);
+        vm.pc = snapshot.pc;
+        vm.accumulator = snapshot.accumulator;
+        vm.running = snapshot.running;
+        vm.registers = snapshot.registers;
+        vm.memory = snapshot.memory;
+        vm.rng = snapshot.rng;
+        vm.return_stack = snapshot.return_stack;
+        vm.steps = snapshot.steps;
+        vm.values_read = snapshot.values_read;
+        vm.values_written = snapshot.values_written;
+        vm.arithmetic = snapshot.arithmetic;
+        vm
+    }
+
+    /// Take a snapshot of the execution state
+    pub fn snapshot(&self) -> Snapshot<I, O>
+    where
+        I: Clone,
+        O: Clone,
+    {
+        Snapshot {
+            pc: self.pc,
+            accumulator: self.accumulator,
+            running: self.running,
+            registers: self.registers.clone(),
+            memory: self.memory.clone(),
+            input: self.input.clone(),
+            output: self.output.clone(),
+            rng: self.rng.clone(),
+            return_stack: self.return_stack.clone(),
+            steps: self.steps,
+            values_read: self.values_read,
+            values_written: self.values_written,
+            arithmetic: self.arithmetic,
         }
     }
 
@@ -67,17 +247,26 @@ impl<I: Input, O: Output> VirtualMachine<I, O> {
         self.accumulator = 0;
         self.pc = 0;
         self.running = true;
+        self.return_stack.clear();
+        self.steps = 0;
+        self.values_read = 0;
+        self.values_written = 0;
+        self.watch_hits.get_mut().clear();
+        self.execution_counts.fill(0);
+        self.last_pc = None;
     }
 
     /// Execute the program until it halts
+    #[instrument(level = "debug", skip_all)]
     pub fn run(&mut self) -> Result<(), VmError> {
-        while self.running && self.pc < self.program.len() {
-            self.step()?;
+        while !self.is_finished() {
+            self.run_batch(BATCH_SIZE)?;
         }
         Ok(())
     }
 
     /// Execute the program until it halts or reaches the maximum number of iterations
+    #[instrument(level = "debug", skip(self))]
     pub fn run_with_max_iterations(&mut self, max_iterations: usize) -> Result<(), VmError> {
         let mut iterations = 0;
         while self.running && self.pc < self.program.len() && iterations < max_iterations {
@@ -95,48 +284,371 @@ impl<I: Input, O: Output> VirtualMachine<I, O> {
         Ok(())
     }
 
+    /// Execute at most `max_steps` instructions, stopping early if the program halts
+    ///
+    /// Unlike [`Self::run_with_max_iterations`], running out of steps is not an
+    /// error, so the run can be resumed later.
+    #[instrument(level = "debug", skip(self))]
+    pub fn run_steps(&mut self, max_steps: usize) -> Result<(), VmError> {
+        let mut remaining = max_steps;
+        while remaining > 0 && !self.is_finished() {
+            let batch = remaining.min(BATCH_SIZE);
+            self.run_batch(batch)?;
+            remaining -= batch;
+        }
+        Ok(())
+    }
+
+    /// Execute at most `max_steps` instructions in a span of their own
+    fn run_batch(&mut self, max_steps: usize) -> Result<(), VmError> {
+        let _span = debug_span!("batch", first_step = self.steps).entered();
+        let mut remaining = max_steps;
+        while remaining > 0 && !self.is_finished() {
+            if remaining >= 2 && self.can_fuse() {
+                self.step_fused()?;
+                remaining -= 2;
+            } else {
+                self.step()?;
+                remaining -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Let runs execute pairs of instructions as superinstructions
+    ///
+    /// Fusion only applies to [`Self::run`] and [`Self::run_steps`], while no
+    /// place is watched, and never changes what a run does; see
+    /// [`crate::decode`].
+    pub fn set_fusion(&mut self, enabled: bool) {
+        self.fusion = enabled;
+    }
+
+    /// Set what arithmetic instructions do when their result does not fit
+    ///
+    /// Machines are [`ArithmeticMode::Checked`] by default, stopping with
+    /// [`VmError::Overflow`] at the first instruction that overflows.
+    pub fn set_arithmetic(&mut self, mode: ArithmeticMode) {
+        self.arithmetic = mode;
+    }
+
+    /// Set the source map of the program, to report where its instructions
+    /// come from when it was compiled or otherwise transformed
+    pub fn set_source_map(&mut self, source_map: Arc<SourceMap>) {
+        self.source_map = Some(source_map);
+    }
+
+    /// Get the source map of the program, if it has one
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_deref()
+    }
+
+    /// Get the original source location of the instruction at an index
+    ///
+    /// Returns `None` if the machine has no source map, or the map does not
+    /// cover the instruction.
+    pub fn source_location(&self, pc: usize) -> Option<&SourceLocation> {
+        self.source_map.as_ref()?.location(pc)
+    }
+
+    /// Check whether the next two instructions can run as a superinstruction
+    fn can_fuse(&self) -> bool {
+        self.fusion
+            && self.watchpoints.is_empty()
+            // The check for cancellation must not fall on the second one
+            && (self.steps + 1) % CANCEL_CHECK_INTERVAL != 0
+            && self.decoded.op(self.pc).is_some_and(|op| op.fusion.is_some())
+    }
+
+    /// Stop if the run was cancelled, when a check is due
+    fn check_cancelled(&self) -> Result<(), VmError> {
+        if self.steps % CANCEL_CHECK_INTERVAL == 0
+            && let Some(cancellation) = &self.cancellation
+            && cancellation.is_cancelled()
+        {
+            debug!("Run cancelled after {} steps", self.steps);
+            return Err(VmError::Cancelled(self.steps));
+        }
+        Ok(())
+    }
+
+    /// Execute the two instructions of a superinstruction
+    ///
+    /// The first instruction neither jumps nor halts, so the second one
+    /// follows without the checks a step makes before each instruction.
+    fn step_fused(&mut self) -> Result<(), VmError> {
+        self.check_cancelled()?;
+        let decoded = Arc::clone(&self.decoded);
+        let first = self.pc;
+        for pc in [first, first + 1] {
+            let op = decoded.op(pc).expect("a superinstruction has two instructions");
+            let definition =
+                decoded.handler(op).expect("both instructions of a superinstruction are defined");
+
+            debug!("PC={}: {}", pc, self.program.instructions[pc]);
+            self.execution_counts[pc] += 1;
+            self.last_pc = Some(pc);
+            self.pc = pc + 1;
+            self.steps += 1;
+            self.output_format = op.output_format;
+            match definition.execute(op.operand.as_ref(), self) {
+                Ok(()) => {}
+                Err(VmError::ProgramTerminated) => {
+                    debug!("Program terminated");
+                    self.running = false;
+                    return self.check_contract_at_halt();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Only `HALT` may stop the program, not running out of instructions
+        if self.pc >= self.program.len() {
+            debug!("Execution fell off the end of the program");
+            self.running = false;
+            return Err(VmError::FellOffEnd);
+        }
+        Ok(())
+    }
+
+    /// Check that a program that halted read and wrote as many values as its
+    /// contract declares
+    fn check_contract_at_halt(&self) -> Result<(), VmError> {
+        let Some(contract) = self.program.contract else {
+            return Ok(());
+        };
+        let counts = [
+            ("read", self.values_read, contract.reads),
+            ("written", self.values_written, contract.writes),
+        ];
+        for (verb, actual, declared) in counts {
+            if let Some(declared) = declared
+                && actual != declared
+            {
+                return Err(VmError::ContractViolation { verb, actual, declared });
+            }
+        }
+        Ok(())
+    }
+
+    /// Let the run be cancelled, stopping with [`VmError::Cancelled`]
+    ///
+    /// The cancellation is checked every [`CANCEL_CHECK_INTERVAL`] steps,
+    /// starting with the first one, and the machine keeps its state when it
+    /// stops, so the run can be reported up to that point.
+    pub fn set_cancellation(&mut self, cancellation: Arc<dyn Cancellation>) {
+        self.cancellation = Some(cancellation);
+    }
+
     /// Execute a single instruction
     pub fn step(&mut self) -> Result<(), VmError> {
+        self.check_cancelled()?;
         if self.pc >= self.program.len() {
             return Err(VmError::InvalidInstruction("Program counter out of bounds".to_string()));
         }
 
-        let instruction = self
-            .program
-            .get_instruction(self.pc)
+        // Hold the decoded program apart, so the operation can be borrowed
+        // while the instruction changes the machine
+        let decoded = Arc::clone(&self.decoded);
+        let op = decoded
+            .op(self.pc)
             .ok_or_else(|| VmError::InvalidInstruction("Invalid program counter".to_string()))?;
 
-        // Debug log
-        let instr_name = format!("{:?}", instruction.kind);
-        let operand_str = match &instruction.operand {
-            Some(op) => format!("{}", op),
-            None => "None".to_string(),
-        };
-        debug!("PC={}: {} {}", self.pc, instr_name, operand_str);
+        debug!("PC={}: {}", self.pc, self.program.instructions[self.pc]);
+        self.execution_counts[self.pc] += 1;
+        self.last_pc = Some(self.pc);
 
         // Increment the PC for the next instruction
         self.pc += 1;
-
-        // Clone instruction data to avoid borrowing issues while executing
-        let kind = instruction.kind.clone();
-        let operand = instruction.operand.clone();
-
-        // Get definition
-        let definition = self
-            .db
-            .get_instruction_definition(&kind)
-            .ok_or_else(|| VmError::InvalidInstruction(format!("Unknown instruction: {}", kind)))?;
-
-        // Execute
-        match definition.execute(operand.as_ref(), self) {
-            Ok(()) => Ok(()),
+        self.steps += 1;
+        self.output_format = op.output_format;
+
+        let definition = decoded.handler(op).ok_or_else(|| {
+            let kind = &self.program.instructions[self.pc - 1].kind;
+            VmError::InvalidInstruction(format!("Unknown instruction: {}", kind))
+        })?;
+
+        // Execute, watching the accesses of the instruction if anything is watched
+        self.watched_pc = (!self.watchpoints.is_empty()).then_some(self.pc - 1);
+        let result = definition.execute(op.operand.as_ref(), self);
+        self.watched_pc = None;
+        match result {
+            Ok(()) => {}
             Err(VmError::ProgramTerminated) => {
                 debug!("Program terminated");
                 self.running = false;
-                Ok(())
+                return self.check_contract_at_halt();
+            }
+            Err(e) => return Err(e),
+        }
+
+        // Only `HALT` may stop the program, not running out of instructions
+        if self.pc >= self.program.len() {
+            debug!("Execution fell off the end of the program");
+            self.running = false;
+            return Err(VmError::FellOffEnd);
+        }
+        Ok(())
+    }
+
+    /// Execute a single instruction, recording what it read and wrote
+    pub fn step_traced(&mut self) -> Result<TraceStep, VmError> {
+        let pc = self.pc;
+        let instruction = self.program.get_instruction(pc).cloned().ok_or_else(|| {
+            VmError::InvalidInstruction("Program counter out of bounds".to_string())
+        })?;
+        let effects = self
+            .decoded
+            .op(pc)
+            .and_then(|op| self.decoded.handler(op))
+            .map(|definition| definition.effects())
+            .unwrap_or_else(InstructionEffects::unknown);
+        let accumulator_before = self.accumulator;
+
+        // Resolve the operand before the instruction changes what it refers to
+        let resolver = DefaultOperandResolver;
+        let operand_value = match &instruction.operand {
+            Some(operand) if effects.reads_operand() => {
+                resolver.resolve_operand_value(operand, self).ok()
+            }
+            _ => None,
+        };
+        let store_target = match &instruction.operand {
+            Some(operand) if effects.writes_operand() => {
+                resolver.resolve_store_address(operand, self).ok()
+            }
+            _ => None,
+        };
+
+        self.step()?;
+
+        let written = match store_target {
+            Some((StoreTarget::Accumulator, _)) => Some((Location::Accumulator, self.accumulator)),
+            Some((StoreTarget::Register, address)) => {
+                Some((Location::Register(address), self.get_register(address)?))
+            }
+            Some((StoreTarget::Memory, address)) => {
+                Some((Location::Memory(address), self.get_memory(address)?))
+            }
+            None => None,
+        };
+
+        Ok(TraceStep {
+            step: self.steps,
+            pc,
+            instruction,
+            effects,
+            accumulator_before,
+            accumulator_after: self.accumulator,
+            operand_value,
+            written,
+            next_pc: self.pc,
+        })
+    }
+
+    /// Execute instructions until one accesses a watched place
+    ///
+    /// Stops early if the program halts or `max_steps` instructions were
+    /// executed. Returns the accesses of the instruction that paused the run,
+    /// or none if it did not pause at a watchpoint.
+    #[instrument(level = "debug", skip(self))]
+    pub fn run_until_watch(&mut self, max_steps: usize) -> Result<Vec<WatchHit>, VmError> {
+        self.watch_hits.get_mut().clear();
+        for _ in 0..max_steps {
+            if self.is_finished() {
+                break;
+            }
+            self.step()?;
+            if !self.watch_hits.get_mut().is_empty() {
+                break;
             }
-            Err(e) => Err(e),
         }
+        Ok(self.take_watch_hits())
+    }
+
+    /// Watch a place of the machine, replacing the watchpoint on it if any
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.insert(watchpoint);
+    }
+
+    /// Stop watching a place of the machine, returning whether it was watched
+    pub fn unwatch(&mut self, location: Location) -> bool {
+        self.watchpoints.remove(location)
+    }
+
+    /// Get the watchpoints of the machine
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    /// Take the accesses to watched places since they were last taken
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(self.watch_hits.get_mut())
+    }
+
+    /// Record an access of the instruction being executed, if it is watched
+    fn observe(&self, location: Location, access: Access, value: i64) {
+        if let Some(pc) = self.watched_pc
+            && self.watchpoints.matches(location, access)
+        {
+            let hit = WatchHit { step: self.steps, pc, location, access, value };
+            let mut hits = self.watch_hits.borrow_mut();
+            if !hits.contains(&hit) {
+                hits.push(hit);
+            }
+        }
+    }
+
+    /// Read a register, where register 0 is the accumulator
+    fn read_register(&self, index: i64) -> Result<i64, VmError> {
+        if index == 0 {
+            return Ok(self.accumulator);
+        }
+        if !self.devices.is_empty()
+            && let Some(result) = self.devices.read(index)
+        {
+            return result;
+        }
+        self.registers.get(index)
+    }
+
+    /// Write a register, where register 0 is the accumulator
+    fn write_register(&mut self, index: i64, value: i64) -> Result<(), VmError> {
+        if index == 0 {
+            self.accumulator = value;
+            return Ok(());
+        }
+        if !self.devices.is_empty()
+            && let Some(result) = self.devices.write(index, value)
+        {
+            return result;
+        }
+        self.registers.set(index, value)
+    }
+
+    /// Read a cell of heap memory
+    fn read_memory(&self, address: i64) -> Result<i64, VmError> {
+        if !self.devices.is_empty()
+            && let Some(result) = self.devices.read(address)
+        {
+            return result;
+        }
+        self.memory.get(address)
+    }
+
+    /// Write a cell of heap memory
+    fn write_memory(&mut self, address: i64, value: i64) -> Result<(), VmError> {
+        if !self.devices.is_empty()
+            && let Some(result) = self.devices.write(address, value)
+        {
+            return result;
+        }
+        self.memory.set(address, value)
+    }
+
+    /// Get the program being executed
+    pub fn program(&self) -> &Program {
+        &self.program
     }
 
     /// Get the current program counter
@@ -144,11 +656,28 @@ impl<I: Input, O: Output> VirtualMachine<I, O> {
         self.pc
     }
 
+    /// Get the index of the last instruction the machine started executing
+    ///
+    /// After a failed step, this is the instruction the step failed at.
+    pub fn last_pc(&self) -> Option<usize> {
+        self.last_pc
+    }
+
     /// Check if the VM is running
     pub fn is_running(&self) -> bool {
         self.running
     }
 
+    /// Check if the program halted or fell off its end
+    pub fn is_finished(&self) -> bool {
+        !self.running || self.pc >= self.program.len()
+    }
+
+    /// Get the number of instructions executed
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
     /// Get the current accumulator value
     pub fn accumulator(&self) -> i64 {
         self.accumulator
@@ -163,36 +692,59 @@ impl<I: Input, O: Output> VirtualMachine<I, O> {
     pub fn get_heap_value(&self, address: i64) -> i64 {
         self.memory.get(address).unwrap_or(0)
     }
+
+    /// Get the number of times each instruction was executed, by index
+    ///
+    /// The counts start over when the machine is reset or restored from a
+    /// snapshot.
+    pub fn execution_counts(&self) -> &[u64] {
+        &self.execution_counts
+    }
+
+    /// Get the devices mapped into the address space
+    pub fn devices(&self) -> &DeviceMap {
+        &self.devices
+    }
+
+    /// Get the device of type `T` mapped at `address`
+    pub fn device<T: Device>(&self, address: i64) -> Option<&T> {
+        self.devices.get(address)
+    }
 }
 
 impl<I: Input, O: Output> VmState for VirtualMachine<I, O> {
     fn accumulator(&self) -> i64 {
+        self.observe(Location::Accumulator, Access::Read, self.accumulator);
         self.accumulator
     }
 
     fn set_accumulator(&mut self, value: i64) {
         self.accumulator = value;
+        self.observe(Location::Accumulator, Access::Write, value);
     }
 
     fn get_register(&self, index: i64) -> Result<i64, VmError> {
-        if index == 0 { Ok(self.accumulator) } else { self.registers.get(index) }
+        let value = self.read_register(index)?;
+        self.observe(Location::register(index), Access::Read, value);
+        Ok(value)
     }
 
     fn set_register(&mut self, index: i64, value: i64) -> Result<(), VmError> {
-        if index == 0 {
-            self.accumulator = value;
-            Ok(())
-        } else {
-            self.registers.set(index, value)
-        }
+        self.write_register(index, value)?;
+        self.observe(Location::register(index), Access::Write, value);
+        Ok(())
     }
 
     fn get_memory(&self, address: i64) -> Result<i64, VmError> {
-        self.memory.get(address)
+        let value = self.read_memory(address)?;
+        self.observe(Location::Memory(address), Access::Read, value);
+        Ok(value)
     }
 
     fn set_memory(&mut self, address: i64, value: i64) -> Result<(), VmError> {
-        self.memory.set(address, value)
+        self.write_memory(address, value)?;
+        self.observe(Location::Memory(address), Access::Write, value);
+        Ok(())
     }
 
     fn program_counter(&self) -> usize {
@@ -204,16 +756,66 @@ impl<I: Input, O: Output> VmState for VirtualMachine<I, O> {
     }
 
     fn read_input(&mut self) -> Result<i64, VmError> {
-        self.input.read()
+        // Reading one value more than the contract declares breaks it already
+        if let Some(declared) = self.program.contract.and_then(|contract| contract.reads)
+            && self.values_read >= declared
+        {
+            return Err(VmError::ContractViolation {
+                verb: "read",
+                actual: self.values_read + 1,
+                declared,
+            });
+        }
+        let value = self.input.read()?;
+        self.values_read += 1;
+        Ok(value)
     }
 
     fn write_output(&mut self, value: i64) -> Result<(), VmError> {
-        self.output.write(value)
+        if let Some(declared) = self.program.contract.and_then(|contract| contract.writes)
+            && self.values_written >= declared
+        {
+            return Err(VmError::ContractViolation {
+                verb: "written",
+                actual: self.values_written + 1,
+                declared,
+            });
+        }
+        match self.output_format {
+            OutputFormat::Number => self.output.write(value)?,
+            OutputFormat::Char => self.output.write_char(value)?,
+        }
+        self.values_written += 1;
+        Ok(())
     }
 
     fn resolve_label(&self, label: &str) -> Result<usize, VmError> {
+        // Instructions resolve the label of their own operand, decoded ahead
+        if let Some(target) = self.last_pc.and_then(|pc| self.decoded.target(pc, label)) {
+            return Ok(target);
+        }
         self.program.resolve_label(label)
     }
+
+    fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic
+    }
+
+    fn next_random(&mut self) -> Result<u64, VmError> {
+        Ok(self.rng.next_u64())
+    }
+
+    fn push_return_address(&mut self, address: usize) -> Result<(), VmError> {
+        if self.return_stack.len() >= MAX_CALL_DEPTH {
+            return Err(VmError::ReturnStackOverflow(MAX_CALL_DEPTH));
+        }
+        self.return_stack.push(address);
+        Ok(())
+    }
+
+    fn pop_return_address(&mut self) -> Result<usize, VmError> {
+        self.return_stack.pop().ok_or(VmError::EmptyReturnStack)
+    }
 }
 
 /// Builder for creating and configuring a virtual machine
@@ -234,6 +836,20 @@ pub struct VirtualMachineBuilder<I: Input, O: Output> {
     initial_accumulator: i64,
     /// Maximum number of iterations
     max_iterations: Option<usize>,
+    /// Seed of the random number generator
+    seed: Option<u64>,
+    /// Devices mapped into the address space
+    devices: DeviceMap,
+    /// Places watched during the run
+    watchpoints: Watchpoints,
+    /// Asks the machine to stop
+    cancellation: Option<Arc<dyn Cancellation>>,
+    /// Whether runs execute superinstructions
+    fusion: bool,
+    /// What arithmetic instructions do on overflow
+    arithmetic: ArithmeticMode,
+    /// The source map of the program
+    source_map: Option<Arc<SourceMap>>,
 }
 
 impl<I: Input, O: Output> VirtualMachineBuilder<I, O> {
@@ -248,6 +864,13 @@ impl<I: Input, O: Output> VirtualMachineBuilder<I, O> {
             initial_heap: HashMap::new(),
             initial_accumulator: 0,
             max_iterations: None,
+            seed: None,
+            devices: DeviceMap::new(),
+            watchpoints: Watchpoints::new(),
+            cancellation: None,
+            fusion: false,
+            arithmetic: ArithmeticMode::default(),
+            source_map: None,
         }
     }
 
@@ -283,16 +906,84 @@ impl<I: Input, O: Output> VirtualMachineBuilder<I, O> {
         self
     }
 
+    /// Seed the random number generator, so random numbers are reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Map a device to a range of addresses
+    ///
+    /// Reads and writes to these addresses, through registers or heap memory,
+    /// go to the device instead of memory. The accumulator (address 0) can not
+    /// be mapped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty, includes address 0 or overlaps the range
+    /// of another device.
+    pub fn map_device(mut self, range: Range<i64>, device: impl Device) -> Self {
+        assert!(!range.contains(&0), "Cannot map device '{}' over the accumulator", device.name());
+        self.devices.map(range, device);
+        self
+    }
+
+    /// Watch a place of the machine, pausing [`VirtualMachine::run_until_watch`]
+    /// when it is accessed
+    pub fn with_watchpoint(mut self, watchpoint: Watchpoint) -> Self {
+        self.watchpoints.insert(watchpoint);
+        self
+    }
+
+    /// Let the run be cancelled, see [`VirtualMachine::set_cancellation`]
+    pub fn with_cancellation(mut self, cancellation: impl Cancellation + 'static) -> Self {
+        self.cancellation = Some(Arc::new(cancellation));
+        self
+    }
+
+    /// Let runs execute superinstructions, see [`VirtualMachine::set_fusion`]
+    pub fn with_fusion(mut self, enabled: bool) -> Self {
+        self.fusion = enabled;
+        self
+    }
+
+    /// Set what arithmetic instructions do on overflow, see
+    /// [`VirtualMachine::set_arithmetic`]
+    pub fn with_arithmetic(mut self, mode: ArithmeticMode) -> Self {
+        self.arithmetic = mode;
+        self
+    }
+
+    /// Set the source map of the program, see [`VirtualMachine::set_source_map`]
+    pub fn with_source_map(mut self, source_map: Arc<SourceMap>) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
     /// Build the virtual machine
     pub fn build(self) -> VirtualMachine<I, O> {
         let mut vm = VirtualMachine::new(self.program, self.input, self.output, self.db);
+        vm.devices = self.devices;
+        vm.watchpoints = self.watchpoints;
+        vm.cancellation = self.cancellation;
+        vm.fusion = self.fusion;
+        vm.arithmetic = self.arithmetic;
+        vm.source_map = self.source_map;
+
+        if let Some(seed) = self.seed {
+            vm.rng = Rng::new(seed);
+        }
 
         // Set the initial accumulator value
         vm.accumulator = self.initial_accumulator;
 
-        // Set the initial register values
+        // Set the initial register values, register 0 being the accumulator
         for (address, value) in self.initial_registers {
-            let _ = vm.registers.set(address, value);
+            if address == 0 {
+                vm.accumulator = value;
+            } else {
+                let _ = vm.registers.set(address, value);
+            }
         }
 
         // Set the initial heap values
@@ -0,0 +1,183 @@
+//! Watchpoints on the places of the machine
+//!
+//! A watchpoint pauses a run when an instruction reads or writes the place it
+//! watches, such as register 5 or a cell of heap memory. The machine checks
+//! its watchpoints as it accesses registers and memory, so a run without any
+//! pays no more than a check that there are none.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::trace::Location;
+
+/// An access of an instruction to a place of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// The instruction read the value of the place
+    Read,
+    /// The instruction wrote a new value to the place
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "written"),
+        }
+    }
+}
+
+/// The accesses a watchpoint pauses on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WatchAccess {
+    /// Only reads of the place
+    Read,
+    /// Only writes to the place
+    Write,
+    /// Both reads of and writes to the place
+    #[default]
+    ReadWrite,
+}
+
+impl WatchAccess {
+    /// Check whether an access pauses the run
+    pub fn matches(self, access: Access) -> bool {
+        match self {
+            WatchAccess::Read => access == Access::Read,
+            WatchAccess::Write => access == Access::Write,
+            WatchAccess::ReadWrite => true,
+        }
+    }
+}
+
+/// A watchpoint on a place of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    /// The watched place
+    pub location: Location,
+    /// The accesses to the place that pause the run
+    pub access: WatchAccess,
+}
+
+impl Watchpoint {
+    /// Create a watchpoint pausing on both reads and writes of a place
+    pub fn new(location: Location) -> Self {
+        Self { location, access: WatchAccess::ReadWrite }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.access {
+            WatchAccess::Read => write!(f, "{}:r", self.location),
+            WatchAccess::Write => write!(f, "{}:w", self.location),
+            WatchAccess::ReadWrite => write!(f, "{}", self.location),
+        }
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /// Parse a watchpoint, such as `5`, `R5:w`, `M[12]:r` or `ACC`
+    ///
+    /// A bare number is a register, and register 0 is the accumulator. The
+    /// optional suffix `:r` or `:w` only pauses on reads or writes.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (place, access) = match text.rsplit_once(':') {
+            Some((place, "r")) => (place, WatchAccess::Read),
+            Some((place, "w")) => (place, WatchAccess::Write),
+            Some((place, "rw")) => (place, WatchAccess::ReadWrite),
+            Some((_, suffix)) => {
+                return Err(format!("Unknown access '{}', expected r, w or rw", suffix));
+            }
+            None => (text, WatchAccess::ReadWrite),
+        };
+
+        let address = |digits: &str| {
+            digits.trim().parse::<i64>().map_err(|_| format!("Invalid watchpoint '{}'", text))
+        };
+        let location = if place.eq_ignore_ascii_case("acc") {
+            Location::Accumulator
+        } else if let Some(digits) = place
+            .strip_prefix("M[")
+            .or_else(|| place.strip_prefix("m["))
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            Location::Memory(address(digits)?)
+        } else {
+            let digits = place.strip_prefix(['R', 'r']).unwrap_or(place);
+            Location::register(address(digits)?)
+        };
+        Ok(Self { location, access })
+    }
+}
+
+/// An access to a watched place that paused a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// The number of the step that accessed the place, counting from 1
+    pub step: u64,
+    /// The index of the instruction that accessed the place
+    pub pc: usize,
+    /// The accessed place
+    pub location: Location,
+    /// How the place was accessed
+    pub access: Access,
+    /// The value read, or the new value written
+    pub value: i64,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} with value {}", self.location, self.access, self.value)
+    }
+}
+
+/// The watchpoints of a machine, by the place they watch
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    /// Map from the watched places to the accesses that pause on them
+    locations: HashMap<Location, WatchAccess>,
+}
+
+impl Watchpoints {
+    /// Create a set without watchpoints
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a watchpoint, replacing the one on the same place
+    pub fn insert(&mut self, watchpoint: Watchpoint) {
+        self.locations.insert(watchpoint.location, watchpoint.access);
+    }
+
+    /// Remove the watchpoint on a place, returning whether there was one
+    pub fn remove(&mut self, location: Location) -> bool {
+        self.locations.remove(&location).is_some()
+    }
+
+    /// Check whether there are no watchpoints
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Get the watchpoints, ordered by place
+    pub fn list(&self) -> Vec<Watchpoint> {
+        let mut watchpoints = self
+            .locations
+            .iter()
+            .map(|(&location, &access)| Watchpoint { location, access })
+            .collect::<Vec<_>>();
+        watchpoints.sort_by_key(|watchpoint| watchpoint.location);
+        watchpoints
+    }
+
+    /// Check whether an access to a place pauses the run
+    pub fn matches(&self, location: Location, access: Access) -> bool {
+        self.locations.get(&location).is_some_and(|watched| watched.matches(access))
+    }
+}
@@ -0,0 +1,328 @@
+//! Virtual file system.
+//!
+//! A [`Vfs`] gives every source file a [`FileId`], wherever its text comes
+//! from: a file on disk, a document open in an editor, or a source bundled
+//! with the compiler. The text of an open document overlays the text on disk
+//! until the document is closed, so the compiler always sees what the user
+//! sees.
+//!
+//! Every change to the text of a file is recorded as a [`ChangedFile`]. The
+//! changes are taken with [`Vfs::take_changes`] and turned into a
+//! [`Change`] of the database with [`Vfs::database_change`].
+//!
+//! # Examples
+//!
+//! ```
+//! use vfs::{ChangeKind, Vfs, VfsPath};
+//!
+//! let mut vfs = Vfs::new();
+//! let path = VfsPath::from("/project/main.ram");
+//!
+//! let file_id = vfs.set_file_contents(path.clone(), Some("HALT\n".to_string()));
+//! vfs.set_overlay(path.clone(), Some("LOAD 1\nHALT\n".to_string()));
+//! assert_eq!(vfs.file_text(file_id).as_deref(), Some("LOAD 1\nHALT\n"));
+//!
+//! // Closing the document shows the text on disk again
+//! vfs.set_overlay(path, None);
+//! assert_eq!(vfs.file_text(file_id).as_deref(), Some("HALT\n"));
+//!
+//! let kinds = vfs.take_changes().into_iter().map(|change| change.kind).collect::<Vec<_>>();
+//! assert_eq!(kinds, [ChangeKind::Create, ChangeKind::Modify, ChangeKind::Modify]);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub use base_db::FileId;
+use base_db::{Change, FileChange};
+
+/// The path of a file in the virtual file system
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VfsPath {
+    /// A file on disk
+    Real(PathBuf),
+    /// A file that only exists in memory, such as a source bundled with the
+    /// compiler or an unsaved document, named by a URI such as
+    /// `bundled:///std/math.ram`
+    Virtual(String),
+}
+
+impl VfsPath {
+    /// Get the path on disk of the file, if it has one
+    pub fn as_path(&self) -> Option<&Path> {
+        match self {
+            VfsPath::Real(path) => Some(path),
+            VfsPath::Virtual(_) => None,
+        }
+    }
+
+    /// Get the name of the file without its extension, which is the name
+    /// other files import it as
+    pub fn file_stem(&self) -> Option<&str> {
+        match self {
+            VfsPath::Real(path) => path.file_stem()?.to_str(),
+            VfsPath::Virtual(uri) => {
+                let name = uri.rsplit('/').next()?;
+                Some(name.split_once('.').map_or(name, |(stem, _)| stem))
+            }
+        }
+    }
+}
+
+impl From<PathBuf> for VfsPath {
+    fn from(path: PathBuf) -> Self {
+        VfsPath::Real(path)
+    }
+}
+
+impl From<&Path> for VfsPath {
+    fn from(path: &Path) -> Self {
+        VfsPath::Real(path.to_path_buf())
+    }
+}
+
+impl From<&str> for VfsPath {
+    fn from(path: &str) -> Self {
+        VfsPath::Real(PathBuf::from(path))
+    }
+}
+
+impl fmt::Display for VfsPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VfsPath::Real(path) => write!(f, "{}", path.display()),
+            VfsPath::Virtual(uri) => write!(f, "{uri}"),
+        }
+    }
+}
+
+/// How the text of a file changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The file did not exist and now has a text
+    Create,
+    /// The text of the file changed, or the file moved to another path
+    Modify,
+    /// The file existed and now has no text
+    Delete,
+}
+
+/// A change to the text of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// The file that changed
+    pub file_id: FileId,
+    /// How it changed
+    pub kind: ChangeKind,
+}
+
+/// The texts a file can have
+#[derive(Debug)]
+struct FileState {
+    /// The path of the file
+    path: VfsPath,
+    /// The text on disk, or the bundled text
+    contents: Option<Arc<str>>,
+    /// The text of the document open in an editor, which overlays `contents`
+    overlay: Option<Arc<str>>,
+}
+
+impl FileState {
+    /// Get the text the compiler sees
+    fn text(&self) -> Option<&Arc<str>> {
+        self.overlay.as_ref().or(self.contents.as_ref())
+    }
+}
+
+/// The virtual file system
+///
+/// Paths are interned: a path keeps its [`FileId`] when its file is deleted
+/// and created again, and no two paths share one.
+#[derive(Debug, Default)]
+pub struct Vfs {
+    /// Map from paths to the IDs of their files
+    ids: HashMap<VfsPath, FileId>,
+    /// The files, indexed by their ID
+    files: Vec<FileState>,
+    /// The changes since they were last taken
+    changes: Vec<ChangedFile>,
+}
+
+impl Vfs {
+    /// Create an empty virtual file system
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the ID of the file at a path, if the file has a text
+    pub fn file_id(&self, path: &VfsPath) -> Option<FileId> {
+        self.ids.get(path).copied().filter(|file_id| self.exists(*file_id))
+    }
+
+    /// Get the path of a file
+    pub fn file_path(&self, file_id: FileId) -> Option<&VfsPath> {
+        self.state(file_id).map(|state| &state.path)
+    }
+
+    /// Check whether a file has a text
+    pub fn exists(&self, file_id: FileId) -> bool {
+        self.state(file_id).is_some_and(|state| state.text().is_some())
+    }
+
+    /// Check whether a file is open in an editor
+    pub fn has_overlay(&self, file_id: FileId) -> bool {
+        self.state(file_id).is_some_and(|state| state.overlay.is_some())
+    }
+
+    /// Get the text of a file, the one in the editor if it is open
+    pub fn file_text(&self, file_id: FileId) -> Option<Arc<str>> {
+        self.state(file_id).and_then(FileState::text).map(Arc::clone)
+    }
+
+    /// Get the files that have a text, with their paths
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, &VfsPath)> + '_ {
+        self.ids
+            .iter()
+            .filter(|(_, file_id)| self.exists(**file_id))
+            .map(|(path, file_id)| (*file_id, path))
+    }
+
+    /// Set the text on disk of a file, or remove it with `None`
+    ///
+    /// Bundled sources are set the same way, under a [`VfsPath::Virtual`].
+    /// Returns the ID of the file, even if it has no text anymore.
+    pub fn set_file_contents(&mut self, path: VfsPath, contents: Option<String>) -> FileId {
+        let file_id = self.intern(path);
+        self.update(file_id, |state| state.contents = contents.map(Arc::from));
+        file_id
+    }
+
+    /// Set the text of a file open in an editor, or close it with `None`
+    ///
+    /// Returns the ID of the file, even if it has no text anymore.
+    pub fn set_overlay(&mut self, path: VfsPath, overlay: Option<String>) -> FileId {
+        let file_id = self.intern(path);
+        self.update(file_id, |state| state.overlay = overlay.map(Arc::from));
+        file_id
+    }
+
+    /// Move a file to another path, keeping its ID and text
+    ///
+    /// A file that was at the new path is deleted. Returns the ID of the
+    /// moved file, or `None` if there is no file at `from`.
+    pub fn rename(&mut self, from: &VfsPath, to: VfsPath) -> Option<FileId> {
+        let file_id = self.file_id(from)?;
+        if *from == to {
+            return Some(file_id);
+        }
+        if let Some(replaced) = self.file_id(&to) {
+            self.update(replaced, |state| {
+                state.contents = None;
+                state.overlay = None;
+            });
+        }
+
+        // The path left behind gets a new ID if a file is created there again
+        self.ids.remove(from);
+        self.ids.insert(to.clone(), file_id);
+        if let Some(state) = self.state_mut(file_id) {
+            state.path = to;
+        }
+        self.changes.push(ChangedFile { file_id, kind: ChangeKind::Modify });
+        Some(file_id)
+    }
+
+    /// Load a file from disk
+    ///
+    /// Returns the ID of the file, or `None` if it cannot be read.
+    pub fn load_file(&mut self, path: &Path) -> Option<FileId> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        Some(self.set_file_contents(VfsPath::from(path), Some(contents)))
+    }
+
+    /// Load the files with an extension under a directory from disk
+    ///
+    /// Hidden directories are skipped. Returns the IDs of the loaded files.
+    pub fn load_dir(&mut self, root: &Path, extension: &str) -> Vec<FileId> {
+        let mut paths = Vec::new();
+        let mut directories = vec![root.to_path_buf()];
+        while let Some(directory) = directories.pop() {
+            let Ok(entries) = std::fs::read_dir(&directory) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    if !entry.file_name().to_string_lossy().starts_with('.') {
+                        directories.push(path);
+                    }
+                } else if path.extension().is_some_and(|ext| ext == extension) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+
+        paths.iter().filter_map(|path| self.load_file(path)).collect()
+    }
+
+    /// Take the changes made since they were last taken, in order
+    pub fn take_changes(&mut self) -> Vec<ChangedFile> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Turn changes into a change of the database
+    ///
+    /// The files keep their IDs in the database, and get the text they have
+    /// now, so the latest of several changes to a file wins.
+    pub fn database_change(&self, changes: &[ChangedFile]) -> Change {
+        let mut change = Change::new();
+        for changed in changes {
+            change.files.push(match self.file_text(changed.file_id) {
+                Some(new_text) => FileChange::Modified { file_id: changed.file_id, new_text },
+                None => FileChange::Removed { file_id: changed.file_id },
+            });
+        }
+        change
+    }
+
+    /// Get the ID of a path, giving it a new one if it has none
+    fn intern(&mut self, path: VfsPath) -> FileId {
+        if let Some(file_id) = self.ids.get(&path) {
+            return *file_id;
+        }
+        let file_id = FileId(u32::try_from(self.files.len()).expect("too many files"));
+        self.files.push(FileState { path: path.clone(), contents: None, overlay: None });
+        self.ids.insert(path, file_id);
+        file_id
+    }
+
+    /// Change the texts of a file, recording how the text it has changed
+    fn update(&mut self, file_id: FileId, update: impl FnOnce(&mut FileState)) {
+        let Some(state) = self.state_mut(file_id) else {
+            return;
+        };
+        let before = state.text().map(Arc::clone);
+        update(state);
+        let kind = match (before, state.text()) {
+            (None, Some(_)) => ChangeKind::Create,
+            (Some(_), None) => ChangeKind::Delete,
+            (Some(before), Some(after)) if before != *after => ChangeKind::Modify,
+            _ => return,
+        };
+        self.changes.push(ChangedFile { file_id, kind });
+    }
+
+    fn state(&self, file_id: FileId) -> Option<&FileState> {
+        self.files.get(file_id.0 as usize)
+    }
+
+    fn state_mut(&mut self, file_id: FileId) -> Option<&mut FileState> {
+        self.files.get_mut(file_id.0 as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,148 @@
+//! Tests for file IDs, overlays and change tracking
+
+use base_db::FileChange;
+
+use crate::{ChangeKind, ChangedFile, Vfs, VfsPath};
+
+fn kinds(vfs: &mut Vfs) -> Vec<ChangeKind> {
+    vfs.take_changes().into_iter().map(|change| change.kind).collect()
+}
+
+#[test]
+fn test_file_ids() {
+    let mut vfs = Vfs::new();
+    let main = VfsPath::from("/project/main.ram");
+    let lib = VfsPath::from("/project/lib.ram");
+
+    let main_id = vfs.set_file_contents(main.clone(), Some("HALT\n".to_string()));
+    let lib_id = vfs.set_file_contents(lib.clone(), Some("HALT\n".to_string()));
+    assert_ne!(main_id, lib_id);
+    assert_eq!(vfs.file_id(&main), Some(main_id));
+    assert_eq!(vfs.file_path(lib_id), Some(&lib));
+
+    // A deleted file has no ID until it is created again, with the same one
+    vfs.set_file_contents(main.clone(), None);
+    assert_eq!(vfs.file_id(&main), None);
+    assert!(!vfs.exists(main_id));
+    assert_eq!(vfs.set_file_contents(main.clone(), Some(String::new())), main_id);
+    assert_eq!(vfs.iter().count(), 2);
+}
+
+#[test]
+fn test_overlays() {
+    let mut vfs = Vfs::new();
+    let path = VfsPath::from("/project/main.ram");
+
+    // A document open in an editor exists before it is saved
+    let file_id = vfs.set_overlay(path.clone(), Some("LOAD 1\n".to_string()));
+    assert!(vfs.has_overlay(file_id));
+    assert_eq!(vfs.file_text(file_id).as_deref(), Some("LOAD 1\n"));
+
+    // Saving does not change the text the compiler sees
+    vfs.set_file_contents(path.clone(), Some("HALT\n".to_string()));
+    assert_eq!(vfs.file_text(file_id).as_deref(), Some("LOAD 1\n"));
+
+    // Deleting the file keeps the document, until it is closed
+    vfs.set_file_contents(path.clone(), None);
+    assert!(vfs.exists(file_id));
+    vfs.set_overlay(path, None);
+    assert!(!vfs.exists(file_id));
+    assert_eq!(kinds(&mut vfs), [ChangeKind::Create, ChangeKind::Delete]);
+}
+
+#[test]
+fn test_changes() {
+    let mut vfs = Vfs::new();
+    let path = VfsPath::Virtual("bundled:///std/math.ram".to_string());
+
+    let file_id = vfs.set_file_contents(path.clone(), Some("HALT\n".to_string()));
+    // Setting the same text again is not a change
+    vfs.set_file_contents(path.clone(), Some("HALT\n".to_string()));
+    vfs.set_file_contents(path.clone(), Some("LOAD 1\nHALT\n".to_string()));
+    let changes = vfs.take_changes();
+    assert_eq!(
+        changes,
+        [
+            ChangedFile { file_id, kind: ChangeKind::Create },
+            ChangedFile { file_id, kind: ChangeKind::Modify },
+        ]
+    );
+    assert!(vfs.take_changes().is_empty());
+
+    // The database gets the text the files have now
+    let change = vfs.database_change(&changes);
+    assert_eq!(change.files.len(), 2);
+    assert!(change.files.iter().all(|change| matches!(
+        change,
+        FileChange::Modified { new_text, .. } if &**new_text == "LOAD 1\nHALT\n"
+    )));
+
+    vfs.set_file_contents(path, None);
+    let changes = vfs.take_changes();
+    let change = vfs.database_change(&changes);
+    assert!(
+        matches!(change.files[..], [FileChange::Removed { file_id: removed }] if removed == file_id)
+    );
+}
+
+#[test]
+fn test_rename() {
+    let mut vfs = Vfs::new();
+    let lib = VfsPath::from("/project/lib.ram");
+    let util = VfsPath::from("/project/util.ram");
+
+    let lib_id = vfs.set_file_contents(lib.clone(), Some("HALT\n".to_string()));
+    let util_id = vfs.set_file_contents(util.clone(), Some("LOAD 1\n".to_string()));
+    vfs.take_changes();
+
+    // The moved file keeps its ID and replaces the file at its new path
+    assert_eq!(vfs.rename(&lib, util.clone()), Some(lib_id));
+    assert_eq!(vfs.file_id(&util), Some(lib_id));
+    assert_eq!(vfs.file_id(&lib), None);
+    assert_eq!(vfs.file_path(lib_id), Some(&util));
+    assert_eq!(vfs.file_text(lib_id).as_deref(), Some("HALT\n"));
+    assert!(!vfs.exists(util_id));
+    assert_eq!(kinds(&mut vfs), [ChangeKind::Delete, ChangeKind::Modify]);
+
+    // A file created at the old path is a new one
+    assert_ne!(vfs.set_file_contents(lib.clone(), Some(String::new())), lib_id);
+    assert_eq!(vfs.rename(&VfsPath::from("/project/missing.ram"), lib), None);
+}
+
+#[test]
+fn test_load_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("lib")).unwrap();
+    std::fs::create_dir_all(dir.path().join(".cache")).unwrap();
+    std::fs::write(dir.path().join("main.ram"), "HALT\n").unwrap();
+    std::fs::write(dir.path().join("lib/math.ram"), "HALT\n").unwrap();
+    std::fs::write(dir.path().join(".cache/old.ram"), "HALT\n").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+    let mut vfs = Vfs::new();
+    let loaded = vfs.load_dir(dir.path(), "ram");
+    let mut stems = loaded
+        .iter()
+        .filter_map(|file_id| vfs.file_path(*file_id)?.file_stem())
+        .collect::<Vec<_>>();
+    stems.sort_unstable();
+    assert_eq!(stems, ["main", "math"]);
+    assert_eq!(kinds(&mut vfs), [ChangeKind::Create, ChangeKind::Create]);
+
+    // Loading a single file again keeps its ID
+    let main = dir.path().join("main.ram");
+    assert_eq!(vfs.load_file(&main), vfs.file_id(&VfsPath::from(main.as_path())));
+    assert_eq!(vfs.load_file(&dir.path().join("missing.ram")), None);
+}
+
+#[test]
+fn test_paths() {
+    let real = VfsPath::from("/project/lib/math.ram");
+    assert_eq!(real.file_stem(), Some("math"));
+    assert!(real.as_path().is_some());
+
+    let bundled = VfsPath::Virtual("bundled:///std/math.ram".to_string());
+    assert_eq!(bundled.file_stem(), Some("math"));
+    assert_eq!(bundled.as_path(), None);
+    assert_eq!(bundled.to_string(), "bundled:///std/math.ram");
+}
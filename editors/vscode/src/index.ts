@@ -3,6 +3,8 @@ import * as vscode from 'vscode';
 
 import { disposeDecorations, initDecorations } from './decorations';
 import { disposeLspClient, initLspClient, restartLspClient, showLspStatus } from './lsp-service';
+import type { EmbeddedTest } from './run';
+import { runProgram, runTest } from './run';
 import { logger } from './utils';
 
 const { activate, deactivate } = defineExtension(async (ctx) => {
@@ -17,6 +19,12 @@ const { activate, deactivate } = defineExtension(async (ctx) => {
     vscode.commands.registerCommand('ram.restartServer', async () => {
       await restartLspClient(ctx);
     }),
+    vscode.commands.registerCommand('ram.runProgram', async (uri: string) => {
+      await runProgram(ctx, uri);
+    }),
+    vscode.commands.registerCommand('ram.runTest', async (uri: string, test: EmbeddedTest) => {
+      await runTest(ctx, uri, test);
+    }),
   );
 
   // Try to initialize the LSP client, but continue even if it fails
@@ -157,11 +157,27 @@ export function getLspStatus(): string {
 }
 
 /**
- * Show the current status of the LSP client
+ * Show the current status of the LSP client, with the server's status document once it is running
  */
-export function showLspStatus(): void {
+export async function showLspStatus(): Promise<void> {
   const status = getLspStatus();
-  vscode.window.showInformationMessage(`RAM Language Server status: ${status}`);
+  if (!client || !clientReady) {
+    vscode.window.showInformationMessage(`RAM Language Server status: ${status}`);
+    return;
+  }
+
+  try {
+    const content = await client.sendRequest<string>('workspace/executeCommand', {
+      command: 'ram.server.showStatus',
+      arguments: [],
+    });
+    const document = await vscode.workspace.openTextDocument({ language: 'markdown', content });
+    await vscode.commands.executeCommand('markdown.showPreview', document.uri);
+  }
+  catch (error) {
+    logger.error('Failed to get the RAM Language Server status:', error);
+    vscode.window.showInformationMessage(`RAM Language Server status: ${status}`);
+  }
 }
 
 /**
@@ -0,0 +1,49 @@
+import * as vscode from 'vscode';
+import { findRamBinary } from './installation';
+
+/**
+ * A test embedded in a program, as sent by the `Run test` code lens
+ */
+export interface EmbeddedTest {
+  input: number[];
+  output: number[];
+}
+
+let terminal: vscode.Terminal | undefined;
+
+/**
+ * Get the terminal programs run in, creating it if it was closed
+ */
+function runTerminal(): vscode.Terminal {
+  if (!terminal || terminal.exitStatus !== undefined) {
+    terminal = vscode.window.createTerminal('RAM');
+  }
+  return terminal;
+}
+
+/**
+ * Run `ram run` on a program in the terminal
+ */
+async function runInTerminal(context: vscode.ExtensionContext, uri: string, args: string[]): Promise<void> {
+  const binary = (await findRamBinary(context)) ?? 'ram';
+  const file = vscode.Uri.parse(uri).fsPath;
+  const term = runTerminal();
+  term.show(true);
+  term.sendText([binary, 'run', file, ...args].map(arg => JSON.stringify(arg)).join(' '));
+}
+
+/**
+ * Run a program, as requested by the `▶ Run` code lens
+ */
+export async function runProgram(context: vscode.ExtensionContext, uri: string): Promise<void> {
+  await runInTerminal(context, uri, []);
+}
+
+/**
+ * Run a program with the input of a test, as requested by the `Run test` code lens
+ */
+export async function runTest(context: vscode.ExtensionContext, uri: string, test: EmbeddedTest): Promise<void> {
+  const args = test.input.length > 0 ? ['--input', test.input.join(' ')] : [];
+  await runInTerminal(context, uri, args);
+  runTerminal().sendText(`echo ${JSON.stringify(`Expected output: ${test.output.join(' ')}`)}`);
+}
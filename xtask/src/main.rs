@@ -1,5 +1,98 @@
 #![expect(clippy::print_stdout)]
 
-fn main() {
-    println!("Hello, world!");
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use ram_diagnostics::codes::{DiagnosticCode, REGISTRY};
+
+/// The directory of the generated diagnostic explanation pages
+const DIAGNOSTICS_DOCS_DIR: &str = "apps/docs/content/docs/diagnostics";
+
+/// The directory of the crate with the C bindings
+const FFI_CRATE_DIR: &str = "crates/ram_ffi";
+
+fn main() -> ExitCode {
+    let task = std::env::args().nth(1);
+    let result = match task.as_deref() {
+        Some("diagnostics-docs") => generate_diagnostics_docs(),
+        Some("ffi-header") => generate_ffi_header(),
+        _ => {
+            println!("Usage: cargo xtask <task>");
+            println!();
+            println!("Tasks:");
+            println!("  diagnostics-docs  Generate the diagnostic code explanation pages");
+            println!("  ffi-header        Generate the C header of the ram_ffi crate");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            println!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns the root directory of the workspace
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// Write one page per registered diagnostic code, plus an index page
+fn generate_diagnostics_docs() -> std::io::Result<()> {
+    let dir = workspace_root().join(DIAGNOSTICS_DOCS_DIR);
+    std::fs::create_dir_all(&dir)?;
+
+    let mut index = String::from(
+        "---\ntitle: Diagnostics\ndescription: Explanations of the diagnostic codes reported by \
+         the compiler\n---\n\n{/* This file is generated by `cargo xtask diagnostics-docs`. */}\n\n\
+         Run `ram explain <code>` to print an explanation from the command line.\n\n\
+         | Code | Description |\n| ---- | ----------- |\n",
+    );
+    let mut pages = Vec::new();
+
+    for code in REGISTRY {
+        let name = code.code.to_lowercase();
+        std::fs::write(dir.join(format!("{name}.mdx")), explanation_page(code))?;
+        let _ = writeln!(index, "| [{}](/docs/diagnostics/{name}) | {} |", code.code, code.title);
+        pages.push(format!("\"{name}\""));
+        println!("Generated {name}.mdx");
+    }
+
+    std::fs::write(dir.join("index.mdx"), index)?;
+    std::fs::write(
+        dir.join("meta.json"),
+        format!(
+            "{{\n  \"title\": \"Diagnostics\",\n  \"pages\": [\"index\", {}]\n}}\n",
+            pages.join(", ")
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Write `include/ram.h` from the functions exported by `ram_ffi`
+fn generate_ffi_header() -> std::io::Result<()> {
+    let crate_dir = workspace_root().join(FFI_CRATE_DIR);
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .map_err(std::io::Error::other)?;
+    let bindings =
+        cbindgen::generate_with_config(&crate_dir, config).map_err(std::io::Error::other)?;
+    bindings.write_to_file(crate_dir.join("include/ram.h"));
+    println!("Generated include/ram.h");
+    Ok(())
+}
+
+/// Render the explanation page of a diagnostic code
+fn explanation_page(code: &DiagnosticCode) -> String {
+    format!(
+        "---\ntitle: {}\ndescription: {}\n---\n\n{{/* This file is generated by `cargo xtask \
+         diagnostics-docs`. */}}\n\n{}",
+        code.code,
+        code.title,
+        code.explanation.replace("```ram", "```")
+    )
 }